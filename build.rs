@@ -0,0 +1,10 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    println!("cargo:rerun-if-changed=proto/dcap.proto");
+    // Migrations are embedded by `sqlx::migrate!`
+    println!("cargo:rerun-if-changed=migrations");
+    // The gRPC API is generated from proto/dcap.proto only when the `grpc`
+    // feature is on, so default builds don't need protoc
+    #[cfg(feature = "grpc")]
+    tonic_build::compile_protos("proto/dcap.proto")?;
+    Ok(())
+}
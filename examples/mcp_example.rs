@@ -2,7 +2,7 @@
 //!
 //! This example demonstrates how to use the MCP server for LLM-to-LLM negotiation.
 
-use std::io::{self, Read, Write};
+use std::io::{self, BufRead, BufReader, Write};
 use std::net::TcpStream;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -11,6 +11,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // Connect to MCP server
     let mut stream = TcpStream::connect("127.0.0.1:8080")?;
+    let mut responses = BufReader::new(stream.try_clone()?);
     println!("Connected to MCP server");
 
     // Example 1: Search for agents
@@ -28,7 +29,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     });
 
     send_request(&mut stream, &search_request)?;
-    let response = read_response(&mut stream)?;
+    let response = read_response(&mut responses)?;
     println!("Search Response: {}", serde_json::to_string_pretty(&response)?);
 
     // Example 2: Get product catalog
@@ -42,7 +43,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     });
 
     send_request(&mut stream, &catalog_request)?;
-    let response = read_response(&mut stream)?;
+    let response = read_response(&mut responses)?;
     println!("Catalog Response: {}", serde_json::to_string_pretty(&response)?);
 
     // Example 3: Get negotiation strategy prompt
@@ -56,7 +57,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     });
 
     send_request(&mut stream, &prompt_request)?;
-    let response = read_response(&mut stream)?;
+    let response = read_response(&mut responses)?;
     println!("Prompt Response: {}", serde_json::to_string_pretty(&response)?);
 
     // Example 4: Register a new agent
@@ -77,7 +78,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     });
 
     send_request(&mut stream, &register_request)?;
-    let response = read_response(&mut stream)?;
+    let response = read_response(&mut responses)?;
     println!("Register Response: {}", serde_json::to_string_pretty(&response)?);
 
     println!("\nMCP Example completed!");
@@ -91,22 +92,11 @@ fn send_request(stream: &mut TcpStream, request: &serde_json::Value) -> io::Resu
     Ok(())
 }
 
-fn read_response(stream: &mut TcpStream) -> io::Result<serde_json::Value> {
-    let mut buffer = Vec::new();
-    let mut temp_buffer = [0; 1024];
-
-    loop {
-        let bytes_read = stream.read(&mut temp_buffer)?;
-        if bytes_read == 0 {
-            break;
-        }
-        buffer.extend_from_slice(&temp_buffer[..bytes_read]);
-
-        // Check if we have a complete JSON response
-        if let Ok(json) = serde_json::from_slice::<serde_json::Value>(&buffer) {
-            return Ok(json);
-        }
+/// Responses come back one per line, in the order the requests were sent
+fn read_response(responses: &mut BufReader<TcpStream>) -> io::Result<serde_json::Value> {
+    let mut line = String::new();
+    if responses.read_line(&mut line)? == 0 {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Incomplete response"));
     }
-
-    Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Incomplete response"))
-}
\ No newline at end of file
+    Ok(serde_json::from_str(&line)?)
+}
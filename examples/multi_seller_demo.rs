@@ -0,0 +1,32 @@
+//! Competitive negotiation between three sellers and one buyer
+//!
+//! Runs the built-in competitive scenario in process: every RFQ goes to all
+//! three sellers, the buyer counters and accepts the best quote, rejects the
+//! rest, and the deal settles. `dcap scenario` runs the same thing.
+
+use dcap::{database::Database, error::Result, scenario::MarketScenario};
+use tempfile::NamedTempFile;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::WARN)
+        .init();
+
+    let temp_file = NamedTempFile::new()?;
+    let database = Database::new(&format!("sqlite://{}", temp_file.path().to_string_lossy())).await?;
+    let report = MarketScenario::competitive().run(database).await?;
+
+    for deal in &report.deals {
+        println!("{} x {}: {} quotes", deal.product_id, deal.quantity, deal.quotes.len());
+        for quote in &deal.quotes {
+            println!("   - {}: {}", quote.seller_name, quote.price);
+        }
+        println!("   ✅ {} settled at {}", deal.seller_name, deal.final_price.unwrap_or_default());
+    }
+    println!();
+    for change in &report.reputations {
+        println!("{}: reputation {} -> {}", change.name, change.before, change.after);
+    }
+    Ok(())
+}
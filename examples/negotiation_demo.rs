@@ -88,6 +88,7 @@ async fn main() -> Result<()> {
     // Accept the quote
     println!("\n5. Accepting quote...");
     buyer_agent.accept_quote(negotiation_id).await?;
+    buyer_agent.confirm_settlements().await?;
     println!("   ✅ Quote accepted! Transaction completed.");
 
     // Check final reputation scores
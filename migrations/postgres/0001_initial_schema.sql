@@ -0,0 +1,416 @@
+-- Tables as created before versioned migrations, so existing databases pick
+-- this up as already applied
+CREATE TABLE IF NOT EXISTS agents (
+    id TEXT PRIMARY KEY,
+    agent_type TEXT NOT NULL,
+    name TEXT NOT NULL,
+    endpoint TEXT NOT NULL,
+    public_key TEXT NOT NULL,
+    reputation_score BIGINT NOT NULL DEFAULT 0,
+    protocol_versions TEXT,
+    preferred_languages TEXT,
+    created_at TEXT NOT NULL,
+    last_active TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS products (
+    id TEXT NOT NULL,
+    agent_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    description TEXT,
+    category TEXT NOT NULL,
+    base_price TEXT NOT NULL,
+    currency TEXT NOT NULL,
+    stock_quantity BIGINT NOT NULL,
+    metadata TEXT,
+    created_at TEXT NOT NULL,
+    PRIMARY KEY (agent_id, id),
+    FOREIGN KEY (agent_id) REFERENCES agents(id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS agent_compliance (
+    agent_id TEXT PRIMARY KEY,
+    region TEXT,
+    attestations TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    FOREIGN KEY (agent_id) REFERENCES agents(id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS agent_response_times (
+    agent_id TEXT PRIMARY KEY,
+    quote_samples BIGINT NOT NULL,
+    average_quote_ms DOUBLE PRECISION NOT NULL,
+    counter_samples BIGINT NOT NULL,
+    average_counter_ms DOUBLE PRECISION NOT NULL,
+    updated_at TEXT NOT NULL,
+    FOREIGN KEY (agent_id) REFERENCES agents(id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS cached_sellers (
+    agent_id TEXT PRIMARY KEY,
+    info TEXT NOT NULL,
+    products TEXT,
+    seen_at TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS product_history (
+    id TEXT PRIMARY KEY,
+    agent_id TEXT NOT NULL,
+    product_id TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    base_price TEXT NOT NULL,
+    currency TEXT NOT NULL,
+    stock_quantity BIGINT NOT NULL,
+    recorded_at TEXT NOT NULL,
+    FOREIGN KEY (agent_id) REFERENCES agents(id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS compliance_violations (
+    id TEXT PRIMARY KEY,
+    agent_id TEXT NOT NULL,
+    product_id TEXT NOT NULL,
+    category TEXT NOT NULL,
+    stage TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS negotiations (
+    id TEXT PRIMARY KEY,
+    rfq_id TEXT NOT NULL UNIQUE,
+    quote_id TEXT,
+    buyer_id TEXT NOT NULL,
+    seller_id TEXT NOT NULL,
+    product_id TEXT NOT NULL,
+    quantity BIGINT NOT NULL,
+    opening_bid TEXT NOT NULL,
+    currency TEXT NOT NULL DEFAULT 'USD',
+    close_price TEXT,
+    delta TEXT,
+    status TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    FOREIGN KEY (buyer_id) REFERENCES agents(id),
+    FOREIGN KEY (seller_id) REFERENCES agents(id)
+);
+
+CREATE TABLE IF NOT EXISTS quotes (
+    id TEXT PRIMARY KEY,
+    rfq_id TEXT NOT NULL,
+    seller_id TEXT NOT NULL,
+    price TEXT NOT NULL,
+    currency TEXT NOT NULL,
+    available_quantity BIGINT NOT NULL,
+    delivery_estimate TEXT,
+    ttl_seconds BIGINT NOT NULL,
+    metadata TEXT,
+    created_at TEXT NOT NULL,
+    FOREIGN KEY (rfq_id) REFERENCES negotiations(rfq_id),
+    FOREIGN KEY (seller_id) REFERENCES agents(id)
+);
+
+CREATE TABLE IF NOT EXISTS negotiation_messages (
+    id TEXT PRIMARY KEY,
+    negotiation_id TEXT NOT NULL,
+    sender_id TEXT NOT NULL,
+    content TEXT NOT NULL,
+    message_type TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    FOREIGN KEY (negotiation_id) REFERENCES negotiations(id) ON DELETE CASCADE,
+    FOREIGN KEY (sender_id) REFERENCES agents(id)
+);
+
+CREATE TABLE IF NOT EXISTS quote_expiry_reminders (
+    quote_id TEXT PRIMARY KEY,
+    rfq_id TEXT NOT NULL,
+    seller_id TEXT NOT NULL,
+    price TEXT NOT NULL,
+    currency TEXT NOT NULL,
+    expires_at TEXT NOT NULL,
+    endpoint TEXT NOT NULL,
+    sent_at TEXT
+);
+
+CREATE TABLE IF NOT EXISTS negotiation_records (
+    id BIGSERIAL PRIMARY KEY,
+    buyer_id TEXT NOT NULL,
+    seller_id TEXT NOT NULL,
+    product_hash TEXT NOT NULL,
+    opening_bid TEXT NOT NULL,
+    close_price TEXT NOT NULL,
+    delta TEXT NOT NULL,
+    timestamp TEXT NOT NULL,
+    duration_seconds BIGINT NOT NULL,
+    message_count BIGINT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS anchor_batches (
+    id TEXT PRIMARY KEY,
+    merkle_root TEXT NOT NULL,
+    first_record_id BIGINT NOT NULL,
+    last_record_id BIGINT NOT NULL,
+    record_count BIGINT NOT NULL,
+    chain TEXT NOT NULL,
+    chain_tx TEXT NOT NULL,
+    anchored_at TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS auctions (
+    id TEXT PRIMARY KEY,
+    rfq TEXT NOT NULL,
+    visibility TEXT NOT NULL,
+    anti_sniping TEXT NOT NULL,
+    status TEXT NOT NULL,
+    closes_at TEXT NOT NULL,
+    extensions BIGINT NOT NULL,
+    winning_bid_id TEXT,
+    created_at TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS auction_bids (
+    id TEXT PRIMARY KEY,
+    auction_id TEXT NOT NULL,
+    seller_id TEXT NOT NULL,
+    price TEXT NOT NULL,
+    delivery_estimate TEXT,
+    submitted_at TEXT NOT NULL,
+    FOREIGN KEY (auction_id) REFERENCES auctions(id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS listings (
+    id TEXT PRIMARY KEY,
+    seller_id TEXT NOT NULL,
+    product_id TEXT NOT NULL,
+    quantity BIGINT NOT NULL,
+    currency TEXT NOT NULL,
+    format TEXT NOT NULL,
+    start_price TEXT NOT NULL,
+    reserve_price TEXT NOT NULL,
+    anti_sniping TEXT NOT NULL,
+    status TEXT NOT NULL,
+    closes_at TEXT NOT NULL,
+    extensions BIGINT NOT NULL,
+    winning_bid_id TEXT,
+    negotiation_id TEXT,
+    created_at TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS listing_bids (
+    id TEXT PRIMARY KEY,
+    listing_id TEXT NOT NULL,
+    buyer_id TEXT NOT NULL,
+    price TEXT NOT NULL,
+    submitted_at TEXT NOT NULL,
+    FOREIGN KEY (listing_id) REFERENCES listings(id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS demand_signals (
+    id TEXT PRIMARY KEY,
+    category TEXT NOT NULL,
+    product_id TEXT,
+    quantity BIGINT NOT NULL,
+    max_price TEXT NOT NULL,
+    currency TEXT NOT NULL,
+    status TEXT NOT NULL,
+    token_hash TEXT NOT NULL,
+    expires_at TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS demand_subscriptions (
+    id TEXT PRIMARY KEY,
+    seller_id TEXT NOT NULL,
+    category TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    UNIQUE (seller_id, category)
+);
+
+CREATE TABLE IF NOT EXISTS demand_responses (
+    id TEXT PRIMARY KEY,
+    signal_id TEXT NOT NULL,
+    seller_id TEXT NOT NULL,
+    product_id TEXT NOT NULL,
+    quantity BIGINT NOT NULL,
+    price TEXT NOT NULL,
+    currency TEXT NOT NULL,
+    valid_until TEXT NOT NULL,
+    note TEXT,
+    created_at TEXT NOT NULL,
+    UNIQUE (signal_id, seller_id),
+    FOREIGN KEY (signal_id) REFERENCES demand_signals(id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS supply_agreements (
+    id TEXT PRIMARY KEY,
+    buyer_id TEXT NOT NULL,
+    seller_id TEXT NOT NULL,
+    currency TEXT NOT NULL,
+    items TEXT NOT NULL,
+    status TEXT NOT NULL,
+    valid_from TEXT NOT NULL,
+    valid_until TEXT NOT NULL,
+    proposal_expires_at TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS agreement_orders (
+    id TEXT PRIMARY KEY,
+    agreement_id TEXT NOT NULL,
+    rfq_id TEXT NOT NULL UNIQUE,
+    product_id TEXT NOT NULL,
+    quantity BIGINT NOT NULL,
+    price TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    FOREIGN KEY (agreement_id) REFERENCES supply_agreements(id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS revoked_session_tokens (
+    negotiation_id TEXT NOT NULL,
+    jti TEXT,
+    revoked_at TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS penalty_obligations (
+    id TEXT PRIMARY KEY,
+    negotiation_id TEXT NOT NULL UNIQUE,
+    quote_id TEXT NOT NULL,
+    seller_id TEXT NOT NULL,
+    buyer_id TEXT NOT NULL,
+    penalty TEXT NOT NULL,
+    currency TEXT NOT NULL,
+    status TEXT NOT NULL,
+    slashed TEXT NOT NULL,
+    breach_reason TEXT,
+    created_at TEXT NOT NULL,
+    resolved_at TEXT
+);
+
+CREATE TABLE IF NOT EXISTS deal_changes (
+    id TEXT PRIMARY KEY,
+    negotiation_id TEXT NOT NULL,
+    requested_by TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    note TEXT,
+    status TEXT NOT NULL,
+    penalised TEXT,
+    fee TEXT NOT NULL,
+    currency TEXT NOT NULL,
+    reputation_penalty BIGINT NOT NULL,
+    created_at TEXT NOT NULL,
+    resolved_at TEXT
+);
+
+CREATE TABLE IF NOT EXISTS agent_stakes (
+    agent_id TEXT NOT NULL,
+    currency TEXT NOT NULL,
+    amount TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    PRIMARY KEY (agent_id, currency)
+);
+
+CREATE TABLE IF NOT EXISTS escrow_holds (
+    id TEXT PRIMARY KEY,
+    transaction_id TEXT NOT NULL,
+    buyer_id TEXT NOT NULL,
+    seller_id TEXT NOT NULL,
+    amount TEXT NOT NULL,
+    currency TEXT NOT NULL,
+    hold_duration_seconds BIGINT NOT NULL,
+    status TEXT NOT NULL,
+    delivery_status TEXT NOT NULL,
+    shipment_proof TEXT,
+    shipped_at TEXT,
+    confirmed_at TEXT,
+    auto_confirm_at TEXT,
+    created_at TEXT NOT NULL,
+    expires_at TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS dead_letters (
+    id TEXT PRIMARY KEY,
+    kind TEXT NOT NULL,
+    payload TEXT NOT NULL,
+    error TEXT NOT NULL,
+    attempts BIGINT NOT NULL,
+    status TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    last_attempt_at TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS payments (
+    payment_id TEXT PRIMARY KEY,
+    transaction_id TEXT NOT NULL,
+    buyer_id TEXT NOT NULL,
+    seller_id TEXT NOT NULL,
+    method TEXT NOT NULL,
+    amount TEXT NOT NULL,
+    currency TEXT NOT NULL,
+    status TEXT NOT NULL,
+    idempotency_key TEXT UNIQUE,
+    error_message TEXT,
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    completed_at TEXT
+);
+
+CREATE TABLE IF NOT EXISTS recovery_policies (
+    agent_id TEXT PRIMARY KEY,
+    policy TEXT NOT NULL,
+    updated_at TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS key_rotations (
+    id TEXT PRIMARY KEY,
+    agent_id TEXT NOT NULL,
+    previous_public_key TEXT NOT NULL,
+    new_public_key TEXT NOT NULL,
+    method TEXT NOT NULL,
+    approvers TEXT NOT NULL,
+    rotated_at TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS artifacts (
+    hash TEXT PRIMARY KEY,
+    kind TEXT NOT NULL,
+    content_type TEXT NOT NULL,
+    size BIGINT NOT NULL,
+    location TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS negotiation_artifacts (
+    negotiation_id TEXT NOT NULL,
+    hash TEXT NOT NULL,
+    attached_at TEXT NOT NULL,
+    PRIMARY KEY (negotiation_id, hash),
+    FOREIGN KEY (negotiation_id) REFERENCES negotiations(id),
+    FOREIGN KEY (hash) REFERENCES artifacts(hash)
+);
+
+CREATE INDEX IF NOT EXISTS idx_agents_type ON agents(agent_type);
+CREATE INDEX IF NOT EXISTS idx_agents_reputation ON agents(reputation_score DESC);
+CREATE INDEX IF NOT EXISTS idx_products_agent ON products(agent_id);
+CREATE INDEX IF NOT EXISTS idx_product_history_product ON product_history(product_id, recorded_at);
+CREATE INDEX IF NOT EXISTS idx_compliance_violations_created ON compliance_violations(created_at);
+CREATE INDEX IF NOT EXISTS idx_negotiations_status ON negotiations(status);
+CREATE INDEX IF NOT EXISTS idx_negotiations_buyer ON negotiations(buyer_id);
+CREATE INDEX IF NOT EXISTS idx_negotiations_seller ON negotiations(seller_id);
+CREATE INDEX IF NOT EXISTS idx_quotes_seller ON quotes(seller_id);
+CREATE INDEX IF NOT EXISTS idx_records_timestamp ON negotiation_records(timestamp);
+CREATE INDEX IF NOT EXISTS idx_anchor_batches_range ON anchor_batches(last_record_id);
+CREATE INDEX IF NOT EXISTS idx_auctions_status ON auctions(status, closes_at);
+CREATE INDEX IF NOT EXISTS idx_auction_bids_auction ON auction_bids(auction_id, submitted_at);
+CREATE INDEX IF NOT EXISTS idx_listings_status ON listings(status, closes_at);
+CREATE INDEX IF NOT EXISTS idx_listing_bids_listing ON listing_bids(listing_id, submitted_at);
+CREATE INDEX IF NOT EXISTS idx_demand_signals_category ON demand_signals(category, status, expires_at);
+CREATE INDEX IF NOT EXISTS idx_demand_subscriptions_category ON demand_subscriptions(category);
+CREATE INDEX IF NOT EXISTS idx_demand_responses_signal ON demand_responses(signal_id, created_at);
+CREATE INDEX IF NOT EXISTS idx_supply_agreements_buyer ON supply_agreements(buyer_id, status);
+CREATE INDEX IF NOT EXISTS idx_agreement_orders_agreement ON agreement_orders(agreement_id, created_at);
+CREATE INDEX IF NOT EXISTS idx_revoked_session_tokens ON revoked_session_tokens(negotiation_id);
+CREATE INDEX IF NOT EXISTS idx_deal_changes_negotiation ON deal_changes(negotiation_id, created_at);
+CREATE INDEX IF NOT EXISTS idx_penalty_obligations_seller ON penalty_obligations(seller_id, status);
+CREATE INDEX IF NOT EXISTS idx_escrow_delivery ON escrow_holds(delivery_status, auto_confirm_at);
+CREATE INDEX IF NOT EXISTS idx_dead_letters_status ON dead_letters(status, created_at);
+CREATE INDEX IF NOT EXISTS idx_payments_transaction ON payments(transaction_id);
+CREATE INDEX IF NOT EXISTS idx_key_rotations_agent ON key_rotations(agent_id, rotated_at);
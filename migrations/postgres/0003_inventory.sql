@@ -0,0 +1,24 @@
+CREATE TABLE inventory (
+    seller_id TEXT NOT NULL,
+    product_id TEXT NOT NULL,
+    on_hand BIGINT NOT NULL,
+    reserved BIGINT NOT NULL DEFAULT 0,
+    updated_at TEXT NOT NULL,
+    PRIMARY KEY (seller_id, product_id),
+    CHECK (reserved >= 0 AND reserved <= on_hand)
+);
+
+CREATE TABLE stock_reservations (
+    rfq_id TEXT PRIMARY KEY,
+    seller_id TEXT NOT NULL,
+    product_id TEXT NOT NULL,
+    buyer_id TEXT NOT NULL,
+    quantity BIGINT NOT NULL,
+    status TEXT NOT NULL,
+    expires_at TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    resolved_at TEXT,
+    FOREIGN KEY (seller_id, product_id) REFERENCES inventory(seller_id, product_id)
+);
+
+CREATE INDEX idx_stock_reservations_status ON stock_reservations(status, expires_at);
@@ -0,0 +1,3 @@
+-- PostgreSQL matches product search terms with LIKE, so only the category
+-- filter is indexed
+CREATE INDEX idx_products_category ON products(category);
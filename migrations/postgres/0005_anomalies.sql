@@ -0,0 +1,13 @@
+CREATE TABLE anomalies (
+    id TEXT PRIMARY KEY,
+    anomaly_key TEXT NOT NULL UNIQUE,
+    kind TEXT NOT NULL,
+    severity TEXT NOT NULL,
+    agent_id TEXT NOT NULL,
+    related_agent_id TEXT,
+    summary TEXT NOT NULL,
+    details TEXT NOT NULL,
+    detected_at TEXT NOT NULL
+);
+
+CREATE INDEX idx_anomalies_detected_at ON anomalies(detected_at);
@@ -0,0 +1,17 @@
+CREATE TABLE rfq_broadcasts (
+    id TEXT PRIMARY KEY,
+    buyer_id TEXT NOT NULL,
+    category TEXT NOT NULL,
+    rfq TEXT NOT NULL,
+    status TEXT NOT NULL,
+    sellers_notified BIGINT NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+CREATE TABLE rfq_broadcast_quotes (
+    broadcast_id TEXT NOT NULL REFERENCES rfq_broadcasts(id),
+    seller_id TEXT NOT NULL,
+    quote TEXT NOT NULL,
+    received_at TEXT NOT NULL,
+    PRIMARY KEY (broadcast_id, seller_id)
+);
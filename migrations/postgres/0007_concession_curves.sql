@@ -0,0 +1,13 @@
+CREATE TABLE negotiation_concessions (
+    negotiation_id TEXT PRIMARY KEY,
+    buyer_id TEXT NOT NULL,
+    seller_id TEXT NOT NULL,
+    product_id TEXT NOT NULL,
+    strategy TEXT NOT NULL,
+    close_price TEXT,
+    curve TEXT NOT NULL,
+    metrics TEXT NOT NULL,
+    recorded_at TEXT NOT NULL
+);
+
+CREATE INDEX idx_negotiation_concessions_recorded ON negotiation_concessions(recorded_at, strategy);
@@ -0,0 +1,4 @@
+-- Agents acting under delegated authority record the principals they act for,
+-- as a JSON array from the original principal down to the acting agent
+ALTER TABLE negotiations ADD COLUMN delegation_chain TEXT;
+ALTER TABLE payments ADD COLUMN delegation_chain TEXT;
@@ -0,0 +1,5 @@
+-- Refunds are recorded as payments giving money back from the payment in
+-- refund_of, for the reason in refund_reason
+ALTER TABLE payments ADD COLUMN refund_of TEXT;
+ALTER TABLE payments ADD COLUMN refund_reason TEXT;
+CREATE INDEX idx_payments_refund_of ON payments(refund_of);
@@ -0,0 +1,3 @@
+-- Payment methods an agent accepts, as a JSON array; discovery filters
+-- sellers by them
+ALTER TABLE agents ADD COLUMN payment_methods TEXT;
@@ -0,0 +1,2 @@
+-- Escrow account of holds placed on chain, as JSON
+ALTER TABLE escrow_holds ADD COLUMN on_chain TEXT;
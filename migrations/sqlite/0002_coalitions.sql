@@ -0,0 +1,36 @@
+CREATE TABLE coalitions (
+    id TEXT PRIMARY KEY,
+    coordinator_id TEXT NOT NULL,
+    product_id TEXT NOT NULL,
+    currency TEXT NOT NULL,
+    max_unit_price TEXT NOT NULL,
+    min_quantity BIGINT NOT NULL,
+    status TEXT NOT NULL,
+    closes_at TEXT NOT NULL,
+    seller_id TEXT,
+    negotiation_id TEXT,
+    price TEXT,
+    created_at TEXT NOT NULL
+);
+
+CREATE TABLE coalition_members (
+    coalition_id TEXT NOT NULL REFERENCES coalitions(id),
+    buyer_id TEXT NOT NULL,
+    quantity BIGINT NOT NULL,
+    share TEXT,
+    payment_id TEXT,
+    joined_at TEXT NOT NULL,
+    PRIMARY KEY (coalition_id, buyer_id)
+);
+
+CREATE TABLE coalition_messages (
+    id TEXT PRIMARY KEY,
+    coalition_id TEXT NOT NULL REFERENCES coalitions(id),
+    sender_id TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    content TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+CREATE INDEX idx_coalitions_product ON coalitions(product_id, status, closes_at);
+CREATE INDEX idx_coalition_messages_coalition ON coalition_messages(coalition_id, created_at);
@@ -0,0 +1,29 @@
+-- Full-text index over product names and descriptions, kept in step with
+-- the products table by triggers
+CREATE VIRTUAL TABLE product_search USING fts5(
+    agent_id UNINDEXED,
+    product_id UNINDEXED,
+    name,
+    description,
+    tokenize = 'porter unicode61'
+);
+
+CREATE TRIGGER products_search_insert AFTER INSERT ON products BEGIN
+    INSERT INTO product_search (agent_id, product_id, name, description)
+    VALUES (new.agent_id, new.id, new.name, new.description);
+END;
+
+CREATE TRIGGER products_search_delete AFTER DELETE ON products BEGIN
+    DELETE FROM product_search WHERE agent_id = old.agent_id AND product_id = old.id;
+END;
+
+CREATE TRIGGER products_search_update AFTER UPDATE ON products BEGIN
+    DELETE FROM product_search WHERE agent_id = old.agent_id AND product_id = old.id;
+    INSERT INTO product_search (agent_id, product_id, name, description)
+    VALUES (new.agent_id, new.id, new.name, new.description);
+END;
+
+INSERT INTO product_search (agent_id, product_id, name, description)
+SELECT agent_id, id, name, description FROM products;
+
+CREATE INDEX idx_products_category ON products(category);
@@ -0,0 +1,9 @@
+CREATE TABLE product_embeddings (
+    agent_id TEXT NOT NULL,
+    product_id TEXT NOT NULL,
+    model TEXT NOT NULL,
+    content_hash TEXT NOT NULL,
+    embedding TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    PRIMARY KEY (agent_id, product_id)
+);
@@ -0,0 +1,15 @@
+-- Fees the platform collected from settled payments; sellers are paid the
+-- net amount
+CREATE TABLE IF NOT EXISTS platform_fees (
+    payment_id TEXT PRIMARY KEY,
+    transaction_id TEXT NOT NULL,
+    seller_id TEXT NOT NULL,
+    method TEXT NOT NULL,
+    currency TEXT NOT NULL,
+    gross_amount TEXT NOT NULL,
+    fee_amount TEXT NOT NULL,
+    net_amount TEXT NOT NULL,
+    collected_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_platform_fees_seller ON platform_fees(seller_id, collected_at);
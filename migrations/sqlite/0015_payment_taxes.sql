@@ -0,0 +1,2 @@
+-- Tax line items charged with a payment, as JSON
+ALTER TABLE payments ADD COLUMN tax TEXT;
@@ -0,0 +1,2 @@
+-- Shipping options quoted with the price, as JSON
+ALTER TABLE quotes ADD COLUMN shipping_options TEXT;
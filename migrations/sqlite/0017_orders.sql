@@ -0,0 +1,20 @@
+-- Orders opened for paid negotiations, tracked through fulfillment
+CREATE TABLE IF NOT EXISTS orders (
+    id TEXT PRIMARY KEY,
+    negotiation_id TEXT NOT NULL UNIQUE,
+    payment_id TEXT NOT NULL,
+    buyer_id TEXT NOT NULL,
+    seller_id TEXT NOT NULL,
+    product_id TEXT NOT NULL,
+    quantity BIGINT NOT NULL,
+    status TEXT NOT NULL,
+    shipment TEXT,
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    shipped_at TEXT,
+    delivered_at TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_orders_buyer ON orders(buyer_id, created_at);
+CREATE INDEX IF NOT EXISTS idx_orders_seller ON orders(seller_id, created_at);
+CREATE INDEX IF NOT EXISTS idx_orders_payment ON orders(payment_id);
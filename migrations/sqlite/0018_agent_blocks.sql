@@ -0,0 +1,22 @@
+-- Agents blacklisted by an operator or greylisted after repeated failures
+CREATE TABLE IF NOT EXISTS agent_blocks (
+    id TEXT PRIMARY KEY,
+    agent_id TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    blocked_until TEXT,
+    created_at TEXT NOT NULL,
+    lifted_at TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_agent_blocks_agent ON agent_blocks(agent_id, created_at);
+
+-- Failed and disputed transactions counted towards greylisting
+CREATE TABLE IF NOT EXISTS agent_failures (
+    id TEXT PRIMARY KEY,
+    agent_id TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_agent_failures_agent ON agent_failures(agent_id, created_at);
@@ -0,0 +1,2 @@
+-- SHA-256 fingerprint of the agent's TLS certificate, for counterparties to pin
+ALTER TABLE agents ADD COLUMN tls_fingerprint TEXT;
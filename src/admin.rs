@@ -0,0 +1,568 @@
+//! Operator tools for running the marketplace.
+//!
+//! [`AdminService`] lists and suspends agents, adjusts reputations, finds
+//! negotiations that stopped moving, force-expires quotes, refunds escrow
+//! holds and sums up the marketplace. [`router`] serves it under `/admin`,
+//! each route to JWTs whose role holds its [`Permission`]: observers may
+//! read, admins may also act. [`AdminClient`] calls it;
+//! the `dcap-admin` binary wraps both. The service works on the database
+//! the marketplace's services share.
+//!
+//! Reputation adjustments carry a reason signed by one of the `[admin]
+//! operator_keys`. A signature is only good once, and only within the replay
+//! window, so an intercepted adjustment can't be submitted again. Each one
+//! is recorded in the audit log with its signature.
+
+use crate::{
+    audit_log::{AuditAction, AuditLog},
+    blocklist::{AgentBlock, BlacklistRequest, BlockList, BlockPolicy},
+    config::{AdminConfig, HttpConfig},
+    database::Database,
+    error::{ApiResult, NegotiationError, Result},
+    http::HttpClient,
+    inventory::ReservationStatus,
+    model::{AgentInfo, AgentType, NegotiationStatus, Quote},
+    nonce::ReplayGuard,
+    recovery::verify_signature,
+    roles::{self, Permission},
+    session::bearer_token,
+    settlement::{EscrowHold, SettlementService},
+    shared_state::SharedState,
+    trust::{JWTClaims, TrustSystem},
+    AgentId, TransactionId,
+};
+use axum::{
+    extract::{Path, Query, State},
+    http::HeaderMap,
+    response::Json,
+    routing::{get, post},
+    Router,
+};
+use base64::{engine::general_purpose, Engine};
+use chrono::{DateTime, Duration, Utc};
+use ed25519_dalek::{Signer, SigningKey};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{collections::BTreeMap, sync::Arc};
+use tokio::sync::RwLock;
+
+pub const DEFAULT_STUCK_AFTER_MINUTES: u64 = 60;
+/// Stuck negotiations listed at a time
+const MAX_STUCK_NEGOTIATIONS: i64 = 500;
+const MAX_REASON_LENGTH: usize = 500;
+/// Replay scope of signed adjustments
+const ADJUSTMENT_SCOPE: &str = "reputation adjustment";
+
+/// An agent as operators see it: its registration, the reputation the
+/// trust system holds for it and any block on it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentOverview {
+    pub agent: AgentInfo,
+    pub reputation: u32,
+    pub block: Option<AgentBlock>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AgentListQuery {
+    pub agent_type: Option<AgentType>,
+}
+
+/// A change to an agent's reputation, with the operator's reason, signed
+/// with their key
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReputationAdjustment {
+    pub score_change: i32,
+    pub reason: String,
+    pub signed_at: DateTime<Utc>,
+    /// Base64 Ed25519 public key of the operator
+    pub signer: String,
+    /// Base64 signature over [`adjustment_message`]
+    pub signature: String,
+}
+
+impl ReputationAdjustment {
+    pub fn sign(agent_id: AgentId, score_change: i32, reason: &str, key: &SigningKey, now: DateTime<Utc>) -> Self {
+        let signature = key.sign(&adjustment_message(agent_id, score_change, reason, now));
+        Self {
+            score_change,
+            reason: reason.to_string(),
+            signed_at: now,
+            signer: general_purpose::STANDARD.encode(key.verifying_key().as_bytes()),
+            signature: general_purpose::STANDARD.encode(signature.to_bytes()),
+        }
+    }
+}
+
+/// What an operator signs to adjust an agent's reputation.
+pub fn adjustment_message(agent_id: AgentId, score_change: i32, reason: &str, signed_at: DateTime<Utc>) -> Vec<u8> {
+    format!("dcap-reputation-adjustment:{}:{}:{}:{}", agent_id, score_change, signed_at.to_rfc3339(), reason).into_bytes()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AdjustedReputation {
+    pub agent_id: AgentId,
+    pub reputation: u32,
+}
+
+/// An open or accepted negotiation nothing has touched for a while
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StuckNegotiation {
+    pub negotiation_id: TransactionId,
+    pub quote_id: Option<TransactionId>,
+    pub buyer_id: AgentId,
+    pub seller_id: AgentId,
+    pub product_id: String,
+    pub status: NegotiationStatus,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExpiredQuote {
+    pub quote: Quote,
+    /// Open negotiations on the quote marked expired
+    pub negotiations_expired: u64,
+    /// Whether stock held for the quote went back on hand
+    pub stock_released: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MarketplaceStats {
+    pub buyers: u64,
+    pub sellers: u64,
+    pub products: u64,
+    /// Agents blacklisted or greylisted right now
+    pub blocked_agents: u64,
+    /// Counts by status
+    pub negotiations: BTreeMap<String, u64>,
+    pub payments: BTreeMap<String, u64>,
+    pub escrow_holds: BTreeMap<String, u64>,
+}
+
+#[derive(Clone)]
+pub struct AdminService {
+    database: Database,
+    trust: Arc<RwLock<TrustSystem>>,
+    settlement: SettlementService,
+    blocks: BlockList,
+    audit: AuditLog,
+    replay_guard: ReplayGuard,
+    operator_keys: Vec<String>,
+    stuck_after: Duration,
+}
+
+impl AdminService {
+    pub fn new(
+        database: Database,
+        trust: TrustSystem,
+        settlement: SettlementService,
+        shared: SharedState,
+        config: &AdminConfig,
+    ) -> Self {
+        Self {
+            blocks: BlockList::new(database.clone(), BlockPolicy::default()),
+            audit: AuditLog::new(database.clone()),
+            trust: Arc::new(RwLock::new(trust)),
+            settlement,
+            replay_guard: ReplayGuard::new(shared),
+            operator_keys: config.operator_keys.clone(),
+            stuck_after: Duration::minutes(config.stuck_after_minutes as i64),
+            database,
+        }
+    }
+
+    /// Checks that the request carries a JWT whose role holds `permission`.
+    pub async fn authorize(&self, headers: &HeaderMap, permission: Permission) -> Result<JWTClaims> {
+        let token = bearer_token(headers)
+            .ok_or_else(|| NegotiationError::Auth("Missing bearer token".to_string()))?;
+        self.trust.read().await.authorize(token, permission).await
+    }
+
+    /// Registered agents, of one type or both
+    pub async fn list_agents(&self, agent_type: Option<AgentType>) -> Result<Vec<AgentOverview>> {
+        let types = match agent_type {
+            Some(agent_type) => vec![agent_type],
+            None => vec![AgentType::Buyer, AgentType::Seller],
+        };
+        let mut agents = Vec::new();
+        for agent_type in types {
+            for agent in self.database.get_agents_by_type(agent_type).await? {
+                let reputation = self.trust.read().await.get_reputation(agent.id).await?;
+                let block = self.blocks.active_block(agent.id).await?;
+                agents.push(AgentOverview { agent, reputation, block });
+            }
+        }
+        Ok(agents)
+    }
+
+    /// Bars an agent from trading, for good or until `request.until`.
+    pub async fn suspend_agent(&self, agent_id: AgentId, request: &BlacklistRequest) -> Result<AgentBlock> {
+        self.agent(agent_id).await?;
+        self.blocks.blacklist(agent_id, &request.reason, request.until).await
+    }
+
+    /// Lifts an agent's suspension, or its greylisting. Returns whether it
+    /// was blocked.
+    pub async fn reinstate_agent(&self, agent_id: AgentId) -> Result<bool> {
+        self.agent(agent_id).await?;
+        self.blocks.unblock(agent_id).await
+    }
+
+    /// Applies an operator's signed adjustment to an agent's reputation.
+    pub async fn adjust_reputation(&self, agent_id: AgentId, adjustment: &ReputationAdjustment) -> Result<AdjustedReputation> {
+        let reason = adjustment.reason.trim();
+        if reason.is_empty() || reason.len() > MAX_REASON_LENGTH {
+            return Err(NegotiationError::Validation(format!("Reasons must be 1 to {} characters", MAX_REASON_LENGTH)));
+        }
+        if !self.operator_keys.contains(&adjustment.signer) {
+            return Err(NegotiationError::Trust(format!("{} isn't an operator key", adjustment.signer)));
+        }
+        let message = adjustment_message(agent_id, adjustment.score_change, &adjustment.reason, adjustment.signed_at);
+        verify_signature(&adjustment.signer, &message, &adjustment.signature)?;
+        self.agent(agent_id).await?;
+        self.replay_guard.check(ADJUSTMENT_SCOPE, Some(&adjustment.signature), Some(adjustment.signed_at)).await?;
+
+        let mut trust = self.trust.write().await;
+        if let Err(e) = trust.adjust_reputation(agent_id, adjustment.score_change, reason).await {
+            self.replay_guard.release(ADJUSTMENT_SCOPE, Some(&adjustment.signature)).await?;
+            return Err(e);
+        }
+        let reputation = trust.get_reputation(agent_id).await?;
+        drop(trust);
+        tracing::info!("Operator {} adjusted agent {}'s reputation by {}: {}", adjustment.signer, agent_id, adjustment.score_change, reason);
+        self.audit.record(AuditAction::ReputationChange, None, &agent_id.to_string(), adjustment).await;
+        Ok(AdjustedReputation { agent_id, reputation })
+    }
+
+    /// Open and accepted negotiations untouched for `stuck_after_minutes`,
+    /// longest idle first
+    pub async fn stuck_negotiations(&self, now: DateTime<Utc>) -> Result<Vec<StuckNegotiation>> {
+        self.database.get_stale_negotiations(now - self.stuck_after, MAX_STUCK_NEGOTIATIONS).await
+    }
+
+    /// Makes a quote lapse now, expiring the open negotiations on it and
+    /// putting the stock held for it back on hand.
+    pub async fn expire_quote(&self, quote_id: TransactionId, now: DateTime<Utc>) -> Result<ExpiredQuote> {
+        let mut quote = self.database.get_quote(quote_id).await?
+            .ok_or_else(|| NegotiationError::NotFound(format!("Quote {}", quote_id)))?;
+        if quote.expires_at() > now {
+            quote.ttl_seconds = (now - quote.created_at).num_seconds().clamp(0, i64::from(quote.ttl_seconds)) as u32;
+            self.database.set_quote_ttl(quote_id, quote.ttl_seconds).await?;
+        }
+        let negotiations_expired = self.database.expire_negotiations_for_quote(quote_id, now).await?;
+        let stock_released = self.database
+            .resolve_stock_reservation(quote.rfq_id, ReservationStatus::Released, now).await?
+            .is_some();
+        tracing::info!("Expired quote {} ({} negotiations)", quote_id, negotiations_expired);
+        Ok(ExpiredQuote { quote, negotiations_expired, stock_released })
+    }
+
+    /// Gives an active escrow hold's funds back to the buyer.
+    pub async fn refund_escrow(&self, escrow_id: uuid::Uuid) -> Result<EscrowHold> {
+        self.settlement.refund_escrow(escrow_id).await
+    }
+
+    pub async fn stats(&self) -> Result<MarketplaceStats> {
+        Ok(MarketplaceStats {
+            buyers: self.database.count_agents(AgentType::Buyer, None).await?,
+            sellers: self.database.count_agents(AgentType::Seller, None).await?,
+            products: self.database.count_products().await?,
+            blocked_agents: self.blocks.active_blocks().await?.len() as u64,
+            negotiations: self.database.count_negotiations_by_status().await?.into_iter().collect(),
+            payments: self.database.count_payments_by_status().await?.into_iter().collect(),
+            escrow_holds: self.database.count_escrow_holds_by_status().await?.into_iter().collect(),
+        })
+    }
+
+    async fn agent(&self, agent_id: AgentId) -> Result<AgentInfo> {
+        self.database.get_agent(agent_id).await?.ok_or(NegotiationError::AgentNotFound(agent_id))
+    }
+}
+
+/// The admin API. Reading needs `view_market`, acting `administer` and
+/// adjusting reputations `adjust_reputation`.
+pub fn router(admin: AdminService) -> Router {
+    let view = Router::new()
+        .route("/admin/agents", get(list_agents))
+        .route("/admin/negotiations/stuck", get(stuck_negotiations))
+        .route("/admin/stats", get(stats));
+    let administer = Router::new()
+        .route("/admin/agents/:agent_id/suspend", post(suspend_agent))
+        .route("/admin/agents/:agent_id/reinstate", post(reinstate_agent))
+        .route("/admin/quotes/:quote_id/expire", post(expire_quote))
+        .route("/admin/escrow/:escrow_id/refund", post(refund_escrow));
+    let adjust = Router::new()
+        .route("/admin/agents/:agent_id/reputation", post(adjust_reputation));
+    roles::require_permission(view, admin.trust.clone(), Permission::ViewMarket)
+        .merge(roles::require_permission(administer, admin.trust.clone(), Permission::Administer))
+        .merge(roles::require_permission(adjust, admin.trust.clone(), Permission::AdjustReputation))
+        .with_state(admin)
+}
+
+async fn list_agents(State(admin): State<AdminService>, Query(query): Query<AgentListQuery>) -> ApiResult<Json<Vec<AgentOverview>>> {
+    Ok(Json(admin.list_agents(query.agent_type).await?))
+}
+
+async fn suspend_agent(
+    State(admin): State<AdminService>,
+    Path(agent_id): Path<AgentId>,
+    Json(request): Json<BlacklistRequest>,
+) -> ApiResult<Json<AgentBlock>> {
+    Ok(Json(admin.suspend_agent(agent_id, &request).await?))
+}
+
+async fn reinstate_agent(State(admin): State<AdminService>, Path(agent_id): Path<AgentId>) -> ApiResult<Json<serde_json::Value>> {
+    match admin.reinstate_agent(agent_id).await? {
+        true => Ok(Json(serde_json::json!({ "status": "success", "message": "Agent reinstated" }))),
+        false => Err(NegotiationError::NotFound(format!("Block on agent {}", agent_id)).into()),
+    }
+}
+
+async fn adjust_reputation(
+    State(admin): State<AdminService>,
+    Path(agent_id): Path<AgentId>,
+    Json(adjustment): Json<ReputationAdjustment>,
+) -> ApiResult<Json<AdjustedReputation>> {
+    Ok(Json(admin.adjust_reputation(agent_id, &adjustment).await?))
+}
+
+async fn stuck_negotiations(State(admin): State<AdminService>) -> ApiResult<Json<Vec<StuckNegotiation>>> {
+    Ok(Json(admin.stuck_negotiations(Utc::now()).await?))
+}
+
+async fn expire_quote(State(admin): State<AdminService>, Path(quote_id): Path<TransactionId>) -> ApiResult<Json<ExpiredQuote>> {
+    Ok(Json(admin.expire_quote(quote_id, Utc::now()).await?))
+}
+
+async fn refund_escrow(State(admin): State<AdminService>, Path(escrow_id): Path<uuid::Uuid>) -> ApiResult<Json<EscrowHold>> {
+    Ok(Json(admin.refund_escrow(escrow_id).await?))
+}
+
+async fn stats(State(admin): State<AdminService>) -> ApiResult<Json<MarketplaceStats>> {
+    Ok(Json(admin.stats().await?))
+}
+
+/// Calls the admin API with an admin JWT.
+#[derive(Clone)]
+pub struct AdminClient {
+    endpoint: String,
+    token: String,
+    client: HttpClient,
+}
+
+impl AdminClient {
+    pub fn new(endpoint: &str, token: &str) -> Self {
+        Self {
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            token: token.to_string(),
+            client: HttpClient::new(&HttpConfig::default()),
+        }
+    }
+
+    pub fn with_http_client(mut self, client: HttpClient) -> Self {
+        self.client = client;
+        self
+    }
+
+    pub async fn list_agents(&self, agent_type: Option<AgentType>) -> Result<Vec<AgentOverview>> {
+        let response = self.client.get(self.url("/admin/agents"))
+            .bearer_auth(&self.token)
+            .query(&AgentListQuery { agent_type })
+            .send()
+            .await?;
+        admin_response(response).await
+    }
+
+    pub async fn suspend_agent(&self, agent_id: AgentId, request: &BlacklistRequest) -> Result<AgentBlock> {
+        self.post(&format!("/admin/agents/{}/suspend", agent_id), request).await
+    }
+
+    pub async fn reinstate_agent(&self, agent_id: AgentId) -> Result<()> {
+        self.post::<serde_json::Value>(&format!("/admin/agents/{}/reinstate", agent_id), &()).await.map(|_| ())
+    }
+
+    pub async fn adjust_reputation(&self, agent_id: AgentId, adjustment: &ReputationAdjustment) -> Result<AdjustedReputation> {
+        self.post(&format!("/admin/agents/{}/reputation", agent_id), adjustment).await
+    }
+
+    pub async fn stuck_negotiations(&self) -> Result<Vec<StuckNegotiation>> {
+        self.get("/admin/negotiations/stuck").await
+    }
+
+    pub async fn expire_quote(&self, quote_id: TransactionId) -> Result<ExpiredQuote> {
+        self.post(&format!("/admin/quotes/{}/expire", quote_id), &()).await
+    }
+
+    pub async fn refund_escrow(&self, escrow_id: uuid::Uuid) -> Result<EscrowHold> {
+        self.post(&format!("/admin/escrow/{}/refund", escrow_id), &()).await
+    }
+
+    pub async fn stats(&self) -> Result<MarketplaceStats> {
+        self.get("/admin/stats").await
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.endpoint, path)
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
+        let response = self.client.get(self.url(path)).bearer_auth(&self.token).send().await?;
+        admin_response(response).await
+    }
+
+    async fn post<T: DeserializeOwned>(&self, path: &str, body: &impl Serialize) -> Result<T> {
+        let response = self.client.post(self.url(path)).bearer_auth(&self.token).json(body).send().await?;
+        admin_response(response).await
+    }
+}
+
+/// The admin API answers failures with an error status and a
+/// `{"code": ..., "message": ...}` body.
+async fn admin_response<T: DeserializeOwned>(response: reqwest::Response) -> Result<T> {
+    let status = response.status();
+    if !status.is_success() {
+        let message = response.json::<serde_json::Value>().await.ok()
+            .and_then(|body| body.get("message").and_then(|m| m.as_str()).map(str::to_string))
+            .unwrap_or_else(|| status.to_string());
+        return Err(NegotiationError::from_status(status, message));
+    }
+    Ok(response.json().await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        model::{Negotiation, PaymentMethod, RFQ},
+        roles::Role,
+        settlement::SettlementConfig,
+    };
+    use axum::http::{header::AUTHORIZATION, HeaderValue};
+    use rust_decimal::Decimal;
+    use tempfile::NamedTempFile;
+
+    async fn admin(operator_keys: Vec<String>) -> (AdminService, Database, NamedTempFile) {
+        let temp_file = NamedTempFile::new().unwrap();
+        let database = Database::new(&format!("sqlite://{}", temp_file.path().to_string_lossy())).await.unwrap();
+        let settlement = SettlementService::new(SettlementConfig {
+            stripe_secret_key: None,
+            solana_rpc_url: None,
+            escrow_service_url: None,
+            webhook_secret: None,
+            delivery_confirmation_timeout_seconds: None,
+        }, database.clone()).await.unwrap();
+        let config = AdminConfig { operator_keys, ..AdminConfig::default() };
+        let admin = AdminService::new(database.clone(), TrustSystem::new().unwrap(), settlement, SharedState::in_memory(), &config);
+        (admin, database, temp_file)
+    }
+
+    async fn agent(database: &Database, agent_type: AgentType) -> AgentInfo {
+        let now = Utc::now();
+        let agent = AgentInfo {
+            id: uuid::Uuid::new_v4(),
+            name: format!("{:?}", agent_type),
+            agent_type,
+            endpoint: "http://localhost:8001".to_string(),
+            public_key: "key".to_string(),
+            reputation_score: 50,
+            products: vec![],
+            payment_methods: vec![PaymentMethod::Stripe],
+            protocol_versions: vec![],
+            preferred_languages: vec![],
+            tls_fingerprint: None,
+            created_at: now,
+            last_active: now,
+        };
+        database.create_agent(&agent).await.unwrap();
+        agent
+    }
+
+    fn bearer(token: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, HeaderValue::from_str(&format!("Bearer {}", token)).unwrap());
+        headers
+    }
+
+    #[tokio::test]
+    async fn test_only_admin_tokens_are_authorized() {
+        let (admin, _database, _temp_file) = admin(vec![]).await;
+        let trust = TrustSystem::new().unwrap();
+
+        let operator = trust.generate_role_jwt("ops@example.com", Role::Admin, Duration::minutes(5)).unwrap();
+        assert_eq!(admin.authorize(&bearer(&operator), Permission::Administer).await.unwrap().sub, "ops@example.com");
+
+        // Observers may look but not act
+        let observer = trust.generate_role_jwt("dashboard", Role::Observer, Duration::minutes(5)).unwrap();
+        assert!(admin.authorize(&bearer(&observer), Permission::ViewMarket).await.is_ok());
+        assert!(matches!(admin.authorize(&bearer(&observer), Permission::Administer).await, Err(NegotiationError::Trust(_))));
+
+        let agent = trust.generate_jwt(uuid::Uuid::new_v4()).await.unwrap();
+        assert!(matches!(admin.authorize(&bearer(&agent), Permission::AdjustReputation).await, Err(NegotiationError::Trust(_))));
+        assert!(matches!(admin.authorize(&HeaderMap::new(), Permission::ViewMarket).await, Err(NegotiationError::Auth(_))));
+    }
+
+    #[tokio::test]
+    async fn test_operators_suspend_agents_and_adjust_reputations() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let operator = general_purpose::STANDARD.encode(key.verifying_key().as_bytes());
+        let (admin, database, _temp_file) = admin(vec![operator]).await;
+        let seller = agent(&database, AgentType::Seller).await;
+
+        let request = BlacklistRequest { reason: "Chargebacks".to_string(), until: None };
+        admin.suspend_agent(seller.id, &request).await.unwrap();
+        let agents = admin.list_agents(Some(AgentType::Seller)).await.unwrap();
+        assert_eq!(agents.len(), 1);
+        assert!(agents[0].block.is_some());
+        assert_eq!(admin.stats().await.unwrap().blocked_agents, 1);
+        assert!(admin.reinstate_agent(seller.id).await.unwrap());
+        assert!(admin.suspend_agent(uuid::Uuid::new_v4(), &request).await.is_err());
+
+        let before = agents[0].reputation;
+        let adjustment = ReputationAdjustment::sign(seller.id, 10, "Resolved dispute in their favour", &key, Utc::now());
+        let adjusted = admin.adjust_reputation(seller.id, &adjustment).await.unwrap();
+        assert!(adjusted.reputation > before);
+        // Each signature is good once
+        assert!(admin.adjust_reputation(seller.id, &adjustment).await.is_err());
+
+        // The reason can't be changed after signing
+        let mut tampered = ReputationAdjustment::sign(seller.id, 10, "Goodwill", &key, Utc::now());
+        tampered.score_change = 50;
+        assert!(admin.adjust_reputation(seller.id, &tampered).await.is_err());
+
+        // Only operator keys may adjust
+        let stranger = SigningKey::from_bytes(&[8; 32]);
+        let unsigned = ReputationAdjustment::sign(seller.id, 10, "Goodwill", &stranger, Utc::now());
+        assert!(matches!(admin.adjust_reputation(seller.id, &unsigned).await, Err(NegotiationError::Trust(_))));
+
+        let audit = AuditLog::new(database).verify_audit_chain().await.unwrap();
+        assert!(audit.is_intact());
+        assert_eq!(audit.entries, 1);
+    }
+
+    #[tokio::test]
+    async fn test_quotes_are_force_expired() {
+        let (admin, database, _temp_file) = admin(vec![]).await;
+        let buyer = agent(&database, AgentType::Buyer).await;
+        let seller = agent(&database, AgentType::Seller).await;
+        let rfq = RFQ::new(buyer.id, "laptop-001".to_string(), 1, Decimal::from(100), "USD".to_string(), Utc::now() + Duration::hours(1));
+        let quote = Quote::new(rfq.id, seller.id, Decimal::from(90), "USD".to_string(), 1, 3600);
+        let mut negotiation = Negotiation::new(rfq, seller.id);
+        negotiation.quote_id = Some(quote.id);
+        negotiation.status = NegotiationStatus::Quoted;
+        database.create_negotiation(&negotiation).await.unwrap();
+        database.create_quote(&quote).await.unwrap();
+
+        // Untouched for longer than `stuck_after_minutes`
+        assert!(admin.stuck_negotiations(Utc::now()).await.unwrap().is_empty());
+        let stuck = admin.stuck_negotiations(Utc::now() + Duration::hours(2)).await.unwrap();
+        assert_eq!(stuck.len(), 1);
+        assert_eq!(stuck[0].negotiation_id, negotiation.id);
+
+        let expired = admin.expire_quote(quote.id, Utc::now()).await.unwrap();
+        assert!(expired.quote.ttl_seconds < 3600);
+        assert_eq!(expired.negotiations_expired, 1);
+        assert_eq!(database.get_quote(quote.id).await.unwrap().unwrap().ttl_seconds, expired.quote.ttl_seconds);
+        assert_eq!(database.get_negotiation(negotiation.id).await.unwrap().unwrap().status, NegotiationStatus::Expired);
+        assert!(admin.stuck_negotiations(Utc::now() + Duration::hours(2)).await.unwrap().is_empty());
+        assert!(matches!(admin.expire_quote(uuid::Uuid::new_v4(), Utc::now()).await, Err(NegotiationError::NotFound(_))));
+    }
+}
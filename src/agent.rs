@@ -1,15 +1,72 @@
 use crate::{
-    discovery::{DiscoveryService, SearchRequest},
+    agreement::{AgreementItemRequest, AgreementRequest, AgreementService, RateCardItem, SupplyAgreement, AGREEMENT_METADATA_KEY},
+    auction::{
+        AntiSniping, Auction, AuctionView, BidVisibility, CreateAuctionRequest, CreateListingRequest, Listing, ListingBid,
+        ListingBidRequest, ListingFormat,
+    },
+    audit_log::AuditAction,
+    calendar::BusinessCalendar,
+    cancellation::{CancellationReason, ChangeStatus, DealChange},
+    catalog::PriceHistory,
+    coalition::{
+        AgreeCoalitionRequest, Coalition, CoalitionMember, CoalitionPaymentRequest, CoalitionStatus, CoalitionView,
+        FormCoalitionRequest, JoinCoalitionRequest, LockCoalitionRequest,
+    },
+    comparison::{rank_quotes, QuoteComparison, RankedQuote, SellerFailure},
+    compliance::ComplianceProfile,
+    concession::ConcessionCurve,
+    config::{CalendarConfig, PricingConfig, QuoteTtlConfig, ShippingConfig, TaxConfig, TemplatesConfig},
+    currency::{self, CurrencyConverter},
+    dead_letter::{DeadLetter, DeadLetterKind, DeadLetterStatus, ReputationUpdatePayload},
+    delegation::{DelegationClaims, DelegationScope, DELEGATION_HEADER},
+    demand::{
+        self, DemandResponse, DemandResponseRequest, DemandSignal, DemandSubscription, PublishDemandRequest,
+        SubscribeRequest, DEMAND_RESPONSE_METADATA_KEY,
+    },
+    discovery::{CatalogSyncResponse, DiscoveryService, SearchRequest},
+    embeddings::{self, EmbeddingProvider},
     error::{NegotiationError, Result},
+    events::{EventBus, EventKind},
+    expiry::{ExpiryAction, ExpiryResponse, ExpiryWarning, EXPIRY_NOTIFY_METADATA_KEY},
+    handover::{self, BuyerState, SellerState, STATE_FORMAT_VERSION},
+    http::HttpClient,
+    idempotency::IDEMPOTENCY_KEY_HEADER,
+    inventory::{InventoryService, StockOutcome, StockUpdate},
+    language::{self, Language},
+    locale::{Locale, PriceFormatter},
+    metrics::Metrics,
     model::*,
-    settlement::SettlementService,
-    trust::TrustSystem,
+    money::Money,
+    nonce::ReplayGuard,
+    obligation::PenaltyObligation,
+    order::{FulfillmentStatus, Order},
+    pricing::{ConfiguredPricingPolicy, FloorRule, PriceFloors, PricingContext, PricingPolicy},
+    product_search, recovery,
+    protocol::{self, ProtocolVersion, PROTOCOL_VERSION_HEADER},
+    quote_ttl::QuoteTtlPolicy,
+    responsiveness::{self, ResponseTimeReport},
+    roles::Role,
+    seller_cache::SellerCache,
+    shared_state::SharedState,
+    shipping::{ConfiguredShipping, ShippingContext, ShippingEstimator},
+    settlement::{PaymentResult, PaymentStatus, Refund, RefundReason, RefundRequest, SettlementService},
+    templates::{MessageTemplates, TemplateKind},
+    strategy::{Decision, NegotiationOutcome, NegotiationStrategy, OfferContext, DEFAULT_MAX_ROUNDS},
+    tax::{FlatRateTaxCalculator, TaxCalculator, TaxContext},
+    telemetry,
+    trust::{TokenPair, TrustSystem},
+    webhook::{self, WebhookSigner},
     AgentId, TransactionId,
 };
-use chrono::{Duration, Utc, Timelike};
-use reqwest::Client;
+use chrono::{DateTime, Duration, Utc};
+use futures::future::join_all;
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::sync::{
+    atomic::{AtomicU32, Ordering},
+    Arc, RwLock,
+};
 use uuid::Uuid;
 use base64::{engine::general_purpose, Engine};
 
@@ -21,6 +78,25 @@ pub struct BuyerAgentConfig {
     pub max_concurrent_negotiations: u32,
     pub default_ttl_hours: u32,
     pub llm_config: LLMConfig,
+    /// Currency the buyer budgets in; quotes in other currencies are converted
+    #[serde(default = "currency::default_currency")]
+    pub currency: String,
+    #[serde(default)]
+    pub locale: Locale,
+    /// Languages to write negotiation messages in, most preferred first; the
+    /// locale's language when empty
+    #[serde(default)]
+    pub preferred_languages: Vec<Language>,
+    /// Region the buyer shops from; needed to see region-restricted categories
+    #[serde(default)]
+    pub region: Option<String>,
+    /// Sellers whose average response time is slower are left out of searches
+    #[serde(default)]
+    pub max_response_time_ms: Option<u64>,
+    /// How the buyer pays; searches only return sellers accepting it. Stripe
+    /// when unset
+    #[serde(default)]
+    pub payment_method: Option<PaymentMethod>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -31,6 +107,32 @@ pub struct SellerAgentConfig {
     pub products: Vec<Product>,
     pub payment_methods: Vec<PaymentMethod>,
     pub llm_config: LLMConfig,
+    #[serde(default)]
+    pub calendar: CalendarConfig,
+    #[serde(default)]
+    pub pricing: PricingConfig,
+    #[serde(default)]
+    pub quote_ttl: QuoteTtlConfig,
+    #[serde(default)]
+    pub quote_firmness: QuoteFirmness,
+    #[serde(default)]
+    pub locale: Locale,
+    /// Languages to write negotiation messages in, most preferred first; the
+    /// locale's language when empty
+    #[serde(default)]
+    pub preferred_languages: Vec<Language>,
+    /// Region and attestations the registry checks for restricted categories
+    #[serde(default)]
+    pub compliance: ComplianceProfile,
+    /// Replacements for the built-in message templates
+    #[serde(default)]
+    pub templates: TemplatesConfig,
+    /// Taxes added to quotes for where the buyer has the goods delivered
+    #[serde(default)]
+    pub tax: TaxConfig,
+    /// Carriers quoted for shipping orders
+    #[serde(default)]
+    pub shipping: ShippingConfig,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -43,13 +145,72 @@ pub struct LLMConfig {
 
 pub struct BuyerAgent {
     config: BuyerAgentConfig,
-    client: Client,
+    client: HttpClient,
     discovery: DiscoveryService,
     trust: TrustSystem,
     settlement: SettlementService,
+    converter: CurrencyConverter,
     active_negotiations: HashMap<TransactionId, Negotiation>,
+    /// Latest quote received for each negotiation
+    latest_quotes: HashMap<TransactionId, Quote>,
+    /// RFQ deadline of each negotiation, past which it expires
+    deadlines: HashMap<TransactionId, DateTime<Utc>>,
+    /// Accepted negotiations whose payment has yet to be confirmed
+    pending_settlements: HashMap<TransactionId, PendingSettlement>,
+    negotiated_versions: HashMap<AgentId, ProtocolVersion>,
+    /// How long to wait for sellers when fanning out RFQs
+    quote_deadline: std::time::Duration,
+    /// Current access and refresh tokens, with the access token's expiry
+    tokens: Option<(TokenPair, usize)>,
+    /// Session token per negotiation, with its expiry
+    session_tokens: HashMap<TransactionId, (String, usize)>,
+    /// Response token for each demand signal this buyer published
+    demand_tokens: HashMap<Uuid, String>,
+    /// Rate cards proposed to or agreed by this buyer
+    agreements: HashMap<Uuid, SupplyAgreement>,
+    events: EventBus,
+    /// Sellers seen through discovery, used while it is unreachable
+    seller_cache: Option<SellerCache>,
+    /// How to answer expiry warnings, and how close to lapsing a quote has
+    /// to be for one to be taken seriously; RFQs ask for warnings when set
+    expiry_handling: Option<(ExpiryAction, Duration)>,
+    /// Matches products to buyer intents, with the least similarity to keep
+    embeddings: Option<(Arc<dyn EmbeddingProvider>, f64)>,
+    /// Delegation token this buyer acts under for a principal, with its claims
+    delegation: Option<(String, DelegationClaims)>,
+    /// Key this buyer signs its RFQs and webhooks with and publishes on
+    /// registering
+    webhook_signer: WebhookSigner,
+    /// Nonces of the quotes received, so a captured quote can't be passed
+    /// off as an answer to a later RFQ
+    replay_guard: ReplayGuard,
+}
+
+/// An accepted negotiation waiting on its payment's confirmation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PendingSettlement {
+    pub payment_id: String,
+    /// Whether the seller holds stock for the negotiation's RFQ, to be
+    /// committed once it settles
+    pub reports_stock: bool,
+    /// Lightning invoice the buyer has to pay for the payment to go through
+    #[serde(default)]
+    pub invoice: Option<String>,
 }
 
+/// Default time sellers have to answer a fanned-out RFQ
+pub const DEFAULT_QUOTE_DEADLINE_SECONDS: u64 = 10;
+
+/// Reputation the seller gains when a deal settles
+pub const SETTLED_SELLER_REPUTATION: i32 = 5;
+/// Reputation the buyer gains when a deal settles
+pub const SETTLED_BUYER_REPUTATION: i32 = 3;
+/// Reputation a seller loses when the buyer rejects their quote
+pub const REJECTED_QUOTE_REPUTATION: i32 = -2;
+/// Reputation below which sellers refuse to quote a buyer, unless
+/// `[trust] min_reputation_threshold` says otherwise
+pub const DEFAULT_MIN_BUYER_REPUTATION: u32 = 50;
+
 impl BuyerAgent {
     pub async fn new(
         config: BuyerAgentConfig,
@@ -57,33 +218,300 @@ impl BuyerAgent {
         trust: TrustSystem,
         settlement: SettlementService,
     ) -> Result<Self> {
-        let client = Client::new();
+        let client = HttpClient::default();
+        let webhook_signer = WebhookSigner::generate(config.agent_id);
         Ok(Self {
             config,
             client,
             discovery,
-            trust,
+            trust: trust.with_agent_role(Role::Buyer),
             settlement,
+            converter: CurrencyConverter::default(),
             active_negotiations: HashMap::new(),
+            latest_quotes: HashMap::new(),
+            deadlines: HashMap::new(),
+            pending_settlements: HashMap::new(),
+            negotiated_versions: HashMap::new(),
+            quote_deadline: std::time::Duration::from_secs(DEFAULT_QUOTE_DEADLINE_SECONDS),
+            tokens: None,
+            session_tokens: HashMap::new(),
+            demand_tokens: HashMap::new(),
+            agreements: HashMap::new(),
+            events: EventBus::default(),
+            seller_cache: None,
+            expiry_handling: None,
+            embeddings: None,
+            delegation: None,
+            webhook_signer,
+            replay_guard: ReplayGuard::new(SharedState::in_memory()),
         })
     }
 
+    pub fn price_formatter(&self) -> PriceFormatter {
+        PriceFormatter::new(self.config.locale)
+    }
+
+    /// Calls sellers and discovery through `client`, e.g. one built from
+    /// `[http]`.
+    pub fn with_http_client(mut self, client: HttpClient) -> Self {
+        self.client = client;
+        self
+    }
+
+    pub fn with_currency_converter(mut self, converter: CurrencyConverter) -> Self {
+        self.converter = converter;
+        self
+    }
+
+    /// Publishes negotiation events on `events`, shared with the agent's
+    /// settlement service so payments appear on the same bus.
+    pub fn with_event_bus(mut self, events: EventBus) -> Self {
+        self.settlement = self.settlement.with_event_bus(events.clone());
+        self.events = events;
+        self
+    }
+
+    /// Subscribe here to follow this buyer's negotiations.
+    pub fn events(&self) -> &EventBus {
+        &self.events
+    }
+
+    pub fn with_quote_deadline(mut self, quote_deadline: std::time::Duration) -> Self {
+        self.quote_deadline = quote_deadline;
+        self
+    }
+
+    /// Asks sellers to warn this buyer's endpoint before firm quotes lapse,
+    /// and answers each warning with `action`. Warnings for quotes that
+    /// aren't due to lapse within `warning_seconds` are refused.
+    pub fn with_expiry_warnings(mut self, action: ExpiryAction, warning_seconds: u64) -> Self {
+        self.expiry_handling = Some((action, Duration::seconds(warning_seconds as i64)));
+        self
+    }
+
+    /// Ranks products matched to an intent by embedding similarity, keeping
+    /// those at or above `min_similarity`, instead of by shared words.
+    pub fn with_embeddings(mut self, provider: Arc<dyn EmbeddingProvider>, min_similarity: f64) -> Self {
+        self.embeddings = Some((provider, min_similarity));
+        self
+    }
+
+    /// Refuses quotes whose nonce `replay_guard` has seen before, or that
+    /// aren't signed when it requires them to be.
+    pub fn with_replay_guard(mut self, replay_guard: ReplayGuard) -> Self {
+        self.replay_guard = replay_guard;
+        self
+    }
+
+    /// Signs this buyer's RFQs and the events it forwards with `signer`, and
+    /// publishes its public key when registering, in place of a key that
+    /// changes on restart.
+    pub fn with_webhook_signer(mut self, signer: WebhookSigner) -> Self {
+        self.webhook_signer = signer;
+        self
+    }
+
+    /// Negotiates, and settles if the scope allows it, on behalf of the
+    /// principal that issued `token` to this buyer. RFQs and payments outside
+    /// the delegated scope are refused, and negotiations and payments record
+    /// the delegation chain.
+    pub fn with_delegation(mut self, token: &str) -> Result<Self> {
+        let claims = self.trust.delegation_tokens().decode(token)?;
+        claims.require_delegate(self.config.agent_id)?;
+        self.delegation = Some((token.to_string(), claims));
+        Ok(self)
+    }
+
+    /// Lets `delegate` act for this buyer within `scope` for `ttl`. A buyer
+    /// that is itself a delegate can only pass on part of its own authority.
+    pub async fn delegate(&mut self, delegate: AgentId, scope: DelegationScope, ttl: Duration) -> Result<String> {
+        if let Some((_, claims)) = &self.delegation {
+            return self.trust.delegation_tokens().sub_delegate(claims, delegate, scope, ttl);
+        }
+        let access_token = self.access_token().await?;
+        self.trust.issue_delegation(&access_token, delegate, scope, ttl).await
+    }
+
+    /// The delegation this buyer acts under, failing once it has expired.
+    fn delegation(&self) -> Result<Option<&DelegationClaims>> {
+        match &self.delegation {
+            Some((_, claims)) if claims.expires_at() <= Utc::now() => {
+                Err(NegotiationError::Auth("Delegation has expired".to_string()))
+            }
+            Some((_, claims)) => Ok(Some(claims)),
+            None => Ok(None),
+        }
+    }
+
+    /// Principals followed by this buyer when it acts under delegation.
+    fn delegation_chain(&self) -> Result<Vec<AgentId>> {
+        match self.delegation()? {
+            Some(claims) => claims.delegation_chain(),
+            None => Ok(vec![]),
+        }
+    }
+
+    /// Fails when an RFQ falls outside the delegated scope.
+    fn authorize_rfq(&self, rfq: &RFQ, category: Option<&str>) -> Result<()> {
+        match self.delegation()? {
+            Some(claims) => claims.authorize_deal(category, &Money::new(rfq.max_price, rfq.currency.clone())),
+            None => Ok(()),
+        }
+    }
+
+    fn authorize_payment(&self, amount: &Money) -> Result<()> {
+        match self.delegation()? {
+            Some(claims) => claims.authorize_settlement(amount),
+            None => Ok(()),
+        }
+    }
+
+    /// Pays `seller_id` through the settlement service, within the delegated
+    /// scope when acting for a principal.
+    async fn pay(&self, seller_id: AgentId, amount: Money, idempotency_key: String) -> Result<PaymentResult> {
+        self.authorize_payment(&amount)?;
+        self.settlement.create_delegated_payment(
+            self.config.agent_id,
+            seller_id,
+            amount,
+            Some(idempotency_key),
+            self.delegation_chain()?,
+            self.payment_method(),
+        ).await
+    }
+
+    fn payment_method(&self) -> PaymentMethod {
+        self.config.payment_method.clone().unwrap_or(PaymentMethod::Stripe)
+    }
+
+    /// Remembers the sellers and products discovery returns in `seller_cache`
+    /// and negotiates with them from there while discovery is unreachable.
+    pub fn with_seller_cache(mut self, seller_cache: SellerCache) -> Self {
+        self.seller_cache = Some(seller_cache);
+        self
+    }
+
+    /// Quote price expressed in the negotiation's (buyer's) currency.
+    pub async fn quote_price_in_budget_currency(&self, negotiation: &Negotiation, quote: &Quote) -> Result<Money> {
+        self.converter.convert(&quote.amount(), &negotiation.currency).await
+    }
+
+    /// Protocol version to speak with a seller. Negotiated on first contact from
+    /// the versions the seller advertised through discovery.
+    fn protocol_version_for(&self, seller: &AgentInfo) -> Result<ProtocolVersion> {
+        if let Some(version) = self.negotiated_versions.get(&seller.id) {
+            return Ok(*version);
+        }
+
+        protocol::negotiate_version(protocol::SUPPORTED_VERSIONS, &seller.protocol_versions)
+            .ok_or_else(|| NegotiationError::Negotiation(format!(
+                "No compatible protocol version with seller {}", seller.id
+            )))
+    }
+
+    /// Language to write a negotiation with a seller in, from the languages
+    /// the seller advertised through discovery.
+    fn language_for(&self, seller: &AgentInfo) -> Language {
+        let local = language::preferred_languages(&self.config.preferred_languages, self.config.locale);
+        language::negotiate_language(&local, &seller.preferred_languages)
+    }
+
+    /// The buyer's access token, renewed with its refresh token shortly
+    /// before it expires. A fresh pair is issued when there's none yet or the
+    /// refresh token is no longer accepted.
+    async fn access_token(&mut self) -> Result<String> {
+        let refresh_after = (Utc::now() + Duration::minutes(1)).timestamp() as usize;
+        let tokens = match self.tokens.take() {
+            Some((tokens, expires)) if expires > refresh_after => {
+                let access_token = tokens.access_token.clone();
+                self.tokens = Some((tokens, expires));
+                return Ok(access_token);
+            }
+            Some((tokens, _)) => match self.trust.refresh_tokens(&tokens.refresh_token).await {
+                Ok(tokens) => tokens,
+                Err(e) => {
+                    tracing::debug!("Reissuing tokens after failed refresh: {}", e);
+                    self.trust.issue_tokens(self.config.agent_id).await?
+                }
+            },
+            None => self.trust.issue_tokens(self.config.agent_id).await?,
+        };
+        let expires = (Utc::now() + Duration::seconds(tokens.expires_in as i64)).timestamp() as usize;
+        let access_token = tokens.access_token.clone();
+        self.tokens = Some((tokens, expires));
+        Ok(access_token)
+    }
+
+    /// Session token for calls about one negotiation, derived from the
+    /// buyer's access token and reissued shortly before it expires.
+    async fn session_token(&mut self, negotiation_id: TransactionId) -> Result<String> {
+        let refresh_after = (Utc::now() + Duration::minutes(1)).timestamp() as usize;
+        if let Some((token, expires)) = self.session_tokens.get(&negotiation_id) {
+            if *expires > refresh_after {
+                return Ok(token.clone());
+            }
+        }
+
+        let agent_token = self.access_token().await?;
+        let token = self.trust.issue_session_token(&agent_token, negotiation_id).await?;
+        let expires = self.trust.session_tokens().decode(&token, negotiation_id)?.exp;
+        self.session_tokens.insert(negotiation_id, (token.clone(), expires));
+        Ok(token)
+    }
+
+    /// Remembers the version the seller answered with so later requests skip negotiation.
+    fn record_protocol_version(&mut self, seller_id: AgentId, response: &reqwest::Response) {
+        self.negotiated_versions.insert(seller_id, answered_protocol_version(response));
+    }
+
     pub async fn browse_products(&self, category: Option<String>) -> Result<Vec<Product>> {
-        let sellers = self.discovery.search_sellers(SearchRequest {
+        let sellers = self.search_sellers(SearchRequest {
             category,
             min_reputation: None,
-            payment_methods: None,
+            payment_methods: self.config.payment_method.clone().map(|method| vec![method]),
+            region: self.config.region.clone(),
+            max_response_time_ms: self.config.max_response_time_ms,
+            rank_by_responsiveness: false,
         }).await?;
 
         let mut all_products = Vec::new();
         for seller in sellers {
+            let version = match self.protocol_version_for(&seller) {
+                Ok(version) => version,
+                Err(e) => {
+                    tracing::warn!("Skipping seller {}: {}", seller.id, e);
+                    continue;
+                }
+            };
+
             let response = self.client
                 .get(&format!("{}/products", seller.endpoint))
+                .header(PROTOCOL_VERSION_HEADER, version.to_string())
+                .headers(telemetry::trace_headers())
                 .send()
-                .await?;
+                .await;
+
+            let response = match response {
+                Ok(response) => response,
+                Err(e) => {
+                    let Some(cache) = &self.seller_cache else {
+                        return Err(e);
+                    };
+                    tracing::warn!("Seller {} unreachable, using its cached products: {}", seller.id, e);
+                    if let Some(cached) = cache.seller(seller.id).await? {
+                        all_products.extend(cached.products);
+                    }
+                    continue;
+                }
+            };
 
             if response.status().is_success() {
                 let products: Vec<Product> = response.json().await?;
+                if let Some(cache) = &self.seller_cache {
+                    if let Err(e) = cache.remember_products(seller.id, &products).await {
+                        tracing::warn!("Failed to cache products of seller {}: {}", seller.id, e);
+                    }
+                }
                 all_products.extend(products);
             }
         }
@@ -91,7 +519,90 @@ impl BuyerAgent {
         Ok(all_products)
     }
 
-    pub async fn request_quote(&mut self, product_id: String, quantity: u32, max_price: f64) -> Result<TransactionId> {
+    /// Browsed products matching a free-text intent, most relevant first,
+    /// with their score: embedding similarity when embeddings are set, text
+    /// relevance otherwise.
+    pub async fn match_products(&self, intent: &str, category: Option<String>) -> Result<Vec<(Product, f64)>> {
+        let products = self.browse_products(category).await?;
+        if let Some((provider, min_similarity)) = &self.embeddings {
+            return embeddings::rank_products(provider.as_ref(), intent, products, *min_similarity).await;
+        }
+        let terms = product_search::search_terms(intent);
+        let mut matches: Vec<(Product, f64)> = products.into_iter()
+            .map(|product| {
+                let score = product_search::text_score(&product, &terms);
+                (product, score)
+            })
+            .filter(|(_, score)| *score > 0.0)
+            .collect();
+        matches.sort_by(|a, b| b.1.total_cmp(&a.1));
+        Ok(matches)
+    }
+
+    /// Searches discovery, or the seller cache while discovery is unreachable.
+    async fn search_sellers(&self, request: SearchRequest) -> Result<Vec<AgentInfo>> {
+        let category = request.category.clone();
+        let sellers = match (self.discovery.search_sellers(request).await, &self.seller_cache) {
+            (Ok(sellers), Some(cache)) => {
+                if let Err(e) = cache.remember_sellers(&sellers).await {
+                    tracing::warn!("Failed to cache sellers: {}", e);
+                }
+                Ok(sellers)
+            }
+            (Err(e), Some(cache)) if e.is_unreachable() => {
+                tracing::warn!("Discovery unreachable, falling back to cached sellers: {}", e);
+                cache.sellers(category.as_deref()).await
+            }
+            (result, _) => result,
+        }?;
+        sellers.iter().for_each(|seller| self.pin_certificate(seller));
+        Ok(sellers)
+    }
+
+    /// Looks a seller up in discovery, or in the seller cache while discovery
+    /// is unreachable.
+    async fn seller(&self, seller_id: AgentId) -> Result<AgentInfo> {
+        let seller = match (self.discovery.get_agent(seller_id).await, &self.seller_cache) {
+            (Ok(seller), Some(cache)) => {
+                if let Err(e) = cache.remember_sellers(std::slice::from_ref(&seller)).await {
+                    tracing::warn!("Failed to cache seller {}: {}", seller_id, e);
+                }
+                Ok(seller)
+            }
+            (Err(e), Some(cache)) if e.is_unreachable() => {
+                tracing::warn!("Discovery unreachable, looking up seller {} in the cache: {}", seller_id, e);
+                cache.seller(seller_id).await?.ok_or(e)
+            }
+            (result, _) => result,
+        }?;
+        self.pin_certificate(&seller);
+        Ok(seller)
+    }
+
+    async fn seller_for_product(&self, product_id: &str) -> Result<AgentInfo> {
+        let seller = match (self.discovery.get_seller_by_product(product_id).await, &self.seller_cache) {
+            (Err(e), Some(cache)) if e.is_unreachable() => {
+                tracing::warn!("Discovery unreachable, looking up a seller of {} in the cache: {}", product_id, e);
+                Ok(cache.product(product_id).await?.ok_or(e)?.0)
+            }
+            (result, _) => result,
+        }?;
+        self.pin_certificate(&seller);
+        Ok(seller)
+    }
+
+    /// Expects the certificate the seller registered when calling it over TLS.
+    fn pin_certificate(&self, seller: &AgentInfo) {
+        let Some(fingerprint) = &seller.tls_fingerprint else {
+            return;
+        };
+        if let Err(e) = self.client.pin_certificate(&seller.endpoint, fingerprint) {
+            tracing::warn!("Failed to pin the certificate of seller {}: {}", seller.id, e);
+        }
+    }
+
+    #[tracing::instrument(skip_all, fields(product_id = %product_id))]
+    pub async fn request_quote(&mut self, product_id: String, quantity: u32, max_price: Decimal) -> Result<TransactionId> {
         let product = self.find_product(&product_id).await?;
 
         if quantity > product.stock_quantity {
@@ -99,284 +610,2112 @@ impl BuyerAgent {
         }
 
         let deadline = Utc::now() + Duration::hours(self.config.default_ttl_hours as i64);
-        let rfq = RFQ::new(
+        let mut rfq = RFQ::new(
             self.config.agent_id,
             product_id.clone(),
             quantity,
             max_price,
-            product.currency.clone(),
+            self.config.currency.clone(),
             deadline,
         );
 
+        self.request_expiry_warnings(&mut rfq);
         rfq.validate()?;
+        self.authorize_rfq(&rfq, Some(&product.category))?;
 
-        let seller = self.discovery.get_seller_by_product(&product_id).await?;
-        let negotiation = Negotiation::new(rfq.clone(), seller.id);
+        let seller = self.seller_for_product(&product_id).await?;
+        let negotiation = Negotiation::new(rfq.clone(), seller.id)
+            .with_delegation_chain(self.delegation_chain()?);
 
         // self.database.create_negotiation(&negotiation).await?;
         self.active_negotiations.insert(negotiation.id, negotiation.clone());
+        self.deadlines.insert(negotiation.id, rfq.deadline);
 
-        let response = self.client
-            .post(&format!("{}/quote", seller.endpoint))
-            .json(&rfq)
-            .send()
-            .await?;
-
-        if response.status().is_success() {
-            let quote: Quote = response.json().await?;
-            let negotiation = self.active_negotiations.get_mut(&negotiation.id).unwrap();
-            negotiation.add_quote(&quote)?;
-            // self.database.update_negotiation(negotiation).await?;
-            Ok(negotiation.id)
-        } else {
-            Err(NegotiationError::Network(response.error_for_status().unwrap_err()))
-        }
+        let access_token = self.access_token().await?;
+        let reply = self.send_rfq(&seller, &rfq, &access_token).await?;
+        self.negotiated_versions.insert(seller.id, reply.version);
+        let negotiation = self.active_negotiations.get_mut(&negotiation.id).unwrap();
+        negotiation.add_quote(&reply.quote)?;
+        reply.add_messages(negotiation);
+        // self.database.update_negotiation(negotiation).await?;
+        let negotiation_id = negotiation.id;
+        self.time_response(negotiation_id).await;
+        self.publish_quote_received(negotiation_id, &reply.quote);
+        self.latest_quotes.insert(negotiation_id, reply.quote);
+        Ok(negotiation_id)
     }
 
-    pub async fn negotiate(&mut self, negotiation_id: TransactionId, counter_offer: f64) -> Result<()> {
-        let negotiation = self.active_negotiations.get_mut(&negotiation_id)
-            .ok_or(NegotiationError::Validation("Negotiation not found".to_string()))?;
-
-        if counter_offer <= negotiation.opening_bid {
-            return Err(NegotiationError::Validation("Counter offer must be less than opening bid".to_string()));
-        }
-
-        let seller = self.discovery.get_agent(negotiation.seller_id).await?;
-        let response = self.client
-            .post(&format!("{}/negotiate/{}", seller.endpoint, negotiation_id))
-            .json(&serde_json::json!({
-                "counter_offer": counter_offer
-            }))
-            .send()
-            .await?;
-
-        if response.status().is_success() {
-            let quote: Quote = response.json().await?;
-            negotiation.add_quote(&quote)?;
-            // self.database.update_negotiation(negotiation).await?;
-            Ok(())
-        } else {
-            Err(NegotiationError::Network(response.error_for_status().unwrap_err()))
+    /// Sends an RFQ for `spec` to up to `n_sellers` sellers at once and ranks
+    /// the quotes that arrive before the quote deadline. Each quote opens its
+    /// own negotiation, so the chosen one can be negotiated or accepted as usual.
+    #[tracing::instrument(skip_all, fields(product_id = %spec.product_id))]
+    pub async fn request_quotes_from_all(&mut self, spec: ProductSpec, n_sellers: usize) -> Result<QuoteComparison> {
+        let mut sellers = self.search_sellers(SearchRequest {
+            category: spec.category.clone(),
+            min_reputation: None,
+            payment_methods: self.config.payment_method.clone().map(|method| vec![method]),
+            region: self.config.region.clone(),
+            max_response_time_ms: self.config.max_response_time_ms,
+            rank_by_responsiveness: false,
+        }).await?;
+        sellers.sort_by_key(|seller| std::cmp::Reverse(seller.reputation_score));
+        sellers.truncate(n_sellers);
+        if sellers.is_empty() {
+            return Err(NegotiationError::Validation("No sellers found".to_string()));
         }
-    }
-
-    pub async fn accept_quote(&mut self, negotiation_id: TransactionId) -> Result<()> {
-        let quote = self.get_quote_for_negotiation(negotiation_id).await?;
-        let negotiation = self.active_negotiations.get_mut(&negotiation_id)
-            .ok_or(NegotiationError::Validation("Negotiation not found".to_string()))?;
 
-        if negotiation.quote_id.is_none() {
-            return Err(NegotiationError::Negotiation("No quote available".to_string()));
+        let deadline = Utc::now() + Duration::hours(self.config.default_ttl_hours as i64);
+        let rfqs: Vec<RFQ> = sellers.iter()
+            .map(|_| {
+                let mut rfq = RFQ::new(
+                    self.config.agent_id,
+                    spec.product_id.clone(),
+                    spec.quantity,
+                    spec.max_price,
+                    self.config.currency.clone(),
+                    deadline,
+                );
+                rfq.delivery_location = spec.delivery_location.clone();
+                self.request_expiry_warnings(&mut rfq);
+                rfq
+            })
+            .collect();
+        for rfq in &rfqs {
+            rfq.validate()?;
+            self.authorize_rfq(rfq, spec.category.as_deref())?;
         }
 
-        negotiation.accept(quote.price)?;
-        // self.database.update_negotiation(negotiation).await?;
-
-        let payment_result = self.settlement.create_payment(
-            negotiation.buyer_id,
-            negotiation.seller_id,
-            quote.price,
-            quote.currency.clone(),
-        ).await?;
+        let access_token = self.access_token().await?;
+        let (agent, access_token) = (&*self, access_token.as_str());
+        let responses = join_all(sellers.iter().zip(&rfqs).map(|(seller, rfq)| async move {
+            tokio::time::timeout(agent.quote_deadline, agent.send_rfq(seller, rfq, access_token))
+                .await
+                .unwrap_or_else(|_| Err(NegotiationError::Negotiation("No quote before the deadline".to_string())))
+        })).await;
 
-        if payment_result.success {
-            negotiation.settle()?;
-            // self.database.update_negotiation(negotiation).await?;
-
-            if let Some(_record) = negotiation.to_record() {
-                // self.database.add_negotiation_record(&record).await?;
+        let mut candidates = Vec::new();
+        let mut failures = Vec::new();
+        for ((seller, rfq), response) in sellers.into_iter().zip(rfqs).zip(responses) {
+            let candidate = match response {
+                Ok(reply) => {
+                    self.negotiated_versions.insert(seller.id, reply.version);
+                    self.open_quoted_negotiation(rfq, &seller, reply).await
+                }
+                Err(e) => Err(e),
+            };
+            match candidate {
+                Ok(candidate) => candidates.push(candidate),
+                Err(e) => failures.push(SellerFailure {
+                    seller_id: seller.id,
+                    seller_name: seller.name,
+                    error: e.to_string(),
+                }),
             }
-
-            self.trust.update_reputation(negotiation.seller_id, 5).await?;
-            self.trust.update_reputation(negotiation.buyer_id, 3).await?;
         }
 
-        Ok(())
-    }
-
-    pub async fn reject_quote(&mut self, negotiation_id: TransactionId) -> Result<()> {
-        let negotiation = self.active_negotiations.get_mut(&negotiation_id)
-            .ok_or(NegotiationError::Validation("Negotiation not found".to_string()))?;
-
-        negotiation.reject()?;
-        // self.database.update_negotiation(negotiation).await?;
-
-        self.trust.update_reputation(negotiation.seller_id, -2).await?;
-        Ok(())
-    }
-
-    async fn find_product(&self, product_id: &str) -> Result<Product> {
-        let response = self.client
-            .get(&format!("{}/discovery/products/{}", self.discovery.endpoint(), product_id))
-            .send()
-            .await?;
-
-        if response.status().is_success() {
-            let product: Product = response.json().await?;
-            Ok(product)
-        } else {
-            Err(NegotiationError::ProductNotFound(product_id.to_string()))
-        }
+        Ok(QuoteComparison {
+            ranked: rank_quotes(candidates),
+            failures,
+        })
     }
 
-    async fn get_quote_for_negotiation(&self, negotiation_id: TransactionId) -> Result<Quote> {
-        // For now, we'll look for the negotiation in active negotiations
-        let negotiation = self.active_negotiations.get(&negotiation_id)
-            .ok_or(NegotiationError::Validation("Negotiation not found".to_string()))?;
-
-        let seller = self.discovery.get_agent(negotiation.seller_id).await?;
-        let response = self.client
-            .get(&format!("{}/quote/{}", seller.endpoint, negotiation.rfq_id))
-            .send()
-            .await?;
-
-        if response.status().is_success() {
-            let quote: Quote = response.json().await?;
-            Ok(quote)
-        } else {
-            Err(NegotiationError::Negotiation("Quote not found".to_string()))
-        }
-    }
+    /// Publishes `spec` as a reverse auction on the discovery service. Sellers
+    /// bid until the window closes and the lowest bid wins.
+    pub async fn start_auction(&self, spec: ProductSpec, visibility: BidVisibility, duration: Duration) -> Result<Auction> {
+        let deadline = Utc::now() + Duration::hours(self.config.default_ttl_hours as i64);
+        let mut rfq = RFQ::new(
+            self.config.agent_id,
+            spec.product_id,
+            spec.quantity,
+            spec.max_price,
+            self.config.currency.clone(),
+            deadline,
+        );
+        rfq.delivery_location = spec.delivery_location;
+        rfq.validate()?;
 
-    pub fn get_active_negotiations(&self) -> Vec<&Negotiation> {
-        self.active_negotiations.values().collect()
+        self.discovery.create_auction(&CreateAuctionRequest {
+            rfq,
+            visibility,
+            duration_seconds: duration.num_seconds(),
+            anti_sniping: AntiSniping::default(),
+        }).await
     }
-}
-
-pub struct SellerAgent {
-    config: SellerAgentConfig,
-    discovery: DiscoveryService,
-    trust: TrustSystem,
-}
 
-impl SellerAgent {
-    pub async fn new(
-        config: SellerAgentConfig,
-        discovery: DiscoveryService,
-        trust: TrustSystem,
-    ) -> Result<Self> {
-        Ok(Self {
-            config,
-            discovery,
-            trust,
-        })
+    pub async fn auction_status(&self, auction_id: Uuid) -> Result<AuctionView> {
+        self.discovery.get_auction(auction_id).await
     }
 
+    /// Registers the buyer with discovery so it can bid on seller listings.
     pub async fn register(&self) -> Result<()> {
         let agent_info = AgentInfo {
             id: self.config.agent_id,
-            agent_type: AgentType::Seller,
+            agent_type: AgentType::Buyer,
             name: self.config.name.clone(),
             endpoint: self.config.endpoint.clone(),
-            public_key: generate_public_key().await?,
+            public_key: self.webhook_signer.public_key(),
             reputation_score: 100,
-            products: self.config.products.clone(),
-            payment_methods: self.config.payment_methods.clone(),
+            products: vec![],
+            payment_methods: vec![PaymentMethod::Stripe],
+            protocol_versions: protocol::supported_versions(),
+            preferred_languages: language::preferred_languages(&self.config.preferred_languages, self.config.locale),
+            tls_fingerprint: None,
             created_at: Utc::now(),
             last_active: Utc::now(),
         };
 
-        self.discovery.register_agent(agent_info).await?;
-        Ok(())
+        self.discovery.register_agent(agent_info).await
     }
 
-    pub async fn handle_rfq(&mut self, rfq: RFQ) -> Result<Quote> {
-        let product_id = rfq.product_id.clone();
-        let product = self.config.products.iter()
-            .find(|p| p.id == product_id)
-            .ok_or(NegotiationError::ProductNotFound(product_id))?;
-
-        if rfq.quantity > product.stock_quantity {
-            return Err(NegotiationError::Validation("Insufficient stock".to_string()));
-        }
+    /// Removes the buyer from the registry, unless it was handed over to
+    /// another host.
+    pub async fn deregister(&self) -> Result<()> {
+        self.discovery.deregister_agent(self.config.agent_id, &self.config.endpoint).await
+    }
 
-        let buyer_reputation = self.trust.get_reputation(rfq.buyer_id).await?;
-        if buyer_reputation < 50 {
-            return Err(NegotiationError::InsufficientReputation(buyer_reputation));
+    /// Snapshot of what the buyer holds in memory, for the host taking over
+    /// from it.
+    pub fn export_state(&self) -> BuyerState {
+        BuyerState {
+            format_version: STATE_FORMAT_VERSION,
+            agent_id: self.config.agent_id,
+            endpoint: self.config.endpoint.clone(),
+            exported_at: Utc::now(),
+            negotiations: self.active_negotiations.values().cloned().collect(),
+            latest_quotes: self.latest_quotes.clone(),
+            negotiated_versions: self.negotiated_versions.clone(),
+            demand_tokens: self.demand_tokens.clone(),
+            agreements: self.agreements.values().cloned().collect(),
+            deadlines: self.deadlines.clone(),
+            pending_settlements: self.pending_settlements.clone(),
         }
+    }
 
-        let base_price = product.base_price * rfq.quantity as f64;
-        let dynamic_pricing_factor = self.calculate_dynamic_pricing(&rfq, buyer_reputation).await?;
-        let final_price = base_price * dynamic_pricing_factor;
-
-        let quote = Quote::new(
-            rfq.id,
-            self.config.agent_id,
-            final_price,
-            product.currency.clone(),
-            rfq.quantity,
-            3600, // 1 hour TTL
-        );
+    /// Picks up where the exporting host left off. Negotiations the buyer
+    /// already holds are replaced by the exported ones; it authenticates
+    /// with tokens of its own.
+    pub fn import_state(&mut self, state: BuyerState) -> Result<()> {
+        handover::check_state(state.format_version, state.agent_id, self.config.agent_id)?;
+        self.active_negotiations.extend(state.negotiations.into_iter().map(|negotiation| (negotiation.id, negotiation)));
+        self.latest_quotes.extend(state.latest_quotes);
+        self.deadlines.extend(state.deadlines);
+        self.pending_settlements.extend(state.pending_settlements);
+        self.negotiated_versions.extend(state.negotiated_versions);
+        self.demand_tokens.extend(state.demand_tokens);
+        self.agreements.extend(state.agreements.into_iter().map(|agreement| (agreement.id, agreement)));
+        Ok(())
+    }
 
-        Ok(quote)
+    /// Moves the buyer's registration from the host at `from_endpoint` to
+    /// this one.
+    pub async fn hand_over(&self, from_endpoint: &str) -> Result<AgentInfo> {
+        let request = handover::handover_request(from_endpoint, &self.config.endpoint)?;
+        self.discovery.hand_over(self.config.agent_id, &request).await
     }
 
-    pub async fn handle_negotiation(&self, negotiation_id: TransactionId, counter_offer: f64) -> Result<Quote> {
-        // For now, this is a mock implementation since database is not implemented
-        // let negotiation = self.database.get_negotiation(negotiation_id).await?
-        //     .ok_or(NegotiationError::Validation("Negotiation not found".to_string()))?;
+    /// Publishes an anonymous demand signal in the buyer's currency for
+    /// subscribed sellers to answer.
+    pub async fn signal_demand(
+        &mut self,
+        category: String,
+        product_id: Option<String>,
+        quantity: u32,
+        max_price: Decimal,
+        duration: Duration,
+    ) -> Result<DemandSignal> {
+        let access_token = self.access_token().await?;
+        let published = self.discovery.publish_demand(&access_token, &PublishDemandRequest {
+            category,
+            product_id,
+            quantity,
+            max_price,
+            currency: self.config.currency.clone(),
+            duration_seconds: duration.num_seconds(),
+        }).await?;
+        self.demand_tokens.insert(published.signal.id, published.response_token);
+        Ok(published.signal)
+    }
 
-        // Mock negotiation data - in real implementation this would come from database
-        let buyer_id = uuid::Uuid::new_v4();
-        let opening_bid = 100.0; // Mock opening bid
+    pub async fn demand_offers(&self, signal_id: Uuid) -> Result<Vec<DemandResponse>> {
+        self.discovery.demand_responses(signal_id, self.demand_token(signal_id)?).await
+    }
 
-        if buyer_id == self.config.agent_id {
-            return Err(NegotiationError::Auth("Unauthorized negotiation".to_string()));
-        }
+    pub async fn withdraw_demand(&mut self, signal_id: Uuid) -> Result<DemandSignal> {
+        let signal = self.discovery.withdraw_demand(signal_id, self.demand_token(signal_id)?).await?;
+        self.demand_tokens.remove(&signal_id);
+        Ok(signal)
+    }
 
-        let min_acceptable_price = opening_bid * 0.8; // 20% minimum discount
-        if counter_offer < min_acceptable_price {
-            return Err(NegotiationError::Negotiation("Counter offer too low".to_string()));
+    /// Takes a seller's offer by sending them an RFQ capped at the offered
+    /// price, which opens a negotiation like any other quote.
+    pub async fn take_demand_offer(&mut self, signal_id: Uuid, response_id: Uuid) -> Result<TransactionId> {
+        let offer = self.demand_offers(signal_id).await?
+            .into_iter()
+            .find(|offer| offer.id == response_id)
+            .ok_or_else(|| NegotiationError::NotFound(format!("Offer {} on signal {}", response_id, signal_id)))?;
+        if offer.valid_until <= Utc::now() {
+            return Err(NegotiationError::Validation("Offer has expired".to_string()));
         }
 
-        let buyer_reputation = self.trust.get_reputation(buyer_id).await?;
-        let acceptance_threshold = match buyer_reputation {
-            score if score >= 80 => 0.95, // High trust buyers get better terms
-            score if score >= 60 => 0.90,
-            _ => 0.85,
-        };
-
-        let adjusted_price = counter_offer * acceptance_threshold;
-        let quote = Quote::new(
-            negotiation_id, // Using negotiation_id as rfq_id for mock
+        let seller = self.seller(offer.seller_id).await?;
+        let mut rfq = RFQ::new(
             self.config.agent_id,
-            adjusted_price,
-            "USD".to_string(), // Should come from product
-            1, // Mock quantity
-            1800, // 30 minutes TTL for counter offers
+            offer.product_id.clone(),
+            offer.quantity,
+            offer.price,
+            offer.currency.clone(),
+            Utc::now() + Duration::hours(self.config.default_ttl_hours as i64),
         );
+        rfq.metadata.insert(DEMAND_RESPONSE_METADATA_KEY.to_string(), offer.id.to_string());
+        self.request_expiry_warnings(&mut rfq);
+        rfq.validate()?;
 
-        Ok(quote)
+        let access_token = self.access_token().await?;
+        let reply = self.send_rfq(&seller, &rfq, &access_token).await?;
+        self.negotiated_versions.insert(seller.id, reply.version);
+        Ok(self.open_quoted_negotiation(rfq, &seller, reply).await?.negotiation_id)
     }
 
-    async fn calculate_dynamic_pricing(&self, rfq: &RFQ, buyer_reputation: u32) -> Result<f64> {
-        let mut factor = 1.0;
+    fn demand_token(&self, signal_id: Uuid) -> Result<&str> {
+        self.demand_tokens.get(&signal_id)
+            .map(String::as_str)
+            .ok_or_else(|| NegotiationError::Validation(format!("Demand signal {} was not published by this agent", signal_id)))
+    }
 
-        // Volume discount
-        if rfq.quantity > 10 {
-            factor *= 0.95;
-        }
+    /// Forms a coalition for `product_id` with this buyer as coordinator and
+    /// its own `quantity` as the first member's.
+    pub async fn form_coalition(
+        &self,
+        product_id: String,
+        quantity: u32,
+        max_unit_price: Decimal,
+        min_quantity: u32,
+        duration: Duration,
+    ) -> Result<CoalitionView> {
+        self.discovery.form_coalition(&FormCoalitionRequest {
+            coordinator_id: self.config.agent_id,
+            product_id,
+            currency: self.config.currency.clone(),
+            quantity,
+            max_unit_price,
+            min_quantity,
+            duration_seconds: duration.num_seconds(),
+        }).await
+    }
 
-        // Reputation bonus
-        if buyer_reputation >= 80 {
-            factor *= 0.98;
-        }
+    pub async fn open_coalitions(&self, product_id: Option<&str>) -> Result<Vec<Coalition>> {
+        self.discovery.list_coalitions(product_id).await
+    }
 
-        // Time-based pricing
-        let hour = Utc::now().hour();
-        if hour >= 9 && hour <= 17 { // Business hours
-            factor *= 1.02;
-        }
+    pub async fn coalition(&self, coalition_id: Uuid) -> Result<CoalitionView> {
+        self.discovery.get_coalition(coalition_id).await
+    }
 
-        // Demand-based pricing (placeholder - would integrate with market data)
-        factor *= 1.01;
+    pub async fn join_coalition(&self, coalition_id: Uuid, quantity: u32) -> Result<CoalitionMember> {
+        self.discovery.join_coalition(coalition_id, &JoinCoalitionRequest {
+            buyer_id: self.config.agent_id,
+            quantity,
+        }).await
+    }
 
-        Ok(factor)
+    pub async fn leave_coalition(&self, coalition_id: Uuid) -> Result<CoalitionView> {
+        self.discovery.leave_coalition(coalition_id, self.config.agent_id).await
     }
+
+    /// Locks a coalition this buyer coordinates and sends a seller of its
+    /// product one RFQ for the members' combined quantity. The negotiation it
+    /// opens is this buyer's, so it can be countered like any other.
+    pub async fn negotiate_for_coalition(&mut self, coalition_id: Uuid) -> Result<TransactionId> {
+        let view = self.discovery.get_coalition(coalition_id).await?;
+        if view.coalition.coordinator_id != self.config.agent_id {
+            return Err(NegotiationError::Auth("Only the coordinator negotiates for a coalition".to_string()));
+        }
+        let seller = self.seller_for_product(&view.coalition.product_id).await?;
+        let mut rfq = view.aggregated_rfq(Utc::now() + Duration::hours(self.config.default_ttl_hours as i64));
+        self.request_expiry_warnings(&mut rfq);
+        rfq.validate()?;
+
+        let access_token = self.access_token().await?;
+        let reply = self.send_rfq(&seller, &rfq, &access_token).await?;
+        self.negotiated_versions.insert(seller.id, reply.version);
+        let negotiation_id = self.open_quoted_negotiation(rfq, &seller, reply).await?.negotiation_id;
+        self.discovery.lock_coalition(coalition_id, &LockCoalitionRequest {
+            coordinator_id: self.config.agent_id,
+            seller_id: seller.id,
+            negotiation_id,
+        }).await?;
+        Ok(negotiation_id)
+    }
+
+    /// Accepts the seller's latest quote for a coalition this buyer
+    /// coordinates and splits its price into the members' shares. Members
+    /// then pay their own shares with [`BuyerAgent::pay_coalition_share`].
+    pub async fn agree_coalition_price(&mut self, coalition_id: Uuid) -> Result<CoalitionView> {
+        let view = self.discovery.get_coalition(coalition_id).await?;
+        let negotiation_id = view.coalition.negotiation_id
+            .ok_or_else(|| NegotiationError::Negotiation(format!("Coalition {} has no negotiation yet", coalition_id)))?;
+        let quote = self.get_quote_for_negotiation(negotiation_id).await?;
+        quote.validate()?;
+        if quote.firmness == QuoteFirmness::Indicative {
+            return Err(NegotiationError::Negotiation(
+                "Indicative quotes must be firmed up before they can be accepted".to_string()
+            ));
+        }
+        if quote.is_expired() {
+            self.events.publish(EventKind::Expired { negotiation_id, quote_id: quote.id });
+            return Err(NegotiationError::QuoteExpired);
+        }
+        let negotiation = self.active_negotiations.get(&negotiation_id)
+            .ok_or(NegotiationError::NotFound("Negotiation".to_string()))?;
+        let close_price = self.quote_price_in_budget_currency(negotiation, &quote).await?;
+
+        let agreed = self.discovery.agree_coalition(coalition_id, &AgreeCoalitionRequest {
+            coordinator_id: self.config.agent_id,
+            price: close_price.amount,
+        }).await?;
+        let negotiation = self.active_negotiations.get_mut(&negotiation_id)
+            .ok_or(NegotiationError::NotFound("Negotiation".to_string()))?;
+        negotiation.accept(close_price.amount)?;
+        self.events.publish(EventKind::Accepted { negotiation_id, price: close_price });
+        Ok(agreed)
+    }
+
+    /// Pays this buyer's share of a coalition's agreed price to its seller.
+    /// The coordinator settles the negotiation once every share is paid.
+    pub async fn pay_coalition_share(&mut self, coalition_id: Uuid) -> Result<CoalitionView> {
+        let view = self.discovery.get_coalition(coalition_id).await?;
+        let seller_id = view.coalition.seller_id
+            .ok_or_else(|| NegotiationError::Negotiation(format!("Coalition {} has no seller yet", coalition_id)))?;
+        let member = view.member(self.config.agent_id)
+            .ok_or_else(|| NegotiationError::Validation("Buyer is not a member".to_string()))?;
+        let share = member.share
+            .ok_or_else(|| NegotiationError::Negotiation(format!("Coalition {} has no agreed price yet", coalition_id)))?;
+
+        let payment_result = self.pay(
+            seller_id,
+            Money::new(share, view.coalition.currency.clone()),
+            format!("coalition-{}-{}", coalition_id, self.config.agent_id),
+        ).await?;
+        // Retrying reuses the payment, so a share still awaiting confirmation
+        // is recorded by a later call
+        let payment_result = match payment_result.status {
+            PaymentStatus::AwaitingConfirmation | PaymentStatus::Processing => {
+                self.settlement.poll_payment(&payment_result.payment_id).await?.to_result()
+            }
+            _ => payment_result,
+        };
+        if !payment_result.is_confirmed() {
+            return Err(NegotiationError::Payment(format!("Payment {} of coalition share is {:?}", payment_result.payment_id, payment_result.status)));
+        }
+        let view = self.discovery.record_coalition_payment(coalition_id, &CoalitionPaymentRequest {
+            buyer_id: self.config.agent_id,
+            payment_id: payment_result.payment_id.clone(),
+        }).await?;
+
+        if view.coalition.status == CoalitionStatus::Settled {
+            if let Some(negotiation) = view.coalition.negotiation_id.and_then(|id| self.active_negotiations.get_mut(&id)) {
+                negotiation.settle()?;
+                self.settlement.metrics().negotiation_settled(negotiation.created_at);
+                self.events.publish(EventKind::Settled { negotiation_id: negotiation.id, payment_id: payment_result.payment_id });
+                let deal_value = negotiation.close_price;
+                apply_reputation_change(&mut self.trust, &self.settlement, seller_id, SETTLED_SELLER_REPUTATION, deal_value).await;
+                apply_reputation_change(&mut self.trust, &self.settlement, self.config.agent_id, SETTLED_BUYER_REPUTATION, deal_value).await;
+            }
+        }
+        Ok(view)
+    }
+
+    /// Asks a seller to price a rate card for committed quantities of its
+    /// products over `duration_days`, in the buyer's currency.
+    pub async fn propose_agreement(
+        &mut self,
+        seller_id: AgentId,
+        items: Vec<AgreementItemRequest>,
+        duration_days: u32,
+    ) -> Result<SupplyAgreement> {
+        let request = AgreementRequest {
+            buyer_id: self.config.agent_id,
+            currency: self.config.currency.clone(),
+            items,
+            duration_days,
+        };
+        request.validate()?;
+
+        let seller = self.seller(seller_id).await?;
+        let version = self.protocol_version_for(&seller)?;
+        let response = self.client
+            .post(format!("{}/agreements", seller.endpoint))
+            .header(PROTOCOL_VERSION_HEADER, version.to_string())
+            .headers(telemetry::trace_headers())
+            .json(&request)
+            .send()
+            .await?
+            .error_for_status()?;
+        self.record_protocol_version(seller.id, &response);
+
+        let agreement: SupplyAgreement = response.json().await?;
+        self.agreements.insert(agreement.id, agreement.clone());
+        Ok(agreement)
+    }
+
+    /// Accepts a rate card the seller proposed. The seller checks the buyer
+    /// through a session token scoped to the agreement.
+    pub async fn accept_agreement(&mut self, agreement_id: Uuid) -> Result<SupplyAgreement> {
+        let seller_id = self.agreement(agreement_id)?.seller_id;
+        let seller = self.seller(seller_id).await?;
+        let version = self.protocol_version_for(&seller)?;
+        let session_token = self.session_token(agreement_id).await?;
+        let response = self.client
+            .post(format!("{}/agreements/{}/accept", seller.endpoint, agreement_id))
+            .header(PROTOCOL_VERSION_HEADER, version.to_string())
+            .headers(telemetry::trace_headers())
+            .bearer_auth(session_token)
+            .send()
+            .await?
+            .error_for_status()?;
+        self.record_protocol_version(seller.id, &response);
+
+        let agreement: SupplyAgreement = response.json().await?;
+        self.agreements.insert(agreement.id, agreement.clone());
+        Ok(agreement)
+    }
+
+    /// Orders under an accepted rate card. The seller quotes the agreed rate,
+    /// which is accepted and paid for straight away without negotiating.
+    pub async fn order_under_agreement(&mut self, agreement_id: Uuid, product_id: String, quantity: u32) -> Result<TransactionId> {
+        let agreement = self.agreement(agreement_id)?;
+        if !agreement.is_in_force_at(Utc::now()) {
+            return Err(NegotiationError::Validation(format!("Agreement {} is not in force", agreement_id)));
+        }
+        let item = agreement.item(&product_id)
+            .ok_or_else(|| NegotiationError::Validation(format!("{} is not on the rate card", product_id)))?;
+        if quantity > item.remaining_quantity() {
+            return Err(NegotiationError::Validation(format!(
+                "Only {} units of {} remain under the agreement", item.remaining_quantity(), product_id
+            )));
+        }
+        let agreed_price = item.unit_price * Decimal::from(quantity);
+        let (seller_id, currency) = (agreement.seller_id, agreement.currency.clone());
+
+        let seller = self.seller(seller_id).await?;
+        let mut rfq = RFQ::new(
+            self.config.agent_id,
+            product_id.clone(),
+            quantity,
+            agreed_price,
+            currency.clone(),
+            Utc::now() + Duration::hours(self.config.default_ttl_hours as i64),
+        );
+        rfq.metadata.insert(AGREEMENT_METADATA_KEY.to_string(), agreement_id.to_string());
+        rfq.validate()?;
+
+        let access_token = self.access_token().await?;
+        let reply = self.send_rfq(&seller, &rfq, &access_token).await?;
+        self.negotiated_versions.insert(seller.id, reply.version);
+        let quote = &reply.quote;
+        if quote.price != agreed_price || quote.currency != currency {
+            return Err(NegotiationError::Negotiation(format!(
+                "Seller quoted {} {} instead of the agreed {} {}", quote.price, quote.currency, agreed_price, currency
+            )));
+        }
+
+        let negotiation_id = self.open_quoted_negotiation(rfq, &seller, reply).await?.negotiation_id;
+        if let Some(item) = self.agreements.get_mut(&agreement_id)
+            .and_then(|agreement| agreement.items.iter_mut().find(|item| item.product_id == product_id))
+        {
+            item.ordered_quantity += quantity;
+        }
+        self.accept_quote(negotiation_id).await?;
+        Ok(negotiation_id)
+    }
+
+    pub fn get_agreements(&self) -> Vec<&SupplyAgreement> {
+        self.agreements.values().collect()
+    }
+
+    fn agreement(&self, agreement_id: Uuid) -> Result<&SupplyAgreement> {
+        self.agreements.get(&agreement_id)
+            .ok_or_else(|| NegotiationError::Validation(format!("Agreement {} is not known to this agent", agreement_id)))
+    }
+
+    pub async fn open_listings(&self) -> Result<Vec<Listing>> {
+        self.discovery.list_listings().await
+    }
+
+    /// Bids on a seller's listing, in the buyer's currency. Taking a Dutch
+    /// listing's current ask wins it straight away.
+    pub async fn bid_on_listing(&self, listing_id: Uuid, price: Decimal) -> Result<ListingBid> {
+        self.discovery.bid_on_listing(listing_id, &ListingBidRequest {
+            buyer_id: self.config.agent_id,
+            price,
+            currency: self.config.currency.clone(),
+        }).await
+    }
+
+    /// Pays for a won listing through the settlement service, as for an
+    /// accepted quote.
+    pub async fn settle_listing(&mut self, listing_id: Uuid) -> Result<Negotiation> {
+        let view = self.discovery.get_listing(listing_id).await?;
+        let negotiation = view.negotiation
+            .ok_or_else(|| NegotiationError::Negotiation(format!("Listing {} has not been won yet", listing_id)))?;
+        if negotiation.buyer_id != self.config.agent_id {
+            return Err(NegotiationError::Auth("Listing was won by another buyer".to_string()));
+        }
+        if self.active_negotiations.get(&negotiation.id).is_some_and(|known| known.status == NegotiationStatus::Settled) {
+            return Err(NegotiationError::Negotiation(format!("Listing {} is already settled", listing_id)));
+        }
+        let price = negotiation.close_price
+            .ok_or_else(|| NegotiationError::Negotiation("Won listing has no price".to_string()))?;
+
+        let payment_result = self.pay(
+            negotiation.seller_id,
+            Money::new(price, negotiation.currency.clone()),
+            format!("negotiation-{}", negotiation.id),
+        ).await?;
+
+        let negotiation_id = negotiation.id;
+        self.active_negotiations.insert(negotiation_id, negotiation);
+        self.settle_once_confirmed(negotiation_id, &payment_result, false).await?;
+        self.active_negotiations.get(&negotiation_id).cloned()
+            .ok_or(NegotiationError::NotFound("Negotiation".to_string()))
+    }
+
+    /// Times the seller's latest response in a negotiation, for this buyer's
+    /// trust records and the registry's rolling averages.
+    async fn time_response(&mut self, negotiation_id: TransactionId) {
+        let Some(negotiation) = self.active_negotiations.get(&negotiation_id) else {
+            return;
+        };
+        let seller_id = negotiation.seller_id;
+        let Some((kind, response_ms)) = responsiveness::last_response(&negotiation.messages, seller_id) else {
+            return;
+        };
+        self.trust.record_response_time(seller_id, kind, response_ms);
+        if let Err(e) = self.discovery.report_response_time(seller_id, &ResponseTimeReport { kind, response_ms }).await {
+            tracing::warn!("Failed to report response time of seller {}: {}", seller_id, e);
+        }
+    }
+
+    fn publish_quote_received(&self, negotiation_id: TransactionId, quote: &Quote) {
+        self.events.publish(EventKind::QuoteReceived {
+            negotiation_id,
+            quote_id: quote.id,
+            seller_id: quote.seller_id,
+            price: quote.amount(),
+        });
+    }
+
+    /// Posts an RFQ to a seller with the buyer's access token, returning its
+    /// quote and the protocol version it answered with.
+    #[tracing::instrument(skip_all, fields(rfq_id = %rfq.id, seller_id = %seller.id))]
+    async fn send_rfq(&self, seller: &AgentInfo, rfq: &RFQ, access_token: &str) -> Result<RfqReply> {
+        self.authorize_rfq(rfq, None)?;
+        let rfq = &rfq.stamped(&self.webhook_signer)?;
+        let version = self.protocol_version_for(seller)?;
+        let language = self.language_for(seller);
+        self.events.publish(EventKind::RfqSent {
+            rfq_id: rfq.id,
+            buyer_id: rfq.buyer_id,
+            seller_id: seller.id,
+            product_id: rfq.product_id.clone(),
+            quantity: rfq.quantity,
+        });
+        let sent_at = Utc::now();
+        let mut request = self.client
+            .post(&format!("{}/quote", seller.endpoint))
+            .header(PROTOCOL_VERSION_HEADER, version.to_string())
+            .header(reqwest::header::ACCEPT_LANGUAGE, language.code())
+            .header(IDEMPOTENCY_KEY_HEADER, rfq.id.to_string())
+            .bearer_auth(access_token)
+            .headers(telemetry::trace_headers());
+        if let Some((token, _)) = &self.delegation {
+            request = request.header(DELEGATION_HEADER, token.as_str());
+        }
+        let response = request.json(rfq).send().await?;
+
+        if response.status().is_success() {
+            let answered = answered_protocol_version(&response);
+            let quote: Quote = response.json().await?;
+            self.replay_guard.check_signed("quote", &quote, Some(&seller.public_key)).await?;
+            Ok(RfqReply {
+                rfq_summary: language.rfq_summary(&rfq.product_id, rfq.quantity),
+                quote,
+                language,
+                version: answered,
+                sent_at,
+                received_at: Utc::now(),
+            })
+        } else {
+            Err(NegotiationError::Network(response.error_for_status().unwrap_err()))
+        }
+    }
+
+    async fn open_quoted_negotiation(&mut self, rfq: RFQ, seller: &AgentInfo, reply: RfqReply) -> Result<RankedQuote> {
+        reply.quote.validate()?;
+        let deadline = rfq.deadline;
+        let mut negotiation = Negotiation::new(rfq, seller.id)
+            .with_delegation_chain(self.delegation_chain()?);
+        negotiation.add_quote(&reply.quote)?;
+        reply.add_messages(&mut negotiation);
+        let quote = reply.quote;
+        let price = self.quote_price_in_budget_currency(&negotiation, &quote).await?;
+        let landed_cost = self.converter.convert(&quote.total()?, &negotiation.currency).await?;
+        let within_budget = price.amount <= negotiation.opening_bid;
+
+        let negotiation_id = negotiation.id;
+        // self.database.create_negotiation(&negotiation).await?;
+        self.active_negotiations.insert(negotiation_id, negotiation);
+        self.deadlines.insert(negotiation_id, deadline);
+        self.time_response(negotiation_id).await;
+        self.publish_quote_received(negotiation_id, &quote);
+        self.latest_quotes.insert(negotiation_id, quote.clone());
+
+        Ok(RankedQuote {
+            negotiation_id,
+            seller_id: seller.id,
+            seller_name: seller.name.clone(),
+            delivery_days: quote.delivery_days(),
+            quote,
+            price,
+            landed_cost,
+            seller_reputation: seller.reputation_score,
+            within_budget,
+            score: 0.0,
+        })
+    }
+
+    #[tracing::instrument(skip(self), fields(%negotiation_id, %counter_offer))]
+    pub async fn negotiate(&mut self, negotiation_id: TransactionId, counter_offer: Decimal) -> Result<()> {
+        self.check_deadline(negotiation_id, Utc::now()).await?;
+        let negotiation = self.active_negotiations.get(&negotiation_id)
+            .ok_or(NegotiationError::NotFound("Negotiation".to_string()))?;
+
+        if counter_offer > negotiation.opening_bid {
+            return Err(NegotiationError::Validation("Counter offer cannot exceed opening bid".to_string()));
+        }
+        if self.latest_quotes.get(&negotiation_id).is_some_and(|quote| quote.metadata.contains_key(AGREEMENT_METADATA_KEY)) {
+            return Err(NegotiationError::Negotiation("Orders under a supply agreement are priced by its rate card".to_string()));
+        }
+
+        let (product_id, quantity) = (negotiation.product_id.clone(), negotiation.quantity);
+        let seller = self.seller(negotiation.seller_id).await?;
+        let version = self.protocol_version_for(&seller)?;
+        let language = self.language_for(&seller);
+        let session_token = self.session_token(negotiation_id).await?;
+        let sent_at = Utc::now();
+        let response = self.client
+            .post(&format!("{}/negotiate/{}", seller.endpoint, negotiation_id))
+            .header(PROTOCOL_VERSION_HEADER, version.to_string())
+            .header(reqwest::header::ACCEPT_LANGUAGE, language.code())
+            .header(IDEMPOTENCY_KEY_HEADER, Uuid::new_v4().to_string())
+            .headers(telemetry::trace_headers())
+            .bearer_auth(session_token)
+            .json(&serde_json::json!({
+                "counter_offer": counter_offer,
+                "product_id": product_id,
+                "quantity": quantity
+            }))
+            .send()
+            .await?;
+
+        if response.status().is_success() {
+            self.record_protocol_version(seller.id, &response);
+            let quote: Quote = response.json().await?;
+            let received_at = Utc::now();
+            let negotiation = self.active_negotiations.get_mut(&negotiation_id)
+                .ok_or(NegotiationError::NotFound("Negotiation".to_string()))?;
+            let counter_price = Money::new(counter_offer, negotiation.currency.clone());
+            negotiation.add_message(negotiation.buyer_id, MessageType::CounterOffer, language.counter_offer(&counter_price), sent_at);
+            self.events.publish(EventKind::CounterOffered { negotiation_id, price: counter_price });
+            negotiation.add_counter_quote(&quote)?;
+            negotiation.add_message(quote.seller_id, MessageType::Quote, language.quote(&quote.amount()), received_at);
+            // self.database.update_negotiation(negotiation).await?;
+            self.time_response(negotiation_id).await;
+            self.publish_quote_received(negotiation_id, &quote);
+            self.latest_quotes.insert(negotiation_id, quote);
+            Ok(())
+        } else {
+            let error = response.error_for_status_ref().unwrap_err();
+            // Sellers explain a declined counter offer with a message of their own
+            if let Ok(SellerRefusal { negotiation_message: Some(message) }) = response.json().await {
+                if let Some(negotiation) = self.active_negotiations.get_mut(&negotiation_id) {
+                    if message.negotiation_id == negotiation_id && message.sender_id == negotiation.seller_id {
+                        negotiation.add_message(message.sender_id, message.message_type, message.content, message.created_at);
+                    }
+                }
+            }
+            Err(NegotiationError::Network(error))
+        }
+    }
+
+    /// Calls off an accepted deal. Reasons that need the seller's consent are
+    /// put to the seller first; penalties are applied once it's cancelled.
+    pub async fn cancel_deal(
+        &mut self,
+        negotiation_id: TransactionId,
+        reason: CancellationReason,
+        note: Option<String>,
+    ) -> Result<DealChange> {
+        let mut negotiation = self.active_negotiations.get(&negotiation_id)
+            .ok_or(NegotiationError::NotFound("Negotiation".to_string()))?
+            .clone();
+
+        let mut change = self.settlement.deal_changes()
+            .request_cancellation(&mut negotiation, self.config.agent_id, reason, note).await?;
+        if change.status == ChangeStatus::Pending {
+            change = self.put_change_to_seller(&mut negotiation, &change).await?;
+        }
+
+        if change.status == ChangeStatus::Approved {
+            self.apply_cancellation_penalty(&negotiation, &change).await?;
+        }
+        self.active_negotiations.insert(negotiation_id, negotiation);
+        Ok(change)
+    }
+
+    /// Asks for money back on a settled deal, all of it unless `amount` is
+    /// given. The refund waits for the seller or an operator to approve it,
+    /// and costs the seller reputation then if it's their fault.
+    pub async fn request_refund(
+        &mut self,
+        negotiation_id: TransactionId,
+        amount: Option<Decimal>,
+        reason: RefundReason,
+    ) -> Result<Refund> {
+        if !self.active_negotiations.contains_key(&negotiation_id) {
+            return Err(NegotiationError::NotFound("Negotiation".to_string()));
+        }
+        let payment = self.settlement.get_negotiation_payment(negotiation_id).await?;
+        self.settlement
+            .refund_payment(&payment.payment_id, self.config.agent_id, RefundRequest { amount, reason }).await
+    }
+
+    /// Proposes new terms for an accepted deal, which apply if the seller agrees.
+    pub async fn renegotiate_deal(
+        &mut self,
+        negotiation_id: TransactionId,
+        price: Decimal,
+        quantity: u32,
+        note: Option<String>,
+    ) -> Result<DealChange> {
+        let mut negotiation = self.active_negotiations.get(&negotiation_id)
+            .ok_or(NegotiationError::NotFound("Negotiation".to_string()))?
+            .clone();
+
+        let proposal = self.settlement.deal_changes()
+            .propose_renegotiation(&negotiation, self.config.agent_id, price, quantity, note).await?;
+        let change = self.put_change_to_seller(&mut negotiation, &proposal).await?;
+        self.active_negotiations.insert(negotiation_id, negotiation);
+        Ok(change)
+    }
+
+    /// Asks the seller to consent to a change and records the answer. A seller
+    /// that can't be reached is taken to decline.
+    async fn put_change_to_seller(&mut self, negotiation: &mut Negotiation, change: &DealChange) -> Result<DealChange> {
+        let approved = match self.request_seller_consent(negotiation, change).await {
+            Ok(approved) => approved,
+            Err(e) => {
+                tracing::warn!("No answer from seller on deal change {}: {}", change.id, e);
+                false
+            }
+        };
+        self.settlement.deal_changes()
+            .respond(negotiation, change.id, negotiation.seller_id, approved).await
+    }
+
+    async fn request_seller_consent(&mut self, negotiation: &Negotiation, change: &DealChange) -> Result<bool> {
+        #[derive(Deserialize)]
+        struct Consent {
+            approved: bool,
+        }
+
+        let seller = self.seller(negotiation.seller_id).await?;
+        let version = self.protocol_version_for(&seller)?;
+        let session_token = self.session_token(negotiation.id).await?;
+        let consent: Consent = self.client
+            .post(format!("{}/negotiate/{}/changes", seller.endpoint, negotiation.id))
+            .header(PROTOCOL_VERSION_HEADER, version.to_string())
+            .headers(telemetry::trace_headers())
+            .bearer_auth(session_token)
+            .json(change)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(consent.approved)
+    }
+
+    /// Charges the cancellation fee and reputation penalty of an applied
+    /// cancellation to the party at fault.
+    async fn apply_cancellation_penalty(&mut self, negotiation: &Negotiation, change: &DealChange) -> Result<()> {
+        let Some(penalised) = change.penalised else {
+            return Ok(());
+        };
+
+        if change.fee > Decimal::ZERO && penalised == negotiation.buyer_id {
+            self.pay(
+                negotiation.seller_id,
+                change.fee_amount(),
+                format!("cancellation-{}", change.id),
+            ).await?;
+        }
+        if change.reputation_penalty > 0 {
+            apply_reputation_change(&mut self.trust, &self.settlement, penalised, -(change.reputation_penalty as i32), negotiation.close_price).await;
+            record_failure(&self.trust, penalised, &format!("Cancelled negotiation {}", negotiation.id)).await;
+        }
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self), fields(%negotiation_id))]
+    pub async fn accept_quote(&mut self, negotiation_id: TransactionId) -> Result<()> {
+        self.check_deadline(negotiation_id, Utc::now()).await?;
+        let quote = self.get_quote_for_negotiation(negotiation_id).await?;
+        quote.validate()?;
+        if quote.firmness == QuoteFirmness::Indicative {
+            return Err(NegotiationError::Negotiation(
+                "Indicative quotes must be firmed up before they can be accepted".to_string()
+            ));
+        }
+        if quote.is_expired() {
+            self.events.publish(EventKind::Expired { negotiation_id, quote_id: quote.id });
+            return Err(NegotiationError::QuoteExpired);
+        }
+        self.authorize_payment(&quote.total()?)?;
+        let delegation_chain = self.delegation_chain()?;
+        let payment_method = self.payment_method();
+        let negotiation = self.active_negotiations.get(&negotiation_id)
+            .ok_or(NegotiationError::NotFound("Negotiation".to_string()))?;
+        let close_price = self.quote_price_in_budget_currency(negotiation, &quote).await?;
+
+        let negotiation = self.active_negotiations.get_mut(&negotiation_id)
+            .ok_or(NegotiationError::NotFound("Negotiation".to_string()))?;
+
+        if negotiation.quote_id.is_none() {
+            return Err(NegotiationError::Negotiation("No quote available".to_string()));
+        }
+
+        negotiation.accept(close_price.amount)?;
+        // self.database.update_negotiation(negotiation).await?;
+        self.events.publish(EventKind::Accepted { negotiation_id, price: close_price.clone() });
+        self.settlement.obligations().open(negotiation, &quote).await?;
+        self.settlement.audit_log().record(AuditAction::Acceptance, Some(negotiation.buyer_id), &negotiation_id.to_string(), &serde_json::json!({
+            "quote_id": quote.id,
+            "seller_id": quote.seller_id,
+            "price": close_price,
+        })).await;
+
+        let payment_result = self.settlement.create_quote_payment(negotiation, &quote, delegation_chain, payment_method).await?;
+
+        self.settle_once_confirmed(negotiation_id, &payment_result, true).await
+    }
+
+    /// Settles an accepted negotiation if its payment is confirmed. One still
+    /// awaiting confirmation, or paid on delivery, waits for
+    /// `confirm_settlements`; one that failed stays accepted. Any payment
+    /// that didn't fail opens the negotiation's order.
+    async fn settle_once_confirmed(&mut self, negotiation_id: TransactionId, payment: &PaymentResult, reports_stock: bool) -> Result<()> {
+        if payment.success {
+            if let Some(negotiation) = self.active_negotiations.get(&negotiation_id) {
+                self.settlement.orders().open(negotiation, &payment.payment_id).await?;
+            }
+        }
+        if payment.is_confirmed() {
+            return self.settle(negotiation_id, payment.payment_id.clone(), reports_stock).await;
+        }
+        if payment.status.is_outstanding() {
+            tracing::info!("Negotiation {} awaits confirmation of payment {}", negotiation_id, payment.payment_id);
+            self.pending_settlements.insert(negotiation_id, PendingSettlement {
+                payment_id: payment.payment_id.clone(),
+                reports_stock,
+                invoice: payment.invoice.clone(),
+            });
+        }
+        Ok(())
+    }
+
+    async fn settle(&mut self, negotiation_id: TransactionId, payment_id: String, reports_stock: bool) -> Result<()> {
+        let negotiation = self.active_negotiations.get_mut(&negotiation_id)
+            .ok_or(NegotiationError::NotFound("Negotiation".to_string()))?;
+        negotiation.settle()?;
+        self.settlement.metrics().negotiation_settled(negotiation.created_at);
+        self.events.publish(EventKind::Settled { negotiation_id, payment_id });
+
+        let (seller_id, buyer_id, deal_value) = (negotiation.seller_id, negotiation.buyer_id, negotiation.close_price);
+        apply_reputation_change(&mut self.trust, &self.settlement, seller_id, SETTLED_SELLER_REPUTATION, deal_value).await;
+        apply_reputation_change(&mut self.trust, &self.settlement, buyer_id, SETTLED_BUYER_REPUTATION, deal_value).await;
+        if reports_stock {
+            self.report_stock(negotiation_id, StockOutcome::Settled).await;
+        }
+        Ok(())
+    }
+
+    /// Checks the payments of accepted negotiations with the settlement
+    /// service, settling those now confirmed. Negotiations whose payment
+    /// failed stop waiting and stay accepted. Returns the ones settled.
+    pub async fn confirm_settlements(&mut self) -> Result<Vec<TransactionId>> {
+        let mut settled = Vec::new();
+        let pending: Vec<_> = self.pending_settlements.iter().map(|(id, pending)| (*id, pending.clone())).collect();
+        for (negotiation_id, pending) in pending {
+            let payment = self.settlement.poll_payment(&pending.payment_id).await?;
+            if payment.status.is_outstanding() {
+                continue;
+            }
+            self.pending_settlements.remove(&negotiation_id);
+            if payment.status == PaymentStatus::Succeeded {
+                self.settle(negotiation_id, payment.payment_id, pending.reports_stock).await?;
+                settled.push(negotiation_id);
+            } else {
+                tracing::warn!("Payment {} for negotiation {} ended {:?}", payment.payment_id, negotiation_id, payment.status);
+            }
+        }
+        Ok(settled)
+    }
+
+    /// Payment each accepted negotiation is waiting on
+    pub fn pending_settlements(&self) -> &HashMap<TransactionId, PendingSettlement> {
+        &self.pending_settlements
+    }
+
+    /// Confirms the seller delivered on an accepted quote, closing their
+    /// obligation. A shipped order is marked delivered, releasing its escrow
+    /// hold. A pay-on-delivery payment is collected now, and the negotiation
+    /// settles once that's confirmed.
+    pub async fn confirm_fulfilment(&mut self, negotiation_id: TransactionId) -> Result<PenaltyObligation> {
+        let obligation = self.settlement.obligations().fulfil(negotiation_id).await?;
+        let order = self.settlement.orders().for_negotiation(negotiation_id).await?;
+        if let Some(order) = order.filter(|order| order.status == FulfillmentStatus::Shipped) {
+            self.settlement.confirm_order_delivery(order.id, self.config.agent_id).await?;
+            self.confirm_settlements().await?;
+        } else if let Some(pending) = self.pending_settlements.get(&negotiation_id) {
+            let payment = self.settlement.get_payment(&pending.payment_id).await?;
+            if payment.payment_method == PaymentMethod::PayOnDelivery && payment.status == PaymentStatus::Created {
+                self.settlement.confirm_payment_delivery(&payment.payment_id, self.config.agent_id).await?;
+                self.confirm_settlements().await?;
+            }
+        }
+        Ok(obligation)
+    }
+
+    /// This buyer's orders, newest first
+    pub async fn orders(&self) -> Result<Vec<Order>> {
+        self.settlement.orders().for_buyer(self.config.agent_id).await
+    }
+
+    /// Reports that the seller backed out of an accepted firm or binding quote.
+    /// The seller loses reputation and, for a binding quote, the penalty is
+    /// slashed from their stake.
+    pub async fn report_renege(&mut self, negotiation_id: TransactionId, reason: &str) -> Result<PenaltyObligation> {
+        let obligation = self.settlement.obligations().breach(negotiation_id, reason).await?;
+        let deal_value = self.active_negotiations.get(&negotiation_id).and_then(|negotiation| negotiation.close_price);
+        apply_reputation_change(&mut self.trust, &self.settlement, obligation.seller_id, obligation.reputation_penalty(), deal_value).await;
+        record_failure(&self.trust, obligation.seller_id, &format!("Reneged on negotiation {}: {}", negotiation_id, reason)).await;
+        tracing::warn!(
+            "Seller {} reneged on negotiation {}: {} (slashed {} of {})",
+            obligation.seller_id, negotiation_id, reason, obligation.slashed, obligation.penalty_amount()
+        );
+        Ok(obligation)
+    }
+
+    /// Each seller's price history for a product, from the discovery registry.
+    pub async fn price_history(&self, product_id: &str, seller_id: Option<AgentId>, days: Option<u32>) -> Result<Vec<PriceHistory>> {
+        self.discovery.price_history(product_id, seller_id, days).await
+    }
+
+    /// How far the seller's list price moved recently. Strategies negotiate
+    /// without it when the registry has no history.
+    async fn recent_price_change(&self, product_id: &str, seller_id: AgentId) -> Option<Decimal> {
+        match self.price_history(product_id, Some(seller_id), None).await {
+            Ok(histories) => histories.first().and_then(|history| history.change_percent),
+            Err(e) => {
+                tracing::debug!("No price history for {} from seller {}: {}", product_id, seller_id, e);
+                None
+            }
+        }
+    }
+
+    /// Negotiates without manual input: counters with offers from `strategy`,
+    /// starting at `target_price`, until the seller's ask is acceptable or the
+    /// rounds run out. Accepting settles the negotiation as `accept_quote` does.
+    pub async fn auto_negotiate(
+        &mut self,
+        negotiation_id: TransactionId,
+        strategy: &dyn NegotiationStrategy,
+        target_price: Decimal,
+    ) -> Result<NegotiationOutcome> {
+        let negotiation = self.active_negotiations.get(&negotiation_id)
+            .ok_or(NegotiationError::NotFound("Negotiation".to_string()))?
+            .clone();
+
+        if target_price <= Decimal::ZERO || target_price > negotiation.opening_bid {
+            return Err(NegotiationError::Validation(
+                "Target price must be positive and no more than the opening bid".to_string()
+            ));
+        }
+
+        let quote = self.get_quote_for_negotiation(negotiation_id).await?;
+        let mut context = OfferContext {
+            round: 0,
+            max_rounds: DEFAULT_MAX_ROUNDS,
+            target_price,
+            reservation_price: negotiation.opening_bid,
+            seller_offers: vec![self.quote_price_in_budget_currency(&negotiation, &quote).await?.amount],
+            buyer_offers: vec![],
+            recent_price_change: self.recent_price_change(&negotiation.product_id, negotiation.seller_id).await,
+        };
+
+        loop {
+            match strategy.decide(&context)? {
+                Decision::Accept => {
+                    self.accept_quote(negotiation_id).await?;
+                    let price = self.active_negotiations.get(&negotiation_id)
+                        .and_then(|negotiation| negotiation.close_price)
+                        .unwrap_or_default();
+                    self.record_concessions(negotiation_id, strategy.name(), context.concession_curve()).await;
+                    tracing::info!("{} strategy accepted {} after {} rounds", strategy.name(), price, context.round);
+                    return Ok(NegotiationOutcome::Accepted { price, rounds: context.round });
+                }
+                Decision::Reject => {
+                    self.reject_quote(negotiation_id).await?;
+                    let last_ask = context.latest_ask().unwrap_or_default();
+                    self.record_concessions(negotiation_id, strategy.name(), context.concession_curve()).await;
+                    tracing::info!("{} strategy walked away at {} after {} rounds", strategy.name(), last_ask, context.round);
+                    return Ok(NegotiationOutcome::Rejected { last_ask, rounds: context.round });
+                }
+                Decision::Counter(offer) => {
+                    let offer = Money::new(offer, negotiation.currency.clone()).round_to_minor_units().amount;
+                    self.negotiate(negotiation_id, offer).await?;
+                    let quote = self.get_quote_for_negotiation(negotiation_id).await?;
+                    let ask = self.quote_price_in_budget_currency(&negotiation, &quote).await?;
+
+                    context.round += 1;
+                    context.buyer_offers.push(offer);
+                    context.seller_offers.push(ask.amount);
+                }
+            }
+        }
+    }
+
+    /// Stores the concession curve of a negotiation `auto_negotiate` ended.
+    /// A curve that can't be stored is logged; the outcome stands.
+    async fn record_concessions(&self, negotiation_id: TransactionId, strategy: &str, curve: ConcessionCurve) {
+        let Some(negotiation) = self.active_negotiations.get(&negotiation_id) else {
+            return;
+        };
+        if let Err(e) = self.settlement.concessions().record(negotiation, strategy, curve).await {
+            tracing::warn!("Failed to record concessions for negotiation {}: {}", negotiation_id, e);
+        }
+    }
+
+    fn request_expiry_warnings(&self, rfq: &mut RFQ) {
+        if self.expiry_handling.is_some() {
+            rfq.metadata.insert(EXPIRY_NOTIFY_METADATA_KEY.to_string(), self.config.endpoint.clone());
+        }
+    }
+
+    /// Handles a seller's warning that a quote is about to lapse: publishes it
+    /// and counters or declines as configured. Only the latest quote of an
+    /// open negotiation, due to lapse within the warning period, is acted on,
+    /// so a stray or forged warning can't end a negotiation early.
+    /// Checks a webhook `payload` claiming to come from `seller_id` against
+    /// the key the seller registered. Sellers that registered a mock key
+    /// can't sign, so their unsigned webhooks are let through.
+    pub async fn verify_seller_webhook(&self, seller_id: AgentId, payload: &[u8], signature: Option<&str>) -> Result<()> {
+        let seller = self.seller(seller_id).await?;
+        match signature {
+            Some(signature) => webhook::verify_webhook(payload, signature, &seller.public_key),
+            None if recovery::is_public_key(&seller.public_key) => {
+                Err(NegotiationError::Auth(format!("Webhook from seller {} is not signed", seller_id)))
+            }
+            None => Ok(()),
+        }
+    }
+
+    pub async fn handle_expiry_warning(&mut self, warning: &ExpiryWarning) -> Result<ExpiryResponse> {
+        let (action, window) = self.expiry_handling
+            .ok_or_else(|| NegotiationError::Validation("Expiry warnings aren't enabled".to_string()))?;
+        let (negotiation_id, quote) = self.latest_quotes.iter()
+            .find(|(_, quote)| quote.id == warning.quote_id && quote.seller_id == warning.seller_id)
+            .map(|(negotiation_id, quote)| (*negotiation_id, quote.clone()))
+            .ok_or_else(|| NegotiationError::Validation(format!("No open quote {}", warning.quote_id)))?;
+        let negotiation = self.active_negotiations.get(&negotiation_id)
+            .ok_or(NegotiationError::NotFound("Negotiation".to_string()))?;
+        if !matches!(negotiation.status, NegotiationStatus::Quoted | NegotiationStatus::Negotiating) {
+            return Err(NegotiationError::Validation(format!("Quote {} is no longer open", quote.id)));
+        }
+        if quote.is_expired() {
+            return Err(NegotiationError::QuoteExpired);
+        }
+        if quote.expires_at() - Utc::now() > window {
+            return Err(NegotiationError::Validation(format!("Quote {} doesn't lapse until {}", quote.id, quote.expires_at())));
+        }
+
+        tracing::info!("Quote {} in negotiation {} expires at {}", quote.id, negotiation_id, quote.expires_at());
+        self.events.publish(EventKind::ExpiryWarning {
+            negotiation_id,
+            quote_id: quote.id,
+            expires_at: quote.expires_at(),
+        });
+        match action {
+            ExpiryAction::Notify => Ok(ExpiryResponse::Notified { negotiation_id }),
+            ExpiryAction::Counter { .. } => {
+                let counter_offer = action.counter_price(&quote, negotiation.opening_bid)
+                    .unwrap_or(negotiation.opening_bid);
+                self.negotiate(negotiation_id, counter_offer).await?;
+                Ok(ExpiryResponse::Countered { negotiation_id, counter_offer })
+            }
+            ExpiryAction::Decline => {
+                self.reject_quote(negotiation_id).await?;
+                Ok(ExpiryResponse::Declined { negotiation_id })
+            }
+        }
+    }
+
+    pub async fn reject_quote(&mut self, negotiation_id: TransactionId) -> Result<()> {
+        let negotiation = self.active_negotiations.get_mut(&negotiation_id)
+            .ok_or(NegotiationError::NotFound("Negotiation".to_string()))?;
+
+        negotiation.reject()?;
+        // self.database.update_negotiation(negotiation).await?;
+
+        apply_reputation_change(&mut self.trust, &self.settlement, negotiation.seller_id, REJECTED_QUOTE_REPUTATION, None).await;
+        self.report_stock(negotiation_id, StockOutcome::Released).await;
+        Ok(())
+    }
+
+    /// Tells the seller how a deal ended so it can take the stock it reserved
+    /// off hand or release it. The seller releases it anyway once the quote
+    /// lapses, so a failure is only logged.
+    async fn report_stock(&mut self, negotiation_id: TransactionId, outcome: StockOutcome) {
+        let Some(negotiation) = self.active_negotiations.get(&negotiation_id) else {
+            return;
+        };
+        let (seller_id, update) = (negotiation.seller_id, StockUpdate { rfq_id: negotiation.rfq_id, outcome });
+        let result = async {
+            let seller = self.seller(seller_id).await?;
+            let version = self.protocol_version_for(&seller)?;
+            let session_token = self.session_token(negotiation_id).await?;
+            self.client
+                .post(format!("{}/negotiate/{}/stock", seller.endpoint, negotiation_id))
+                .header(PROTOCOL_VERSION_HEADER, version.to_string())
+                .headers(telemetry::trace_headers())
+                .bearer_auth(session_token)
+                .json(&update)
+                .send()
+                .await?
+                .error_for_status()?;
+            Ok::<_, NegotiationError>(())
+        }.await;
+        if let Err(e) = result {
+            tracing::warn!("Failed to report {:?} stock to seller {}: {}", outcome, seller_id, e);
+        }
+    }
+
+    pub async fn list_dead_letters(&self, limit: i64) -> Result<Vec<DeadLetter>> {
+        self.settlement.dead_letters().list(Some(DeadLetterStatus::Pending), limit).await
+    }
+
+    pub async fn replay_dead_letter(&mut self, dead_letter_id: uuid::Uuid) -> Result<DeadLetter> {
+        self.settlement.replay_dead_letter(dead_letter_id, Some(&mut self.trust)).await
+    }
+
+    async fn find_product(&self, product_id: &str) -> Result<Product> {
+        let response = match self.client
+            .get(&format!("{}/discovery/products/{}", self.discovery.endpoint(), product_id))
+            .send()
+            .await
+        {
+            Ok(response) => response,
+            Err(e) => {
+                let Some(cache) = self.seller_cache.as_ref().filter(|_| e.is_unreachable()) else {
+                    return Err(e);
+                };
+                tracing::warn!("Discovery unreachable, using cached product {}: {}", product_id, e);
+                return Ok(cache.product(product_id).await?.ok_or(e)?.1);
+            }
+        };
+
+        if response.status().is_success() {
+            let product: Product = response.json().await?;
+            Ok(product)
+        } else {
+            Err(NegotiationError::ProductNotFound(product_id.to_string()))
+        }
+    }
+
+    async fn get_quote_for_negotiation(&self, negotiation_id: TransactionId) -> Result<Quote> {
+        if let Some(quote) = self.latest_quotes.get(&negotiation_id) {
+            return Ok(quote.clone());
+        }
+
+        // For now, we'll look for the negotiation in active negotiations
+        let negotiation = self.active_negotiations.get(&negotiation_id)
+            .ok_or(NegotiationError::NotFound("Negotiation".to_string()))?;
+
+        let seller = self.seller(negotiation.seller_id).await?;
+        let version = self.protocol_version_for(&seller)?;
+        let response = self.client
+            .get(&format!("{}/quote/{}", seller.endpoint, negotiation.rfq_id))
+            .header(PROTOCOL_VERSION_HEADER, version.to_string())
+            .headers(telemetry::trace_headers())
+            .send()
+            .await?;
+
+        if response.status().is_success() {
+            let quote: Quote = response.json().await?;
+            Ok(quote)
+        } else {
+            Err(NegotiationError::NotFound("Quote".to_string()))
+        }
+    }
+
+    /// RFQ deadline of a negotiation, when the buyer knows it
+    pub fn deadline(&self, negotiation_id: TransactionId) -> Option<DateTime<Utc>> {
+        self.deadlines.get(&negotiation_id).copied()
+    }
+
+    /// Expires every open negotiation whose RFQ deadline passed by `now`,
+    /// returning their IDs. Each seller is told the deal is off, so it
+    /// releases the stock it reserved, and the negotiation's session token is
+    /// dropped. Expired negotiations stay listed by `get_active_negotiations`.
+    pub async fn expire_overdue(&mut self, now: DateTime<Utc>) -> Vec<TransactionId> {
+        let overdue: Vec<(TransactionId, DateTime<Utc>)> = self.active_negotiations.values()
+            .filter(|negotiation| matches!(
+                negotiation.status,
+                NegotiationStatus::Pending | NegotiationStatus::Quoted | NegotiationStatus::Negotiating
+            ))
+            .filter_map(|negotiation| self.deadlines.get(&negotiation.id).map(|deadline| (negotiation.id, *deadline)))
+            .filter(|(_, deadline)| *deadline <= now)
+            .collect();
+
+        let mut expired = Vec::new();
+        for (negotiation_id, deadline) in overdue {
+            let Some(negotiation) = self.active_negotiations.get_mut(&negotiation_id) else {
+                continue;
+            };
+            if let Err(e) = negotiation.expire() {
+                tracing::warn!("Failed to expire negotiation {}: {}", negotiation_id, e);
+                continue;
+            }
+            // self.database.update_negotiation(negotiation).await?;
+            tracing::info!("Negotiation {} expired at its deadline {}", negotiation_id, deadline);
+            self.events.publish(EventKind::DeadlinePassed { negotiation_id, deadline });
+            self.report_stock(negotiation_id, StockOutcome::Released).await;
+            self.session_tokens.remove(&negotiation_id);
+            expired.push(negotiation_id);
+        }
+        expired
+    }
+
+    /// Expires the negotiation if its deadline passed by `now`, failing so
+    /// the caller doesn't act on it.
+    async fn check_deadline(&mut self, negotiation_id: TransactionId, now: DateTime<Utc>) -> Result<()> {
+        match self.deadlines.get(&negotiation_id) {
+            Some(deadline) if *deadline <= now => {
+                let deadline = *deadline;
+                self.expire_overdue(now).await;
+                Err(NegotiationError::Negotiation(format!("Negotiation {} passed its deadline {}", negotiation_id, deadline)))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Runs `expire_overdue` on a shared buyer every `interval` until the
+    /// task is aborted.
+    pub fn spawn_deadline_timer(agent: Arc<tokio::sync::Mutex<BuyerAgent>>, interval: std::time::Duration) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                let expired = agent.lock().await.expire_overdue(Utc::now()).await;
+                if !expired.is_empty() {
+                    tracing::info!("Expired {} negotiations past their deadline", expired.len());
+                }
+            }
+        })
+    }
+
+    /// Runs `confirm_settlements` on a shared buyer every `interval` until
+    /// the task is aborted.
+    pub fn spawn_settlement_poller(agent: Arc<tokio::sync::Mutex<BuyerAgent>>, interval: std::time::Duration) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                match agent.lock().await.confirm_settlements().await {
+                    Ok(settled) if !settled.is_empty() => tracing::info!("Settled {} negotiations on payment confirmation", settled.len()),
+                    Ok(_) => {}
+                    Err(e) => tracing::error!("Failed to confirm settlements: {}", e),
+                }
+            }
+        })
+    }
+
+    pub fn get_active_negotiations(&self) -> Vec<&Negotiation> {
+        self.active_negotiations.values().collect()
+    }
+
+    pub fn get_negotiation(&self, negotiation_id: TransactionId) -> Option<&Negotiation> {
+        self.active_negotiations.get(&negotiation_id)
+    }
+
+    /// Latest quote the seller sent in a negotiation
+    pub fn latest_quote(&self, negotiation_id: TransactionId) -> Option<&Quote> {
+        self.latest_quotes.get(&negotiation_id)
+    }
+}
+
+pub struct SellerAgent {
+    config: SellerAgentConfig,
+    /// The catalog, starting from `config.products` and edited while running
+    products: RwLock<Vec<Product>>,
+    calendar: BusinessCalendar,
+    /// Rebuilt from `[pricing]` when the config is reloaded, unless
+    /// replaced with `with_pricing_policy`
+    pricing: RwLock<Box<dyn PricingPolicy>>,
+    configured_pricing: bool,
+    floors: RwLock<PriceFloors>,
+    /// Buyers below this reputation are refused quotes
+    min_buyer_reputation: AtomicU32,
+    tax: Box<dyn TaxCalculator>,
+    shipping: Box<dyn ShippingEstimator>,
+    quote_ttl: QuoteTtlPolicy,
+    templates: MessageTemplates,
+    discovery: DiscoveryService,
+    trust: TrustSystem,
+    agreements: Option<AgreementService>,
+    inventory: Option<InventoryService>,
+    metrics: Metrics,
+    /// Key this seller signs its quotes and webhooks with and publishes on
+    /// registering
+    webhook_signer: WebhookSigner,
+}
+
+impl SellerAgent {
+    pub async fn new(
+        config: SellerAgentConfig,
+        discovery: DiscoveryService,
+        trust: TrustSystem,
+    ) -> Result<Self> {
+        let calendar = BusinessCalendar::from_config(&config.calendar)?;
+        let pricing = Box::new(ConfiguredPricingPolicy::from_config(&config.pricing)?);
+        let floors = PriceFloors::from_config(&config.pricing.floors)?;
+        let tax = Box::new(FlatRateTaxCalculator::from_config(&config.tax)?);
+        let shipping = Box::new(ConfiguredShipping::from_config(&config.shipping)?);
+        let quote_ttl = QuoteTtlPolicy::from_config(&config.quote_ttl)?;
+        let templates = MessageTemplates::new(&config.templates)?;
+        let webhook_signer = WebhookSigner::generate(config.agent_id);
+        Ok(Self {
+            products: RwLock::new(config.products.clone()),
+            config,
+            calendar,
+            pricing: RwLock::new(pricing),
+            configured_pricing: true,
+            floors: RwLock::new(floors),
+            min_buyer_reputation: AtomicU32::new(DEFAULT_MIN_BUYER_REPUTATION),
+            tax,
+            shipping,
+            quote_ttl,
+            templates,
+            discovery,
+            trust: trust.with_agent_role(Role::Seller),
+            agreements: None,
+            inventory: None,
+            metrics: Metrics::default(),
+            webhook_signer,
+        })
+    }
+
+    pub fn price_formatter(&self) -> PriceFormatter {
+        PriceFormatter::new(self.config.locale)
+    }
+
+    /// A routine message to the buyer written from the seller's templates
+    /// rather than by an LLM: in `buyer_language` when the seller writes it,
+    /// otherwise the default language. The seller's name is filled in as
+    /// `{seller}`.
+    pub fn compose_message(
+        &self,
+        kind: TemplateKind,
+        negotiation_id: TransactionId,
+        buyer_language: Option<Language>,
+        variables: &[(&str, String)],
+    ) -> Result<NegotiationMessage> {
+        let local = language::preferred_languages(&self.config.preferred_languages, self.config.locale);
+        let language = language::negotiate_language(&local, buyer_language.as_slice());
+        let mut variables = variables.to_vec();
+        variables.push(("seller", self.config.name.clone()));
+        self.templates.message(kind, language, negotiation_id, self.config.agent_id, &variables, Utc::now())
+    }
+
+    /// Replaces the pricing policy built from `config.pricing`. The
+    /// configured price floors still apply, and config reloads leave the
+    /// policy alone.
+    pub fn with_pricing_policy(mut self, pricing: Box<dyn PricingPolicy>) -> Self {
+        self.pricing = RwLock::new(pricing);
+        self.configured_pricing = false;
+        self
+    }
+
+    /// Refuses quotes to buyers below `reputation` rather than
+    /// [`DEFAULT_MIN_BUYER_REPUTATION`].
+    pub fn with_min_buyer_reputation(self, reputation: u32) -> Self {
+        self.set_min_buyer_reputation(reputation);
+        self
+    }
+
+    pub fn min_buyer_reputation(&self) -> u32 {
+        self.min_buyer_reputation.load(Ordering::Relaxed)
+    }
+
+    /// Changes the reputation buyers need while the seller runs.
+    pub fn set_min_buyer_reputation(&self, reputation: u32) {
+        self.min_buyer_reputation.store(reputation, Ordering::Relaxed);
+    }
+
+    /// Reprices with a reloaded `[pricing]` section from the next quote on.
+    /// Nothing changes if it doesn't build; a policy set with
+    /// `with_pricing_policy` is kept, with only the floors replaced.
+    pub fn update_pricing(&self, pricing: &PricingConfig) -> Result<()> {
+        let floors = PriceFloors::from_config(&pricing.floors)?;
+        if self.configured_pricing {
+            let policy = ConfiguredPricingPolicy::from_config(pricing)?;
+            *self.pricing.write().unwrap() = Box::new(policy);
+        }
+        *self.floors.write().unwrap() = floors;
+        Ok(())
+    }
+
+    /// Replaces the tax calculator built from `config.tax`.
+    pub fn with_tax_calculator(mut self, tax: Box<dyn TaxCalculator>) -> Self {
+        self.tax = tax;
+        self
+    }
+
+    /// Replaces the carriers configured in `config.shipping`.
+    pub fn with_shipping_estimator(mut self, shipping: Box<dyn ShippingEstimator>) -> Self {
+        self.shipping = shipping;
+        self
+    }
+
+    /// Enables rate cards, tracked in the given service.
+    pub fn with_agreements(mut self, agreements: AgreementService) -> Self {
+        self.agreements = Some(agreements);
+        self
+    }
+
+    /// Reserves stock for each quote in `inventory` instead of only checking
+    /// the configured stock levels.
+    pub fn with_inventory(mut self, inventory: InventoryService) -> Self {
+        self.inventory = Some(inventory);
+        self
+    }
+
+    /// Signs this seller's quotes and expiry warnings with `signer`, and
+    /// publishes its public key when registering, in place of a key that
+    /// changes on restart.
+    pub fn with_webhook_signer(mut self, signer: WebhookSigner) -> Self {
+        self.webhook_signer = signer;
+        self
+    }
+
+    /// Counts RFQs and issued quotes in `metrics`.
+    pub fn with_metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// The seller's current catalog.
+    pub fn products(&self) -> Vec<Product> {
+        self.products.read().unwrap().clone()
+    }
+
+    fn product(&self, product_id: &str) -> Result<Product> {
+        self.products.read().unwrap().iter()
+            .find(|p| p.id == product_id)
+            .cloned()
+            .ok_or_else(|| NegotiationError::ProductNotFound(product_id.to_string()))
+    }
+
+    fn advertise(&self, product: &Product, quantity: u32, price: Money) -> Money {
+        let (price, violated) = self.floors.read().unwrap().advertise(product, quantity, price);
+        if let Some(rule) = violated {
+            self.metrics.price_floor_violated(rule);
+        }
+        price
+    }
+
+    /// Refuses an offer of `price` for `quantity` units below the product's
+    /// hard floor. Every counter quote goes through this, whether the
+    /// seller's rules, a strategy or an LLM drafted it.
+    pub fn check_offer(&self, product_id: &str, quantity: u32, price: Decimal) -> Result<()> {
+        let product = self.product(product_id)?;
+        self.floors.read().unwrap().check(&product, quantity, price)
+            .inspect_err(|_| self.metrics.price_floor_violated(FloorRule::MinPrice))
+    }
+
+    fn agreements(&self) -> Result<&AgreementService> {
+        self.agreements.as_ref()
+            .ok_or_else(|| NegotiationError::Config("Supply agreements are not enabled".to_string()))
+    }
+
+    /// How the seller describes itself to the registry, and at `/agent`
+    /// to buyers finding it through DNS.
+    pub fn agent_info(&self) -> AgentInfo {
+        AgentInfo {
+            id: self.config.agent_id,
+            agent_type: AgentType::Seller,
+            name: self.config.name.clone(),
+            endpoint: self.config.endpoint.clone(),
+            public_key: self.webhook_signer.public_key(),
+            reputation_score: 100,
+            products: self.products(),
+            payment_methods: self.config.payment_methods.clone(),
+            protocol_versions: protocol::supported_versions(),
+            preferred_languages: language::preferred_languages(&self.config.preferred_languages, self.config.locale),
+            tls_fingerprint: None,
+            created_at: Utc::now(),
+            last_active: Utc::now(),
+        }
+    }
+
+    pub async fn register(&self) -> Result<()> {
+        self.discovery.register_agent_with_compliance(self.agent_info(), self.config.compliance.clone()).await?;
+        Ok(())
+    }
+
+    /// Removes the seller and its listings from the registry, so buyers stop
+    /// finding it. A seller handed over to another host stays listed there.
+    pub async fn deregister(&self) -> Result<()> {
+        self.discovery.deregister_agent(self.config.agent_id, &self.config.endpoint).await
+    }
+
+    /// Snapshot of what the seller holds in memory, for the host taking
+    /// over from it.
+    pub fn export_state(&self) -> SellerState {
+        SellerState {
+            format_version: STATE_FORMAT_VERSION,
+            agent_id: self.config.agent_id,
+            endpoint: self.config.endpoint.clone(),
+            exported_at: Utc::now(),
+            products: self.products(),
+            counters: self.metrics.seller_counters(),
+        }
+    }
+
+    /// Takes on the exporting host's catalog and counters. Products the
+    /// inventory doesn't track yet start at their listed stock.
+    pub async fn import_state(&self, state: SellerState) -> Result<()> {
+        handover::check_state(state.format_version, state.agent_id, self.config.agent_id)?;
+        if let Some(inventory) = &self.inventory {
+            inventory.track(self.config.agent_id, &state.products, Utc::now()).await?;
+        }
+        *self.products.write().unwrap() = state.products;
+        self.metrics.restore_seller_counters(state.counters);
+        Ok(())
+    }
+
+    /// Moves the seller's registration, with its listings, from the host at
+    /// `from_endpoint` to this one.
+    pub async fn hand_over(&self, from_endpoint: &str) -> Result<AgentInfo> {
+        let request = handover::handover_request(from_endpoint, &self.config.endpoint)?;
+        self.discovery.hand_over(self.config.agent_id, &request).await
+    }
+
+    /// Pushes the current catalog to the registry. Products in categories
+    /// the seller isn't cleared for come back as withheld.
+    pub async fn sync_catalog(&self) -> Result<CatalogSyncResponse> {
+        let response = self.discovery.sync_catalog(self.config.agent_id, self.products()).await?;
+        for violation in &response.withheld {
+            tracing::warn!("Registry withheld {}: {}", violation.product_id, violation.reason);
+        }
+        Ok(response)
+    }
+
+    /// Adds a product to the catalog with `stock_quantity` units on hand and
+    /// lists it in the registry.
+    pub async fn add_product(&self, product: Product) -> Result<CatalogSyncResponse> {
+        product.validate()?;
+        if self.product(&product.id).is_ok() {
+            return Err(NegotiationError::Validation(format!("Product {} is already listed", product.id)));
+        }
+        self.set_stock(&product).await?;
+        {
+            let mut products = self.products.write().unwrap();
+            if products.iter().any(|p| p.id == product.id) {
+                return Err(NegotiationError::Validation(format!("Product {} is already listed", product.id)));
+            }
+            products.push(product);
+        }
+        self.sync_catalog().await
+    }
+
+    /// Replaces a product's details, setting its units on hand to
+    /// `stock_quantity`, and updates the registry.
+    pub async fn update_product(&self, product: Product) -> Result<CatalogSyncResponse> {
+        product.validate()?;
+        self.product(&product.id)?;
+        self.set_stock(&product).await?;
+        {
+            let mut products = self.products.write().unwrap();
+            let listed = products.iter_mut()
+                .find(|p| p.id == product.id)
+                .ok_or_else(|| NegotiationError::ProductNotFound(product.id.clone()))?;
+            *listed = product;
+        }
+        self.sync_catalog().await
+    }
+
+    /// Takes a product out of the catalog and the registry. Stock reserved
+    /// for its open quotes stays reserved until they settle or lapse.
+    pub async fn remove_product(&self, product_id: &str) -> Result<CatalogSyncResponse> {
+        {
+            let mut products = self.products.write().unwrap();
+            let index = products.iter()
+                .position(|p| p.id == product_id)
+                .ok_or_else(|| NegotiationError::ProductNotFound(product_id.to_string()))?;
+            products.remove(index);
+        }
+        self.sync_catalog().await
+    }
+
+    async fn set_stock(&self, product: &Product) -> Result<()> {
+        if let Some(inventory) = &self.inventory {
+            inventory.set_on_hand(self.config.agent_id, &product.id, product.stock_quantity, Utc::now()).await?;
+        }
+        Ok(())
+    }
+
+    /// Lists stock for auction on the discovery service, opening at the
+    /// product's list price for the quantity.
+    pub async fn list_product(
+        &self,
+        product_id: &str,
+        quantity: u32,
+        format: ListingFormat,
+        reserve_price: Decimal,
+        duration: Duration,
+    ) -> Result<Listing> {
+        let product = self.product(product_id)?;
+        if quantity > self.available_stock(product_id).await? {
+            return Err(NegotiationError::Validation("Insufficient stock".to_string()));
+        }
+        // The reserve is the lowest the listing may sell for
+        self.check_offer(product_id, quantity, reserve_price)?;
+
+        let start_price = self.advertise(&product, quantity, product.unit_price().times(Decimal::from(quantity)).round_to_minor_units());
+        self.discovery.create_listing(&CreateListingRequest {
+            seller_id: self.config.agent_id,
+            product_id: product.id.clone(),
+            quantity,
+            currency: start_price.currency,
+            format,
+            start_price: start_price.amount,
+            reserve_price,
+            duration_seconds: duration.num_seconds(),
+            anti_sniping: AntiSniping::default(),
+        }).await
+    }
+
+    /// Subscribes to demand signals in every category the seller stocks.
+    pub async fn subscribe_to_demand(&self) -> Result<Vec<DemandSubscription>> {
+        let products = self.products();
+        let mut categories: Vec<&str> = products.iter().map(|p| p.category.as_str()).collect();
+        categories.sort();
+        categories.dedup();
+
+        let mut subscriptions = Vec::new();
+        for category in categories {
+            subscriptions.push(self.discovery.subscribe_to_demand(&SubscribeRequest {
+                seller_id: self.config.agent_id,
+                category: category.to_string(),
+            }).await?);
+        }
+        Ok(subscriptions)
+    }
+
+    /// Prices an offer for a demand signal: the cheapest matching product in
+    /// stock, priced for an anonymous buyer. `None` if nothing fits the
+    /// buyer's budget.
+    pub fn offer_for_demand(&self, signal: &DemandSignal) -> Option<DemandResponseRequest> {
+        let now = Utc::now();
+        self.products().iter()
+            .filter(|product| match &signal.product_id {
+                Some(product_id) => &product.id == product_id,
+                None => demand::normalize_category(&product.category) == signal.category,
+            })
+            .filter(|product| product.currency == signal.currency && product.stock_quantity >= signal.quantity)
+            .map(|product| {
+                let factor = self.pricing.read().unwrap().factor(&PricingContext {
+                    product,
+                    quantity: signal.quantity,
+                    buyer_reputation: demand::ANONYMOUS_BUYER_REPUTATION,
+                    at: now,
+                    calendar: &self.calendar,
+                });
+                let price = product.unit_price().times(Decimal::from(signal.quantity)).times(factor).round_to_minor_units();
+                (product, self.advertise(product, signal.quantity, price))
+            })
+            .filter(|(_, price)| price.amount <= signal.max_price)
+            .min_by(|(_, a), (_, b)| a.amount.cmp(&b.amount))
+            .map(|(product, price)| DemandResponseRequest {
+                seller_id: self.config.agent_id,
+                product_id: product.id.clone(),
+                quantity: signal.quantity,
+                price: price.amount,
+                currency: price.currency,
+                ttl_seconds: self.calendar.quote_ttl_seconds(
+                    self.quote_ttl.quote_seconds(product, demand::ANONYMOUS_BUYER_REPUTATION), now,
+                ),
+                note: None,
+            })
+    }
+
+    /// Answers a demand signal if the seller can fill it within budget.
+    pub async fn respond_to_demand(&self, signal: &DemandSignal) -> Result<Option<DemandResponse>> {
+        let Some(offer) = self.offer_for_demand(signal) else {
+            return Ok(None);
+        };
+        let token = self.trust.generate_jwt(self.config.agent_id).await?;
+        Ok(Some(self.discovery.respond_to_demand(signal.id, &offer, &token).await?))
+    }
+
+    /// Prices a rate card: each product at the rate its committed quantity
+    /// earns over the agreement, for the buyer to accept within the usual
+    /// quote TTL.
+    pub async fn propose_agreement(&self, request: &AgreementRequest) -> Result<SupplyAgreement> {
+        let agreements = self.agreements()?;
+        request.validate()?;
+
+        let buyer_reputation = self.trust.get_reputation(request.buyer_id).await?;
+        if buyer_reputation < self.min_buyer_reputation() {
+            return Err(NegotiationError::InsufficientReputation(buyer_reputation));
+        }
+
+        let now = Utc::now();
+        let products = self.products();
+        let mut items = Vec::new();
+        let mut ttl_seconds = None;
+        for item in &request.items {
+            let product = products.iter()
+                .find(|p| p.id == item.product_id)
+                .ok_or_else(|| NegotiationError::ProductNotFound(item.product_id.clone()))?;
+            if product.currency != request.currency {
+                return Err(NegotiationError::Currency(format!(
+                    "{} is priced in {}, not {}", product.id, product.currency, request.currency
+                )));
+            }
+            let factor = self.pricing.read().unwrap().factor(&PricingContext {
+                product,
+                quantity: item.committed_quantity,
+                buyer_reputation,
+                at: now,
+                calendar: &self.calendar,
+            });
+            let product_ttl = self.quote_ttl.quote_seconds(product, buyer_reputation);
+            ttl_seconds = Some(ttl_seconds.map_or(product_ttl, |ttl: u32| ttl.min(product_ttl)));
+            items.push(RateCardItem {
+                product_id: product.id.clone(),
+                unit_price: self.advertise(product, 1, product.unit_price().times(factor).round_to_minor_units()).amount,
+                committed_quantity: item.committed_quantity,
+                ordered_quantity: 0,
+            });
+        }
+
+        // The card lasts as long as the shortest-lived of its products' quotes
+        let ttl_seconds = ttl_seconds.unwrap_or(self.config.quote_ttl.quote_seconds);
+        let proposal_ttl = Duration::seconds(self.calendar.quote_ttl_seconds(ttl_seconds, now) as i64);
+        agreements.propose(request, self.config.agent_id, items, proposal_ttl, now).await
+    }
+
+    pub async fn accept_agreement(&self, agreement_id: Uuid, buyer_id: AgentId) -> Result<SupplyAgreement> {
+        self.agreements()?.accept(agreement_id, buyer_id, Utc::now()).await
+    }
+
+    pub async fn get_agreement(&self, agreement_id: Uuid) -> Result<SupplyAgreement> {
+        self.agreements()?.get(agreement_id).await
+    }
+
+    /// Quotes an order placed under an agreement at the card rate, drawing
+    /// it down. The buyer was vetted when the agreement was made, so there
+    /// is no reputation check or repricing.
+    async fn quote_under_agreement(&self, rfq: &RFQ, agreement_id: &str) -> Result<Quote> {
+        let agreement_id = Uuid::parse_str(agreement_id)?;
+        let agreements = self.agreements()?;
+        let agreement = agreements.get(agreement_id).await?;
+        if agreement.seller_id != self.config.agent_id {
+            return Err(NegotiationError::Validation(format!("Agreement {} is with another seller", agreement_id)));
+        }
+        if rfq.currency != agreement.currency {
+            return Err(NegotiationError::Currency(format!(
+                "Orders under agreement {} are in {}", agreement_id, agreement.currency
+            )));
+        }
+        let product = self.product(&rfq.product_id)?;
+        if agreement.price_for(&rfq.product_id, rfq.quantity).is_some_and(|price| price > rfq.max_price) {
+            return Err(NegotiationError::Negotiation("Agreed rate exceeds the RFQ's max price".to_string()));
+        }
+
+        let shipping_options = self.shipping_options(&product, rfq)?;
+        let now = Utc::now();
+        let buyer_reputation = self.trust.get_reputation(rfq.buyer_id).await?;
+        let ttl_seconds = self.calendar.quote_ttl_seconds(self.quote_ttl.quote_seconds(&product, buyer_reputation), now);
+        self.reserve_stock(rfq, now + Duration::seconds(ttl_seconds as i64)).await?;
+        let order = match agreements.place_order(agreement_id, rfq.buyer_id, rfq.id, &rfq.product_id, rfq.quantity, now).await {
+            Ok(order) => order,
+            Err(e) => {
+                self.release_stock(rfq.id, rfq.buyer_id).await?;
+                return Err(e);
+            }
+        };
+        // The rate card is already a commitment, so these quotes are never merely indicative
+        let firmness = match self.config.quote_firmness {
+            QuoteFirmness::Indicative => QuoteFirmness::Firm,
+            firmness => firmness,
+        };
+        let mut quote = Quote::new(
+            rfq.id,
+            self.config.agent_id,
+            order.price,
+            agreement.currency,
+            rfq.quantity,
+            ttl_seconds,
+        ).with_firmness(firmness)
+        .with_shipping_options(shipping_options);
+        quote.metadata.insert(AGREEMENT_METADATA_KEY.to_string(), agreement_id.to_string());
+        self.add_tax(&mut quote, &product, rfq)?;
+        Ok(quote)
+    }
+
+    #[tracing::instrument(skip_all, fields(rfq_id = %rfq.id, buyer_id = %rfq.buyer_id))]
+    pub async fn handle_rfq(&self, rfq: RFQ) -> Result<Quote> {
+        self.metrics.rfq_received();
+        let quote = self.quote_rfq(rfq).await?.stamped(&self.webhook_signer)?;
+        self.metrics.quote_issued();
+        Ok(quote)
+    }
+
+    /// Checks that an RFQ was signed with the key its buyer registered in
+    /// discovery, then claims its nonce with `replay_guard`.
+    pub async fn check_rfq(&self, replay_guard: &ReplayGuard, rfq: &RFQ) -> Result<()> {
+        let public_key = match rfq.signature {
+            Some(_) => self.discovery.get_agent(rfq.buyer_id).await.ok().map(|buyer| buyer.public_key),
+            None => None,
+        };
+        replay_guard.check_signed("rfq", rfq, public_key.as_deref()).await
+    }
+
+    async fn quote_rfq(&self, rfq: RFQ) -> Result<Quote> {
+        rfq.validate()?;
+        self.trust.ensure_not_blocked(rfq.buyer_id).await?;
+        if let Some(agreement_id) = rfq.metadata.get(AGREEMENT_METADATA_KEY) {
+            return self.quote_under_agreement(&rfq, agreement_id).await;
+        }
+        let product = self.product(&rfq.product_id)?;
+        if rfq.quantity > self.available_stock(&rfq.product_id).await? {
+            return Err(NegotiationError::Validation("Insufficient stock".to_string()));
+        }
+
+        let buyer_reputation = self.trust.get_reputation(rfq.buyer_id).await?;
+        if buyer_reputation < self.min_buyer_reputation() {
+            return Err(NegotiationError::InsufficientReputation(buyer_reputation));
+        }
+
+        let shipping_options = self.shipping_options(&product, &rfq)?;
+        let now = Utc::now();
+        let base_price = product.unit_price().times(Decimal::from(rfq.quantity));
+        let pricing_factor = self.pricing.read().unwrap().factor(&PricingContext {
+            product: &product,
+            quantity: rfq.quantity,
+            buyer_reputation,
+            at: now,
+            calendar: &self.calendar,
+        });
+        let final_price = self.advertise(&product, rfq.quantity, base_price.times(pricing_factor).round_to_minor_units());
+
+        let mut quote = Quote::new(
+            rfq.id,
+            self.config.agent_id,
+            final_price.amount,
+            final_price.currency,
+            rfq.quantity,
+            self.calendar.quote_ttl_seconds(self.quote_ttl.quote_seconds(&product, buyer_reputation), now),
+        ).with_firmness(self.config.quote_firmness)
+        .with_shipping_options(shipping_options);
+        self.add_tax(&mut quote, &product, &rfq)?;
+        self.reserve_stock(&rfq, quote.expires_at()).await?;
+
+        Ok(quote)
+    }
+
+    /// Adds the taxes on a quote's price, for the RFQ's delivery location, to
+    /// its metadata. The buyer pays them on top of the price.
+    fn add_tax(&self, quote: &mut Quote, product: &Product, rfq: &RFQ) -> Result<()> {
+        let amount = quote.amount();
+        let context = TaxContext {
+            category: &product.category,
+            delivery_location: rfq.delivery_location.as_deref(),
+            amount: &amount,
+        };
+        let tax = self.tax.calculate(&context)?;
+        tax.write_metadata(&context, &mut quote.metadata)
+    }
+
+    /// Ways of shipping the order to the RFQ's delivery location.
+    fn shipping_options(&self, product: &Product, rfq: &RFQ) -> Result<Vec<ShippingOption>> {
+        self.shipping.estimate(&ShippingContext {
+            product,
+            quantity: rfq.quantity,
+            delivery_location: rfq.delivery_location.as_deref(),
+        })
+    }
+
+    /// Holds the RFQ's quantity until `expires_at`, when its quote lapses.
+    /// Without an inventory the configured stock is only checked.
+    pub async fn reserve_stock(&self, rfq: &RFQ, expires_at: DateTime<Utc>) -> Result<()> {
+        let Some(inventory) = &self.inventory else {
+            if rfq.quantity > self.product(&rfq.product_id)?.stock_quantity {
+                return Err(NegotiationError::Validation("Insufficient stock".to_string()));
+            }
+            return Ok(());
+        };
+        inventory.reserve(self.config.agent_id, rfq, expires_at, Utc::now()).await?;
+        Ok(())
+    }
+
+    /// Takes stock reserved for a buyer's RFQ off hand once their deal settles.
+    pub async fn commit_stock(&self, rfq_id: TransactionId, buyer_id: AgentId) -> Result<()> {
+        if let Some(inventory) = &self.inventory {
+            inventory.commit(rfq_id, buyer_id, Utc::now()).await?;
+        }
+        Ok(())
+    }
+
+    /// Returns stock reserved for a buyer's RFQ when they reject its quote.
+    pub async fn release_stock(&self, rfq_id: TransactionId, buyer_id: AgentId) -> Result<()> {
+        if let Some(inventory) = &self.inventory {
+            inventory.release(rfq_id, buyer_id, Utc::now()).await?;
+        }
+        Ok(())
+    }
+
+    /// Units of a product not held for open quotes.
+    pub async fn available_stock(&self, product_id: &str) -> Result<u32> {
+        match &self.inventory {
+            Some(inventory) => Ok(inventory.level(self.config.agent_id, product_id).await?.available()),
+            None => Ok(self.product(product_id)?.stock_quantity),
+        }
+    }
+
+    /// Seconds a counter quote to `buyer_id` stays open: the `[quote_ttl]`
+    /// counter TTL of the product, or `counter_seconds` when none is named,
+    /// stretched by the business calendar.
+    pub async fn counter_ttl_seconds(&self, product_id: Option<&str>, buyer_id: AgentId) -> Result<u32> {
+        let buyer_reputation = self.trust.get_reputation(buyer_id).await?;
+        let seconds = match product_id {
+            Some(product_id) => self.quote_ttl.counter_seconds(&self.product(product_id)?, buyer_reputation),
+            None => self.quote_ttl.default_counter_seconds(buyer_reputation),
+        };
+        Ok(self.calendar.quote_ttl_seconds(seconds, Utc::now()))
+    }
+
+    #[tracing::instrument(skip(self), fields(%negotiation_id, %counter_offer))]
+    pub async fn handle_negotiation(&self, negotiation_id: TransactionId, product_id: &str, quantity: u32, counter_offer: Decimal) -> Result<Quote> {
+        // For now, this is a mock implementation since database is not implemented
+        // let negotiation = self.database.get_negotiation(negotiation_id).await?
+        //     .ok_or(NegotiationError::NotFound("Negotiation".to_string()))?;
+
+        // Mock negotiation data - in real implementation this would come from database
+        let buyer_id = uuid::Uuid::new_v4();
+        let opening_bid = Decimal::from(100); // Mock opening bid
+
+        if buyer_id == self.config.agent_id {
+            return Err(NegotiationError::Auth("Unauthorized negotiation".to_string()));
+        }
+
+        let min_acceptable_price = opening_bid * Decimal::new(8, 1); // 20% minimum discount
+        if counter_offer < min_acceptable_price {
+            return Err(NegotiationError::Negotiation("Counter offer too low".to_string()));
+        }
+
+        let buyer_reputation = self.trust.get_reputation(buyer_id).await?;
+        let acceptance_threshold = match buyer_reputation {
+            score if score >= 80 => Decimal::new(95, 2), // High trust buyers get better terms
+            score if score >= 60 => Decimal::new(90, 2),
+            _ => Decimal::new(85, 2),
+        };
+
+        let product = self.product(product_id)?;
+        let adjusted_price = Money::new(counter_offer * acceptance_threshold, product.currency.clone()).round_to_minor_units();
+        self.check_offer(product_id, quantity, adjusted_price.amount)?;
+        let quote = Quote::new(
+            negotiation_id, // Using negotiation_id as rfq_id for mock
+            self.config.agent_id,
+            adjusted_price.amount,
+            adjusted_price.currency,
+            quantity,
+            self.calendar.quote_ttl_seconds(self.quote_ttl.counter_seconds(&product, buyer_reputation), Utc::now()),
+        ).with_firmness(self.config.quote_firmness);
+
+        self.metrics.quote_issued();
+        Ok(quote)
+    }
+}
+
+/// Applies a reputation change, dead-lettering it on failure so an operator can
+/// replay it rather than failing a workflow that has already settled.
+async fn apply_reputation_change(
+    trust: &mut TrustSystem,
+    settlement: &SettlementService,
+    agent_id: AgentId,
+    score_change: i32,
+    deal_value: Option<Decimal>,
+) {
+    let payload = ReputationUpdatePayload { agent_id, score_change, deal_value };
+    match trust.record_reputation_change(agent_id, score_change, deal_value).await {
+        Ok(()) => settlement.audit_log().record(AuditAction::ReputationChange, None, &agent_id.to_string(), &payload).await,
+        Err(e) => settlement.dead_letters().record(DeadLetterKind::ReputationUpdate, &payload, &e.to_string()).await,
+    }
+}
+
+/// Counts a failed or disputed transaction against the agent at fault,
+/// logging rather than failing a workflow that has already gone through.
+async fn record_failure(trust: &TrustSystem, agent_id: AgentId, reason: &str) {
+    if let Err(e) = trust.record_failure(agent_id, reason).await {
+        tracing::error!("Failed to count a failure against agent {}: {}", agent_id, e);
+    }
+}
+
+/// A seller's quote for an RFQ, with when the RFQ went out and the quote came back
+struct RfqReply {
+    rfq_summary: String,
+    quote: Quote,
+    /// Language negotiated with the seller, for the messages recorded
+    language: Language,
+    version: ProtocolVersion,
+    sent_at: chrono::DateTime<Utc>,
+    received_at: chrono::DateTime<Utc>,
+}
+
+impl RfqReply {
+    fn add_messages(&self, negotiation: &mut Negotiation) {
+        negotiation.add_message(negotiation.buyer_id, MessageType::RFQ, self.rfq_summary.clone(), self.sent_at);
+        negotiation.add_message(self.quote.seller_id, MessageType::Quote, self.language.quote(&self.quote.amount()), self.received_at);
+    }
+}
+
+/// Body of a seller's error response, which may carry a message to the buyer
+#[derive(Deserialize)]
+struct SellerRefusal {
+    #[serde(default)]
+    negotiation_message: Option<NegotiationMessage>,
 }
 
-async fn generate_public_key() -> Result<String> {
-    // For now, return a mock public key
-    // In production, this would generate a real Ed25519 keypair
-    Ok("mock_public_key_base64_encoded".to_string())
+/// Protocol version a seller answered with; sellers that omit the header predate versioning.
+fn answered_protocol_version(response: &reqwest::Response) -> ProtocolVersion {
+    response.headers()
+        .get(PROTOCOL_VERSION_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<ProtocolVersion>().ok())
+        .unwrap_or(protocol::LEGACY_VERSION)
 }
\ No newline at end of file
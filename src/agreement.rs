@@ -0,0 +1,297 @@
+//! Rate cards and long-term supply agreements.
+//!
+//! Instead of a one-off sale, a buyer can negotiate a rate card: a unit price
+//! per product, valid for a period and up to a committed quantity. The seller
+//! prices the card and the buyer accepts it. Later orders reference the
+//! agreement in their RFQ, are quoted at the card rate without negotiation,
+//! and draw down the committed quantity. Anything outside the card (another
+//! product, more than the remaining quantity, after the period) is refused
+//! at quote time.
+
+use crate::{
+    database::Database,
+    error::{NegotiationError, Result},
+    AgentId, TransactionId,
+};
+use chrono::{DateTime, Duration, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+/// RFQ metadata key naming the agreement an order is placed under
+pub const AGREEMENT_METADATA_KEY: &str = "agreement_id";
+
+/// Longest period a rate card can be agreed for
+pub const MAX_AGREEMENT_DAYS: u32 = 365;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AgreementStatus {
+    /// Priced by the seller, waiting for the buyer to accept
+    Proposed,
+    Active,
+    /// Every committed unit has been ordered
+    Completed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RateCardItem {
+    pub product_id: String,
+    pub unit_price: Decimal,
+    pub committed_quantity: u32,
+    pub ordered_quantity: u32,
+}
+
+impl RateCardItem {
+    pub fn remaining_quantity(&self) -> u32 {
+        self.committed_quantity.saturating_sub(self.ordered_quantity)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SupplyAgreement {
+    pub id: uuid::Uuid,
+    pub buyer_id: AgentId,
+    pub seller_id: AgentId,
+    pub currency: String,
+    pub items: Vec<RateCardItem>,
+    pub status: AgreementStatus,
+    pub valid_from: DateTime<Utc>,
+    pub valid_until: DateTime<Utc>,
+    /// The buyer must accept the proposal before this
+    pub proposal_expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl SupplyAgreement {
+    /// Orders can be placed: accepted, with quantity left, within the period
+    pub fn is_in_force_at(&self, now: DateTime<Utc>) -> bool {
+        self.status == AgreementStatus::Active && self.valid_from <= now && now < self.valid_until
+    }
+
+    pub fn item(&self, product_id: &str) -> Option<&RateCardItem> {
+        self.items.iter().find(|item| item.product_id == product_id)
+    }
+
+    /// Price for `quantity` units at the card rate
+    pub fn price_for(&self, product_id: &str, quantity: u32) -> Option<Decimal> {
+        self.item(product_id).map(|item| item.unit_price * Decimal::from(quantity))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgreementItemRequest {
+    pub product_id: String,
+    pub committed_quantity: u32,
+}
+
+/// What a buyer asks a seller to price as a rate card
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgreementRequest {
+    pub buyer_id: AgentId,
+    pub currency: String,
+    pub items: Vec<AgreementItemRequest>,
+    pub duration_days: u32,
+}
+
+impl AgreementRequest {
+    pub fn validate(&self) -> Result<()> {
+        if self.items.is_empty() {
+            return Err(NegotiationError::Validation("A rate card needs at least one product".to_string()));
+        }
+        if self.items.iter().any(|item| item.committed_quantity == 0) {
+            return Err(NegotiationError::Validation("Committed quantities must be greater than 0".to_string()));
+        }
+        for (i, item) in self.items.iter().enumerate() {
+            if self.items[..i].iter().any(|other| other.product_id == item.product_id) {
+                return Err(NegotiationError::Validation(format!("{} is listed twice", item.product_id)));
+            }
+        }
+        if self.duration_days == 0 || self.duration_days > MAX_AGREEMENT_DAYS {
+            return Err(NegotiationError::Validation(format!(
+                "Agreements run for 1 to {} days", MAX_AGREEMENT_DAYS
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// An order placed under an agreement, at the card rate
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgreementOrder {
+    pub id: uuid::Uuid,
+    pub agreement_id: uuid::Uuid,
+    pub rfq_id: TransactionId,
+    pub product_id: String,
+    pub quantity: u32,
+    /// Price for the whole quantity
+    pub price: Decimal,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Clone)]
+pub struct AgreementService {
+    database: Database,
+}
+
+impl AgreementService {
+    pub fn new(database: Database) -> Self {
+        Self { database }
+    }
+
+    /// Records a rate card the seller has priced, for the buyer to accept
+    /// within `proposal_ttl`.
+    pub async fn propose(
+        &self,
+        request: &AgreementRequest,
+        seller_id: AgentId,
+        items: Vec<RateCardItem>,
+        proposal_ttl: Duration,
+        now: DateTime<Utc>,
+    ) -> Result<SupplyAgreement> {
+        request.validate()?;
+        let agreement = SupplyAgreement {
+            id: uuid::Uuid::new_v4(),
+            buyer_id: request.buyer_id,
+            seller_id,
+            currency: request.currency.clone(),
+            items,
+            status: AgreementStatus::Proposed,
+            valid_from: now,
+            valid_until: now + Duration::days(request.duration_days as i64),
+            proposal_expires_at: now + proposal_ttl,
+            created_at: now,
+        };
+        self.database.create_supply_agreement(&agreement).await?;
+        Ok(agreement)
+    }
+
+    pub async fn get(&self, agreement_id: uuid::Uuid) -> Result<SupplyAgreement> {
+        self.database.get_supply_agreement(agreement_id).await?
+            .ok_or_else(|| NegotiationError::NotFound(format!("Agreement {}", agreement_id)))
+    }
+
+    /// The buyer accepts a proposed rate card. The agreement runs for its
+    /// full period from acceptance.
+    pub async fn accept(&self, agreement_id: uuid::Uuid, buyer_id: AgentId, now: DateTime<Utc>) -> Result<SupplyAgreement> {
+        let mut agreement = self.get(agreement_id).await?;
+        if agreement.buyer_id != buyer_id {
+            return Err(NegotiationError::Auth("Agreement was proposed to another buyer".to_string()));
+        }
+        if agreement.status != AgreementStatus::Proposed {
+            return Err(NegotiationError::Validation(format!("Agreement is already {:?}", agreement.status)));
+        }
+        if now >= agreement.proposal_expires_at {
+            return Err(NegotiationError::QuoteExpired);
+        }
+
+        let period = agreement.valid_until - agreement.valid_from;
+        agreement.status = AgreementStatus::Active;
+        agreement.valid_from = now;
+        agreement.valid_until = now + period;
+        self.database.update_supply_agreement(&agreement).await?;
+
+        tracing::info!("Agreement {} active until {}", agreement.id, agreement.valid_until);
+        Ok(agreement)
+    }
+
+    /// Prices an order at the card rate and draws it down from the committed
+    /// quantity. Orders the agreement doesn't cover are refused.
+    pub async fn place_order(
+        &self,
+        agreement_id: uuid::Uuid,
+        buyer_id: AgentId,
+        rfq_id: TransactionId,
+        product_id: &str,
+        quantity: u32,
+        now: DateTime<Utc>,
+    ) -> Result<AgreementOrder> {
+        let mut agreement = self.get(agreement_id).await?;
+        if agreement.buyer_id != buyer_id {
+            return Err(NegotiationError::Auth("Agreement belongs to another buyer".to_string()));
+        }
+        if !agreement.is_in_force_at(now) {
+            return Err(NegotiationError::Validation(format!("Agreement {} is not in force", agreement_id)));
+        }
+        if quantity == 0 {
+            return Err(NegotiationError::Validation("Quantity must be greater than 0".to_string()));
+        }
+
+        let item = agreement.items.iter_mut()
+            .find(|item| item.product_id == product_id)
+            .ok_or_else(|| NegotiationError::Validation(format!("{} is not on the rate card", product_id)))?;
+        if quantity > item.remaining_quantity() {
+            return Err(NegotiationError::Validation(format!(
+                "Only {} units of {} remain under the agreement", item.remaining_quantity(), product_id
+            )));
+        }
+        item.ordered_quantity += quantity;
+        let price = item.unit_price * Decimal::from(quantity);
+        if agreement.items.iter().all(|item| item.remaining_quantity() == 0) {
+            agreement.status = AgreementStatus::Completed;
+        }
+
+        let order = AgreementOrder {
+            id: uuid::Uuid::new_v4(),
+            agreement_id,
+            rfq_id,
+            product_id: product_id.to_string(),
+            quantity,
+            price,
+            created_at: now,
+        };
+        self.database.record_agreement_order(&agreement, &order).await?;
+        Ok(order)
+    }
+
+    pub async fn orders(&self, agreement_id: uuid::Uuid) -> Result<Vec<AgreementOrder>> {
+        self.database.get_agreement_orders(agreement_id).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::NamedTempFile;
+
+    #[tokio::test]
+    async fn test_orders_draw_down_the_rate_card_within_its_terms() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let database = Database::new(&format!("sqlite://{}", temp_file.path().to_string_lossy())).await.unwrap();
+        let service = AgreementService::new(database);
+
+        let (buyer_id, seller_id) = (uuid::Uuid::new_v4(), uuid::Uuid::new_v4());
+        let request = AgreementRequest {
+            buyer_id,
+            currency: "USD".to_string(),
+            items: vec![AgreementItemRequest { product_id: "laptop-001".to_string(), committed_quantity: 10 }],
+            duration_days: 90,
+        };
+        let items = vec![RateCardItem {
+            product_id: "laptop-001".to_string(),
+            unit_price: Decimal::new(229999, 2),
+            committed_quantity: 10,
+            ordered_quantity: 0,
+        }];
+        let now = Utc::now();
+        let proposed = service.propose(&request, seller_id, items, Duration::hours(1), now).await.unwrap();
+
+        // Nothing can be ordered until the buyer accepts
+        assert!(service.place_order(proposed.id, buyer_id, uuid::Uuid::new_v4(), "laptop-001", 1, now).await.is_err());
+        assert!(service.accept(proposed.id, seller_id, now).await.is_err());
+        let agreement = service.accept(proposed.id, buyer_id, now).await.unwrap();
+        assert_eq!(agreement.status, AgreementStatus::Active);
+
+        let order = service.place_order(agreement.id, buyer_id, uuid::Uuid::new_v4(), "laptop-001", 4, now).await.unwrap();
+        assert_eq!(order.price, Decimal::new(919996, 2));
+        assert!(service.place_order(agreement.id, buyer_id, uuid::Uuid::new_v4(), "phone-001", 1, now).await.is_err());
+        assert!(service.place_order(agreement.id, buyer_id, uuid::Uuid::new_v4(), "laptop-001", 7, now).await.is_err());
+        assert!(service.place_order(agreement.id, uuid::Uuid::new_v4(), uuid::Uuid::new_v4(), "laptop-001", 1, now).await.is_err());
+        assert!(service.place_order(agreement.id, buyer_id, uuid::Uuid::new_v4(), "laptop-001", 1, agreement.valid_until).await.is_err());
+
+        service.place_order(agreement.id, buyer_id, uuid::Uuid::new_v4(), "laptop-001", 6, now).await.unwrap();
+        let completed = service.get(agreement.id).await.unwrap();
+        assert_eq!(completed.status, AgreementStatus::Completed);
+        assert_eq!(completed.items[0].ordered_quantity, 10);
+        assert_eq!(service.orders(agreement.id).await.unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,249 @@
+//! Market analytics over completed deals.
+//!
+//! Aggregates the negotiation records and negotiations in the database into
+//! deal volume, average price deltas, daily price trends per product category
+//! and seller win rates. Deals are grouped by the category the seller listed
+//! the product under, or `uncategorized` when the product isn't in the
+//! catalogue. Served by the settlement service's `/analytics` endpoint and the
+//! `market://analytics` MCP resource, redacted when privacy mode is on.
+
+use crate::{
+    database::Database,
+    error::{NegotiationError, Result},
+    privacy::{PriceBand, PrivacyFilter},
+};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_ANALYTICS_DAYS: u32 = 30;
+pub const MAX_ANALYTICS_DAYS: u32 = 365;
+/// Category of deals on products missing from the catalogue
+pub const UNCATEGORIZED: &str = "uncategorized";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AnalyticsQuery {
+    /// How far back to look, `DEFAULT_ANALYTICS_DAYS` by default
+    pub days: Option<u32>,
+    pub category: Option<String>,
+}
+
+impl AnalyticsQuery {
+    /// Start of the window the query covers.
+    pub fn since(&self, now: DateTime<Utc>) -> Result<DateTime<Utc>> {
+        let days = self.days.unwrap_or(DEFAULT_ANALYTICS_DAYS);
+        if days == 0 || days > MAX_ANALYTICS_DAYS {
+            return Err(NegotiationError::Validation(format!(
+                "Analytics cover 1 to {} days", MAX_ANALYTICS_DAYS
+            )));
+        }
+        Ok(now - Duration::days(days as i64))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MarketAnalytics {
+    pub since: DateTime<Utc>,
+    pub deal_count: u64,
+    /// Close price minus opening bid, averaged over deals
+    pub average_delta: Decimal,
+    pub average_duration_seconds: u64,
+    pub categories: Vec<CategoryAnalytics>,
+    pub sellers: Vec<SellerWinRate>,
+    /// Categories left out for having fewer than `min_group_size` deals
+    pub suppressed_categories: usize,
+    pub redacted: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CategoryAnalytics {
+    pub category: String,
+    pub deal_count: u64,
+    /// Exact average when privacy mode is off (`low == high`)
+    pub average_close_price: PriceBand,
+    pub average_delta: Decimal,
+    /// One point per day with deals, oldest first
+    pub trend: Vec<TrendPoint>,
+    /// Change in the average close price from the first day to the last
+    pub price_change_percent: Option<Decimal>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrendPoint {
+    pub date: NaiveDate,
+    pub deal_count: u64,
+    pub average_close_price: PriceBand,
+}
+
+/// Share of a seller's decided negotiations that ended in a deal. Pending
+/// and ongoing negotiations aren't counted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SellerWinRate {
+    /// Agent ID, or its pseudonym when redacted
+    pub seller: String,
+    pub negotiations: u64,
+    pub won: u64,
+    pub win_rate: f64,
+}
+
+impl SellerWinRate {
+    pub fn new(seller: String, negotiations: u64, won: u64) -> Self {
+        Self {
+            seller,
+            negotiations,
+            won,
+            win_rate: if negotiations == 0 { 0.0 } else { won as f64 / negotiations as f64 },
+        }
+    }
+}
+
+impl CategoryAnalytics {
+    pub(crate) fn with_trend(mut self, trend: Vec<TrendPoint>) -> Self {
+        self.price_change_percent = match (trend.first(), trend.last()) {
+            (Some(first), Some(last)) if trend.len() > 1 && !first.average_close_price.low.is_zero() => {
+                let (first, last) = (first.average_close_price.low, last.average_close_price.low);
+                Some(((last - first) / first * Decimal::ONE_HUNDRED).round_dp(2))
+            }
+            _ => None,
+        };
+        self.trend = trend;
+        self
+    }
+}
+
+/// Market analytics for `query`, redacted by `privacy` when it is enabled.
+pub async fn market_analytics(database: &Database, query: &AnalyticsQuery, privacy: &PrivacyFilter) -> Result<MarketAnalytics> {
+    let since = query.since(Utc::now())?;
+    let analytics = database.market_analytics(since, query.category.as_deref()).await?;
+    Ok(privacy.redact_analytics(analytics))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::PrivacyConfig,
+        model::{AgentInfo, AgentType, Negotiation, NegotiationRecord, NegotiationStatus, Product},
+        AgentId,
+    };
+    use std::collections::HashMap;
+    use tempfile::NamedTempFile;
+
+    async fn seller(database: &Database, category: &str) -> AgentId {
+        let agent = AgentInfo {
+            id: uuid::Uuid::new_v4(),
+            agent_type: AgentType::Seller,
+            name: "Seller".to_string(),
+            endpoint: "http://localhost:8001".to_string(),
+            public_key: "key".to_string(),
+            reputation_score: 50,
+            products: vec![Product {
+                id: "widget-001".to_string(),
+                name: "Widget".to_string(),
+                description: String::new(),
+                category: category.to_string(),
+                base_price: Decimal::from(100),
+                currency: "USD".to_string(),
+                stock_quantity: 10,
+                metadata: HashMap::new(),
+            }],
+            payment_methods: vec![],
+            protocol_versions: vec![],
+            preferred_languages: vec![],
+            tls_fingerprint: None,
+            created_at: Utc::now(),
+            last_active: Utc::now(),
+        };
+        database.create_agent(&agent).await.unwrap();
+        agent.id
+    }
+
+    fn record(seller_id: AgentId, close_price: i64, days_ago: i64) -> NegotiationRecord {
+        NegotiationRecord {
+            buyer_id: uuid::Uuid::new_v4(),
+            seller_id,
+            product_hash: "widget-001".to_string(),
+            opening_bid: Decimal::from(90),
+            close_price: Decimal::from(close_price),
+            delta: Decimal::from(close_price - 90),
+            timestamp: Utc::now() - Duration::days(days_ago),
+            duration_seconds: 60,
+            message_count: 4,
+        }
+    }
+
+    /// Deals in tools and toys, one of them too old for the default window,
+    /// and tools' seller in three negotiations: one won, one lost, one open
+    async fn market(temp_file: &NamedTempFile) -> Database {
+        let database = Database::new(&format!("sqlite://{}", temp_file.path().to_string_lossy())).await.unwrap();
+        let tools = seller(&database, "tools").await;
+        let toys = seller(&database, "toys").await;
+        let buyer = seller(&database, "unused").await;
+        for record in [record(tools, 100, 2), record(tools, 110, 1), record(toys, 50, 1), record(tools, 500, 40)] {
+            database.add_negotiation_record(&record).await.unwrap();
+        }
+        for status in [NegotiationStatus::Settled, NegotiationStatus::Rejected, NegotiationStatus::Negotiating] {
+            let mut negotiation = Negotiation::new(
+                crate::model::RFQ::new(buyer, "widget-001".to_string(), 1, Decimal::from(100), "USD".to_string(), Utc::now()),
+                tools,
+            );
+            negotiation.status = status;
+            database.create_negotiation(&negotiation).await.unwrap();
+        }
+        database
+    }
+
+    fn privacy(enabled: bool) -> PrivacyFilter {
+        PrivacyFilter::from_config(&PrivacyConfig { enabled, ..PrivacyConfig::default() }).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_aggregates_records_by_category() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let database = market(&temp_file).await;
+
+        let analytics = market_analytics(&database, &AnalyticsQuery::default(), &privacy(false)).await.unwrap();
+        assert_eq!(analytics.deal_count, 3);
+        assert_eq!(analytics.average_delta, Decimal::new(-333, 2));
+        let tools_stats = analytics.categories.iter().find(|category| category.category == "tools").unwrap();
+        assert_eq!(tools_stats.deal_count, 2);
+        assert_eq!(tools_stats.average_close_price.low, Decimal::from(105));
+        assert_eq!(tools_stats.trend.len(), 2);
+        assert_eq!(tools_stats.price_change_percent, Some(Decimal::from(10)));
+    }
+
+    #[tokio::test]
+    async fn test_counts_the_negotiations_each_seller_won() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let database = market(&temp_file).await;
+
+        let analytics = market_analytics(&database, &AnalyticsQuery::default(), &privacy(false)).await.unwrap();
+        assert_eq!(analytics.sellers.len(), 1);
+        assert_eq!((analytics.sellers[0].negotiations, analytics.sellers[0].won), (2, 1));
+    }
+
+    #[tokio::test]
+    async fn test_filters_by_category() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let database = market(&temp_file).await;
+
+        let query = AnalyticsQuery { days: Some(60), category: Some("toys".to_string()) };
+        let toys_only = market_analytics(&database, &query, &privacy(false)).await.unwrap();
+        assert_eq!(toys_only.deal_count, 1);
+        assert!(toys_only.sellers.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_privacy_leaves_out_sellers() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let database = market(&temp_file).await;
+
+        let redacted = market_analytics(&database, &AnalyticsQuery::default(), &privacy(true)).await.unwrap();
+        assert!(redacted.redacted && redacted.sellers.is_empty());
+    }
+
+    #[test]
+    fn test_window_must_be_at_least_a_day() {
+        assert!(AnalyticsQuery { days: Some(0), category: None }.since(Utc::now()).is_err());
+    }
+}
@@ -0,0 +1,370 @@
+//! Anchoring of negotiation records to a public chain.
+//!
+//! Completed-deal records are grouped into batches in insertion order. Each
+//! batch's Merkle root is committed on chain, and any record can later be
+//! proven part of its batch, so a reputation audit can detect records that
+//! were altered or removed after the fact.
+
+use crate::{
+    database::Database,
+    error::{NegotiationError, Result},
+    http::HttpClient,
+    model::NegotiationRecord,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+
+/// Domain separation so a leaf can never be passed off as an inner node
+const LEAF_PREFIX: u8 = 0x00;
+const NODE_PREFIX: u8 = 0x01;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnchoringConfig {
+    /// Chain the roots are written to, e.g. "solana"
+    pub chain: String,
+    /// Anchoring gateway that writes roots on chain
+    pub endpoint: String,
+    pub batch_size: i64,
+    pub interval_seconds: u64,
+}
+
+/// A batch of consecutive records whose Merkle root was committed on chain.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnchorBatch {
+    pub id: uuid::Uuid,
+    pub merkle_root: String,
+    pub first_record_id: i64,
+    pub last_record_id: i64,
+    pub record_count: u32,
+    pub chain: String,
+    pub chain_tx: String,
+    pub anchored_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SiblingPosition {
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProofStep {
+    pub sibling: String,
+    pub position: SiblingPosition,
+}
+
+/// Everything an auditor needs to check a record against the on-chain root.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InclusionProof {
+    pub record_id: i64,
+    pub record: NegotiationRecord,
+    pub leaf_hash: String,
+    pub path: Vec<ProofStep>,
+    pub batch: AnchorBatch,
+}
+
+impl InclusionProof {
+    /// Recomputes the root from the record and path and compares it with the
+    /// batch's anchored root.
+    pub fn verify(&self) -> bool {
+        let leaf = leaf_hash(self.record_id, &self.record);
+        if hex::encode(leaf) != self.leaf_hash {
+            return false;
+        }
+
+        let mut hash = leaf;
+        for step in &self.path {
+            let sibling = match hex::decode(&step.sibling).ok().and_then(|bytes| <[u8; 32]>::try_from(bytes).ok()) {
+                Some(sibling) => sibling,
+                None => return false,
+            };
+            hash = match step.position {
+                SiblingPosition::Left => node_hash(&sibling, &hash),
+                SiblingPosition::Right => node_hash(&hash, &sibling),
+            };
+        }
+        hex::encode(hash) == self.batch.merkle_root
+    }
+}
+
+#[async_trait]
+pub trait AnchorBackend: Send + Sync {
+    fn chain(&self) -> &str;
+
+    /// Commits a batch root on chain and returns the transaction reference.
+    async fn commit_root(&self, batch_id: uuid::Uuid, merkle_root: &str) -> Result<String>;
+}
+
+/// Posts roots to an anchoring gateway (`POST {endpoint}/anchor`) that writes
+/// them on chain, e.g. as a Solana memo transaction.
+pub struct HttpAnchorBackend {
+    client: HttpClient,
+    endpoint: String,
+    chain: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct AnchorResponse {
+    transaction_id: String,
+}
+
+impl HttpAnchorBackend {
+    pub fn new(endpoint: String, chain: String) -> Self {
+        Self {
+            client: HttpClient::default(),
+            endpoint,
+            chain,
+        }
+    }
+
+    pub fn with_http_client(mut self, client: HttpClient) -> Self {
+        self.client = client;
+        self
+    }
+}
+
+#[async_trait]
+impl AnchorBackend for HttpAnchorBackend {
+    fn chain(&self) -> &str {
+        &self.chain
+    }
+
+    async fn commit_root(&self, batch_id: uuid::Uuid, merkle_root: &str) -> Result<String> {
+        let response: AnchorResponse = self.client
+            .post(format!("{}/anchor", self.endpoint.trim_end_matches('/')))
+            .json(&serde_json::json!({
+                "chain": self.chain,
+                "batch_id": batch_id,
+                "merkle_root": merkle_root,
+            }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(response.transaction_id)
+    }
+}
+
+#[derive(Clone)]
+pub struct AnchoringService {
+    database: Database,
+    backend: Arc<dyn AnchorBackend>,
+    batch_size: i64,
+}
+
+impl AnchoringService {
+    pub fn new(database: Database, backend: Arc<dyn AnchorBackend>, batch_size: i64) -> Self {
+        Self {
+            database,
+            backend,
+            batch_size,
+        }
+    }
+
+    pub fn from_config(database: Database, config: &AnchoringConfig) -> Result<Self> {
+        if config.batch_size <= 0 {
+            return Err(NegotiationError::Config("Anchoring batch size must be positive".to_string()));
+        }
+        let backend = HttpAnchorBackend::new(config.endpoint.clone(), config.chain.clone());
+        Ok(Self::new(database, Arc::new(backend), config.batch_size))
+    }
+
+    /// Anchors the next batch of unanchored records, if there are any. The
+    /// batch is only stored once the chain commit succeeds, so a failed commit
+    /// is simply retried on the next run.
+    pub async fn anchor_pending(&self) -> Result<Option<AnchorBatch>> {
+        let after = self.database.get_last_anchored_record_id().await?;
+        let records = self.database.get_negotiation_records_after(after, self.batch_size).await?;
+        let (first_record_id, last_record_id) = match (records.first(), records.last()) {
+            (Some((first, _)), Some((last, _))) => (*first, *last),
+            _ => return Ok(None),
+        };
+
+        let leaves: Vec<[u8; 32]> = records.iter().map(|(id, record)| leaf_hash(*id, record)).collect();
+        let merkle_root = hex::encode(merkle_root(&leaves));
+        let batch_id = uuid::Uuid::new_v4();
+        let chain_tx = self.backend.commit_root(batch_id, &merkle_root).await?;
+
+        let batch = AnchorBatch {
+            id: batch_id,
+            merkle_root,
+            first_record_id,
+            last_record_id,
+            record_count: records.len() as u32,
+            chain: self.backend.chain().to_string(),
+            chain_tx,
+            anchored_at: Utc::now(),
+        };
+        self.database.create_anchor_batch(&batch).await?;
+
+        tracing::info!(
+            "Anchored {} negotiation records ({}..={}) to {}: {}",
+            batch.record_count, batch.first_record_id, batch.last_record_id, batch.chain, batch.chain_tx
+        );
+        Ok(Some(batch))
+    }
+
+    pub async fn list_batches(&self, limit: i64) -> Result<Vec<AnchorBatch>> {
+        self.database.get_anchor_batches(limit).await
+    }
+
+    pub async fn inclusion_proof(&self, record_id: i64) -> Result<InclusionProof> {
+        let batch = self.database.get_anchor_batch_for_record(record_id).await?
+            .ok_or_else(|| NegotiationError::Validation(format!("Record {} has not been anchored yet", record_id)))?;
+        let records = self.database
+            .get_negotiation_records_between(batch.first_record_id, batch.last_record_id)
+            .await?;
+
+        batch_proofs(&batch, &records, &[record_id]).pop()
+            .ok_or_else(|| NegotiationError::NotFound(format!("Record {}", record_id)))
+    }
+}
+
+/// Proofs for the records in `wanted`, sorted by ID, given every record
+/// `batch` covers. IDs the batch doesn't cover are skipped.
+pub fn batch_proofs(batch: &AnchorBatch, records: &[(i64, NegotiationRecord)], wanted: &[i64]) -> Vec<InclusionProof> {
+    let leaves: Vec<[u8; 32]> = records.iter().map(|(id, record)| leaf_hash(*id, record)).collect();
+    records.iter()
+        .enumerate()
+        .filter(|(_, (id, _))| wanted.binary_search(id).is_ok())
+        .map(|(index, (id, record))| InclusionProof {
+            record_id: *id,
+            record: record.clone(),
+            leaf_hash: hex::encode(leaves[index]),
+            path: merkle_path(&leaves, index),
+            batch: batch.clone(),
+        })
+        .collect()
+}
+
+/// Hash of a record's canonical encoding, bound to its position in the log.
+pub fn leaf_hash(record_id: i64, record: &NegotiationRecord) -> [u8; 32] {
+    let canonical = format!(
+        "{}|{}|{}|{}|{}|{}|{}|{}|{}|{}",
+        record_id,
+        record.buyer_id,
+        record.seller_id,
+        record.product_hash,
+        record.opening_bid,
+        record.close_price,
+        record.delta,
+        record.timestamp.to_rfc3339_opts(SecondsFormat::Micros, true),
+        record.duration_seconds,
+        record.message_count,
+    );
+    let mut hasher = Sha256::new();
+    hasher.update([LEAF_PREFIX]);
+    hasher.update(canonical.as_bytes());
+    hasher.finalize().into()
+}
+
+fn node_hash(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update([NODE_PREFIX]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+/// An odd node at the end of a level is carried up unchanged rather than
+/// paired with itself.
+fn next_level(level: &[[u8; 32]]) -> Vec<[u8; 32]> {
+    level.chunks(2)
+        .map(|pair| match pair {
+            [left, right] => node_hash(left, right),
+            [single] => *single,
+            _ => unreachable!(),
+        })
+        .collect()
+}
+
+pub fn merkle_root(leaves: &[[u8; 32]]) -> [u8; 32] {
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        level = next_level(&level);
+    }
+    level.first().copied().unwrap_or_else(|| Sha256::digest([]).into())
+}
+
+fn merkle_path(leaves: &[[u8; 32]], mut index: usize) -> Vec<ProofStep> {
+    let mut path = Vec::new();
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        let sibling = index ^ 1;
+        if sibling < level.len() {
+            path.push(ProofStep {
+                sibling: hex::encode(level[sibling]),
+                position: if sibling < index { SiblingPosition::Left } else { SiblingPosition::Right },
+            });
+        }
+        level = next_level(&level);
+        index /= 2;
+    }
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal::Decimal;
+    use tempfile::NamedTempFile;
+
+    struct RecordingBackend;
+
+    #[async_trait]
+    impl AnchorBackend for RecordingBackend {
+        fn chain(&self) -> &str {
+            "test"
+        }
+
+        async fn commit_root(&self, batch_id: uuid::Uuid, _merkle_root: &str) -> Result<String> {
+            Ok(format!("tx_{}", batch_id))
+        }
+    }
+
+    fn record(close_price: i64) -> NegotiationRecord {
+        NegotiationRecord {
+            buyer_id: uuid::Uuid::new_v4(),
+            seller_id: uuid::Uuid::new_v4(),
+            product_hash: "laptop-001".to_string(),
+            opening_bid: Decimal::from(100),
+            close_price: Decimal::from(close_price),
+            delta: Decimal::from(close_price - 100),
+            timestamp: Utc::now(),
+            duration_seconds: 60,
+            message_count: 4,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_anchor_batches_and_inclusion_proofs() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let database = Database::new(&format!("sqlite://{}", temp_file.path().to_string_lossy())).await.unwrap();
+        let service = AnchoringService::new(database.clone(), Arc::new(RecordingBackend), 3);
+
+        for price in 90..95 {
+            database.add_negotiation_record(&record(price)).await.unwrap();
+        }
+
+        let first = service.anchor_pending().await.unwrap().unwrap();
+        let second = service.anchor_pending().await.unwrap().unwrap();
+        assert_eq!((first.record_count, second.record_count), (3, 2));
+        assert_eq!(second.first_record_id, first.last_record_id + 1);
+        assert!(service.anchor_pending().await.unwrap().is_none());
+
+        for record_id in first.first_record_id..=second.last_record_id {
+            assert!(service.inclusion_proof(record_id).await.unwrap().verify());
+        }
+
+        // A record altered after anchoring no longer matches the root
+        let mut proof = service.inclusion_proof(first.first_record_id + 1).await.unwrap();
+        proof.record.close_price = Decimal::from(50);
+        proof.leaf_hash = hex::encode(leaf_hash(proof.record_id, &proof.record));
+        assert!(!proof.verify());
+    }
+}
@@ -0,0 +1,414 @@
+//! Anomaly detection on pricing and reputation activity.
+//!
+//! The settlement service periodically runs [`AnomalyDetector::analyze`]
+//! over recent activity and flags three kinds of anomaly into the
+//! `anomalies` table:
+//!
+//! - reputation spikes: an agent gaining far more reputation from settled
+//!   deals over the last window than it used to. Reputation changes aren't
+//!   stored, so gains are derived from settlements by the rules the buyer
+//!   agent applies, as in [`replay`](crate::replay).
+//! - price outliers: quotes far outside the band of prices the product has
+//!   settled at.
+//! - wash trading: the same two agents settling deal after deal with each
+//!   other, worst when the deals run both ways.
+//!
+//! Each anomaly carries a key naming what was flagged, so a condition that
+//! persists across runs is flagged once a day, and a quote once. Flagged
+//! anomalies are served by the settlement service's `/admin/anomalies`
+//! endpoint and the `market://anomalies` MCP resource.
+
+use crate::{
+    agent::{SETTLED_BUYER_REPUTATION, SETTLED_SELLER_REPUTATION},
+    config::AnomalyConfig,
+    database::Database,
+    error::{NegotiationError, Result},
+    model::Quote,
+    AgentId, TransactionId,
+};
+use chrono::{DateTime, Duration, Utc};
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+pub const DEFAULT_ANOMALY_INTERVAL_SECONDS: u64 = 300;
+pub const DEFAULT_ANOMALY_WINDOW_HOURS: u32 = 24;
+pub const DEFAULT_ANOMALY_BASELINE_DAYS: u32 = 30;
+pub const DEFAULT_REPUTATION_SPIKE_POINTS: i32 = 50;
+pub const DEFAULT_REPUTATION_SPIKE_FACTOR: f64 = 5.0;
+pub const DEFAULT_PRICE_BAND_DEVIATIONS: f64 = 3.0;
+pub const DEFAULT_MIN_PRICE_SAMPLES: usize = 5;
+pub const DEFAULT_WASH_TRADE_MIN_DEALS: usize = 5;
+
+/// Quotes closer than this share of the mean settled price are never
+/// outliers, however tight the band
+const MIN_PRICE_DEVIATION: f64 = 0.1;
+
+/// Anomalies listed when the query doesn't set a limit
+const DEFAULT_ANOMALY_LIMIT: i64 = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnomalyKind {
+    ReputationSpike,
+    PriceOutlier,
+    WashTrading,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnomalySeverity {
+    Low,
+    Medium,
+    High,
+}
+
+impl AnomalySeverity {
+    /// Grades how far `measure` went past `threshold`: three times or more
+    /// is high, half again medium.
+    pub fn grade(measure: f64, threshold: f64) -> Self {
+        let ratio = measure / threshold;
+        if ratio >= 3.0 {
+            AnomalySeverity::High
+        } else if ratio >= 1.5 {
+            AnomalySeverity::Medium
+        } else {
+            AnomalySeverity::Low
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Anomaly {
+    pub id: uuid::Uuid,
+    /// Names what was flagged; an anomaly whose key was already flagged
+    /// isn't stored again
+    pub key: String,
+    pub kind: AnomalyKind,
+    pub severity: AnomalySeverity,
+    /// The agent flagged: the one gaining reputation, the quoting seller, or
+    /// the buyer of a trading pair
+    pub agent_id: AgentId,
+    /// The seller of a trading pair
+    pub related_agent_id: Option<AgentId>,
+    pub summary: String,
+    /// The measurements behind the flag
+    pub details: serde_json::Value,
+    pub detected_at: DateTime<Utc>,
+}
+
+/// A settled negotiation, as the detectors see it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SettledDeal {
+    pub negotiation_id: TransactionId,
+    pub buyer_id: AgentId,
+    pub seller_id: AgentId,
+    pub product_id: String,
+    pub close_price: Decimal,
+    pub currency: String,
+    pub settled_at: DateTime<Utc>,
+}
+
+/// Filters for listing anomalies, newest first
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AnomalyQuery {
+    #[serde(default)]
+    pub kind: Option<AnomalyKind>,
+    #[serde(default)]
+    pub severity: Option<AnomalySeverity>,
+    /// Anomalies involving this agent on either side
+    #[serde(default)]
+    pub agent_id: Option<AgentId>,
+    #[serde(default)]
+    pub limit: Option<i64>,
+}
+
+impl AnomalyQuery {
+    pub fn limit(&self) -> Result<i64> {
+        match self.limit {
+            Some(limit) if limit <= 0 => Err(NegotiationError::Validation("Limit must be greater than 0".to_string())),
+            Some(limit) => Ok(limit),
+            None => Ok(DEFAULT_ANOMALY_LIMIT),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct AnomalyDetector {
+    database: Database,
+    config: AnomalyConfig,
+}
+
+impl AnomalyDetector {
+    pub fn new(database: Database, config: AnomalyConfig) -> Self {
+        Self { database, config }
+    }
+
+    /// Looks for anomalies in the activity up to `now` and stores those not
+    /// flagged before, returning them.
+    pub async fn analyze(&self, now: DateTime<Utc>) -> Result<Vec<Anomaly>> {
+        let window_start = now - Duration::hours(i64::from(self.config.window_hours));
+        let baseline_start = window_start - Duration::days(i64::from(self.config.baseline_days));
+        let deals = self.database.get_settled_deals_since(baseline_start).await?;
+        let (recent, history): (Vec<SettledDeal>, Vec<SettledDeal>) =
+            deals.into_iter().partition(|deal| deal.settled_at >= window_start);
+        let quotes = self.database.get_quotes_since(window_start).await?;
+
+        let mut candidates = reputation_spikes(&recent, &history, &self.config, now);
+        candidates.extend(price_outliers(&quotes, &history, &self.config, now));
+        candidates.extend(wash_trading(&recent, &self.config, now));
+
+        let mut flagged = Vec::new();
+        for anomaly in candidates {
+            if self.database.create_anomaly(&anomaly).await? {
+                tracing::warn!("{:?} anomaly flagged ({:?}): {}", anomaly.kind, anomaly.severity, anomaly.summary);
+                flagged.push(anomaly);
+            }
+        }
+        Ok(flagged)
+    }
+
+    pub async fn list(&self, query: &AnomalyQuery) -> Result<Vec<Anomaly>> {
+        self.database.get_anomalies(query).await
+    }
+}
+
+/// Agents whose reputation gained over the window reaches the spike points
+/// and is more than the spike factor times their average over the baseline.
+pub fn reputation_spikes(recent: &[SettledDeal], history: &[SettledDeal], config: &AnomalyConfig, now: DateTime<Utc>) -> Vec<Anomaly> {
+    let recent_gains = reputation_gains(recent);
+    let baseline_gains = reputation_gains(history);
+    let windows_in_baseline = f64::from(config.baseline_days * 24) / f64::from(config.window_hours.max(1));
+
+    let mut spikes: Vec<Anomaly> = recent_gains.into_iter()
+        .filter_map(|(agent_id, gained)| {
+            let usual = f64::from(baseline_gains.get(&agent_id).copied().unwrap_or(0)) / windows_in_baseline;
+            if gained < config.reputation_spike_points || f64::from(gained) <= usual * config.reputation_spike_factor {
+                return None;
+            }
+            Some(Anomaly {
+                id: uuid::Uuid::new_v4(),
+                key: format!("reputation_spike:{}:{}", agent_id, now.date_naive()),
+                kind: AnomalyKind::ReputationSpike,
+                severity: AnomalySeverity::grade(f64::from(gained), f64::from(config.reputation_spike_points)),
+                agent_id,
+                related_agent_id: None,
+                summary: format!(
+                    "Agent {} gained {} reputation in {} hours, against {:.1} usually",
+                    agent_id, gained, config.window_hours, usual
+                ),
+                details: serde_json::json!({
+                    "gained": gained,
+                    "usual": usual,
+                    "window_hours": config.window_hours,
+                }),
+                detected_at: now,
+            })
+        })
+        .collect();
+    spikes.sort_by(|a, b| a.key.cmp(&b.key));
+    spikes
+}
+
+fn reputation_gains(deals: &[SettledDeal]) -> HashMap<AgentId, i32> {
+    let mut gains = HashMap::new();
+    for deal in deals {
+        *gains.entry(deal.seller_id).or_insert(0) += SETTLED_SELLER_REPUTATION;
+        *gains.entry(deal.buyer_id).or_insert(0) += SETTLED_BUYER_REPUTATION;
+    }
+    gains
+}
+
+/// Quotes priced more than the band's deviations from the mean price the
+/// product settled at in the same currency. Products with fewer than the
+/// minimum samples have no band yet.
+pub fn price_outliers(quotes: &[(String, Quote)], history: &[SettledDeal], config: &AnomalyConfig, now: DateTime<Utc>) -> Vec<Anomaly> {
+    let mut prices: HashMap<(&str, &str), Vec<f64>> = HashMap::new();
+    for deal in history {
+        if let Some(price) = deal.close_price.to_f64() {
+            prices.entry((&deal.product_id, &deal.currency)).or_default().push(price);
+        }
+    }
+
+    quotes.iter()
+        .filter_map(|(product_id, quote)| {
+            let samples = prices.get(&(product_id.as_str(), quote.currency.as_str()))?;
+            if samples.len() < config.min_price_samples.max(1) {
+                return None;
+            }
+            let mean = samples.iter().sum::<f64>() / samples.len() as f64;
+            let deviation = (samples.iter().map(|price| (price - mean).powi(2)).sum::<f64>() / samples.len() as f64).sqrt();
+            let price = quote.price.to_f64()?;
+            let distance = (price - mean).abs();
+            if distance <= mean * MIN_PRICE_DEVIATION || distance <= deviation * config.price_band_deviations {
+                return None;
+            }
+            let deviations = if deviation > 0.0 { distance / deviation } else { f64::INFINITY };
+            Some(Anomaly {
+                id: uuid::Uuid::new_v4(),
+                key: format!("price_outlier:{}", quote.id),
+                kind: AnomalyKind::PriceOutlier,
+                severity: AnomalySeverity::grade(deviations, config.price_band_deviations),
+                agent_id: quote.seller_id,
+                related_agent_id: None,
+                summary: format!(
+                    "Quote {} prices {} at {} {}, against a mean of {:.2} over {} deals",
+                    quote.id, product_id, quote.price, quote.currency, mean, samples.len()
+                ),
+                details: serde_json::json!({
+                    "quote_id": quote.id,
+                    "product_id": product_id,
+                    "price": quote.price,
+                    "currency": quote.currency,
+                    "mean": mean,
+                    "std_dev": deviation,
+                    "samples": samples.len(),
+                }),
+                detected_at: now,
+            })
+        })
+        .collect()
+}
+
+/// Pairs of agents that settled at least the minimum deals with each other
+/// over the window. Deals in both directions are always graded high.
+pub fn wash_trading(recent: &[SettledDeal], config: &AnomalyConfig, now: DateTime<Utc>) -> Vec<Anomaly> {
+    // Per pair, ordered by ID: deals each way
+    let mut pairs: HashMap<(AgentId, AgentId), (usize, usize)> = HashMap::new();
+    for deal in recent {
+        if deal.buyer_id < deal.seller_id {
+            pairs.entry((deal.buyer_id, deal.seller_id)).or_default().0 += 1;
+        } else {
+            pairs.entry((deal.seller_id, deal.buyer_id)).or_default().1 += 1;
+        }
+    }
+
+    let min_deals = config.wash_trade_min_deals.max(1);
+    let mut flagged: Vec<Anomaly> = pairs.into_iter()
+        .filter(|(_, (forward, backward))| forward + backward >= min_deals)
+        .map(|((first, second), (forward, backward))| {
+            let deals = forward + backward;
+            let both_ways = forward > 0 && backward > 0;
+            // The buyer of most of the deals is the flagged agent
+            let (buyer_id, seller_id) = if forward >= backward { (first, second) } else { (second, first) };
+            Anomaly {
+                id: uuid::Uuid::new_v4(),
+                key: format!("wash_trading:{}:{}:{}", first, second, now.date_naive()),
+                kind: AnomalyKind::WashTrading,
+                severity: if both_ways {
+                    AnomalySeverity::High
+                } else {
+                    AnomalySeverity::grade(deals as f64, min_deals as f64)
+                },
+                agent_id: buyer_id,
+                related_agent_id: Some(seller_id),
+                summary: format!(
+                    "Agents {} and {} settled {} deals with each other in {} hours{}",
+                    buyer_id, seller_id, deals, config.window_hours, if both_ways { ", in both directions" } else { "" }
+                ),
+                details: serde_json::json!({
+                    "deals": deals,
+                    "both_ways": both_ways,
+                    "window_hours": config.window_hours,
+                }),
+                detected_at: now,
+            }
+        })
+        .collect();
+    flagged.sort_by(|a, b| a.key.cmp(&b.key));
+    flagged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::QuoteFirmness;
+    use tempfile::NamedTempFile;
+
+    fn deal(buyer_id: AgentId, seller_id: AgentId, price: i64, settled_at: DateTime<Utc>) -> SettledDeal {
+        SettledDeal {
+            negotiation_id: uuid::Uuid::new_v4(),
+            buyer_id,
+            seller_id,
+            product_id: "laptop-001".to_string(),
+            close_price: Decimal::from(price),
+            currency: "USD".to_string(),
+            settled_at,
+        }
+    }
+
+    fn quote(seller_id: AgentId, price: i64) -> (String, Quote) {
+        ("laptop-001".to_string(), Quote {
+            id: uuid::Uuid::new_v4(),
+            rfq_id: uuid::Uuid::new_v4(),
+            seller_id,
+            price: Decimal::from(price),
+            currency: "USD".to_string(),
+            available_quantity: 5,
+            delivery_estimate: None,
+            ttl_seconds: 300,
+            firmness: QuoteFirmness::default(),
+            shipping_options: vec![],
+            metadata: HashMap::new(),
+            created_at: Utc::now(),
+            nonce: None,
+            signature: None,
+        })
+    }
+
+    #[test]
+    fn test_detectors_flag_spikes_outliers_and_wash_trading() {
+        let config = AnomalyConfig { reputation_spike_points: 15, ..Default::default() };
+        let now = Utc::now();
+        let (buyer, seller, other) = (AgentId::new_v4(), AgentId::new_v4(), AgentId::new_v4());
+        let history: Vec<SettledDeal> = [990, 1000, 1010, 1000, 1005, 995]
+            .into_iter()
+            .map(|price| deal(other, seller, price, now - Duration::days(10)))
+            .collect();
+
+        // Six deals in a day between one pair: both spike, and the pair is flagged
+        let recent: Vec<SettledDeal> = (0..6).map(|_| deal(buyer, seller, 1000, now - Duration::hours(1))).collect();
+        let spikes = reputation_spikes(&recent, &history, &config, now);
+        assert_eq!(spikes.len(), 2);
+        let washing = wash_trading(&recent, &config, now);
+        assert_eq!(washing.len(), 1);
+        assert_eq!((washing[0].agent_id, washing[0].related_agent_id), (buyer, Some(seller)));
+        assert_eq!(washing[0].severity, AnomalySeverity::Low);
+
+        // The same deals running both ways are graded high
+        let looped: Vec<SettledDeal> = (0..6)
+            .map(|i| if i % 2 == 0 { deal(buyer, seller, 1000, now) } else { deal(seller, buyer, 1000, now) })
+            .collect();
+        assert_eq!(wash_trading(&looped, &config, now)[0].severity, AnomalySeverity::High);
+        assert!(wash_trading(&recent[..4], &config, now).is_empty());
+
+        let outliers = price_outliers(&[quote(seller, 1004), quote(seller, 2500), quote(seller, 100)], &history, &config, now);
+        assert_eq!(outliers.len(), 2);
+        assert!(outliers.iter().all(|anomaly| anomaly.severity == AnomalySeverity::High));
+        // Too little history for a band
+        assert!(price_outliers(&[quote(seller, 2500)], &history[..3], &config, now).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_analyzer_flags_each_anomaly_once() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let database = Database::new(&format!("sqlite://{}", temp_file.path().to_string_lossy())).await.unwrap();
+        let detector = AnomalyDetector::new(database.clone(), AnomalyConfig::default());
+        let now = Utc::now();
+
+        let anomaly = wash_trading(
+            &(0..5).map(|_| deal(AgentId::new_v4(), AgentId::new_v4(), 1000, now)).collect::<Vec<_>>(),
+            &AnomalyConfig { wash_trade_min_deals: 1, ..Default::default() },
+            now,
+        ).remove(0);
+        assert!(database.create_anomaly(&anomaly).await.unwrap());
+        assert!(!database.create_anomaly(&Anomaly { id: uuid::Uuid::new_v4(), ..anomaly.clone() }).await.unwrap());
+        assert!(detector.analyze(now).await.unwrap().is_empty());
+
+        let listed = detector.list(&AnomalyQuery { agent_id: anomaly.related_agent_id, ..Default::default() }).await.unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].details, anomaly.details);
+        assert!(detector.list(&AnomalyQuery { kind: Some(AnomalyKind::PriceOutlier), ..Default::default() }).await.unwrap().is_empty());
+        assert!(detector.list(&AnomalyQuery { limit: Some(0), ..Default::default() }).await.is_err());
+    }
+}
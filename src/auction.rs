@@ -0,0 +1,713 @@
+//! Buyer-run reverse auctions and seller-run listings.
+//!
+//! A buyer publishes an RFQ with a bidding window and sellers bid the price
+//! down. Sealed auctions take one hidden bid per seller; open auctions show the
+//! lowest bid, require each bid to beat it, and extend the window when a bid
+//! lands in its last moments so nobody can win by sniping. The lowest bid wins
+//! when the window closes, earliest first on ties. Every bid is persisted.
+//!
+//! Sellers with scarce stock can instead list it. English listings take rising
+//! bids from buyers and go to the highest bid at or above the reserve when the
+//! window closes; Dutch listings lower the ask over time and go to the first
+//! buyer to take it. A won listing becomes an accepted negotiation that the
+//! buyer settles like any other.
+
+use crate::{
+    database::Database,
+    error::{NegotiationError, Result},
+    model::{AgentType, Negotiation, Quote, RFQ},
+    AgentId, TransactionId,
+};
+use chrono::{DateTime, Duration, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BidVisibility {
+    Sealed,
+    Open,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuctionStatus {
+    Open,
+    Awarded,
+    /// Closed without any bids
+    Unawarded,
+}
+
+/// A bid within `window_seconds` of the close pushes the close back by
+/// `extension_seconds`, at most `max_extensions` times. Open auctions only.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AntiSniping {
+    pub window_seconds: i64,
+    pub extension_seconds: i64,
+    pub max_extensions: u32,
+}
+
+impl Default for AntiSniping {
+    fn default() -> Self {
+        Self {
+            window_seconds: 60,
+            extension_seconds: 120,
+            max_extensions: 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Auction {
+    pub id: uuid::Uuid,
+    pub rfq: RFQ,
+    pub visibility: BidVisibility,
+    pub anti_sniping: AntiSniping,
+    pub status: AuctionStatus,
+    pub closes_at: DateTime<Utc>,
+    pub extensions: u32,
+    pub winning_bid_id: Option<uuid::Uuid>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Bid {
+    pub id: uuid::Uuid,
+    pub auction_id: uuid::Uuid,
+    pub seller_id: AgentId,
+    /// Price for the whole RFQ, in the RFQ's currency
+    pub price: Decimal,
+    pub delivery_estimate: Option<String>,
+    pub submitted_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateAuctionRequest {
+    pub rfq: RFQ,
+    pub visibility: BidVisibility,
+    pub duration_seconds: i64,
+    #[serde(default)]
+    pub anti_sniping: AntiSniping,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BidRequest {
+    pub seller_id: AgentId,
+    pub price: Decimal,
+    pub currency: String,
+    pub delivery_estimate: Option<String>,
+}
+
+/// What bidders and the buyer see. Sealed bids stay hidden until the close.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuctionView {
+    pub auction: Auction,
+    pub bid_count: usize,
+    pub lowest_price: Option<Decimal>,
+    pub bids: Vec<Bid>,
+}
+
+impl Auction {
+    pub fn is_open_at(&self, now: DateTime<Utc>) -> bool {
+        self.status == AuctionStatus::Open && now < self.closes_at
+    }
+
+    /// Extends the close if a bid at `now` falls in the anti-sniping window.
+    /// Returns whether the auction was extended.
+    fn extend_for_bid(&mut self, now: DateTime<Utc>) -> bool {
+        self.visibility == BidVisibility::Open
+            && self.anti_sniping.extend(&mut self.closes_at, &mut self.extensions, now)
+    }
+}
+
+impl AntiSniping {
+    fn extend(&self, closes_at: &mut DateTime<Utc>, extensions: &mut u32, now: DateTime<Utc>) -> bool {
+        if *extensions >= self.max_extensions || *closes_at - now > Duration::seconds(self.window_seconds) {
+            return false;
+        }
+        *closes_at += Duration::seconds(self.extension_seconds);
+        *extensions += 1;
+        true
+    }
+}
+
+/// Lowest price wins; ties go to the earlier bid.
+pub fn pick_winner(bids: &[Bid]) -> Option<&Bid> {
+    bids.iter().min_by(|a, b| a.price.cmp(&b.price).then(a.submitted_at.cmp(&b.submitted_at)))
+}
+
+/// How long the winner of a listing has to settle at the winning price
+pub const LISTING_SETTLEMENT_WINDOW_SECONDS: u32 = 86400;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "format", rename_all = "snake_case")]
+pub enum ListingFormat {
+    /// Ascending: each bid must beat the highest by `min_increment`
+    English { min_increment: Decimal },
+    /// Descending: the ask drops by `decrement` every `interval_seconds`
+    /// until it reaches the reserve
+    Dutch { decrement: Decimal, interval_seconds: i64 },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Listing {
+    pub id: uuid::Uuid,
+    pub seller_id: AgentId,
+    pub product_id: String,
+    pub quantity: u32,
+    pub currency: String,
+    pub format: ListingFormat,
+    /// Opening bid (English) or opening ask (Dutch), for the whole quantity
+    pub start_price: Decimal,
+    /// Lowest price the seller will sell at
+    pub reserve_price: Decimal,
+    /// English listings only
+    pub anti_sniping: AntiSniping,
+    pub status: AuctionStatus,
+    pub closes_at: DateTime<Utc>,
+    pub extensions: u32,
+    pub winning_bid_id: Option<uuid::Uuid>,
+    /// Accepted negotiation created for the winner
+    pub negotiation_id: Option<TransactionId>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListingBid {
+    pub id: uuid::Uuid,
+    pub listing_id: uuid::Uuid,
+    pub buyer_id: AgentId,
+    pub price: Decimal,
+    pub submitted_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateListingRequest {
+    pub seller_id: AgentId,
+    pub product_id: String,
+    pub quantity: u32,
+    pub currency: String,
+    pub format: ListingFormat,
+    pub start_price: Decimal,
+    pub reserve_price: Decimal,
+    pub duration_seconds: i64,
+    #[serde(default)]
+    pub anti_sniping: AntiSniping,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListingBidRequest {
+    pub buyer_id: AgentId,
+    pub price: Decimal,
+    pub currency: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListingView {
+    pub listing: Listing,
+    pub bid_count: usize,
+    pub highest_price: Option<Decimal>,
+    /// What a Dutch listing would sell for right now
+    pub current_ask: Option<Decimal>,
+    pub bids: Vec<ListingBid>,
+    pub negotiation: Option<Negotiation>,
+}
+
+impl Listing {
+    pub fn is_open_at(&self, now: DateTime<Utc>) -> bool {
+        self.status == AuctionStatus::Open && now < self.closes_at
+    }
+
+    /// Current ask of a Dutch listing; `None` for English listings.
+    pub fn current_ask(&self, now: DateTime<Utc>) -> Option<Decimal> {
+        let ListingFormat::Dutch { decrement, interval_seconds } = self.format else {
+            return None;
+        };
+        let steps = ((now - self.created_at).num_seconds() / interval_seconds).max(0);
+        Some((self.start_price - decrement * Decimal::from(steps)).max(self.reserve_price))
+    }
+}
+
+/// Highest price wins; ties go to the earlier bid.
+pub fn pick_highest(bids: &[ListingBid]) -> Option<&ListingBid> {
+    bids.iter().max_by(|a, b| a.price.cmp(&b.price).then(b.submitted_at.cmp(&a.submitted_at)))
+}
+
+#[derive(Clone)]
+pub struct AuctionService {
+    database: Database,
+}
+
+impl AuctionService {
+    pub fn new(database: Database) -> Self {
+        Self { database }
+    }
+
+    pub async fn create_auction(&self, request: CreateAuctionRequest) -> Result<Auction> {
+        request.rfq.validate()?;
+        if request.duration_seconds <= 0 {
+            return Err(NegotiationError::Validation("Bidding window must be positive".to_string()));
+        }
+        let rule = request.anti_sniping;
+        if rule.window_seconds < 0 || rule.extension_seconds <= 0 {
+            return Err(NegotiationError::Validation("Invalid anti-sniping rule".to_string()));
+        }
+
+        let now = Utc::now();
+        let auction = Auction {
+            id: uuid::Uuid::new_v4(),
+            rfq: request.rfq,
+            visibility: request.visibility,
+            anti_sniping: rule,
+            status: AuctionStatus::Open,
+            closes_at: now + Duration::seconds(request.duration_seconds),
+            extensions: 0,
+            winning_bid_id: None,
+            created_at: now,
+        };
+        self.database.create_auction(&auction).await?;
+
+        tracing::info!("Auction {} open for {} until {}", auction.id, auction.rfq.product_id, auction.closes_at);
+        Ok(auction)
+    }
+
+    pub async fn submit_bid(&self, auction_id: uuid::Uuid, request: BidRequest, now: DateTime<Utc>) -> Result<Bid> {
+        let mut auction = self.get_auction(auction_id).await?;
+        if !auction.is_open_at(now) {
+            return Err(NegotiationError::Validation(format!("Auction {} is closed", auction_id)));
+        }
+
+        let seller = self.database.get_agent(request.seller_id).await?
+            .ok_or(NegotiationError::AgentNotFound(request.seller_id))?;
+        if !matches!(seller.agent_type, AgentType::Seller) {
+            return Err(NegotiationError::Validation("Only sellers can bid".to_string()));
+        }
+        if request.currency != auction.rfq.currency {
+            return Err(NegotiationError::Currency(format!(
+                "Bids must be in {}, got {}", auction.rfq.currency, request.currency
+            )));
+        }
+        if request.price <= Decimal::ZERO || request.price > auction.rfq.max_price {
+            return Err(NegotiationError::Validation("Bid must be positive and within the buyer's max price".to_string()));
+        }
+
+        let bids = self.database.get_auction_bids(auction_id).await?;
+        match auction.visibility {
+            BidVisibility::Sealed => {
+                if bids.iter().any(|bid| bid.seller_id == request.seller_id) {
+                    return Err(NegotiationError::Validation("Seller has already bid in this sealed auction".to_string()));
+                }
+            }
+            BidVisibility::Open => {
+                if let Some(lowest) = pick_winner(&bids) {
+                    if request.price >= lowest.price {
+                        return Err(NegotiationError::Validation(format!("Bid must beat the current lowest of {}", lowest.price)));
+                    }
+                }
+            }
+        }
+
+        let bid = Bid {
+            id: uuid::Uuid::new_v4(),
+            auction_id,
+            seller_id: request.seller_id,
+            price: request.price,
+            delivery_estimate: request.delivery_estimate,
+            submitted_at: now,
+        };
+        self.database.create_auction_bid(&bid).await?;
+
+        if auction.extend_for_bid(now) {
+            self.database.update_auction(&auction).await?;
+            tracing::info!("Late bid extended auction {} to {}", auction_id, auction.closes_at);
+        }
+        Ok(bid)
+    }
+
+    pub async fn get_auction(&self, auction_id: uuid::Uuid) -> Result<Auction> {
+        self.database.get_auction(auction_id).await?
+            .ok_or_else(|| NegotiationError::NotFound(format!("Auction {}", auction_id)))
+    }
+
+    pub async fn view_auction(&self, auction_id: uuid::Uuid) -> Result<AuctionView> {
+        let auction = self.get_auction(auction_id).await?;
+        let bids = self.database.get_auction_bids(auction_id).await?;
+        let hidden = auction.visibility == BidVisibility::Sealed && auction.status == AuctionStatus::Open;
+
+        Ok(AuctionView {
+            bid_count: bids.len(),
+            lowest_price: if hidden { None } else { pick_winner(&bids).map(|bid| bid.price) },
+            bids: if hidden { Vec::new() } else { bids },
+            auction,
+        })
+    }
+
+    pub async fn list_open_auctions(&self) -> Result<Vec<Auction>> {
+        self.database.get_open_auctions().await
+    }
+
+    /// Picks the winner of an auction whose window has passed.
+    pub async fn close_auction(&self, auction_id: uuid::Uuid, now: DateTime<Utc>) -> Result<Auction> {
+        let mut auction = self.get_auction(auction_id).await?;
+        if auction.status != AuctionStatus::Open {
+            return Ok(auction);
+        }
+        if now < auction.closes_at {
+            return Err(NegotiationError::Validation(format!("Auction {} is open until {}", auction_id, auction.closes_at)));
+        }
+
+        let bids = self.database.get_auction_bids(auction_id).await?;
+        match pick_winner(&bids) {
+            Some(winner) => {
+                auction.status = AuctionStatus::Awarded;
+                auction.winning_bid_id = Some(winner.id);
+                tracing::info!("Auction {} awarded to seller {} at {}", auction_id, winner.seller_id, winner.price);
+            }
+            None => {
+                auction.status = AuctionStatus::Unawarded;
+                tracing::info!("Auction {} closed without bids", auction_id);
+            }
+        }
+        self.database.update_auction(&auction).await?;
+        Ok(auction)
+    }
+
+    /// Closes every open auction whose window has passed.
+    pub async fn close_due_auctions(&self, now: DateTime<Utc>) -> Result<Vec<Auction>> {
+        let mut closed = Vec::new();
+        for auction in self.database.get_open_auctions().await? {
+            if auction.closes_at <= now {
+                closed.push(self.close_auction(auction.id, now).await?);
+            }
+        }
+        Ok(closed)
+    }
+
+    pub async fn create_listing(&self, request: CreateListingRequest) -> Result<Listing> {
+        let seller = self.database.get_agent(request.seller_id).await?
+            .ok_or(NegotiationError::AgentNotFound(request.seller_id))?;
+        if !matches!(seller.agent_type, AgentType::Seller) {
+            return Err(NegotiationError::Validation("Only sellers can list products".to_string()));
+        }
+        crate::currency::validate_currency_code(&request.currency)?;
+        if request.quantity == 0 {
+            return Err(NegotiationError::Validation("Quantity must be greater than 0".to_string()));
+        }
+        if request.start_price <= Decimal::ZERO || request.reserve_price <= Decimal::ZERO {
+            return Err(NegotiationError::Validation("Start and reserve prices must be positive".to_string()));
+        }
+        if request.duration_seconds <= 0 {
+            return Err(NegotiationError::Validation("Bidding window must be positive".to_string()));
+        }
+        match request.format {
+            ListingFormat::English { min_increment } => {
+                let rule = request.anti_sniping;
+                if min_increment <= Decimal::ZERO || rule.window_seconds < 0 || rule.extension_seconds <= 0 {
+                    return Err(NegotiationError::Validation("Invalid English listing rules".to_string()));
+                }
+            }
+            ListingFormat::Dutch { decrement, interval_seconds } => {
+                if decrement <= Decimal::ZERO || interval_seconds <= 0 || request.reserve_price > request.start_price {
+                    return Err(NegotiationError::Validation(
+                        "Dutch listings need a positive decrement and interval, and a reserve no higher than the start price".to_string()
+                    ));
+                }
+            }
+        }
+
+        let now = Utc::now();
+        let listing = Listing {
+            id: uuid::Uuid::new_v4(),
+            seller_id: request.seller_id,
+            product_id: request.product_id,
+            quantity: request.quantity,
+            currency: request.currency,
+            format: request.format,
+            start_price: request.start_price,
+            reserve_price: request.reserve_price,
+            anti_sniping: request.anti_sniping,
+            status: AuctionStatus::Open,
+            closes_at: now + Duration::seconds(request.duration_seconds),
+            extensions: 0,
+            winning_bid_id: None,
+            negotiation_id: None,
+            created_at: now,
+        };
+        self.database.create_listing(&listing).await?;
+
+        tracing::info!("Listing {} open for {} until {}", listing.id, listing.product_id, listing.closes_at);
+        Ok(listing)
+    }
+
+    /// Takes a buyer's bid. A bid at or above a Dutch listing's current ask
+    /// wins it outright, at the ask.
+    pub async fn submit_listing_bid(&self, listing_id: uuid::Uuid, request: ListingBidRequest, now: DateTime<Utc>) -> Result<ListingBid> {
+        let mut listing = self.get_listing(listing_id).await?;
+        if !listing.is_open_at(now) {
+            return Err(NegotiationError::Validation(format!("Listing {} is closed", listing_id)));
+        }
+
+        let buyer = self.database.get_agent(request.buyer_id).await?
+            .ok_or(NegotiationError::AgentNotFound(request.buyer_id))?;
+        if !matches!(buyer.agent_type, AgentType::Buyer) {
+            return Err(NegotiationError::Validation("Only buyers can bid on listings".to_string()));
+        }
+        if request.currency != listing.currency {
+            return Err(NegotiationError::Currency(format!(
+                "Bids must be in {}, got {}", listing.currency, request.currency
+            )));
+        }
+
+        let price = match listing.format {
+            ListingFormat::English { min_increment } => {
+                let bids = self.database.get_listing_bids(listing_id).await?;
+                let minimum = pick_highest(&bids)
+                    .map(|highest| highest.price + min_increment)
+                    .unwrap_or(listing.start_price);
+                if request.price < minimum {
+                    return Err(NegotiationError::Validation(format!("Bid must be at least {}", minimum)));
+                }
+                request.price
+            }
+            ListingFormat::Dutch { .. } => {
+                let ask = listing.current_ask(now).unwrap_or(listing.start_price);
+                if request.price < ask {
+                    return Err(NegotiationError::Validation(format!("Bid is below the current ask of {}", ask)));
+                }
+                ask
+            }
+        };
+
+        let bid = ListingBid {
+            id: uuid::Uuid::new_v4(),
+            listing_id,
+            buyer_id: request.buyer_id,
+            price,
+            submitted_at: now,
+        };
+        self.database.create_listing_bid(&bid).await?;
+
+        match listing.format {
+            ListingFormat::Dutch { .. } => self.award_listing(&mut listing, &bid).await?,
+            ListingFormat::English { .. } => {
+                if listing.anti_sniping.extend(&mut listing.closes_at, &mut listing.extensions, now) {
+                    self.database.update_listing(&listing).await?;
+                    tracing::info!("Late bid extended listing {} to {}", listing_id, listing.closes_at);
+                }
+            }
+        }
+        Ok(bid)
+    }
+
+    pub async fn get_listing(&self, listing_id: uuid::Uuid) -> Result<Listing> {
+        self.database.get_listing(listing_id).await?
+            .ok_or_else(|| NegotiationError::NotFound(format!("Listing {}", listing_id)))
+    }
+
+    pub async fn view_listing(&self, listing_id: uuid::Uuid) -> Result<ListingView> {
+        let listing = self.get_listing(listing_id).await?;
+        let bids = self.database.get_listing_bids(listing_id).await?;
+        let negotiation = match listing.negotiation_id {
+            Some(negotiation_id) => self.database.get_negotiation(negotiation_id).await?,
+            None => None,
+        };
+
+        Ok(ListingView {
+            bid_count: bids.len(),
+            highest_price: pick_highest(&bids).map(|bid| bid.price),
+            current_ask: if listing.status == AuctionStatus::Open { listing.current_ask(Utc::now()) } else { None },
+            bids,
+            negotiation,
+            listing,
+        })
+    }
+
+    pub async fn list_open_listings(&self) -> Result<Vec<Listing>> {
+        self.database.get_open_listings().await
+    }
+
+    /// Settles a listing whose window has passed: English listings go to the
+    /// highest bid if it meets the reserve, unsold Dutch listings lapse.
+    pub async fn close_listing(&self, listing_id: uuid::Uuid, now: DateTime<Utc>) -> Result<Listing> {
+        let mut listing = self.get_listing(listing_id).await?;
+        if listing.status != AuctionStatus::Open {
+            return Ok(listing);
+        }
+        if now < listing.closes_at {
+            return Err(NegotiationError::Validation(format!("Listing {} is open until {}", listing_id, listing.closes_at)));
+        }
+
+        let bids = self.database.get_listing_bids(listing_id).await?;
+        match pick_highest(&bids).filter(|bid| bid.price >= listing.reserve_price) {
+            Some(winner) => self.award_listing(&mut listing, winner).await?,
+            None => {
+                listing.status = AuctionStatus::Unawarded;
+                self.database.update_listing(&listing).await?;
+                tracing::info!("Listing {} closed unsold", listing_id);
+            }
+        }
+        Ok(listing)
+    }
+
+    /// Closes every open listing whose window has passed.
+    pub async fn close_due_listings(&self, now: DateTime<Utc>) -> Result<Vec<Listing>> {
+        let mut closed = Vec::new();
+        for listing in self.database.get_open_listings().await? {
+            if listing.closes_at <= now {
+                closed.push(self.close_listing(listing.id, now).await?);
+            }
+        }
+        Ok(closed)
+    }
+
+    /// Records the winning bid as an accepted negotiation, which the buyer then
+    /// settles through the settlement service.
+    async fn award_listing(&self, listing: &mut Listing, bid: &ListingBid) -> Result<()> {
+        let rfq = RFQ::new(bid.buyer_id, listing.product_id.clone(), listing.quantity, listing.start_price, listing.currency.clone(), listing.closes_at);
+        let mut negotiation = Negotiation::new(rfq, listing.seller_id);
+        self.database.create_negotiation(&negotiation).await?;
+
+        let quote = Quote::new(
+            negotiation.rfq_id,
+            listing.seller_id,
+            bid.price,
+            listing.currency.clone(),
+            listing.quantity,
+            LISTING_SETTLEMENT_WINDOW_SECONDS,
+        );
+        self.database.create_quote(&quote).await?;
+        negotiation.add_quote(&quote)?;
+        negotiation.accept(bid.price)?;
+        self.database.update_negotiation(&negotiation).await?;
+
+        listing.status = AuctionStatus::Awarded;
+        listing.winning_bid_id = Some(bid.id);
+        listing.negotiation_id = Some(negotiation.id);
+        self.database.update_listing(listing).await?;
+
+        tracing::info!("Listing {} sold to buyer {} at {}", listing.id, bid.buyer_id, bid.price);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{AgentInfo, PaymentMethod};
+    use tempfile::NamedTempFile;
+
+    async fn seller(database: &Database) -> AgentId {
+        agent(database, AgentType::Seller).await
+    }
+
+    async fn agent(database: &Database, agent_type: AgentType) -> AgentId {
+        let agent = AgentInfo {
+            id: uuid::Uuid::new_v4(),
+            agent_type,
+            name: "TechStore".to_string(),
+            endpoint: "http://localhost:8001".to_string(),
+            public_key: "key".to_string(),
+            reputation_score: 80,
+            products: vec![],
+            payment_methods: vec![PaymentMethod::Stripe],
+            protocol_versions: vec![],
+            preferred_languages: vec![],
+            tls_fingerprint: None,
+            created_at: Utc::now(),
+            last_active: Utc::now(),
+        };
+        database.create_agent(&agent).await.unwrap();
+        agent.id
+    }
+
+    fn bid(seller_id: AgentId, price: i64) -> BidRequest {
+        BidRequest {
+            seller_id,
+            price: Decimal::from(price),
+            currency: "USD".to_string(),
+            delivery_estimate: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_open_auction_extends_and_awards_lowest_bid() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let database = Database::new(&format!("sqlite://{}", temp_file.path().to_string_lossy())).await.unwrap();
+        let service = AuctionService::new(database.clone());
+        let (first, second) = (seller(&database).await, seller(&database).await);
+
+        let rfq = RFQ::new(uuid::Uuid::new_v4(), "laptop-001".to_string(), 2, Decimal::from(5000), "USD".to_string(), Utc::now() + Duration::hours(1));
+        let auction = service.create_auction(CreateAuctionRequest {
+            rfq,
+            visibility: BidVisibility::Open,
+            duration_seconds: 600,
+            anti_sniping: AntiSniping::default(),
+        }).await.unwrap();
+
+        let early = auction.created_at + Duration::seconds(10);
+        service.submit_bid(auction.id, bid(first, 4800), early).await.unwrap();
+        assert!(service.submit_bid(auction.id, bid(second, 4800), early).await.is_err());
+        assert!(service.submit_bid(auction.id, bid(second, 5100), early).await.is_err());
+
+        // A bid in the last minute pushes the close back
+        let late = auction.closes_at - Duration::seconds(30);
+        service.submit_bid(auction.id, bid(second, 4600), late).await.unwrap();
+        let extended = service.get_auction(auction.id).await.unwrap();
+        assert_eq!(extended.closes_at, auction.closes_at + Duration::seconds(120));
+        assert!(service.close_auction(auction.id, auction.closes_at).await.is_err());
+
+        let closed = service.close_due_auctions(extended.closes_at).await.unwrap();
+        assert_eq!(closed.len(), 1);
+        assert_eq!(closed[0].status, AuctionStatus::Awarded);
+        let bids = database.get_auction_bids(auction.id).await.unwrap();
+        assert_eq!(bids.len(), 2);
+        assert_eq!(closed[0].winning_bid_id, Some(pick_winner(&bids).unwrap().id));
+        assert_eq!(pick_winner(&bids).unwrap().seller_id, second);
+    }
+
+    #[tokio::test]
+    async fn test_listings_sell_to_highest_bid_or_first_taker() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let database = Database::new(&format!("sqlite://{}", temp_file.path().to_string_lossy())).await.unwrap();
+        let service = AuctionService::new(database.clone());
+        let seller_id = seller(&database).await;
+        let (first, second) = (agent(&database, AgentType::Buyer).await, agent(&database, AgentType::Buyer).await);
+        let listing = |format, reserve_price: i64| CreateListingRequest {
+            seller_id,
+            product_id: "laptop-001".to_string(),
+            quantity: 1,
+            currency: "USD".to_string(),
+            format,
+            start_price: Decimal::from(2000),
+            reserve_price: Decimal::from(reserve_price),
+            duration_seconds: 600,
+            anti_sniping: AntiSniping::default(),
+        };
+        let bid = |buyer_id, price: i64| ListingBidRequest { buyer_id, price: Decimal::from(price), currency: "USD".to_string() };
+
+        let english = service.create_listing(listing(ListingFormat::English { min_increment: Decimal::from(50) }, 2100)).await.unwrap();
+        let early = english.created_at + Duration::seconds(10);
+        assert!(service.submit_listing_bid(english.id, bid(seller_id, 2500), early).await.is_err());
+        service.submit_listing_bid(english.id, bid(first, 2000), early).await.unwrap();
+        assert!(service.submit_listing_bid(english.id, bid(second, 2040), early).await.is_err());
+        service.submit_listing_bid(english.id, bid(second, 2150), early).await.unwrap();
+
+        let sold = service.close_listing(english.id, english.closes_at).await.unwrap();
+        assert_eq!(sold.status, AuctionStatus::Awarded);
+        let negotiation = database.get_negotiation(sold.negotiation_id.unwrap()).await.unwrap().unwrap();
+        assert_eq!(negotiation.buyer_id, second);
+        assert_eq!(negotiation.status, crate::model::NegotiationStatus::Accepted);
+        assert_eq!(negotiation.close_price, Some(Decimal::from(2150)));
+
+        // The ask drops 100 a minute down to the reserve; the first taker pays the ask
+        let dutch = service.create_listing(listing(ListingFormat::Dutch { decrement: Decimal::from(100), interval_seconds: 60 }, 1800)).await.unwrap();
+        let later = dutch.created_at + Duration::seconds(150);
+        assert_eq!(dutch.current_ask(later), Some(Decimal::from(1800)));
+        assert_eq!(dutch.current_ask(dutch.created_at + Duration::seconds(90)), Some(Decimal::from(1900)));
+        assert!(service.submit_listing_bid(dutch.id, bid(first, 1750), later).await.is_err());
+        let taken = service.submit_listing_bid(dutch.id, bid(first, 1900), later).await.unwrap();
+        assert_eq!(taken.price, Decimal::from(1800));
+        assert_eq!(service.get_listing(dutch.id).await.unwrap().status, AuctionStatus::Awarded);
+        assert!(service.submit_listing_bid(dutch.id, bid(second, 2000), later).await.is_err());
+    }
+}
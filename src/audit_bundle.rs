@@ -0,0 +1,443 @@
+//! Signed audit bundles for regulators and partners.
+//!
+//! [`export`] gathers what the marketplace recorded over a date range into
+//! one JSON document: the negotiations opened in the range and their
+//! messages, the payments and escrow holds placed in it, the reputation
+//! changes those negotiations caused and the key recoveries in the range,
+//! the compliance attestations of the sellers involved and the violations
+//! recorded, and anchoring proofs for the deals closed in the range.
+//! Reputation changes aren't stored, so they're derived from each
+//! negotiation's [`replay`](crate::replay).
+//!
+//! The manifest holds a SHA-256 digest of every section and is signed with
+//! the operator's ed25519 key. Whoever receives the bundle can run
+//! [`verify`] (`dcap audit verify`) to check that nothing was added, removed
+//! or changed since it was signed, and that every anchored deal hashes to
+//! its batch's root; comparing those roots with the chain transactions
+//! named in the proofs is left to them. Sections and the manifest are
+//! hashed in canonical JSON, with object keys sorted and no whitespace, so
+//! the digests don't depend on how the document was formatted.
+
+use crate::{
+    anchoring::{self, InclusionProof},
+    compliance::{ComplianceProfile, ComplianceViolation},
+    database::Database,
+    error::{NegotiationError, Result},
+    export::{ExportQuery, ExportTable, MessageExportRow, NegotiationExportRow},
+    recovery::{verify_signature, KeyRotation},
+    replay::{self, ReplayEvent},
+    settlement::{EscrowHold, PaymentRecord},
+    AgentId, TransactionId,
+};
+use base64::{engine::general_purpose, Engine};
+use chrono::{DateTime, Utc};
+use ed25519_dalek::{Signer, SigningKey};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+/// Version of the bundle format; other versions are refused
+pub const BUNDLE_FORMAT_VERSION: u32 = 1;
+
+/// Digest of one section of the bundle's contents
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SectionDigest {
+    pub name: String,
+    /// Entries in the section
+    pub count: usize,
+    /// Hex SHA-256 of the section's canonical JSON
+    pub sha256: String,
+}
+
+/// What the operator signs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditManifest {
+    pub format_version: u32,
+    /// Inclusive
+    pub from: DateTime<Utc>,
+    /// Exclusive
+    pub to: DateTime<Utc>,
+    pub generated_at: DateTime<Utc>,
+    /// Base64 ed25519 key of the operator who signed the bundle
+    pub signer: String,
+    /// In name order
+    pub sections: Vec<SectionDigest>,
+}
+
+/// A reputation change a negotiation caused
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReputationChange {
+    pub negotiation_id: TransactionId,
+    pub agent_id: AgentId,
+    pub change: i32,
+    pub reason: String,
+    pub at: DateTime<Utc>,
+}
+
+/// A seller's compliance profile, with the attestations it declared
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SellerCompliance {
+    pub seller_id: AgentId,
+    #[serde(flatten)]
+    pub profile: ComplianceProfile,
+}
+
+/// The bundle's sections, each listed in the manifest
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditContents {
+    pub negotiations: Vec<NegotiationExportRow>,
+    pub messages: Vec<MessageExportRow>,
+    pub payments: Vec<PaymentRecord>,
+    pub escrow_holds: Vec<EscrowHold>,
+    pub reputation_changes: Vec<ReputationChange>,
+    pub key_rotations: Vec<KeyRotation>,
+    /// As the registry holds them when the bundle is made
+    pub attestations: Vec<SellerCompliance>,
+    pub compliance_violations: Vec<ComplianceViolation>,
+    /// Deals closed in the range that have been anchored
+    pub anchor_proofs: Vec<InclusionProof>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditBundle {
+    pub manifest: AuditManifest,
+    /// Base64 signature over the manifest's canonical JSON
+    pub signature: String,
+    pub contents: AuditContents,
+}
+
+/// What [`verify`] checked
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleVerification {
+    pub manifest: AuditManifest,
+    pub anchor_proofs: usize,
+    pub attestations: usize,
+    /// Attestations whose attestor signature holds; whether the attestor is
+    /// trusted is for the reader to judge
+    pub valid_attestations: usize,
+}
+
+/// Reads an operator signing key: a base64 32-byte ed25519 secret key.
+pub fn signing_key(encoded: &str) -> Result<SigningKey> {
+    let bytes: [u8; 32] = general_purpose::STANDARD.decode(encoded.trim())
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| NegotiationError::Config("Signing key must be 32 bytes of base64".to_string()))?;
+    Ok(SigningKey::from_bytes(&bytes))
+}
+
+/// Base64 public key of `key`, as named in the manifests it signs.
+pub fn public_key(key: &SigningKey) -> String {
+    general_purpose::STANDARD.encode(key.verifying_key().to_bytes())
+}
+
+/// Gathers everything recorded in `[from, to)` and signs it with `key`.
+pub async fn export(database: &Database, from: DateTime<Utc>, to: DateTime<Utc>, key: &SigningKey) -> Result<AuditBundle> {
+    if from >= to {
+        return Err(NegotiationError::Validation("Audit range must end after it starts".to_string()));
+    }
+
+    let mut query = ExportQuery::new(ExportTable::Negotiations);
+    query.from = Some(from);
+    query.to = Some(to);
+    let negotiations = database.negotiation_export_rows(&query).await?;
+    let messages = database.message_export_rows(&query).await?;
+
+    let mut reputation_changes = Vec::new();
+    let mut attestations: Vec<SellerCompliance> = Vec::new();
+    for row in &negotiations {
+        let negotiation_id = TransactionId::parse_str(&row.id)?;
+        if let Some(replay) = replay::replay(database, negotiation_id).await? {
+            for entry in replay.timeline {
+                if let ReplayEvent::Reputation { agent_id, change, reason } = entry.event {
+                    reputation_changes.push(ReputationChange { negotiation_id, agent_id, change, reason, at: entry.at });
+                }
+            }
+        }
+
+        let seller_id = AgentId::parse_str(&row.seller_id)?;
+        if attestations.iter().any(|seller| seller.seller_id == seller_id) {
+            continue;
+        }
+        if let Some(profile) = database.get_agent_compliance(seller_id).await? {
+            attestations.push(SellerCompliance { seller_id, profile });
+        }
+    }
+
+    let contents = AuditContents {
+        negotiations,
+        messages,
+        payments: database.get_payments_between(from, to).await?,
+        escrow_holds: database.get_escrow_holds_between(from, to).await?,
+        reputation_changes,
+        key_rotations: database.get_key_rotations_between(from, to).await?,
+        attestations,
+        compliance_violations: database.get_compliance_violations_between(from, to).await?,
+        anchor_proofs: anchor_proofs(database, from, to).await?,
+    };
+    seal(from, to, contents, key, Utc::now())
+}
+
+/// Proofs for the anchored records of deals closed in `[from, to)`, loading
+/// each batch they fall in once.
+async fn anchor_proofs(database: &Database, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<Vec<InclusionProof>> {
+    let record_ids: Vec<i64> = database.get_negotiation_records_closed_between(from, to).await?
+        .into_iter()
+        .map(|(id, _)| id)
+        .collect();
+
+    let mut proofs = Vec::new();
+    let mut next = 0;
+    while let Some(&record_id) = record_ids.get(next) {
+        let Some(batch) = database.get_anchor_batch_for_record(record_id).await? else {
+            next += 1;
+            continue;
+        };
+        let records = database.get_negotiation_records_between(batch.first_record_id, batch.last_record_id).await?;
+        proofs.extend(anchoring::batch_proofs(&batch, &records, &record_ids));
+        next += record_ids[next..].iter().take_while(|id| **id <= batch.last_record_id).count();
+    }
+    Ok(proofs)
+}
+
+fn seal(from: DateTime<Utc>, to: DateTime<Utc>, contents: AuditContents, key: &SigningKey, now: DateTime<Utc>) -> Result<AuditBundle> {
+    let manifest = AuditManifest {
+        format_version: BUNDLE_FORMAT_VERSION,
+        from,
+        to,
+        generated_at: now,
+        signer: public_key(key),
+        sections: section_digests(&serde_json::to_value(&contents)?)?,
+    };
+    let signature = key.sign(&canonical(&serde_json::to_value(&manifest)?)?);
+    Ok(AuditBundle {
+        manifest,
+        signature: general_purpose::STANDARD.encode(signature.to_bytes()),
+        contents,
+    })
+}
+
+/// Checks a bundle as received: the manifest's signature, by
+/// `expected_signer` when given, every section against its digest, and
+/// every anchoring proof against its batch root.
+pub fn verify(bundle: &[u8], expected_signer: Option<&str>) -> Result<BundleVerification> {
+    let document: Value = serde_json::from_slice(bundle)?;
+    let (Some(manifest_json), Some(signature), Some(contents_json)) = (
+        document.get("manifest"),
+        document.get("signature").and_then(Value::as_str),
+        document.get("contents"),
+    ) else {
+        return Err(NegotiationError::Validation("Not an audit bundle".to_string()));
+    };
+
+    let manifest: AuditManifest = serde_json::from_value(manifest_json.clone())?;
+    if manifest.format_version != BUNDLE_FORMAT_VERSION {
+        return Err(NegotiationError::Validation(format!(
+            "Bundle format {} isn't supported; expected {}", manifest.format_version, BUNDLE_FORMAT_VERSION
+        )));
+    }
+    if expected_signer.is_some_and(|signer| signer != manifest.signer) {
+        return Err(NegotiationError::Auth(format!("Bundle was signed by {}", manifest.signer)));
+    }
+    verify_signature(&manifest.signer, &canonical(manifest_json)?, signature)?;
+
+    let digests = section_digests(contents_json)?;
+    for expected in &manifest.sections {
+        if !digests.contains(expected) {
+            return Err(NegotiationError::Validation(format!("Section {} doesn't match the manifest", expected.name)));
+        }
+    }
+    if let Some(extra) = digests.iter().find(|digest| !manifest.sections.iter().any(|section| section.name == digest.name)) {
+        return Err(NegotiationError::Validation(format!("Section {} isn't in the manifest", extra.name)));
+    }
+
+    let contents: AuditContents = serde_json::from_value(contents_json.clone())?;
+    if let Some(proof) = contents.anchor_proofs.iter().find(|proof| !proof.verify()) {
+        return Err(NegotiationError::Validation(format!(
+            "Record {} doesn't match the root of batch {}", proof.record_id, proof.batch.id
+        )));
+    }
+    let declared = contents.attestations.iter()
+        .flat_map(|seller| seller.profile.attestations.iter().map(move |attestation| (seller.seller_id, attestation)));
+    let (attestations, valid_attestations) = declared.fold((0, 0), |(total, valid), (seller_id, attestation)| {
+        (total + 1, valid + usize::from(attestation.verify(seller_id).is_ok()))
+    });
+
+    Ok(BundleVerification {
+        manifest,
+        anchor_proofs: contents.anchor_proofs.len(),
+        attestations,
+        valid_attestations,
+    })
+}
+
+fn section_digests(contents: &Value) -> Result<Vec<SectionDigest>> {
+    let sections = contents.as_object()
+        .ok_or_else(|| NegotiationError::Validation("Bundle contents must be an object".to_string()))?;
+    sections.iter()
+        .map(|(name, section)| Ok(SectionDigest {
+            name: name.clone(),
+            count: section.as_array().map_or(0, Vec::len),
+            sha256: hex::encode(Sha256::digest(canonical(section)?)),
+        }))
+        .collect()
+}
+
+/// Compact JSON; `serde_json` keeps object keys sorted.
+fn canonical(value: &Value) -> Result<Vec<u8>> {
+    Ok(serde_json::to_vec(value)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        anchoring::{AnchorBackend, AnchoringService},
+        compliance::{attestation_message, SellerAttestation},
+        model::{AgentInfo, AgentType, MessageType, Negotiation, NegotiationStatus, PaymentMethod},
+        settlement::PaymentStatus,
+    };
+    use async_trait::async_trait;
+    use chrono::Duration;
+    use rust_decimal::Decimal;
+    use std::sync::Arc;
+    use tempfile::NamedTempFile;
+
+    struct RecordingBackend;
+
+    #[async_trait]
+    impl AnchorBackend for RecordingBackend {
+        fn chain(&self) -> &str {
+            "test"
+        }
+
+        async fn commit_root(&self, batch_id: uuid::Uuid, _merkle_root: &str) -> Result<String> {
+            Ok(format!("tx_{}", batch_id))
+        }
+    }
+
+    async fn agent(database: &Database, agent_type: AgentType) -> AgentId {
+        let agent = AgentInfo {
+            id: uuid::Uuid::new_v4(),
+            agent_type,
+            name: "Agent".to_string(),
+            endpoint: "http://localhost:8001".to_string(),
+            public_key: "key".to_string(),
+            reputation_score: 50,
+            products: vec![],
+            payment_methods: vec![],
+            protocol_versions: vec![],
+            preferred_languages: vec![],
+            tls_fingerprint: None,
+            created_at: Utc::now(),
+            last_active: Utc::now(),
+        };
+        database.create_agent(&agent).await.unwrap();
+        agent.id
+    }
+
+    async fn settled_negotiation(database: &Database, buyer_id: AgentId, seller_id: AgentId, opened: DateTime<Utc>) -> Negotiation {
+        let mut negotiation = Negotiation {
+            id: uuid::Uuid::new_v4(),
+            rfq_id: uuid::Uuid::new_v4(),
+            quote_id: None,
+            buyer_id,
+            seller_id,
+            product_id: "laptop-001".to_string(),
+            quantity: 1,
+            opening_bid: Decimal::new(900, 0),
+            currency: "USD".to_string(),
+            close_price: None,
+            delta: None,
+            status: NegotiationStatus::Pending,
+            messages: vec![],
+            created_at: opened,
+            updated_at: opened,
+            delegation_chain: vec![],
+        };
+        negotiation.add_message(buyer_id, MessageType::RFQ, "One laptop".to_string(), opened);
+        database.create_negotiation(&negotiation).await.unwrap();
+        negotiation.status = NegotiationStatus::Settled;
+        negotiation.close_price = Some(Decimal::new(950, 0));
+        negotiation.delta = Some(Decimal::new(50, 0));
+        database.update_negotiation(&negotiation).await.unwrap();
+        database.add_negotiation_record(&negotiation.to_record().unwrap()).await.unwrap();
+        negotiation
+    }
+
+    #[tokio::test]
+    async fn test_exported_bundle_verifies_until_tampered_with() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let database = Database::new(&format!("sqlite://{}", temp_file.path().to_string_lossy())).await.unwrap();
+        let buyer_id = agent(&database, AgentType::Buyer).await;
+        let seller_id = agent(&database, AgentType::Seller).await;
+        let now = Utc::now();
+        let negotiation = settled_negotiation(&database, buyer_id, seller_id, now - Duration::minutes(10)).await;
+        settled_negotiation(&database, buyer_id, seller_id, now - Duration::days(3)).await;
+
+        let attestor = SigningKey::from_bytes(&[7; 32]);
+        let expires_at = now + Duration::days(30);
+        let attestation = SellerAttestation {
+            category: "Electronics".to_string(),
+            region: "US".to_string(),
+            attestor: public_key(&attestor),
+            expires_at,
+            signature: general_purpose::STANDARD.encode(
+                attestor.sign(&attestation_message(seller_id, "Electronics", "US", expires_at)).to_bytes()
+            ),
+        };
+        database.set_agent_compliance(seller_id, &ComplianceProfile {
+            region: Some("US".to_string()),
+            attestations: vec![attestation],
+        }).await.unwrap();
+
+        database.create_payment(&PaymentRecord {
+            payment_id: "pi_1".to_string(),
+            transaction_id: negotiation.id,
+            buyer_id,
+            seller_id,
+            payment_method: PaymentMethod::Stripe,
+            amount: Decimal::new(950, 0),
+            currency: "USD".to_string(),
+            status: PaymentStatus::Succeeded,
+            idempotency_key: None,
+            error_message: None,
+            created_at: now,
+            updated_at: now,
+            completed_at: Some(now),
+            delegation_chain: vec![],
+            refund_of: None,
+            refund_reason: None,
+            tax: None,
+        }).await.unwrap();
+        AnchoringService::new(database.clone(), Arc::new(RecordingBackend), 10).anchor_pending().await.unwrap();
+
+        let operator = signing_key(&general_purpose::STANDARD.encode([3; 32])).unwrap();
+        let bundle = export(&database, now - Duration::days(1), now + Duration::minutes(1), &operator).await.unwrap();
+        assert_eq!(bundle.contents.negotiations.len(), 1);
+        assert_eq!(bundle.contents.messages.len(), 1);
+        assert_eq!(bundle.contents.payments.len(), 1);
+        assert_eq!(bundle.contents.reputation_changes.len(), 2);
+        assert_eq!(bundle.contents.anchor_proofs.len(), 1);
+
+        let bytes = serde_json::to_vec_pretty(&bundle).unwrap();
+        let report = verify(&bytes, Some(&public_key(&operator))).unwrap();
+        assert_eq!((report.anchor_proofs, report.attestations, report.valid_attestations), (1, 1, 1));
+        assert!(verify(&bytes, Some(&public_key(&attestor))).is_err());
+
+        // Changing a settled amount breaks the payments digest
+        let mut document: Value = serde_json::from_slice(&bytes).unwrap();
+        document["contents"]["payments"][0]["amount"] = Value::String("9.50".to_string());
+        assert!(verify(&serde_json::to_vec(&document).unwrap(), None).is_err());
+
+        // Re-signing with another key doesn't pass for the operator's bundle
+        let mut forged = bundle.clone();
+        forged.contents.payments.clear();
+        let forged = seal(forged.manifest.from, forged.manifest.to, forged.contents, &attestor, now).unwrap();
+        let forged = serde_json::to_vec(&forged).unwrap();
+        assert!(verify(&forged, None).is_ok());
+        assert!(verify(&forged, Some(&public_key(&operator))).is_err());
+
+        assert!(export(&database, now, now, &operator).await.is_err());
+    }
+}
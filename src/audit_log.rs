@@ -0,0 +1,284 @@
+//! Append-only, hash-chained log of state-changing actions.
+//!
+//! Every registration, quote, acceptance, payment and reputation change is
+//! appended to the `audit_log` table as an [`AuditEntry`] holding the hash of
+//! the entry before it, so removing, reordering or editing any entry breaks
+//! every hash after it. The table refuses updates and deletes outright.
+//! [`AuditLog::verify_audit_chain`] walks the stored chain, and
+//! [`verify_chain`] checks entries exported with `dcap audit export-log` for
+//! compliance reviews. Entries are hashed in canonical JSON, as audit bundles
+//! are, with SHA-256.
+
+use crate::{
+    database::Database,
+    error::{NegotiationError, Result},
+    AgentId,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::io::Write;
+
+/// `prev_hash` of the first entry
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+/// Appends retried when other writers keep taking the next sequence number
+const APPEND_ATTEMPTS: usize = 10;
+/// Entries read from the database at a time
+const PAGE_SIZE: i64 = 500;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    Registration,
+    Quote,
+    Acceptance,
+    Payment,
+    ReputationChange,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AuditEntry {
+    pub sequence: i64,
+    pub action: AuditAction,
+    /// Agent whose action this was, if any
+    pub actor_id: Option<AgentId>,
+    /// What the action was taken on, e.g. an agent, quote or payment ID
+    pub subject: String,
+    pub details: Value,
+    pub created_at: DateTime<Utc>,
+    pub prev_hash: String,
+    pub hash: String,
+}
+
+impl AuditEntry {
+    /// The SHA-256 of every field but `hash`, hex encoded
+    pub fn compute_hash(&self) -> Result<String> {
+        let fields = json!({
+            "sequence": self.sequence,
+            "action": self.action,
+            "actor_id": self.actor_id,
+            "subject": self.subject,
+            "details": self.details,
+            "created_at": self.created_at,
+            "prev_hash": self.prev_hash,
+        });
+        Ok(hex::encode(Sha256::digest(serde_json::to_vec(&fields)?)))
+    }
+}
+
+/// Where and why a chain stopped verifying
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ChainBreak {
+    pub sequence: i64,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ChainReport {
+    /// Entries checked, up to and including a break
+    pub entries: u64,
+    /// Hash of the last intact entry
+    pub head_hash: Option<String>,
+    pub broken: Option<ChainBreak>,
+}
+
+impl ChainReport {
+    pub fn is_intact(&self) -> bool {
+        self.broken.is_none()
+    }
+}
+
+/// Checks entries one at a time, in sequence order.
+struct ChainVerifier {
+    report: ChainReport,
+}
+
+impl ChainVerifier {
+    fn new() -> Self {
+        Self { report: ChainReport { entries: 0, head_hash: None, broken: None } }
+    }
+
+    /// Returns false once the chain is broken.
+    fn push(&mut self, entry: &AuditEntry) -> bool {
+        if self.report.broken.is_some() {
+            return false;
+        }
+        self.report.entries += 1;
+        let expected_sequence = self.report.entries as i64;
+        let expected_prev = self.report.head_hash.as_deref().unwrap_or(GENESIS_HASH);
+
+        let reason = if entry.sequence != expected_sequence {
+            Some(format!("Expected entry {}, found {}", expected_sequence, entry.sequence))
+        } else if entry.prev_hash != expected_prev {
+            Some("Previous hash doesn't match the entry before it".to_string())
+        } else {
+            match entry.compute_hash() {
+                Ok(hash) if hash == entry.hash => None,
+                Ok(_) => Some("Hash doesn't match the entry's contents".to_string()),
+                Err(e) => Some(format!("Entry can't be hashed: {}", e)),
+            }
+        };
+
+        match reason {
+            Some(reason) => {
+                self.report.broken = Some(ChainBreak { sequence: entry.sequence, reason });
+                false
+            }
+            None => {
+                self.report.head_hash = Some(entry.hash.clone());
+                true
+            }
+        }
+    }
+}
+
+/// Verifies exported entries, which must start from the first.
+pub fn verify_chain<'a>(entries: impl IntoIterator<Item = &'a AuditEntry>) -> ChainReport {
+    let mut verifier = ChainVerifier::new();
+    for entry in entries {
+        if !verifier.push(entry) {
+            break;
+        }
+    }
+    verifier.report
+}
+
+#[derive(Clone)]
+pub struct AuditLog {
+    database: Database,
+}
+
+impl AuditLog {
+    pub fn new(database: Database) -> Self {
+        Self { database }
+    }
+
+    /// Appends an entry after the current head of the chain.
+    pub async fn append<T: Serialize>(
+        &self,
+        action: AuditAction,
+        actor_id: Option<AgentId>,
+        subject: &str,
+        details: &T,
+    ) -> Result<AuditEntry> {
+        let details = serde_json::to_value(details)?;
+        for _ in 0..APPEND_ATTEMPTS {
+            let (sequence, prev_hash) = match self.database.get_audit_head().await? {
+                Some((sequence, hash)) => (sequence + 1, hash),
+                None => (1, GENESIS_HASH.to_string()),
+            };
+            let mut entry = AuditEntry {
+                sequence,
+                action,
+                actor_id,
+                subject: subject.to_string(),
+                details: details.clone(),
+                created_at: Utc::now(),
+                prev_hash,
+                hash: String::new(),
+            };
+            entry.hash = entry.compute_hash()?;
+            if self.database.append_audit_entry(&entry).await? {
+                return Ok(entry);
+            }
+        }
+        Err(NegotiationError::Io(format!(
+            "Gave up appending {:?} of {} to the audit log after {} attempts", action, subject, APPEND_ATTEMPTS
+        )))
+    }
+
+    /// Appends an entry, logging rather than failing the action it records.
+    pub async fn record<T: Serialize>(&self, action: AuditAction, actor_id: Option<AgentId>, subject: &str, details: &T) {
+        if let Err(e) = self.append(action, actor_id, subject, details).await {
+            tracing::error!("Failed to audit {:?} of {}: {}", action, subject, e);
+        }
+    }
+
+    /// Walks the whole stored chain, stopping at the first broken entry.
+    pub async fn verify_audit_chain(&self) -> Result<ChainReport> {
+        let mut verifier = ChainVerifier::new();
+        let mut after = 0;
+        loop {
+            let page = self.database.get_audit_entries(after, PAGE_SIZE).await?;
+            for entry in &page {
+                if !verifier.push(entry) {
+                    return Ok(verifier.report);
+                }
+            }
+            match page.last() {
+                Some(last) if page.len() as i64 == PAGE_SIZE => after = last.sequence,
+                _ => return Ok(verifier.report),
+            }
+        }
+    }
+
+    /// Writes every entry as a line of JSON, oldest first. Returns how many
+    /// were written.
+    pub async fn export<W: Write>(&self, mut out: W) -> Result<u64> {
+        let mut written = 0;
+        let mut after = 0;
+        loop {
+            let page = self.database.get_audit_entries(after, PAGE_SIZE).await?;
+            for entry in &page {
+                serde_json::to_writer(&mut out, entry)?;
+                out.write_all(b"\n")?;
+                written += 1;
+            }
+            match page.last() {
+                Some(last) if page.len() as i64 == PAGE_SIZE => after = last.sequence,
+                _ => return Ok(written),
+            }
+        }
+    }
+}
+
+/// Reads entries written by [`AuditLog::export`].
+pub fn read_export(export: &str) -> Result<Vec<AuditEntry>> {
+    export.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| Ok(serde_json::from_str(line)?))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_audit_chain_detects_tampering() {
+        let temp_file = tempfile::NamedTempFile::new().unwrap();
+        let database = Database::new(&format!("sqlite://{}", temp_file.path().to_string_lossy())).await.unwrap();
+        let log = AuditLog::new(database.clone());
+        let agent_id = uuid::Uuid::new_v4();
+
+        let first = log.append(AuditAction::Registration, Some(agent_id), &agent_id.to_string(), &json!({"name": "Seller"})).await.unwrap();
+        assert_eq!(first.sequence, 1);
+        assert_eq!(first.prev_hash, GENESIS_HASH);
+        let second = log.append(AuditAction::Payment, Some(agent_id), "payment-1", &json!({"amount": "100.00"})).await.unwrap();
+        assert_eq!(second.prev_hash, first.hash);
+        log.record(AuditAction::ReputationChange, Some(agent_id), &agent_id.to_string(), &json!({"score_change": 0.1})).await;
+
+        let report = log.verify_audit_chain().await.unwrap();
+        assert!(report.is_intact());
+        assert_eq!(report.entries, 3);
+
+        let mut exported = Vec::new();
+        assert_eq!(log.export(&mut exported).await.unwrap(), 3);
+        let mut entries = read_export(std::str::from_utf8(&exported).unwrap()).unwrap();
+        assert!(verify_chain(&entries).is_intact());
+
+        // Editing an entry breaks its own hash
+        entries[1].details = json!({"amount": "1.00"});
+        let report = verify_chain(&entries);
+        assert_eq!(report.broken.unwrap().sequence, 2);
+        assert_eq!(report.head_hash, Some(first.hash.clone()));
+
+        // Dropping one breaks the sequence
+        entries.remove(1);
+        assert_eq!(verify_chain(&entries).broken.unwrap().sequence, 3);
+
+        // An entry can't be written over
+        assert!(database.append_audit_entry(&first).await.is_ok_and(|appended| !appended));
+    }
+}
@@ -0,0 +1,1097 @@
+use dcap::{
+    agent::{BuyerAgent, BuyerAgentConfig, LLMConfig, PendingSettlement},
+    agreement::{AgreementItemRequest, SupplyAgreement},
+    auction::AuctionStatus,
+    blocklist::{BlockList, BlockPolicy},
+    catalog::PriceHistory,
+    config::LifecycleConfig,
+    currency::CurrencyConverter,
+    delegation::{DelegationScope, DEFAULT_DELEGATION_TTL_HOURS},
+    discovery::DiscoveryService,
+    embeddings,
+    error::{ApiError, ApiResult, NegotiationError},
+    expiry::{ExpiryResponse, ExpiryWarning},
+    handover::{self, BuyerState},
+    http::HttpClient,
+    lifecycle::{self, PodIdentity, Readiness},
+    protocol,
+    registry,
+    reload::ConfigSource,
+    roles::{self, Permission},
+    runtime,
+    security,
+    seller_cache::SellerCache,
+    model::{Negotiation, PaymentMethod, Product, ProductSpec, Quote},
+    money::Money,
+    nonce::ReplayGuard,
+    settlement::SettlementService,
+    shared_state::SharedState,
+    strategy::{self, NegotiationOutcome},
+    telemetry,
+    trust::TrustSystem,
+    validation::{FieldErrors, Valid, Validate},
+    webhook::{WebhookSigner, SIGNATURE_HEADER},
+};
+use axum::{
+    extract::{Path, Query, State},
+    body::Bytes,
+    http::{HeaderMap, StatusCode},
+    middleware,
+    response::Json,
+    routing::{get, post},
+    Router,
+};
+use clap::Parser;
+use repl::{ReplCommand, ReplHelper};
+use rust_decimal::Decimal;
+use rustyline::{error::ReadlineError, history::DefaultHistory, Editor};
+use std::env;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio::sync::{Mutex, RwLock};
+
+mod repl;
+
+#[derive(Parser)]
+#[command(name = "buyer-agent")]
+#[command(about = "LLM-powered buyer agent for marketplace negotiations")]
+struct Args {
+    #[arg(short, long, default_value = "config.toml")]
+    config: String,
+
+    /// Overrides a config field, e.g. `--set logging.level=debug`; repeatable
+    #[arg(long = "set", value_name = "KEY=VALUE")]
+    overrides: Vec<String>,
+
+    #[arg(short, long, default_value = "sqlite://negotiation.db")]
+    database_url: String,
+
+    #[arg(long, default_value = "http://localhost:8000")]
+    discovery_endpoint: String,
+
+    #[arg(short, long, default_value = "8002")]
+    port: u16,
+
+    /// Address the HTTP API binds to. The API spends the buyer's budget, so
+    /// it only listens locally unless told otherwise
+    #[arg(long, default_value = "127.0.0.1")]
+    host: String,
+
+    /// Run the interactive command prompt instead of the HTTP API
+    #[arg(long)]
+    interactive: bool,
+
+    /// Region the buyer shops from; region-restricted categories are hidden without it
+    #[arg(long)]
+    region: Option<String>,
+
+    /// Skip sellers whose average response time is slower than this
+    #[arg(long)]
+    max_response_time_ms: Option<u64>,
+
+    /// Pay with this method (stripe, solana, escrow, pay_on_delivery or lightning),
+    /// only dealing with sellers that accept it
+    #[arg(long, value_parser = parse_payment_method)]
+    payment_method: Option<PaymentMethod>,
+
+    /// Take over from the buyer whose API runs at this URL: import its
+    /// state, then move its registration here
+    #[arg(long)]
+    take_over: Option<String>,
+
+    /// Act as a sub-agent under the delegation token a principal issued to
+    /// this buyer's agent ID
+    #[arg(long, env = "DCAP_DELEGATION_TOKEN")]
+    delegation_token: Option<String>,
+
+    /// How often to check payments of accepted deals for confirmation; 0 disables
+    #[arg(long, default_value = "15")]
+    settlement_poll_seconds: u64,
+}
+
+#[tokio::main]
+async fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse();
+
+    let loaded = ConfigSource::new(Some(&args.config)).with_overrides(args.overrides.clone()).load();
+    // A config that loads but doesn't validate is refused, not replaced
+    if let Ok(config) = &loaded {
+        config.validate()?;
+    }
+    let config = loaded.as_ref().cloned().unwrap_or_default();
+    if loaded.is_err() {
+        config.validate()?;
+    }
+    let _telemetry = telemetry::init("buyer-agent", &config.logging)?;
+    if let Err(e) = &loaded {
+        tracing::warn!("Using default configuration: {}", e);
+    }
+    let http = HttpClient::for_server(&config.http, config.server.tls.as_ref())?;
+    let discovery = DiscoveryService::new(args.discovery_endpoint.clone()).with_http_client(http.clone())
+        .with_registry(registry::from_config(&config.discovery, &args.discovery_endpoint, http.clone())?);
+    let database = lifecycle::wait_for_database(&args.database_url, &config.lifecycle).await?;
+    let trust = TrustSystem::from_config(&config.trust)?
+        .with_block_list(BlockList::new(database.clone(), BlockPolicy::from_config(&config.trust)));
+    // Checks the admin tokens the state export is served to
+    let operators = Arc::new(RwLock::new(TrustSystem::from_config(&config.trust)?));
+    let settlement_config = dcap::settlement::SettlementConfig {
+        stripe_secret_key: None,
+        solana_rpc_url: None,
+        escrow_service_url: None,
+        webhook_secret: None,
+        delivery_confirmation_timeout_seconds: None,
+    };
+    let seller_cache = SellerCache::new(database.clone());
+    let settlement = SettlementService::new(settlement_config, database.clone()).await?
+        .with_cancellation_config(config.cancellation.clone());
+
+    // A standby runs as the buyer it takes over from
+    let handover_state = match &args.take_over {
+        Some(endpoint) => {
+            let admin_token = handover::handover_token(&*operators.read().await)?;
+            Some(handover::fetch_state::<BuyerState>(endpoint, &admin_token).await?)
+        }
+        None => None,
+    };
+
+    let identity = PodIdentity::from_env(&config.lifecycle)?;
+    let buyer_config = BuyerAgentConfig {
+        agent_id: handover_state.as_ref().map(|state| state.agent_id)
+            .or(identity.agent_id)
+            .unwrap_or_else(uuid::Uuid::new_v4),
+        name: identity.name.clone().unwrap_or_else(|| "TechBuyer".to_string()),
+        endpoint: identity.endpoint(args.port).unwrap_or_else(|| format!("http://localhost:{}", args.port)),
+        max_concurrent_negotiations: 5,
+        default_ttl_hours: 24,
+        llm_config: LLMConfig {
+            model: "gpt-4".to_string(),
+            api_key: env::var("OPENAI_API_KEY").unwrap_or_else(|_| "mock_key".to_string()),
+            max_tokens: 1000,
+            temperature: 0.7,
+        },
+        currency: config.currency.base_currency.clone(),
+        locale: config.locale,
+        preferred_languages: config.preferred_languages.clone(),
+        region: args.region.clone().or(identity.region),
+        max_response_time_ms: args.max_response_time_ms,
+        payment_method: args.payment_method.clone(),
+    };
+
+    let signer = WebhookSigner::from_config(buyer_config.agent_id, &config.webhooks)?;
+    let mut buyer_agent = BuyerAgent::new(
+        buyer_config,
+        discovery,
+        trust,
+        settlement,
+    ).await?
+    .with_http_client(http.clone())
+    .with_currency_converter(CurrencyConverter::from_config(&config.currency)?)
+    .with_seller_cache(seller_cache)
+    .with_webhook_signer(signer.clone())
+    .with_replay_guard(ReplayGuard::from_config(SharedState::in_memory(), &config.server));
+    if !config.webhooks.endpoints.is_empty() {
+        signer.forward_events(buyer_agent.events(), http.clone(), config.webhooks.endpoints.clone());
+    }
+    if let Some(provider) = embeddings::provider_from_config(&config.embeddings, http)? {
+        buyer_agent = buyer_agent.with_embeddings(provider, config.embeddings.min_similarity);
+    }
+    if let Some(token) = &args.delegation_token {
+        buyer_agent = buyer_agent.with_delegation(token)?;
+    }
+    let taking_over_from = match handover_state {
+        Some(state) => {
+            let endpoint = state.endpoint.clone();
+            buyer_agent.import_state(state)?;
+            Some(endpoint)
+        }
+        None => None,
+    };
+
+    if args.interactive {
+        let buyer_agent = Mutex::new(buyer_agent);
+        register(&buyer_agent, taking_over_from.as_deref(), &args.discovery_endpoint, &config.lifecycle).await;
+        return run_interactive(buyer_agent.into_inner()).await;
+    }
+
+    // Sellers can only reach the API, so only ask them for expiry warnings
+    // here. A check interval of slack allows for clock drift between them.
+    config.expiry.on_warning.validate()?;
+    let buyer_agent = if config.expiry.warning_seconds > 0 {
+        let window = config.expiry.warning_seconds + config.expiry.check_interval_seconds;
+        buyer_agent.with_expiry_warnings(config.expiry.on_warning, window)
+    } else {
+        buyer_agent
+    };
+
+    // Buyer operations hold the agent for their whole round trip to the seller,
+    // so API requests are served one at a time
+    let buyer_agent = Arc::new(Mutex::new(buyer_agent));
+    if config.expiry.deadline_check_seconds > 0 {
+        let interval = std::time::Duration::from_secs(config.expiry.deadline_check_seconds);
+        BuyerAgent::spawn_deadline_timer(buyer_agent.clone(), interval);
+    }
+    if args.settlement_poll_seconds > 0 {
+        let interval = std::time::Duration::from_secs(args.settlement_poll_seconds);
+        BuyerAgent::spawn_settlement_poller(buyer_agent.clone(), interval);
+    }
+    let app_state = AppState {
+        buyer_agent: buyer_agent.clone(),
+    };
+
+    let administer = Router::new()
+        .route("/admin/state", get(export_state));
+    let app = Router::new()
+        .route("/products", get(browse_products))
+        .route("/products/:product_id/history", get(price_history))
+        .route("/quotes", post(request_quote))
+        .route("/quotes/expiring", post(handle_expiry_warning))
+        .route("/negotiations", get(list_negotiations))
+        .route("/negotiations/:negotiation_id", get(get_negotiation))
+        .route("/negotiations/:negotiation_id/negotiate", post(negotiate))
+        .route("/negotiations/:negotiation_id/accept", post(accept_quote))
+        .route("/negotiations/:negotiation_id/reject", post(reject_quote))
+        .route("/delegations", post(delegate))
+        .route("/agreements", get(list_agreements).post(propose_agreement))
+        .route("/agreements/:agreement_id/accept", post(accept_agreement))
+        .route("/agreements/:agreement_id/orders", post(order_under_agreement))
+        .route("/health", get(health_check))
+        .merge(roles::require_permission(administer, operators, Permission::Administer))
+        .layer(middleware::from_fn(protocol::protocol_version_layer))
+        .with_state(app_state);
+    let readiness = Readiness::new();
+    let app = readiness.route(app);
+    let app = telemetry::trace_requests(app);
+    let app = security::harden(app, &config.server);
+
+    let listener = TcpListener::bind(format!("{}:{}", args.host, args.port)).await?;
+    println!("Buyer agent API listening on {}:{}", args.host, args.port);
+
+    let server_config = config.server.clone();
+    let server = tokio::spawn(async move { runtime::serve(listener, app, &server_config).await });
+
+    register(&buyer_agent, taking_over_from.as_deref(), &args.discovery_endpoint, &config.lifecycle).await;
+    readiness.mark_ready();
+
+    server.await??;
+
+    if let Err(e) = buyer_agent.lock().await.deregister().await {
+        tracing::warn!("Failed to deregister from discovery: {}", e);
+    }
+    database.close().await;
+    println!("Buyer agent stopped");
+
+    Ok(())
+}
+
+/// Registers the buyer once discovery is up, or moves its registration from
+/// the host it takes over from. Registration only matters for bidding on
+/// seller listings, so a registry that can't be reached doesn't stop the
+/// buyer.
+async fn register(buyer_agent: &Mutex<BuyerAgent>, taking_over_from: Option<&str>, discovery_endpoint: &str, config: &LifecycleConfig) {
+    let registered = match (lifecycle::wait_for_discovery(discovery_endpoint, config).await, taking_over_from) {
+        (Ok(()), Some(endpoint)) => buyer_agent.lock().await.hand_over(endpoint).await
+            .map(|_| println!("Took over from {}; it can be stopped", endpoint)),
+        (Ok(()), None) => buyer_agent.lock().await.register().await,
+        (Err(e), _) => Err(e),
+    };
+    if let Err(e) = registered {
+        tracing::warn!("Failed to register with discovery: {}", e);
+    }
+}
+
+async fn run_interactive(mut buyer_agent: BuyerAgent) -> std::result::Result<(), Box<dyn std::error::Error>> {
+    println!("Buyer agent interactive CLI ready");
+    println!("Type 'help' for available commands; Tab completes commands");
+
+    let mut editor = Editor::<ReplHelper, DefaultHistory>::new()?;
+    editor.set_helper(Some(ReplHelper::new()));
+    loop {
+        let line = match editor.readline("> ") {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e.into()),
+        };
+        if !line.trim().is_empty() {
+            editor.add_history_entry(line.as_str())?;
+        }
+
+        match repl::parse_line(&line) {
+            Ok(None) => {}
+            Ok(Some(ReplCommand::Exit)) => break,
+            Ok(Some(ReplCommand::History)) => {
+                for (i, entry) in editor.history().iter().enumerate() {
+                    println!("{:>4}  {}", i + 1, entry);
+                }
+            }
+            Ok(Some(command)) => {
+                // Nothing ticks in the background here, so catch up on deadlines first
+                for negotiation_id in buyer_agent.expire_overdue(chrono::Utc::now()).await {
+                    println!("Negotiation {} expired at its deadline", negotiation_id);
+                }
+                run_command(&mut buyer_agent, command).await
+            }
+            Err(message) => print!("{}", message),
+        }
+    }
+
+    println!("Buyer agent shutting down");
+    Ok(())
+}
+
+async fn run_command(buyer_agent: &mut BuyerAgent, command: ReplCommand) {
+    match command {
+        ReplCommand::Active => {
+            let negotiations = buyer_agent.get_active_negotiations();
+            for neg in negotiations {
+                println!("Negotiation {}: Status: {:?}", neg.id, neg.status);
+            }
+        }
+        ReplCommand::DeadLetters => {
+            match buyer_agent.list_dead_letters(50).await {
+                Ok(dead_letters) => {
+                    println!("Found {} pending dead letters:", dead_letters.len());
+                    for dead_letter in dead_letters {
+                        println!(
+                            "  {} {:?} (attempts: {}) - {}",
+                            dead_letter.id, dead_letter.kind, dead_letter.attempts, dead_letter.error
+                        );
+                    }
+                }
+                Err(e) => println!("Error listing dead letters: {}", e),
+            }
+        }
+        ReplCommand::Replay { dead_letter_id } => {
+            match buyer_agent.replay_dead_letter(dead_letter_id).await {
+                Ok(_) => println!("Dead letter replayed"),
+                Err(e) => println!("Error replaying dead letter: {}", e),
+            }
+        }
+        ReplCommand::Browse { category } => {
+            let category = (!category.is_empty()).then(|| category.join(" "));
+            match buyer_agent.browse_products(category).await {
+                Ok(products) => {
+                    println!("Found {} products:", products.len());
+                    let formatter = buyer_agent.price_formatter();
+                    for product in products {
+                        println!("  {} - {} ({})", product.name, formatter.format_price(product.base_price, &product.currency), product.category);
+                    }
+                }
+                Err(e) => println!("Error browsing products: {}", e),
+            }
+        }
+        ReplCommand::Prices { product_id, days } => {
+            match buyer_agent.price_history(&product_id, None, days).await {
+                Ok(histories) => {
+                    let formatter = buyer_agent.price_formatter();
+                    println!("Price history from {} sellers:", histories.len());
+                    for history in histories {
+                        let change = history.change_percent
+                            .map(|percent| format!("{:+}%", percent))
+                            .unwrap_or_else(|| "no change".to_string());
+                        println!("  Seller {} ({}):", history.seller_id, change);
+                        for point in &history.points {
+                            println!(
+                                "    {} {:?} at {}, {} in stock",
+                                point.recorded_at, point.kind, formatter.format_price(point.price, &point.currency), point.stock_quantity
+                            );
+                        }
+                    }
+                }
+                Err(e) => println!("Error getting price history: {}", e),
+            }
+        }
+        ReplCommand::Quote { product_id, quantity, max_price } => {
+            match buyer_agent.request_quote(product_id, quantity, max_price).await {
+                Ok(negotiation_id) => println!("Quote requested. Negotiation ID: {}", negotiation_id),
+                Err(e) => println!("Error requesting quote: {}", e),
+            }
+        }
+        ReplCommand::Compare { product_id, quantity, max_price, sellers, to } => {
+            let spec = ProductSpec {
+                product_id,
+                category: None,
+                quantity,
+                max_price,
+                delivery_location: to,
+            };
+
+            match buyer_agent.request_quotes_from_all(spec, sellers).await {
+                Ok(comparison) => {
+                    let formatter = buyer_agent.price_formatter();
+                    println!("Received {} quotes:", comparison.ranked.len());
+                    for (rank, quote) in comparison.ranked.iter().enumerate() {
+                        println!(
+                            "  {}. {} - {}, {} landed (reputation {}, delivery {}){} - Negotiation ID: {}",
+                            rank + 1,
+                            quote.seller_name,
+                            formatter.format_money(&quote.price),
+                            formatter.format_money(&quote.landed_cost),
+                            quote.seller_reputation,
+                            quote.delivery_days.map_or("unknown".to_string(), |days| format!("{} days", days)),
+                            if quote.within_budget { "" } else { " [over budget]" },
+                            quote.negotiation_id
+                        );
+                    }
+                    for failure in &comparison.failures {
+                        println!("  No quote from {}: {}", failure.seller_name, failure.error);
+                    }
+                }
+                Err(e) => println!("Error requesting quotes: {}", e),
+            }
+        }
+        ReplCommand::AuctionStatus { auction_id } => {
+            match buyer_agent.auction_status(auction_id).await {
+                Ok(view) => {
+                    let formatter = buyer_agent.price_formatter();
+                    let currency = &view.auction.rfq.currency;
+                    println!(
+                        "Auction {}: {:?}, closes {} ({} bids)",
+                        view.auction.id, view.auction.status, view.auction.closes_at, view.bid_count
+                    );
+                    for bid in &view.bids {
+                        let winner = view.auction.winning_bid_id == Some(bid.id);
+                        println!(
+                            "  {} - {}{}",
+                            bid.seller_id,
+                            formatter.format_price(bid.price, currency),
+                            if winner { " [winner]" } else { "" }
+                        );
+                    }
+                    if view.auction.status == AuctionStatus::Open && view.bids.is_empty() && view.bid_count > 0 {
+                        println!("  Sealed bids are revealed when the auction closes");
+                    }
+                }
+                Err(e) => println!("Error getting auction: {}", e),
+            }
+        }
+        ReplCommand::Auction { product_id, quantity, max_price, seconds, visibility } => {
+            let spec = ProductSpec {
+                product_id,
+                category: None,
+                quantity,
+                max_price,
+                delivery_location: None,
+            };
+            let duration = chrono::Duration::seconds(seconds);
+
+            match buyer_agent.start_auction(spec, visibility.into(), duration).await {
+                Ok(auction) => println!("Auction {} open until {}", auction.id, auction.closes_at),
+                Err(e) => println!("Error starting auction: {}", e),
+            }
+        }
+        ReplCommand::Listings => {
+            match buyer_agent.open_listings().await {
+                Ok(listings) => {
+                    let formatter = buyer_agent.price_formatter();
+                    println!("Found {} open listings:", listings.len());
+                    for listing in listings {
+                        let now = chrono::Utc::now();
+                        let price = listing.current_ask(now).unwrap_or(listing.start_price);
+                        println!(
+                            "  {} - {} x{} {:?} at {}, closes {}",
+                            listing.id,
+                            listing.product_id,
+                            listing.quantity,
+                            listing.format,
+                            formatter.format_price(price, &listing.currency),
+                            listing.closes_at
+                        );
+                    }
+                }
+                Err(e) => println!("Error listing auctions: {}", e),
+            }
+        }
+        ReplCommand::Bid { listing_id, price } => {
+            match buyer_agent.bid_on_listing(listing_id, price).await {
+                Ok(bid) => println!("Bid {} placed at {}", bid.id, bid.price),
+                Err(e) => println!("Error bidding: {}", e),
+            }
+        }
+        ReplCommand::SettleListing { listing_id } => {
+            let settled = async {
+                let negotiation = buyer_agent.settle_listing(listing_id).await?;
+                buyer_agent.confirm_settlements().await?;
+                Ok::<_, NegotiationError>(buyer_agent.get_negotiation(negotiation.id).cloned().unwrap_or(negotiation))
+            }.await;
+            match settled {
+                Ok(negotiation) => println!("Listing paid. Negotiation ID: {} ({:?})", negotiation.id, negotiation.status),
+                Err(e) => println!("Error settling listing: {}", e),
+            }
+        }
+        ReplCommand::Demand { category, quantity, max_price, seconds } => {
+            let duration = chrono::Duration::seconds(seconds);
+            match buyer_agent.signal_demand(category, None, quantity, max_price, duration).await {
+                Ok(signal) => println!("Demand signal {} open until {}", signal.id, signal.expires_at),
+                Err(e) => println!("Error signalling demand: {}", e),
+            }
+        }
+        ReplCommand::Offers { signal_id } => {
+            match buyer_agent.demand_offers(signal_id).await {
+                Ok(offers) => {
+                    let formatter = buyer_agent.price_formatter();
+                    println!("Found {} offers:", offers.len());
+                    for offer in offers {
+                        println!(
+                            "  {} - {} x{} at {} from seller {}, valid until {}",
+                            offer.id,
+                            offer.product_id,
+                            offer.quantity,
+                            formatter.format_price(offer.price, &offer.currency),
+                            offer.seller_id,
+                            offer.valid_until
+                        );
+                    }
+                }
+                Err(e) => println!("Error getting offers: {}", e),
+            }
+        }
+        ReplCommand::TakeOffer { signal_id, offer_id } => {
+            match buyer_agent.take_demand_offer(signal_id, offer_id).await {
+                Ok(negotiation_id) => println!("Quote requested. Negotiation ID: {}", negotiation_id),
+                Err(e) => println!("Error taking offer: {}", e),
+            }
+        }
+        ReplCommand::WithdrawDemand { signal_id } => {
+            match buyer_agent.withdraw_demand(signal_id).await {
+                Ok(signal) => println!("Demand signal {} withdrawn", signal.id),
+                Err(e) => println!("Error withdrawing demand: {}", e),
+            }
+        }
+        ReplCommand::Coalition { product_id, quantity, max_unit_price, min_quantity, seconds } => {
+            let duration = chrono::Duration::seconds(seconds);
+            match buyer_agent.form_coalition(product_id, quantity, max_unit_price, min_quantity, duration).await {
+                Ok(view) => println!("Coalition {} open until {}", view.coalition.id, view.coalition.closes_at),
+                Err(e) => println!("Error forming coalition: {}", e),
+            }
+        }
+        ReplCommand::Coalitions { product_id } => {
+            match buyer_agent.open_coalitions(product_id.as_deref()).await {
+                Ok(coalitions) => {
+                    let formatter = buyer_agent.price_formatter();
+                    println!("Found {} coalitions:", coalitions.len());
+                    for coalition in coalitions {
+                        println!(
+                            "  {} - {} at up to {} each, {} units needed, open until {}",
+                            coalition.id,
+                            coalition.product_id,
+                            formatter.format_price(coalition.max_unit_price, &coalition.currency),
+                            coalition.min_quantity,
+                            coalition.closes_at
+                        );
+                    }
+                }
+                Err(e) => println!("Error listing coalitions: {}", e),
+            }
+        }
+        ReplCommand::CoalitionStatus { coalition_id } => {
+            match buyer_agent.coalition(coalition_id).await {
+                Ok(view) => {
+                    let formatter = buyer_agent.price_formatter();
+                    println!("Coalition {}: {:?}, {} units of {}", view.coalition.id, view.coalition.status, view.total_quantity(), view.coalition.product_id);
+                    for member in &view.members {
+                        let share = member.share
+                            .map(|share| formatter.format_price(share, &view.coalition.currency))
+                            .unwrap_or_else(|| "-".to_string());
+                        let paid = if member.payment_id.is_some() { "paid" } else { "unpaid" };
+                        println!("  {} x{} share {} ({})", member.buyer_id, member.quantity, share, paid);
+                    }
+                    for message in &view.messages {
+                        println!("  [{}] {:?} from {}: {}", message.created_at, message.kind, message.sender_id, message.content);
+                    }
+                }
+                Err(e) => println!("Error getting coalition: {}", e),
+            }
+        }
+        ReplCommand::JoinCoalition { coalition_id, quantity } => {
+            match buyer_agent.join_coalition(coalition_id, quantity).await {
+                Ok(member) => println!("Joined coalition {} for {} units", member.coalition_id, member.quantity),
+                Err(e) => println!("Error joining coalition: {}", e),
+            }
+        }
+        ReplCommand::LeaveCoalition { coalition_id } => {
+            match buyer_agent.leave_coalition(coalition_id).await {
+                Ok(view) => println!("Left coalition {}", view.coalition.id),
+                Err(e) => println!("Error leaving coalition: {}", e),
+            }
+        }
+        ReplCommand::NegotiateCoalition { coalition_id } => {
+            match buyer_agent.negotiate_for_coalition(coalition_id).await {
+                Ok(negotiation_id) => println!("Aggregated RFQ sent. Negotiation ID: {}", negotiation_id),
+                Err(e) => println!("Error negotiating for coalition: {}", e),
+            }
+        }
+        ReplCommand::AgreeCoalition { coalition_id } => {
+            match buyer_agent.agree_coalition_price(coalition_id).await {
+                Ok(view) => println!("Coalition {} agreed; members can now pay their shares", view.coalition.id),
+                Err(e) => println!("Error agreeing coalition price: {}", e),
+            }
+        }
+        ReplCommand::PayCoalition { coalition_id } => {
+            match buyer_agent.pay_coalition_share(coalition_id).await {
+                Ok(view) => println!("Share paid. Coalition {} is {:?}", view.coalition.id, view.coalition.status),
+                Err(e) => println!("Error paying coalition share: {}", e),
+            }
+        }
+        ReplCommand::Agreements => {
+            let formatter = buyer_agent.price_formatter();
+            for agreement in buyer_agent.get_agreements() {
+                println!("Agreement {}: {:?} with seller {} until {}", agreement.id, agreement.status, agreement.seller_id, agreement.valid_until);
+                for item in &agreement.items {
+                    println!(
+                        "  {} at {} each, {} of {} remaining",
+                        item.product_id,
+                        formatter.format_price(item.unit_price, &agreement.currency),
+                        item.remaining_quantity(),
+                        item.committed_quantity
+                    );
+                }
+            }
+        }
+        ReplCommand::Agreement { seller_id, days, items } => {
+            match buyer_agent.propose_agreement(seller_id, items, days).await {
+                Ok(agreement) => println!(
+                    "Agreement {} proposed; accept before {}", agreement.id, agreement.proposal_expires_at
+                ),
+                Err(e) => println!("Error proposing agreement: {}", e),
+            }
+        }
+        ReplCommand::AcceptAgreement { agreement_id } => {
+            match buyer_agent.accept_agreement(agreement_id).await {
+                Ok(agreement) => println!("Agreement {} active until {}", agreement.id, agreement.valid_until),
+                Err(e) => println!("Error accepting agreement: {}", e),
+            }
+        }
+        ReplCommand::Order { agreement_id, product_id, quantity } => {
+            match buyer_agent.order_under_agreement(agreement_id, product_id, quantity).await {
+                Ok(negotiation_id) => println!("Ordered at the agreed rate. Negotiation ID: {}", negotiation_id),
+                Err(e) => println!("Error ordering under agreement: {}", e),
+            }
+        }
+        ReplCommand::Negotiate { negotiation_id, counter_offer } => {
+            match buyer_agent.negotiate(negotiation_id, counter_offer).await {
+                Ok(()) => println!("Negotiation offer sent"),
+                Err(e) => println!("Error negotiating: {}", e),
+            }
+        }
+        ReplCommand::Auto { negotiation_id, strategy, target_price } => {
+            let result = match strategy::strategy_from_name(&strategy) {
+                Ok(strategy) => buyer_agent.auto_negotiate(negotiation_id, strategy.as_ref(), target_price).await,
+                Err(e) => Err(e),
+            };
+            let formatter = buyer_agent.price_formatter();
+            let currency = buyer_agent.get_active_negotiations().into_iter()
+                .find(|negotiation| negotiation.id == negotiation_id)
+                .map(|negotiation| negotiation.currency.clone())
+                .unwrap_or_default();
+
+            match result {
+                Ok(NegotiationOutcome::Accepted { price, rounds }) => {
+                    println!("Accepted {} after {} rounds", formatter.format_price(price, &currency), rounds)
+                }
+                Ok(NegotiationOutcome::Rejected { last_ask, rounds }) => {
+                    println!("Walked away after {} rounds; last ask {}", rounds, formatter.format_price(last_ask, &currency))
+                }
+                Err(e) => println!("Error negotiating: {}", e),
+            }
+        }
+        ReplCommand::Accept { negotiation_id } => {
+            let accepted = async {
+                buyer_agent.accept_quote(negotiation_id).await?;
+                buyer_agent.confirm_settlements().await
+            }.await;
+            match accepted.map(|_| buyer_agent.pending_settlements().get(&negotiation_id)) {
+                Ok(Some(PendingSettlement { invoice: Some(invoice), .. })) => {
+                    println!("Quote accepted; pay Lightning invoice {} to settle", invoice)
+                }
+                Ok(Some(_)) => println!("Quote accepted; payment awaiting confirmation"),
+                Ok(_) => println!("Quote accepted and payment processed"),
+                Err(e) => println!("Error accepting quote: {}", e),
+            }
+        }
+        ReplCommand::Reject { negotiation_id } => {
+            match buyer_agent.reject_quote(negotiation_id).await {
+                Ok(()) => println!("Quote rejected"),
+                Err(e) => println!("Error rejecting quote: {}", e),
+            }
+        }
+        ReplCommand::Cancel { negotiation_id, reason, note } => {
+            let note = (!note.is_empty()).then(|| note.join(" "));
+            match buyer_agent.cancel_deal(negotiation_id, reason, note).await {
+                Ok(change) => {
+                    let formatter = buyer_agent.price_formatter();
+                    println!("Cancellation {:?}; fee {}", change.status, formatter.format_money(&change.fee_amount()))
+                }
+                Err(e) => println!("Error cancelling deal: {}", e),
+            }
+        }
+        ReplCommand::Refund { negotiation_id, reason, amount } => {
+            match buyer_agent.request_refund(negotiation_id, amount, reason).await {
+                Ok(refund) => {
+                    let formatter = buyer_agent.price_formatter();
+                    let refunded = Money::new(refund.refund.amount, refund.refund.currency.clone());
+                    println!("Asked for {} back; refund {} awaits the seller's approval", formatter.format_money(&refunded), refund.refund.payment_id)
+                }
+                Err(e) => println!("Error requesting refund: {}", e),
+            }
+        }
+        ReplCommand::Renegotiate { negotiation_id, price, quantity } => {
+            match buyer_agent.renegotiate_deal(negotiation_id, price, quantity, None).await {
+                Ok(change) => println!("Renegotiation {:?} by seller", change.status),
+                Err(e) => println!("Error renegotiating deal: {}", e),
+            }
+        }
+        ReplCommand::Delivered { negotiation_id } => {
+            match buyer_agent.confirm_fulfilment(negotiation_id).await {
+                Ok(_) => println!("Seller obligation fulfilled"),
+                Err(e) => println!("Error confirming delivery: {}", e),
+            }
+        }
+        ReplCommand::Orders => {
+            match buyer_agent.orders().await {
+                Ok(orders) if orders.is_empty() => println!("No orders"),
+                Ok(orders) => {
+                    for order in orders {
+                        let tracking = order.shipment
+                            .map(|shipment| format!(", {} {}", shipment.carrier, shipment.tracking_number))
+                            .unwrap_or_default();
+                        println!(
+                            "  {} x{} - {:?}{} - Negotiation ID: {}",
+                            order.product_id, order.quantity, order.status, tracking, order.negotiation_id
+                        );
+                    }
+                }
+                Err(e) => println!("Error listing orders: {}", e),
+            }
+        }
+        ReplCommand::Renege { negotiation_id, reason } => {
+            match buyer_agent.report_renege(negotiation_id, &reason.join(" ")).await {
+                Ok(obligation) => {
+                    let formatter = buyer_agent.price_formatter();
+                    println!(
+                        "Renege recorded; {} slashed from seller stake (penalty {})",
+                        formatter.format_price(obligation.slashed, &obligation.currency),
+                        formatter.format_money(&obligation.penalty_amount())
+                    )
+                }
+                Err(e) => println!("Error reporting renege: {}", e),
+            }
+        }
+        // Handled by the prompt loop, which owns the line editor
+        ReplCommand::History | ReplCommand::Exit => {}
+    }
+}
+
+#[derive(Clone)]
+struct AppState {
+    buyer_agent: Arc<Mutex<BuyerAgent>>,
+}
+
+#[derive(serde::Deserialize)]
+struct BrowseQuery {
+    category: Option<String>,
+    /// Free-text intent; products are matched to it and ranked when given
+    q: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+struct QuoteRequest {
+    product_id: String,
+    quantity: u32,
+    max_price: Decimal,
+}
+
+impl Validate for QuoteRequest {
+    fn validate_fields(&self, errors: &mut FieldErrors) {
+        errors.check(!self.product_id.trim().is_empty(), "product_id", "Product ID must not be empty");
+        errors.check(self.quantity > 0, "quantity", "Quantity must be greater than 0");
+        errors.check(self.max_price > Decimal::ZERO, "max_price", "Max price must be greater than 0");
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct NegotiateRequest {
+    counter_offer: Decimal,
+}
+
+impl Validate for NegotiateRequest {
+    fn validate_fields(&self, errors: &mut FieldErrors) {
+        errors.check(self.counter_offer > Decimal::ZERO, "counter_offer", "Counter offer must be greater than 0");
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct AgreementProposal {
+    seller_id: uuid::Uuid,
+    duration_days: u32,
+    items: Vec<AgreementItemRequest>,
+}
+
+#[derive(serde::Deserialize)]
+struct DelegationRequest {
+    delegate_id: uuid::Uuid,
+    #[serde(flatten)]
+    scope: DelegationScope,
+    ttl_hours: Option<i64>,
+}
+
+impl Validate for DelegationRequest {
+    fn validate_fields(&self, errors: &mut FieldErrors) {
+        self.scope.validate_fields(errors);
+        if let Some(ttl_hours) = self.ttl_hours {
+            errors.check(ttl_hours > 0, "ttl_hours", "TTL must be greater than 0");
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct OrderRequest {
+    product_id: String,
+    quantity: u32,
+}
+
+/// A negotiation with the latest quote the seller sent in it
+#[derive(serde::Serialize)]
+struct NegotiationView {
+    negotiation: Negotiation,
+    latest_quote: Option<Quote>,
+}
+
+fn parse_payment_method(method: &str) -> std::result::Result<PaymentMethod, String> {
+    serde_json::from_value(serde_json::Value::String(method.to_string()))
+        .map_err(|_| format!("unknown payment method '{}'", method))
+}
+
+fn negotiation_not_found() -> ApiError {
+    NegotiationError::NotFound("Negotiation".to_string()).into()
+}
+
+fn negotiation_view(buyer_agent: &BuyerAgent, negotiation_id: uuid::Uuid) -> ApiResult<Json<NegotiationView>> {
+    let negotiation = buyer_agent.get_negotiation(negotiation_id).ok_or_else(negotiation_not_found)?;
+    Ok(Json(NegotiationView {
+        negotiation: negotiation.clone(),
+        latest_quote: buyer_agent.latest_quote(negotiation_id).cloned(),
+    }))
+}
+
+async fn browse_products(
+    State(state): State<AppState>,
+    Query(query): Query<BrowseQuery>,
+) -> ApiResult<Json<Vec<Product>>> {
+    let buyer_agent = state.buyer_agent.lock().await;
+    let products = match query.q.as_deref().filter(|q| !q.trim().is_empty()) {
+        Some(q) => buyer_agent.match_products(q, query.category).await
+            .map(|matches| matches.into_iter().map(|(product, _)| product).collect()),
+        None => buyer_agent.browse_products(query.category).await,
+    };
+    match products {
+        Ok(products) => Ok(Json(products)),
+        Err(e) => {
+            tracing::error!("Failed to browse products: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct PriceHistoryQuery {
+    seller_id: Option<uuid::Uuid>,
+    days: Option<u32>,
+}
+
+async fn price_history(
+    State(state): State<AppState>,
+    Path(product_id): Path<String>,
+    Query(query): Query<PriceHistoryQuery>,
+) -> ApiResult<Json<Vec<PriceHistory>>> {
+    let buyer_agent = state.buyer_agent.lock().await;
+    match buyer_agent.price_history(&product_id, query.seller_id, query.days).await {
+        Ok(histories) => Ok(Json(histories)),
+        Err(e) => {
+            tracing::error!("Failed to get price history: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+async fn request_quote(
+    State(state): State<AppState>,
+    Valid(request): Valid<QuoteRequest>,
+) -> ApiResult<Json<NegotiationView>> {
+    let mut buyer_agent = state.buyer_agent.lock().await;
+    match buyer_agent.request_quote(request.product_id, request.quantity, request.max_price).await {
+        Ok(negotiation_id) => negotiation_view(&buyer_agent, negotiation_id),
+        Err(e) => {
+            tracing::error!("Failed to request quote: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+/// Takes a seller's warning that a quote is about to lapse.
+async fn handle_expiry_warning(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> ApiResult<Json<ExpiryResponse>> {
+    let warning: ExpiryWarning = serde_json::from_slice(&body)
+        .map_err(|e| ApiError::new(StatusCode::BAD_REQUEST, format!("Invalid expiry warning: {}", e)))?;
+    let signature = headers.get(SIGNATURE_HEADER).and_then(|value| value.to_str().ok());
+    let mut buyer_agent = state.buyer_agent.lock().await;
+    if let Err(e) = buyer_agent.verify_seller_webhook(warning.seller_id, &body, signature).await {
+        tracing::warn!("Rejected expiry warning for quote {}: {}", warning.quote_id, e);
+        return Err(e.into());
+    }
+    match buyer_agent.handle_expiry_warning(&warning).await {
+        Ok(response) => Ok(Json(response)),
+        Err(e) => {
+            tracing::warn!("Ignored expiry warning for quote {}: {}", warning.quote_id, e);
+            Err(e.into())
+        }
+    }
+}
+
+async fn list_negotiations(State(state): State<AppState>) -> Json<Vec<Negotiation>> {
+    let buyer_agent = state.buyer_agent.lock().await;
+    Json(buyer_agent.get_active_negotiations().into_iter().cloned().collect())
+}
+
+async fn get_negotiation(
+    State(state): State<AppState>,
+    Path(negotiation_id): Path<uuid::Uuid>,
+) -> ApiResult<Json<NegotiationView>> {
+    let buyer_agent = state.buyer_agent.lock().await;
+    negotiation_view(&buyer_agent, negotiation_id)
+}
+
+async fn negotiate(
+    State(state): State<AppState>,
+    Path(negotiation_id): Path<uuid::Uuid>,
+    Valid(request): Valid<NegotiateRequest>,
+) -> ApiResult<Json<NegotiationView>> {
+    let mut buyer_agent = state.buyer_agent.lock().await;
+    if buyer_agent.get_negotiation(negotiation_id).is_none() {
+        return Err(negotiation_not_found());
+    }
+    match buyer_agent.negotiate(negotiation_id, request.counter_offer).await {
+        Ok(()) => negotiation_view(&buyer_agent, negotiation_id),
+        Err(e) => {
+            tracing::error!("Failed to negotiate: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+async fn accept_quote(
+    State(state): State<AppState>,
+    Path(negotiation_id): Path<uuid::Uuid>,
+) -> ApiResult<Json<NegotiationView>> {
+    let mut buyer_agent = state.buyer_agent.lock().await;
+    if buyer_agent.get_negotiation(negotiation_id).is_none() {
+        return Err(negotiation_not_found());
+    }
+    match buyer_agent.accept_quote(negotiation_id).await {
+        Ok(()) => negotiation_view(&buyer_agent, negotiation_id),
+        Err(e) => {
+            tracing::error!("Failed to accept quote: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+async fn reject_quote(
+    State(state): State<AppState>,
+    Path(negotiation_id): Path<uuid::Uuid>,
+) -> ApiResult<Json<NegotiationView>> {
+    let mut buyer_agent = state.buyer_agent.lock().await;
+    if buyer_agent.get_negotiation(negotiation_id).is_none() {
+        return Err(negotiation_not_found());
+    }
+    match buyer_agent.reject_quote(negotiation_id).await {
+        Ok(()) => negotiation_view(&buyer_agent, negotiation_id),
+        Err(e) => {
+            tracing::error!("Failed to reject quote: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+async fn list_agreements(State(state): State<AppState>) -> Json<Vec<SupplyAgreement>> {
+    let buyer_agent = state.buyer_agent.lock().await;
+    Json(buyer_agent.get_agreements().into_iter().cloned().collect())
+}
+
+/// Issues a delegation token letting another agent negotiate, and settle if
+/// allowed, on this buyer's behalf.
+async fn delegate(
+    State(state): State<AppState>,
+    Valid(request): Valid<DelegationRequest>,
+) -> ApiResult<Json<serde_json::Value>> {
+    let ttl = chrono::Duration::hours(request.ttl_hours.unwrap_or(DEFAULT_DELEGATION_TTL_HOURS));
+    let mut buyer_agent = state.buyer_agent.lock().await;
+    match buyer_agent.delegate(request.delegate_id, request.scope, ttl).await {
+        Ok(token) => Ok(Json(serde_json::json!({ "delegation_token": token }))),
+        Err(e) => {
+            tracing::error!("Failed to delegate to {}: {}", request.delegate_id, e);
+            Err(e.into())
+        }
+    }
+}
+
+async fn propose_agreement(
+    State(state): State<AppState>,
+    Json(request): Json<AgreementProposal>,
+) -> ApiResult<Json<SupplyAgreement>> {
+    let mut buyer_agent = state.buyer_agent.lock().await;
+    match buyer_agent.propose_agreement(request.seller_id, request.items, request.duration_days).await {
+        Ok(agreement) => Ok(Json(agreement)),
+        Err(e) => {
+            tracing::error!("Failed to propose agreement: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+async fn accept_agreement(
+    State(state): State<AppState>,
+    Path(agreement_id): Path<uuid::Uuid>,
+) -> ApiResult<Json<SupplyAgreement>> {
+    let mut buyer_agent = state.buyer_agent.lock().await;
+    match buyer_agent.accept_agreement(agreement_id).await {
+        Ok(agreement) => Ok(Json(agreement)),
+        Err(e) => {
+            tracing::error!("Failed to accept agreement: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+async fn order_under_agreement(
+    State(state): State<AppState>,
+    Path(agreement_id): Path<uuid::Uuid>,
+    Json(request): Json<OrderRequest>,
+) -> ApiResult<Json<NegotiationView>> {
+    let mut buyer_agent = state.buyer_agent.lock().await;
+    match buyer_agent.order_under_agreement(agreement_id, request.product_id, request.quantity).await {
+        Ok(negotiation_id) => negotiation_view(&buyer_agent, negotiation_id),
+        Err(e) => {
+            tracing::error!("Failed to order under agreement: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+/// The buyer's in-memory state, tokens included, for a standby taking over
+/// from it.
+async fn export_state(
+    State(state): State<AppState>,
+) -> Json<BuyerState> {
+    Json(state.buyer_agent.lock().await.export_state())
+}
+
+async fn health_check() -> Json<serde_json::Value> {
+    Json(serde_json::json!({
+        "status": "healthy",
+        "protocol_versions": protocol::supported_versions()
+    }))
+}
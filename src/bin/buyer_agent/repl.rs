@@ -0,0 +1,384 @@
+//! Command grammar and line editing for the buyer agent's interactive prompt.
+//!
+//! Each line is split shell-style (quotes group words) and parsed as a clap
+//! subcommand, so `help` and `help <command>` come from the same definitions
+//! that validate arguments. The rustyline helper completes command names and
+//! the fixed values some arguments take.
+
+use clap::{
+    builder::{PossibleValuesParser, TypedValueParser},
+    CommandFactory, Parser, Subcommand, ValueEnum,
+};
+use dcap::{
+    agreement::AgreementItemRequest,
+    auction::BidVisibility,
+    cancellation::CancellationReason,
+    coalition::DEFAULT_COALITION_DURATION_SECONDS,
+    demand::DEFAULT_DEMAND_DURATION_SECONDS,
+    settlement::RefundReason,
+};
+use rust_decimal::Decimal;
+use rustyline::{
+    completion::Completer, highlight::Highlighter, hint::Hinter, validate::Validator, Context, Helper,
+};
+
+#[derive(Parser, Debug)]
+#[command(multicall = true, name = "buyer-agent")]
+pub struct ReplLine {
+    #[command(subcommand)]
+    pub command: ReplCommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ReplCommand {
+    /// Browse products
+    Browse {
+        category: Vec<String>,
+    },
+    /// Show how sellers' prices for a product have moved
+    Prices {
+        product_id: String,
+        /// Days of history, 30 by default
+        days: Option<u32>,
+    },
+    /// Request quote
+    Quote {
+        product_id: String,
+        quantity: u32,
+        max_price: Decimal,
+    },
+    /// Request quotes from several sellers and rank them
+    Compare {
+        product_id: String,
+        quantity: u32,
+        max_price: Decimal,
+        #[arg(default_value_t = 5)]
+        sellers: usize,
+        /// Where to deliver, e.g. US-CA; sellers quote shipping and taxes for it
+        #[arg(long)]
+        to: Option<String>,
+    },
+    /// Run a reverse auction
+    Auction {
+        product_id: String,
+        quantity: u32,
+        max_price: Decimal,
+        seconds: i64,
+        #[arg(value_enum, default_value_t = AuctionVisibility::Sealed)]
+        visibility: AuctionVisibility,
+    },
+    /// Show auction bids and winner
+    AuctionStatus {
+        auction_id: uuid::Uuid,
+    },
+    /// Show seller auctions open for bids
+    Listings,
+    /// Bid on a seller's listing
+    Bid {
+        listing_id: uuid::Uuid,
+        price: Decimal,
+    },
+    /// Pay for a won listing
+    SettleListing {
+        listing_id: uuid::Uuid,
+    },
+    /// Anonymously signal demand to subscribed sellers
+    Demand {
+        category: String,
+        quantity: u32,
+        max_price: Decimal,
+        #[arg(default_value_t = DEFAULT_DEMAND_DURATION_SECONDS)]
+        seconds: i64,
+    },
+    /// Show sellers' offers on a demand signal
+    Offers {
+        signal_id: uuid::Uuid,
+    },
+    /// Request a quote on a seller's offer
+    TakeOffer {
+        signal_id: uuid::Uuid,
+        offer_id: uuid::Uuid,
+    },
+    /// Withdraw a demand signal
+    WithdrawDemand {
+        signal_id: uuid::Uuid,
+    },
+    /// Form a coalition to buy a product with other buyers
+    Coalition {
+        product_id: String,
+        quantity: u32,
+        max_unit_price: Decimal,
+        min_quantity: u32,
+        #[arg(default_value_t = DEFAULT_COALITION_DURATION_SECONDS)]
+        seconds: i64,
+    },
+    /// Show coalitions open to join
+    Coalitions {
+        product_id: Option<String>,
+    },
+    /// Show a coalition's members and messages
+    CoalitionStatus {
+        coalition_id: uuid::Uuid,
+    },
+    /// Join a coalition with a quantity
+    JoinCoalition {
+        coalition_id: uuid::Uuid,
+        quantity: u32,
+    },
+    /// Leave a coalition
+    LeaveCoalition {
+        coalition_id: uuid::Uuid,
+    },
+    /// Send a seller the coalition's aggregated RFQ
+    NegotiateCoalition {
+        coalition_id: uuid::Uuid,
+    },
+    /// Accept the coalition's quote and split it into shares
+    AgreeCoalition {
+        coalition_id: uuid::Uuid,
+    },
+    /// Pay this buyer's share of a coalition
+    PayCoalition {
+        coalition_id: uuid::Uuid,
+    },
+    /// Ask a seller to price a rate card
+    Agreement {
+        seller_id: uuid::Uuid,
+        days: u32,
+        /// <product_id>:<quantity>
+        #[arg(required = true, value_parser = parse_agreement_item)]
+        items: Vec<AgreementItemRequest>,
+    },
+    /// Accept a proposed rate card
+    AcceptAgreement {
+        agreement_id: uuid::Uuid,
+    },
+    /// Order at the agreed rate, without negotiating
+    Order {
+        agreement_id: uuid::Uuid,
+        product_id: String,
+        quantity: u32,
+    },
+    /// Show rate cards and what remains on them
+    Agreements,
+    /// Negotiate price
+    Negotiate {
+        negotiation_id: uuid::Uuid,
+        counter_offer: Decimal,
+    },
+    /// Negotiate automatically
+    Auto {
+        negotiation_id: uuid::Uuid,
+        #[arg(value_parser = ["linear", "conceder", "boulware", "tit_for_tat"])]
+        strategy: String,
+        target_price: Decimal,
+    },
+    /// Accept quote
+    Accept {
+        negotiation_id: uuid::Uuid,
+    },
+    /// Reject quote
+    Reject {
+        negotiation_id: uuid::Uuid,
+    },
+    /// Call off an accepted deal
+    Cancel {
+        negotiation_id: uuid::Uuid,
+        #[arg(value_parser = PossibleValuesParser::new([
+            "buyer_withdrawal", "payment_failed", "mutual_agreement", "pricing_error",
+        ]).try_map(|reason| reason.parse::<CancellationReason>()))]
+        reason: CancellationReason,
+        #[arg(trailing_var_arg = true)]
+        note: Vec<String>,
+    },
+    /// Ask for money back on a settled deal, in full unless an amount is given
+    Refund {
+        negotiation_id: uuid::Uuid,
+        #[arg(value_parser = PossibleValuesParser::new([
+            "not_delivered", "not_as_described", "duplicate_charge",
+        ]).try_map(|reason| reason.parse::<RefundReason>()))]
+        reason: RefundReason,
+        amount: Option<Decimal>,
+    },
+    /// Propose new terms for an accepted deal
+    Renegotiate {
+        negotiation_id: uuid::Uuid,
+        price: Decimal,
+        quantity: u32,
+    },
+    /// Confirm the seller honoured an accepted quote
+    Delivered {
+        negotiation_id: uuid::Uuid,
+    },
+    /// Show orders and where their shipments are
+    Orders,
+    /// Report a seller backing out of an accepted quote
+    Renege {
+        negotiation_id: uuid::Uuid,
+        #[arg(required = true, trailing_var_arg = true)]
+        reason: Vec<String>,
+    },
+    /// Show active negotiations
+    Active,
+    /// List failed workflows awaiting replay
+    DeadLetters,
+    /// Replay a failed workflow
+    Replay {
+        dead_letter_id: uuid::Uuid,
+    },
+    /// Show commands entered this session
+    History,
+    /// Exit program
+    #[command(alias = "quit")]
+    Exit,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AuctionVisibility {
+    Sealed,
+    Open,
+}
+
+impl From<AuctionVisibility> for BidVisibility {
+    fn from(visibility: AuctionVisibility) -> Self {
+        match visibility {
+            AuctionVisibility::Sealed => BidVisibility::Sealed,
+            AuctionVisibility::Open => BidVisibility::Open,
+        }
+    }
+}
+
+fn parse_agreement_item(item: &str) -> std::result::Result<AgreementItemRequest, String> {
+    let (product_id, quantity) = item.split_once(':')
+        .ok_or_else(|| format!("expected <product_id>:<quantity>, got {}", item))?;
+    Ok(AgreementItemRequest {
+        product_id: product_id.to_string(),
+        committed_quantity: quantity.parse().map_err(|_| format!("invalid quantity in {}", item))?,
+    })
+}
+
+/// Parses one line of input. `Ok(None)` is a blank line; errors carry
+/// clap's message, usage and help text, ready to print.
+pub fn parse_line(line: &str) -> std::result::Result<Option<ReplCommand>, String> {
+    let words = shlex::split(line).ok_or_else(|| "error: unbalanced quotes".to_string())?;
+    if words.is_empty() {
+        return Ok(None);
+    }
+    ReplLine::try_parse_from(words)
+        .map(|parsed| Some(parsed.command))
+        .map_err(|e| e.render().to_string())
+}
+
+/// Tab completion for command names, `help <command>`, and arguments that
+/// take one of a fixed set of values.
+pub struct ReplHelper {
+    command: clap::Command,
+}
+
+impl ReplHelper {
+    pub fn new() -> Self {
+        let mut command = ReplLine::command();
+        command.build();
+        Self { command }
+    }
+
+    fn command_names(&self) -> Vec<String> {
+        self.command.get_subcommands().map(|sub| sub.get_name().to_string()).collect()
+    }
+
+    fn candidates(&self, previous: &[&str]) -> Vec<String> {
+        match previous.split_first() {
+            None => self.command_names(),
+            Some((&"help", [])) => self.command_names(),
+            Some((name, args)) => self.command.find_subcommand(name)
+                .and_then(|sub| sub.get_positionals().nth(args.len()))
+                .map(|arg| arg.get_possible_values().iter().map(|value| value.get_name().to_string()).collect())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl Completer for ReplHelper {
+    type Candidate = String;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<String>)> {
+        let line = &line[..pos];
+        let start = line.rfind(char::is_whitespace).map_or(0, |i| i + 1);
+        let (previous, word) = line.split_at(start);
+        let previous: Vec<&str> = previous.split_whitespace().collect();
+        let candidates = self.candidates(&previous).into_iter()
+            .filter(|candidate| candidate.starts_with(word))
+            .collect();
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ReplHelper {}
+
+impl Validator for ReplHelper {}
+
+impl Helper for ReplHelper {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lines_parse_into_validated_commands() {
+        assert!(parse_line("   ").unwrap().is_none());
+        assert!(matches!(
+            parse_line("quote laptop-001 2 2500.00").unwrap(),
+            Some(ReplCommand::Quote { quantity: 2, .. })
+        ));
+        assert!(matches!(
+            parse_line("browse Home Goods").unwrap(),
+            Some(ReplCommand::Browse { category }) if category.join(" ") == "Home Goods"
+        ));
+
+        let seller_id = uuid::Uuid::new_v4();
+        match parse_line(&format!("agreement {} 90 laptop-001:10 phone-001:5", seller_id)).unwrap() {
+            Some(ReplCommand::Agreement { items, .. }) => assert_eq!(items[1].committed_quantity, 5),
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse_line(&format!("cancel {} buyer_withdrawal \"found it cheaper\"", seller_id)).unwrap() {
+            Some(ReplCommand::Cancel { reason, note, .. }) => {
+                assert_eq!(reason, CancellationReason::BuyerWithdrawal);
+                assert_eq!(note, vec!["found it cheaper"]);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+
+        assert!(matches!(
+            parse_line(&format!("refund {} not_delivered 25.50", seller_id)).unwrap(),
+            Some(ReplCommand::Refund { reason: RefundReason::NotDelivered, amount: Some(_), .. })
+        ));
+        assert!(parse_line(&format!("refund {} goodwill", seller_id)).is_err());
+
+        assert!(parse_line("quote laptop-001 two 2500").is_err());
+        assert!(parse_line("accept not-a-uuid").is_err());
+        assert!(parse_line(&format!("auto {} haggle 100", seller_id)).is_err());
+        assert!(parse_line(&format!("agreement {} 90", seller_id)).is_err());
+        assert!(parse_line("frobnicate").is_err());
+    }
+
+    #[test]
+    fn test_completion_offers_commands_and_fixed_values() {
+        let helper = ReplHelper::new();
+        let complete = |line: &str| {
+            let history = rustyline::history::DefaultHistory::new();
+            helper.complete(line, line.len(), &Context::new(&history)).unwrap()
+        };
+
+        let (start, candidates) = complete("auc");
+        assert_eq!(start, 0);
+        assert_eq!(candidates, vec!["auction", "auction-status"]);
+        assert_eq!(complete("help wi").1, vec!["withdraw-demand"]);
+        assert_eq!(complete("auto 123 bo"), (9, vec!["boulware".to_string()]));
+        assert_eq!(complete("auction laptop-001 1 2000 600 ").1, vec!["sealed", "open"]);
+        assert!(complete("quote lap").1.is_empty());
+    }
+}
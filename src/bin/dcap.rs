@@ -0,0 +1,498 @@
+use chrono::{DateTime, Utc};
+use clap::{Parser, Subcommand};
+use dcap::{
+    artifacts::{ArtifactKind, ArtifactStore},
+    audit_bundle,
+    audit_log::{self, AuditLog, ChainReport},
+    database::Database,
+    export::{self, ExportFormat, ExportQuery, ExportTable},
+    jwt_keys::JwtKeyring,
+    model::NegotiationStatus,
+    reload::ConfigSource,
+    replay,
+    reputation_snapshot::ReputationSnapshot,
+    scenario::MarketScenario,
+    shared_state::SharedState,
+    strategy_bench::{self, Scenario},
+    trust::TrustSystem,
+    AgentId, TransactionId,
+};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+#[derive(Parser)]
+#[command(name = "dcap")]
+#[command(about = "DCAP operator tools")]
+struct Args {
+    #[arg(short, long, default_value = "sqlite://negotiation.db")]
+    database_url: String,
+
+    /// Config file with the `[artifacts]` settings
+    #[arg(short, long)]
+    config: Option<String>,
+
+    /// Overrides a config field, e.g. `--set logging.level=debug`; repeatable
+    #[arg(long = "set", value_name = "KEY=VALUE")]
+    overrides: Vec<String>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Dump negotiations, their messages or negotiation records for offline analysis
+    Export {
+        /// negotiations, messages or records
+        #[arg(long, default_value = "negotiations")]
+        table: ExportTable,
+
+        /// jsonl or csv
+        #[arg(long, default_value = "jsonl")]
+        format: ExportFormat,
+
+        /// Only negotiations involving this agent, as buyer or seller
+        #[arg(long)]
+        agent: Option<AgentId>,
+
+        #[arg(long)]
+        product: Option<String>,
+
+        /// e.g. settled, rejected; not available for records
+        #[arg(long, value_parser = parse_status)]
+        status: Option<NegotiationStatus>,
+
+        /// Start of the range, inclusive: a date (2024-06-01) or RFC 3339 timestamp
+        #[arg(long, value_parser = parse_bound)]
+        from: Option<DateTime<Utc>>,
+
+        /// End of the range, exclusive
+        #[arg(long, value_parser = parse_bound)]
+        to: Option<DateTime<Utc>>,
+
+        /// Write here instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
+
+        /// Keep the export in the artifact store instead, as an audit export
+        #[arg(long, conflicts_with = "output")]
+        store: bool,
+    },
+    /// Print a negotiation's timeline, from RFQ to settlement, as JSON
+    Replay {
+        negotiation: TransactionId,
+
+        /// Write here instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// Compare negotiation strategies against a simulated seller
+    Bench {
+        /// Strategy to run, repeatable; the config's `[bench]` strategies by default
+        #[arg(long = "strategy")]
+        strategies: Vec<String>,
+
+        /// Scenarios each strategy negotiates
+        #[arg(long)]
+        scenarios: Option<usize>,
+
+        #[arg(long)]
+        seed: Option<u64>,
+
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Run three sellers and a buyer in process through quotes, counter
+    /// offers, settlement and reputation, and print what happened
+    Scenario {
+        /// JSON scenario to run instead of the built-in competitive one
+        #[arg(long)]
+        file: Option<String>,
+
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Apply pending schema migrations; other commands apply them too
+    Migrate {
+        /// List the pending migrations without applying them
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Store and fetch contracts, invoices and other negotiation documents
+    Artifact {
+        #[command(subcommand)]
+        command: ArtifactCommand,
+    },
+    /// Export signed audit bundles and the audit log, and verify them
+    Audit {
+        #[command(subcommand)]
+        command: AuditCommand,
+    },
+    /// Move reputations between deployments as signed snapshots, through
+    /// the `[shared_state]` Redis cache
+    Reputation {
+        #[command(subcommand)]
+        command: ReputationCommand,
+    },
+    /// Generate an Ed25519 key for signing agent JWTs, as a `[[trust.signing_keys]]` entry
+    Keygen,
+}
+
+#[derive(Subcommand)]
+enum AuditCommand {
+    /// Sign everything recorded over a date range into one bundle
+    Export {
+        /// Start of the range, inclusive: a date (2024-06-01) or RFC 3339 timestamp
+        #[arg(long, value_parser = parse_bound)]
+        from: DateTime<Utc>,
+
+        /// End of the range, exclusive
+        #[arg(long, value_parser = parse_bound)]
+        to: DateTime<Utc>,
+
+        /// File holding the operator's base64 ed25519 secret key
+        #[arg(long)]
+        signing_key: String,
+
+        /// Write here instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// Check a bundle's signature, section digests and anchoring proofs
+    Verify {
+        bundle: String,
+
+        /// Only accept bundles signed by this base64 public key
+        #[arg(long)]
+        signer: Option<String>,
+    },
+    /// Write the hash-chained audit log as JSON lines, oldest entry first
+    ExportLog {
+        /// Write here instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// Check the audit log's hash chain, in the database or as exported
+    VerifyLog {
+        /// A file written by `export-log`; the database's log when left out
+        file: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ReputationCommand {
+    /// Sign every cached reputation into a snapshot
+    Export {
+        /// File holding the operator's base64 ed25519 secret key
+        #[arg(long)]
+        signing_key: String,
+
+        /// Write here instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// Merge a snapshot in, keeping reputations newer than the snapshot's
+    Import {
+        snapshot: String,
+
+        /// Base64 public key the snapshot must be signed with
+        #[arg(long)]
+        signer: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum ArtifactCommand {
+    /// Store a file, optionally attaching it to a negotiation
+    Put {
+        /// contract, invoice, attachment or audit_export
+        #[arg(long, default_value = "attachment")]
+        kind: ArtifactKind,
+
+        #[arg(long, default_value = "application/octet-stream")]
+        content_type: String,
+
+        /// Attach to this negotiation, announced by a message from `--sender`
+        #[arg(long, requires = "sender")]
+        negotiation: Option<TransactionId>,
+
+        #[arg(long)]
+        sender: Option<AgentId>,
+
+        file: String,
+    },
+    /// Fetch an artifact by its SHA-256 hash
+    Get {
+        hash: String,
+
+        /// Write here instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// List the artifacts attached to a negotiation
+    List {
+        negotiation: TransactionId,
+    },
+}
+
+fn parse_status(s: &str) -> Result<NegotiationStatus, String> {
+    serde_json::from_value(serde_json::Value::String(s.to_ascii_lowercase()))
+        .map_err(|_| format!("unknown negotiation status {}", s))
+}
+
+fn parse_bound(s: &str) -> Result<DateTime<Utc>, String> {
+    export::parse_export_bound(s).map_err(|e| e.to_string())
+}
+
+/// Prints how far the audit log's chain holds, failing if it's broken.
+fn print_chain_report(report: &ChainReport) -> std::result::Result<(), Box<dyn std::error::Error>> {
+    println!("{} entries checked", report.entries);
+    if let Some(head) = &report.head_hash {
+        println!("Head hash {}", head);
+    }
+    match &report.broken {
+        Some(broken) => Err(format!("Audit log chain broken at entry {}: {}", broken.sequence, broken.reason).into()),
+        None => Ok(()),
+    }
+}
+
+#[tokio::main]
+async fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse();
+    if let Command::Keygen = args.command {
+        let key = JwtKeyring::generate();
+        println!("[[trust.signing_keys]]\n{}", toml::to_string(&key)?);
+        return Ok(());
+    }
+    // Verifying needs only the bundle, not the database
+    if let Command::Audit { command: AuditCommand::Verify { bundle, signer } } = &args.command {
+        let report = audit_bundle::verify(&std::fs::read(bundle)?, signer.as_deref())?;
+        let manifest = &report.manifest;
+        println!("Signed by {} at {}", manifest.signer, manifest.generated_at.to_rfc3339());
+        println!("Covers {} to {}", manifest.from.to_rfc3339(), manifest.to.to_rfc3339());
+        for section in &manifest.sections {
+            println!("{:<24} {:>8} {}", section.name, section.count, section.sha256);
+        }
+        println!("{} anchoring proofs match their batch roots", report.anchor_proofs);
+        println!("{} of {} seller attestations carry a valid attestor signature", report.valid_attestations, report.attestations);
+        return Ok(());
+    }
+    if let Command::Audit { command: AuditCommand::VerifyLog { file: Some(file) } } = &args.command {
+        let entries = audit_log::read_export(&std::fs::read_to_string(file)?)?;
+        return print_chain_report(&audit_log::verify_chain(&entries));
+    }
+    let database = match args.command {
+        Command::Migrate { .. } => Database::connect(&args.database_url).await?,
+        _ => Database::new(&args.database_url).await?,
+    };
+    let config = ConfigSource::new(args.config.as_deref()).with_overrides(args.overrides.clone()).load()?;
+    config.validate()?;
+
+    match args.command {
+        Command::Export { table, format, agent, product, status, from, to, output, store } => {
+            let query = ExportQuery {
+                table,
+                from,
+                to,
+                agent_id: agent,
+                product_id: product,
+                status,
+            };
+            if store {
+                let mut out = Vec::new();
+                let written = database.export_negotiations(&query, format, &mut out).await?;
+                let content_type = match format {
+                    ExportFormat::JsonLines => "application/jsonl",
+                    ExportFormat::Csv => "text/csv",
+                };
+                let artifacts = ArtifactStore::from_config(database, &config.artifacts)?;
+                let artifact = artifacts.put(ArtifactKind::AuditExport, content_type, out).await?;
+                eprintln!("Exported {} rows to {}", written, artifact.uri());
+                return Ok(());
+            }
+            let written = match output {
+                Some(path) => database.export_negotiations(&query, format, BufWriter::new(File::create(&path)?)).await?,
+                None => database.export_negotiations(&query, format, io::stdout().lock()).await?,
+            };
+            eprintln!("Exported {} rows", written);
+        }
+        Command::Replay { negotiation, output } => {
+            let Some(replay) = replay::replay(&database, negotiation).await? else {
+                return Err(format!("No negotiation {}", negotiation).into());
+            };
+            match output {
+                Some(path) => serde_json::to_writer_pretty(BufWriter::new(File::create(&path)?), &replay)?,
+                None => {
+                    let mut stdout = io::stdout().lock();
+                    serde_json::to_writer_pretty(&mut stdout, &replay)?;
+                    writeln!(stdout)?;
+                }
+            }
+        }
+        Command::Bench { strategies, scenarios, seed, json } => {
+            let bench = &config.bench;
+            let strategies = if strategies.is_empty() { bench.strategies.clone() } else { strategies };
+            let seed = seed.unwrap_or(bench.seed);
+            let contenders = strategy_bench::contenders(&strategies, &bench.llm)?;
+            let scenarios = Scenario::generate(seed, scenarios.unwrap_or(bench.scenarios))?;
+            let report = strategy_bench::bench(&contenders, &scenarios, seed, bench.max_rounds).await;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+                return Ok(());
+            }
+            println!("{} scenarios, seed {}, up to {} rounds", report.scenarios, report.seed, report.max_rounds);
+            println!("{:<24} {:>10} {:>12} {:>10} {:>8}", "strategy", "close rate", "avg surplus", "avg rounds", "errors");
+            for strategy in &report.strategies {
+                println!(
+                    "{:<24} {:>9.1}% {:>11.1}% {:>10.1} {:>8}",
+                    strategy.strategy,
+                    strategy.close_rate * 100.0,
+                    strategy.average_surplus_percent,
+                    strategy.average_rounds,
+                    strategy.errors
+                );
+            }
+        }
+        Command::Scenario { file, json } => {
+            let scenario = match file {
+                Some(path) => serde_json::from_slice(&std::fs::read(path)?)?,
+                None => MarketScenario::competitive(),
+            };
+            let report = scenario.run(database).await?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+                return Ok(());
+            }
+            for deal in &report.deals {
+                println!("{} x {}", deal.product_id, deal.quantity);
+                for quote in &deal.quotes {
+                    println!("  {:<24} {:>14} score {:.2}", quote.seller_name, quote.price.to_string(), quote.score);
+                }
+                for failure in &deal.failures {
+                    println!("  {:<24} failed: {}", failure.seller_name, failure.error);
+                }
+                let countered = deal.countered_price.map_or_else(|| "declined".to_string(), |price| price.to_string());
+                println!(
+                    "  {} countered at {}, seller answered {}; {:?} at {}",
+                    deal.seller_name,
+                    deal.counter_offer,
+                    countered,
+                    deal.status,
+                    deal.final_price.map_or_else(|| "-".to_string(), |price| price.to_string())
+                );
+            }
+            println!("{:<24} {:>6} {:>6}", "reputation", "before", "after");
+            for change in &report.reputations {
+                println!("{:<24} {:>6} {:>6}", change.name, change.before, change.after);
+            }
+            if !report.all_settled() {
+                return Err("Not every deal settled".into());
+            }
+        }
+        Command::Migrate { dry_run } => {
+            let report = database.migrate_to_latest(dry_run).await?;
+            let version = |version: Option<i64>| version.map_or_else(|| "none".to_string(), |v| v.to_string());
+            println!("Schema version: {}", version(report.from_version));
+            for migration in &report.pending {
+                println!("{} {} {}", if dry_run { "Pending" } else { "Applied" }, migration.version, migration.description);
+            }
+            if report.pending.is_empty() {
+                println!("Up to date");
+            } else if !dry_run {
+                println!("Schema version: {}", version(report.to_version));
+            }
+        }
+        Command::Artifact { command } => {
+            let artifacts = ArtifactStore::from_config(database, &config.artifacts)?;
+            match command {
+                ArtifactCommand::Put { kind, content_type, negotiation, sender, file } => {
+                    let artifact = artifacts.put(kind, &content_type, std::fs::read(&file)?).await?;
+                    if let (Some(negotiation_id), Some(sender_id)) = (negotiation, sender) {
+                        artifacts.attach(negotiation_id, sender_id, &artifact).await?;
+                    }
+                    println!("{}", artifact.uri());
+                }
+                ArtifactCommand::Get { hash, output } => {
+                    let Some((_, bytes)) = artifacts.get(&hash).await? else {
+                        return Err(format!("No artifact {}", hash).into());
+                    };
+                    match output {
+                        Some(path) => std::fs::write(path, bytes)?,
+                        None => io::stdout().lock().write_all(&bytes)?,
+                    }
+                }
+                ArtifactCommand::List { negotiation } => {
+                    for artifact in artifacts.for_negotiation(negotiation).await? {
+                        println!("{}\t{}\t{}\t{}", artifact.uri(), artifact.kind, artifact.content_type, artifact.size);
+                    }
+                }
+            }
+        }
+        Command::Audit { command } => match command {
+            AuditCommand::Export { from, to, signing_key, output } => {
+                let key = audit_bundle::signing_key(&std::fs::read_to_string(&signing_key)?)?;
+                let bundle = audit_bundle::export(&database, from, to, &key).await?;
+                match output {
+                    Some(path) => serde_json::to_writer_pretty(BufWriter::new(File::create(&path)?), &bundle)?,
+                    None => {
+                        let mut stdout = io::stdout().lock();
+                        serde_json::to_writer_pretty(&mut stdout, &bundle)?;
+                        writeln!(stdout)?;
+                    }
+                }
+                eprintln!("Signed audit bundle as {}", bundle.manifest.signer);
+            }
+            AuditCommand::Verify { .. } | AuditCommand::VerifyLog { file: Some(_) } => {
+                unreachable!("verified before opening the database")
+            }
+            AuditCommand::ExportLog { output } => {
+                let log = AuditLog::new(database.clone());
+                let written = match output {
+                    Some(path) => {
+                        let mut file = BufWriter::new(File::create(&path)?);
+                        let written = log.export(&mut file).await?;
+                        file.flush()?;
+                        written
+                    }
+                    None => log.export(io::stdout().lock()).await?,
+                };
+                eprintln!("Exported {} audit log entries", written);
+            }
+            AuditCommand::VerifyLog { file: None } => {
+                print_chain_report(&AuditLog::new(database.clone()).verify_audit_chain().await?)?;
+            }
+        },
+        Command::Reputation { command } => {
+            let shared = SharedState::from_config(&config.shared_state).await?;
+            if shared.backend_name() == "memory" {
+                return Err("Reputation snapshots need [shared_state] redis_url; in-process reputations aren't reachable".into());
+            }
+            let mut trust = TrustSystem::from_config(&config.trust)?.with_shared_cache(shared);
+            match command {
+                ReputationCommand::Export { signing_key, output } => {
+                    let key = audit_bundle::signing_key(&std::fs::read_to_string(&signing_key)?)?;
+                    let snapshot = trust.export_snapshot(&key).await?;
+                    match output {
+                        Some(path) => serde_json::to_writer_pretty(BufWriter::new(File::create(&path)?), &snapshot)?,
+                        None => {
+                            let mut stdout = io::stdout().lock();
+                            serde_json::to_writer_pretty(&mut stdout, &snapshot)?;
+                            writeln!(stdout)?;
+                        }
+                    }
+                    eprintln!("Signed {} reputations as {}", snapshot.contents.reputations.len(), snapshot.signer);
+                }
+                ReputationCommand::Import { snapshot, signer } => {
+                    let snapshot: ReputationSnapshot = serde_json::from_slice(&std::fs::read(&snapshot)?)?;
+                    let import = trust.import_snapshot(&snapshot, &signer).await?;
+                    println!("{} reputations imported, {} newer ones kept", import.reputations_imported, import.reputations_kept);
+                }
+            }
+        }
+        Command::Keygen => unreachable!("generated before opening the database"),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,233 @@
+use chrono::{DateTime, Duration, Utc};
+use clap::{Parser, Subcommand};
+use dcap::{
+    admin::{self, AdminClient, AdminService, ReputationAdjustment},
+    audit_bundle,
+    blocklist::BlacklistRequest,
+    config::AppConfig,
+    export,
+    lifecycle::{self, Readiness},
+    model::AgentType,
+    roles::Role,
+    reload::ConfigSource,
+    runtime, security,
+    settlement::{SettlementConfig, SettlementService},
+    shared_state::SharedState,
+    telemetry,
+    trust::TrustSystem,
+    AgentId, TransactionId,
+};
+use tokio::net::TcpListener;
+
+#[derive(Parser)]
+#[command(name = "dcap-admin")]
+#[command(about = "DCAP marketplace operator API and CLI")]
+struct Args {
+    /// Config file with the `[admin]`, `[trust]` and `[shared_state]` settings
+    #[arg(short, long)]
+    config: Option<String>,
+
+    /// Overrides a config field, e.g. `--set logging.level=debug`; repeatable
+    #[arg(long = "set", value_name = "KEY=VALUE")]
+    overrides: Vec<String>,
+
+    /// Admin API to call
+    #[arg(short, long, env = "DCAP_ADMIN_ENDPOINT", default_value = "http://localhost:8010")]
+    endpoint: String,
+
+    /// JWT with the admin role, from `dcap-admin token`
+    #[arg(short, long, env = "DCAP_ADMIN_TOKEN")]
+    token: Option<String>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Serve the admin API over the marketplace's database
+    Serve {
+        #[arg(short, long, default_value = "8010")]
+        port: u16,
+
+        #[arg(short, long, default_value = "sqlite://settlement.db")]
+        database_url: String,
+    },
+    /// Issue an operator JWT signed with the `[trust]` keys
+    Token {
+        /// Who the token is for, recorded with each request
+        operator: String,
+
+        /// admin, or observer for read-only access
+        #[arg(long, default_value = "admin")]
+        role: Role,
+
+        #[arg(long, default_value = "60")]
+        ttl_minutes: i64,
+    },
+    /// List agents with their reputation and any block on them
+    Agents {
+        /// buyer or seller; both when left out
+        #[arg(long = "type", value_parser = parse_agent_type)]
+        agent_type: Option<AgentType>,
+    },
+    /// Bar an agent from trading
+    Suspend {
+        agent: AgentId,
+
+        #[arg(long)]
+        reason: String,
+
+        /// End of the suspension: a date (2024-06-01) or RFC 3339 timestamp; for good when left out
+        #[arg(long, value_parser = parse_bound)]
+        until: Option<DateTime<Utc>>,
+    },
+    /// Lift an agent's suspension or greylisting
+    Reinstate {
+        agent: AgentId,
+    },
+    /// Adjust an agent's reputation, signing the reason with an operator key
+    AdjustReputation {
+        agent: AgentId,
+
+        /// Points to add, negative to take away
+        #[arg(long, allow_hyphen_values = true)]
+        change: i32,
+
+        #[arg(long)]
+        reason: String,
+
+        /// File holding the operator's base64 ed25519 secret key
+        #[arg(long)]
+        signing_key: String,
+    },
+    /// List open negotiations nothing has touched for a while
+    Stuck,
+    /// Make a quote lapse now, expiring its negotiations and releasing its stock
+    ExpireQuote {
+        quote: TransactionId,
+    },
+    /// Give an active escrow hold's funds back to the buyer
+    RefundEscrow {
+        escrow: uuid::Uuid,
+    },
+    /// Print agent, negotiation, payment and escrow counts
+    Stats,
+}
+
+fn parse_agent_type(s: &str) -> Result<AgentType, String> {
+    serde_json::from_value(serde_json::Value::String(s.to_ascii_lowercase()))
+        .map_err(|_| format!("unknown agent type {}", s))
+}
+
+fn parse_bound(s: &str) -> Result<DateTime<Utc>, String> {
+    export::parse_export_bound(s).map_err(|e| e.to_string())
+}
+
+fn print_json(value: &impl serde::Serialize) -> std::result::Result<(), Box<dyn std::error::Error>> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse();
+    let config = ConfigSource::new(args.config.as_deref()).with_overrides(args.overrides.clone()).load()?;
+    config.validate()?;
+
+    match args.command {
+        Command::Serve { port, database_url } => return serve(&config, port, &database_url).await,
+        Command::Token { operator, role, ttl_minutes } => {
+            let trust = TrustSystem::from_config(&config.trust)?;
+            println!("{}", trust.generate_role_jwt(&operator, role, Duration::minutes(ttl_minutes))?);
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    let token = args.token.ok_or("--token or DCAP_ADMIN_TOKEN is required")?;
+    let client = AdminClient::new(&args.endpoint, &token);
+    match args.command {
+        Command::Agents { agent_type } => {
+            for overview in client.list_agents(agent_type).await? {
+                let agent = &overview.agent;
+                let block = overview.block.map_or_else(|| "-".to_string(), |block| format!("{:?}: {}", block.kind, block.reason));
+                println!("{}\t{:?}\t{}\t{}\t{}", agent.id, agent.agent_type, agent.name, overview.reputation, block);
+            }
+        }
+        Command::Suspend { agent, reason, until } => {
+            print_json(&client.suspend_agent(agent, &BlacklistRequest { reason, until }).await?)?;
+        }
+        Command::Reinstate { agent } => {
+            client.reinstate_agent(agent).await?;
+            println!("Reinstated {}", agent);
+        }
+        Command::AdjustReputation { agent, change, reason, signing_key } => {
+            let key = audit_bundle::signing_key(&std::fs::read_to_string(&signing_key)?)?;
+            let adjustment = ReputationAdjustment::sign(agent, change, &reason, &key, Utc::now());
+            let adjusted = client.adjust_reputation(agent, &adjustment).await?;
+            println!("{} reputation now {}", adjusted.agent_id, adjusted.reputation);
+        }
+        Command::Stuck => {
+            for stuck in client.stuck_negotiations().await? {
+                println!(
+                    "{}\t{:?}\t{}\tbuyer {}\tseller {}\tidle since {}",
+                    stuck.negotiation_id,
+                    stuck.status,
+                    stuck.product_id,
+                    stuck.buyer_id,
+                    stuck.seller_id,
+                    stuck.updated_at.to_rfc3339()
+                );
+            }
+        }
+        Command::ExpireQuote { quote } => {
+            let expired = client.expire_quote(quote).await?;
+            println!(
+                "Expired quote {}: {} negotiations expired, stock {}",
+                expired.quote.id,
+                expired.negotiations_expired,
+                if expired.stock_released { "released" } else { "not held" }
+            );
+        }
+        Command::RefundEscrow { escrow } => print_json(&client.refund_escrow(escrow).await?)?,
+        Command::Stats => print_json(&client.stats().await?)?,
+        Command::Serve { .. } | Command::Token { .. } => unreachable!("handled without a token"),
+    }
+
+    Ok(())
+}
+
+async fn serve(config: &AppConfig, port: u16, database_url: &str) -> std::result::Result<(), Box<dyn std::error::Error>> {
+    let _telemetry = telemetry::init("dcap-admin", &config.logging)?;
+    if config.admin.operator_keys.is_empty() {
+        tracing::warn!("No [admin] operator_keys configured; reputation adjustments will be refused");
+    }
+
+    let database = lifecycle::wait_for_database(database_url, &config.lifecycle).await?;
+    let settlement = SettlementService::new(SettlementConfig {
+        stripe_secret_key: None,
+        solana_rpc_url: None,
+        escrow_service_url: None,
+        webhook_secret: None,
+        delivery_confirmation_timeout_seconds: None,
+    }, database.clone()).await?;
+    let shared = SharedState::from_config(&config.shared_state).await?;
+    let trust = TrustSystem::from_config(&config.trust)?.with_shared_cache(shared.clone());
+    let service = AdminService::new(database.clone(), trust, settlement, shared, &config.admin);
+
+    let readiness = Readiness::new();
+    let app = readiness.route(admin::router(service));
+    let app = telemetry::trace_requests(app);
+    let app = security::harden(app, &config.server);
+
+    let listener = TcpListener::bind(format!("0.0.0.0:{}", port)).await?;
+    println!("Admin API listening on {}", port);
+
+    readiness.mark_ready();
+    runtime::serve(listener, app, &config.server).await?;
+
+    database.close().await;
+    println!("Admin API stopped");
+    Ok(())
+}
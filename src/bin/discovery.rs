@@ -1,15 +1,45 @@
 use dcap::{
-    discovery::{DiscoveryServer, RegisterRequest, SearchRequest},
-    error::NegotiationError,
+    auction::{BidRequest, CreateAuctionRequest, CreateListingRequest, ListingBidRequest},
+    blocklist::BlacklistRequest,
+    broadcast::BroadcastRfqRequest,
+    compliance::CompliancePolicy,
+    coalition::{
+        AgreeCoalitionRequest, CoalitionActionRequest, CoalitionPaymentRequest, FormCoalitionRequest,
+        JoinCoalitionRequest, LockCoalitionRequest,
+    },
+    demand::{DemandResponseRequest, PublishDemandRequest, SubscribeRequest},
+    discovery::{CatalogSyncRequest, DiscoveryServer, HandoverRequest, RegisterRequest, SearchRequest},
+    embeddings::ProductEmbeddings,
+    error::{ApiResult, NegotiationError},
+    explorer::{self, Explorer, EXPLORER_ROUTES},
+    http::HttpClient,
+    lifecycle::{self, Readiness},
+    metrics::Metrics,
+    product_search::ProductSearchQuery,
+    protocol,
+    reload::ConfigSource,
+    runtime,
+    security,
+    session::bearer_token,
+    shared_state::SharedState,
+    recovery::{RecoveryPolicyRequest, RecoveryRequest},
+    responsiveness::ResponseTimeReport,
+    roles::{self, Permission},
+    taxonomy::{CategoryTaxonomy, CategoryTree},
+    trust::{JWTClaims, TrustSystem},
+    validation::Valid,
 };
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
+    extract::{Path, Query, State},
+    http::HeaderMap,
+    middleware,
     response::Json,
-    routing::{get, post},
-    Router,
+    routing::{delete, get, post},
+    Extension, Router,
 };
 use clap::Parser;
+use std::{sync::Arc, time::Duration};
+use tokio::sync::RwLock;
 use tokio::net::TcpListener;
 
 #[derive(Parser)]
@@ -19,8 +49,27 @@ struct Args {
     #[arg(short, long, default_value = "sqlite://discovery.db")]
     database_url: String,
 
+    /// Config file with the `[compliance]` policy, `[taxonomy]`,
+    /// `[response_sla]` and `[metrics]` settings; no category is restricted
+    /// without one
+    #[arg(short, long)]
+    config: Option<String>,
+
+    /// Overrides a config field, e.g. `--set logging.level=debug`; repeatable
+    #[arg(long = "set", value_name = "KEY=VALUE")]
+    overrides: Vec<String>,
+
     #[arg(short, long, default_value = "8000")]
     port: u16,
+
+    /// How often to close auctions and listings whose bidding window has passed
+    #[arg(long, default_value = "5")]
+    auction_close_interval_seconds: u64,
+
+    /// Also serve the gRPC API on this port
+    #[cfg(feature = "grpc")]
+    #[arg(long)]
+    grpc_port: Option<u16>,
 }
 
 #[tokio::main]
@@ -31,20 +80,129 @@ async fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
 
     let args = Args::parse();
 
-    let discovery_server = DiscoveryServer::new(&args.database_url).await?;
+    let config = ConfigSource::new(args.config.as_deref()).with_overrides(args.overrides.clone()).load()?;
+    config.validate()?;
+    let shared = SharedState::from_config(&config.shared_state).await?;
+    let database = lifecycle::wait_for_database(&args.database_url, &config.lifecycle).await?;
+    let mut discovery_server = DiscoveryServer::from_database(database.clone())
+        .with_compliance_policy(CompliancePolicy::new(config.compliance))
+        .with_taxonomy(CategoryTaxonomy::new(&config.taxonomy)?)
+        .with_response_sla(config.response_sla)
+        .with_shared_state(shared.clone());
+    if let Some(embeddings) = ProductEmbeddings::from_config(database.clone(), &config.embeddings, HttpClient::new(&config.http))? {
+        discovery_server = discovery_server.with_embeddings(embeddings);
+    }
     let app_state = AppState { discovery_server };
 
+    let auctions = app_state.discovery_server.auctions().clone();
+    let close_interval = Duration::from_secs(args.auction_close_interval_seconds.max(1));
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(close_interval);
+        loop {
+            interval.tick().await;
+            let now = chrono::Utc::now();
+            if let Err(e) = auctions.close_due_auctions(now).await {
+                tracing::error!("Failed to close auctions: {}", e);
+            }
+            if let Err(e) = auctions.close_due_listings(now).await {
+                tracing::error!("Failed to close listings: {}", e);
+            }
+        }
+    });
+
+    #[cfg(feature = "grpc")]
+    if let Some(grpc_port) = args.grpc_port {
+        let service = dcap::grpc::DiscoveryGrpc::new(app_state.discovery_server.clone()).into_service();
+        tokio::spawn(async move {
+            let addr = std::net::SocketAddr::from(([0, 0, 0, 0], grpc_port));
+            if let Err(e) = tonic::transport::Server::builder().add_service(service).serve(addr).await {
+                tracing::error!("gRPC server failed: {}", e);
+            }
+        });
+        println!("Discovery gRPC listening on {}", grpc_port);
+    }
+
+    // Agent JWT keys, published so anyone can check reputation claims
+    let trust = TrustSystem::from_config(&config.trust)?;
+    let jwks = trust.jwks();
+    let subjects = trust.token_subjects();
+    let trust = Arc::new(RwLock::new(trust));
+    // Only sellers answer demand, and only operators block agents
+    let answer_demand = Router::new()
+        .route("/demand/:signal_id/responses", post(respond_to_demand));
+    // Broadcast RFQs go out to sellers with the buyer's token
+    let request_quotes = Router::new()
+        .route("/demand", post(publish_demand))
+        .route("/rfq-broadcasts", post(broadcast_rfq));
+    let administer = Router::new()
+        .route("/admin/blocks", get(list_blocked_agents))
+        .route("/admin/agents/:agent_id/blacklist", post(blacklist_agent))
+        .route("/admin/agents/:agent_id/unblock", post(unblock_agent));
     let app = Router::new()
+        .route("/.well-known/jwks.json", get(move || async move { Json(jwks) }))
         .route("/register", post(register_agent))
         .route("/search", post(search_agents))
-        .route("/agents/:agent_id", get(get_agent))
+        .route("/agents/:agent_id", get(get_agent).delete(deregister_agent))
+        .route("/agents/:agent_id/catalog", post(sync_catalog))
+        .route("/agents/:agent_id/handover", post(hand_over_agent))
+        .route("/agents/:agent_id/recovery-policy", post(set_recovery_policy))
+        .route("/agents/:agent_id/recover", post(recover_agent_key))
+        .route("/agents/:agent_id/keys", get(get_key_history))
+        .route("/agents/:agent_id/trust-history", get(get_trust_history))
+        .route("/agents/:agent_id/response-times", post(report_response_time).get(get_response_times))
+        .route("/auctions", post(create_auction).get(list_auctions))
+        .route("/auctions/:auction_id", get(get_auction))
+        .route("/auctions/:auction_id/bids", post(submit_bid))
+        .route("/listings", post(create_listing).get(list_listings))
+        .route("/listings/:listing_id", get(get_listing))
+        .route("/listings/:listing_id/bids", post(submit_listing_bid))
+        .route("/demand", get(list_demand))
+        .route("/demand/subscriptions", post(subscribe_to_demand))
+        .route("/demand/subscriptions/:subscription_id", delete(unsubscribe_from_demand))
+        .route("/demand/:signal_id/responses", get(get_demand_responses))
+        .route("/demand/:signal_id/withdraw", post(withdraw_demand))
+        .route("/rfq-broadcasts/:broadcast_id", get(get_rfq_broadcast))
+        .route("/coalitions", post(form_coalition).get(list_coalitions))
+        .route("/coalitions/:coalition_id", get(get_coalition))
+        .route("/coalitions/:coalition_id/join", post(join_coalition))
+        .route("/coalitions/:coalition_id/leave", post(leave_coalition))
+        .route("/coalitions/:coalition_id/lock", post(lock_coalition))
+        .route("/coalitions/:coalition_id/agree", post(agree_coalition))
+        .route("/coalitions/:coalition_id/payments", post(record_coalition_payment))
+        .route("/coalitions/:coalition_id/cancel", post(cancel_coalition))
+        .route("/products/search", get(search_products))
+        .route("/categories", get(list_categories))
+        .route("/products/:product_id/history", get(get_price_history))
+        .route("/compliance/violations", get(list_compliance_violations))
         .route("/health", get(health_check))
-        .with_state(app_state);
+        .merge(roles::require_permission(answer_demand, trust.clone(), Permission::AnswerRfqs))
+        .merge(roles::require_permission(request_quotes, trust.clone(), Permission::RequestQuotes))
+        .merge(roles::require_permission(administer, trust, Permission::Administer))
+        .layer(middleware::from_fn(protocol::protocol_version_layer))
+        .with_state(app_state)
+        .merge(explorer::router(Explorer::new(database.clone(), &config.privacy, &config.explorer)?));
+    let readiness = Readiness::new();
+    let app = readiness.route(app);
+    let metrics = Metrics::new();
+    let app = if config.metrics.enabled { metrics.instrument(app) } else { app };
+    // The explorer is public, so each of its routes gets its own budget
+    let mut server_config = config.server.clone();
+    if config.explorer.rate_limit_per_minute > 0 {
+        for route in EXPLORER_ROUTES {
+            server_config.rate_limit_routes.entry(route.to_string()).or_insert(config.explorer.rate_limit_per_minute);
+        }
+    }
+    let app = security::rate_limit(app, &server_config, shared.clone(), Some(subjects), Some(metrics));
+    let app = security::harden(app, &config.server);
 
     let listener = TcpListener::bind(format!("0.0.0.0:{}", args.port)).await?;
-    println!("Discovery service listening on {}", args.port);
+    println!("Discovery service listening on {} ({} shared state)", args.port, shared.backend_name());
 
-    axum::serve(listener, app).await?;
+    readiness.mark_ready();
+    runtime::serve(listener, app, &config.server).await?;
+
+    database.close().await;
+    println!("Discovery service stopped");
 
     Ok(())
 }
@@ -56,20 +214,18 @@ struct AppState {
 
 async fn register_agent(
     State(state): State<AppState>,
-    Json(request): Json<RegisterRequest>,
-) -> Json<serde_json::Value> {
+    Valid(request): Valid<RegisterRequest>,
+) -> ApiResult<Json<serde_json::Value>> {
     match state.discovery_server.handle_register(request).await {
-        Ok(agent) => Json(serde_json::json!({
+        Ok(agent) => Ok(Json(serde_json::json!({
             "status": "success",
             "agent_id": agent.id,
+            "products_listed": agent.products.len(),
             "message": "Agent registered successfully"
-        })),
+        }))),
         Err(e) => {
             tracing::error!("Failed to register agent: {}", e);
-            Json(serde_json::json!({
-                "status": "error",
-                "message": e.to_string()
-            }))
+            Err(e.into())
         }
     }
 }
@@ -77,15 +233,12 @@ async fn register_agent(
 async fn search_agents(
     State(state): State<AppState>,
     Json(request): Json<SearchRequest>,
-) -> Json<serde_json::Value> {
+) -> ApiResult<Json<serde_json::Value>> {
     match state.discovery_server.handle_search(request).await {
-        Ok(response) => Json(serde_json::json!(response)),
+        Ok(response) => Ok(Json(serde_json::json!(response))),
         Err(e) => {
             tracing::error!("Failed to search agents: {}", e);
-            Json(serde_json::json!({
-                "status": "error",
-                "message": e.to_string()
-            }))
+            Err(e.into())
         }
     }
 }
@@ -93,23 +246,613 @@ async fn search_agents(
 async fn get_agent(
     State(state): State<AppState>,
     Path(agent_id): Path<uuid::Uuid>,
-) -> Json<serde_json::Value> {
+) -> ApiResult<Json<serde_json::Value>> {
     match state.discovery_server.get_agent_info(agent_id).await {
-        Ok(Some(agent)) => Json(serde_json::json!(agent)),
-        Ok(None) => Json(serde_json::json!({
-            "status": "error",
-            "message": "Agent not found"
-        })),
+        Ok(Some(agent)) => Ok(Json(serde_json::json!(agent))),
+        Ok(None) => Err(NegotiationError::AgentNotFound(agent_id).into()),
         Err(e) => {
             tracing::error!("Failed to get agent: {}", e);
-            Json(serde_json::json!({
-                "status": "error",
-                "message": e.to_string()
-            }))
+            Err(e.into())
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct DeregisterQuery {
+    /// Only removes the agent while it is registered here
+    endpoint: Option<String>,
+}
+
+/// Removes an agent that is shutting down, with its listings.
+async fn deregister_agent(
+    State(state): State<AppState>,
+    Path(agent_id): Path<uuid::Uuid>,
+    Query(query): Query<DeregisterQuery>,
+) -> ApiResult<Json<serde_json::Value>> {
+    match state.discovery_server.remove_agent(agent_id, query.endpoint.as_deref()).await {
+        Ok(()) => Ok(Json(serde_json::json!({
+            "status": "success",
+            "message": "Agent deregistered"
+        }))),
+        Err(e) => {
+            tracing::error!("Failed to deregister agent: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+/// Moves an agent's registration to the host taking over from it.
+async fn hand_over_agent(
+    State(state): State<AppState>,
+    Path(agent_id): Path<uuid::Uuid>,
+    Json(request): Json<HandoverRequest>,
+) -> ApiResult<Json<serde_json::Value>> {
+    match state.discovery_server.hand_over(agent_id, request).await {
+        Ok(agent) => Ok(Json(serde_json::json!(agent))),
+        Err(e) => {
+            tracing::error!("Failed to hand over agent: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+/// Replaces a seller's catalog, withholding products the compliance policy
+/// doesn't allow.
+async fn sync_catalog(
+    State(state): State<AppState>,
+    Path(agent_id): Path<uuid::Uuid>,
+    Json(request): Json<CatalogSyncRequest>,
+) -> ApiResult<Json<serde_json::Value>> {
+    match state.discovery_server.sync_catalog(agent_id, request).await {
+        Ok(response) => Ok(Json(serde_json::json!(response))),
+        Err(e) => {
+            tracing::error!("Failed to sync catalog: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+/// Full-text search over every listed product, with facets.
+async fn list_categories(State(state): State<AppState>) -> Json<Vec<CategoryTree>> {
+    Json(state.discovery_server.categories())
+}
+
+async fn search_products(
+    State(state): State<AppState>,
+    Query(query): Query<ProductSearchQuery>,
+) -> ApiResult<Json<serde_json::Value>> {
+    match state.discovery_server.search_products(query).await {
+        Ok(response) => Ok(Json(serde_json::json!(response))),
+        Err(e) => {
+            tracing::error!("Failed to search products: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct PriceHistoryQuery {
+    seller_id: Option<uuid::Uuid>,
+    days: Option<u32>,
+}
+
+async fn get_price_history(
+    State(state): State<AppState>,
+    Path(product_id): Path<String>,
+    Query(query): Query<PriceHistoryQuery>,
+) -> ApiResult<Json<serde_json::Value>> {
+    match state.discovery_server.price_history(&product_id, query.seller_id, query.days).await {
+        Ok(histories) => Ok(Json(serde_json::json!({ "histories": histories }))),
+        Err(e) => {
+            tracing::error!("Failed to get price history: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct ViolationsQuery {
+    limit: Option<i64>,
+}
+
+async fn list_compliance_violations(
+    State(state): State<AppState>,
+    Query(query): Query<ViolationsQuery>,
+) -> ApiResult<Json<serde_json::Value>> {
+    match state.discovery_server.compliance_violations(query.limit.unwrap_or(100)).await {
+        Ok(violations) => Ok(Json(serde_json::json!({ "violations": violations }))),
+        Err(e) => {
+            tracing::error!("Failed to list compliance violations: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+async fn set_recovery_policy(
+    State(state): State<AppState>,
+    Path(agent_id): Path<uuid::Uuid>,
+    Json(request): Json<RecoveryPolicyRequest>,
+) -> ApiResult<Json<serde_json::Value>> {
+    match state.discovery_server.set_recovery_policy(agent_id, request).await {
+        Ok(()) => Ok(Json(serde_json::json!({
+            "status": "success",
+            "message": "Recovery policy registered"
+        }))),
+        Err(e) => {
+            tracing::error!("Failed to register recovery policy: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+async fn recover_agent_key(
+    State(state): State<AppState>,
+    Path(agent_id): Path<uuid::Uuid>,
+    Json(request): Json<RecoveryRequest>,
+) -> ApiResult<Json<serde_json::Value>> {
+    match state.discovery_server.recover_agent_key(agent_id, request).await {
+        Ok(rotation) => Ok(Json(serde_json::json!({
+            "status": "success",
+            "rotation": rotation
+        }))),
+        Err(e) => {
+            tracing::error!("Failed to recover agent key: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+/// Bars an agent from trading and hides it from searches.
+async fn blacklist_agent(
+    State(state): State<AppState>,
+    Path(agent_id): Path<uuid::Uuid>,
+    Json(request): Json<BlacklistRequest>,
+) -> ApiResult<Json<serde_json::Value>> {
+    match state.discovery_server.blacklist_agent(agent_id, request).await {
+        Ok(block) => Ok(Json(serde_json::json!(block))),
+        Err(e) => {
+            tracing::error!("Failed to blacklist agent: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+/// Lifts an agent's blacklisting or greylisting.
+async fn unblock_agent(
+    State(state): State<AppState>,
+    Path(agent_id): Path<uuid::Uuid>,
+) -> ApiResult<Json<serde_json::Value>> {
+    match state.discovery_server.unblock_agent(agent_id).await {
+        Ok(true) => Ok(Json(serde_json::json!({
+            "status": "success",
+            "message": "Agent unblocked"
+        }))),
+        Ok(false) => Err(NegotiationError::NotFound(format!("Block on agent {}", agent_id)).into()),
+        Err(e) => {
+            tracing::error!("Failed to unblock agent: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+async fn list_blocked_agents(State(state): State<AppState>) -> ApiResult<Json<serde_json::Value>> {
+    match state.discovery_server.blocked_agents().await {
+        Ok(blocks) => Ok(Json(serde_json::json!({ "blocks": blocks }))),
+        Err(e) => {
+            tracing::error!("Failed to list blocked agents: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+async fn get_key_history(
+    State(state): State<AppState>,
+    Path(agent_id): Path<uuid::Uuid>,
+) -> ApiResult<Json<serde_json::Value>> {
+    match state.discovery_server.get_key_history(agent_id).await {
+        Ok(rotations) => Ok(Json(serde_json::json!({ "rotations": rotations }))),
+        Err(e) => {
+            tracing::error!("Failed to get key history: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+async fn get_trust_history(
+    State(state): State<AppState>,
+    Path(agent_id): Path<uuid::Uuid>,
+) -> ApiResult<Json<serde_json::Value>> {
+    match state.discovery_server.get_trust_history(agent_id).await {
+        Ok(activities) => Ok(Json(serde_json::json!({ "activities": activities }))),
+        Err(e) => {
+            tracing::error!("Failed to get trust history: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+async fn report_response_time(
+    State(state): State<AppState>,
+    Path(agent_id): Path<uuid::Uuid>,
+    Json(report): Json<ResponseTimeReport>,
+) -> ApiResult<Json<serde_json::Value>> {
+    match state.discovery_server.record_response_time(agent_id, report).await {
+        Ok(response_times) => Ok(Json(serde_json::json!(response_times))),
+        Err(e) => {
+            tracing::error!("Failed to record response time: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+async fn get_response_times(
+    State(state): State<AppState>,
+    Path(agent_id): Path<uuid::Uuid>,
+) -> ApiResult<Json<serde_json::Value>> {
+    match state.discovery_server.response_times(agent_id).await {
+        Ok(response_times) => Ok(Json(serde_json::json!(response_times))),
+        Err(e) => {
+            tracing::error!("Failed to get response times: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+async fn create_auction(
+    State(state): State<AppState>,
+    Json(request): Json<CreateAuctionRequest>,
+) -> ApiResult<Json<serde_json::Value>> {
+    match state.discovery_server.auctions().create_auction(request).await {
+        Ok(auction) => Ok(Json(serde_json::json!(auction))),
+        Err(e) => {
+            tracing::error!("Failed to create auction: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+async fn list_auctions(State(state): State<AppState>) -> ApiResult<Json<serde_json::Value>> {
+    match state.discovery_server.auctions().list_open_auctions().await {
+        Ok(auctions) => Ok(Json(serde_json::json!({ "auctions": auctions }))),
+        Err(e) => {
+            tracing::error!("Failed to list auctions: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+async fn get_auction(
+    State(state): State<AppState>,
+    Path(auction_id): Path<uuid::Uuid>,
+) -> ApiResult<Json<serde_json::Value>> {
+    match state.discovery_server.auctions().view_auction(auction_id).await {
+        Ok(view) => Ok(Json(serde_json::json!(view))),
+        Err(e) => {
+            tracing::error!("Failed to get auction: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+async fn submit_bid(
+    State(state): State<AppState>,
+    Path(auction_id): Path<uuid::Uuid>,
+    Json(request): Json<BidRequest>,
+) -> ApiResult<Json<serde_json::Value>> {
+    match state.discovery_server.auctions().submit_bid(auction_id, request, chrono::Utc::now()).await {
+        Ok(bid) => Ok(Json(serde_json::json!({
+            "status": "success",
+            "bid": bid
+        }))),
+        Err(e) => {
+            tracing::error!("Failed to submit bid: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+async fn create_listing(
+    State(state): State<AppState>,
+    Json(request): Json<CreateListingRequest>,
+) -> ApiResult<Json<serde_json::Value>> {
+    match state.discovery_server.auctions().create_listing(request).await {
+        Ok(listing) => Ok(Json(serde_json::json!(listing))),
+        Err(e) => {
+            tracing::error!("Failed to create listing: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+async fn list_listings(State(state): State<AppState>) -> ApiResult<Json<serde_json::Value>> {
+    match state.discovery_server.auctions().list_open_listings().await {
+        Ok(listings) => Ok(Json(serde_json::json!({ "listings": listings }))),
+        Err(e) => {
+            tracing::error!("Failed to list listings: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+async fn get_listing(
+    State(state): State<AppState>,
+    Path(listing_id): Path<uuid::Uuid>,
+) -> ApiResult<Json<serde_json::Value>> {
+    match state.discovery_server.auctions().view_listing(listing_id).await {
+        Ok(view) => Ok(Json(serde_json::json!(view))),
+        Err(e) => {
+            tracing::error!("Failed to get listing: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+async fn submit_listing_bid(
+    State(state): State<AppState>,
+    Path(listing_id): Path<uuid::Uuid>,
+    Json(request): Json<ListingBidRequest>,
+) -> ApiResult<Json<serde_json::Value>> {
+    match state.discovery_server.auctions().submit_listing_bid(listing_id, request, chrono::Utc::now()).await {
+        Ok(bid) => Ok(Json(serde_json::json!({
+            "status": "success",
+            "bid": bid
+        }))),
+        Err(e) => {
+            tracing::error!("Failed to submit listing bid: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+async fn publish_demand(
+    State(state): State<AppState>,
+    Json(request): Json<PublishDemandRequest>,
+) -> ApiResult<Json<serde_json::Value>> {
+    let demand = state.discovery_server.demand().clone();
+    match demand.publish(request, chrono::Utc::now()).await {
+        Ok(published) => {
+            let signal = published.signal.clone();
+            tokio::spawn(async move { demand.notify_subscribers(&signal).await });
+            Ok(Json(serde_json::json!(published)))
+        }
+        Err(e) => {
+            tracing::error!("Failed to publish demand signal: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+async fn broadcast_rfq(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<BroadcastRfqRequest>,
+) -> ApiResult<Json<serde_json::Value>> {
+    // Checked by the permission gate already
+    let access_token = bearer_token(&headers).unwrap_or_default().to_string();
+    match state.discovery_server.open_broadcast(request).await {
+        Ok(broadcast) => {
+            let broadcasts = state.discovery_server.broadcasts().clone();
+            let pending = broadcast.clone();
+            tokio::spawn(async move {
+                if let Err(e) = broadcasts.fan_out(&pending, &access_token).await {
+                    tracing::error!("Failed to fan out RFQ broadcast {}: {}", pending.id, e);
+                }
+            });
+            Ok(Json(serde_json::json!(broadcast)))
+        }
+        Err(e) => {
+            tracing::error!("Failed to broadcast RFQ: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+async fn get_rfq_broadcast(
+    State(state): State<AppState>,
+    Path(broadcast_id): Path<uuid::Uuid>,
+) -> ApiResult<Json<serde_json::Value>> {
+    match state.discovery_server.broadcasts().get(broadcast_id).await {
+        Ok(view) => Ok(Json(serde_json::json!(view))),
+        Err(e) => {
+            tracing::error!("Failed to get RFQ broadcast: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct DemandQuery {
+    category: Option<String>,
+}
+
+async fn list_demand(
+    State(state): State<AppState>,
+    Query(query): Query<DemandQuery>,
+) -> ApiResult<Json<serde_json::Value>> {
+    match state.discovery_server.demand().list_open_signals(query.category.as_deref(), chrono::Utc::now()).await {
+        Ok(signals) => Ok(Json(serde_json::json!({ "signals": signals }))),
+        Err(e) => {
+            tracing::error!("Failed to list demand signals: {}", e);
+            Err(e.into())
         }
     }
 }
 
+async fn subscribe_to_demand(
+    State(state): State<AppState>,
+    Json(request): Json<SubscribeRequest>,
+) -> ApiResult<Json<serde_json::Value>> {
+    match state.discovery_server.demand().subscribe(request).await {
+        Ok(subscription) => Ok(Json(serde_json::json!(subscription))),
+        Err(e) => {
+            tracing::error!("Failed to subscribe to demand: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+async fn unsubscribe_from_demand(
+    State(state): State<AppState>,
+    Path(subscription_id): Path<uuid::Uuid>,
+) -> ApiResult<Json<serde_json::Value>> {
+    match state.discovery_server.demand().unsubscribe(subscription_id).await {
+        Ok(()) => Ok(Json(serde_json::json!({
+            "status": "success",
+            "message": "Subscription removed"
+        }))),
+        Err(e) => {
+            tracing::error!("Failed to remove demand subscription: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+/// Sellers may only offer in their own name.
+async fn respond_to_demand(
+    State(state): State<AppState>,
+    Path(signal_id): Path<uuid::Uuid>,
+    Extension(claims): Extension<JWTClaims>,
+    Json(request): Json<DemandResponseRequest>,
+) -> ApiResult<Json<serde_json::Value>> {
+    if claims.sub != request.seller_id.to_string() {
+        return Err(NegotiationError::Trust("Sellers may only answer demand for themselves".to_string()).into());
+    }
+    match state.discovery_server.demand().respond(signal_id, request, chrono::Utc::now()).await {
+        Ok(response) => Ok(Json(serde_json::json!(response))),
+        Err(e) => {
+            tracing::error!("Failed to respond to demand signal: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+/// Offers are only shown to the holder of the signal's response token.
+async fn get_demand_responses(
+    State(state): State<AppState>,
+    Path(signal_id): Path<uuid::Uuid>,
+    headers: HeaderMap,
+) -> ApiResult<Json<serde_json::Value>> {
+    let token = bearer_token(&headers).unwrap_or_default();
+    match state.discovery_server.demand().responses(signal_id, token).await {
+        Ok(responses) => Ok(Json(serde_json::json!({ "responses": responses }))),
+        Err(e) => {
+            tracing::error!("Failed to get demand responses: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+async fn withdraw_demand(
+    State(state): State<AppState>,
+    Path(signal_id): Path<uuid::Uuid>,
+    headers: HeaderMap,
+) -> ApiResult<Json<serde_json::Value>> {
+    let token = bearer_token(&headers).unwrap_or_default();
+    match state.discovery_server.demand().withdraw(signal_id, token).await {
+        Ok(signal) => Ok(Json(serde_json::json!(signal))),
+        Err(e) => {
+            tracing::error!("Failed to withdraw demand signal: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+/// Answers with `value`, or the error
+fn coalition_response<T: serde::Serialize>(result: dcap::error::Result<T>, action: &str) -> ApiResult<Json<serde_json::Value>> {
+    match result {
+        Ok(value) => Ok(Json(serde_json::json!(value))),
+        Err(e) => {
+            tracing::error!("Failed to {}: {}", action, e);
+            Err(e.into())
+        }
+    }
+}
+
+async fn form_coalition(
+    State(state): State<AppState>,
+    Json(request): Json<FormCoalitionRequest>,
+) -> ApiResult<Json<serde_json::Value>> {
+    coalition_response(state.discovery_server.coalitions().form(request, chrono::Utc::now()).await, "form coalition")
+}
+
+#[derive(serde::Deserialize)]
+struct CoalitionQuery {
+    product_id: Option<String>,
+}
+
+async fn list_coalitions(
+    State(state): State<AppState>,
+    Query(query): Query<CoalitionQuery>,
+) -> ApiResult<Json<serde_json::Value>> {
+    let coalitions = state.discovery_server.coalitions().list_open(query.product_id.as_deref(), chrono::Utc::now()).await
+        .map(|coalitions| serde_json::json!({ "coalitions": coalitions }));
+    coalition_response(coalitions, "list coalitions")
+}
+
+async fn get_coalition(
+    State(state): State<AppState>,
+    Path(coalition_id): Path<uuid::Uuid>,
+) -> ApiResult<Json<serde_json::Value>> {
+    coalition_response(state.discovery_server.coalitions().get(coalition_id).await, "get coalition")
+}
+
+async fn join_coalition(
+    State(state): State<AppState>,
+    Path(coalition_id): Path<uuid::Uuid>,
+    Json(request): Json<JoinCoalitionRequest>,
+) -> ApiResult<Json<serde_json::Value>> {
+    coalition_response(state.discovery_server.coalitions().join(coalition_id, request, chrono::Utc::now()).await, "join coalition")
+}
+
+async fn leave_coalition(
+    State(state): State<AppState>,
+    Path(coalition_id): Path<uuid::Uuid>,
+    Json(request): Json<CoalitionActionRequest>,
+) -> ApiResult<Json<serde_json::Value>> {
+    let coalitions = state.discovery_server.coalitions();
+    let left = match coalitions.leave(coalition_id, request.agent_id, chrono::Utc::now()).await {
+        Ok(()) => coalitions.get(coalition_id).await,
+        Err(e) => Err(e),
+    };
+    coalition_response(left, "leave coalition")
+}
+
+async fn lock_coalition(
+    State(state): State<AppState>,
+    Path(coalition_id): Path<uuid::Uuid>,
+    Json(request): Json<LockCoalitionRequest>,
+) -> ApiResult<Json<serde_json::Value>> {
+    coalition_response(state.discovery_server.coalitions().lock(coalition_id, request, chrono::Utc::now()).await, "lock coalition")
+}
+
+async fn agree_coalition(
+    State(state): State<AppState>,
+    Path(coalition_id): Path<uuid::Uuid>,
+    Json(request): Json<AgreeCoalitionRequest>,
+) -> ApiResult<Json<serde_json::Value>> {
+    coalition_response(state.discovery_server.coalitions().agree(coalition_id, request, chrono::Utc::now()).await, "agree coalition price")
+}
+
+async fn record_coalition_payment(
+    State(state): State<AppState>,
+    Path(coalition_id): Path<uuid::Uuid>,
+    Json(request): Json<CoalitionPaymentRequest>,
+) -> ApiResult<Json<serde_json::Value>> {
+    let recorded = state.discovery_server.coalitions().record_payment(coalition_id, request, chrono::Utc::now()).await;
+    coalition_response(recorded, "record coalition payment")
+}
+
+async fn cancel_coalition(
+    State(state): State<AppState>,
+    Path(coalition_id): Path<uuid::Uuid>,
+    Json(request): Json<CoalitionActionRequest>,
+) -> ApiResult<Json<serde_json::Value>> {
+    coalition_response(state.discovery_server.coalitions().cancel(coalition_id, request.agent_id, chrono::Utc::now()).await, "cancel coalition")
+}
+
 async fn health_check() -> Json<serde_json::Value> {
-    Json(serde_json::json!({"status": "healthy"}))
+    Json(serde_json::json!({
+        "status": "healthy",
+        "protocol_versions": protocol::supported_versions()
+    }))
 }
\ No newline at end of file
@@ -1,4 +1,4 @@
-use dcap::mcp::NegotiationMcpServer;
+use dcap::{mcp::NegotiationMcpServer, runtime};
 use tokio::net::TcpListener;
 use tracing::{info, error};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
@@ -23,6 +23,13 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let listener = TcpListener::bind("127.0.0.1:8080").await?;
     info!("MCP server listening on {}", listener.local_addr()?);
 
+    // Drain connections on Ctrl-C or SIGTERM
+    let shutdown = server.shutdown_token();
+    tokio::spawn(async move {
+        runtime::shutdown_signal().await;
+        shutdown.cancel();
+    });
+
     // Run server
     if let Err(e) = server.run(listener).await {
         error!("Server error: {}", e);
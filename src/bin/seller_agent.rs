@@ -1,25 +1,59 @@
 use dcap::{
-    agent::{SellerAgent, SellerAgentConfig, LLMConfig},
+    agent::{SellerAgent, SellerAgentConfig, LLMConfig, DEFAULT_MIN_BUYER_REPUTATION},
+    agreement::{AgreementRequest, AgreementService},
+    audit_log::{AuditAction, AuditLog},
+    blocklist::{BlockList, BlockPolicy},
+    cancellation::{self, DealChange},
+    catalog,
+    compliance::ComplianceProfile,
     config::AppConfig,
+    database::Database,
+    delegation::DelegationTokens,
+    demand::DemandSignal,
     discovery::DiscoveryService,
-    error::NegotiationError,
-    model::{Product, RFQ, Quote, PaymentMethod},
+    error::{ApiError, ApiResult, NegotiationError},
+    http::HttpClient,
+    idempotency::{self, Caller, IdempotencyStore},
+    expiry::ExpiryReminders,
+    gossip::{Attestations, ReputationGossip},
+    handover::{self, SellerState},
+    inventory::{InventoryService, StockOutcome, StockUpdate},
+    language::Language,
+    lifecycle::{self, PodIdentity, Readiness},
+    metrics::Metrics,
+    model::{AgentInfo, Product, RFQ, Quote, PaymentMethod},
+    money::Money,
+    nonce::ReplayGuard,
+    protocol,
+    registry::{self, RegistryKind},
+    roles::{self, Permission},
+    reload::{ConfigReloader, ConfigSource},
+    runtime,
+    security,
+    session::{bearer_token, SessionClaims, SessionTokens},
     settlement::SettlementService,
-    trust::TrustSystem,
+    shared_state::{SharedLock, SharedState},
+    telemetry,
+    templates::TemplateKind,
+    trust::{TokenPair, TrustSystem},
+    validation::Valid,
+    webhook::WebhookSigner,
 };
 use chrono;
 use axum::{
-    extract::{Path, Query, State},
-    http::StatusCode,
-    response::Json,
+    extract::{Path, Query, Request, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Json, Response},
     routing::{get, post},
     Router,
 };
 use clap::Parser;
+use rust_decimal::Decimal;
 use std::collections::HashMap;
 use std::env;
 use std::sync::{Arc, Mutex};
-use tokio::net::TcpListener;
+use tokio::{net::TcpListener, sync::watch};
 
 #[derive(Parser)]
 #[command(name = "seller-agent")]
@@ -28,38 +62,89 @@ struct Args {
     #[arg(short, long, default_value = "config.toml")]
     config: String,
 
+    /// Overrides a config field, e.g. `--set trust.min_reputation_threshold=60`; repeatable
+    #[arg(long = "set", value_name = "KEY=VALUE")]
+    overrides: Vec<String>,
+
     #[arg(short, long, default_value = "sqlite://negotiation.db")]
     database_url: String,
 
-    #[arg(short, long, default_value = "http://localhost:8000")]
+    #[arg(long, default_value = "http://localhost:8000")]
     discovery_endpoint: String,
 
     #[arg(short, long, default_value = "8001")]
     port: u16,
+
+    /// Region the seller sells from, checked by the registry for restricted categories
+    #[arg(long)]
+    region: Option<String>,
+
+    /// JSON file of attestations clearing the seller for restricted categories
+    #[arg(long)]
+    attestations: Option<String>,
+
+    /// Take over from the seller running at this URL: import its state,
+    /// then move its registration here
+    #[arg(long)]
+    take_over: Option<String>,
+
+    /// Also serve the gRPC API on this port
+    #[cfg(feature = "grpc")]
+    #[arg(long)]
+    grpc_port: Option<u16>,
 }
 
 #[derive(Clone)]
 struct AppState {
+    seller_agent: Arc<SellerAgent>,
     seller_agent_config: SellerAgentConfig,
+    database: Database,
+    session_tokens: SessionTokens,
+    /// Checks the delegated authority of buyers acting for a principal
+    delegations: DelegationTokens,
+    /// Trades refresh tokens for new access tokens, and holds the
+    /// reputations exchanged with gossip peers
+    auth: Arc<tokio::sync::Mutex<TrustSystem>>,
+    gossip: Arc<ReputationGossip>,
+    expiry: ExpiryReminders,
+    /// Negotiation locks shared with the seller's other replicas
+    shared: SharedState,
+    /// Nonces of the RFQs received, shared with the seller's other replicas
+    replay_guard: ReplayGuard,
+    /// Records the quotes given
+    audit: AuditLog,
 }
 
 #[tokio::main]
 async fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
-    tracing_subscriber::fmt()
-        .with_max_level(tracing::Level::INFO)
-        .init();
-
     let args = Args::parse();
 
-    let config = AppConfig::load(&args.config)?;
-    let discovery = DiscoveryService::new(args.discovery_endpoint.clone());
-    let trust = TrustSystem::new()?;
+    let source = ConfigSource::new(Some(&args.config)).with_overrides(args.overrides.clone());
+    let config = source.load()?;
+    config.validate()?;
+    let _telemetry = telemetry::init("seller-agent", &config.logging)?;
+    let http = HttpClient::for_server(&config.http, config.server.tls.as_ref())?;
+    let discovery = DiscoveryService::new(args.discovery_endpoint.clone()).with_http_client(http.clone())
+        .with_registry(registry::from_config(&config.discovery, &args.discovery_endpoint, http.clone())?);
+    let shared = SharedState::from_config(&config.shared_state).await?;
+    let database = lifecycle::wait_for_database(&args.database_url, &config.lifecycle).await?;
+    let trust = TrustSystem::from_config(&config.trust)?.with_shared_cache(shared.clone())
+        .with_block_list(BlockList::new(database.clone(), BlockPolicy::from_config(&config.trust)));
+    let session_tokens = trust.session_tokens();
+    let delegations = trust.delegation_tokens();
+    // Checks the admin tokens the state export is served to, and the seller
+    // and admin tokens catalog changes need
+    let operators = Arc::new(tokio::sync::RwLock::new(TrustSystem::from_config(&config.trust)?));
     let settlement_config = dcap::settlement::SettlementConfig {
         stripe_secret_key: None,
         solana_rpc_url: None,
         escrow_service_url: None,
+        webhook_secret: None,
+        delivery_confirmation_timeout_seconds: None,
     };
-    let settlement = SettlementService::new(settlement_config).await?;
+    let settlement = SettlementService::new(settlement_config, database.clone()).await?;
+    // Counted where the seller's traffic is handled: its agent, router and rate limiter
+    let metrics = Metrics::new();
 
     let products = vec![
         Product {
@@ -67,7 +152,7 @@ async fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
             name: "Gaming Laptop".to_string(),
             description: "High-performance gaming laptop with RTX 4080".to_string(),
             category: "Electronics".to_string(),
-            base_price: 2499.99,
+            base_price: Decimal::new(249999, 2),
             currency: "USD".to_string(),
             stock_quantity: 10,
             metadata: HashMap::new(),
@@ -77,17 +162,34 @@ async fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
             name: "Smartphone Pro".to_string(),
             description: "Latest flagship smartphone with 5G".to_string(),
             category: "Electronics".to_string(),
-            base_price: 1299.99,
+            base_price: Decimal::new(129999, 2),
             currency: "USD".to_string(),
             stock_quantity: 25,
             metadata: HashMap::new(),
         },
     ];
 
+    let attestations = match &args.attestations {
+        Some(path) => serde_json::from_str(&std::fs::read_to_string(path)?)?,
+        None => Vec::new(),
+    };
+
+    // A standby runs as the seller it takes over from
+    let handover_state = match &args.take_over {
+        Some(endpoint) => {
+            let admin_token = handover::handover_token(&*operators.read().await)?;
+            Some(handover::fetch_state::<SellerState>(endpoint, &admin_token).await?)
+        }
+        None => None,
+    };
+
+    let identity = PodIdentity::from_env(&config.lifecycle)?;
     let seller_config = SellerAgentConfig {
-        agent_id: uuid::Uuid::new_v4(),
-        name: "TechSeller".to_string(),
-        endpoint: format!("http://localhost:{}", args.port),
+        agent_id: handover_state.as_ref().map(|state| state.agent_id)
+            .or(identity.agent_id)
+            .unwrap_or_else(uuid::Uuid::new_v4),
+        name: identity.name.clone().unwrap_or_else(|| "TechSeller".to_string()),
+        endpoint: identity.endpoint(args.port).unwrap_or_else(|| format!("http://localhost:{}", args.port)),
         products,
         payment_methods: vec![PaymentMethod::Stripe, PaymentMethod::Escrow],
         llm_config: LLMConfig {
@@ -96,117 +198,867 @@ async fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
             max_tokens: 1000,
             temperature: 0.7,
         },
+        calendar: config.calendar.clone(),
+        pricing: config.pricing.clone(),
+        quote_ttl: config.quote_ttl.clone(),
+        quote_firmness: config.quote_firmness,
+        locale: config.locale,
+        preferred_languages: config.preferred_languages.clone(),
+        compliance: ComplianceProfile {
+            region: args.region.clone().or(identity.region),
+            attestations,
+        },
+        templates: config.templates.clone(),
+        tax: config.tax.clone(),
+        shipping: config.shipping.clone(),
     };
 
+    // Stock levels survive restarts; only new products start at their configured stock
+    let inventory = InventoryService::new(database.clone());
+    inventory.track(seller_config.agent_id, &seller_config.products, chrono::Utc::now()).await?;
+
+    let signer = WebhookSigner::from_config(seller_config.agent_id, &config.webhooks)?;
     let seller_agent = SellerAgent::new(
         seller_config.clone(),
         discovery,
         trust,
-    ).await?;
+    ).await?
+    .with_agreements(AgreementService::new(database.clone()))
+    .with_inventory(inventory.clone())
+    .with_metrics(metrics.clone())
+    .with_webhook_signer(signer.clone())
+    .with_min_buyer_reputation(config.trust.min_reputation_threshold.unwrap_or(DEFAULT_MIN_BUYER_REPUTATION));
+    let taking_over_from = match handover_state {
+        Some(state) => {
+            let endpoint = state.endpoint.clone();
+            seller_agent.import_state(state).await?;
+            Some(endpoint)
+        }
+        None => None,
+    };
 
-    // Register with discovery service
-    seller_agent.register().await?;
+    let seller_agent = Arc::new(seller_agent);
+
+    // Pricing and the reputation asked of buyers follow config reloads
+    let reloader = ConfigReloader::new(source, config.clone());
+    reloader.watch(&config.reload);
+    follow_reloads(seller_agent.clone(), reloader.subscribe());
+
+    let replay_guard = ReplayGuard::from_config(shared.clone(), &config.server);
+    // Peers' reputations land in the shared cache the seller's trust reads
+    let auth = Arc::new(tokio::sync::Mutex::new(TrustSystem::from_config(&config.trust)?.with_shared_cache(shared.clone())));
+
+    #[cfg(feature = "grpc")]
+    if let Some(grpc_port) = args.grpc_port {
+        let service = dcap::grpc::SellerGrpc::new(seller_agent.clone(), database.clone(), session_tokens.clone(), auth.clone())
+            .with_replay_guard(replay_guard.clone())
+            .into_service();
+        tokio::spawn(async move {
+            let addr = std::net::SocketAddr::from(([0, 0, 0, 0], grpc_port));
+            if let Err(e) = tonic::transport::Server::builder().add_service(service).serve(addr).await {
+                tracing::error!("gRPC server failed: {}", e);
+            }
+        });
+        println!("Seller agent gRPC listening on {}", grpc_port);
+    }
+
+    // Periodically warn buyers whose firm quotes are about to lapse
+    let expiry = ExpiryReminders::new(database.clone(), config.expiry.warning_seconds).with_signer(signer);
+    if config.expiry.warning_seconds > 0 {
+        let expiry = expiry.clone();
+        let check_interval = std::time::Duration::from_secs(config.expiry.check_interval_seconds.max(1));
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(check_interval);
+            loop {
+                interval.tick().await;
+                if let Err(e) = expiry.send_due(chrono::Utc::now()).await {
+                    tracing::error!("Failed to send quote expiry warnings: {}", e);
+                }
+            }
+        });
+    }
+
+    // Return stock held for quotes that lapsed without a decision
+    let release_interval = std::time::Duration::from_secs(config.expiry.check_interval_seconds.max(1));
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(release_interval);
+        loop {
+            interval.tick().await;
+            match inventory.release_expired(chrono::Utc::now()).await {
+                Ok(0) => {}
+                Ok(released) => tracing::info!("Released stock held for {} lapsed quotes", released),
+                Err(e) => tracing::error!("Failed to release stock for lapsed quotes: {}", e),
+            }
+        }
+    });
+
+    // Retried quote and negotiation requests get their first response back
+    let idempotency = IdempotencyStore::from_config(database.clone(), &config.server);
+    let purge = idempotency.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(3600));
+        loop {
+            interval.tick().await;
+            if let Err(e) = purge.purge_expired().await {
+                tracing::error!("Failed to purge expired idempotency keys: {}", e);
+            }
+        }
+    });
+
+    let gossip = Arc::new(ReputationGossip::from_config(&config.gossip, http.clone())?);
+    if !gossip.peers().is_empty() {
+        let (gossip, trust) = (gossip.clone(), auth.clone());
+        let interval_seconds = config.gossip.interval_seconds;
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(interval_seconds));
+            loop {
+                interval.tick().await;
+                if let Err(e) = gossip.pull(&trust).await {
+                    tracing::error!("Failed to pull reputation from gossip peers: {}", e);
+                }
+            }
+        });
+    }
 
     let app_state = AppState {
+        seller_agent: seller_agent.clone(),
         seller_agent_config: seller_config.clone(),
+        database: database.clone(),
+        session_tokens,
+        delegations,
+        auth,
+        gossip,
+        expiry,
+        shared: shared.clone(),
+        replay_guard,
+        audit: AuditLog::new(database.clone()),
     };
 
+    // Callers are authenticated before their retries are replayed
+    let quote_routes = idempotency::idempotent(Router::new().route("/quote", post(handle_quote)), idempotency.clone())
+        .route_layer(middleware::from_fn_with_state(app_state.clone(), authenticate_buyer));
+    let negotiation_routes = idempotency::idempotent(
+        Router::new().route("/negotiate/:negotiation_id", post(handle_negotiation)),
+        idempotency,
+    ).route_layer(middleware::from_fn_with_state(app_state.clone(), authenticate_session));
+    let administer = Router::new()
+        .route("/admin/state", get(export_state));
     let app = Router::new()
-        .route("/quote", post(handle_quote))
+        .merge(quote_routes)
+        .merge(negotiation_routes)
         .route("/quote/:rfq_id", get(get_quote))
-        .route("/negotiate/:negotiation_id", post(handle_negotiation))
+        .route("/negotiate/:negotiation_id/revoke", post(revoke_session_tokens))
+        .route("/negotiate/:negotiation_id/changes", post(review_deal_change))
+        .route("/negotiate/:negotiation_id/stock", post(report_stock))
+        .route("/demand", post(handle_demand_signal))
+        .route("/agreements", post(propose_agreement))
+        .route("/agreements/:agreement_id", get(get_agreement))
+        .route("/agreements/:agreement_id/accept", post(accept_agreement))
+        .route("/auth/refresh", post(refresh_tokens))
+        .route("/reputation/attestations", get(get_reputation_attestations))
         .route("/products", get(list_products))
+        .route("/agent", get(describe_agent))
         .route("/health", get(health_check))
+        .merge(roles::require_permission(administer, operators.clone(), Permission::Administer))
+        .merge(catalog::router(seller_agent.clone(), operators))
+        .layer(middleware::from_fn(protocol::protocol_version_layer))
         .with_state(app_state);
+    let readiness = Readiness::new();
+    let app = readiness.route(app);
+    let app = telemetry::trace_requests(app);
+    let app = if config.metrics.enabled { metrics.instrument(app) } else { app };
+    let subjects = TrustSystem::from_config(&config.trust)?.token_subjects();
+    let app = security::rate_limit(app, &config.server, shared.clone(), Some(subjects), Some(metrics.clone()));
+    let app = security::harden(app, &config.server);
 
     let listener = TcpListener::bind(format!("0.0.0.0:{}", args.port)).await?;
-    println!("Seller agent listening on {}", args.port);
+    println!("Seller agent listening on {} ({} shared state)", args.port, shared.backend_name());
+
+    let server_config = config.server.clone();
+    let server = tokio::spawn(async move { runtime::serve(listener, app, &server_config).await });
 
-    axum::serve(listener, app).await?;
+    // Buyers are only routed here once the registry lists the seller. Sellers
+    // found through DNS or a static file are listed by their deployment.
+    if config.discovery.registry == RegistryKind::Http {
+        lifecycle::wait_for_discovery(&args.discovery_endpoint, &config.lifecycle).await?;
+    }
+    match &taking_over_from {
+        Some(endpoint) => {
+            seller_agent.hand_over(endpoint).await?;
+            println!("Took over from {}; it can be stopped", endpoint);
+        }
+        None => seller_agent.register().await?,
+    }
+    if let Err(e) = seller_agent.subscribe_to_demand().await {
+        tracing::warn!("Failed to subscribe to demand signals: {}", e);
+    }
+    readiness.mark_ready();
+
+    server.await??;
+
+    // Buyers stop finding the seller before its database goes away
+    if let Err(e) = seller_agent.deregister().await {
+        tracing::warn!("Failed to deregister from discovery: {}", e);
+    }
+    database.close().await;
+    println!("Seller agent stopped");
 
     Ok(())
 }
 
+/// Reprices the seller and updates the reputation it asks of buyers with
+/// each reloaded config.
+fn follow_reloads(seller_agent: Arc<SellerAgent>, mut reloads: watch::Receiver<Arc<AppConfig>>) {
+    tokio::spawn(async move {
+        while reloads.changed().await.is_ok() {
+            let config = reloads.borrow_and_update().clone();
+            if let Err(e) = seller_agent.update_pricing(&config.pricing) {
+                tracing::warn!("Keeping the current pricing: {}", e);
+            }
+            seller_agent.set_min_buyer_reputation(
+                config.trust.min_reputation_threshold.unwrap_or(DEFAULT_MIN_BUYER_REPUTATION),
+            );
+        }
+    });
+}
+
 async fn handle_quote(
-    State(_state): State<AppState>,
-    Json(rfq): Json<RFQ>,
-) -> Json<serde_json::Value> {
-    // Mock quote response
-    Json(serde_json::json!({
-        "id": uuid::Uuid::new_v4(),
-        "rfq_id": rfq.id,
-        "seller_id": uuid::Uuid::new_v4(),
-        "price": rfq.max_price * 0.9,
-        "currency": rfq.currency,
-        "available_quantity": rfq.quantity,
-        "ttl_seconds": 3600,
-        "created_at": chrono::Utc::now(),
-        "metadata": {}
-    }))
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Valid(rfq): Valid<RFQ>,
+) -> ApiResult<Json<serde_json::Value>> {
+    if let Err(e) = state.seller_agent.check_rfq(&state.replay_guard, &rfq).await {
+        tracing::warn!("Refused RFQ {} from buyer {}: {}", rfq.id, rfq.buyer_id, e);
+        return Err(e.into());
+    }
+    let nonce = rfq.nonce.clone();
+    let quote = quote_rfq(&state, &headers, rfq).await;
+    // The buyer may retry an RFQ that wasn't quoted
+    if quote.is_err() {
+        if let Err(e) = state.replay_guard.release("rfq", nonce.as_deref()).await {
+            tracing::warn!("Failed to release RFQ nonce: {}", e);
+        }
+    }
+    quote
+}
+
+async fn quote_rfq(state: &AppState, headers: &HeaderMap, rfq: RFQ) -> ApiResult<Json<serde_json::Value>> {
+    if let Err(e) = authorize_delegation(state, headers, &rfq) {
+        tracing::warn!("Refused RFQ {} outside its delegation: {}", rfq.id, e);
+        return Err(ApiError::from(e).with_status(StatusCode::FORBIDDEN));
+    }
+
+    // Priced, taxed and given shipping options by the seller's policies;
+    // orders under a supply agreement from its rate card
+    let quote = match state.seller_agent.handle_rfq(rfq.clone()).await {
+        Ok(quote) => quote,
+        Err(e) => {
+            tracing::info!("Refused RFQ {} from buyer {}: {}", rfq.id, rfq.buyer_id, e);
+            if matches!(e, NegotiationError::Validation(_)) && out_of_stock(state, &rfq).await {
+                // No negotiation exists yet, so the message is filed under the RFQ
+                return Err(refusal(state, headers, StatusCode::CONFLICT, TemplateKind::OutOfStock, rfq.id, &[
+                    ("product_id", rfq.product_id.clone()),
+                    ("quantity", rfq.quantity.to_string()),
+                ]));
+            }
+            return Err(e.into());
+        }
+    };
+
+    if let Err(e) = state.expiry.track(&rfq, &quote).await {
+        tracing::warn!("Failed to schedule expiry warning for quote {}: {}", quote.id, e);
+    }
+    state.audit.record(AuditAction::Quote, Some(quote.seller_id), &quote.id.to_string(), &quote).await;
+    Ok(Json(serde_json::json!(quote)))
+}
+
+/// Whether the seller has fewer units of the RFQ's product free than it asks
+/// for.
+async fn out_of_stock(state: &AppState, rfq: &RFQ) -> bool {
+    state.seller_agent.available_stock(&rfq.product_id).await
+        .is_ok_and(|available| available < rfq.quantity)
+}
+
+/// Checks that a buyer acting for a principal is allowed to ask for this
+/// RFQ: the token must be issued to the buyer and cover the product's
+/// category and the RFQ's value.
+fn authorize_delegation(state: &AppState, headers: &HeaderMap, rfq: &RFQ) -> dcap::error::Result<()> {
+    let Some(claims) = state.delegations.from_headers(headers, rfq.buyer_id)? else {
+        return Ok(());
+    };
+    let category = state.seller_agent_config.products.iter()
+        .find(|product| product.id == rfq.product_id)
+        .map(|product| product.category.as_str());
+    claims.authorize_deal(category, &Money::new(rfq.max_price, rfq.currency.clone()))?;
+    tracing::info!("RFQ {} from buyer {} on behalf of {:?}", rfq.id, rfq.buyer_id, claims.principal());
+    Ok(())
+}
+
+/// An error response carrying the seller's message to the buyer, written
+/// from its templates in the language the buyer asked for.
+fn refusal(
+    state: &AppState,
+    headers: &HeaderMap,
+    status: StatusCode,
+    kind: TemplateKind,
+    negotiation_id: uuid::Uuid,
+    variables: &[(&str, String)],
+) -> ApiError {
+    match state.seller_agent.compose_message(kind, negotiation_id, buyer_language(headers), variables) {
+        Ok(message) => ApiError::new(status, message.content.clone()).with_detail("negotiation_message", message),
+        Err(e) => {
+            tracing::warn!("Failed to write {:?} message for negotiation {}: {}", kind, negotiation_id, e);
+            ApiError::new(status, format!("{:?}", kind))
+        }
+    }
+}
+
+/// Language the buyer asked to be written to in, from `Accept-Language`.
+fn buyer_language(headers: &HeaderMap) -> Option<Language> {
+    headers.get(header::ACCEPT_LANGUAGE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(',').find_map(|tag| tag.split(';').next()?.trim().parse().ok()))
 }
 
 async fn get_quote(
     State(state): State<AppState>,
     Path(rfq_id): Path<uuid::Uuid>,
-) -> Json<serde_json::Value> {
+) -> ApiResult<Json<Quote>> {
     // This would typically fetch the quote from the database
-    // For now, we'll return a mock response
-    Json(serde_json::json!({
-        "status": "error",
-        "message": "Quote not found"
-    }))
+    Err(NegotiationError::NotFound(format!("Quote for RFQ {}", rfq_id)).into())
 }
 
 async fn handle_negotiation(
-    State(_state): State<AppState>,
-    Path(_negotiation_id): Path<uuid::Uuid>,
+    State(state): State<AppState>,
+    Path(negotiation_id): Path<uuid::Uuid>,
+    headers: HeaderMap,
     Json(payload): Json<serde_json::Value>,
-) -> Json<serde_json::Value> {
+) -> ApiResult<Json<serde_json::Value>> {
+    let buyer_id = authorize_session(&state, &headers, negotiation_id).await?.agent_id()?;
+    let lock = lock_negotiation(&state, negotiation_id).await?;
+
     let counter_offer = payload.get("counter_offer")
-        .and_then(|v| v.as_f64())
-        .unwrap_or(0.0);
+        .and_then(|v| serde_json::from_value::<Decimal>(v.clone()).ok())
+        .unwrap_or_default();
+    let price = counter_offer * Decimal::new(95, 2);
+
+    // Buyers that predate price floors don't say what they're negotiating
+    let product_id = payload.get("product_id").and_then(|v| v.as_str());
+    if let Some(product_id) = product_id {
+        let quantity = payload.get("quantity").and_then(|v| v.as_u64()).unwrap_or(1) as u32;
+        if let Err(e) = state.seller_agent.check_offer(product_id, quantity, price) {
+            release_negotiation(lock, negotiation_id).await;
+            tracing::info!("Declined counter offer on negotiation {}: {}", negotiation_id, e);
+            return Err(refusal(&state, &headers, StatusCode::UNPROCESSABLE_ENTITY, TemplateKind::PriceTooLow, negotiation_id, &[
+                ("product_id", product_id.to_string()),
+                ("price", counter_offer.to_string()),
+            ]));
+        }
+    }
+
+    let ttl_seconds = match state.seller_agent.counter_ttl_seconds(product_id, buyer_id).await {
+        Ok(ttl_seconds) => ttl_seconds,
+        Err(e) => {
+            release_negotiation(lock, negotiation_id).await;
+            return Err(e.into());
+        }
+    };
 
     // Mock negotiation response
-    Json(serde_json::json!({
+    let response = serde_json::json!({
         "id": uuid::Uuid::new_v4(),
         "rfq_id": uuid::Uuid::new_v4(),
         "seller_id": uuid::Uuid::new_v4(),
-        "price": counter_offer * 0.95,
+        "price": price,
         "currency": "USD",
         "available_quantity": 1,
-        "ttl_seconds": 1800,
+        "ttl_seconds": ttl_seconds,
         "created_at": chrono::Utc::now(),
         "metadata": {}
-    }))
+    });
+    release_negotiation(lock, negotiation_id).await;
+    Ok(Json(response))
+}
+
+/// Receives a demand signal the seller subscribed to and answers it with an
+/// offer when a product fits.
+async fn handle_demand_signal(
+    State(state): State<AppState>,
+    Json(signal): Json<DemandSignal>,
+) -> ApiResult<Json<serde_json::Value>> {
+    let offer = state.seller_agent.respond_to_demand(&signal).await
+        .inspect_err(|e| tracing::error!("Failed to respond to demand signal {}: {}", signal.id, e))?;
+    if let Some(offer) = &offer {
+        tracing::info!("Offered {} for demand signal {}", offer.price, signal.id);
+    }
+    Ok(Json(serde_json::json!({ "status": "success", "offer": offer })))
+}
+
+/// Prices a rate card for the buyer to accept.
+async fn propose_agreement(
+    State(state): State<AppState>,
+    Json(request): Json<AgreementRequest>,
+) -> ApiResult<Json<serde_json::Value>> {
+    let agreement = state.seller_agent.propose_agreement(&request).await
+        .inspect_err(|e| tracing::warn!("Declined to propose agreement to {}: {}", request.buyer_id, e))?;
+    Ok(Json(serde_json::json!(agreement)))
+}
+
+async fn get_agreement(
+    State(state): State<AppState>,
+    Path(agreement_id): Path<uuid::Uuid>,
+    headers: HeaderMap,
+) -> ApiResult<Json<serde_json::Value>> {
+    let claims = authorize_session(&state, &headers, agreement_id).await?;
+    let agreement = state.seller_agent.get_agreement(agreement_id).await?;
+    if claims.agent_id().ok() != Some(agreement.buyer_id) {
+        return Err(ApiError::new(StatusCode::FORBIDDEN, "Agreement is with another buyer"));
+    }
+    Ok(Json(serde_json::json!(agreement)))
+}
+
+/// Accepts a proposed rate card on behalf of the buyer holding the session
+/// token.
+async fn accept_agreement(
+    State(state): State<AppState>,
+    Path(agreement_id): Path<uuid::Uuid>,
+    headers: HeaderMap,
+) -> ApiResult<Json<serde_json::Value>> {
+    let claims = authorize_session(&state, &headers, agreement_id).await?;
+    let buyer_id = claims.agent_id()?;
+    match state.seller_agent.accept_agreement(agreement_id, buyer_id).await {
+        Ok(agreement) => Ok(Json(serde_json::json!(agreement))),
+        // The buyer is authenticated, just not a party to this agreement
+        Err(e @ NegotiationError::Auth(_)) => Err(ApiError::from(e).with_status(StatusCode::FORBIDDEN)),
+        Err(e) => {
+            tracing::warn!("Failed to accept agreement {}: {}", agreement_id, e);
+            Err(e.into())
+        }
+    }
+}
+
+/// Answers a buyer's request to cancel or renegotiate an accepted deal.
+async fn review_deal_change(
+    State(state): State<AppState>,
+    Path(negotiation_id): Path<uuid::Uuid>,
+    headers: HeaderMap,
+    Json(change): Json<DealChange>,
+) -> ApiResult<Json<serde_json::Value>> {
+    authorize_session(&state, &headers, negotiation_id).await?;
+    if change.negotiation_id != negotiation_id {
+        return Err(ApiError::new(StatusCode::BAD_REQUEST, "Deal change is for another negotiation"));
+    }
+    let lock = lock_negotiation(&state, negotiation_id).await?;
+
+    let approved = cancellation::default_consent(&change);
+    tracing::info!("{} deal change {} on negotiation {}", if approved { "Approved" } else { "Declined" }, change.id, negotiation_id);
+    release_negotiation(lock, negotiation_id).await;
+    Ok(Json(serde_json::json!({ "approved": approved })))
 }
 
+/// Takes stock reserved for the buyer's RFQ off hand when their deal
+/// settles, or returns it when they reject the quote.
+async fn report_stock(
+    State(state): State<AppState>,
+    Path(negotiation_id): Path<uuid::Uuid>,
+    headers: HeaderMap,
+    Json(update): Json<StockUpdate>,
+) -> ApiResult<Response> {
+    let claims = authorize_session(&state, &headers, negotiation_id).await?;
+    let buyer_id = claims.agent_id()?;
+    let result = match update.outcome {
+        StockOutcome::Settled => state.seller_agent.commit_stock(update.rfq_id, buyer_id).await,
+        StockOutcome::Released => state.seller_agent.release_stock(update.rfq_id, buyer_id).await,
+    };
+    match result {
+        // A settled deal is confirmed to the buyer in their language
+        Ok(()) if update.outcome == StockOutcome::Settled => {
+            let reference = [("reference", negotiation_id.to_string())];
+            match state.seller_agent.compose_message(TemplateKind::Acceptance, negotiation_id, buyer_language(&headers), &reference) {
+                Ok(message) => Ok(Json(message).into_response()),
+                Err(e) => {
+                    tracing::warn!("Failed to write order confirmation for negotiation {}: {}", negotiation_id, e);
+                    Ok(StatusCode::NO_CONTENT.into_response())
+                }
+            }
+        }
+        Ok(()) => Ok(StatusCode::NO_CONTENT.into_response()),
+        Err(e @ NegotiationError::Auth(_)) => {
+            tracing::warn!("Refused stock update on negotiation {}: {}", negotiation_id, e);
+            Err(ApiError::from(e).with_status(StatusCode::FORBIDDEN))
+        }
+        Err(e) => {
+            tracing::warn!("Failed to apply stock update on negotiation {}: {}", negotiation_id, e);
+            Err(ApiError::from(e).with_status(StatusCode::CONFLICT))
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Default)]
+struct RevokeRequest {
+    /// Revokes a single token; all tokens for the negotiation when omitted
+    jti: Option<uuid::Uuid>,
+}
+
+async fn revoke_session_tokens(
+    State(state): State<AppState>,
+    Path(negotiation_id): Path<uuid::Uuid>,
+    headers: HeaderMap,
+    request: Option<Json<RevokeRequest>>,
+) -> ApiResult<Json<serde_json::Value>> {
+    authorize_session(&state, &headers, negotiation_id).await?;
+
+    let Json(request) = request.unwrap_or_default();
+    state.database.revoke_session_tokens(negotiation_id, request.jti).await?;
+    Ok(Json(serde_json::json!({
+        "status": "success",
+        "message": "Session tokens revoked"
+    })))
+}
+
+#[derive(serde::Deserialize)]
+struct RefreshRequest {
+    refresh_token: String,
+}
+
+async fn refresh_tokens(
+    State(state): State<AppState>,
+    Json(request): Json<RefreshRequest>,
+) -> ApiResult<Json<TokenPair>> {
+    match state.auth.lock().await.refresh_tokens(&request.refresh_token).await {
+        Ok(tokens) => Ok(Json(tokens)),
+        Err(e) => {
+            tracing::warn!("Rejected refresh token: {}", e);
+            Err(ApiError::from(e).with_status(StatusCode::UNAUTHORIZED))
+        }
+    }
+}
+
+/// Signed attestations of the reputations this seller earned, for its
+/// gossip peers. 404 when no gossip signing key is configured.
+async fn get_reputation_attestations(
+    State(state): State<AppState>,
+) -> ApiResult<Json<Attestations>> {
+    let trust = state.auth.lock().await;
+    match state.gossip.attestations(&trust).await {
+        Ok(attestations) => Ok(Json(Attestations { attestations })),
+        Err(NegotiationError::Config(e)) => Err(ApiError::new(StatusCode::NOT_FOUND, e)),
+        Err(e) => {
+            tracing::error!("Failed to attest reputations: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+/// Takes the buyer's agent JWT as who sent the RFQ, refusing roles that may
+/// not request quotes.
+async fn authenticate_buyer(State(state): State<AppState>, mut request: Request, next: Next) -> ApiResult<Response> {
+    let token = bearer_token(request.headers())
+        .ok_or_else(|| NegotiationError::Auth("Missing bearer token".to_string()))?;
+    let claims = state.auth.lock().await.authorize(token, Permission::RequestQuotes).await
+        .inspect_err(|e| tracing::warn!("Rejected agent token on RFQ: {}", e))?;
+    request.extensions_mut().insert(Caller(claims.sub));
+    Ok(next.run(request).await)
+}
+
+async fn authenticate_session(
+    State(state): State<AppState>,
+    Path(negotiation_id): Path<uuid::Uuid>,
+    mut request: Request,
+    next: Next,
+) -> ApiResult<Response> {
+    let claims = authorize_session(&state, request.headers(), negotiation_id).await?;
+    request.extensions_mut().insert(Caller(claims.sub));
+    Ok(next.run(request).await)
+}
+
+/// Checks the negotiation session token and that the role it was issued to
+/// may negotiate.
+async fn authorize_session(state: &AppState, headers: &HeaderMap, negotiation_id: uuid::Uuid) -> ApiResult<SessionClaims> {
+    let claims = match state.session_tokens.authorize(&state.database, headers, negotiation_id).await {
+        Ok(claims) => claims,
+        Err(e) => {
+            tracing::warn!("Rejected session token for negotiation {}: {}", negotiation_id, e);
+            return Err(ApiError::from(e).with_status(StatusCode::UNAUTHORIZED));
+        }
+    };
+    if let Err(e) = state.auth.lock().await.roles().check(&claims.role, Permission::Negotiate) {
+        tracing::warn!("Refused session token for negotiation {}: {}", negotiation_id, e);
+        return Err(e.into());
+    }
+    Ok(claims)
+}
+
+/// Keeps replicas from acting on the same negotiation at once. Answers 409
+/// Conflict when another request holds it for longer than `[shared_state]
+/// lock_wait_ms`.
+async fn lock_negotiation(state: &AppState, negotiation_id: uuid::Uuid) -> ApiResult<SharedLock> {
+    match state.shared.lock_negotiation(negotiation_id).await {
+        Ok(lock) => Ok(lock),
+        Err(e @ NegotiationError::Negotiation(_)) => {
+            tracing::warn!("Negotiation {} is busy: {}", negotiation_id, e);
+            Err(e.into())
+        }
+        Err(e) => {
+            tracing::error!("Failed to lock negotiation {}: {}", negotiation_id, e);
+            Err(StatusCode::SERVICE_UNAVAILABLE.into())
+        }
+    }
+}
+
+async fn release_negotiation(lock: SharedLock, negotiation_id: uuid::Uuid) {
+    if let Err(e) = lock.release().await {
+        tracing::warn!("Failed to release lock on negotiation {}: {}", negotiation_id, e);
+    }
+}
+
+/// The seller's products, with the stock not held for open quotes.
 async fn list_products(
     State(state): State<AppState>,
-) -> Json<serde_json::Value> {
-    // In a real implementation, this would query the database
-    // For now, return mock products
-    Json(serde_json::json!([
-        {
-            "id": "laptop-001",
-            "name": "Gaming Laptop",
-            "description": "High-performance gaming laptop with RTX 4080",
-            "category": "Electronics",
-            "base_price": 2499.99,
-            "currency": "USD",
-            "stock_quantity": 10
-        },
-        {
-            "id": "phone-001",
-            "name": "Smartphone Pro",
-            "description": "Latest flagship smartphone with 5G",
-            "category": "Electronics",
-            "base_price": 1299.99,
-            "currency": "USD",
-            "stock_quantity": 25
+) -> Json<Vec<Product>> {
+    let mut products = state.seller_agent.products();
+    for product in &mut products {
+        match state.seller_agent.available_stock(&product.id).await {
+            Ok(available) => product.stock_quantity = available,
+            Err(e) => tracing::warn!("Failed to read stock of {}: {}", product.id, e),
         }
-    ]))
+    }
+    Json(products)
+}
+
+/// The seller's in-memory state, for a standby taking over from it.
+async fn export_state(
+    State(state): State<AppState>,
+) -> Json<SellerState> {
+    Json(state.seller_agent.export_state())
+}
+
+/// The seller as registries list it, for buyers finding it through DNS
+async fn describe_agent(State(state): State<AppState>) -> Json<AgentInfo> {
+    Json(state.seller_agent.agent_info())
 }
 
 async fn health_check() -> Json<serde_json::Value> {
     Json(serde_json::json!({"status": "healthy"}))
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dcap::{
+        config::{CalendarConfig, CarrierConfig, GossipConfig, PricingConfig, PricingRuleConfig, QuoteTtlConfig, ShippingConfig, TaxConfig},
+        tax::TaxBreakdown,
+    };
+    use tempfile::NamedTempFile;
+
+    fn laptop() -> Product {
+        Product {
+            id: "laptop-001".to_string(),
+            name: "Gaming Laptop".to_string(),
+            description: String::new(),
+            category: "Electronics".to_string(),
+            base_price: Decimal::from(1000),
+            currency: "USD".to_string(),
+            stock_quantity: 10,
+            metadata: HashMap::new(),
+        }
+    }
+
+    /// Pricing that marks the laptop up by `multiplier` and nothing else
+    fn flat_pricing(multiplier: Decimal) -> PricingConfig {
+        PricingConfig {
+            default: vec![],
+            products: HashMap::from([("laptop-001".to_string(), vec![PricingRuleConfig::Flat { multiplier }])]),
+            ..Default::default()
+        }
+    }
+
+    fn seller_config(pricing: PricingConfig) -> SellerAgentConfig {
+        SellerAgentConfig {
+            agent_id: uuid::Uuid::new_v4(),
+            name: "TechSeller".to_string(),
+            endpoint: "http://seller:8001".to_string(),
+            products: vec![laptop()],
+            payment_methods: vec![],
+            llm_config: LLMConfig { model: "gpt-4".to_string(), api_key: String::new(), max_tokens: 1000, temperature: 0.7 },
+            calendar: Default::default(),
+            pricing,
+            quote_ttl: Default::default(),
+            quote_firmness: Default::default(),
+            locale: Default::default(),
+            preferred_languages: vec![],
+            compliance: Default::default(),
+            templates: Default::default(),
+            shipping: Default::default(),
+            tax: Default::default(),
+        }
+    }
+
+    /// The state `/quote` is served with, for a seller checking buyers
+    /// against a block list in its database
+    async fn app_state(config: SellerAgentConfig, database_file: &NamedTempFile) -> (AppState, BlockList) {
+        let database = Database::new(&format!("sqlite://{}", database_file.path().to_string_lossy())).await.unwrap();
+        let blocks = BlockList::new(database.clone(), BlockPolicy::default());
+        let trust = TrustSystem::new().unwrap().with_block_list(blocks.clone());
+        let session_tokens = trust.session_tokens();
+        let delegations = trust.delegation_tokens();
+        let seller_agent = SellerAgent::new(config.clone(), DiscoveryService::new(String::new()), trust).await.unwrap()
+            .with_min_buyer_reputation(0);
+        let shared = SharedState::in_memory();
+        let state = AppState {
+            seller_agent: Arc::new(seller_agent),
+            seller_agent_config: config,
+            database: database.clone(),
+            session_tokens,
+            delegations,
+            auth: Arc::new(tokio::sync::Mutex::new(TrustSystem::new().unwrap())),
+            gossip: Arc::new(ReputationGossip::from_config(&GossipConfig::default(), HttpClient::default()).unwrap()),
+            expiry: ExpiryReminders::new(database.clone(), 0),
+            shared: shared.clone(),
+            replay_guard: ReplayGuard::new(shared),
+            audit: AuditLog::new(database),
+        };
+        (state, blocks)
+    }
+
+    fn rfq(quantity: u32) -> RFQ {
+        RFQ::new(
+            uuid::Uuid::new_v4(),
+            "laptop-001".to_string(),
+            quantity,
+            Decimal::from(5000),
+            "USD".to_string(),
+            chrono::Utc::now() + chrono::Duration::hours(1),
+        )
+    }
+
+    async fn quote(state: &AppState, rfq: RFQ) -> ApiResult<Quote> {
+        let Json(quote) = handle_quote(State(state.clone()), HeaderMap::new(), Valid(rfq)).await?;
+        Ok(serde_json::from_value(quote).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_quotes_are_priced_by_the_pricing_policy() {
+        let database_file = NamedTempFile::new().unwrap();
+        let (state, _) = app_state(seller_config(flat_pricing(Decimal::new(110, 2))), &database_file).await;
+
+        let quote = quote(&state, rfq(2)).await.unwrap();
+        assert_eq!(quote.price, Decimal::from(2200));
+        assert_eq!(quote.seller_id, state.seller_agent_config.agent_id);
+        assert!(quote.signature.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_blocked_buyers_are_not_quoted() {
+        let database_file = NamedTempFile::new().unwrap();
+        let (state, blocks) = app_state(seller_config(PricingConfig::default()), &database_file).await;
+        let blocked = rfq(1);
+        blocks.blacklist(blocked.buyer_id, "chargebacks", None).await.unwrap();
+
+        let refused = quote(&state, blocked).await.unwrap_err();
+        assert_eq!(refused.status, StatusCode::FORBIDDEN);
+        assert_eq!(state.seller_agent.available_stock("laptop-001").await.unwrap(), 10);
+        assert!(quote(&state, rfq(1)).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_quotes_and_counters_last_as_long_as_the_ttl_policy_says() {
+        let database_file = NamedTempFile::new().unwrap();
+        let config = SellerAgentConfig {
+            // Always open, so no TTL is stretched to the next opening
+            calendar: CalendarConfig {
+                open_hour: 0,
+                close_hour: 24,
+                business_days: ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"].iter().map(|d| d.to_string()).collect(),
+                ..Default::default()
+            },
+            quote_ttl: QuoteTtlConfig { quote_seconds: 900, counter_seconds: 600, ..Default::default() },
+            ..seller_config(PricingConfig::default())
+        };
+        let (state, _) = app_state(config, &database_file).await;
+        let rfq = rfq(1);
+        let buyer_id = rfq.buyer_id;
+        assert_eq!(quote(&state, rfq).await.unwrap().ttl_seconds, 900);
+
+        let negotiation_id = uuid::Uuid::new_v4();
+        let trust = TrustSystem::new().unwrap();
+        let session_token = trust.issue_session_token(&trust.generate_jwt(buyer_id).await.unwrap(), negotiation_id).await.unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, format!("Bearer {}", session_token).parse().unwrap());
+        let counter = serde_json::json!({ "counter_offer": 900, "product_id": "laptop-001", "quantity": 1 });
+        let Json(counter) = handle_negotiation(State(state.clone()), Path(negotiation_id), headers, Json(counter)).await.unwrap();
+        assert_eq!(counter["ttl_seconds"], 600);
+    }
+
+    #[tokio::test]
+    async fn test_quotes_carry_the_taxes_due_where_they_deliver() {
+        let database_file = NamedTempFile::new().unwrap();
+        let config = SellerAgentConfig {
+            tax: TaxConfig { rate: Decimal::new(10, 2), ..Default::default() },
+            ..seller_config(flat_pricing(Decimal::ONE))
+        };
+        let (state, _) = app_state(config, &database_file).await;
+        let mut rfq = rfq(1);
+        rfq.delivery_location = Some("US-CA".to_string());
+
+        let quote = quote(&state, rfq).await.unwrap();
+        let tax = TaxBreakdown::from_metadata(&quote.metadata).unwrap().unwrap();
+        assert_eq!(tax.total(), Decimal::from(100));
+    }
+
+    #[tokio::test]
+    async fn test_quotes_offer_the_carriers_delivering_there() {
+        let database_file = NamedTempFile::new().unwrap();
+        let carrier = |name: &str, locations: &[&str]| CarrierConfig {
+            carrier: name.to_string(),
+            base_cost: Decimal::from(12),
+            per_unit_cost: Decimal::ZERO,
+            eta_days: 3,
+            locations: locations.iter().map(|location| location.to_string()).collect(),
+        };
+        let config = SellerAgentConfig {
+            shipping: ShippingConfig { carriers: vec![carrier("UPS", &[]), carrier("DHL", &["DE"])] },
+            ..seller_config(PricingConfig::default())
+        };
+        let (state, _) = app_state(config, &database_file).await;
+        let mut rfq = rfq(1);
+        rfq.delivery_location = Some("US-CA".to_string());
+
+        let quote = quote(&state, rfq).await.unwrap();
+        assert_eq!(quote.shipping_options.iter().map(|option| option.carrier.as_str()).collect::<Vec<_>>(), ["UPS"]);
+    }
+
+    #[tokio::test]
+    async fn test_reloaded_pricing_and_reputation_apply_to_served_quotes() {
+        let database_file = NamedTempFile::new().unwrap();
+        let (state, _) = app_state(seller_config(flat_pricing(Decimal::ONE)), &database_file).await;
+        let config_file = NamedTempFile::new().unwrap();
+        let mut config = AppConfig::default();
+        config.trust.jwt_secret = Some("test-secret".to_string());
+        config.trust.min_reputation_threshold = Some(0);
+        config.pricing = flat_pricing(Decimal::ONE);
+        std::fs::write(config_file.path(), toml::to_string(&config).unwrap()).unwrap();
+        let reloader = ConfigReloader::new(ConfigSource::new(Some(config_file.path())), config.clone());
+        follow_reloads(state.seller_agent.clone(), reloader.subscribe());
+        assert_eq!(quote(&state, rfq(1)).await.unwrap().price, Decimal::from(1000));
+
+        config.pricing = flat_pricing(Decimal::new(120, 2));
+        std::fs::write(config_file.path(), toml::to_string(&config).unwrap()).unwrap();
+        assert!(reloader.reload().unwrap());
+        tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            while quote(&state, rfq(1)).await.unwrap().price != Decimal::from(1200) {
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+        }).await.unwrap();
+
+        config.trust.min_reputation_threshold = Some(101);
+        std::fs::write(config_file.path(), toml::to_string(&config).unwrap()).unwrap();
+        assert!(reloader.reload().unwrap());
+        tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            while quote(&state, rfq(1)).await.is_ok() {
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+        }).await.unwrap();
+        assert_eq!(quote(&state, rfq(1)).await.unwrap_err().status, StatusCode::FORBIDDEN);
+    }
+}
@@ -1,18 +1,51 @@
 use dcap::{
-    error::Result,
+    analytics::{self, AnalyticsQuery, MarketAnalytics},
+    anchoring::{AnchorBatch, AnchoringConfig, AnchoringService, InclusionProof},
+    anomaly::{Anomaly, AnomalyDetector, AnomalyQuery},
+    artifacts::{ArtifactRef, ArtifactStore},
+    concession::{ConcessionAnalytics, ConcessionQuery, ConcessionRecord},
+    database::Database,
+    currency::CurrencyConverter,
+    fees::{FeeBreakdown, PlatformFee},
+    tax::FlatRateTaxCalculator,
+    dead_letter,
+    delegation::DelegationTokens,
+    error::{ApiError, ApiResult},
+    lifecycle::{self, Readiness},
+    metrics::Metrics,
     model::PaymentMethod,
-    settlement::{PaymentRequest, PaymentResult, SettlementConfig, SettlementService},
+    money::Money,
+    order::Order,
+    privacy::PrivacyFilter,
+    replay::{self, NegotiationReplay},
+    protocol,
+    reload::ConfigSource,
+    roles::{self, Permission},
+    runtime,
+    security,
+    session::SessionTokens,
+    solana_escrow::{self, SolanaEscrow, SolanaEscrowConfig},
+    settlement::{EscrowHold, PaymentRecord, PaymentRequest, PaymentResult, Refund, RefundRequest, SettlementConfig, SettlementService, ShipmentProof},
+    telemetry,
+    trust::TrustSystem,
+    validation::Valid,
+    AgentId, NegotiationError, TransactionId,
 };
+#[cfg(feature = "lightning")]
+use dcap::lightning::{LightningConfig, LndClient};
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
-    response::Json,
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware,
+    response::{IntoResponse, Json, Response},
     routing::{get, post},
     Router,
 };
 use clap::Parser;
+use serde::Deserialize;
 use std::collections::HashMap;
-use tokio::net::TcpListener;
+use std::sync::Arc;
+use tokio::{net::TcpListener, sync::RwLock};
 
 #[derive(Parser)]
 #[command(name = "settlement")]
@@ -21,6 +54,18 @@ struct Args {
     #[arg(short, long, default_value = "8002")]
     port: u16,
 
+    #[arg(short, long, default_value = "sqlite://settlement.db")]
+    database_url: String,
+
+    /// Config file with the `[metrics]`, `[artifacts]` and `[anomaly]`
+    /// settings; metrics are off without one
+    #[arg(short, long)]
+    config: Option<String>,
+
+    /// Overrides a config field, e.g. `--set logging.level=debug`; repeatable
+    #[arg(long = "set", value_name = "KEY=VALUE")]
+    overrides: Vec<String>,
+
     #[arg(long, env = "STRIPE_SECRET_KEY")]
     stripe_secret_key: Option<String>,
 
@@ -29,38 +74,292 @@ struct Args {
 
     #[arg(long, env = "ESCROW_SERVICE_URL")]
     escrow_service_url: Option<String>,
+
+    #[arg(long, env = "STRIPE_WEBHOOK_SECRET")]
+    webhook_secret: Option<String>,
+
+    #[arg(long)]
+    delivery_confirmation_timeout_seconds: Option<u64>,
+
+    /// Solana escrow program to place escrow holds on chain with, through
+    /// `--solana-rpc-url`; holds are only recorded without it
+    #[arg(long, env = "ESCROW_PROGRAM_ID")]
+    escrow_program_id: Option<String>,
+
+    /// Keypair the service signs escrow releases and refunds with
+    #[arg(long, env = "ESCROW_ARBITER_KEYPAIR")]
+    escrow_arbiter_keypair: Option<String>,
+
+    /// Wallet escrow releases pay the platform's fee into; the arbiter's
+    /// when unset
+    #[arg(long, env = "ESCROW_FEE_WALLET")]
+    escrow_fee_wallet: Option<String>,
+
+    #[arg(long, default_value_t = solana_escrow::DEFAULT_CONFIRMATION_TIMEOUT_SECONDS)]
+    escrow_confirmation_timeout_seconds: u64,
+
+    #[arg(long, default_value = "300")]
+    auto_confirm_interval_seconds: u64,
+
+    /// How often to ask providers about payments awaiting confirmation,
+    /// for those whose webhooks never arrived; 0 disables
+    #[arg(long, default_value = "15")]
+    payment_poll_interval_seconds: u64,
+
+    /// Anchoring gateway URL; negotiation records are anchored only when set
+    #[arg(long, env = "ANCHOR_ENDPOINT")]
+    anchor_endpoint: Option<String>,
+
+    #[arg(long, default_value = "solana")]
+    anchor_chain: String,
+
+    #[arg(long, default_value = "256")]
+    anchor_batch_size: i64,
+
+    /// How often to anchor completed-deal records; 0 disables
+    #[arg(long, default_value = "3600")]
+    anchor_interval_seconds: u64,
+
+    /// LND REST endpoint to take Lightning payments through
+    #[cfg(feature = "lightning")]
+    #[arg(long, env = "LND_REST_URL")]
+    lnd_rest_url: Option<String>,
+
+    /// Hex-encoded LND macaroon allowed to create and read invoices
+    #[cfg(feature = "lightning")]
+    #[arg(long, env = "LND_MACAROON", default_value = "")]
+    lnd_macaroon: String,
+
+    /// TLS certificate the LND node serves
+    #[cfg(feature = "lightning")]
+    #[arg(long, env = "LND_TLS_CERT")]
+    lnd_tls_cert: Option<String>,
+
+    #[cfg(feature = "lightning")]
+    #[arg(long, default_value_t = dcap::lightning::DEFAULT_INVOICE_EXPIRY_SECONDS)]
+    lightning_invoice_expiry_seconds: u64,
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    tracing_subscriber::fmt()
-        .with_max_level(tracing::Level::INFO)
-        .init();
+/// Wait before resubscribing to a Lightning node's invoices
+#[cfg(feature = "lightning")]
+const LIGHTNING_RESUBSCRIBE_DELAY: std::time::Duration = std::time::Duration::from_secs(5);
 
+#[tokio::main]
+async fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
     let args = Args::parse();
 
+    let app_config = ConfigSource::new(args.config.as_deref()).with_overrides(args.overrides.clone()).load()?;
+    app_config.validate()?;
+    let _telemetry = telemetry::init("settlement", &app_config.logging)?;
+    let metrics = Metrics::new();
+
     let config = SettlementConfig {
         stripe_secret_key: args.stripe_secret_key,
-        solana_rpc_url: args.solana_rpc_url,
+        solana_rpc_url: args.solana_rpc_url.clone(),
         escrow_service_url: args.escrow_service_url,
+        webhook_secret: args.webhook_secret,
+        delivery_confirmation_timeout_seconds: args.delivery_confirmation_timeout_seconds,
     };
 
-    let settlement_service = SettlementService::new(config).await?;
-    let app_state = AppState { settlement_service };
+    let database = lifecycle::wait_for_database(&args.database_url, &app_config.lifecycle).await?;
+    let anchoring_service = match args.anchor_endpoint {
+        Some(endpoint) => Some(AnchoringService::from_config(database.clone(), &AnchoringConfig {
+            chain: args.anchor_chain,
+            endpoint,
+            batch_size: args.anchor_batch_size,
+            interval_seconds: args.anchor_interval_seconds,
+        })?),
+        None => None,
+    };
+    let settlement_service = SettlementService::new(config, database.clone()).await?
+        .with_cancellation_config(app_config.cancellation.clone())
+        .with_fees(app_config.fees.clone())
+        .with_tax_calculator(Arc::new(FlatRateTaxCalculator::from_config(&app_config.tax)?))
+        .with_metrics(metrics.clone());
+    let settlement_service = match (&args.escrow_program_id, &args.solana_rpc_url, &args.escrow_arbiter_keypair) {
+        (Some(program_id), Some(rpc_url), Some(arbiter_keypair_path)) => {
+            let solana_escrow = SolanaEscrow::new(&SolanaEscrowConfig {
+                rpc_url: rpc_url.clone(),
+                program_id: program_id.clone(),
+                arbiter_keypair_path: arbiter_keypair_path.clone(),
+                fee_wallet: args.escrow_fee_wallet.clone(),
+                confirmation_timeout_seconds: args.escrow_confirmation_timeout_seconds,
+            })?.with_currency_converter(CurrencyConverter::from_config(&app_config.currency)?);
+            tracing::info!("Placing escrow holds on chain with program {} as arbiter {}", program_id, solana_escrow.arbiter());
+            settlement_service.with_solana_escrow(solana_escrow)
+        }
+        (Some(_), _, _) => return Err("--escrow-program-id needs --solana-rpc-url and --escrow-arbiter-keypair".into()),
+        _ => settlement_service,
+    };
+    #[cfg(feature = "lightning")]
+    let settlement_service = match &args.lnd_rest_url {
+        Some(rest_url) => {
+            let client = LndClient::new(LightningConfig {
+                rest_url: rest_url.clone(),
+                macaroon_hex: args.lnd_macaroon.clone(),
+                tls_cert_path: args.lnd_tls_cert.clone(),
+                invoice_expiry_seconds: args.lightning_invoice_expiry_seconds,
+            })?.with_currency_converter(CurrencyConverter::from_config(&app_config.currency)?);
+            settlement_service.with_lightning(client)
+        }
+        None => settlement_service,
+    };
+    let trust = TrustSystem::from_config(&app_config.trust)?;
+    let session_tokens = trust.session_tokens();
+    let delegations = trust.delegation_tokens();
+    let auth = Arc::new(RwLock::new(trust));
+    let app_state = AppState {
+        settlement_service: settlement_service.clone(),
+        anchoring_service: anchoring_service.clone(),
+        session_tokens,
+        delegations,
+        trust: auth.clone(),
+        privacy: PrivacyFilter::from_config(&app_config.privacy)?,
+        artifacts: ArtifactStore::from_config(database.clone(), &app_config.artifacts)?,
+        anomalies: AnomalyDetector::new(database.clone(), app_config.anomaly.clone()),
+        database: database.clone(),
+    };
+
+    // Periodically check payments providers have yet to confirm
+    if args.payment_poll_interval_seconds > 0 {
+        let payment_poll_interval = std::time::Duration::from_secs(args.payment_poll_interval_seconds);
+        let poller = settlement_service.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(payment_poll_interval);
+            loop {
+                interval.tick().await;
+                if let Err(e) = poller.poll_pending().await {
+                    tracing::error!("Failed to poll pending payments: {}", e);
+                }
+            }
+        });
+    }
+
+    // Follow the Lightning node's invoices, resubscribing whenever the stream drops
+    #[cfg(feature = "lightning")]
+    if args.lnd_rest_url.is_some() {
+        let watcher = settlement_service.clone();
+        tokio::spawn(async move {
+            loop {
+                if let Err(e) = watcher.watch_lightning_invoices().await {
+                    tracing::error!("Lightning invoice subscription failed: {}", e);
+                }
+                tokio::time::sleep(LIGHTNING_RESUBSCRIBE_DELAY).await;
+            }
+        });
+    }
 
+    // Periodically auto-confirm deliveries whose buyer confirmation window
+    // lapsed, and give back holds that expired before shipment
+    let auto_confirm_interval = std::time::Duration::from_secs(args.auto_confirm_interval_seconds);
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(auto_confirm_interval);
+        loop {
+            interval.tick().await;
+            if let Err(e) = settlement_service.auto_confirm_deliveries().await {
+                tracing::error!("Failed to auto-confirm deliveries: {}", e);
+            }
+            if let Err(e) = settlement_service.auto_confirm_payment_deliveries().await {
+                tracing::error!("Failed to auto-confirm pay-on-delivery orders: {}", e);
+            }
+            if let Err(e) = settlement_service.expire_escrows().await {
+                tracing::error!("Failed to expire escrow holds: {}", e);
+            }
+        }
+    });
+
+    // Periodically commit batches of completed-deal records on chain
+    if let Some(anchoring_service) = anchoring_service.filter(|_| args.anchor_interval_seconds > 0) {
+        let anchor_interval = std::time::Duration::from_secs(args.anchor_interval_seconds);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(anchor_interval);
+            loop {
+                interval.tick().await;
+                // Drain the backlog a batch at a time
+                loop {
+                    match anchoring_service.anchor_pending().await {
+                        Ok(Some(_)) => continue,
+                        Ok(None) => break,
+                        Err(e) => {
+                            tracing::error!("Failed to anchor negotiation records: {}", e);
+                            break;
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    // Periodically flag suspicious pricing and reputation activity
+    if app_config.anomaly.interval_seconds > 0 {
+        let detector = app_state.anomalies.clone();
+        let anomaly_interval = std::time::Duration::from_secs(app_config.anomaly.interval_seconds);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(anomaly_interval);
+            loop {
+                interval.tick().await;
+                if let Err(e) = detector.analyze(chrono::Utc::now()).await {
+                    tracing::error!("Failed to analyze activity for anomalies: {}", e);
+                }
+            }
+        });
+    }
+
+    // Operators read the market's history, act on failed deliveries and
+    // settle refunds sellers leave waiting
+    let view = Router::new()
+        .route("/admin/negotiations/:negotiation_id/replay", get(replay_negotiation))
+        .route("/admin/negotiations/:negotiation_id/concessions", get(get_concessions))
+        .route("/admin/anomalies", get(list_anomalies))
+        .route("/admin/sellers/:seller_id/fees", get(list_seller_fees));
+    let administer = Router::new()
+        .route("/admin/refunds/:refund_id/approve", post(approve_refund_as_operator))
+        .route("/admin/refunds/:refund_id/decline", post(decline_refund_as_operator));
     let app = Router::new()
         .route("/payment", post(create_payment))
         .route("/payment/:payment_id/status", get(get_payment_status))
         .route("/payment/:payment_id/refund", post(refund_payment))
+        .route("/payment/:payment_id/refunds", get(list_refunds))
+        .route("/payment/:payment_id/refunds/:refund_id/approve", post(approve_refund))
+        .route("/payment/:payment_id/refunds/:refund_id/decline", post(decline_refund))
+        .route("/payment/:payment_id/fees", get(get_payment_fees))
+        .route("/payment/:payment_id/delivered", post(confirm_payment_delivery))
+        .route("/escrow/:escrow_id", get(get_escrow))
+        .route("/escrow/:escrow_id/shipment", post(submit_shipment_proof))
+        .route("/escrow/:escrow_id/confirm", post(confirm_delivery))
         .route("/escrow/:escrow_id/release", post(release_escrow))
+        .route("/orders/:order_id", get(get_order))
+        .route("/orders/:order_id/shipment", post(ship_order))
+        .route("/orders/:order_id/delivered", post(confirm_order_delivery))
         .route("/webhook/stripe", post(handle_stripe_webhook))
+        .route("/negotiations/:negotiation_id/revoke", post(revoke_session_tokens))
+        .route("/negotiations/:negotiation_id/order", get(get_negotiation_order))
+        .route("/negotiations/:negotiation_id/artifacts", get(list_artifacts))
+        .route("/negotiations/:negotiation_id/artifacts/:hash", get(get_artifact))
+        .route("/anchors", get(list_anchor_batches))
+        .route("/anchors/records/:record_id/proof", get(get_inclusion_proof))
+        .route("/analytics", get(get_market_analytics))
+        .route("/analytics/concessions", get(get_concession_analytics))
         .route("/health", get(health_check))
+        .merge(roles::require_permission(view, auth.clone(), Permission::ViewMarket))
+        .merge(roles::require_permission(administer, auth.clone(), Permission::Administer))
+        .merge(dead_letter::router(app_state.settlement_service.clone(), auth))
+        .layer(middleware::from_fn(protocol::protocol_version_layer))
         .with_state(app_state);
+    let readiness = Readiness::new();
+    let app = readiness.route(app);
+    let app = telemetry::trace_requests(app);
+    let app = if app_config.metrics.enabled { metrics.instrument(app) } else { app };
+    let app = security::harden(app, &app_config.server);
 
     let listener = TcpListener::bind(format!("0.0.0.0:{}", args.port)).await?;
     println!("Settlement service listening on {}", args.port);
 
-    axum::serve(listener, app).await?;
+    readiness.mark_ready();
+    runtime::serve(listener, app, &app_config.server).await?;
+
+    database.close().await;
+    println!("Settlement service stopped");
 
     Ok(())
 }
@@ -68,81 +367,451 @@ async fn main() -> Result<()> {
 #[derive(Clone)]
 struct AppState {
     settlement_service: SettlementService,
+    anchoring_service: Option<AnchoringService>,
+    session_tokens: SessionTokens,
+    delegations: DelegationTokens,
+    /// Charges sellers the reputation refunds they're at fault for cost
+    trust: Arc<RwLock<TrustSystem>>,
+    privacy: PrivacyFilter,
+    artifacts: ArtifactStore,
+    anomalies: AnomalyDetector,
+    database: Database,
+}
+
+/// Checks the request's negotiation session token, that its holder is one
+/// of `parties` and that the role it was issued to may settle.
+async fn authorize_session(
+    state: &AppState,
+    headers: &HeaderMap,
+    negotiation_id: TransactionId,
+    parties: &[AgentId],
+) -> ApiResult<AgentId> {
+    let (claims, agent_id) = state.session_tokens.authorize(&state.database, headers, negotiation_id).await
+        .and_then(|claims| claims.require_party(parties).map(|agent_id| (claims, agent_id)))
+        .map_err(|e| {
+            tracing::warn!("Rejected session token for negotiation {}: {}", negotiation_id, e);
+            ApiError::from(e).with_status(StatusCode::UNAUTHORIZED)
+        })?;
+    if let Err(e) = state.trust.read().await.roles().check(&claims.role, Permission::Settle) {
+        tracing::warn!("Refused session token for negotiation {}: {}", negotiation_id, e);
+        return Err(e.into());
+    }
+    Ok(agent_id)
+}
+
+async fn escrow_for_session(
+    state: &AppState,
+    headers: &HeaderMap,
+    escrow_id: uuid::Uuid,
+    party: impl Fn(&EscrowHold) -> Vec<AgentId>,
+) -> ApiResult<EscrowHold> {
+    let escrow_hold = state.settlement_service.get_escrow(escrow_id).await?;
+    authorize_session(state, headers, escrow_hold.transaction_id, &party(&escrow_hold)).await?;
+    Ok(escrow_hold)
 }
 
 async fn create_payment(
     State(state): State<AppState>,
-    Json(request): Json<serde_json::Value>,
-) -> Result<Json<PaymentResult>, StatusCode> {
-    let payment_request = serde_json::from_value::<PaymentRequest>(request.clone())
-        .map_err(|_| StatusCode::BAD_REQUEST)?;
+    headers: HeaderMap,
+    Valid(mut payment_request): Valid<PaymentRequest>,
+) -> ApiResult<Json<PaymentResult>> {
+    authorize_session(&state, &headers, payment_request.transaction_id, &[payment_request.buyer_id]).await?;
 
-    match state.settlement_service.process_payment(payment_request).await {
-        Ok(result) => Ok(Json(result)),
+    // Only a verified delegation token decides whose behalf the buyer pays on
+    let amount = Money::new(payment_request.amount, payment_request.currency.clone());
+    payment_request.delegation_chain = match state.delegations.from_headers(&headers, payment_request.buyer_id)
+        .and_then(|claims| claims.map(|claims| {
+            claims.authorize_settlement(&amount)?;
+            claims.delegation_chain()
+        }).transpose())
+    {
+        Ok(chain) => chain.unwrap_or_default(),
         Err(e) => {
-            tracing::error!("Failed to create payment: {}", e);
-            Err(StatusCode::BAD_REQUEST)
+            tracing::warn!("Refused payment for transaction {} outside its delegation: {}", payment_request.transaction_id, e);
+            return Err(ApiError::from(e).with_status(StatusCode::FORBIDDEN));
         }
-    }
+    };
+
+    let result = state.settlement_service.process_payment(payment_request).await
+        .inspect_err(|e| tracing::error!("Failed to create payment: {}", e))?;
+    Ok(Json(result))
 }
 
 async fn get_payment_status(
     State(state): State<AppState>,
     Path(payment_id): Path<String>,
-) -> Result<Json<serde_json::Value>, StatusCode> {
-    match state.settlement_service.get_payment_status(&payment_id).await {
-        Ok(status) => Ok(Json(serde_json::json!({
-            "payment_id": payment_id,
-            "status": status
-        }))),
-        Err(e) => {
-            tracing::error!("Failed to get payment status: {}", e);
-            Err(StatusCode::NOT_FOUND)
-        }
-    }
+    headers: HeaderMap,
+) -> ApiResult<Json<serde_json::Value>> {
+    let payment = state.settlement_service.get_payment(&payment_id).await?;
+    authorize_session(&state, &headers, payment.transaction_id, &[payment.buyer_id, payment.seller_id]).await?;
+
+    Ok(Json(serde_json::json!({
+        "payment_id": payment_id,
+        "status": payment.status
+    })))
 }
 
 async fn refund_payment(
     State(state): State<AppState>,
     Path(payment_id): Path<String>,
-) -> Result<Json<PaymentResult>, StatusCode> {
-    match state.settlement_service.refund_payment(&payment_id).await {
-        Ok(result) => Ok(Json(result)),
-        Err(e) => {
-            tracing::error!("Failed to refund payment: {}", e);
-            Err(StatusCode::BAD_REQUEST)
-        }
+    headers: HeaderMap,
+    Valid(refund_request): Valid<RefundRequest>,
+) -> ApiResult<Json<Refund>> {
+    let payment = state.settlement_service.get_payment(&payment_id).await?;
+    let requested_by = authorize_session(&state, &headers, payment.transaction_id, &[payment.buyer_id, payment.seller_id]).await?;
+
+    let refund = state.settlement_service.refund_payment(&payment_id, requested_by, refund_request).await
+        .inspect_err(|e| tracing::error!("Failed to refund payment: {}", e))?;
+    Ok(Json(refund))
+}
+
+/// The seller gives a refund the buyer asked for.
+async fn approve_refund(
+    State(state): State<AppState>,
+    Path((payment_id, refund_id)): Path<(String, String)>,
+    headers: HeaderMap,
+) -> ApiResult<Json<Refund>> {
+    let payment = refund_for_seller(&state, &headers, &payment_id, &refund_id).await?;
+    let refund = state.settlement_service.approve_refund(&refund_id, Some(payment.seller_id)).await
+        .inspect_err(|e| tracing::error!("Failed to approve refund: {}", e))?;
+    charge_refund_penalty(&state, &refund).await;
+    Ok(Json(refund))
+}
+
+async fn decline_refund(
+    State(state): State<AppState>,
+    Path((payment_id, refund_id)): Path<(String, String)>,
+    headers: HeaderMap,
+) -> ApiResult<Json<PaymentRecord>> {
+    let payment = refund_for_seller(&state, &headers, &payment_id, &refund_id).await?;
+    Ok(Json(state.settlement_service.decline_refund(&refund_id, Some(payment.seller_id)).await?))
+}
+
+/// Checks the seller's session token for a payment, and that the refund is one of it.
+async fn refund_for_seller(state: &AppState, headers: &HeaderMap, payment_id: &str, refund_id: &str) -> ApiResult<PaymentRecord> {
+    let payment = state.settlement_service.get_payment(payment_id).await?;
+    authorize_session(state, headers, payment.transaction_id, &[payment.seller_id]).await?;
+    let refund = state.settlement_service.get_payment(refund_id).await?;
+    if refund.refund_of.as_deref() != Some(payment_id) {
+        return Err(NegotiationError::NotFound(format!("Refund {}", refund_id)).into());
     }
+    Ok(payment)
+}
+
+async fn approve_refund_as_operator(State(state): State<AppState>, Path(refund_id): Path<String>) -> ApiResult<Json<Refund>> {
+    let refund = state.settlement_service.approve_refund(&refund_id, None).await?;
+    charge_refund_penalty(&state, &refund).await;
+    Ok(Json(refund))
+}
+
+async fn decline_refund_as_operator(State(state): State<AppState>, Path(refund_id): Path<String>) -> ApiResult<Json<PaymentRecord>> {
+    Ok(Json(state.settlement_service.decline_refund(&refund_id, None).await?))
+}
+
+async fn charge_refund_penalty(state: &AppState, refund: &Refund) {
+    if refund.reputation_penalty == 0 {
+        return;
+    }
+    let seller_id = refund.payment.seller_id;
+    if let Err(e) = state.trust.write().await
+        .record_reputation_change(seller_id, -(refund.reputation_penalty as i32), Some(refund.payment.amount)).await
+    {
+        tracing::error!("Failed to charge seller {} for refund {}: {}", seller_id, refund.refund.payment_id, e);
+    }
+}
+
+async fn list_refunds(
+    State(state): State<AppState>,
+    Path(payment_id): Path<String>,
+    headers: HeaderMap,
+) -> ApiResult<Json<Vec<PaymentRecord>>> {
+    let payment = state.settlement_service.get_payment(&payment_id).await?;
+    authorize_session(&state, &headers, payment.transaction_id, &[payment.buyer_id, payment.seller_id]).await?;
+
+    Ok(Json(state.settlement_service.get_refunds(&payment_id).await?))
+}
+
+async fn get_payment_fees(
+    State(state): State<AppState>,
+    Path(payment_id): Path<String>,
+    headers: HeaderMap,
+) -> ApiResult<Json<FeeBreakdown>> {
+    let payment = state.settlement_service.get_payment(&payment_id).await?;
+    authorize_session(&state, &headers, payment.transaction_id, &[payment.buyer_id, payment.seller_id]).await?;
+
+    Ok(Json(state.settlement_service.payment_fees(&payment).await?))
+}
+
+async fn confirm_payment_delivery(
+    State(state): State<AppState>,
+    Path(payment_id): Path<String>,
+    headers: HeaderMap,
+) -> ApiResult<Json<PaymentRecord>> {
+    let payment = state.settlement_service.get_payment(&payment_id).await?;
+    authorize_session(&state, &headers, payment.transaction_id, &[payment.buyer_id]).await?;
+
+    let payment = state.settlement_service.confirm_payment_delivery(&payment_id, payment.buyer_id).await
+        .inspect_err(|e| tracing::error!("Failed to confirm delivery: {}", e))?;
+    Ok(Json(payment))
 }
 
 async fn release_escrow(
     State(state): State<AppState>,
     Path(escrow_id): Path<uuid::Uuid>,
-) -> Result<Json<PaymentResult>, StatusCode> {
-    match state.settlement_service.release_escrow(escrow_id).await {
-        Ok(result) => Ok(Json(result)),
-        Err(e) => {
-            tracing::error!("Failed to release escrow: {}", e);
-            Err(StatusCode::BAD_REQUEST)
-        }
-    }
+    headers: HeaderMap,
+) -> ApiResult<Json<PaymentResult>> {
+    escrow_for_session(&state, &headers, escrow_id, |escrow_hold| vec![escrow_hold.buyer_id]).await?;
+
+    let result = state.settlement_service.release_escrow(escrow_id).await
+        .inspect_err(|e| tracing::error!("Failed to release escrow: {}", e))?;
+    Ok(Json(result))
+}
+
+#[derive(Deserialize)]
+struct ShipmentProofRequest {
+    seller_id: AgentId,
+    #[serde(flatten)]
+    proof: ShipmentProof,
+}
+
+#[derive(Deserialize)]
+struct ConfirmDeliveryRequest {
+    buyer_id: AgentId,
+}
+
+async fn get_escrow(
+    State(state): State<AppState>,
+    Path(escrow_id): Path<uuid::Uuid>,
+    headers: HeaderMap,
+) -> ApiResult<Json<EscrowHold>> {
+    let escrow_hold = escrow_for_session(&state, &headers, escrow_id, |escrow_hold| {
+        vec![escrow_hold.buyer_id, escrow_hold.seller_id]
+    }).await?;
+    Ok(Json(escrow_hold))
+}
+
+async fn submit_shipment_proof(
+    State(state): State<AppState>,
+    Path(escrow_id): Path<uuid::Uuid>,
+    headers: HeaderMap,
+    Json(request): Json<ShipmentProofRequest>,
+) -> ApiResult<Json<EscrowHold>> {
+    escrow_for_session(&state, &headers, escrow_id, |_| vec![request.seller_id]).await?;
+
+    let escrow_hold = state.settlement_service.submit_shipment_proof(escrow_id, request.seller_id, request.proof).await
+        .inspect_err(|e| tracing::error!("Failed to record shipment: {}", e))?;
+    Ok(Json(escrow_hold))
+}
+
+async fn confirm_delivery(
+    State(state): State<AppState>,
+    Path(escrow_id): Path<uuid::Uuid>,
+    headers: HeaderMap,
+    Json(request): Json<ConfirmDeliveryRequest>,
+) -> ApiResult<Json<EscrowHold>> {
+    escrow_for_session(&state, &headers, escrow_id, |_| vec![request.buyer_id]).await?;
+
+    let escrow_hold = state.settlement_service.confirm_delivery(escrow_id, request.buyer_id).await
+        .inspect_err(|e| tracing::error!("Failed to confirm delivery: {}", e))?;
+    Ok(Json(escrow_hold))
+}
+
+async fn get_order(
+    State(state): State<AppState>,
+    Path(order_id): Path<uuid::Uuid>,
+    headers: HeaderMap,
+) -> ApiResult<Json<Order>> {
+    let order = state.settlement_service.orders().get(order_id).await?;
+    authorize_session(&state, &headers, order.negotiation_id, &[order.buyer_id, order.seller_id]).await?;
+    Ok(Json(order))
+}
+
+async fn get_negotiation_order(
+    State(state): State<AppState>,
+    Path(negotiation_id): Path<TransactionId>,
+    headers: HeaderMap,
+) -> ApiResult<Json<Order>> {
+    let order = state.settlement_service.orders().for_negotiation(negotiation_id).await?
+        .ok_or_else(|| NegotiationError::NotFound(format!("Order for negotiation {}", negotiation_id)))?;
+    authorize_session(&state, &headers, order.negotiation_id, &[order.buyer_id, order.seller_id]).await?;
+    Ok(Json(order))
+}
+
+/// Seller posts the carrier and tracking number, or corrects them.
+async fn ship_order(
+    State(state): State<AppState>,
+    Path(order_id): Path<uuid::Uuid>,
+    headers: HeaderMap,
+    Json(request): Json<ShipmentProofRequest>,
+) -> ApiResult<Json<Order>> {
+    let order = state.settlement_service.orders().get(order_id).await?;
+    authorize_session(&state, &headers, order.negotiation_id, &[request.seller_id]).await?;
+
+    let order = state.settlement_service.ship_order(order_id, request.seller_id, request.proof).await
+        .inspect_err(|e| tracing::error!("Failed to ship order: {}", e))?;
+    Ok(Json(order))
+}
+
+async fn confirm_order_delivery(
+    State(state): State<AppState>,
+    Path(order_id): Path<uuid::Uuid>,
+    headers: HeaderMap,
+    Json(request): Json<ConfirmDeliveryRequest>,
+) -> ApiResult<Json<Order>> {
+    let order = state.settlement_service.orders().get(order_id).await?;
+    authorize_session(&state, &headers, order.negotiation_id, &[request.buyer_id]).await?;
+
+    let order = state.settlement_service.confirm_order_delivery(order_id, request.buyer_id).await
+        .inspect_err(|e| tracing::error!("Failed to confirm order delivery: {}", e))?;
+    Ok(Json(order))
 }
 
 async fn handle_stripe_webhook(
     State(state): State<AppState>,
     headers: axum::http::HeaderMap,
     body: String,
-) -> Result<Json<serde_json::Value>, StatusCode> {
+) -> ApiResult<Json<serde_json::Value>> {
     let signature = headers
         .get("stripe-signature")
         .and_then(|h| h.to_str().ok())
         .unwrap_or("");
 
-    match state.settlement_service.handle_webhook(&body, signature).await {
-        Ok(_) => Ok(Json(serde_json::json!({"status": "received"}))),
-        Err(e) => {
+    // Stripe retries anything but a 2xx or 400
+    let event = state.settlement_service.handle_webhook(&body, signature).await
+        .map_err(|e| {
             tracing::error!("Failed to handle webhook: {}", e);
-            Err(StatusCode::BAD_REQUEST)
+            ApiError::from(e).with_status(StatusCode::BAD_REQUEST)
+        })?;
+    Ok(Json(serde_json::json!({
+        "status": "received",
+        "event_id": event.id,
+        "event_type": event.event_type
+    })))
+}
+
+async fn replay_negotiation(
+    State(state): State<AppState>,
+    Path(negotiation_id): Path<TransactionId>,
+) -> ApiResult<Json<NegotiationReplay>> {
+    replay::replay(&state.database, negotiation_id).await?
+        .map(Json)
+        .ok_or_else(|| NegotiationError::NotFound(format!("Negotiation {}", negotiation_id)).into())
+}
+
+async fn get_concessions(
+    State(state): State<AppState>,
+    Path(negotiation_id): Path<TransactionId>,
+) -> ApiResult<Json<ConcessionRecord>> {
+    Ok(Json(state.settlement_service.concessions().get(negotiation_id).await?))
+}
+
+async fn get_concession_analytics(
+    State(state): State<AppState>,
+    Query(query): Query<ConcessionQuery>,
+) -> ApiResult<Json<ConcessionAnalytics>> {
+    Ok(Json(state.settlement_service.concessions().analytics(&query).await?))
+}
+
+async fn list_anomalies(
+    State(state): State<AppState>,
+    Query(query): Query<AnomalyQuery>,
+) -> ApiResult<Json<Vec<Anomaly>>> {
+    Ok(Json(state.anomalies.list(&query).await?))
+}
+
+async fn list_seller_fees(
+    State(state): State<AppState>,
+    Path(seller_id): Path<AgentId>,
+) -> ApiResult<Json<Vec<PlatformFee>>> {
+    Ok(Json(state.settlement_service.get_seller_fees(seller_id).await?))
+}
+
+#[derive(Deserialize)]
+struct AnchorQuery {
+    limit: Option<i64>,
+}
+
+fn anchoring_service(state: &AppState) -> ApiResult<&AnchoringService> {
+    state.anchoring_service.as_ref()
+        .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, "Anchoring is not enabled"))
+}
+
+async fn list_anchor_batches(
+    State(state): State<AppState>,
+    Query(query): Query<AnchorQuery>,
+) -> ApiResult<Json<Vec<AnchorBatch>>> {
+    Ok(Json(anchoring_service(&state)?.list_batches(query.limit.unwrap_or(100)).await?))
+}
+
+async fn get_market_analytics(
+    State(state): State<AppState>,
+    Query(query): Query<AnalyticsQuery>,
+) -> ApiResult<Json<MarketAnalytics>> {
+    Ok(Json(analytics::market_analytics(&state.database, &query, &state.privacy).await?))
+}
+
+async fn get_inclusion_proof(
+    State(state): State<AppState>,
+    Path(record_id): Path<i64>,
+) -> ApiResult<Json<InclusionProof>> {
+    Ok(Json(anchoring_service(&state)?.inclusion_proof(record_id).await?))
+}
+
+#[derive(Deserialize, Default)]
+struct RevokeRequest {
+    /// Revokes a single token; all tokens for the negotiation when omitted
+    jti: Option<uuid::Uuid>,
+}
+
+/// Lets a party cut off session tokens for a negotiation, e.g. after a leak.
+async fn revoke_session_tokens(
+    State(state): State<AppState>,
+    Path(negotiation_id): Path<TransactionId>,
+    headers: HeaderMap,
+    request: Option<Json<RevokeRequest>>,
+) -> ApiResult<Json<serde_json::Value>> {
+    let claims = state.session_tokens.authorize(&state.database, &headers, negotiation_id).await
+        .map_err(|e| ApiError::from(e).with_status(StatusCode::UNAUTHORIZED))?;
+
+    let Json(request) = request.unwrap_or_default();
+    state.database.revoke_session_tokens(negotiation_id, request.jti).await?;
+    tracing::info!("Agent {} revoked session tokens for negotiation {}", claims.sub, negotiation_id);
+    Ok(Json(serde_json::json!({ "negotiation_id": negotiation_id, "revoked": true })))
+}
+
+/// Contracts, invoices and other documents attached to a negotiation, for
+/// holders of its session token.
+async fn list_artifacts(
+    State(state): State<AppState>,
+    Path(negotiation_id): Path<TransactionId>,
+    headers: HeaderMap,
+) -> ApiResult<Json<Vec<ArtifactRef>>> {
+    state.session_tokens.authorize(&state.database, &headers, negotiation_id).await
+        .map_err(|e| ApiError::from(e).with_status(StatusCode::UNAUTHORIZED))?;
+
+    Ok(Json(state.artifacts.for_negotiation(negotiation_id).await?))
+}
+
+/// The content of an artifact attached to the negotiation, checked against
+/// its hash.
+async fn get_artifact(
+    State(state): State<AppState>,
+    Path((negotiation_id, hash)): Path<(TransactionId, String)>,
+    headers: HeaderMap,
+) -> ApiResult<Response> {
+    state.session_tokens.authorize(&state.database, &headers, negotiation_id).await
+        .map_err(|e| ApiError::from(e).with_status(StatusCode::UNAUTHORIZED))?;
+
+    let not_found = || ApiError::from(NegotiationError::NotFound(format!("Artifact {}", hash)));
+    if !state.artifacts.is_attached(negotiation_id, &hash).await? {
+        return Err(not_found());
+    }
+    match state.artifacts.get(&hash).await {
+        Ok(Some((artifact, bytes))) => Ok(([(header::CONTENT_TYPE, artifact.content_type)], bytes).into_response()),
+        Ok(None) => Err(not_found()),
+        Err(e) => {
+            tracing::error!("Failed to read artifact {}: {}", hash, e);
+            Err(StatusCode::BAD_GATEWAY.into())
         }
     }
 }
@@ -0,0 +1,226 @@
+//! Agents barred from trading.
+//!
+//! Operators blacklist agents outright, for good or until a given time.
+//! Agents are greylisted automatically for a while once they pile up failed
+//! or disputed transactions: `greylist_after_failures` within
+//! `failure_window_seconds`, counting only failures since their last block
+//! ended. Sellers refuse RFQs from blocked buyers, and blacklisted sellers
+//! are left out of discovery searches. Blocks live in the database, so every
+//! service pointed at the same database enforces them.
+
+use crate::{
+    config::TrustConfig,
+    database::Database,
+    error::{NegotiationError, Result},
+    AgentId,
+};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_GREYLIST_AFTER_FAILURES: u32 = 3;
+pub const DEFAULT_FAILURE_WINDOW_SECONDS: u64 = 2_592_000;
+pub const DEFAULT_GREYLIST_SECONDS: u64 = 604_800;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockKind {
+    Blacklisted,
+    Greylisted,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentBlock {
+    pub id: uuid::Uuid,
+    pub agent_id: AgentId,
+    pub kind: BlockKind,
+    pub reason: String,
+    /// Open-ended when unset
+    pub blocked_until: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    /// When an operator unblocked the agent
+    pub lifted_at: Option<DateTime<Utc>>,
+}
+
+impl AgentBlock {
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        self.lifted_at.is_none() && self.blocked_until.is_none_or(|until| until > now)
+    }
+
+    /// When the block stopped applying, if it has
+    pub fn ended_at(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.lifted_at.or(self.blocked_until.filter(|until| *until <= now))
+    }
+}
+
+/// Body of `POST /admin/agents/:agent_id/blacklist`, and of suspending an
+/// agent through `dcap-admin`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlacklistRequest {
+    pub reason: String,
+    /// Blacklisted for good when unset
+    pub until: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockPolicy {
+    pub greylist_after_failures: u32,
+    pub failure_window: Duration,
+    pub greylist_duration: Duration,
+}
+
+impl Default for BlockPolicy {
+    fn default() -> Self {
+        Self {
+            greylist_after_failures: DEFAULT_GREYLIST_AFTER_FAILURES,
+            failure_window: Duration::seconds(DEFAULT_FAILURE_WINDOW_SECONDS as i64),
+            greylist_duration: Duration::seconds(DEFAULT_GREYLIST_SECONDS as i64),
+        }
+    }
+}
+
+impl BlockPolicy {
+    /// A threshold of 0 turns automatic greylisting off.
+    pub fn from_config(config: &TrustConfig) -> Self {
+        Self {
+            greylist_after_failures: config.greylist_after_failures.unwrap_or(DEFAULT_GREYLIST_AFTER_FAILURES),
+            failure_window: Duration::seconds(config.failure_window_seconds.unwrap_or(DEFAULT_FAILURE_WINDOW_SECONDS) as i64),
+            greylist_duration: Duration::seconds(config.greylist_seconds.unwrap_or(DEFAULT_GREYLIST_SECONDS) as i64),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct BlockList {
+    database: Database,
+    policy: BlockPolicy,
+}
+
+impl BlockList {
+    pub fn new(database: Database, policy: BlockPolicy) -> Self {
+        Self { database, policy }
+    }
+
+    /// Blacklists the agent until `until`, or for good, replacing any block
+    /// it's under.
+    pub async fn blacklist(&self, agent_id: AgentId, reason: &str, until: Option<DateTime<Utc>>) -> Result<AgentBlock> {
+        let now = Utc::now();
+        if reason.trim().is_empty() {
+            return Err(NegotiationError::Validation("A blacklisting needs a reason".to_string()));
+        }
+        if until.is_some_and(|until| until <= now) {
+            return Err(NegotiationError::Validation("A blacklisting must end in the future".to_string()));
+        }
+        self.database.lift_agent_blocks(agent_id, now).await?;
+        self.block(agent_id, BlockKind::Blacklisted, reason, until, now).await
+    }
+
+    /// Lifts the agent's blocks. Returns whether it was blocked.
+    pub async fn unblock(&self, agent_id: AgentId) -> Result<bool> {
+        let lifted = self.database.lift_agent_blocks(agent_id, Utc::now()).await?;
+        if lifted > 0 {
+            tracing::info!("Unblocked agent {}", agent_id);
+        }
+        Ok(lifted > 0)
+    }
+
+    pub async fn active_block(&self, agent_id: AgentId) -> Result<Option<AgentBlock>> {
+        self.database.get_active_agent_block(agent_id, Utc::now()).await
+    }
+
+    /// Blacklisted and greylisted agents, most recently blocked first
+    pub async fn active_blocks(&self) -> Result<Vec<AgentBlock>> {
+        self.database.get_active_agent_blocks(Utc::now()).await
+    }
+
+    /// Fails if the agent may not trade.
+    pub async fn ensure_not_blocked(&self, agent_id: AgentId) -> Result<()> {
+        match self.active_block(agent_id).await? {
+            Some(block) => Err(NegotiationError::Trust(format!(
+                "Agent {} is {}: {}", agent_id, format!("{:?}", block.kind).to_lowercase(), block.reason
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Counts a failed or disputed transaction against the agent, greylisting
+    /// it once it reaches the policy's threshold.
+    pub async fn record_failure(&self, agent_id: AgentId, reason: &str) -> Result<Option<AgentBlock>> {
+        let now = Utc::now();
+        self.database.record_agent_failure(agent_id, reason, now).await?;
+        if self.policy.greylist_after_failures == 0 {
+            return Ok(None);
+        }
+
+        let last_block = self.database.get_latest_agent_block(agent_id).await?;
+        if last_block.as_ref().is_some_and(|block| block.is_active(now)) {
+            return Ok(None);
+        }
+        let window_start = now - self.policy.failure_window;
+        let since = last_block.and_then(|block| block.ended_at(now))
+            .map_or(window_start, |ended| ended.max(window_start));
+        let failures = self.database.count_agent_failures_since(agent_id, since).await?;
+        if failures < u64::from(self.policy.greylist_after_failures) {
+            return Ok(None);
+        }
+
+        let reason = format!("{} failed or disputed transactions, the last: {}", failures, reason);
+        let block = self.block(agent_id, BlockKind::Greylisted, &reason, Some(now + self.policy.greylist_duration), now).await?;
+        Ok(Some(block))
+    }
+
+    async fn block(
+        &self,
+        agent_id: AgentId,
+        kind: BlockKind,
+        reason: &str,
+        blocked_until: Option<DateTime<Utc>>,
+        now: DateTime<Utc>,
+    ) -> Result<AgentBlock> {
+        let block = AgentBlock {
+            id: uuid::Uuid::new_v4(),
+            agent_id,
+            kind,
+            reason: reason.to_string(),
+            blocked_until,
+            created_at: now,
+            lifted_at: None,
+        };
+        self.database.create_agent_block(&block).await?;
+        tracing::warn!("Agent {} {:?} until {:?}: {}", agent_id, block.kind, blocked_until, reason);
+        Ok(block)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::NamedTempFile;
+
+    #[tokio::test]
+    async fn test_failures_greylist_and_blacklisting_is_lifted() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let database = Database::new(&format!("sqlite://{}", temp_file.path().to_string_lossy())).await.unwrap();
+        let blocks = BlockList::new(database, BlockPolicy { greylist_after_failures: 2, ..BlockPolicy::default() });
+        let agent_id = uuid::Uuid::new_v4();
+
+        assert!(blocks.record_failure(agent_id, "refund").await.unwrap().is_none());
+        blocks.ensure_not_blocked(agent_id).await.unwrap();
+        let greylisted = blocks.record_failure(agent_id, "reneged").await.unwrap().unwrap();
+        assert_eq!(greylisted.kind, BlockKind::Greylisted);
+        assert!(greylisted.blocked_until.is_some());
+        assert!(blocks.ensure_not_blocked(agent_id).await.is_err());
+
+        // Failures before an unblock don't count towards the next greylisting
+        assert!(blocks.unblock(agent_id).await.unwrap());
+        blocks.ensure_not_blocked(agent_id).await.unwrap();
+        assert!(blocks.record_failure(agent_id, "refund").await.unwrap().is_none());
+
+        let blacklisted = blocks.blacklist(agent_id, "fraud", None).await.unwrap();
+        assert_eq!(blocks.active_block(agent_id).await.unwrap().unwrap().id, blacklisted.id);
+        assert_eq!(blocks.active_blocks().await.unwrap().len(), 1);
+        assert!(blocks.blacklist(agent_id, "fraud", Some(Utc::now() - Duration::hours(1))).await.is_err());
+        assert!(blocks.unblock(agent_id).await.unwrap());
+        assert!(!blocks.unblock(agent_id).await.unwrap());
+        assert!(blocks.active_blocks().await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,282 @@
+//! RFQ broadcasts: one RFQ fanned out to every seller following a category.
+//!
+//! Sellers subscribe to categories as they do for demand signals. A buyer
+//! posts an RFQ with the category it falls under to the discovery service,
+//! which pushes it to the `/quote` endpoint of each seller subscribed to that
+//! category or one above it, and keeps the quotes they answer with. The
+//! buyer gets the broadcast's ID back straight away and polls it for quotes
+//! while the fan-out runs, then negotiates with the seller it picks as usual.
+//!
+//! The buyer stamps and signs the RFQ before posting it, and it goes out to
+//! sellers exactly as signed, so each can check it came from the buyer. It
+//! goes with the buyer's agent JWT too, which sellers check may request
+//! quotes.
+
+use crate::{
+    database::Database,
+    demand::DemandService,
+    error::{NegotiationError, Result},
+    model::{AgentInfo, AgentType, Quote, RFQ},
+    nonce::Stamped,
+    protocol::{CURRENT_VERSION, PROTOCOL_VERSION_HEADER},
+    recovery,
+    taxonomy::category_matches,
+};
+use chrono::{DateTime, Utc};
+use reqwest::Client;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+/// How long a seller has to answer a broadcast RFQ
+const SELLER_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BroadcastStatus {
+    /// Sellers are still being sent the RFQ
+    Sending,
+    /// Every subscribed seller was sent the RFQ
+    Sent,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RfqBroadcast {
+    pub id: uuid::Uuid,
+    pub category: String,
+    pub rfq: RFQ,
+    pub status: BroadcastStatus,
+    /// Subscribed sellers the RFQ was pushed to
+    pub sellers_notified: u32,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BroadcastRfqRequest {
+    /// Sellers subscribed to this category or one above it are sent the RFQ
+    pub category: String,
+    pub rfq: RFQ,
+}
+
+/// A broadcast with the quotes sellers answered it with so far, cheapest first
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BroadcastView {
+    pub broadcast: RfqBroadcast,
+    pub quotes: Vec<Quote>,
+}
+
+#[derive(Clone)]
+pub struct BroadcastService {
+    database: Database,
+    demand: DemandService,
+    client: Client,
+}
+
+impl BroadcastService {
+    pub fn new(database: Database) -> Self {
+        Self {
+            demand: DemandService::new(database.clone()),
+            database,
+            client: Client::new(),
+        }
+    }
+
+    /// Records a broadcast for `fan_out` to send. `category` is expected
+    /// filed under the registry's taxonomy already.
+    pub async fn open(&self, request: BroadcastRfqRequest, now: DateTime<Utc>) -> Result<RfqBroadcast> {
+        let rfq = request.rfq;
+        if request.category.trim().is_empty() {
+            return Err(NegotiationError::Validation("Category is required".to_string()));
+        }
+        let buyer = self.database.get_agent(rfq.buyer_id).await?
+            .ok_or(NegotiationError::AgentNotFound(rfq.buyer_id))?;
+        if !matches!(buyer.agent_type, AgentType::Buyer) {
+            return Err(NegotiationError::Validation("Only buyers can broadcast RFQs".to_string()));
+        }
+        crate::currency::validate_currency_code(&rfq.currency)?;
+        if rfq.quantity == 0 {
+            return Err(NegotiationError::Validation("Quantity must be greater than 0".to_string()));
+        }
+        if rfq.max_price <= Decimal::ZERO {
+            return Err(NegotiationError::Validation("Max price must be greater than 0".to_string()));
+        }
+        if rfq.deadline <= now {
+            return Err(NegotiationError::Validation("RFQ deadline has passed".to_string()));
+        }
+        let (Some(_), Some(signature)) = (&rfq.nonce, &rfq.signature) else {
+            return Err(NegotiationError::Validation("Broadcast RFQs must be stamped and signed by the buyer".to_string()));
+        };
+        recovery::verify_signature(&buyer.public_key, &rfq.signed_payload()?, signature)?;
+
+        let broadcast = RfqBroadcast {
+            id: uuid::Uuid::new_v4(),
+            category: request.category.trim().to_string(),
+            rfq,
+            status: BroadcastStatus::Sending,
+            sellers_notified: 0,
+            created_at: now,
+        };
+        self.database.create_rfq_broadcast(&broadcast).await?;
+        tracing::info!("RFQ broadcast {} opened for {} by buyer {}", broadcast.id, broadcast.category, broadcast.rfq.buyer_id);
+        Ok(broadcast)
+    }
+
+    /// Sellers subscribed to the broadcast's category or one above it, other
+    /// than the buyer.
+    pub async fn subscribers(&self, broadcast: &RfqBroadcast) -> Result<Vec<AgentInfo>> {
+        let mut sellers: Vec<AgentInfo> = Vec::new();
+        for subscription in self.demand.subscriptions().await? {
+            if !category_matches(&broadcast.category, &subscription.category)
+                || subscription.seller_id == broadcast.rfq.buyer_id
+                || sellers.iter().any(|seller| seller.id == subscription.seller_id)
+            {
+                continue;
+            }
+            if let Some(seller) = self.database.get_agent(subscription.seller_id).await? {
+                sellers.push(seller);
+            }
+        }
+        Ok(sellers)
+    }
+
+    /// Pushes the RFQ to every subscriber's `/quote` endpoint at once and
+    /// keeps the quotes they answer with. Sellers that decline or can't be
+    /// reached are skipped. `access_token` is the agent JWT the buyer
+    /// broadcast with.
+    pub async fn fan_out(&self, broadcast: &RfqBroadcast, access_token: &str) -> Result<RfqBroadcast> {
+        let sellers = self.subscribers(broadcast).await?;
+        let deliveries = sellers.iter().map(|seller| self.request_quote(seller, &broadcast.rfq, access_token));
+        for (seller, quote) in sellers.iter().zip(futures::future::join_all(deliveries).await) {
+            match quote {
+                Ok(quote) if quote.seller_id == seller.id && quote.rfq_id == broadcast.rfq.id => {
+                    self.database.add_rfq_broadcast_quote(broadcast.id, &quote).await?;
+                }
+                Ok(quote) => {
+                    tracing::warn!("Seller {} answered RFQ broadcast {} with a quote for {} from {}", seller.id, broadcast.id, quote.rfq_id, quote.seller_id);
+                }
+                Err(e) => {
+                    tracing::warn!("Seller {} didn't quote RFQ broadcast {}: {}", seller.id, broadcast.id, e);
+                }
+            }
+        }
+
+        let mut broadcast = broadcast.clone();
+        broadcast.status = BroadcastStatus::Sent;
+        broadcast.sellers_notified = sellers.len() as u32;
+        self.database.update_rfq_broadcast(&broadcast).await?;
+        Ok(broadcast)
+    }
+
+    async fn request_quote(&self, seller: &AgentInfo, rfq: &RFQ, access_token: &str) -> Result<Quote> {
+        let response = self.client
+            .post(format!("{}/quote", seller.endpoint))
+            .header(PROTOCOL_VERSION_HEADER, CURRENT_VERSION.to_string())
+            .bearer_auth(access_token)
+            .timeout(SELLER_TIMEOUT)
+            .json(rfq)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(response.json().await?)
+    }
+
+    /// The broadcast and the quotes it has drawn so far.
+    pub async fn get(&self, broadcast_id: uuid::Uuid) -> Result<BroadcastView> {
+        let broadcast = self.database.get_rfq_broadcast(broadcast_id).await?
+            .ok_or_else(|| NegotiationError::NotFound(format!("RFQ broadcast {}", broadcast_id)))?;
+        let mut quotes = self.database.get_rfq_broadcast_quotes(broadcast_id).await?;
+        quotes.sort_by_key(|quote| quote.price);
+        Ok(BroadcastView { broadcast, quotes })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{demand::SubscribeRequest, model::PaymentMethod, webhook::WebhookSigner, AgentId};
+    use axum::{routing::post, Json, Router};
+    use std::collections::HashMap;
+    use tempfile::NamedTempFile;
+
+    async fn agent(database: &Database, agent_type: AgentType, endpoint: &str, public_key: &str) -> AgentId {
+        let agent = AgentInfo {
+            id: uuid::Uuid::new_v4(),
+            agent_type,
+            name: "TechStore".to_string(),
+            endpoint: endpoint.to_string(),
+            public_key: public_key.to_string(),
+            reputation_score: 80,
+            products: vec![],
+            payment_methods: vec![PaymentMethod::Stripe],
+            protocol_versions: vec![],
+            preferred_languages: vec![],
+            tls_fingerprint: None,
+            created_at: Utc::now(),
+            last_active: Utc::now(),
+        };
+        database.create_agent(&agent).await.unwrap();
+        agent.id
+    }
+
+    /// A seller answering every RFQ with a quote at `price`
+    async fn quoting_seller(database: &Database, price: i64) -> AgentId {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let seller_id = agent(database, AgentType::Seller, &endpoint, "key").await;
+        let app = Router::new().route("/quote", post(move |headers: axum::http::HeaderMap, Json(rfq): Json<RFQ>| async move {
+            assert_eq!(crate::session::bearer_token(&headers), Some("buyer-token"));
+            Json(Quote::new(rfq.id, seller_id, Decimal::from(price), rfq.currency, rfq.quantity, 3600))
+        }));
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        seller_id
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_reaches_sellers_following_the_category() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let database = Database::new(&format!("sqlite://{}", temp_file.path().to_string_lossy())).await.unwrap();
+        let demand = DemandService::new(database.clone());
+        let service = BroadcastService::new(database.clone());
+        let signer = WebhookSigner::generate(uuid::Uuid::new_v4());
+        let buyer = agent(&database, AgentType::Buyer, "http://localhost:8003", &signer.public_key()).await;
+        let (cheap, dear) = (quoting_seller(&database, 900).await, quoting_seller(&database, 1100).await);
+        let (unreachable, elsewhere) = (
+            agent(&database, AgentType::Seller, "http://127.0.0.1:9", "key").await,
+            quoting_seller(&database, 500).await,
+        );
+        for (seller_id, category) in [(cheap, "Electronics"), (dear, "Laptops"), (unreachable, "Electronics"), (elsewhere, "Phones")] {
+            demand.subscribe(SubscribeRequest { seller_id, category: category.to_string() }).await.unwrap();
+        }
+
+        let now = Utc::now();
+        let rfq = RFQ {
+            id: uuid::Uuid::new_v4(),
+            buyer_id: buyer,
+            product_id: "laptop-001".to_string(),
+            quantity: 2,
+            max_price: Decimal::from(1200),
+            currency: "USD".to_string(),
+            delivery_location: None,
+            deadline: now + chrono::Duration::hours(1),
+            metadata: HashMap::new(),
+            nonce: None,
+            issued_at: None,
+            signature: None,
+        };
+        let request = |category: &str, rfq: &RFQ| BroadcastRfqRequest { category: category.to_string(), rfq: rfq.stamped(&signer).unwrap() };
+        assert!(service.open(request("Electronics > Laptops", &RFQ { buyer_id: cheap, ..rfq.clone() }), now).await.is_err());
+        assert!(service.open(request("Electronics > Laptops", &RFQ { deadline: now, ..rfq.clone() }), now).await.is_err());
+        // Only RFQs the buyer signed go out
+        assert!(service.open(BroadcastRfqRequest { category: "Electronics".to_string(), rfq: rfq.clone() }, now).await.is_err());
+        let forged = RFQ { max_price: Decimal::from(10), ..request("Electronics", &rfq).rfq };
+        assert!(service.open(BroadcastRfqRequest { category: "Electronics".to_string(), rfq: forged }, now).await.is_err());
+        let broadcast = service.open(request("Electronics > Laptops", &rfq), now).await.unwrap();
+        assert_eq!(service.get(broadcast.id).await.unwrap().broadcast.status, BroadcastStatus::Sending);
+
+        let sent = service.fan_out(&broadcast, "buyer-token").await.unwrap();
+        assert_eq!((sent.status, sent.sellers_notified), (BroadcastStatus::Sent, 3));
+        let view = service.get(broadcast.id).await.unwrap();
+        assert_eq!(view.broadcast.status, BroadcastStatus::Sent);
+        assert_eq!(view.broadcast.rfq.signature, broadcast.rfq.signature);
+        assert_eq!(view.quotes.iter().map(|quote| quote.seller_id).collect::<Vec<_>>(), [cheap, dear]);
+    }
+}
@@ -0,0 +1,166 @@
+//! Seller business calendars: timezone, opening hours and holidays.
+//!
+//! Pricing and quote lifetimes depend on whether the seller is open, which
+//! must be judged in the seller's own timezone rather than UTC.
+
+use crate::{
+    config::CalendarConfig,
+    error::{NegotiationError, Result},
+};
+use chrono::{DateTime, Datelike, Duration, NaiveDate, TimeZone, Timelike, Utc, Weekday};
+use chrono_tz::Tz;
+use rust_decimal::Decimal;
+use std::collections::HashSet;
+
+/// Price multiplier applied while the seller is open for business
+pub const BUSINESS_HOURS_PREMIUM: Decimal = Decimal::from_parts(102, 0, 0, false, 2);
+
+/// How far ahead to look for the next opening before giving up
+const MAX_LOOKAHEAD_DAYS: i64 = 366;
+
+#[derive(Debug, Clone)]
+pub struct BusinessCalendar {
+    timezone: Tz,
+    open_hour: u32,
+    close_hour: u32,
+    business_days: Vec<Weekday>,
+    holidays: HashSet<NaiveDate>,
+}
+
+impl BusinessCalendar {
+    pub fn from_config(config: &CalendarConfig) -> Result<Self> {
+        let timezone = config.timezone.parse::<Tz>()
+            .map_err(|_| NegotiationError::Config(format!("Unknown timezone: {}", config.timezone)))?;
+
+        if config.open_hour >= config.close_hour || config.close_hour > 24 {
+            return Err(NegotiationError::Config(format!(
+                "Invalid business hours: {}-{}", config.open_hour, config.close_hour
+            )));
+        }
+
+        let business_days = config.business_days.iter()
+            .map(|day| day.parse::<Weekday>()
+                .map_err(|_| NegotiationError::Config(format!("Invalid business day: {}", day))))
+            .collect::<Result<Vec<_>>>()?;
+
+        let holidays = config.holidays.iter()
+            .map(|date| NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .map_err(|_| NegotiationError::Config(format!("Invalid holiday date: {}", date))))
+            .collect::<Result<HashSet<_>>>()?;
+
+        Ok(Self {
+            timezone,
+            open_hour: config.open_hour,
+            close_hour: config.close_hour,
+            business_days,
+            holidays,
+        })
+    }
+
+    pub fn timezone(&self) -> Tz {
+        self.timezone
+    }
+
+    pub fn is_holiday(&self, at: DateTime<Utc>) -> bool {
+        self.holidays.contains(&at.with_timezone(&self.timezone).date_naive())
+    }
+
+    pub fn is_business_hours(&self, at: DateTime<Utc>) -> bool {
+        let local = at.with_timezone(&self.timezone);
+        self.is_business_day(local.date_naive())
+            && local.hour() >= self.open_hour
+            && local.hour() < self.close_hour
+    }
+
+    /// Next time the seller opens strictly after `at`, if any within a year.
+    pub fn next_opening(&self, at: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let start = at.with_timezone(&self.timezone).date_naive();
+
+        (0..=MAX_LOOKAHEAD_DAYS)
+            .map(|offset| start + Duration::days(offset))
+            .filter(|date| self.is_business_day(*date))
+            .filter_map(|date| {
+                let opening = date.and_hms_opt(self.open_hour, 0, 0)?;
+                self.timezone.from_local_datetime(&opening).earliest()
+            })
+            .map(|opening| opening.with_timezone(&Utc))
+            .find(|opening| *opening > at)
+    }
+
+    /// Multiplier the pricing engine applies for a quote issued at `at`.
+    pub fn pricing_factor(&self, at: DateTime<Utc>) -> Decimal {
+        if self.is_business_hours(at) {
+            BUSINESS_HOURS_PREMIUM
+        } else {
+            Decimal::ONE
+        }
+    }
+
+    /// Quotes issued while the seller is closed stay valid until the next
+    /// opening so they don't lapse before anyone can act on them.
+    pub fn quote_ttl_seconds(&self, base_ttl_seconds: u32, at: DateTime<Utc>) -> u32 {
+        if self.is_business_hours(at) {
+            return base_ttl_seconds;
+        }
+
+        self.next_opening(at)
+            .map(|opening| (opening - at).num_seconds().clamp(0, u32::MAX as i64) as u32)
+            .map_or(base_ttl_seconds, |until_open| until_open.max(base_ttl_seconds))
+    }
+
+    fn is_business_day(&self, date: NaiveDate) -> bool {
+        self.business_days.contains(&date.weekday()) && !self.holidays.contains(&date)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokyo_calendar() -> BusinessCalendar {
+        BusinessCalendar::from_config(&CalendarConfig {
+            timezone: "Asia/Tokyo".to_string(),
+            holidays: vec!["2026-01-01".to_string()],
+            ..CalendarConfig::default()
+        }).unwrap()
+    }
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_business_hours_use_seller_timezone() {
+        let calendar = tokyo_calendar();
+
+        // 01:00 UTC is 10:00 in Tokyo on a Wednesday
+        assert!(calendar.is_business_hours(utc("2026-01-07T01:00:00Z")));
+        assert_eq!(calendar.pricing_factor(utc("2026-01-07T01:00:00Z")), BUSINESS_HOURS_PREMIUM);
+        // 12:00 UTC is 21:00 in Tokyo
+        assert!(!calendar.is_business_hours(utc("2026-01-07T12:00:00Z")));
+        // New Year's Day holiday
+        assert!(calendar.is_holiday(utc("2026-01-01T01:00:00Z")));
+        assert!(!calendar.is_business_hours(utc("2026-01-01T01:00:00Z")));
+    }
+
+    #[test]
+    fn test_quote_ttl_extends_to_next_opening() {
+        let calendar = tokyo_calendar();
+
+        assert_eq!(calendar.quote_ttl_seconds(3600, utc("2026-01-07T01:00:00Z")), 3600);
+
+        // Friday 21:00 Tokyo: next opening is Monday 09:00 Tokyo (Monday 00:00 UTC)
+        let friday_evening = utc("2026-01-09T12:00:00Z");
+        assert_eq!(calendar.next_opening(friday_evening), Some(utc("2026-01-12T00:00:00Z")));
+        assert_eq!(calendar.quote_ttl_seconds(3600, friday_evening), 60 * 60 * 60);
+    }
+
+    #[test]
+    fn test_invalid_calendar_config() {
+        let config = CalendarConfig { timezone: "Mars/Olympus".to_string(), ..CalendarConfig::default() };
+        assert!(BusinessCalendar::from_config(&config).is_err());
+
+        let config = CalendarConfig { open_hour: 18, close_hour: 9, ..CalendarConfig::default() };
+        assert!(BusinessCalendar::from_config(&config).is_err());
+    }
+}
@@ -0,0 +1,389 @@
+//! Cancelling and renegotiating accepted deals before they settle.
+//!
+//! Either party can ask to change an accepted deal. Renegotiated terms and
+//! cancellations by mutual agreement or for a pricing error only take effect
+//! once the counterparty consents. A buyer withdrawing, a seller unable to
+//! fulfil, or a failed payment cancel the deal straight away, and the party at
+//! fault pays for it: a withdrawing buyer owes the seller a fee, a seller who
+//! can't fulfil breaches their quote obligation, and the penalised party loses
+//! reputation.
+
+use crate::{
+    config::CancellationConfig,
+    database::Database,
+    error::{NegotiationError, Result},
+    model::{Negotiation, NegotiationStatus},
+    money::Money,
+    obligation::{ObligationService, ObligationStatus},
+    settlement::PaymentStatus,
+    AgentId, TransactionId,
+};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Party {
+    Buyer,
+    Seller,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CancellationReason {
+    /// Buyer no longer wants the deal
+    BuyerWithdrawal,
+    /// Seller can't deliver on the agreed terms
+    SellerUnableToFulfil,
+    /// Buyer's payment didn't go through
+    PaymentFailed,
+    MutualAgreement,
+    /// The deal was struck at a wrong price
+    PricingError,
+}
+
+impl CancellationReason {
+    pub fn may_be_raised_by(&self, party: Party) -> bool {
+        match self {
+            Self::BuyerWithdrawal => party == Party::Buyer,
+            Self::SellerUnableToFulfil => party == Party::Seller,
+            Self::PaymentFailed | Self::MutualAgreement | Self::PricingError => true,
+        }
+    }
+
+    pub fn requires_consent(&self) -> bool {
+        matches!(self, Self::MutualAgreement | Self::PricingError)
+    }
+
+    /// Party who pays for a cancellation with this reason
+    pub fn penalised_party(&self) -> Option<Party> {
+        match self {
+            Self::BuyerWithdrawal | Self::PaymentFailed => Some(Party::Buyer),
+            Self::SellerUnableToFulfil => Some(Party::Seller),
+            Self::MutualAgreement | Self::PricingError => None,
+        }
+    }
+}
+
+impl FromStr for CancellationReason {
+    type Err = NegotiationError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        serde_json::from_value(serde_json::Value::String(s.to_string()))
+            .map_err(|_| NegotiationError::InvalidInput(format!("Unknown cancellation reason: {}", s)))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ChangeKind {
+    Cancellation { reason: CancellationReason },
+    Renegotiation {
+        price: Decimal,
+        quantity: u32,
+        previous_price: Decimal,
+        previous_quantity: u32,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeStatus {
+    /// Waiting for the counterparty's consent
+    Pending,
+    Approved,
+    Declined,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DealChange {
+    pub id: uuid::Uuid,
+    pub negotiation_id: TransactionId,
+    pub requested_by: AgentId,
+    pub kind: ChangeKind,
+    pub note: Option<String>,
+    pub status: ChangeStatus,
+    /// Party who pays for the cancellation, once applied
+    pub penalised: Option<AgentId>,
+    /// Fee the penalised party owes the counterparty, in the deal's currency
+    pub fee: Decimal,
+    pub currency: String,
+    pub reputation_penalty: u32,
+    pub created_at: DateTime<Utc>,
+    pub resolved_at: Option<DateTime<Utc>>,
+}
+
+impl DealChange {
+    pub fn fee_amount(&self) -> Money {
+        Money::new(self.fee, self.currency.clone())
+    }
+}
+
+/// Consent rule of the reference seller: cancellations by mutual agreement or
+/// for a pricing error are accepted, as are renegotiations that don't cut the
+/// unit price by more than 5%.
+pub fn default_consent(change: &DealChange) -> bool {
+    match change.kind {
+        ChangeKind::Cancellation { reason } => reason.requires_consent(),
+        ChangeKind::Renegotiation { price, quantity, previous_price, previous_quantity } => {
+            let unit_price = price / Decimal::from(quantity);
+            let previous_unit_price = previous_price / Decimal::from(previous_quantity);
+            unit_price >= previous_unit_price * Decimal::new(95, 2)
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct DealChangeService {
+    database: Database,
+    obligations: ObligationService,
+    config: CancellationConfig,
+}
+
+impl DealChangeService {
+    pub fn new(database: Database, config: CancellationConfig) -> Self {
+        Self {
+            obligations: ObligationService::new(database.clone()),
+            database,
+            config,
+        }
+    }
+
+    /// Asks to call off an accepted deal. Reasons that don't need consent
+    /// cancel it straight away; the rest wait for the counterparty.
+    pub async fn request_cancellation(
+        &self,
+        negotiation: &mut Negotiation,
+        requester: AgentId,
+        reason: CancellationReason,
+        note: Option<String>,
+    ) -> Result<DealChange> {
+        let party = self.check_changeable(negotiation, requester).await?;
+        if !reason.may_be_raised_by(party) {
+            return Err(NegotiationError::Validation(format!("{:?} can't be raised by the {:?}", reason, party)));
+        }
+        if reason == CancellationReason::PaymentFailed {
+            let key = format!("negotiation-{}", negotiation.id);
+            if let Some(payment) = self.database.get_payment_by_idempotency_key(&key).await? {
+                if payment.status == PaymentStatus::Succeeded {
+                    return Err(NegotiationError::Validation("Payment for this deal succeeded".to_string()));
+                }
+            }
+        }
+
+        let mut change = self.new_change(negotiation, requester, ChangeKind::Cancellation { reason }, note);
+        if !reason.requires_consent() {
+            self.apply(&mut change, negotiation).await?;
+        }
+        self.database.create_deal_change(&change).await?;
+        Ok(change)
+    }
+
+    /// Proposes new terms for an accepted deal, for the counterparty to accept
+    /// or decline.
+    pub async fn propose_renegotiation(
+        &self,
+        negotiation: &Negotiation,
+        requester: AgentId,
+        price: Decimal,
+        quantity: u32,
+        note: Option<String>,
+    ) -> Result<DealChange> {
+        self.check_changeable(negotiation, requester).await?;
+        if price <= Decimal::ZERO || quantity == 0 {
+            return Err(NegotiationError::Validation("Renegotiated price and quantity must be positive".to_string()));
+        }
+        let previous_price = negotiation.close_price
+            .ok_or_else(|| NegotiationError::Negotiation("Accepted deal has no price".to_string()))?;
+
+        let kind = ChangeKind::Renegotiation {
+            price,
+            quantity,
+            previous_price,
+            previous_quantity: negotiation.quantity,
+        };
+        let change = self.new_change(negotiation, requester, kind, note);
+        self.database.create_deal_change(&change).await?;
+        Ok(change)
+    }
+
+    /// Records the counterparty's answer to a pending change, applying it to
+    /// the deal when approved.
+    pub async fn respond(
+        &self,
+        negotiation: &mut Negotiation,
+        change_id: uuid::Uuid,
+        responder: AgentId,
+        approve: bool,
+    ) -> Result<DealChange> {
+        let mut change = self.database.get_deal_change(change_id).await?
+            .ok_or_else(|| NegotiationError::NotFound(format!("Deal change {}", change_id)))?;
+        if change.negotiation_id != negotiation.id || change.status != ChangeStatus::Pending {
+            return Err(NegotiationError::Validation(format!("Deal change {} is not pending on this negotiation", change_id)));
+        }
+        party_of(negotiation, responder)?;
+        if responder == change.requested_by {
+            return Err(NegotiationError::Auth("Only the counterparty can answer a change request".to_string()));
+        }
+
+        if approve {
+            self.apply(&mut change, negotiation).await?;
+        } else {
+            change.status = ChangeStatus::Declined;
+            change.resolved_at = Some(Utc::now());
+        }
+        self.database.update_deal_change(&change).await?;
+        Ok(change)
+    }
+
+    pub async fn changes(&self, negotiation_id: TransactionId) -> Result<Vec<DealChange>> {
+        self.database.get_deal_changes(negotiation_id).await
+    }
+
+    async fn check_changeable(&self, negotiation: &Negotiation, requester: AgentId) -> Result<Party> {
+        let party = party_of(negotiation, requester)?;
+        if negotiation.status != NegotiationStatus::Accepted {
+            return Err(NegotiationError::Negotiation(
+                "Only accepted deals can be changed; reject the quote instead".to_string()
+            ));
+        }
+        if self.changes(negotiation.id).await?.iter().any(|change| change.status == ChangeStatus::Pending) {
+            return Err(NegotiationError::Negotiation("Another change to this deal is awaiting an answer".to_string()));
+        }
+        Ok(party)
+    }
+
+    fn new_change(&self, negotiation: &Negotiation, requester: AgentId, kind: ChangeKind, note: Option<String>) -> DealChange {
+        DealChange {
+            id: uuid::Uuid::new_v4(),
+            negotiation_id: negotiation.id,
+            requested_by: requester,
+            kind,
+            note,
+            status: ChangeStatus::Pending,
+            penalised: None,
+            fee: Decimal::ZERO,
+            currency: negotiation.currency.clone(),
+            reputation_penalty: 0,
+            created_at: Utc::now(),
+            resolved_at: None,
+        }
+    }
+
+    async fn apply(&self, change: &mut DealChange, negotiation: &mut Negotiation) -> Result<()> {
+        match change.kind {
+            ChangeKind::Renegotiation { price, quantity, .. } => negotiation.amend(price, quantity)?,
+            ChangeKind::Cancellation { reason } => {
+                negotiation.cancel()?;
+                if let Some(party) = reason.penalised_party() {
+                    change.penalised = Some(match party {
+                        Party::Buyer => negotiation.buyer_id,
+                        Party::Seller => negotiation.seller_id,
+                    });
+                    change.reputation_penalty = self.config.reputation_penalty;
+                }
+                match reason {
+                    CancellationReason::BuyerWithdrawal => {
+                        let price = negotiation.close_price.unwrap_or_default();
+                        change.fee = (price * self.config.withdrawal_fee_rate).round_dp(2);
+                    }
+                    CancellationReason::SellerUnableToFulfil => self.breach_obligation(change, negotiation.id).await?,
+                    _ => {}
+                }
+            }
+        }
+        change.status = ChangeStatus::Approved;
+        change.resolved_at = Some(Utc::now());
+        Ok(())
+    }
+
+    /// A seller backing out of a firm or binding quote breaches its obligation,
+    /// which sets the reputation penalty and slashes any stake.
+    async fn breach_obligation(&self, change: &mut DealChange, negotiation_id: TransactionId) -> Result<()> {
+        let open = self.obligations.get(negotiation_id).await?
+            .is_some_and(|obligation| obligation.status == ObligationStatus::Open);
+        if open {
+            let reason = change.note.as_deref().unwrap_or("Seller unable to fulfil");
+            let obligation = self.obligations.breach(negotiation_id, reason).await?;
+            change.reputation_penalty = obligation.reputation_penalty().unsigned_abs();
+        }
+        Ok(())
+    }
+}
+
+fn party_of(negotiation: &Negotiation, agent_id: AgentId) -> Result<Party> {
+    if agent_id == negotiation.buyer_id {
+        Ok(Party::Buyer)
+    } else if agent_id == negotiation.seller_id {
+        Ok(Party::Seller)
+    } else {
+        Err(NegotiationError::Auth("Not a party to this negotiation".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{Quote, RFQ};
+    use tempfile::NamedTempFile;
+
+    async fn service() -> (DealChangeService, NamedTempFile) {
+        let temp_file = NamedTempFile::new().unwrap();
+        let database = Database::new(&format!("sqlite://{}", temp_file.path().to_string_lossy())).await.unwrap();
+        (DealChangeService::new(database, CancellationConfig::default()), temp_file)
+    }
+
+    fn accepted_deal(price: i64) -> Negotiation {
+        let rfq = RFQ::new(uuid::Uuid::new_v4(), "laptop-001".to_string(), 2, Decimal::from(2500), "USD".to_string(), Utc::now() + chrono::Duration::hours(1));
+        let mut negotiation = Negotiation::new(rfq, uuid::Uuid::new_v4());
+        let quote = Quote::new(negotiation.rfq_id, negotiation.seller_id, Decimal::from(price), "USD".to_string(), 2, 3600);
+        negotiation.add_quote(&quote).unwrap();
+        negotiation.accept(Decimal::from(price)).unwrap();
+        negotiation
+    }
+
+    #[tokio::test]
+    async fn test_withdrawal_cancels_with_fee_and_consent_reasons_wait() {
+        let (service, _db) = service().await;
+
+        let mut deal = accepted_deal(2000);
+        let (buyer, seller) = (deal.buyer_id, deal.seller_id);
+        assert!(service.request_cancellation(&mut deal, seller, CancellationReason::BuyerWithdrawal, None).await.is_err());
+
+        let change = service.request_cancellation(&mut deal, buyer, CancellationReason::BuyerWithdrawal, None).await.unwrap();
+        assert_eq!(change.status, ChangeStatus::Approved);
+        assert_eq!(change.penalised, Some(buyer));
+        assert_eq!(change.fee, Decimal::from(100));
+        assert_eq!(deal.status, NegotiationStatus::Cancelled);
+        assert!(deal.settle().is_err());
+
+        let mut deal = accepted_deal(2000);
+        let (buyer, seller) = (deal.buyer_id, deal.seller_id);
+        let change = service.request_cancellation(&mut deal, seller, CancellationReason::PricingError, None).await.unwrap();
+        assert_eq!(change.status, ChangeStatus::Pending);
+        assert_eq!(deal.status, NegotiationStatus::Accepted);
+        // The requester can't consent to their own request
+        assert!(service.respond(&mut deal, change.id, seller, true).await.is_err());
+        let declined = service.respond(&mut deal, change.id, buyer, false).await.unwrap();
+        assert_eq!(declined.status, ChangeStatus::Declined);
+        assert_eq!(deal.status, NegotiationStatus::Accepted);
+    }
+
+    #[tokio::test]
+    async fn test_renegotiation_applies_on_consent() {
+        let (service, _db) = service().await;
+        let mut deal = accepted_deal(2000);
+        let (buyer, seller) = (deal.buyer_id, deal.seller_id);
+
+        let change = service.propose_renegotiation(&deal, buyer, Decimal::from(980), 1, None).await.unwrap();
+        assert!(default_consent(&change));
+        assert!(service.propose_renegotiation(&deal, seller, Decimal::from(1900), 2, None).await.is_err());
+
+        service.respond(&mut deal, change.id, seller, true).await.unwrap();
+        assert_eq!(deal.close_price, Some(Decimal::from(980)));
+        assert_eq!(deal.quantity, 1);
+        assert_eq!(deal.status, NegotiationStatus::Accepted);
+    }
+}
@@ -0,0 +1,381 @@
+//! Catalog change tracking.
+//!
+//! The registry diffs each catalog a seller registers or syncs against what
+//! it already lists and records every product listed, repriced, restocked or
+//! delisted. Buyers read the price history of a product per seller, with the
+//! change over the window, to spot signals like "down 12% this month" before
+//! they negotiate.
+//!
+//! Sellers change their catalog while they run through [`router`], which
+//! only takes writes from the seller itself or an operator.
+
+use crate::{
+    agent::SellerAgent,
+    discovery::CatalogSyncResponse,
+    error::{ApiError, ApiResult, NegotiationError},
+    model::Product,
+    roles::{self, Permission, Role},
+    trust::{JWTClaims, TrustSystem},
+    validation::Valid,
+    AgentId,
+};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Json,
+    routing::{post, put},
+    Extension, Router,
+};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// Window of price history served when none is asked for
+pub const DEFAULT_HISTORY_DAYS: u32 = 30;
+
+/// Longest window of price history served
+pub const MAX_HISTORY_DAYS: u32 = 365;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProductChangeKind {
+    Listed,
+    /// Price, currency or stock changed
+    Updated,
+    /// Removed from the catalog; the price is the last one listed
+    Delisted,
+}
+
+/// A product's price and stock as of a catalog change
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProductChange {
+    pub id: uuid::Uuid,
+    pub agent_id: AgentId,
+    pub product_id: String,
+    pub kind: ProductChangeKind,
+    pub base_price: Decimal,
+    pub currency: String,
+    pub stock_quantity: u32,
+    pub recorded_at: DateTime<Utc>,
+}
+
+impl ProductChange {
+    fn new(agent_id: AgentId, product: &Product, kind: ProductChangeKind, now: DateTime<Utc>) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4(),
+            agent_id,
+            product_id: product.id.clone(),
+            kind,
+            base_price: product.base_price,
+            currency: product.currency.clone(),
+            stock_quantity: if kind == ProductChangeKind::Delisted { 0 } else { product.stock_quantity },
+            recorded_at: now,
+        }
+    }
+}
+
+/// Changes that turn the `listed` catalog into `products`. Edits to names,
+/// descriptions and metadata aren't tracked.
+pub fn diff_catalog(agent_id: AgentId, listed: &[Product], products: &[Product], now: DateTime<Utc>) -> Vec<ProductChange> {
+    let mut changes = Vec::new();
+    for product in products {
+        match listed.iter().find(|old| old.id == product.id) {
+            None => changes.push(ProductChange::new(agent_id, product, ProductChangeKind::Listed, now)),
+            Some(old) if old.base_price != product.base_price
+                || old.currency != product.currency
+                || old.stock_quantity != product.stock_quantity => {
+                changes.push(ProductChange::new(agent_id, product, ProductChangeKind::Updated, now))
+            }
+            Some(_) => {}
+        }
+    }
+    for old in listed {
+        if !products.iter().any(|product| product.id == old.id) {
+            changes.push(ProductChange::new(agent_id, old, ProductChangeKind::Delisted, now));
+        }
+    }
+    changes
+}
+
+/// A product listed in the registry, with the seller listing it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CatalogEntry {
+    pub seller_id: AgentId,
+    pub seller_name: String,
+    pub seller_reputation: u32,
+    #[serde(flatten)]
+    pub product: Product,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PricePoint {
+    pub kind: ProductChangeKind,
+    pub price: Decimal,
+    pub currency: String,
+    pub stock_quantity: u32,
+    pub recorded_at: DateTime<Utc>,
+}
+
+/// One seller's prices for a product over a window
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PriceHistory {
+    pub seller_id: AgentId,
+    pub product_id: String,
+    /// The price in force when the window opened, then every change since
+    pub points: Vec<PricePoint>,
+    /// Percent change from the first to the latest listed price, when both
+    /// are in the same currency; negative when the price dropped
+    pub change_percent: Option<Decimal>,
+}
+
+impl PriceHistory {
+    fn new(seller_id: AgentId, changes: &[&ProductChange], since: DateTime<Utc>) -> Option<Self> {
+        // The last change before the window is the price the window opened at
+        let opening = changes.iter().rposition(|change| change.recorded_at <= since).unwrap_or(0);
+        let points: Vec<PricePoint> = changes[opening..].iter()
+            .map(|change| PricePoint {
+                kind: change.kind,
+                price: change.base_price,
+                currency: change.currency.clone(),
+                stock_quantity: change.stock_quantity,
+                recorded_at: change.recorded_at,
+            })
+            .collect();
+        if points.is_empty() {
+            return None;
+        }
+
+        let mut listed = points.iter().filter(|point| point.kind != ProductChangeKind::Delisted);
+        let change_percent = match (listed.next(), listed.next_back()) {
+            (Some(first), Some(latest)) if first.currency == latest.currency && !first.price.is_zero() => {
+                Some(((latest.price - first.price) / first.price * Decimal::ONE_HUNDRED).round_dp(2))
+            }
+            _ => None,
+        };
+
+        Some(Self {
+            seller_id,
+            product_id: changes[opening].product_id.clone(),
+            points,
+            change_percent,
+        })
+    }
+}
+
+/// Groups a product's changes, oldest first, into a history per seller
+/// covering everything since `since`.
+pub fn price_histories(changes: &[ProductChange], since: DateTime<Utc>) -> Vec<PriceHistory> {
+    let mut sellers: Vec<AgentId> = Vec::new();
+    for change in changes {
+        if !sellers.contains(&change.agent_id) {
+            sellers.push(change.agent_id);
+        }
+    }
+    sellers.into_iter()
+        .filter_map(|seller_id| {
+            let seller_changes: Vec<&ProductChange> = changes.iter()
+                .filter(|change| change.agent_id == seller_id)
+                .collect();
+            PriceHistory::new(seller_id, &seller_changes, since)
+        })
+        .collect()
+}
+
+/// The seller's catalog write API. Every route needs `manage_catalog`, and
+/// seller tokens must be the seller's own.
+pub fn router<S>(seller: Arc<SellerAgent>, trust: Arc<RwLock<TrustSystem>>) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    let manage = Router::new()
+        .route("/products", post(add_product))
+        .route("/products/:product_id", put(update_product).delete(remove_product));
+    roles::require_permission(manage, trust, Permission::ManageCatalog)
+        .with_state(seller)
+}
+
+/// Refuses seller tokens issued to another seller.
+fn check_owner(seller: &SellerAgent, claims: &JWTClaims) -> ApiResult<()> {
+    if claims.role == Role::Seller.as_str() && claims.sub != seller.agent_info().id.to_string() {
+        return Err(NegotiationError::Trust("Sellers may only change their own catalog".to_string()).into());
+    }
+    Ok(())
+}
+
+/// Adds a product to the catalog and lists it in the registry.
+async fn add_product(
+    State(seller): State<Arc<SellerAgent>>,
+    Extension(claims): Extension<JWTClaims>,
+    Valid(product): Valid<Product>,
+) -> ApiResult<(StatusCode, Json<CatalogSyncResponse>)> {
+    check_owner(&seller, &claims)?;
+    let product_id = product.id.clone();
+    let response = seller.add_product(product).await
+        .map_err(|e| catalog_error(&product_id, e))?;
+    Ok((StatusCode::CREATED, Json(response)))
+}
+
+/// Replaces a product's details and stock on hand, and updates the registry.
+async fn update_product(
+    State(seller): State<Arc<SellerAgent>>,
+    Extension(claims): Extension<JWTClaims>,
+    Path(product_id): Path<String>,
+    Valid(product): Valid<Product>,
+) -> ApiResult<Json<CatalogSyncResponse>> {
+    check_owner(&seller, &claims)?;
+    if product.id != product_id {
+        return Err(ApiError::new(StatusCode::BAD_REQUEST, "Product id doesn't match the path"));
+    }
+    let response = seller.update_product(product).await
+        .map_err(|e| catalog_error(&product_id, e))?;
+    Ok(Json(response))
+}
+
+/// Takes a product out of the catalog and the registry.
+async fn remove_product(
+    State(seller): State<Arc<SellerAgent>>,
+    Extension(claims): Extension<JWTClaims>,
+    Path(product_id): Path<String>,
+) -> ApiResult<Json<CatalogSyncResponse>> {
+    check_owner(&seller, &claims)?;
+    let response = seller.remove_product(&product_id).await
+        .map_err(|e| catalog_error(&product_id, e))?;
+    Ok(Json(response))
+}
+
+/// The catalog changes even when the registry can't be reached; the next
+/// change or restart lists it there.
+fn catalog_error(product_id: &str, error: NegotiationError) -> ApiError {
+    match error {
+        e @ NegotiationError::ProductNotFound(_) => e.into(),
+        e @ NegotiationError::Validation(_) => {
+            tracing::info!("Refused catalog change to {}: {}", product_id, e);
+            ApiError::from(e).with_status(StatusCode::CONFLICT)
+        }
+        NegotiationError::Database(e) => {
+            tracing::error!("Failed to set stock of {}: {}", product_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR.into()
+        }
+        e => {
+            tracing::error!("Failed to list catalog change to {} in the registry: {}", product_id, e);
+            StatusCode::BAD_GATEWAY.into()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use std::collections::HashMap;
+
+    fn product(id: &str, price: i64, stock: u32) -> Product {
+        Product {
+            id: id.to_string(),
+            name: id.to_string(),
+            description: String::new(),
+            category: "Electronics".to_string(),
+            base_price: Decimal::from(price),
+            currency: "USD".to_string(),
+            stock_quantity: stock,
+            metadata: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_catalog_diffs_build_price_history() {
+        let seller_id = uuid::Uuid::new_v4();
+        let start = Utc::now() - Duration::days(60);
+
+        let mut changes = diff_catalog(seller_id, &[], &[product("laptop-001", 2500, 10), product("mouse-001", 40, 5)], start);
+        assert_eq!(changes.len(), 2);
+
+        let renamed = Product { name: "Gaming Laptop".to_string(), ..product("laptop-001", 2500, 10) };
+        assert!(diff_catalog(seller_id, &[product("laptop-001", 2500, 10)], &[renamed], start).is_empty());
+
+        let repriced = diff_catalog(
+            seller_id,
+            &[product("laptop-001", 2500, 10), product("mouse-001", 40, 5)],
+            &[product("laptop-001", 2000, 10)],
+            start + Duration::days(45),
+        );
+        assert_eq!(repriced.iter().map(|change| change.kind).collect::<Vec<_>>(),
+            vec![ProductChangeKind::Updated, ProductChangeKind::Delisted]);
+        changes.extend(repriced);
+        changes.extend(diff_catalog(
+            seller_id, &[product("laptop-001", 2000, 10)], &[product("laptop-001", 2200, 8)], start + Duration::days(50),
+        ));
+
+        let laptop: Vec<ProductChange> = changes.iter().filter(|change| change.product_id == "laptop-001").cloned().collect();
+        let histories = price_histories(&laptop, start + Duration::days(30));
+        assert_eq!(histories.len(), 1);
+        // The listing price in force when the window opened, then both changes
+        assert_eq!(histories[0].points.len(), 3);
+        assert_eq!(histories[0].change_percent, Some(Decimal::new(-1200, 2)));
+
+        let mouse: Vec<ProductChange> = changes.iter().filter(|change| change.product_id == "mouse-001").cloned().collect();
+        let histories = price_histories(&mouse, start + Duration::days(30));
+        assert_eq!(histories[0].points.last().unwrap().kind, ProductChangeKind::Delisted);
+        assert_eq!(histories[0].change_percent, None);
+    }
+
+    #[tokio::test]
+    async fn test_catalog_writes_need_the_seller_or_an_operator() {
+        use crate::{
+            agent::{LLMConfig, SellerAgentConfig},
+            discovery::DiscoveryService,
+        };
+        use reqwest::StatusCode;
+
+        let seller_id = AgentId::new_v4();
+        let config = SellerAgentConfig {
+            agent_id: seller_id,
+            name: "TechSeller".to_string(),
+            endpoint: "http://seller:8001".to_string(),
+            products: vec![product("laptop-001", 2500, 10)],
+            payment_methods: vec![],
+            llm_config: LLMConfig { model: "gpt-4".to_string(), api_key: String::new(), max_tokens: 1000, temperature: 0.7 },
+            calendar: Default::default(),
+            pricing: Default::default(),
+            quote_ttl: Default::default(),
+            quote_firmness: Default::default(),
+            locale: Default::default(),
+            preferred_languages: vec![],
+            compliance: Default::default(),
+            templates: Default::default(),
+            shipping: Default::default(),
+            tax: Default::default(),
+        };
+        let seller = Arc::new(SellerAgent::new(config, DiscoveryService::new(String::new()), TrustSystem::new().unwrap()).await.unwrap());
+        let trust = TrustSystem::new().unwrap().with_agent_role(Role::Seller);
+        let own = trust.generate_jwt(seller_id).await.unwrap();
+        let other_seller = trust.generate_jwt(AgentId::new_v4()).await.unwrap();
+        let buyer = TrustSystem::new().unwrap().generate_jwt(AgentId::new_v4()).await.unwrap();
+        let operator = trust.generate_role_jwt("ops@example.com", Role::Admin, Duration::minutes(5)).unwrap();
+        let app: Router = router(seller.clone(), Arc::new(RwLock::new(trust)));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}/products", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let client = reqwest::Client::new();
+        let add = |token: Option<&str>, id: &str| {
+            let request = client.post(&base).json(&product(id, 40, 5));
+            match token {
+                Some(token) => request.bearer_auth(token),
+                None => request,
+            }
+        };
+        assert_eq!(add(None, "mouse-001").send().await.unwrap().status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(client.delete(format!("{}/laptop-001", base)).send().await.unwrap().status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(add(Some(&buyer), "mouse-001").send().await.unwrap().status(), StatusCode::FORBIDDEN);
+        assert_eq!(add(Some(&other_seller), "mouse-001").send().await.unwrap().status(), StatusCode::FORBIDDEN);
+        assert_eq!(seller.products().len(), 1);
+
+        // No registry to list them in, but both changes apply
+        assert_eq!(add(Some(&own), "mouse-001").send().await.unwrap().status(), StatusCode::BAD_GATEWAY);
+        assert_eq!(add(Some(&operator), "keyboard-001").send().await.unwrap().status(), StatusCode::BAD_GATEWAY);
+        assert_eq!(seller.products().len(), 3);
+    }
+}
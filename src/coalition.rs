@@ -0,0 +1,556 @@
+//! Buyer coalitions, or group buys.
+//!
+//! Buyers after the same product pool their demand behind a coordinator, one
+//! of themselves. The coordinator forms a coalition on the discovery service
+//! with its own quantity and a cap on the unit price, and other buyers join
+//! with theirs until it closes. The coordinator then locks the membership,
+//! sends a seller one aggregated RFQ for the combined quantity and negotiates
+//! the volume price like any other negotiation. The agreed price is split
+//! between members in proportion to their quantities, and each member's share
+//! is paid as a payment of its own. Every step is posted to the coalition as a
+//! message its members can read.
+
+use crate::{
+    database::Database,
+    error::{NegotiationError, Result},
+    model::{AgentType, RFQ},
+    money::{minor_units, Money},
+    AgentId, TransactionId,
+};
+use chrono::{DateTime, Duration, Utc};
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::{Deserialize, Serialize};
+
+/// RFQ metadata key naming the coalition an aggregated RFQ buys for
+pub const COALITION_METADATA_KEY: &str = "coalition_id";
+
+/// How long a coalition takes members when the coordinator doesn't say
+pub const DEFAULT_COALITION_DURATION_SECONDS: i64 = 86400;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CoalitionStatus {
+    /// Taking members
+    Open,
+    /// Membership is locked and the coordinator is negotiating with a seller
+    Negotiating,
+    /// A price is agreed and split into shares, waiting for members to pay
+    Agreed,
+    /// Every share is paid
+    Settled,
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Coalition {
+    pub id: uuid::Uuid,
+    pub coordinator_id: AgentId,
+    pub product_id: String,
+    pub currency: String,
+    /// Most any member pays per unit
+    pub max_unit_price: Decimal,
+    /// Combined quantity needed before the coordinator can go to sellers
+    pub min_quantity: u32,
+    pub status: CoalitionStatus,
+    /// Buyers can join until then
+    pub closes_at: DateTime<Utc>,
+    pub seller_id: Option<AgentId>,
+    pub negotiation_id: Option<TransactionId>,
+    /// Agreed price for the combined quantity
+    pub price: Option<Decimal>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Coalition {
+    pub fn is_open_at(&self, now: DateTime<Utc>) -> bool {
+        self.status == CoalitionStatus::Open && now < self.closes_at
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CoalitionMember {
+    pub coalition_id: uuid::Uuid,
+    pub buyer_id: AgentId,
+    pub quantity: u32,
+    /// The member's part of the agreed price, once there is one
+    pub share: Option<Decimal>,
+    /// Payment of the share, once made
+    pub payment_id: Option<String>,
+    pub joined_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CoalitionMessageKind {
+    Formed,
+    Joined,
+    Left,
+    /// The coordinator locked membership and sent the aggregated RFQ
+    Locked,
+    /// A price was agreed and split into shares
+    Agreed,
+    Paid,
+    Settled,
+    Cancelled,
+}
+
+/// Coordination message posted to a coalition's members
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CoalitionMessage {
+    pub id: uuid::Uuid,
+    pub coalition_id: uuid::Uuid,
+    pub sender_id: AgentId,
+    pub kind: CoalitionMessageKind,
+    pub content: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A coalition with its members and messages, oldest first
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CoalitionView {
+    pub coalition: Coalition,
+    pub members: Vec<CoalitionMember>,
+    pub messages: Vec<CoalitionMessage>,
+}
+
+impl CoalitionView {
+    pub fn total_quantity(&self) -> u32 {
+        self.members.iter().map(|member| member.quantity).sum()
+    }
+
+    pub fn member(&self, buyer_id: AgentId) -> Option<&CoalitionMember> {
+        self.members.iter().find(|member| member.buyer_id == buyer_id)
+    }
+
+    /// One RFQ from the coordinator for the combined quantity, capped at the
+    /// coalition's unit price.
+    pub fn aggregated_rfq(&self, deadline: DateTime<Utc>) -> RFQ {
+        let quantity = self.total_quantity();
+        let mut rfq = RFQ::new(
+            self.coalition.coordinator_id,
+            self.coalition.product_id.clone(),
+            quantity,
+            self.coalition.max_unit_price * Decimal::from(quantity),
+            self.coalition.currency.clone(),
+            deadline,
+        );
+        rfq.metadata.insert(COALITION_METADATA_KEY.to_string(), self.coalition.id.to_string());
+        rfq
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FormCoalitionRequest {
+    pub coordinator_id: AgentId,
+    pub product_id: String,
+    pub currency: String,
+    /// The coordinator's own quantity
+    pub quantity: u32,
+    pub max_unit_price: Decimal,
+    pub min_quantity: u32,
+    #[serde(default = "default_duration_seconds")]
+    pub duration_seconds: i64,
+}
+
+fn default_duration_seconds() -> i64 {
+    DEFAULT_COALITION_DURATION_SECONDS
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JoinCoalitionRequest {
+    pub buyer_id: AgentId,
+    pub quantity: u32,
+}
+
+/// Names the agent acting on a coalition
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CoalitionActionRequest {
+    pub agent_id: AgentId,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LockCoalitionRequest {
+    pub coordinator_id: AgentId,
+    pub seller_id: AgentId,
+    pub negotiation_id: TransactionId,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgreeCoalitionRequest {
+    pub coordinator_id: AgentId,
+    /// Price for the combined quantity, in the coalition's currency
+    pub price: Decimal,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CoalitionPaymentRequest {
+    pub buyer_id: AgentId,
+    pub payment_id: String,
+}
+
+/// Splits `total` in proportion to `quantities`, to the currency's minor
+/// unit. Shares are rounded down and the leftover minor units go one each to
+/// the largest remainders, earlier members first, so they add up to `total`.
+pub fn split_proportionally(total: &Money, quantities: &[u32]) -> Vec<Money> {
+    let combined: u32 = quantities.iter().sum();
+    if combined == 0 {
+        return quantities.iter().map(|_| Money::zero(total.currency.clone())).collect();
+    }
+    let dp = minor_units(&total.currency);
+    let exact: Vec<Decimal> = quantities.iter()
+        .map(|quantity| total.amount * Decimal::from(*quantity) / Decimal::from(combined))
+        .collect();
+    let mut shares: Vec<Decimal> = exact.iter()
+        .map(|share| share.round_dp_with_strategy(dp, RoundingStrategy::ToZero))
+        .collect();
+
+    let unit = Decimal::new(1, dp);
+    let total = total.round_to_minor_units();
+    let mut leftover = total.amount - shares.iter().sum::<Decimal>();
+    let mut by_remainder: Vec<usize> = (0..shares.len()).collect();
+    by_remainder.sort_by(|a, b| (exact[*b] - shares[*b]).cmp(&(exact[*a] - shares[*a])));
+    for i in by_remainder.into_iter().cycle() {
+        if leftover < unit {
+            break;
+        }
+        shares[i] += unit;
+        leftover -= unit;
+    }
+
+    shares.into_iter().map(|share| Money::new(share, total.currency.clone())).collect()
+}
+
+/// Runs coalitions for the discovery service.
+#[derive(Clone)]
+pub struct CoalitionService {
+    database: Database,
+}
+
+impl CoalitionService {
+    pub fn new(database: Database) -> Self {
+        Self { database }
+    }
+
+    /// Opens a coalition with the coordinator as its first member.
+    pub async fn form(&self, request: FormCoalitionRequest, now: DateTime<Utc>) -> Result<CoalitionView> {
+        self.ensure_buyer(request.coordinator_id).await?;
+        if request.product_id.trim().is_empty() {
+            return Err(NegotiationError::Validation("Product is required".to_string()));
+        }
+        crate::currency::validate_currency_code(&request.currency)?;
+        if request.quantity == 0 || request.min_quantity == 0 {
+            return Err(NegotiationError::Validation("Quantities must be greater than 0".to_string()));
+        }
+        if request.max_unit_price <= Decimal::ZERO {
+            return Err(NegotiationError::Validation("Max unit price must be greater than 0".to_string()));
+        }
+        if request.duration_seconds <= 0 {
+            return Err(NegotiationError::Validation("Coalition duration must be positive".to_string()));
+        }
+
+        let coalition = Coalition {
+            id: uuid::Uuid::new_v4(),
+            coordinator_id: request.coordinator_id,
+            product_id: request.product_id,
+            currency: request.currency,
+            max_unit_price: request.max_unit_price,
+            min_quantity: request.min_quantity,
+            status: CoalitionStatus::Open,
+            closes_at: now + Duration::seconds(request.duration_seconds),
+            seller_id: None,
+            negotiation_id: None,
+            price: None,
+            created_at: now,
+        };
+        self.database.create_coalition(&coalition).await?;
+        self.database.add_coalition_member(&CoalitionMember {
+            coalition_id: coalition.id,
+            buyer_id: coalition.coordinator_id,
+            quantity: request.quantity,
+            share: None,
+            payment_id: None,
+            joined_at: now,
+        }).await?;
+        self.post(&coalition, coalition.coordinator_id, CoalitionMessageKind::Formed, format!(
+            "Buying {} x{} at up to {} {} a unit, needs {} in all",
+            coalition.product_id, request.quantity, coalition.max_unit_price, coalition.currency, coalition.min_quantity
+        ), now).await?;
+
+        tracing::info!("Coalition {} open for {} until {}", coalition.id, coalition.product_id, coalition.closes_at);
+        self.get(coalition.id).await
+    }
+
+    pub async fn get(&self, coalition_id: uuid::Uuid) -> Result<CoalitionView> {
+        let coalition = self.database.get_coalition(coalition_id).await?
+            .ok_or_else(|| NegotiationError::NotFound(format!("Coalition {}", coalition_id)))?;
+        Ok(CoalitionView {
+            coalition,
+            members: self.database.get_coalition_members(coalition_id).await?,
+            messages: self.database.get_coalition_messages(coalition_id).await?,
+        })
+    }
+
+    /// Coalitions still taking members at `now`, optionally for one product.
+    pub async fn list_open(&self, product_id: Option<&str>, now: DateTime<Utc>) -> Result<Vec<Coalition>> {
+        self.database.get_open_coalitions(product_id, now).await
+    }
+
+    pub async fn join(&self, coalition_id: uuid::Uuid, request: JoinCoalitionRequest, now: DateTime<Utc>) -> Result<CoalitionMember> {
+        let view = self.get(coalition_id).await?;
+        if !view.coalition.is_open_at(now) {
+            return Err(NegotiationError::Validation(format!("Coalition {} is closed", coalition_id)));
+        }
+        self.ensure_buyer(request.buyer_id).await?;
+        if request.quantity == 0 {
+            return Err(NegotiationError::Validation("Quantity must be greater than 0".to_string()));
+        }
+        if view.member(request.buyer_id).is_some() {
+            return Err(NegotiationError::Validation("Buyer is already a member".to_string()));
+        }
+
+        let member = CoalitionMember {
+            coalition_id,
+            buyer_id: request.buyer_id,
+            quantity: request.quantity,
+            share: None,
+            payment_id: None,
+            joined_at: now,
+        };
+        self.database.add_coalition_member(&member).await?;
+        self.post(&view.coalition, request.buyer_id, CoalitionMessageKind::Joined, format!("Joined with {} units", request.quantity), now).await?;
+        Ok(member)
+    }
+
+    /// Takes a member out while the coalition is open. The coordinator
+    /// cancels instead.
+    pub async fn leave(&self, coalition_id: uuid::Uuid, buyer_id: AgentId, now: DateTime<Utc>) -> Result<()> {
+        let view = self.get(coalition_id).await?;
+        if view.coalition.status != CoalitionStatus::Open {
+            return Err(NegotiationError::Validation("Members can only leave an open coalition".to_string()));
+        }
+        if buyer_id == view.coalition.coordinator_id {
+            return Err(NegotiationError::Validation("The coordinator cancels the coalition instead of leaving".to_string()));
+        }
+        if !self.database.remove_coalition_member(coalition_id, buyer_id).await? {
+            return Err(NegotiationError::Validation("Buyer is not a member".to_string()));
+        }
+        self.post(&view.coalition, buyer_id, CoalitionMessageKind::Left, "Left the coalition".to_string(), now).await
+    }
+
+    /// Closes membership once the members' combined quantity reaches the
+    /// minimum, recording the negotiation the aggregated RFQ opened.
+    pub async fn lock(&self, coalition_id: uuid::Uuid, request: LockCoalitionRequest, now: DateTime<Utc>) -> Result<CoalitionView> {
+        let mut view = self.coordinated(coalition_id, request.coordinator_id).await?;
+        if view.coalition.status != CoalitionStatus::Open {
+            return Err(NegotiationError::Validation(format!("Coalition {} is not open", coalition_id)));
+        }
+        let quantity = view.total_quantity();
+        if quantity < view.coalition.min_quantity {
+            return Err(NegotiationError::Validation(format!(
+                "Members want {} units, short of the {} needed", quantity, view.coalition.min_quantity
+            )));
+        }
+
+        view.coalition.status = CoalitionStatus::Negotiating;
+        view.coalition.seller_id = Some(request.seller_id);
+        view.coalition.negotiation_id = Some(request.negotiation_id);
+        self.database.update_coalition(&view.coalition).await?;
+        self.post(&view.coalition, request.coordinator_id, CoalitionMessageKind::Locked, format!(
+            "Asked seller {} for {} units in negotiation {}", request.seller_id, quantity, request.negotiation_id
+        ), now).await?;
+        self.get(coalition_id).await
+    }
+
+    /// Records the price the coordinator agreed and splits it into each
+    /// member's share.
+    pub async fn agree(&self, coalition_id: uuid::Uuid, request: AgreeCoalitionRequest, now: DateTime<Utc>) -> Result<CoalitionView> {
+        let mut view = self.coordinated(coalition_id, request.coordinator_id).await?;
+        if view.coalition.status != CoalitionStatus::Negotiating {
+            return Err(NegotiationError::Validation(format!("Coalition {} is not negotiating", coalition_id)));
+        }
+        let quantity = view.total_quantity();
+        if request.price <= Decimal::ZERO || request.price > view.coalition.max_unit_price * Decimal::from(quantity) {
+            return Err(NegotiationError::Validation("Agreed price must be positive and within the coalition's cap".to_string()));
+        }
+
+        let price = Money::new(request.price, view.coalition.currency.clone());
+        let quantities: Vec<u32> = view.members.iter().map(|member| member.quantity).collect();
+        for (member, share) in view.members.iter_mut().zip(split_proportionally(&price, &quantities)) {
+            member.share = Some(share.amount);
+            self.database.update_coalition_member(member).await?;
+        }
+        view.coalition.status = CoalitionStatus::Agreed;
+        view.coalition.price = Some(request.price);
+        self.database.update_coalition(&view.coalition).await?;
+        self.post(&view.coalition, request.coordinator_id, CoalitionMessageKind::Agreed, format!(
+            "Agreed {} for {} units", price, quantity
+        ), now).await?;
+        self.get(coalition_id).await
+    }
+
+    /// Records a member's share as paid, settling the coalition with the
+    /// last one.
+    pub async fn record_payment(&self, coalition_id: uuid::Uuid, request: CoalitionPaymentRequest, now: DateTime<Utc>) -> Result<CoalitionView> {
+        let mut view = self.get(coalition_id).await?;
+        if view.coalition.status != CoalitionStatus::Agreed {
+            return Err(NegotiationError::Validation(format!("Coalition {} has no shares to pay", coalition_id)));
+        }
+        let member = view.members.iter_mut()
+            .find(|member| member.buyer_id == request.buyer_id)
+            .ok_or_else(|| NegotiationError::Validation("Buyer is not a member".to_string()))?;
+        if member.payment_id.is_some() {
+            return Err(NegotiationError::Validation("Share is already paid".to_string()));
+        }
+        member.payment_id = Some(request.payment_id.clone());
+        self.database.update_coalition_member(member).await?;
+        self.post(&view.coalition, request.buyer_id, CoalitionMessageKind::Paid, format!("Paid in {}", request.payment_id), now).await?;
+
+        if view.members.iter().all(|member| member.payment_id.is_some()) {
+            view.coalition.status = CoalitionStatus::Settled;
+            self.database.update_coalition(&view.coalition).await?;
+            self.post(&view.coalition, view.coalition.coordinator_id, CoalitionMessageKind::Settled, "Every share is paid".to_string(), now).await?;
+        }
+        self.get(coalition_id).await
+    }
+
+    /// Calls the coalition off before a price is agreed.
+    pub async fn cancel(&self, coalition_id: uuid::Uuid, coordinator_id: AgentId, now: DateTime<Utc>) -> Result<CoalitionView> {
+        let mut view = self.coordinated(coalition_id, coordinator_id).await?;
+        if !matches!(view.coalition.status, CoalitionStatus::Open | CoalitionStatus::Negotiating) {
+            return Err(NegotiationError::Validation(format!("Coalition {} can no longer be cancelled", coalition_id)));
+        }
+        view.coalition.status = CoalitionStatus::Cancelled;
+        self.database.update_coalition(&view.coalition).await?;
+        self.post(&view.coalition, coordinator_id, CoalitionMessageKind::Cancelled, "Cancelled by the coordinator".to_string(), now).await?;
+        self.get(coalition_id).await
+    }
+
+    async fn coordinated(&self, coalition_id: uuid::Uuid, coordinator_id: AgentId) -> Result<CoalitionView> {
+        let view = self.get(coalition_id).await?;
+        if view.coalition.coordinator_id != coordinator_id {
+            return Err(NegotiationError::Auth("Only the coordinator can do that".to_string()));
+        }
+        Ok(view)
+    }
+
+    async fn ensure_buyer(&self, agent_id: AgentId) -> Result<()> {
+        let agent = self.database.get_agent(agent_id).await?
+            .ok_or(NegotiationError::AgentNotFound(agent_id))?;
+        if !matches!(agent.agent_type, AgentType::Buyer) {
+            return Err(NegotiationError::Validation("Only buyers can take part in coalitions".to_string()));
+        }
+        Ok(())
+    }
+
+    async fn post(&self, coalition: &Coalition, sender_id: AgentId, kind: CoalitionMessageKind, content: String, now: DateTime<Utc>) -> Result<()> {
+        self.database.add_coalition_message(&CoalitionMessage {
+            id: uuid::Uuid::new_v4(),
+            coalition_id: coalition.id,
+            sender_id,
+            kind,
+            content,
+            created_at: now,
+        }).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::AgentInfo;
+    use std::str::FromStr;
+    use tempfile::NamedTempFile;
+
+    async fn agent(database: &Database, agent_type: AgentType) -> AgentId {
+        let agent = AgentInfo {
+            id: uuid::Uuid::new_v4(),
+            agent_type,
+            name: "Buyer".to_string(),
+            endpoint: "http://localhost:8002".to_string(),
+            public_key: "key".to_string(),
+            reputation_score: 80,
+            products: vec![],
+            payment_methods: vec![],
+            protocol_versions: vec![],
+            preferred_languages: vec![],
+            tls_fingerprint: None,
+            created_at: Utc::now(),
+            last_active: Utc::now(),
+        };
+        database.create_agent(&agent).await.unwrap();
+        agent.id
+    }
+
+    fn usd(amount: &str) -> Money {
+        Money::new(Decimal::from_str(amount).unwrap(), "USD")
+    }
+
+    #[test]
+    fn test_shares_add_up_to_the_price() {
+        let shares = split_proportionally(&usd("100.00"), &[1, 1, 1]);
+        assert_eq!(shares, [usd("33.34"), usd("33.33"), usd("33.33")]);
+        let shares = split_proportionally(&usd("1000.00"), &[5, 3, 2]);
+        assert_eq!(shares, [usd("500.00"), usd("300.00"), usd("200.00")]);
+        let shares = split_proportionally(&Money::new(Decimal::from(1000), "JPY"), &[1, 2]);
+        assert_eq!(shares.iter().map(|share| share.amount).sum::<Decimal>(), Decimal::from(1000));
+        assert_eq!(shares[1].amount, Decimal::from(667));
+    }
+
+    #[tokio::test]
+    async fn test_coalition_pools_demand_and_splits_the_price() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let database = Database::new(&format!("sqlite://{}", temp_file.path().to_string_lossy())).await.unwrap();
+        let service = CoalitionService::new(database.clone());
+        let coordinator = agent(&database, AgentType::Buyer).await;
+        let (member, leaver) = (agent(&database, AgentType::Buyer).await, agent(&database, AgentType::Buyer).await);
+        let seller = agent(&database, AgentType::Seller).await;
+        let now = Utc::now();
+        let at = |step: i64| now + Duration::seconds(step);
+
+        let view = service.form(FormCoalitionRequest {
+            coordinator_id: coordinator,
+            product_id: "laptop-001".to_string(),
+            currency: "USD".to_string(),
+            quantity: 30,
+            max_unit_price: Decimal::from(2000),
+            min_quantity: 50,
+            duration_seconds: 600,
+        }, at(1)).await.unwrap();
+        let coalition_id = view.coalition.id;
+        assert_eq!(service.list_open(Some("laptop-001"), at(2)).await.unwrap().len(), 1);
+
+        service.join(coalition_id, JoinCoalitionRequest { buyer_id: member, quantity: 20 }, at(3)).await.unwrap();
+        assert!(service.join(coalition_id, JoinCoalitionRequest { buyer_id: member, quantity: 5 }, at(4)).await.is_err());
+        assert!(service.join(coalition_id, JoinCoalitionRequest { buyer_id: seller, quantity: 5 }, at(5)).await.is_err());
+        service.join(coalition_id, JoinCoalitionRequest { buyer_id: leaver, quantity: 10 }, at(6)).await.unwrap();
+        service.leave(coalition_id, leaver, at(7)).await.unwrap();
+        assert!(service.leave(coalition_id, coordinator, at(8)).await.is_err());
+
+        let lock = |coordinator_id| LockCoalitionRequest { coordinator_id, seller_id: seller, negotiation_id: uuid::Uuid::new_v4() };
+        assert!(service.lock(coalition_id, lock(member), at(9)).await.is_err());
+        let view = service.lock(coalition_id, lock(coordinator), at(10)).await.unwrap();
+        assert_eq!(view.coalition.status, CoalitionStatus::Negotiating);
+        let rfq = view.aggregated_rfq(now + Duration::hours(1));
+        assert_eq!((rfq.buyer_id, rfq.quantity, rfq.max_price), (coordinator, 50, Decimal::from(100000)));
+        assert!(service.join(coalition_id, JoinCoalitionRequest { buyer_id: leaver, quantity: 10 }, at(11)).await.is_err());
+
+        assert!(service.agree(coalition_id, AgreeCoalitionRequest { coordinator_id: coordinator, price: Decimal::from(100001) }, at(12)).await.is_err());
+        let view = service.agree(coalition_id, AgreeCoalitionRequest { coordinator_id: coordinator, price: Decimal::new(9000001, 2) }, at(13)).await.unwrap();
+        assert_eq!(view.member(coordinator).unwrap().share, Some(Decimal::new(5400001, 2)));
+        assert_eq!(view.member(member).unwrap().share, Some(Decimal::new(3600000, 2)));
+
+        let pay = |buyer_id, payment_id: &str| CoalitionPaymentRequest { buyer_id, payment_id: payment_id.to_string() };
+        service.record_payment(coalition_id, pay(member, "pay_1"), at(14)).await.unwrap();
+        assert!(service.record_payment(coalition_id, pay(member, "pay_2"), at(15)).await.is_err());
+        let view = service.record_payment(coalition_id, pay(coordinator, "pay_3"), at(16)).await.unwrap();
+        assert_eq!(view.coalition.status, CoalitionStatus::Settled);
+        assert_eq!(
+            view.messages.iter().map(|message| message.kind).collect::<Vec<_>>(),
+            [
+                CoalitionMessageKind::Formed, CoalitionMessageKind::Joined, CoalitionMessageKind::Joined,
+                CoalitionMessageKind::Left, CoalitionMessageKind::Locked, CoalitionMessageKind::Agreed,
+                CoalitionMessageKind::Paid, CoalitionMessageKind::Paid, CoalitionMessageKind::Settled,
+            ]
+        );
+    }
+}
@@ -0,0 +1,140 @@
+//! Ranking of competing quotes from an RFQ fan-out.
+//!
+//! Quotes are scored on landed cost (price, taxes and the cheapest shipping,
+//! in the buyer's currency), seller reputation and delivery time. Quotes
+//! within the buyer's budget always rank ahead of those over it.
+
+use crate::{model::Quote, money::Money, AgentId, TransactionId};
+use rust_decimal::prelude::ToPrimitive;
+use serde::{Deserialize, Serialize};
+
+const PRICE_WEIGHT: f64 = 0.6;
+const REPUTATION_WEIGHT: f64 = 0.3;
+const DELIVERY_WEIGHT: f64 = 0.1;
+
+/// Delivery score for quotes that don't give an estimate
+const UNKNOWN_DELIVERY_SCORE: f64 = 0.5;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RankedQuote {
+    pub negotiation_id: TransactionId,
+    pub seller_id: AgentId,
+    pub seller_name: String,
+    pub quote: Quote,
+    /// Quoted price converted to the buyer's currency
+    pub price: Money,
+    /// What the buyer would pay all in, in the buyer's currency
+    pub landed_cost: Money,
+    pub seller_reputation: u32,
+    pub delivery_days: Option<u32>,
+    pub within_budget: bool,
+    pub score: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SellerFailure {
+    pub seller_id: AgentId,
+    pub seller_name: String,
+    pub error: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct QuoteComparison {
+    /// Best first
+    pub ranked: Vec<RankedQuote>,
+    /// Sellers that errored or missed the deadline
+    pub failures: Vec<SellerFailure>,
+}
+
+impl QuoteComparison {
+    pub fn best(&self) -> Option<&RankedQuote> {
+        self.ranked.first()
+    }
+}
+
+/// Scores each quote relative to the cheapest and fastest on offer, then sorts
+/// best first.
+pub fn rank_quotes(mut quotes: Vec<RankedQuote>) -> Vec<RankedQuote> {
+    let lowest_price = quotes.iter()
+        .filter_map(|quote| quote.landed_cost.amount.to_f64())
+        .filter(|price| *price > 0.0)
+        .fold(f64::INFINITY, f64::min);
+    let fastest_delivery = quotes.iter()
+        .filter_map(|quote| quote.delivery_days)
+        .min();
+
+    for quote in &mut quotes {
+        let price_score = match quote.landed_cost.amount.to_f64() {
+            Some(price) if price > 0.0 => lowest_price / price,
+            _ => 0.0,
+        };
+        let reputation_score = (quote.seller_reputation.min(100) as f64) / 100.0;
+        let delivery_score = match (quote.delivery_days, fastest_delivery) {
+            (Some(days), Some(fastest)) => (fastest.max(1) as f64) / (days.max(1) as f64),
+            _ => UNKNOWN_DELIVERY_SCORE,
+        };
+        quote.score = PRICE_WEIGHT * price_score
+            + REPUTATION_WEIGHT * reputation_score
+            + DELIVERY_WEIGHT * delivery_score;
+    }
+
+    quotes.sort_by(|a, b| {
+        b.within_budget.cmp(&a.within_budget)
+            .then(b.score.total_cmp(&a.score))
+    });
+    quotes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::ShippingOption;
+    use rust_decimal::Decimal;
+
+    fn ranked(name: &str, price: i64, reputation: u32, delivery_days: Option<u32>, within_budget: bool) -> RankedQuote {
+        let seller_id = uuid::Uuid::new_v4();
+        RankedQuote {
+            negotiation_id: uuid::Uuid::new_v4(),
+            seller_id,
+            seller_name: name.to_string(),
+            quote: Quote::new(uuid::Uuid::new_v4(), seller_id, Decimal::from(price), "USD".to_string(), 1, 3600),
+            price: Money::new(Decimal::from(price), "USD"),
+            landed_cost: Money::new(Decimal::from(price), "USD"),
+            seller_reputation: reputation,
+            delivery_days,
+            within_budget,
+            score: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_rank_quotes() {
+        let ranked = rank_quotes(vec![
+            ranked("pricey", 120, 95, Some(2), true),
+            ranked("cheap", 100, 90, Some(5), true),
+            ranked("over-budget", 80, 100, Some(1), false),
+            ranked("shady", 98, 20, None, true),
+        ]);
+        let names: Vec<&str> = ranked.iter().map(|quote| quote.seller_name.as_str()).collect();
+        assert_eq!(names, vec!["cheap", "pricey", "shady", "over-budget"]);
+    }
+
+    #[test]
+    fn test_quotes_rank_by_landed_cost() {
+        let mut free_shipping = ranked("free-shipping", 110, 90, None, true);
+        let mut cheap = ranked("cheap", 100, 90, None, true);
+        for (quote, cost) in [(&mut free_shipping, 0), (&mut cheap, 30)] {
+            quote.quote = quote.quote.clone().with_shipping_options(vec![
+                ShippingOption { carrier: "UPS".to_string(), cost: Decimal::from(cost), eta_days: 3 },
+                ShippingOption { carrier: "FedEx".to_string(), cost: Decimal::from(cost + 20), eta_days: 1 },
+            ]);
+            quote.landed_cost = quote.quote.total().unwrap();
+            quote.delivery_days = quote.quote.delivery_days();
+        }
+        assert_eq!(cheap.landed_cost.amount, Decimal::from(130));
+        assert_eq!(cheap.delivery_days, Some(3));
+
+        let ranked = rank_quotes(vec![cheap, free_shipping]);
+        assert_eq!(ranked[0].seller_name, "free-shipping");
+    }
+}
@@ -0,0 +1,250 @@
+//! Per-category compliance policy enforced by the discovery registry.
+//!
+//! Some categories (age-gated or regulated goods) may only be listed by
+//! sellers holding an attestation for the category from an attestor the
+//! registry trusts, and only sold in certain regions. The registry screens a
+//! seller's products when it registers and when it syncs its catalog,
+//! withholding those the policy doesn't allow and recording why. Search
+//! applies the policy again, since attestations expire and buyers search
+//! from a region.
+
+use crate::{
+    config::{CategoryRestriction, ComplianceConfig},
+    demand::normalize_category,
+    error::{NegotiationError, Result},
+    model::Product,
+    recovery::verify_signature,
+    taxonomy::category_matches,
+    AgentId,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// A trusted attestor's statement that a seller in `region` may sell
+/// `category` until `expires_at`, signed over [`attestation_message`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SellerAttestation {
+    pub category: String,
+    pub region: String,
+    /// Base64 ed25519 key of the attestor
+    pub attestor: String,
+    pub expires_at: DateTime<Utc>,
+    pub signature: String,
+}
+
+impl SellerAttestation {
+    /// Checks the attestor's signature; not whether the attestor is trusted
+    /// or the attestation still current.
+    pub fn verify(&self, seller_id: AgentId) -> Result<()> {
+        let message = attestation_message(seller_id, &self.category, &self.region, self.expires_at);
+        verify_signature(&self.attestor, &message, &self.signature)
+            .map_err(|e| NegotiationError::Auth(format!("Invalid seller attestation: {}", e)))
+    }
+}
+
+/// What a seller declares to the registry for compliance checks
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ComplianceProfile {
+    /// Region the seller sells from, e.g. an ISO 3166 code
+    #[serde(default)]
+    pub region: Option<String>,
+    #[serde(default)]
+    pub attestations: Vec<SellerAttestation>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ComplianceStage {
+    Registration,
+    CatalogSync,
+}
+
+/// A product the registry refused to list, and why
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ComplianceViolation {
+    pub id: uuid::Uuid,
+    pub agent_id: AgentId,
+    pub product_id: String,
+    pub category: String,
+    pub stage: ComplianceStage,
+    pub reason: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Bytes an attestor signs to attest a seller for a category and region.
+pub fn attestation_message(seller_id: AgentId, category: &str, region: &str, expires_at: DateTime<Utc>) -> Vec<u8> {
+    format!(
+        "dcap-seller-attestation:v1:{}:{}:{}:{}",
+        seller_id, normalize_category(category), region.to_uppercase(), expires_at.timestamp()
+    ).into_bytes()
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CompliancePolicy {
+    config: ComplianceConfig,
+}
+
+impl CompliancePolicy {
+    pub fn new(config: ComplianceConfig) -> Self {
+        Self { config }
+    }
+
+    /// The restriction on `category` or a category it falls under.
+    pub fn restriction(&self, category: &str) -> Option<&CategoryRestriction> {
+        self.config.restricted_categories.iter()
+            .find(|restriction| category_matches(category, &restriction.category))
+    }
+
+    /// Why the seller may not list products in `category`, if anything.
+    pub fn seller_violation(
+        &self,
+        seller_id: AgentId,
+        profile: &ComplianceProfile,
+        category: &str,
+        now: DateTime<Utc>,
+    ) -> Option<String> {
+        let restriction = self.restriction(category)?;
+        if !restriction.allowed_regions.is_empty() {
+            match &profile.region {
+                Some(region) if region_allowed(restriction, region) => {}
+                Some(region) => return Some(format!("{} may not be sold from {}", restriction.category, region)),
+                None => return Some(format!("{} requires the seller to declare a region", restriction.category)),
+            }
+        }
+        if restriction.requires_attestation && !self.has_valid_attestation(seller_id, profile, category, now) {
+            return Some(format!("{} requires a current attestation from a trusted attestor", restriction.category));
+        }
+        None
+    }
+
+    /// Why a buyer searching from `buyer_region` may not be shown products
+    /// in `category`, if anything. Region-restricted categories are hidden
+    /// from searches that don't name a region.
+    pub fn buyer_violation(&self, category: &str, buyer_region: Option<&str>) -> Option<String> {
+        let restriction = self.restriction(category)?;
+        if restriction.allowed_regions.is_empty() {
+            return None;
+        }
+        match buyer_region {
+            Some(region) if region_allowed(restriction, region) => None,
+            Some(region) => Some(format!("{} may not be sold to {}", restriction.category, region)),
+            None => Some(format!("{} is only shown to searches that name a region", restriction.category)),
+        }
+    }
+
+    /// Splits a seller's products into those it may list and the violations
+    /// for the rest.
+    pub fn screen(
+        &self,
+        seller_id: AgentId,
+        profile: &ComplianceProfile,
+        products: Vec<Product>,
+        stage: ComplianceStage,
+        now: DateTime<Utc>,
+    ) -> (Vec<Product>, Vec<ComplianceViolation>) {
+        let mut listed = Vec::new();
+        let mut violations = Vec::new();
+        for product in products {
+            match self.seller_violation(seller_id, profile, &product.category, now) {
+                Some(reason) => violations.push(ComplianceViolation {
+                    id: uuid::Uuid::new_v4(),
+                    agent_id: seller_id,
+                    product_id: product.id,
+                    category: product.category,
+                    stage,
+                    reason,
+                    created_at: now,
+                }),
+                None => listed.push(product),
+            }
+        }
+        (listed, violations)
+    }
+
+    fn has_valid_attestation(&self, seller_id: AgentId, profile: &ComplianceProfile, category: &str, now: DateTime<Utc>) -> bool {
+        let Some(region) = &profile.region else {
+            return false;
+        };
+        profile.attestations.iter().any(|attestation| {
+            category_matches(category, &attestation.category)
+                && attestation.region.eq_ignore_ascii_case(region)
+                && attestation.expires_at > now
+                && self.config.trusted_attestors.contains(&attestation.attestor)
+                && attestation.verify(seller_id).is_ok()
+        })
+    }
+}
+
+fn region_allowed(restriction: &CategoryRestriction, region: &str) -> bool {
+    restriction.allowed_regions.iter().any(|allowed| allowed.eq_ignore_ascii_case(region))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::{engine::general_purpose, Engine};
+    use chrono::Duration;
+    use ed25519_dalek::{Signer, SigningKey};
+    use std::collections::HashMap;
+
+    fn product(id: &str, category: &str) -> Product {
+        Product {
+            id: id.to_string(),
+            name: id.to_string(),
+            description: String::new(),
+            category: category.to_string(),
+            base_price: rust_decimal::Decimal::from(40),
+            currency: "USD".to_string(),
+            stock_quantity: 10,
+            metadata: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_restricted_categories_need_attestation_and_allowed_region() {
+        let attestor = SigningKey::from_bytes(&[7; 32]);
+        let attestor_key = general_purpose::STANDARD.encode(attestor.verifying_key().to_bytes());
+        let policy = CompliancePolicy::new(ComplianceConfig {
+            trusted_attestors: vec![attestor_key.clone()],
+            restricted_categories: vec![CategoryRestriction {
+                category: "Alcohol".to_string(),
+                requires_attestation: true,
+                allowed_regions: vec!["GB".to_string(), "FR".to_string()],
+            }],
+        });
+
+        let seller_id = uuid::Uuid::new_v4();
+        let now = Utc::now();
+        let expires_at = now + Duration::days(30);
+        let attestation = |region: &str| SellerAttestation {
+            category: "alcohol".to_string(),
+            region: region.to_string(),
+            attestor: attestor_key.clone(),
+            expires_at,
+            signature: general_purpose::STANDARD.encode(
+                attestor.sign(&attestation_message(seller_id, "alcohol", region, expires_at)).to_bytes()
+            ),
+        };
+        let products = || vec![product("wine-001", "Alcohol"), product("laptop-001", "Electronics")];
+
+        let unattested = ComplianceProfile { region: Some("GB".to_string()), attestations: vec![] };
+        let (listed, violations) = policy.screen(seller_id, &unattested, products(), ComplianceStage::Registration, now);
+        assert_eq!(listed.len(), 1);
+        assert_eq!(violations[0].product_id, "wine-001");
+
+        let attested = ComplianceProfile { region: Some("gb".to_string()), attestations: vec![attestation("GB")] };
+        let (listed, violations) = policy.screen(seller_id, &attested, products(), ComplianceStage::CatalogSync, now);
+        assert_eq!(listed.len(), 2);
+        assert!(violations.is_empty());
+        assert!(policy.seller_violation(seller_id, &attested, "Alcohol", expires_at).is_some());
+        assert!(policy.seller_violation(uuid::Uuid::new_v4(), &attested, "Alcohol", now).is_some());
+
+        let elsewhere = ComplianceProfile { region: Some("US".to_string()), attestations: vec![attestation("US")] };
+        assert!(policy.seller_violation(seller_id, &elsewhere, "Alcohol", now).is_some());
+
+        assert!(policy.buyer_violation("alcohol", Some("FR")).is_none());
+        assert!(policy.buyer_violation("alcohol", Some("US")).is_some());
+        assert!(policy.buyer_violation("alcohol", None).is_some());
+        assert!(policy.buyer_violation("Electronics", None).is_none());
+    }
+}
@@ -0,0 +1,307 @@
+//! Concession curves: how far each side moved, round by round.
+//!
+//! `BuyerAgent::auto_negotiate` records the buyer's counter offers and the
+//! seller's asks of every negotiation it runs as a curve, with metrics
+//! computed from it, keyed by the negotiation. Strategies see the curve so
+//! far through `OfferContext::concession_curve`. The settlement service serves
+//! each negotiation's curve and per-strategy averages for tuning strategies
+//! after the fact.
+//!
+//! Concession rates are fractions of the opening gap (the seller's first ask
+//! less the buyer's first offer) given up per round, so deals at different
+//! prices compare.
+
+use crate::{
+    analytics::AnalyticsQuery,
+    database::Database,
+    error::{NegotiationError, Result},
+    model::Negotiation,
+    AgentId, TransactionId,
+};
+use chrono::{DateTime, Utc};
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// One round: the buyer's counter offer and the seller's answer to it. Round
+/// 0 holds the seller's opening ask alone.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConcessionPoint {
+    pub round: u32,
+    pub buyer_offer: Option<Decimal>,
+    /// None while the seller hasn't answered
+    pub seller_ask: Option<Decimal>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConcessionCurve {
+    pub points: Vec<ConcessionPoint>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ConcessionMetrics {
+    /// Counter offers the buyer made
+    pub rounds: u32,
+    /// Seller's first ask less the buyer's first offer
+    pub opening_gap: Option<Decimal>,
+    /// Seller's last ask less the buyer's last offer
+    pub final_gap: Option<Decimal>,
+    /// Share of the opening gap the buyer gave up per round
+    pub buyer_concession_rate: Option<f64>,
+    /// Share of the opening gap the seller gave up per round
+    pub seller_concession_rate: Option<f64>,
+    /// Where the final price landed between the opening positions: -1 at the
+    /// buyer's first offer, 0 at the midpoint, 1 at the seller's first ask
+    pub midpoint_convergence: Option<f64>,
+}
+
+impl ConcessionCurve {
+    /// The curve of a negotiation's offers, oldest first; the seller's asks
+    /// start with its opening quote.
+    pub fn from_offers(seller_offers: &[Decimal], buyer_offers: &[Decimal]) -> Self {
+        let rounds = buyer_offers.len().max(seller_offers.len().saturating_sub(1));
+        let points = (0..=rounds)
+            .map(|round| ConcessionPoint {
+                round: round as u32,
+                buyer_offer: round.checked_sub(1).and_then(|index| buyer_offers.get(index)).copied(),
+                seller_ask: seller_offers.get(round).copied(),
+            })
+            .filter(|point| point.buyer_offer.is_some() || point.seller_ask.is_some())
+            .collect();
+        Self { points }
+    }
+
+    fn buyer_offers(&self) -> Vec<Decimal> {
+        self.points.iter().filter_map(|point| point.buyer_offer).collect()
+    }
+
+    fn seller_asks(&self) -> Vec<Decimal> {
+        self.points.iter().filter_map(|point| point.seller_ask).collect()
+    }
+
+    /// Metrics of the curve. The final price is `close_price` for a deal,
+    /// otherwise the seller's last ask.
+    pub fn metrics(&self, close_price: Option<Decimal>) -> ConcessionMetrics {
+        let (buyer, seller) = (self.buyer_offers(), self.seller_asks());
+        let rounds = buyer.len() as u32;
+        let (Some(first_offer), Some(first_ask)) = (buyer.first().copied(), seller.first().copied()) else {
+            return ConcessionMetrics { rounds, ..Default::default() };
+        };
+        let (last_offer, last_ask) = (buyer[buyer.len() - 1], seller[seller.len() - 1]);
+        let opening_gap = first_ask - first_offer;
+
+        let share = |moved: Decimal| if opening_gap > Decimal::ZERO {
+            (moved / opening_gap).to_f64()
+        } else {
+            None
+        };
+        let per_round = |moved: Decimal, moves: usize| if moves == 0 {
+            Some(0.0)
+        } else {
+            share(moved).map(|share| share / moves as f64)
+        };
+        let final_price = close_price.unwrap_or(last_ask);
+        let midpoint = (first_offer + first_ask) / Decimal::TWO;
+
+        ConcessionMetrics {
+            rounds,
+            opening_gap: Some(opening_gap),
+            final_gap: Some(last_ask - last_offer),
+            buyer_concession_rate: per_round(last_offer - first_offer, buyer.len() - 1),
+            seller_concession_rate: per_round(first_ask - last_ask, seller.len() - 1),
+            midpoint_convergence: share((final_price - midpoint) * Decimal::TWO),
+        }
+    }
+}
+
+/// A finished negotiation's curve, as stored
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConcessionRecord {
+    pub negotiation_id: TransactionId,
+    pub buyer_id: AgentId,
+    pub seller_id: AgentId,
+    pub product_id: String,
+    /// Strategy that made the buyer's offers
+    pub strategy: String,
+    /// None when the buyer walked away
+    pub close_price: Option<Decimal>,
+    pub curve: ConcessionCurve,
+    pub metrics: ConcessionMetrics,
+    pub recorded_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ConcessionQuery {
+    /// How far back to look, `DEFAULT_ANALYTICS_DAYS` by default
+    pub days: Option<u32>,
+    pub strategy: Option<String>,
+}
+
+/// How a strategy's negotiations went, averaged over those where the metric
+/// could be computed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StrategyConcessions {
+    pub strategy: String,
+    pub negotiations: u64,
+    pub deals: u64,
+    pub average_rounds: f64,
+    pub average_buyer_concession_rate: Option<f64>,
+    pub average_seller_concession_rate: Option<f64>,
+    pub average_midpoint_convergence: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConcessionAnalytics {
+    pub since: DateTime<Utc>,
+    pub strategies: Vec<StrategyConcessions>,
+}
+
+#[derive(Clone)]
+pub struct ConcessionService {
+    database: Database,
+}
+
+impl ConcessionService {
+    pub fn new(database: Database) -> Self {
+        Self { database }
+    }
+
+    /// Stores a finished negotiation's curve with its metrics, replacing any
+    /// curve recorded for it before. The negotiation's close price, if any,
+    /// is taken as the final price.
+    pub async fn record(&self, negotiation: &Negotiation, strategy: &str, curve: ConcessionCurve) -> Result<ConcessionRecord> {
+        let record = ConcessionRecord {
+            negotiation_id: negotiation.id,
+            buyer_id: negotiation.buyer_id,
+            seller_id: negotiation.seller_id,
+            product_id: negotiation.product_id.clone(),
+            strategy: strategy.to_string(),
+            close_price: negotiation.close_price,
+            metrics: curve.metrics(negotiation.close_price),
+            curve,
+            recorded_at: Utc::now(),
+        };
+        self.database.save_concession_record(&record).await?;
+        Ok(record)
+    }
+
+    pub async fn get(&self, negotiation_id: TransactionId) -> Result<ConcessionRecord> {
+        self.database.get_concession_record(negotiation_id).await?
+            .ok_or_else(|| NegotiationError::NotFound(format!("Concession curve for negotiation {}", negotiation_id)))
+    }
+
+    /// Metrics averaged per strategy over the negotiations `query` covers.
+    pub async fn analytics(&self, query: &ConcessionQuery) -> Result<ConcessionAnalytics> {
+        let since = AnalyticsQuery { days: query.days, category: None }.since(Utc::now())?;
+        let records = self.database.get_concession_records_since(since, query.strategy.as_deref()).await?;
+        Ok(ConcessionAnalytics { since, strategies: summarize(&records) })
+    }
+}
+
+fn summarize(records: &[ConcessionRecord]) -> Vec<StrategyConcessions> {
+    let mut groups: BTreeMap<&str, Vec<&ConcessionRecord>> = BTreeMap::new();
+    for record in records {
+        groups.entry(record.strategy.as_str()).or_default().push(record);
+    }
+
+    let average = |values: Vec<f64>| if values.is_empty() {
+        None
+    } else {
+        Some(values.iter().sum::<f64>() / values.len() as f64)
+    };
+    groups.into_iter()
+        .map(|(strategy, records)| {
+            let metric = |pick: fn(&ConcessionMetrics) -> Option<f64>| {
+                average(records.iter().filter_map(|record| pick(&record.metrics)).collect())
+            };
+            StrategyConcessions {
+                strategy: strategy.to_string(),
+                negotiations: records.len() as u64,
+                deals: records.iter().filter(|record| record.close_price.is_some()).count() as u64,
+                average_rounds: average(records.iter().map(|record| record.metrics.rounds as f64).collect()).unwrap_or_default(),
+                average_buyer_concession_rate: metric(|metrics| metrics.buyer_concession_rate),
+                average_seller_concession_rate: metric(|metrics| metrics.seller_concession_rate),
+                average_midpoint_convergence: metric(|metrics| metrics.midpoint_convergence),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::RFQ;
+    use std::collections::HashMap;
+    use tempfile::NamedTempFile;
+
+    fn prices(prices: &[i64]) -> Vec<Decimal> {
+        prices.iter().map(|price| Decimal::from(*price)).collect()
+    }
+
+    #[test]
+    fn test_curve_metrics() {
+        let curve = ConcessionCurve::from_offers(&prices(&[120, 110, 100]), &prices(&[60, 80]));
+        assert_eq!(curve.points.len(), 3);
+        assert_eq!(curve.points[0], ConcessionPoint { round: 0, buyer_offer: None, seller_ask: Some(Decimal::from(120)) });
+        assert_eq!(curve.points[2].buyer_offer, Some(Decimal::from(80)));
+
+        let metrics = curve.metrics(Some(Decimal::from(100)));
+        assert_eq!(metrics.rounds, 2);
+        assert_eq!((metrics.opening_gap, metrics.final_gap), (Some(Decimal::from(60)), Some(Decimal::from(20))));
+        // Buyer moved 20 of 60 in one move, seller 20 of 60 over two
+        assert!((metrics.buyer_concession_rate.unwrap() - 1.0 / 3.0).abs() < 1e-9);
+        assert!((metrics.seller_concession_rate.unwrap() - 1.0 / 6.0).abs() < 1e-9);
+        // Closed at 100, 10 above the midpoint of 90 in a half gap of 30
+        assert!((metrics.midpoint_convergence.unwrap() - 1.0 / 3.0).abs() < 1e-9);
+
+        let walked_away = ConcessionCurve::from_offers(&prices(&[120]), &[]).metrics(None);
+        assert_eq!((walked_away.rounds, walked_away.buyer_concession_rate), (0, None));
+    }
+
+    #[tokio::test]
+    async fn test_records_are_summarized_per_strategy() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let database = Database::new(&format!("sqlite://{}", temp_file.path().to_string_lossy())).await.unwrap();
+        let service = ConcessionService::new(database);
+        let negotiation = |close_price: Option<i64>| Negotiation {
+            close_price: close_price.map(Decimal::from),
+            ..Negotiation::new(RFQ {
+                id: uuid::Uuid::new_v4(),
+                buyer_id: uuid::Uuid::new_v4(),
+                product_id: "laptop-001".to_string(),
+                quantity: 1,
+                max_price: Decimal::from(130),
+                currency: "USD".to_string(),
+                delivery_location: None,
+                deadline: Utc::now() + chrono::Duration::hours(1),
+                metadata: HashMap::new(),
+                nonce: None,
+                issued_at: None,
+                signature: None,
+            }, uuid::Uuid::new_v4())
+        };
+
+        let deal = negotiation(Some(100));
+        let curve = ConcessionCurve::from_offers(&prices(&[120, 100]), &prices(&[60]));
+        service.record(&deal, "boulware", curve).await.unwrap();
+        let curve = ConcessionCurve::from_offers(&prices(&[120, 115, 110]), &prices(&[60, 70]));
+        service.record(&negotiation(None), "boulware", curve).await.unwrap();
+        let curve = ConcessionCurve::from_offers(&prices(&[120]), &[]);
+        service.record(&negotiation(Some(120)), "linear", curve).await.unwrap();
+
+        let stored = service.get(deal.id).await.unwrap();
+        assert_eq!(stored.curve.points.len(), 2);
+        assert_eq!(stored.metrics.rounds, 1);
+        assert!(service.get(uuid::Uuid::new_v4()).await.is_err());
+
+        let analytics = service.analytics(&ConcessionQuery::default()).await.unwrap();
+        let boulware = &analytics.strategies[0];
+        assert_eq!((boulware.strategy.as_str(), boulware.negotiations, boulware.deals), ("boulware", 2, 1));
+        assert_eq!(boulware.average_rounds, 1.5);
+        assert_eq!(analytics.strategies[1].average_buyer_concession_rate, None);
+
+        let linear = service.analytics(&ConcessionQuery { days: None, strategy: Some("linear".to_string()) }).await.unwrap();
+        assert_eq!(linear.strategies.len(), 1);
+    }
+}
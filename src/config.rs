@@ -1,5 +1,46 @@
-use crate::error::Result;
+use crate::{
+    admin::DEFAULT_STUCK_AFTER_MINUTES,
+    agent::DEFAULT_MIN_BUYER_REPUTATION,
+    anomaly::{
+        DEFAULT_ANOMALY_BASELINE_DAYS, DEFAULT_ANOMALY_INTERVAL_SECONDS, DEFAULT_ANOMALY_WINDOW_HOURS,
+        DEFAULT_MIN_PRICE_SAMPLES, DEFAULT_PRICE_BAND_DEVIATIONS, DEFAULT_REPUTATION_SPIKE_FACTOR,
+        DEFAULT_REPUTATION_SPIKE_POINTS, DEFAULT_WASH_TRADE_MIN_DEALS,
+    },
+    artifacts::DEFAULT_MAX_ARTIFACT_BYTES, calendar::BUSINESS_HOURS_PREMIUM,
+    embeddings::{DEFAULT_EMBEDDING_DIMENSIONS, DEFAULT_MIN_SIMILARITY, DEFAULT_SEMANTIC_WEIGHT}, error::Result,
+    explorer::{
+        DEFAULT_ACTIVE_SELLER_DAYS, DEFAULT_EXPLORER_RATE_LIMIT_PER_MINUTE, DEFAULT_RECENT_DEALS,
+        DEFAULT_TOP_CATEGORIES,
+    },
+    expiry::{ExpiryAction, DEFAULT_CHECK_INTERVAL_SECONDS, DEFAULT_DEADLINE_CHECK_SECONDS, DEFAULT_WARNING_SECONDS},
+    gossip::DEFAULT_GOSSIP_INTERVAL_SECONDS,
+    http::{
+        DEFAULT_CONNECT_TIMEOUT_MS, DEFAULT_FAILURE_THRESHOLD, DEFAULT_INITIAL_BACKOFF_MS, DEFAULT_MAX_BACKOFF_MS,
+        DEFAULT_MAX_RETRIES, DEFAULT_OPEN_SECONDS, DEFAULT_TIMEOUT_MS,
+    },
+    language::Language,
+    lifecycle::{DEFAULT_POD_LABELS_PATH, DEFAULT_STARTUP_TIMEOUT_SECONDS}, locale::Locale,
+    mcp::{
+        DEFAULT_MCP_IDLE_TIMEOUT_SECONDS, DEFAULT_MCP_MAX_CONNECTIONS, DEFAULT_MCP_SHUTDOWN_GRACE_SECONDS,
+        DEFAULT_MCP_WRITE_TIMEOUT_SECONDS,
+    },
+    model::{PaymentMethod, QuoteFirmness},
+    quote_ttl::{DEFAULT_COUNTER_TTL_SECONDS, DEFAULT_HIGHLY_TRUSTED_MULTIPLIER, DEFAULT_QUOTE_TTL_SECONDS},
+    registry::RegistryKind,
+    reload::DEFAULT_WATCH_INTERVAL_SECONDS,
+    responsiveness::ResponseSla,
+    roles::Permission,
+    shared_state::{DEFAULT_KEY_PREFIX, DEFAULT_LOCK_TTL_MS, DEFAULT_LOCK_WAIT_MS, DEFAULT_SEARCH_CACHE_SECONDS},
+    strategy::DEFAULT_MAX_ROUNDS,
+    strategy_bench::{DEFAULT_BENCH_SCENARIOS, DEFAULT_BENCH_SEED},
+    taxonomy::CategoryNode,
+    templates::TemplateKind,
+    trust::TrustLevel,
+    webhook::DEFAULT_SIGNING_KEY_ENV,
+};
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::path::Path;
 
 #[derive(Debug, Deserialize, Clone, Serialize)]
@@ -11,6 +52,88 @@ pub struct AppConfig {
     pub trust: TrustConfig,
     pub llm: LLMConfig,
     pub logging: LoggingConfig,
+    #[serde(default)]
+    pub calendar: CalendarConfig,
+    #[serde(default)]
+    pub currency: CurrencyConfig,
+    #[serde(default)]
+    pub pricing: PricingConfig,
+    #[serde(default)]
+    pub privacy: PrivacyConfig,
+    /// Commitment sellers attach to their quotes
+    #[serde(default)]
+    pub quote_firmness: QuoteFirmness,
+    /// How long seller quotes stay open
+    #[serde(default)]
+    pub quote_ttl: QuoteTtlConfig,
+    #[serde(default)]
+    pub cancellation: CancellationConfig,
+    /// Commission the platform takes from each payment
+    #[serde(default)]
+    pub fees: FeesConfig,
+    /// Sales tax and VAT added to quotes, by delivery location
+    #[serde(default)]
+    pub tax: TaxConfig,
+    /// Carriers sellers quote shipping with
+    #[serde(default)]
+    pub shipping: ShippingConfig,
+    #[serde(default)]
+    pub expiry: ExpiryConfig,
+    #[serde(default)]
+    pub artifacts: ArtifactConfig,
+    #[serde(default)]
+    pub shared_state: SharedStateConfig,
+    #[serde(default)]
+    pub mcp: McpConfig,
+    #[serde(default)]
+    pub lifecycle: LifecycleConfig,
+    /// Retries, timeouts and circuit breakers for calls to other services
+    #[serde(default)]
+    pub http: HttpConfig,
+    /// Signing key and subscribers for the webhooks agents send
+    #[serde(default)]
+    pub webhooks: WebhookConfig,
+    /// Reputation exchanged with other DCAP instances
+    #[serde(default)]
+    pub gossip: GossipConfig,
+    /// Public marketplace stats served by discovery
+    #[serde(default)]
+    pub explorer: ExplorerConfig,
+    /// Categories the discovery registry restricts
+    #[serde(default)]
+    pub compliance: ComplianceConfig,
+    /// Category hierarchy the discovery registry files products under
+    #[serde(default)]
+    pub taxonomy: TaxonomyConfig,
+    /// Semantic product matching in registry search and buyer browsing
+    #[serde(default)]
+    pub embeddings: EmbeddingsConfig,
+    #[serde(default)]
+    pub templates: TemplatesConfig,
+    #[serde(default)]
+    pub metrics: MetricsConfig,
+    /// Thresholds the settlement service flags suspicious activity at
+    #[serde(default)]
+    pub anomaly: AnomalyConfig,
+    /// Operator keys and thresholds for the `dcap-admin` API
+    #[serde(default)]
+    pub admin: AdminConfig,
+    /// How running services notice a changed config file
+    #[serde(default)]
+    pub reload: ReloadConfig,
+    /// Strategies and models `dcap bench` compares
+    #[serde(default)]
+    pub bench: BenchConfig,
+    /// Response times sellers are scored against
+    #[serde(default)]
+    pub response_sla: ResponseSla,
+    /// Locale used to render prices in CLI output and LLM prompts
+    #[serde(default)]
+    pub locale: Locale,
+    /// Languages agents write negotiation messages in, most preferred first;
+    /// the locale's language when empty
+    #[serde(default)]
+    pub preferred_languages: Vec<Language>,
 }
 
 #[derive(Debug, Deserialize, Clone, Serialize)]
@@ -19,6 +142,71 @@ pub struct ServerConfig {
     pub port: u16,
     pub workers: Option<usize>,
     pub max_connections: Option<usize>,
+    /// Largest request body accepted, in bytes
+    pub max_body_bytes: Option<usize>,
+    /// Browser origins allowed to make state-changing requests; when empty,
+    /// `Origin` isn't checked
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+    /// Requests a client may make per minute; unlimited when unset
+    #[serde(default)]
+    pub rate_limit_per_minute: Option<u32>,
+    /// Per-minute budgets of single routes, by route pattern (`/quote`,
+    /// `/negotiate/:negotiation_id`), counted apart from
+    /// `rate_limit_per_minute`
+    #[serde(default)]
+    pub rate_limit_routes: HashMap<String, u32>,
+    /// Identify clients by the first `X-Forwarded-For` address rather than
+    /// the peer address, for services behind a load balancer
+    #[serde(default)]
+    pub trust_forwarded_for: bool,
+    /// How long requests in progress get to finish on shutdown
+    #[serde(default)]
+    pub shutdown_grace_seconds: Option<u64>,
+    /// How long responses to requests carrying an `Idempotency-Key` are kept
+    /// for replay
+    #[serde(default)]
+    pub idempotency_ttl_hours: Option<u64>,
+    /// How long RFQ and quote nonces are remembered; older messages are
+    /// refused
+    #[serde(default)]
+    pub replay_window_seconds: Option<u64>,
+    /// How far other services' clocks may be from this one's
+    #[serde(default)]
+    pub clock_skew_seconds: Option<u64>,
+    /// Refuse RFQs and quotes that aren't stamped and signed, e.g. from
+    /// older peers
+    #[serde(default = "require_nonces")]
+    pub require_nonces: bool,
+    /// Serves HTTPS and presents the same certificate on outbound calls;
+    /// plain HTTP when unset
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+}
+
+/// Mutual TLS between agents
+#[derive(Debug, Deserialize, Clone, Serialize, Default)]
+pub struct TlsConfig {
+    /// PEM certificate chain, leaf first
+    pub cert_path: String,
+    /// PEM private key of the leaf certificate
+    pub key_path: String,
+    /// Write a self-signed certificate for `hostnames` to the paths when
+    /// they don't exist yet
+    #[serde(default)]
+    pub generate: bool,
+    #[serde(default)]
+    pub hostnames: Vec<String>,
+    /// PEM CA bundle client certificates may chain to
+    #[serde(default)]
+    pub client_ca_path: Option<String>,
+    /// Refuse connections without a client certificate
+    #[serde(default)]
+    pub require_client_cert: bool,
+    /// Hex SHA-256 fingerprints of certificates trusted whatever signed
+    /// them, as clients and as servers
+    #[serde(default)]
+    pub pinned_fingerprints: Vec<String>,
 }
 
 #[derive(Debug, Deserialize, Clone, Serialize)]
@@ -34,6 +222,23 @@ pub struct DiscoveryConfig {
     pub endpoint: String,
     pub cache_ttl_seconds: Option<u64>,
     pub max_cache_size: Option<usize>,
+    /// Where agents register and find sellers: `http` (the discovery
+    /// service), `dns` or `static`
+    #[serde(default)]
+    pub registry: RegistryKind,
+    /// For the `dns` registry: an SRV name such as
+    /// `_http._tcp.sellers.dcap.svc.cluster.local`, or a headless service's
+    /// `host:port`
+    #[serde(default)]
+    pub dns_name: Option<String>,
+    /// For the `static` registry: JSON file listing the agents
+    #[serde(default)]
+    pub static_file: Option<String>,
+    /// For the `dns` registry: JSON file of the agents to trust, giving each
+    /// one's public key, reputation and certificate fingerprint, since
+    /// agents found through DNS only vouch for themselves
+    #[serde(default)]
+    pub trusted_agents_file: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Clone, Serialize)]
@@ -42,14 +247,62 @@ pub struct SettlementConfig {
     pub solana_rpc_url: Option<String>,
     pub escrow_service_url: Option<String>,
     pub webhook_secret: Option<String>,
+    pub delivery_confirmation_timeout_seconds: Option<u64>,
 }
 
 #[derive(Debug, Deserialize, Clone, Serialize)]
 pub struct TrustConfig {
     pub jwt_secret: Option<String>,
+    /// Reputation buyers need before sellers quote them; reloadable
     pub min_reputation_threshold: Option<u32>,
+    /// Share of a reputation change's weight lost per day
     pub reputation_decay_rate: Option<f64>,
+    /// Deal value, in the agent's currency, whose changes carry full weight
+    pub reference_deal_value: Option<f64>,
+    /// Bounds on how much a deal's value scales its change
+    pub min_value_weight: Option<f64>,
+    pub max_value_weight: Option<f64>,
+    /// How strongly weighted changes are normalized by their volume: 0 sums
+    /// them, 1 averages them
+    pub volume_exponent: Option<f64>,
     pub cache_ttl_seconds: Option<u64>,
+    /// Lifetime of agent access tokens
+    pub access_token_ttl_seconds: Option<u64>,
+    /// Lifetime of the refresh tokens traded for new access tokens
+    pub refresh_token_ttl_seconds: Option<u64>,
+    /// Ed25519 keys agent JWTs are signed and checked with. Without any, and
+    /// without `jwks_url`, agent JWTs are signed with `jwt_secret` (HS256)
+    #[serde(default)]
+    pub signing_keys: Vec<JwtKeyConfig>,
+    /// `kid` of the key new agent JWTs are signed with; the last key with a
+    /// private key by default
+    pub active_signing_key: Option<String>,
+    /// JWKS of another issuer whose agent JWTs are accepted, fetched when a
+    /// token names a key not seen yet
+    pub jwks_url: Option<String>,
+    /// Failed or disputed transactions that get an agent greylisted; 0 never
+    /// greylists
+    pub greylist_after_failures: Option<u32>,
+    /// How far back failures count towards greylisting
+    pub failure_window_seconds: Option<u64>,
+    /// How long a greylisting lasts
+    pub greylist_seconds: Option<u64>,
+    /// Permissions of the roles named, replacing their defaults. `agent`
+    /// covers tokens issued before roles
+    #[serde(default)]
+    pub roles: HashMap<String, Vec<Permission>>,
+}
+
+/// An agent JWT signing key. Keys with only a public key check tokens
+/// signed elsewhere, such as by a key being rotated out
+#[derive(Debug, Deserialize, Clone, Serialize)]
+pub struct JwtKeyConfig {
+    /// Defaults to the key's RFC 7638 thumbprint
+    pub kid: Option<String>,
+    /// Base64 32-byte Ed25519 secret key
+    pub private_key: Option<String>,
+    /// Base64 Ed25519 public key
+    pub public_key: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Clone, Serialize)]
@@ -62,11 +315,491 @@ pub struct LLMConfig {
     pub timeout_seconds: Option<u64>,
 }
 
+/// Seller business calendar used for pricing and quote TTLs
+#[derive(Debug, Deserialize, Clone, Serialize)]
+pub struct CalendarConfig {
+    pub timezone: String,
+    pub open_hour: u32,
+    pub close_hour: u32,
+    pub business_days: Vec<String>,
+    pub holidays: Vec<String>,
+}
+
+/// Exchange rates used to compare prices across currencies
+#[derive(Debug, Deserialize, Clone, Serialize)]
+pub struct CurrencyConfig {
+    pub base_currency: String,
+    /// "fixed" uses `rates`; "http" fetches from `rates_endpoint`
+    pub provider: String,
+    pub rates: HashMap<String, f64>,
+    pub rates_endpoint: Option<String>,
+    pub cache_ttl_seconds: Option<u64>,
+}
+
+/// Seller pricing rules. `default` applies to every product unless a rule set
+/// is given for the product's id or, failing that, its category.
+#[derive(Debug, Deserialize, Clone, Serialize)]
+#[serde(default)]
+pub struct PricingConfig {
+    pub default: Vec<PricingRuleConfig>,
+    pub categories: HashMap<String, Vec<PricingRuleConfig>>,
+    pub products: HashMap<String, Vec<PricingRuleConfig>>,
+    pub floors: PriceFloorsConfig,
+}
+
+/// Seconds seller quotes stay open, by product id or, failing that,
+/// category, stretched by `trust_multipliers` for the buyer's trust level
+#[derive(Debug, Deserialize, Clone, Serialize)]
+#[serde(default)]
+pub struct QuoteTtlConfig {
+    pub quote_seconds: u32,
+    /// Counter quotes, capped at the product's quote TTL
+    pub counter_seconds: u32,
+    pub categories: HashMap<String, u32>,
+    pub products: HashMap<String, u32>,
+    pub trust_multipliers: HashMap<TrustLevel, Decimal>,
+}
+
+/// Price floors by product id or, failing that, category. Products with
+/// neither may be priced anywhere the rules take them.
+#[derive(Debug, Default, Deserialize, Clone, Serialize)]
+#[serde(default)]
+pub struct PriceFloorsConfig {
+    pub categories: HashMap<String, PriceFloorConfig>,
+    pub products: HashMap<String, PriceFloorConfig>,
+}
+
+/// Unit prices in the product's currency
+#[derive(Debug, Default, Deserialize, Clone, Serialize)]
+#[serde(default)]
+pub struct PriceFloorConfig {
+    /// Hard floor: no quote, counter quote, rate card or listing reserve goes below it
+    pub min_price: Option<Decimal>,
+    /// Minimum advertised price: quotes to RFQs, demand offers, rate cards and
+    /// listings start no lower, though negotiation may go down to `min_price`
+    pub map_price: Option<Decimal>,
+}
+
+/// A pricing rule; each contributes a multiplier to the quoted price
+#[derive(Debug, Deserialize, Clone, Serialize)]
+#[serde(tag = "rule", rename_all = "snake_case")]
+pub enum PricingRuleConfig {
+    /// Multiplier of the highest tier whose `min_quantity` the order reaches
+    VolumeTiers { tiers: Vec<VolumeTierConfig> },
+    /// Multiplier of the highest tier whose `min_reputation` the buyer reaches
+    ReputationDiscount { tiers: Vec<ReputationTierConfig> },
+    /// Markdown while stock is at or above `min_stock`
+    InventoryPressure { min_stock: u32, multiplier: Decimal },
+    /// Separate multipliers inside and outside the seller's business hours
+    TimeOfDay { business_hours: Decimal, after_hours: Decimal },
+    /// Fixed multiplier, e.g. a demand adjustment
+    Flat { multiplier: Decimal },
+}
+
+#[derive(Debug, Deserialize, Clone, Serialize)]
+pub struct VolumeTierConfig {
+    pub min_quantity: u32,
+    pub multiplier: Decimal,
+}
+
+#[derive(Debug, Deserialize, Clone, Serialize)]
+pub struct ReputationTierConfig {
+    pub min_reputation: u32,
+    pub multiplier: Decimal,
+}
+
+/// Redaction applied to exported negotiation records and market analytics
+#[derive(Debug, Deserialize, Clone, Serialize)]
+#[serde(default)]
+pub struct PrivacyConfig {
+    pub enabled: bool,
+    /// Keys the counterparty pseudonyms; identifiers are dropped when unset
+    pub pseudonym_secret: Option<String>,
+    /// Prices are reported as bands of this width
+    pub price_band_width: Decimal,
+    /// Price deltas are rounded to the nearest multiple of this
+    pub delta_rounding: Decimal,
+    /// Products with fewer deals are left out of market reports
+    pub min_group_size: usize,
+    /// Laplace noise on reported deal counts; smaller is noisier
+    pub count_noise_epsilon: Option<f64>,
+}
+
+/// Penalties for calling off an accepted deal without the counterparty's
+/// consent, or refunding it
+#[derive(Debug, Deserialize, Clone, Serialize)]
+#[serde(default)]
+pub struct CancellationConfig {
+    /// Share of the agreed price a withdrawing buyer owes the seller
+    pub withdrawal_fee_rate: Decimal,
+    /// Reputation points the cancelling party loses
+    pub reputation_penalty: u32,
+    /// Reputation points a seller loses for a refund they're at fault for
+    pub refund_reputation_penalty: u32,
+}
+
+/// Marketplace fees, by payment method
+#[derive(Debug, Deserialize, Clone, Serialize, Default)]
+#[serde(default)]
+pub struct FeesConfig {
+    /// Fee for payment methods without a rule of their own
+    pub default: FeeRule,
+    pub methods: HashMap<PaymentMethod, FeeRule>,
+}
+
+#[derive(Debug, Deserialize, Clone, Serialize, Default)]
+#[serde(default)]
+pub struct FeeRule {
+    /// Share of the payment's amount
+    pub rate: Decimal,
+    /// Charged on top, in the payment's currency
+    pub flat: Decimal,
+}
+
+/// Tax rates by jurisdiction. Jurisdictions are matched against RFQ
+/// delivery locations, e.g. `DE` or `US-CA`
+#[derive(Debug, Deserialize, Clone, Serialize)]
+#[serde(default)]
+pub struct TaxConfig {
+    /// Name of the tax on quotes and payments, e.g. "VAT"
+    pub name: String,
+    /// Rate where no jurisdiction covers the delivery location
+    pub rate: Decimal,
+    pub jurisdictions: HashMap<String, JurisdictionTaxConfig>,
+}
+
+#[derive(Debug, Deserialize, Clone, Serialize, Default)]
+#[serde(default)]
+pub struct JurisdictionTaxConfig {
+    /// Overrides the tax's name in this jurisdiction
+    pub name: Option<String>,
+    pub rate: Decimal,
+    /// Rates for categories taxed differently, e.g. reduced-rate books
+    pub categories: HashMap<String, Decimal>,
+}
+
+/// Carriers a seller ships with; quotes offer those serving the RFQ's
+/// delivery location
+#[derive(Debug, Deserialize, Clone, Serialize, Default)]
+#[serde(default)]
+pub struct ShippingConfig {
+    pub carriers: Vec<CarrierConfig>,
+}
+
+/// Costs are in the quote's currency
+#[derive(Debug, Deserialize, Clone, Serialize)]
+pub struct CarrierConfig {
+    pub carrier: String,
+    #[serde(default)]
+    pub base_cost: Decimal,
+    #[serde(default)]
+    pub per_unit_cost: Decimal,
+    pub eta_days: u32,
+    /// Locations delivered to, e.g. `US` or `DE`; anywhere when empty
+    #[serde(default)]
+    pub locations: Vec<String>,
+}
+
+/// Warnings sellers send before firm quotes lapse, and how buyers answer them
+#[derive(Debug, Deserialize, Clone, Serialize)]
+#[serde(default)]
+pub struct ExpiryConfig {
+    /// How long before a quote lapses the seller warns the buyer; 0 turns
+    /// reminders off
+    pub warning_seconds: u64,
+    /// How often the seller looks for quotes about to lapse
+    pub check_interval_seconds: u64,
+    pub on_warning: ExpiryAction,
+    /// How often the buyer expires negotiations past their RFQ deadline; 0
+    /// leaves them open until acted on
+    pub deadline_check_seconds: u64,
+}
+
+/// Where replicas of a service keep their shared caches, rate limits and locks
+#[derive(Debug, Deserialize, Clone, Serialize)]
+#[serde(default)]
+pub struct SharedStateConfig {
+    /// Redis URL, e.g. `redis://localhost:6379`; the state stays in process
+    /// when unset. Needs the `redis` feature
+    pub redis_url: Option<String>,
+    /// Prefix of every key written to Redis
+    pub key_prefix: String,
+    /// How long discovery search results are cached
+    pub search_cache_seconds: u64,
+    /// How long a negotiation lock is held before it lapses
+    pub lock_ttl_ms: u64,
+    /// How long a request waits for a negotiation lock
+    pub lock_wait_ms: u64,
+}
+
+/// Connection handling of the MCP server
+#[derive(Debug, Deserialize, Clone, Serialize)]
+#[serde(default)]
+pub struct McpConfig {
+    /// Connections served at once; further clients wait to be accepted
+    pub max_connections: usize,
+    /// How long a connection may sit without sending a request before it's
+    /// closed
+    pub idle_timeout_seconds: u64,
+    /// How long writing a response may take before the connection is closed
+    pub write_timeout_seconds: u64,
+    /// How long shutdown waits for requests in progress to finish
+    pub shutdown_grace_seconds: u64,
+}
+
+/// How services start up when deployed as separate containers
+#[derive(Debug, Deserialize, Clone, Serialize)]
+#[serde(default)]
+pub struct LifecycleConfig {
+    /// How long a service waits for its database and the discovery registry
+    pub startup_timeout_seconds: u64,
+    /// Labels file of a downward API volume, read into agent identity when
+    /// present
+    pub pod_labels_path: String,
+}
+
+impl LifecycleConfig {
+    pub fn startup_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.startup_timeout_seconds)
+    }
+}
+
+/// How calls to other services are retried and cut off
+#[derive(Debug, Deserialize, Clone, Serialize)]
+#[serde(default)]
+pub struct HttpConfig {
+    /// Retries after the first attempt; 0 turns retrying off
+    pub max_retries: u32,
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+    /// Time allowed for a whole request, including reading the response
+    pub timeout_ms: u64,
+    pub connect_timeout_ms: u64,
+    /// Failures in a row before calls to an endpoint fail fast
+    pub failure_threshold: u32,
+    /// How long an endpoint's circuit stays open before a trial call
+    pub open_seconds: u64,
+}
+
+/// How agents sign the webhooks they send, and where they forward their
+/// negotiation events
+#[derive(Debug, Deserialize, Clone, Serialize)]
+#[serde(default)]
+pub struct WebhookConfig {
+    /// Environment variable holding the agent's base64 Ed25519 signing key;
+    /// a key is generated on startup when it isn't set
+    pub signing_key_env: String,
+    /// URLs every negotiation event is posted to, signed
+    pub endpoints: Vec<String>,
+}
+
+/// Signed reputation attestations exchanged with other instances
+#[derive(Debug, Deserialize, Clone, Serialize)]
+#[serde(default)]
+pub struct GossipConfig {
+    /// Base64 Ed25519 secret key this instance signs its attestations with;
+    /// none are served without one
+    pub signing_key: Option<String>,
+    /// Instances whose attestations are pulled and trusted
+    pub peers: Vec<GossipPeer>,
+    /// How often peers' attestations are pulled
+    pub interval_seconds: u64,
+}
+
+#[derive(Debug, Deserialize, Clone, Serialize)]
+pub struct GossipPeer {
+    /// Base URL of the peer serving `/reputation/attestations`
+    pub url: String,
+    /// Base64 Ed25519 public key the peer signs its attestations with
+    pub public_key: String,
+}
+
+/// The operator API served by `dcap-admin serve`
+#[derive(Debug, Deserialize, Clone, Serialize)]
+#[serde(default)]
+pub struct AdminConfig {
+    /// Base64 Ed25519 public keys whose holders may adjust reputations; no
+    /// adjustment is accepted without one
+    pub operator_keys: Vec<String>,
+    /// Open negotiations untouched for this long are reported as stuck
+    pub stuck_after_minutes: u64,
+}
+
+/// Picking up config changes without a restart; SIGHUP always reloads
+#[derive(Debug, Deserialize, Clone, Serialize)]
+#[serde(default)]
+pub struct ReloadConfig {
+    /// Seconds between checks of the config file's modification time; 0
+    /// leaves reloading to SIGHUP
+    pub watch_interval_seconds: u64,
+}
+
+/// Discovery's public `/explorer` routes
+#[derive(Debug, Deserialize, Clone, Serialize)]
+#[serde(default)]
+pub struct ExplorerConfig {
+    /// Requests each client may make to each explorer route per minute; 0
+    /// leaves them to the `[server]` limits
+    pub rate_limit_per_minute: u32,
+    /// Deals listed by `/explorer/deals`, up to 100
+    pub recent_deals: usize,
+    pub top_categories: usize,
+    /// Sellers seen within this many days count as active
+    pub active_seller_days: u32,
+}
+
+/// Where contracts, invoices, attachments and audit exports are kept
+#[derive(Debug, Deserialize, Clone, Serialize)]
+#[serde(default)]
+pub struct ArtifactConfig {
+    pub backend: ArtifactBackendKind,
+    /// Directory the local backend writes to
+    pub local_dir: String,
+    pub s3: S3Config,
+    /// Largest artifact accepted, in bytes
+    pub max_bytes: usize,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Serialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ArtifactBackendKind {
+    #[default]
+    Local,
+    S3,
+}
+
+#[derive(Debug, Deserialize, Clone, Serialize, Default)]
+#[serde(default)]
+pub struct S3Config {
+    pub bucket: String,
+    pub region: String,
+    /// S3-compatible endpoint such as MinIO, addressed path-style; AWS
+    /// virtual-hosted addressing when unset
+    pub endpoint: Option<String>,
+    /// Key prefix inside the bucket
+    pub prefix: String,
+    /// Fall back to `AWS_ACCESS_KEY_ID` and `AWS_SECRET_ACCESS_KEY`
+    pub access_key_id: Option<String>,
+    pub secret_access_key: Option<String>,
+}
+
+/// Categories (age-gated or regulated goods) the registry only lists for
+/// attested sellers and allowed regions
+#[derive(Debug, Deserialize, Clone, Serialize, Default)]
+#[serde(default)]
+pub struct ComplianceConfig {
+    /// Base64 ed25519 keys whose seller attestations the registry accepts
+    pub trusted_attestors: Vec<String>,
+    pub restricted_categories: Vec<CategoryRestriction>,
+}
+
+#[derive(Debug, Deserialize, Clone, Serialize)]
+pub struct CategoryRestriction {
+    pub category: String,
+    /// Sellers need a current attestation for the category from a trusted attestor
+    #[serde(default)]
+    pub requires_attestation: bool,
+    /// Regions the category may be sold in and to; any region when empty
+    #[serde(default)]
+    pub allowed_regions: Vec<String>,
+}
+
+/// Parent/child categories; a product listed under a category the registry
+/// knows is filed under its full path
+#[derive(Debug, Deserialize, Clone, Serialize, Default)]
+#[serde(default)]
+pub struct TaxonomyConfig {
+    pub categories: Vec<CategoryNode>,
+}
+
+/// How products are embedded for semantic matching
+#[derive(Debug, Deserialize, Clone, Serialize)]
+#[serde(default)]
+pub struct EmbeddingsConfig {
+    pub provider: EmbeddingProviderKind,
+    /// Base URL of an OpenAI-compatible embeddings API, for the http provider
+    pub endpoint: Option<String>,
+    /// Model the http provider asks for
+    pub model: String,
+    /// Environment variable holding the API key, if the endpoint needs one
+    pub api_key_env: Option<String>,
+    /// Size of the hashing provider's vectors
+    pub dimensions: usize,
+    /// Least cosine similarity a product needs to match a query
+    pub min_similarity: f64,
+    /// Score a perfect match adds to a product's text relevance
+    pub weight: f64,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Serialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EmbeddingProviderKind {
+    #[default]
+    Off,
+    Hashing,
+    Http,
+}
+
+/// Seller message templates replacing the built-in ones, per kind and language
+#[derive(Debug, Deserialize, Clone, Serialize, Default)]
+pub struct TemplatesConfig {
+    #[serde(flatten)]
+    pub overrides: HashMap<TemplateKind, HashMap<Language, String>>,
+}
+
+/// Prometheus metrics served at `/metrics`
+#[derive(Debug, Deserialize, Clone, Serialize, Default)]
+#[serde(default)]
+pub struct MetricsConfig {
+    pub enabled: bool,
+}
+
+/// Anomaly detection the settlement service runs over recent activity
+#[derive(Debug, Deserialize, Clone, Serialize)]
+#[serde(default)]
+pub struct AnomalyConfig {
+    /// How often the analyzer runs; 0 turns it off
+    pub interval_seconds: u64,
+    /// Recent activity analyzed on each run
+    pub window_hours: u32,
+    /// History before the window that usual activity is measured over
+    pub baseline_days: u32,
+    /// Reputation an agent must gain over the window to be flagged
+    pub reputation_spike_points: i32,
+    /// How many times its usual gain per window an agent must gain
+    pub reputation_spike_factor: f64,
+    /// Standard deviations from the mean settled price a quote may be
+    pub price_band_deviations: f64,
+    /// Settled deals a product needs before its quotes are checked
+    pub min_price_samples: usize,
+    /// Deals between the same two agents over the window that are flagged
+    pub wash_trade_min_deals: usize,
+}
+
+/// Strategy benchmark run by `dcap bench`
+#[derive(Debug, Deserialize, Clone, Serialize)]
+#[serde(default)]
+pub struct BenchConfig {
+    /// Strategy names as the buyer's `auto` command takes them
+    pub strategies: Vec<String>,
+    /// Language models to benchmark alongside the strategies
+    pub llm: Vec<LLMConfig>,
+    pub scenarios: usize,
+    pub seed: u64,
+    pub max_rounds: u32,
+}
+
 #[derive(Debug, Deserialize, Clone, Serialize)]
 pub struct LoggingConfig {
     pub level: String,
     pub format: Option<String>,
     pub file: Option<String>,
+    /// OTLP/HTTP traces endpoint, e.g. `http://localhost:4318/v1/traces`;
+    /// spans are only exported when set
+    pub otlp_endpoint: Option<String>,
+    /// Fraction of new traces to sample, 0.0-1.0 (all of them by default).
+    /// Services downstream follow the caller's decision.
+    pub trace_sample_ratio: Option<f64>,
 }
 
 impl Default for AppConfig {
@@ -79,6 +812,37 @@ impl Default for AppConfig {
             trust: TrustConfig::default(),
             llm: LLMConfig::default(),
             logging: LoggingConfig::default(),
+            calendar: CalendarConfig::default(),
+            currency: CurrencyConfig::default(),
+            pricing: PricingConfig::default(),
+            privacy: PrivacyConfig::default(),
+            quote_firmness: QuoteFirmness::default(),
+            quote_ttl: QuoteTtlConfig::default(),
+            cancellation: CancellationConfig::default(),
+            fees: FeesConfig::default(),
+            tax: TaxConfig::default(),
+            shipping: ShippingConfig::default(),
+            expiry: ExpiryConfig::default(),
+            artifacts: ArtifactConfig::default(),
+            shared_state: SharedStateConfig::default(),
+            mcp: McpConfig::default(),
+            lifecycle: LifecycleConfig::default(),
+            http: HttpConfig::default(),
+            webhooks: WebhookConfig::default(),
+            gossip: GossipConfig::default(),
+            explorer: ExplorerConfig::default(),
+            compliance: ComplianceConfig::default(),
+            taxonomy: TaxonomyConfig::default(),
+            embeddings: EmbeddingsConfig::default(),
+            templates: TemplatesConfig::default(),
+            metrics: MetricsConfig::default(),
+            anomaly: AnomalyConfig::default(),
+            admin: AdminConfig::default(),
+            reload: ReloadConfig::default(),
+            bench: BenchConfig::default(),
+            response_sla: ResponseSla::default(),
+            locale: Locale::default(),
+            preferred_languages: Vec::new(),
         }
     }
 }
@@ -90,10 +854,25 @@ impl Default for ServerConfig {
             port: 8000,
             workers: Some(4),
             max_connections: Some(1000),
+            max_body_bytes: Some(1_048_576),
+            allowed_origins: Vec::new(),
+            rate_limit_per_minute: None,
+            rate_limit_routes: HashMap::new(),
+            trust_forwarded_for: false,
+            shutdown_grace_seconds: Some(30),
+            idempotency_ttl_hours: None,
+            replay_window_seconds: None,
+            clock_skew_seconds: None,
+            require_nonces: true,
+            tls: None,
         }
     }
 }
 
+fn require_nonces() -> bool {
+    true
+}
+
 impl Default for DatabaseConfig {
     fn default() -> Self {
         Self {
@@ -111,6 +890,10 @@ impl Default for DiscoveryConfig {
             endpoint: "http://localhost:8000".to_string(),
             cache_ttl_seconds: Some(300),
             max_cache_size: Some(1000),
+            registry: RegistryKind::Http,
+            dns_name: None,
+            static_file: None,
+            trusted_agents_file: None,
         }
     }
 }
@@ -122,6 +905,7 @@ impl Default for SettlementConfig {
             solana_rpc_url: None,
             escrow_service_url: None,
             webhook_secret: None,
+            delivery_confirmation_timeout_seconds: Some(259200),
         }
     }
 }
@@ -130,9 +914,22 @@ impl Default for TrustConfig {
     fn default() -> Self {
         Self {
             jwt_secret: None,
-            min_reputation_threshold: Some(50),
+            min_reputation_threshold: Some(DEFAULT_MIN_BUYER_REPUTATION),
             reputation_decay_rate: Some(0.01),
+            reference_deal_value: Some(1000.0),
+            min_value_weight: Some(0.1),
+            max_value_weight: Some(3.0),
+            volume_exponent: Some(0.5),
             cache_ttl_seconds: Some(1800),
+            access_token_ttl_seconds: Some(900),
+            refresh_token_ttl_seconds: Some(604800),
+            signing_keys: Vec::new(),
+            active_signing_key: None,
+            jwks_url: None,
+            greylist_after_failures: Some(3),
+            failure_window_seconds: Some(2592000),
+            greylist_seconds: Some(604800),
+            roles: HashMap::new(),
         }
     }
 }
@@ -150,12 +947,262 @@ impl Default for LLMConfig {
     }
 }
 
+impl Default for CalendarConfig {
+    fn default() -> Self {
+        Self {
+            timezone: "UTC".to_string(),
+            open_hour: 9,
+            close_hour: 17,
+            business_days: ["Mon", "Tue", "Wed", "Thu", "Fri"].iter().map(|d| d.to_string()).collect(),
+            holidays: Vec::new(),
+        }
+    }
+}
+
+impl Default for QuoteTtlConfig {
+    fn default() -> Self {
+        Self {
+            quote_seconds: DEFAULT_QUOTE_TTL_SECONDS,
+            counter_seconds: DEFAULT_COUNTER_TTL_SECONDS,
+            categories: HashMap::new(),
+            products: HashMap::new(),
+            trust_multipliers: HashMap::from([(TrustLevel::HighlyTrusted, DEFAULT_HIGHLY_TRUSTED_MULTIPLIER)]),
+        }
+    }
+}
+
+impl Default for CurrencyConfig {
+    fn default() -> Self {
+        Self {
+            base_currency: "USD".to_string(),
+            provider: "fixed".to_string(),
+            rates: HashMap::new(),
+            rates_endpoint: None,
+            cache_ttl_seconds: Some(3600),
+        }
+    }
+}
+
+impl Default for PricingConfig {
+    fn default() -> Self {
+        Self {
+            default: vec![
+                PricingRuleConfig::VolumeTiers {
+                    tiers: vec![VolumeTierConfig { min_quantity: 11, multiplier: Decimal::new(95, 2) }],
+                },
+                PricingRuleConfig::ReputationDiscount {
+                    tiers: vec![ReputationTierConfig { min_reputation: 80, multiplier: Decimal::new(98, 2) }],
+                },
+                PricingRuleConfig::TimeOfDay {
+                    business_hours: BUSINESS_HOURS_PREMIUM,
+                    after_hours: Decimal::ONE,
+                },
+                PricingRuleConfig::Flat { multiplier: Decimal::new(101, 2) },
+            ],
+            categories: HashMap::new(),
+            products: HashMap::new(),
+            floors: PriceFloorsConfig::default(),
+        }
+    }
+}
+
+impl Default for PrivacyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            pseudonym_secret: None,
+            price_band_width: Decimal::from(100),
+            delta_rounding: Decimal::from(10),
+            min_group_size: 5,
+            count_noise_epsilon: None,
+        }
+    }
+}
+
+impl Default for CancellationConfig {
+    fn default() -> Self {
+        Self {
+            withdrawal_fee_rate: Decimal::new(5, 2),
+            reputation_penalty: 5,
+            refund_reputation_penalty: 3,
+        }
+    }
+}
+
+impl Default for TaxConfig {
+    fn default() -> Self {
+        Self {
+            name: "Sales tax".to_string(),
+            rate: Decimal::ZERO,
+            jurisdictions: HashMap::new(),
+        }
+    }
+}
+
+impl Default for ExpiryConfig {
+    fn default() -> Self {
+        Self {
+            warning_seconds: DEFAULT_WARNING_SECONDS,
+            check_interval_seconds: DEFAULT_CHECK_INTERVAL_SECONDS,
+            on_warning: ExpiryAction::default(),
+            deadline_check_seconds: DEFAULT_DEADLINE_CHECK_SECONDS,
+        }
+    }
+}
+
+impl Default for EmbeddingsConfig {
+    fn default() -> Self {
+        Self {
+            provider: EmbeddingProviderKind::default(),
+            endpoint: None,
+            model: String::new(),
+            api_key_env: None,
+            dimensions: DEFAULT_EMBEDDING_DIMENSIONS,
+            min_similarity: DEFAULT_MIN_SIMILARITY,
+            weight: DEFAULT_SEMANTIC_WEIGHT,
+        }
+    }
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: DEFAULT_MAX_RETRIES,
+            initial_backoff_ms: DEFAULT_INITIAL_BACKOFF_MS,
+            max_backoff_ms: DEFAULT_MAX_BACKOFF_MS,
+            timeout_ms: DEFAULT_TIMEOUT_MS,
+            connect_timeout_ms: DEFAULT_CONNECT_TIMEOUT_MS,
+            failure_threshold: DEFAULT_FAILURE_THRESHOLD,
+            open_seconds: DEFAULT_OPEN_SECONDS,
+        }
+    }
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self {
+            signing_key_env: DEFAULT_SIGNING_KEY_ENV.to_string(),
+            endpoints: Vec::new(),
+        }
+    }
+}
+
+impl Default for GossipConfig {
+    fn default() -> Self {
+        Self {
+            signing_key: None,
+            peers: Vec::new(),
+            interval_seconds: DEFAULT_GOSSIP_INTERVAL_SECONDS,
+        }
+    }
+}
+
+impl Default for ReloadConfig {
+    fn default() -> Self {
+        Self {
+            watch_interval_seconds: DEFAULT_WATCH_INTERVAL_SECONDS,
+        }
+    }
+}
+
+impl Default for AdminConfig {
+    fn default() -> Self {
+        Self {
+            operator_keys: Vec::new(),
+            stuck_after_minutes: DEFAULT_STUCK_AFTER_MINUTES,
+        }
+    }
+}
+
+impl Default for ExplorerConfig {
+    fn default() -> Self {
+        Self {
+            rate_limit_per_minute: DEFAULT_EXPLORER_RATE_LIMIT_PER_MINUTE,
+            recent_deals: DEFAULT_RECENT_DEALS,
+            top_categories: DEFAULT_TOP_CATEGORIES,
+            active_seller_days: DEFAULT_ACTIVE_SELLER_DAYS,
+        }
+    }
+}
+
+impl Default for SharedStateConfig {
+    fn default() -> Self {
+        Self {
+            redis_url: None,
+            key_prefix: DEFAULT_KEY_PREFIX.to_string(),
+            search_cache_seconds: DEFAULT_SEARCH_CACHE_SECONDS,
+            lock_ttl_ms: DEFAULT_LOCK_TTL_MS,
+            lock_wait_ms: DEFAULT_LOCK_WAIT_MS,
+        }
+    }
+}
+
+impl Default for McpConfig {
+    fn default() -> Self {
+        Self {
+            max_connections: DEFAULT_MCP_MAX_CONNECTIONS,
+            idle_timeout_seconds: DEFAULT_MCP_IDLE_TIMEOUT_SECONDS,
+            write_timeout_seconds: DEFAULT_MCP_WRITE_TIMEOUT_SECONDS,
+            shutdown_grace_seconds: DEFAULT_MCP_SHUTDOWN_GRACE_SECONDS,
+        }
+    }
+}
+
+impl Default for LifecycleConfig {
+    fn default() -> Self {
+        Self {
+            startup_timeout_seconds: DEFAULT_STARTUP_TIMEOUT_SECONDS,
+            pod_labels_path: DEFAULT_POD_LABELS_PATH.to_string(),
+        }
+    }
+}
+
+impl Default for AnomalyConfig {
+    fn default() -> Self {
+        Self {
+            interval_seconds: DEFAULT_ANOMALY_INTERVAL_SECONDS,
+            window_hours: DEFAULT_ANOMALY_WINDOW_HOURS,
+            baseline_days: DEFAULT_ANOMALY_BASELINE_DAYS,
+            reputation_spike_points: DEFAULT_REPUTATION_SPIKE_POINTS,
+            reputation_spike_factor: DEFAULT_REPUTATION_SPIKE_FACTOR,
+            price_band_deviations: DEFAULT_PRICE_BAND_DEVIATIONS,
+            min_price_samples: DEFAULT_MIN_PRICE_SAMPLES,
+            wash_trade_min_deals: DEFAULT_WASH_TRADE_MIN_DEALS,
+        }
+    }
+}
+
+impl Default for BenchConfig {
+    fn default() -> Self {
+        Self {
+            strategies: ["linear", "conceder", "boulware", "tit_for_tat"].map(String::from).to_vec(),
+            llm: Vec::new(),
+            scenarios: DEFAULT_BENCH_SCENARIOS,
+            seed: DEFAULT_BENCH_SEED,
+            max_rounds: DEFAULT_MAX_ROUNDS,
+        }
+    }
+}
+
+impl Default for ArtifactConfig {
+    fn default() -> Self {
+        Self {
+            backend: ArtifactBackendKind::default(),
+            local_dir: "artifacts".to_string(),
+            s3: S3Config::default(),
+            max_bytes: DEFAULT_MAX_ARTIFACT_BYTES,
+        }
+    }
+}
+
 impl Default for LoggingConfig {
     fn default() -> Self {
         Self {
             level: "info".to_string(),
             format: Some("json".to_string()),
             file: None,
+            otlp_endpoint: None,
+            trace_sample_ratio: None,
         }
     }
 }
@@ -191,6 +1238,10 @@ impl AppConfig {
             config.llm.api_key = Some(llm_key);
         }
 
+        if let Ok(pseudonym_secret) = std::env::var("PRIVACY_PSEUDONYM_SECRET") {
+            config.privacy.pseudonym_secret = Some(pseudonym_secret);
+        }
+
         if let Ok(log_level) = std::env::var("RUST_LOG") {
             config.logging.level = log_level;
         }
@@ -219,6 +1270,13 @@ impl AppConfig {
             return Err(crate::error::NegotiationError::Config("LLM model cannot be empty".to_string()));
         }
 
+        // Validate trust config
+        // `TrustSystem` falls back to `JWT_SECRET`, but never to a built-in key
+        let jwt_secret = self.trust.jwt_secret.clone().or_else(|| std::env::var("JWT_SECRET").ok());
+        if jwt_secret.unwrap_or_default().is_empty() {
+            return Err(crate::error::NegotiationError::Config("JWT secret must be set (trust.jwt_secret or JWT_SECRET)".to_string()));
+        }
+
         Ok(())
     }
 
@@ -278,12 +1336,20 @@ mod tests {
     #[test]
     fn test_config_validation() {
         let mut config = AppConfig::default();
+        config.trust.jwt_secret = Some("test-secret".to_string());
         assert!(config.validate().is_ok());
 
         config.server.port = 0;
         assert!(config.validate().is_err());
     }
 
+    #[test]
+    fn test_config_validation_requires_a_jwt_secret() {
+        let mut config = AppConfig::default();
+        config.trust.jwt_secret = Some(String::new());
+        assert!(config.validate().is_err());
+    }
+
     #[tokio::test]
     async fn test_config_file_creation() {
         let temp_file = NamedTempFile::new().unwrap();
@@ -295,4 +1361,4 @@ mod tests {
         let loaded_config = AppConfig::load(path).unwrap();
         assert_eq!(loaded_config.server.port, 8000);
     }
-}
\ No newline at end of file
+}
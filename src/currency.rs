@@ -0,0 +1,221 @@
+//! Currency validation and conversion.
+//!
+//! RFQs are priced in the buyer's currency and quotes in the seller's. An
+//! `ExchangeRateProvider` supplies the rates used to compare the two; the
+//! fixed-rate provider suits tests and closed markets, the HTTP provider reads
+//! live rates from a Frankfurter-compatible API.
+
+use crate::{
+    config::CurrencyConfig,
+    error::{NegotiationError, Result},
+    money::{decimal_from_f64, Money},
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use reqwest::Client;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+pub const DEFAULT_CURRENCY: &str = "USD";
+
+pub fn default_currency() -> String {
+    DEFAULT_CURRENCY.to_string()
+}
+
+/// Checks that a currency is an ISO 4217 style code, e.g. "USD".
+pub fn validate_currency_code(code: &str) -> Result<()> {
+    if code.len() == 3 && code.chars().all(|c| c.is_ascii_uppercase()) {
+        Ok(())
+    } else {
+        Err(NegotiationError::Currency(format!("Invalid currency code: {}", code)))
+    }
+}
+
+#[async_trait]
+pub trait ExchangeRateProvider: Send + Sync {
+    /// Units of `to` bought by one unit of `from`.
+    async fn rate(&self, from: &str, to: &str) -> Result<Decimal>;
+}
+
+/// Static rates expressed against a single base currency. Cross rates are
+/// derived through the base.
+#[derive(Debug, Clone)]
+pub struct FixedRateProvider {
+    base: String,
+    rates: HashMap<String, Decimal>,
+}
+
+impl FixedRateProvider {
+    pub fn new(base: impl Into<String>) -> Self {
+        Self {
+            base: base.into(),
+            rates: HashMap::new(),
+        }
+    }
+
+    /// One unit of the base currency buys `rate` units of `currency`.
+    pub fn with_rate(mut self, currency: impl Into<String>, rate: Decimal) -> Self {
+        self.rates.insert(currency.into(), rate);
+        self
+    }
+
+    fn rate_from_base(&self, currency: &str) -> Result<Decimal> {
+        if currency == self.base {
+            return Ok(Decimal::ONE);
+        }
+        self.rates.get(currency)
+            .copied()
+            .filter(|rate| *rate > Decimal::ZERO)
+            .ok_or_else(|| NegotiationError::Currency(format!("No exchange rate for {}", currency)))
+    }
+}
+
+#[async_trait]
+impl ExchangeRateProvider for FixedRateProvider {
+    async fn rate(&self, from: &str, to: &str) -> Result<Decimal> {
+        Ok(self.rate_from_base(to)? / self.rate_from_base(from)?)
+    }
+}
+
+/// Cached rate per (from, to) pair with the time it was fetched
+type RateCache = HashMap<(String, String), (Decimal, DateTime<Utc>)>;
+
+#[derive(Debug, Deserialize)]
+struct RatesResponse {
+    rates: HashMap<String, f64>,
+}
+
+/// Fetches rates over HTTP from a Frankfurter-compatible endpoint
+/// (`GET {endpoint}/latest?from=USD&to=EUR`), caching each pair for a while.
+pub struct HttpRateProvider {
+    client: Client,
+    endpoint: String,
+    cache_ttl: Duration,
+    cache: Mutex<RateCache>,
+}
+
+impl HttpRateProvider {
+    pub fn new(endpoint: String, cache_ttl_seconds: u64) -> Self {
+        Self {
+            client: Client::new(),
+            endpoint,
+            cache_ttl: Duration::seconds(cache_ttl_seconds as i64),
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn cached(&self, key: &(String, String)) -> Option<Decimal> {
+        let cache = self.cache.lock().ok()?;
+        cache.get(key)
+            .filter(|(_, fetched_at)| Utc::now() - *fetched_at < self.cache_ttl)
+            .map(|(rate, _)| *rate)
+    }
+}
+
+#[async_trait]
+impl ExchangeRateProvider for HttpRateProvider {
+    async fn rate(&self, from: &str, to: &str) -> Result<Decimal> {
+        if from == to {
+            return Ok(Decimal::ONE);
+        }
+
+        let key = (from.to_string(), to.to_string());
+        if let Some(rate) = self.cached(&key) {
+            return Ok(rate);
+        }
+
+        let response: RatesResponse = self.client
+            .get(format!("{}/latest", self.endpoint.trim_end_matches('/')))
+            .query(&[("from", from), ("to", to)])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let rate = response.rates.get(to)
+            .copied()
+            .ok_or_else(|| NegotiationError::Currency(format!("No exchange rate from {} to {}", from, to)))
+            .and_then(decimal_from_f64)?;
+
+        if let Ok(mut cache) = self.cache.lock() {
+            cache.insert(key, (rate, Utc::now()));
+        }
+        Ok(rate)
+    }
+}
+
+/// Converts amounts between currencies using a pluggable rate provider.
+#[derive(Clone)]
+pub struct CurrencyConverter {
+    provider: Arc<dyn ExchangeRateProvider>,
+}
+
+impl CurrencyConverter {
+    pub fn new(provider: Arc<dyn ExchangeRateProvider>) -> Self {
+        Self { provider }
+    }
+
+    pub fn from_config(config: &CurrencyConfig) -> Result<Self> {
+        let provider: Arc<dyn ExchangeRateProvider> = match config.provider.as_str() {
+            "fixed" => {
+                let provider = config.rates.iter()
+                    .try_fold(FixedRateProvider::new(config.base_currency.clone()), |provider, (currency, rate)| {
+                        Ok::<_, NegotiationError>(provider.with_rate(currency.clone(), decimal_from_f64(*rate)?))
+                    })?;
+                Arc::new(provider)
+            }
+            "http" => {
+                let endpoint = config.rates_endpoint.clone()
+                    .ok_or_else(|| NegotiationError::Config("HTTP exchange rate provider requires rates_endpoint".to_string()))?;
+                Arc::new(HttpRateProvider::new(endpoint, config.cache_ttl_seconds.unwrap_or(3600)))
+            }
+            other => return Err(NegotiationError::Config(format!("Unknown exchange rate provider: {}", other))),
+        };
+        Ok(Self::new(provider))
+    }
+
+    pub async fn convert(&self, money: &Money, to: &str) -> Result<Money> {
+        validate_currency_code(&money.currency)?;
+        validate_currency_code(to)?;
+        if money.currency == to {
+            return Ok(money.clone());
+        }
+        let rate = self.provider.rate(&money.currency, to).await?;
+        Ok(Money::new(money.amount * rate, to))
+    }
+}
+
+impl Default for CurrencyConverter {
+    fn default() -> Self {
+        Self::new(Arc::new(FixedRateProvider::new(DEFAULT_CURRENCY)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_currency_code_validation() {
+        assert!(validate_currency_code("EUR").is_ok());
+        assert!(validate_currency_code("eur").is_err());
+        assert!(validate_currency_code("EURO").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_fixed_rate_conversion() {
+        let provider = FixedRateProvider::new("USD")
+            .with_rate("EUR", Decimal::new(5, 1))
+            .with_rate("GBP", Decimal::new(25, 2));
+        let converter = CurrencyConverter::new(Arc::new(provider));
+        let hundred = |currency: &str| Money::new(Decimal::from(100), currency);
+
+        assert_eq!(converter.convert(&hundred("USD"), "USD").await.unwrap(), hundred("USD"));
+        assert_eq!(converter.convert(&hundred("USD"), "EUR").await.unwrap(), Money::new(Decimal::from(50), "EUR"));
+        assert_eq!(converter.convert(&hundred("EUR"), "GBP").await.unwrap(), Money::new(Decimal::from(50), "GBP"));
+        assert!(converter.convert(&hundred("USD"), "JPY").await.is_err());
+    }
+}
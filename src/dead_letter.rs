@@ -0,0 +1,220 @@
+//! Persisted dead-letter store for workflows that failed after the caller
+//! moved on: settlements, webhook deliveries, and reputation updates. Entries
+//! keep the original payload and error so operators can inspect and replay them.
+//! [`router`] serves them under `/admin/dead-letters` to operator JWTs only.
+
+use crate::{
+    database::Database,
+    error::{ApiResult, Result},
+    roles::{self, Permission},
+    settlement::SettlementService,
+    trust::TrustSystem,
+    AgentId,
+};
+use axum::{
+    extract::{Path, Query, State},
+    response::Json,
+    routing::{get, post},
+    Router,
+};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DeadLetterKind {
+    Settlement,
+    Webhook,
+    ReputationUpdate,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DeadLetterStatus {
+    Pending,
+    Replayed,
+    Discarded,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeadLetter {
+    pub id: uuid::Uuid,
+    pub kind: DeadLetterKind,
+    pub payload: serde_json::Value,
+    pub error: String,
+    pub attempts: u32,
+    pub status: DeadLetterStatus,
+    pub created_at: DateTime<Utc>,
+    pub last_attempt_at: DateTime<Utc>,
+}
+
+/// Payload stored for a failed reputation update
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReputationUpdatePayload {
+    pub agent_id: AgentId,
+    pub score_change: i32,
+    #[serde(default)]
+    pub deal_value: Option<Decimal>,
+}
+
+#[derive(Clone)]
+pub struct DeadLetterQueue {
+    database: Database,
+}
+
+impl DeadLetterQueue {
+    pub fn new(database: Database) -> Self {
+        Self { database }
+    }
+
+    /// Records a failed workflow. Failing to persist the dead letter is logged
+    /// rather than returned so it never masks the original error.
+    pub async fn record<T: Serialize>(&self, kind: DeadLetterKind, payload: &T, error: &str) {
+        let payload = match serde_json::to_value(payload) {
+            Ok(payload) => payload,
+            Err(e) => {
+                tracing::error!("Failed to serialize {:?} dead letter: {}", kind, e);
+                return;
+            }
+        };
+
+        let now = Utc::now();
+        let dead_letter = DeadLetter {
+            id: uuid::Uuid::new_v4(),
+            kind,
+            payload,
+            error: error.to_string(),
+            attempts: 1,
+            status: DeadLetterStatus::Pending,
+            created_at: now,
+            last_attempt_at: now,
+        };
+
+        match self.database.create_dead_letter(&dead_letter).await {
+            Ok(()) => tracing::warn!("Dead-lettered {:?} {}: {}", dead_letter.kind, dead_letter.id, error),
+            Err(e) => tracing::error!("Failed to persist dead letter ({}): {}", error, e),
+        }
+    }
+
+    pub async fn list(&self, status: Option<DeadLetterStatus>, limit: i64) -> Result<Vec<DeadLetter>> {
+        self.database.get_dead_letters(status, limit).await
+    }
+
+    pub async fn get(&self, id: uuid::Uuid) -> Result<Option<DeadLetter>> {
+        self.database.get_dead_letter(id).await
+    }
+
+    pub async fn mark_replayed(&self, dead_letter: &mut DeadLetter) -> Result<()> {
+        dead_letter.status = DeadLetterStatus::Replayed;
+        dead_letter.attempts += 1;
+        dead_letter.last_attempt_at = Utc::now();
+        self.database.update_dead_letter(dead_letter).await
+    }
+
+    pub async fn mark_failed(&self, dead_letter: &mut DeadLetter, error: &str) -> Result<()> {
+        dead_letter.error = error.to_string();
+        dead_letter.attempts += 1;
+        dead_letter.last_attempt_at = Utc::now();
+        self.database.update_dead_letter(dead_letter).await
+    }
+
+    pub async fn discard(&self, id: uuid::Uuid) -> Result<DeadLetter> {
+        let mut dead_letter = self.database.get_dead_letter(id).await?
+            .ok_or_else(|| crate::error::NegotiationError::NotFound(format!("Dead letter {}", id)))?;
+        dead_letter.status = DeadLetterStatus::Discarded;
+        self.database.update_dead_letter(&dead_letter).await?;
+        Ok(dead_letter)
+    }
+}
+
+/// The dead-letter API. Listing needs `view_market`; replaying and
+/// discarding need `administer`.
+pub fn router<S>(settlement: SettlementService, trust: Arc<RwLock<TrustSystem>>) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    let view = Router::new()
+        .route("/admin/dead-letters", get(list_dead_letters));
+    let administer = Router::new()
+        .route("/admin/dead-letters/:dead_letter_id/replay", post(replay_dead_letter))
+        .route("/admin/dead-letters/:dead_letter_id/discard", post(discard_dead_letter));
+    roles::require_permission(view, trust.clone(), Permission::ViewMarket)
+        .merge(roles::require_permission(administer, trust, Permission::Administer))
+        .with_state(settlement)
+}
+
+#[derive(Deserialize)]
+struct DeadLetterQuery {
+    status: Option<DeadLetterStatus>,
+    limit: Option<i64>,
+}
+
+async fn list_dead_letters(
+    State(settlement): State<SettlementService>,
+    Query(query): Query<DeadLetterQuery>,
+) -> ApiResult<Json<Vec<DeadLetter>>> {
+    let status = query.status.or(Some(DeadLetterStatus::Pending));
+    Ok(Json(settlement.dead_letters().list(status, query.limit.unwrap_or(100)).await?))
+}
+
+async fn replay_dead_letter(
+    State(settlement): State<SettlementService>,
+    Path(dead_letter_id): Path<uuid::Uuid>,
+) -> ApiResult<Json<DeadLetter>> {
+    let dead_letter = settlement.replay_dead_letter(dead_letter_id, None).await
+        .inspect_err(|e| tracing::error!("Failed to replay dead letter: {}", e))?;
+    Ok(Json(dead_letter))
+}
+
+async fn discard_dead_letter(
+    State(settlement): State<SettlementService>,
+    Path(dead_letter_id): Path<uuid::Uuid>,
+) -> ApiResult<Json<DeadLetter>> {
+    Ok(Json(settlement.dead_letters().discard(dead_letter_id).await?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{roles::Role, settlement::SettlementConfig};
+    use axum::http::StatusCode;
+    use chrono::Duration;
+
+    #[tokio::test]
+    async fn test_dead_letters_are_for_operators_only() {
+        let temp_file = tempfile::NamedTempFile::new().unwrap();
+        let database = Database::new(&format!("sqlite://{}", temp_file.path().to_string_lossy())).await.unwrap();
+        let settlement = SettlementService::new(SettlementConfig {
+            stripe_secret_key: None,
+            solana_rpc_url: None,
+            escrow_service_url: None,
+            webhook_secret: None,
+            delivery_confirmation_timeout_seconds: None,
+        }, database.clone()).await.unwrap();
+        let payload = ReputationUpdatePayload { agent_id: uuid::Uuid::new_v4(), score_change: 5, deal_value: None };
+        settlement.dead_letters().record(DeadLetterKind::ReputationUpdate, &payload, "trust service down").await;
+        let dead_letter = settlement.dead_letters().list(None, 10).await.unwrap().remove(0);
+
+        let trust = TrustSystem::new().unwrap();
+        let observer = trust.generate_role_jwt("dashboard", Role::Observer, Duration::minutes(5)).unwrap();
+        let agent = trust.generate_jwt(payload.agent_id).await.unwrap();
+        let app: Router = router(settlement, Arc::new(RwLock::new(trust)));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}/admin/dead-letters", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let client = reqwest::Client::new();
+        let discard = format!("{}/{}/discard", base, dead_letter.id);
+        assert_eq!(client.get(&base).send().await.unwrap().status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(client.post(&discard).send().await.unwrap().status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(client.get(&base).bearer_auth(&agent).send().await.unwrap().status(), StatusCode::FORBIDDEN);
+
+        let listed: Vec<DeadLetter> = client.get(&base).bearer_auth(&observer).send().await.unwrap().json().await.unwrap();
+        assert_eq!(listed.iter().map(|letter| letter.id).collect::<Vec<_>>(), [dead_letter.id]);
+        assert_eq!(client.post(&discard).bearer_auth(&observer).send().await.unwrap().status(), StatusCode::FORBIDDEN);
+        assert_eq!(database.get_dead_letter(dead_letter.id).await.unwrap().unwrap().status, DeadLetterStatus::Pending);
+    }
+}
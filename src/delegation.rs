@@ -0,0 +1,290 @@
+//! Time-limited delegated authority for sub-agents.
+//!
+//! A principal agent trades its agent JWT for a delegation token naming a
+//! sub-agent, such as a buyer that only shops one category, and the limits it
+//! may act within: the categories it may buy in, the most a single deal may be
+//! worth and whether it may settle deals or only negotiate them. A delegate
+//! can hand a narrower slice of its authority on to another agent, up to
+//! [`MAX_DELEGATION_DEPTH`] links, and every token carries the chain of
+//! principals it came from so negotiations and payments record who the
+//! acting agent was working for.
+
+use crate::{
+    error::{NegotiationError, Result},
+    money::Money,
+    taxonomy,
+    trust::JWTClaims,
+    validation::{FieldErrors, Validate},
+    AgentId,
+};
+use axum::http::HeaderMap;
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use hmac::{Hmac, Mac};
+use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+/// Header a delegate presents its delegation token in
+pub const DELEGATION_HEADER: &str = "x-dcap-delegation";
+
+pub const DEFAULT_DELEGATION_TTL_HOURS: i64 = 24;
+pub const MAX_DELEGATION_TTL_HOURS: i64 = 30 * 24;
+/// Most principals a delegation chain may pass through
+pub const MAX_DELEGATION_DEPTH: usize = 4;
+
+const DELEGATION_AUDIENCE: &str = "dcap-delegation";
+
+/// Delegation tokens are signed with their own key derived from the JWT
+/// secret, so they can't stand in for agent JWTs or session tokens
+const DELEGATION_KEY_CONTEXT: &[u8] = b"dcap-delegation:v1";
+
+/// What a delegate may do on its principal's behalf
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DelegationScope {
+    /// Categories the delegate may buy in, including their subcategories; any
+    /// category when empty
+    #[serde(default)]
+    pub categories: Vec<String>,
+    /// Most a single deal may be worth
+    pub max_deal_value: Decimal,
+    pub currency: String,
+    /// Whether the delegate may pay for deals, not only negotiate them
+    #[serde(default)]
+    pub can_settle: bool,
+}
+
+impl DelegationScope {
+    pub fn validate(&self) -> Result<()> {
+        self.field_errors().into_result()
+    }
+
+    pub fn covers_category(&self, category: &str) -> bool {
+        self.categories.is_empty()
+            || self.categories.iter().any(|wanted| taxonomy::category_matches(category, wanted))
+    }
+
+    /// Whether every deal this scope allows is also allowed by `parent`.
+    pub fn within(&self, parent: &DelegationScope) -> bool {
+        let categories = if self.categories.is_empty() {
+            parent.categories.is_empty()
+        } else {
+            self.categories.iter().all(|category| parent.covers_category(category))
+        };
+        categories
+            && self.currency == parent.currency
+            && self.max_deal_value <= parent.max_deal_value
+            && (parent.can_settle || !self.can_settle)
+    }
+}
+
+impl Validate for DelegationScope {
+    fn validate_fields(&self, errors: &mut FieldErrors) {
+        errors.check(self.max_deal_value > Decimal::ZERO, "max_deal_value", "Delegated deal value must be greater than 0");
+        errors.currency("currency", &self.currency);
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DelegationClaims {
+    /// The delegate
+    pub sub: String,
+    pub aud: String,
+    /// Principals from the one holding the original authority down to the
+    /// agent that issued this token
+    pub chain: Vec<AgentId>,
+    pub scope: DelegationScope,
+    pub jti: uuid::Uuid,
+    pub iat: usize,
+    pub exp: usize,
+}
+
+impl DelegationClaims {
+    pub fn agent_id(&self) -> Result<AgentId> {
+        AgentId::parse_str(&self.sub).map_err(|_| NegotiationError::Auth("Invalid delegation subject".to_string()))
+    }
+
+    /// The agent the authority ultimately comes from
+    pub fn principal(&self) -> Option<AgentId> {
+        self.chain.first().copied()
+    }
+
+    /// The principals followed by the delegate, as recorded on negotiations
+    /// and payments.
+    pub fn delegation_chain(&self) -> Result<Vec<AgentId>> {
+        let mut chain = self.chain.clone();
+        chain.push(self.agent_id()?);
+        Ok(chain)
+    }
+
+    pub fn expires_at(&self) -> DateTime<Utc> {
+        Utc.timestamp_opt(self.exp as i64, 0).single().unwrap_or_default()
+    }
+
+    /// Fails unless the token was issued to `agent_id`.
+    pub fn require_delegate(&self, agent_id: AgentId) -> Result<()> {
+        if self.agent_id()? == agent_id {
+            Ok(())
+        } else {
+            Err(NegotiationError::Auth("Delegation token was issued to another agent".to_string()))
+        }
+    }
+
+    /// Fails unless the delegate may negotiate a deal worth `value`, in
+    /// `category` when it's known.
+    pub fn authorize_deal(&self, category: Option<&str>, value: &Money) -> Result<()> {
+        if let Some(category) = category {
+            if !self.scope.covers_category(category) {
+                return Err(NegotiationError::Auth(format!("Delegation does not cover category {}", category)));
+            }
+        }
+        if value.currency != self.scope.currency {
+            return Err(NegotiationError::Auth(format!(
+                "Delegation is limited to deals in {}, not {}", self.scope.currency, value.currency
+            )));
+        }
+        if value.amount > self.scope.max_deal_value {
+            return Err(NegotiationError::Auth(format!(
+                "Deal worth {} exceeds the delegated limit of {} {}", value, self.scope.max_deal_value, self.scope.currency
+            )));
+        }
+        Ok(())
+    }
+
+    /// Fails unless the delegate may pay `value` for a deal.
+    pub fn authorize_settlement(&self, value: &Money) -> Result<()> {
+        if !self.scope.can_settle {
+            return Err(NegotiationError::Auth("Delegation does not allow settling deals".to_string()));
+        }
+        self.authorize_deal(None, value)
+    }
+}
+
+#[derive(Clone)]
+pub struct DelegationTokens {
+    key: Vec<u8>,
+}
+
+impl DelegationTokens {
+    pub fn new(jwt_secret: &str) -> Self {
+        let mut mac = Hmac::<Sha256>::new_from_slice(jwt_secret.as_bytes())
+            .expect("HMAC accepts keys of any length");
+        mac.update(DELEGATION_KEY_CONTEXT);
+        Self { key: mac.finalize().into_bytes().to_vec() }
+    }
+
+    /// Issues a token from an already validated agent JWT of the principal.
+    pub fn issue(&self, principal_claims: &JWTClaims, delegate: AgentId, scope: DelegationScope, ttl: Duration) -> Result<String> {
+        let principal = AgentId::parse_str(&principal_claims.sub)
+            .map_err(|_| NegotiationError::Auth("Invalid agent subject".to_string()))?;
+        self.sign(vec![principal], delegate, scope, Utc::now() + ttl)
+    }
+
+    /// Hands part of a delegate's authority on to another agent. The new
+    /// scope has to fall within the parent's and the token never outlives it.
+    pub fn sub_delegate(&self, parent: &DelegationClaims, delegate: AgentId, scope: DelegationScope, ttl: Duration) -> Result<String> {
+        if !scope.within(&parent.scope) {
+            return Err(NegotiationError::Auth("Sub-delegation exceeds the delegator's scope".to_string()));
+        }
+        let chain = parent.delegation_chain()?;
+        if chain.len() >= MAX_DELEGATION_DEPTH {
+            return Err(NegotiationError::Auth("Delegation chain is too long".to_string()));
+        }
+        self.sign(chain, delegate, scope, (Utc::now() + ttl).min(parent.expires_at()))
+    }
+
+    fn sign(&self, chain: Vec<AgentId>, delegate: AgentId, scope: DelegationScope, expires: DateTime<Utc>) -> Result<String> {
+        scope.validate()?;
+        if chain.contains(&delegate) {
+            return Err(NegotiationError::Auth("An agent can't delegate to itself".to_string()));
+        }
+        let now = Utc::now();
+        let claims = DelegationClaims {
+            sub: delegate.to_string(),
+            aud: DELEGATION_AUDIENCE.to_string(),
+            chain,
+            scope,
+            jti: uuid::Uuid::new_v4(),
+            iat: now.timestamp() as usize,
+            exp: expires.min(now + Duration::hours(MAX_DELEGATION_TTL_HOURS)).timestamp() as usize,
+        };
+
+        encode(&Header::default(), &claims, &EncodingKey::from_secret(&self.key))
+            .map_err(|e| NegotiationError::Auth(format!("Failed to issue delegation token: {}", e)))
+    }
+
+    /// Checks the signature and expiry of a token.
+    pub fn decode(&self, token: &str) -> Result<DelegationClaims> {
+        let mut validation = Validation::new(Algorithm::HS256);
+        validation.set_audience(&[DELEGATION_AUDIENCE]);
+
+        let claims = decode::<DelegationClaims>(token, &DecodingKey::from_secret(&self.key), &validation)
+            .map(|data| data.claims)
+            .map_err(|e| NegotiationError::Auth(format!("Invalid delegation token: {}", e)))?;
+        if claims.chain.is_empty() || claims.chain.len() > MAX_DELEGATION_DEPTH {
+            return Err(NegotiationError::Auth("Invalid delegation chain".to_string()));
+        }
+        Ok(claims)
+    }
+
+    /// Decodes the delegation token a request carries, if any, and checks it
+    /// was issued to `agent_id`.
+    pub fn from_headers(&self, headers: &HeaderMap, agent_id: AgentId) -> Result<Option<DelegationClaims>> {
+        let Some(token) = headers.get(DELEGATION_HEADER) else {
+            return Ok(None);
+        };
+        let token = token.to_str()
+            .map_err(|_| NegotiationError::Auth("Invalid delegation header".to_string()))?;
+        let claims = self.decode(token)?;
+        claims.require_delegate(agent_id)?;
+        Ok(Some(claims))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trust::TrustSystem;
+
+    fn scope(categories: &[&str], max_deal_value: i64, can_settle: bool) -> DelegationScope {
+        DelegationScope {
+            categories: categories.iter().map(|category| category.to_string()).collect(),
+            max_deal_value: Decimal::from(max_deal_value),
+            currency: "USD".to_string(),
+            can_settle,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_delegation_is_scoped_and_chained() {
+        let mut trust = TrustSystem::new().unwrap();
+        let principal = uuid::Uuid::new_v4();
+        let delegate = uuid::Uuid::new_v4();
+        let agent_token = trust.generate_jwt(principal).await.unwrap();
+        let tokens = trust.delegation_tokens();
+
+        let token = trust.issue_delegation(&agent_token, delegate, scope(&["Electronics"], 1000, true), Duration::hours(1)).await.unwrap();
+        let claims = tokens.decode(&token).unwrap();
+        assert_eq!(claims.principal(), Some(principal));
+        assert_eq!(claims.delegation_chain().unwrap(), vec![principal, delegate]);
+        claims.authorize_deal(Some("Electronics > Laptops"), &Money::new(Decimal::from(900), "USD".to_string())).unwrap();
+        assert!(claims.authorize_deal(Some("Furniture"), &Money::new(Decimal::from(10), "USD".to_string())).is_err());
+        assert!(claims.authorize_deal(None, &Money::new(Decimal::from(1001), "USD".to_string())).is_err());
+        assert!(claims.authorize_deal(None, &Money::new(Decimal::from(10), "EUR".to_string())).is_err());
+
+        // A sub-delegate gets a narrower slice that can't settle
+        let sub_delegate = uuid::Uuid::new_v4();
+        let narrower = scope(&["Electronics > Laptops"], 500, false);
+        let sub_token = tokens.sub_delegate(&claims, sub_delegate, narrower, Duration::hours(2)).unwrap();
+        let sub_claims = tokens.decode(&sub_token).unwrap();
+        assert_eq!(sub_claims.delegation_chain().unwrap(), vec![principal, delegate, sub_delegate]);
+        assert!(sub_claims.exp <= claims.exp);
+        assert!(sub_claims.authorize_settlement(&Money::new(Decimal::from(100), "USD".to_string())).is_err());
+        assert!(tokens.sub_delegate(&claims, sub_delegate, scope(&[], 500, false), Duration::hours(1)).is_err());
+        assert!(tokens.sub_delegate(&claims, sub_delegate, scope(&["Electronics"], 2000, false), Duration::hours(1)).is_err());
+
+        // Neither the agent JWT nor a token signed with another secret passes
+        assert!(tokens.decode(&agent_token).is_err());
+        assert!(DelegationTokens::new("other-secret").decode(&token).is_err());
+    }
+}
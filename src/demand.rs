@@ -0,0 +1,427 @@
+//! Anonymous demand signals published by buyers.
+//!
+//! A buyer posts what they are looking for ("100 units in a category under a
+//! price") without revealing who they are, and gets back a response token.
+//! Sellers subscribe to categories and are notified of matching signals; they
+//! answer with a targeted offer. Only the holder of the response token can
+//! read the offers, and takes one by sending the seller an ordinary RFQ.
+
+use crate::{
+    database::Database,
+    error::{NegotiationError, Result},
+    model::{AgentInfo, AgentType},
+    protocol::{CURRENT_VERSION, PROTOCOL_VERSION_HEADER},
+    AgentId,
+};
+use chrono::{DateTime, Duration, Utc};
+use reqwest::Client;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// How long a signal stays open when the buyer doesn't say
+pub const DEFAULT_DEMAND_DURATION_SECONDS: i64 = 86400;
+
+/// Reputation sellers assume when pricing an offer for an anonymous buyer
+pub const ANONYMOUS_BUYER_REPUTATION: u32 = 50;
+
+/// RFQ metadata key naming the offer a buyer is taking
+pub const DEMAND_RESPONSE_METADATA_KEY: &str = "demand_response_id";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DemandStatus {
+    Open,
+    Withdrawn,
+}
+
+/// What sellers see of a buyer's demand. It carries nothing that identifies
+/// the buyer.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DemandSignal {
+    pub id: uuid::Uuid,
+    pub category: String,
+    /// Narrows the signal to one product when set
+    pub product_id: Option<String>,
+    pub quantity: u32,
+    /// Most the buyer will pay for the whole quantity
+    pub max_price: Decimal,
+    pub currency: String,
+    pub status: DemandStatus,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PublishDemandRequest {
+    pub category: String,
+    #[serde(default)]
+    pub product_id: Option<String>,
+    pub quantity: u32,
+    pub max_price: Decimal,
+    pub currency: String,
+    #[serde(default = "default_duration_seconds")]
+    pub duration_seconds: i64,
+}
+
+fn default_duration_seconds() -> i64 {
+    DEFAULT_DEMAND_DURATION_SECONDS
+}
+
+/// Returned to the buyer only. The token is needed to read offers and to
+/// withdraw the signal, and is not stored in the clear.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PublishedDemand {
+    pub signal: DemandSignal,
+    pub response_token: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DemandSubscription {
+    pub id: uuid::Uuid,
+    pub seller_id: AgentId,
+    pub category: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubscribeRequest {
+    pub seller_id: AgentId,
+    pub category: String,
+}
+
+/// A seller's offer against a signal
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DemandResponse {
+    pub id: uuid::Uuid,
+    pub signal_id: uuid::Uuid,
+    pub seller_id: AgentId,
+    pub product_id: String,
+    pub quantity: u32,
+    /// Price for the whole quantity, in the signal's currency
+    pub price: Decimal,
+    pub currency: String,
+    pub valid_until: DateTime<Utc>,
+    pub note: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DemandResponseRequest {
+    pub seller_id: AgentId,
+    pub product_id: String,
+    pub quantity: u32,
+    pub price: Decimal,
+    pub currency: String,
+    pub ttl_seconds: u32,
+    #[serde(default)]
+    pub note: Option<String>,
+}
+
+impl DemandSignal {
+    pub fn is_open_at(&self, now: DateTime<Utc>) -> bool {
+        self.status == DemandStatus::Open && now < self.expires_at
+    }
+}
+
+/// Categories are matched case-insensitively
+pub fn normalize_category(category: &str) -> String {
+    category.trim().to_lowercase()
+}
+
+fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+#[derive(Clone)]
+pub struct DemandService {
+    database: Database,
+    client: Client,
+}
+
+impl DemandService {
+    pub fn new(database: Database) -> Self {
+        Self {
+            database,
+            client: Client::new(),
+        }
+    }
+
+    pub async fn publish(&self, request: PublishDemandRequest, now: DateTime<Utc>) -> Result<PublishedDemand> {
+        let category = normalize_category(&request.category);
+        if category.is_empty() {
+            return Err(NegotiationError::Validation("Category is required".to_string()));
+        }
+        crate::currency::validate_currency_code(&request.currency)?;
+        if request.quantity == 0 {
+            return Err(NegotiationError::Validation("Quantity must be greater than 0".to_string()));
+        }
+        if request.max_price <= Decimal::ZERO {
+            return Err(NegotiationError::Validation("Max price must be greater than 0".to_string()));
+        }
+        if request.duration_seconds <= 0 {
+            return Err(NegotiationError::Validation("Signal duration must be positive".to_string()));
+        }
+
+        let signal = DemandSignal {
+            id: uuid::Uuid::new_v4(),
+            category,
+            product_id: request.product_id,
+            quantity: request.quantity,
+            max_price: request.max_price,
+            currency: request.currency,
+            status: DemandStatus::Open,
+            expires_at: now + Duration::seconds(request.duration_seconds),
+            created_at: now,
+        };
+        let response_token = uuid::Uuid::new_v4().simple().to_string();
+        self.database.create_demand_signal(&signal, &hash_token(&response_token)).await?;
+
+        tracing::info!("Demand signal {} open for {} x{} until {}", signal.id, signal.category, signal.quantity, signal.expires_at);
+        Ok(PublishedDemand { signal, response_token })
+    }
+
+    pub async fn get_signal(&self, signal_id: uuid::Uuid) -> Result<DemandSignal> {
+        self.database.get_demand_signal(signal_id).await?
+            .map(|(signal, _)| signal)
+            .ok_or_else(|| NegotiationError::NotFound(format!("Demand signal {}", signal_id)))
+    }
+
+    /// Signals still open at `now`, optionally in one category.
+    pub async fn list_open_signals(&self, category: Option<&str>, now: DateTime<Utc>) -> Result<Vec<DemandSignal>> {
+        let category = category.map(normalize_category);
+        self.database.get_open_demand_signals(category.as_deref(), now).await
+    }
+
+    pub async fn withdraw(&self, signal_id: uuid::Uuid, response_token: &str) -> Result<DemandSignal> {
+        let mut signal = self.authorized_signal(signal_id, response_token).await?;
+        signal.status = DemandStatus::Withdrawn;
+        self.database.update_demand_signal_status(signal_id, signal.status).await?;
+        Ok(signal)
+    }
+
+    /// Subscribes a seller to a category. Subscribing twice returns the
+    /// existing subscription.
+    pub async fn subscribe(&self, request: SubscribeRequest) -> Result<DemandSubscription> {
+        let seller = self.database.get_agent(request.seller_id).await?
+            .ok_or(NegotiationError::AgentNotFound(request.seller_id))?;
+        if !matches!(seller.agent_type, AgentType::Seller) {
+            return Err(NegotiationError::Validation("Only sellers can subscribe to demand".to_string()));
+        }
+        let category = normalize_category(&request.category);
+        if category.is_empty() {
+            return Err(NegotiationError::Validation("Category is required".to_string()));
+        }
+
+        if let Some(existing) = self.database.get_demand_subscriptions(Some(request.seller_id), None).await?
+            .into_iter()
+            .find(|subscription| subscription.category == category)
+        {
+            return Ok(existing);
+        }
+
+        let subscription = DemandSubscription {
+            id: uuid::Uuid::new_v4(),
+            seller_id: request.seller_id,
+            category,
+            created_at: Utc::now(),
+        };
+        self.database.create_demand_subscription(&subscription).await?;
+        Ok(subscription)
+    }
+
+    pub async fn unsubscribe(&self, subscription_id: uuid::Uuid) -> Result<()> {
+        if !self.database.delete_demand_subscription(subscription_id).await? {
+            return Err(NegotiationError::NotFound(format!("Subscription {}", subscription_id)));
+        }
+        Ok(())
+    }
+
+    /// Every seller's subscriptions.
+    pub async fn subscriptions(&self) -> Result<Vec<DemandSubscription>> {
+        self.database.get_demand_subscriptions(None, None).await
+    }
+
+    /// Sellers subscribed to the signal's category.
+    pub async fn subscribers(&self, signal: &DemandSignal) -> Result<Vec<AgentInfo>> {
+        let mut sellers = Vec::new();
+        for subscription in self.database.get_demand_subscriptions(None, Some(&signal.category)).await? {
+            if let Some(seller) = self.database.get_agent(subscription.seller_id).await? {
+                sellers.push(seller);
+            }
+        }
+        Ok(sellers)
+    }
+
+    /// Pushes a new signal to each subscriber's `/demand` endpoint. Sellers
+    /// that can't be reached can still find it by listing open signals.
+    pub async fn notify_subscribers(&self, signal: &DemandSignal) {
+        let sellers = match self.subscribers(signal).await {
+            Ok(sellers) => sellers,
+            Err(e) => {
+                tracing::error!("Failed to load subscribers for demand signal {}: {}", signal.id, e);
+                return;
+            }
+        };
+
+        for seller in sellers {
+            let delivery = self.client
+                .post(format!("{}/demand", seller.endpoint))
+                .header(PROTOCOL_VERSION_HEADER, CURRENT_VERSION.to_string())
+                .json(signal)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status());
+            if let Err(e) = delivery {
+                tracing::warn!("Failed to notify seller {} of demand signal {}: {}", seller.id, signal.id, e);
+            }
+        }
+    }
+
+    /// Records a seller's offer. Each seller answers a signal once, within
+    /// its quantity and price.
+    pub async fn respond(&self, signal_id: uuid::Uuid, request: DemandResponseRequest, now: DateTime<Utc>) -> Result<DemandResponse> {
+        let signal = self.get_signal(signal_id).await?;
+        if !signal.is_open_at(now) {
+            return Err(NegotiationError::Validation(format!("Demand signal {} is closed", signal_id)));
+        }
+
+        let seller = self.database.get_agent(request.seller_id).await?
+            .ok_or(NegotiationError::AgentNotFound(request.seller_id))?;
+        if !matches!(seller.agent_type, AgentType::Seller) {
+            return Err(NegotiationError::Validation("Only sellers can respond to demand".to_string()));
+        }
+        if request.currency != signal.currency {
+            return Err(NegotiationError::Currency(format!(
+                "Offers must be in {}, got {}", signal.currency, request.currency
+            )));
+        }
+        if request.quantity == 0 || request.quantity > signal.quantity {
+            return Err(NegotiationError::Validation("Offer quantity must be between 1 and the quantity sought".to_string()));
+        }
+        if request.price <= Decimal::ZERO || request.price > signal.max_price {
+            return Err(NegotiationError::Validation("Offer must be positive and within the buyer's max price".to_string()));
+        }
+        if request.ttl_seconds == 0 {
+            return Err(NegotiationError::Validation("Offer TTL must be greater than 0".to_string()));
+        }
+        let responses = self.database.get_demand_responses(signal_id).await?;
+        if responses.iter().any(|response| response.seller_id == request.seller_id) {
+            return Err(NegotiationError::Validation("Seller has already responded to this signal".to_string()));
+        }
+
+        let response = DemandResponse {
+            id: uuid::Uuid::new_v4(),
+            signal_id,
+            seller_id: request.seller_id,
+            product_id: request.product_id,
+            quantity: request.quantity,
+            price: request.price,
+            currency: request.currency,
+            valid_until: now + Duration::seconds(request.ttl_seconds as i64),
+            note: request.note,
+            created_at: now,
+        };
+        self.database.create_demand_response(&response).await?;
+        Ok(response)
+    }
+
+    /// Offers on a signal, for the holder of its response token.
+    pub async fn responses(&self, signal_id: uuid::Uuid, response_token: &str) -> Result<Vec<DemandResponse>> {
+        self.authorized_signal(signal_id, response_token).await?;
+        self.database.get_demand_responses(signal_id).await
+    }
+
+    async fn authorized_signal(&self, signal_id: uuid::Uuid, response_token: &str) -> Result<DemandSignal> {
+        let (signal, token_hash) = self.database.get_demand_signal(signal_id).await?
+            .ok_or_else(|| NegotiationError::NotFound(format!("Demand signal {}", signal_id)))?;
+        if hash_token(response_token) != token_hash {
+            return Err(NegotiationError::Auth("Invalid response token for demand signal".to_string()));
+        }
+        Ok(signal)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::PaymentMethod;
+    use tempfile::NamedTempFile;
+
+    async fn agent(database: &Database, agent_type: AgentType) -> AgentId {
+        let agent = AgentInfo {
+            id: uuid::Uuid::new_v4(),
+            agent_type,
+            name: "TechStore".to_string(),
+            endpoint: "http://localhost:8001".to_string(),
+            public_key: "key".to_string(),
+            reputation_score: 80,
+            products: vec![],
+            payment_methods: vec![PaymentMethod::Stripe],
+            protocol_versions: vec![],
+            preferred_languages: vec![],
+            tls_fingerprint: None,
+            created_at: Utc::now(),
+            last_active: Utc::now(),
+        };
+        database.create_agent(&agent).await.unwrap();
+        agent.id
+    }
+
+    fn offer(seller_id: AgentId, price: i64, quantity: u32) -> DemandResponseRequest {
+        DemandResponseRequest {
+            seller_id,
+            product_id: "laptop-001".to_string(),
+            quantity,
+            price: Decimal::from(price),
+            currency: "USD".to_string(),
+            ttl_seconds: 3600,
+            note: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_subscribed_sellers_answer_signals_only_the_buyer_can_read() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let database = Database::new(&format!("sqlite://{}", temp_file.path().to_string_lossy())).await.unwrap();
+        let service = DemandService::new(database.clone());
+        let (seller, buyer) = (agent(&database, AgentType::Seller).await, agent(&database, AgentType::Buyer).await);
+
+        let subscription = service.subscribe(SubscribeRequest { seller_id: seller, category: "Electronics".to_string() }).await.unwrap();
+        let again = service.subscribe(SubscribeRequest { seller_id: seller, category: " electronics".to_string() }).await.unwrap();
+        assert_eq!(subscription.id, again.id);
+        assert!(service.subscribe(SubscribeRequest { seller_id: buyer, category: "Electronics".to_string() }).await.is_err());
+
+        let now = Utc::now();
+        let published = service.publish(PublishDemandRequest {
+            category: "ELECTRONICS".to_string(),
+            product_id: None,
+            quantity: 100,
+            max_price: Decimal::from(200000),
+            currency: "USD".to_string(),
+            duration_seconds: 600,
+        }, now).await.unwrap();
+        let signal = published.signal;
+        assert_eq!(service.subscribers(&signal).await.unwrap().len(), 1);
+        assert_eq!(service.list_open_signals(Some("Electronics"), now).await.unwrap().len(), 1);
+        assert!(service.list_open_signals(None, signal.expires_at).await.unwrap().is_empty());
+
+        assert!(service.respond(signal.id, offer(seller, 210000, 100), now).await.is_err());
+        assert!(service.respond(signal.id, offer(seller, 190000, 101), now).await.is_err());
+        assert!(service.respond(signal.id, offer(buyer, 190000, 100), now).await.is_err());
+        service.respond(signal.id, offer(seller, 190000, 100), now).await.unwrap();
+        assert!(service.respond(signal.id, offer(seller, 180000, 100), now).await.is_err());
+
+        assert!(service.responses(signal.id, "guess").await.is_err());
+        let offers = service.responses(signal.id, &published.response_token).await.unwrap();
+        assert_eq!(offers.len(), 1);
+        assert_eq!(offers[0].price, Decimal::from(190000));
+
+        assert!(service.withdraw(signal.id, "guess").await.is_err());
+        service.withdraw(signal.id, &published.response_token).await.unwrap();
+        assert!(service.respond(signal.id, offer(seller, 150000, 100), now).await.is_err());
+
+        service.unsubscribe(subscription.id).await.unwrap();
+        assert!(service.subscribers(&signal).await.unwrap().is_empty());
+    }
+}
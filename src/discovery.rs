@@ -1,26 +1,142 @@
 use crate::{
+    auction::{
+        Auction, AuctionService, AuctionView, CreateAuctionRequest, CreateListingRequest, Listing, ListingBid,
+        ListingBidRequest, ListingView,
+    },
+    audit_log::{AuditAction, AuditLog},
+    blocklist::{AgentBlock, BlacklistRequest, BlockKind, BlockList, BlockPolicy},
+    broadcast::{BroadcastRfqRequest, BroadcastService, BroadcastView, RfqBroadcast},
+    catalog::{self, CatalogEntry, PriceHistory, DEFAULT_HISTORY_DAYS, MAX_HISTORY_DAYS},
+    coalition::{
+        AgreeCoalitionRequest, Coalition, CoalitionActionRequest, CoalitionMember, CoalitionPaymentRequest,
+        CoalitionService, CoalitionView, FormCoalitionRequest, JoinCoalitionRequest, LockCoalitionRequest,
+    },
+    compliance::{ComplianceProfile, CompliancePolicy, ComplianceStage, ComplianceViolation},
+    database::Database,
+    demand::{
+        DemandResponse, DemandResponseRequest, DemandService, DemandSignal, DemandSubscription, PublishDemandRequest,
+        PublishedDemand, SubscribeRequest,
+    },
+    embeddings::ProductEmbeddings,
     error::{NegotiationError, Result},
-    model::{AgentInfo, AgentType, PaymentMethod},
+    language::Language,
+    model::{AgentInfo, AgentType, PaymentMethod, Product},
+    http::HttpClient,
+    product_search::{self, ProductSearchHit, ProductSearchQuery, ProductSearchResponse},
+    protocol::{ProtocolVersion, CURRENT_VERSION, PROTOCOL_VERSION_HEADER},
+    recovery::{KeyRotation, RecoveryPolicyRequest, RecoveryRequest},
+    registry::{HttpRegistry, Registry},
+    responsiveness::{ResponseSla, ResponseStats, ResponseTimeReport, ResponseTimesView},
+    shared_state::SharedState,
+    taxonomy::{self, CategoryTaxonomy, CategoryTree},
+    tls,
+    trust::TrustActivity,
+    validation::{FieldErrors, Validate},
     AgentId,
 };
-use reqwest::Client;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use sha2::{Digest, Sha256};
+use std::collections::{hash_map::Entry, HashMap, HashSet};
+use std::sync::Arc;
+
+/// Shared key counting registry writes; cached search results are keyed by it
+const SEARCH_GENERATION_KEY: &str = "discovery:generation";
+/// Outlives any cached search result, so a lapsed counter restarting can't
+/// revive stale results
+const SEARCH_GENERATION_TTL: std::time::Duration = std::time::Duration::from_secs(7 * 24 * 3600);
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct RegisterRequest {
+    /// ID the agent already uses; a new one is assigned when absent
+    #[serde(default)]
+    pub agent_id: Option<AgentId>,
     pub agent_type: AgentType,
     pub name: String,
     pub endpoint: String,
     pub public_key: String,
     pub payment_methods: Vec<PaymentMethod>,
+    #[serde(default)]
+    pub protocol_versions: Vec<ProtocolVersion>,
+    /// Languages the agent writes negotiation messages in, most preferred first
+    #[serde(default)]
+    pub preferred_languages: Vec<Language>,
+    /// Seller catalog, screened against the registry's compliance policy
+    #[serde(default)]
+    pub products: Vec<Product>,
+    #[serde(default)]
+    pub compliance: ComplianceProfile,
+    /// Hex SHA-256 fingerprint of the agent's TLS certificate
+    #[serde(default)]
+    pub tls_fingerprint: Option<String>,
+}
+
+impl Validate for RegisterRequest {
+    fn validate_fields(&self, errors: &mut FieldErrors) {
+        errors.check(!self.name.trim().is_empty(), "name", "Agent name must not be empty");
+        errors.check(
+            self.endpoint.starts_with("http://") || self.endpoint.starts_with("https://"),
+            "endpoint",
+            "Endpoint must be an http or https URL",
+        );
+        errors.check(!self.public_key.trim().is_empty(), "public_key", "Public key must not be empty");
+        if let Some(fingerprint) = &self.tls_fingerprint {
+            let fingerprint = tls::normalize_fingerprint(fingerprint);
+            errors.check(
+                fingerprint.len() == 64 && fingerprint.bytes().all(|b| b.is_ascii_hexdigit()),
+                "tls_fingerprint",
+                "TLS fingerprint must be a hex SHA-256 digest",
+            );
+        }
+        for (index, product) in self.products.iter().enumerate() {
+            errors.nested(&format!("products[{}]", index), product);
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SearchRequest {
+    /// Narrows sellers to products in this category or one under it, e.g.
+    /// `Electronics` finds `Electronics > Laptops`
     pub category: Option<String>,
     pub min_reputation: Option<u32>,
     pub payment_methods: Option<Vec<PaymentMethod>>,
+    /// Region the buyer is searching from; region-restricted categories are
+    /// hidden when absent
+    #[serde(default)]
+    pub region: Option<String>,
+    /// Leaves out sellers whose average response time is slower. Sellers
+    /// with no timed responses yet are kept.
+    #[serde(default)]
+    pub max_response_time_ms: Option<u64>,
+    /// Lists the fastest responders first, sellers with no timed responses last
+    #[serde(default)]
+    pub rank_by_responsiveness: bool,
+}
+
+/// Replaces a seller's catalog in the registry
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CatalogSyncRequest {
+    pub products: Vec<Product>,
+}
+
+/// Moves an agent's registration to the host taking over from it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HandoverRequest {
+    /// Where the agent is registered now; the handover fails if it has
+    /// already moved
+    pub from_endpoint: String,
+    pub to_endpoint: String,
+    /// Fingerprint of the new host's TLS certificate
+    #[serde(default)]
+    pub tls_fingerprint: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CatalogSyncResponse {
+    /// Products now listed
+    pub products: Vec<Product>,
+    /// Products the compliance policy kept out of the registry
+    pub withheld: Vec<ComplianceViolation>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -31,15 +147,40 @@ pub struct SearchResponse {
 
 pub struct DiscoveryService {
     endpoint: String,
-    client: Client,
+    client: HttpClient,
+    /// Where agents register and sellers are found; the discovery
+    /// service's own registry unless replaced
+    registry: Arc<dyn Registry>,
 }
 
 impl DiscoveryService {
     pub fn new(endpoint: String) -> Self {
+        let client = HttpClient::default();
         Self {
+            registry: Arc::new(HttpRegistry::new(endpoint.clone(), client.clone())),
             endpoint,
-            client: Client::new(),
+            client,
+        }
+    }
+
+    /// Calls the registry through `client`, e.g. one built from `[http]`.
+    pub fn with_http_client(mut self, client: HttpClient) -> Self {
+        if self.registry.name() == "http" {
+            self.registry = Arc::new(HttpRegistry::new(self.endpoint.clone(), client.clone()));
         }
+        self.client = client;
+        self
+    }
+
+    /// Registers agents and finds sellers through `registry` instead of
+    /// the discovery service, e.g. one from `registry::from_config`.
+    pub fn with_registry(mut self, registry: Arc<dyn Registry>) -> Self {
+        self.registry = registry;
+        self
+    }
+
+    pub fn registry_name(&self) -> &'static str {
+        self.registry.name()
     }
 
     pub fn endpoint(&self) -> &str {
@@ -47,38 +188,113 @@ impl DiscoveryService {
     }
 
     pub async fn register_agent(&self, agent_info: AgentInfo) -> Result<()> {
-        // Notify remote discovery service if available
-        if !self.endpoint.is_empty() {
-            let request = RegisterRequest {
-                agent_type: agent_info.agent_type,
-                name: agent_info.name,
-                endpoint: agent_info.endpoint,
-                public_key: agent_info.public_key,
-                payment_methods: agent_info.payment_methods,
-            };
+        self.register_agent_with_compliance(agent_info, ComplianceProfile::default()).await
+    }
 
-            let response = self.client
-                .post(&format!("{}/register", self.endpoint))
-                .json(&request)
-                .send()
-                .await?;
+    /// Registers an agent with the region and attestations the registry
+    /// checks before listing products in restricted categories.
+    pub async fn register_agent_with_compliance(&self, agent_info: AgentInfo, compliance: ComplianceProfile) -> Result<()> {
+        self.registry.register(agent_info, compliance).await
+    }
 
-            if !response.status().is_success() {
-                return Err(NegotiationError::Network(response.error_for_status().unwrap_err()));
-            }
+    /// Removes the agent from the registry, e.g. when it shuts down.
+    /// Removes the agent registered at `endpoint`. An agent that was handed
+    /// over to another endpoint stays registered there.
+    pub async fn deregister_agent(&self, agent_id: AgentId, endpoint: &str) -> Result<()> {
+        self.registry.deregister(agent_id, endpoint).await
+    }
+
+    /// Points the agent's registration at `request.to_endpoint` in place of
+    /// `request.from_endpoint`, in one step, so buyers find one host or the
+    /// other but never neither.
+    pub async fn hand_over(&self, agent_id: AgentId, request: &HandoverRequest) -> Result<AgentInfo> {
+        let request = HandoverRequest {
+            tls_fingerprint: request.tls_fingerprint.clone().or_else(|| self.client.tls_fingerprint().map(str::to_string)),
+            ..request.clone()
+        };
+        let response = self.client
+            .post(format!("{}/agents/{}/handover", self.endpoint, agent_id))
+            .header(PROTOCOL_VERSION_HEADER, CURRENT_VERSION.to_string())
+            .json(&request)
+            .send()
+            .await?;
+        Self::service_response(response).await
+    }
+
+    /// Replaces the seller's catalog in the registry. Products the registry's
+    /// compliance policy doesn't allow come back as withheld.
+    pub async fn sync_catalog(&self, agent_id: AgentId, products: Vec<Product>) -> Result<CatalogSyncResponse> {
+        let response = self.client
+            .post(format!("{}/agents/{}/catalog", self.endpoint, agent_id))
+            .header(PROTOCOL_VERSION_HEADER, CURRENT_VERSION.to_string())
+            .json(&CatalogSyncRequest { products })
+            .send()
+            .await?;
+        Self::service_response(response).await
+    }
+
+    /// Listed products matching a full-text query, most relevant first,
+    /// with facets.
+    pub async fn search_products(&self, query: &ProductSearchQuery) -> Result<ProductSearchResponse> {
+        let response = self.client
+            .get(format!("{}/products/search", self.endpoint))
+            .header(PROTOCOL_VERSION_HEADER, CURRENT_VERSION.to_string())
+            .query(query)
+            .send()
+            .await?;
+        Self::service_response(response).await
+    }
+
+    /// Each seller's price history for a product, over the registry's
+    /// default window unless `days` is given.
+    pub async fn price_history(&self, product_id: &str, seller_id: Option<AgentId>, days: Option<u32>) -> Result<Vec<PriceHistory>> {
+        #[derive(Deserialize)]
+        struct Histories {
+            histories: Vec<PriceHistory>,
         }
 
-        Ok(())
+        let mut request = self.client
+            .get(format!("{}/products/{}/history", self.endpoint, product_id))
+            .header(PROTOCOL_VERSION_HEADER, CURRENT_VERSION.to_string());
+        if let Some(seller_id) = seller_id {
+            request = request.query(&[("seller_id", seller_id.to_string())]);
+        }
+        if let Some(days) = days {
+            request = request.query(&[("days", days)]);
+        }
+        let response = request.send().await?;
+        Ok(Self::service_response::<Histories>(response).await?.histories)
+    }
+
+    /// Reports how long an agent took to respond, for the registry's rolling averages.
+    pub async fn report_response_time(&self, agent_id: AgentId, report: &ResponseTimeReport) -> Result<ResponseTimesView> {
+        let response = self.client
+            .post(format!("{}/agents/{}/response-times", self.endpoint, agent_id))
+            .header(PROTOCOL_VERSION_HEADER, CURRENT_VERSION.to_string())
+            .json(report)
+            .send()
+            .await?;
+        Self::service_response(response).await
+    }
+
+    pub async fn response_times(&self, agent_id: AgentId) -> Result<ResponseTimesView> {
+        let response = self.client
+            .get(format!("{}/agents/{}/response-times", self.endpoint, agent_id))
+            .header(PROTOCOL_VERSION_HEADER, CURRENT_VERSION.to_string())
+            .send()
+            .await?;
+        Self::service_response(response).await
     }
 
     pub async fn search_sellers(&self, request: SearchRequest) -> Result<Vec<AgentInfo>> {
         let mut agents = Vec::new();
 
-        // Query remote discovery service if available
-        if !self.endpoint.is_empty() {
-            if let Ok(remote_agents) = self.search_remote_sellers(&request).await {
-                agents = remote_agents;
-            }
+        // Only an unreachable registry is an error, so callers can fall back
+        // to sellers they know.
+        match self.registry.search_sellers(&request).await {
+            Ok(remote_agents) => agents = remote_agents,
+            Err(e) if e.is_unreachable() => return Err(e),
+            Err(_) => {}
         }
 
         // Apply filters
@@ -101,19 +317,270 @@ impl DiscoveryService {
     }
 
     pub async fn get_agent(&self, agent_id: AgentId) -> Result<AgentInfo> {
-        // Try remote discovery service
-        if !self.endpoint.is_empty() {
-            let response = self.client
-                .get(&format!("{}/agents/{}", self.endpoint, agent_id))
-                .send()
-                .await?;
-
-            if response.status().is_success() {
-                return response.json().await.map_err(Into::into);
-            }
+        self.registry.get_agent(agent_id).await
+    }
+
+    /// Publishes a reverse auction on the discovery service for sellers to bid in.
+    pub async fn create_auction(&self, request: &CreateAuctionRequest) -> Result<Auction> {
+        let response = self.client
+            .post(format!("{}/auctions", self.endpoint))
+            .header(PROTOCOL_VERSION_HEADER, CURRENT_VERSION.to_string())
+            .json(request)
+            .send()
+            .await?;
+        Self::service_response(response).await
+    }
+
+    pub async fn get_auction(&self, auction_id: uuid::Uuid) -> Result<AuctionView> {
+        let response = self.client
+            .get(format!("{}/auctions/{}", self.endpoint, auction_id))
+            .header(PROTOCOL_VERSION_HEADER, CURRENT_VERSION.to_string())
+            .send()
+            .await?;
+        Self::service_response(response).await
+    }
+
+    /// Lists a seller's product for an English or Dutch auction.
+    pub async fn create_listing(&self, request: &CreateListingRequest) -> Result<Listing> {
+        let response = self.client
+            .post(format!("{}/listings", self.endpoint))
+            .header(PROTOCOL_VERSION_HEADER, CURRENT_VERSION.to_string())
+            .json(request)
+            .send()
+            .await?;
+        Self::service_response(response).await
+    }
+
+    pub async fn list_listings(&self) -> Result<Vec<Listing>> {
+        #[derive(Deserialize)]
+        struct Listings {
+            listings: Vec<Listing>,
         }
 
-        Err(NegotiationError::AgentNotFound(agent_id))
+        let response = self.client
+            .get(format!("{}/listings", self.endpoint))
+            .header(PROTOCOL_VERSION_HEADER, CURRENT_VERSION.to_string())
+            .send()
+            .await?;
+        Ok(Self::service_response::<Listings>(response).await?.listings)
+    }
+
+    pub async fn get_listing(&self, listing_id: uuid::Uuid) -> Result<ListingView> {
+        let response = self.client
+            .get(format!("{}/listings/{}", self.endpoint, listing_id))
+            .header(PROTOCOL_VERSION_HEADER, CURRENT_VERSION.to_string())
+            .send()
+            .await?;
+        Self::service_response(response).await
+    }
+
+    pub async fn bid_on_listing(&self, listing_id: uuid::Uuid, request: &ListingBidRequest) -> Result<ListingBid> {
+        #[derive(Deserialize)]
+        struct Accepted {
+            bid: ListingBid,
+        }
+
+        let response = self.client
+            .post(format!("{}/listings/{}/bids", self.endpoint, listing_id))
+            .header(PROTOCOL_VERSION_HEADER, CURRENT_VERSION.to_string())
+            .json(request)
+            .send()
+            .await?;
+        Ok(Self::service_response::<Accepted>(response).await?.bid)
+    }
+
+    /// Publishes an anonymous demand signal with the buyer's agent JWT, whose
+    /// role must be allowed to request quotes; sellers aren't told who sent
+    /// it. Keep the returned response token: it is the only way to read the
+    /// offers.
+    pub async fn publish_demand(&self, access_token: &str, request: &PublishDemandRequest) -> Result<PublishedDemand> {
+        let response = self.client
+            .post(format!("{}/demand", self.endpoint))
+            .header(PROTOCOL_VERSION_HEADER, CURRENT_VERSION.to_string())
+            .bearer_auth(access_token)
+            .json(request)
+            .send()
+            .await?;
+        Self::service_response(response).await
+    }
+
+    pub async fn list_demand(&self, category: Option<&str>) -> Result<Vec<DemandSignal>> {
+        #[derive(Deserialize)]
+        struct Signals {
+            signals: Vec<DemandSignal>,
+        }
+
+        let mut request = self.client
+            .get(format!("{}/demand", self.endpoint))
+            .header(PROTOCOL_VERSION_HEADER, CURRENT_VERSION.to_string());
+        if let Some(category) = category {
+            request = request.query(&[("category", category)]);
+        }
+        let response = request.send().await?;
+        Ok(Self::service_response::<Signals>(response).await?.signals)
+    }
+
+    pub async fn withdraw_demand(&self, signal_id: uuid::Uuid, response_token: &str) -> Result<DemandSignal> {
+        let response = self.client
+            .post(format!("{}/demand/{}/withdraw", self.endpoint, signal_id))
+            .header(PROTOCOL_VERSION_HEADER, CURRENT_VERSION.to_string())
+            .bearer_auth(response_token)
+            .send()
+            .await?;
+        Self::service_response(response).await
+    }
+
+    pub async fn subscribe_to_demand(&self, request: &SubscribeRequest) -> Result<DemandSubscription> {
+        let response = self.client
+            .post(format!("{}/demand/subscriptions", self.endpoint))
+            .header(PROTOCOL_VERSION_HEADER, CURRENT_VERSION.to_string())
+            .json(request)
+            .send()
+            .await?;
+        Self::service_response(response).await
+    }
+
+    /// Offers to fill a demand signal, with the seller's access token.
+    pub async fn respond_to_demand(&self, signal_id: uuid::Uuid, request: &DemandResponseRequest, seller_token: &str) -> Result<DemandResponse> {
+        let response = self.client
+            .post(format!("{}/demand/{}/responses", self.endpoint, signal_id))
+            .header(PROTOCOL_VERSION_HEADER, CURRENT_VERSION.to_string())
+            .bearer_auth(seller_token)
+            .json(request)
+            .send()
+            .await?;
+        Self::service_response(response).await
+    }
+
+    pub async fn demand_responses(&self, signal_id: uuid::Uuid, response_token: &str) -> Result<Vec<DemandResponse>> {
+        #[derive(Deserialize)]
+        struct Responses {
+            responses: Vec<DemandResponse>,
+        }
+
+        let response = self.client
+            .get(format!("{}/demand/{}/responses", self.endpoint, signal_id))
+            .header(PROTOCOL_VERSION_HEADER, CURRENT_VERSION.to_string())
+            .bearer_auth(response_token)
+            .send()
+            .await?;
+        Ok(Self::service_response::<Responses>(response).await?.responses)
+    }
+
+    /// Sends an RFQ to every seller following its category. Quotes come in
+    /// while the registry fans it out; poll them with `get_rfq_broadcast`.
+    /// Sellers are sent `access_token`, the buyer's agent JWT, with the RFQ.
+    pub async fn broadcast_rfq(&self, access_token: &str, request: &BroadcastRfqRequest) -> Result<RfqBroadcast> {
+        let response = self.client
+            .post(format!("{}/rfq-broadcasts", self.endpoint))
+            .header(PROTOCOL_VERSION_HEADER, CURRENT_VERSION.to_string())
+            .bearer_auth(access_token)
+            .json(request)
+            .send()
+            .await?;
+        Self::service_response(response).await
+    }
+
+    pub async fn get_rfq_broadcast(&self, broadcast_id: uuid::Uuid) -> Result<BroadcastView> {
+        let response = self.client
+            .get(format!("{}/rfq-broadcasts/{}", self.endpoint, broadcast_id))
+            .header(PROTOCOL_VERSION_HEADER, CURRENT_VERSION.to_string())
+            .send()
+            .await?;
+        Self::service_response(response).await
+    }
+
+    /// Forms a coalition with the coordinator as its first member.
+    pub async fn form_coalition(&self, request: &FormCoalitionRequest) -> Result<CoalitionView> {
+        let response = self.client
+            .post(format!("{}/coalitions", self.endpoint))
+            .header(PROTOCOL_VERSION_HEADER, CURRENT_VERSION.to_string())
+            .json(request)
+            .send()
+            .await?;
+        Self::service_response(response).await
+    }
+
+    /// Coalitions taking members, optionally for one product.
+    pub async fn list_coalitions(&self, product_id: Option<&str>) -> Result<Vec<Coalition>> {
+        #[derive(Deserialize)]
+        struct Coalitions {
+            coalitions: Vec<Coalition>,
+        }
+
+        let mut request = self.client
+            .get(format!("{}/coalitions", self.endpoint))
+            .header(PROTOCOL_VERSION_HEADER, CURRENT_VERSION.to_string());
+        if let Some(product_id) = product_id {
+            request = request.query(&[("product_id", product_id)]);
+        }
+        let response = request.send().await?;
+        Ok(Self::service_response::<Coalitions>(response).await?.coalitions)
+    }
+
+    pub async fn get_coalition(&self, coalition_id: uuid::Uuid) -> Result<CoalitionView> {
+        let response = self.client
+            .get(format!("{}/coalitions/{}", self.endpoint, coalition_id))
+            .header(PROTOCOL_VERSION_HEADER, CURRENT_VERSION.to_string())
+            .send()
+            .await?;
+        Self::service_response(response).await
+    }
+
+    pub async fn join_coalition(&self, coalition_id: uuid::Uuid, request: &JoinCoalitionRequest) -> Result<CoalitionMember> {
+        self.coalition_action(coalition_id, "join", request).await
+    }
+
+    pub async fn leave_coalition(&self, coalition_id: uuid::Uuid, buyer_id: AgentId) -> Result<CoalitionView> {
+        self.coalition_action(coalition_id, "leave", &CoalitionActionRequest { agent_id: buyer_id }).await
+    }
+
+    pub async fn lock_coalition(&self, coalition_id: uuid::Uuid, request: &LockCoalitionRequest) -> Result<CoalitionView> {
+        self.coalition_action(coalition_id, "lock", request).await
+    }
+
+    pub async fn agree_coalition(&self, coalition_id: uuid::Uuid, request: &AgreeCoalitionRequest) -> Result<CoalitionView> {
+        self.coalition_action(coalition_id, "agree", request).await
+    }
+
+    pub async fn record_coalition_payment(&self, coalition_id: uuid::Uuid, request: &CoalitionPaymentRequest) -> Result<CoalitionView> {
+        self.coalition_action(coalition_id, "payments", request).await
+    }
+
+    pub async fn cancel_coalition(&self, coalition_id: uuid::Uuid, coordinator_id: AgentId) -> Result<CoalitionView> {
+        self.coalition_action(coalition_id, "cancel", &CoalitionActionRequest { agent_id: coordinator_id }).await
+    }
+
+    async fn coalition_action<T: serde::de::DeserializeOwned>(&self, coalition_id: uuid::Uuid, action: &str, body: &impl Serialize) -> Result<T> {
+        let response = self.client
+            .post(format!("{}/coalitions/{}/{}", self.endpoint, coalition_id, action))
+            .header(PROTOCOL_VERSION_HEADER, CURRENT_VERSION.to_string())
+            .json(body)
+            .send()
+            .await?;
+        Self::service_response(response).await
+    }
+
+    /// The discovery service reports failures as an error status with a
+    /// `{"status": "error", "message": ...}` body; older ones answer 200.
+    pub(crate) async fn service_response<T: serde::de::DeserializeOwned>(response: reqwest::Response) -> Result<T> {
+        if let Err(error) = response.error_for_status_ref() {
+            let status = response.status();
+            let message = match response.json::<serde_json::Value>().await {
+                Ok(body) if !status.is_server_error() => body.get("message").and_then(|m| m.as_str()).map(str::to_string),
+                _ => None,
+            };
+            return Err(match message {
+                Some(message) => NegotiationError::from_status(status, message),
+                None => error.into(),
+            });
+        }
+        let body: serde_json::Value = response.json().await?;
+        if body.get("status").and_then(|status| status.as_str()) == Some("error") {
+            let message = body.get("message").and_then(|message| message.as_str()).unwrap_or("unknown error");
+            return Err(NegotiationError::Negotiation(message.to_string()));
+        }
+        serde_json::from_value(body).map_err(Into::into)
     }
 
     pub async fn get_seller_by_product(&self, product_id: &str) -> Result<AgentInfo> {
@@ -123,6 +590,9 @@ impl DiscoveryService {
             category: None,
             min_reputation: None,
             payment_methods: None,
+            region: None,
+            max_response_time_ms: None,
+            rank_by_responsiveness: false,
         }).await?;
 
         agents.into_iter()
@@ -130,21 +600,6 @@ impl DiscoveryService {
             .ok_or_else(|| NegotiationError::Validation("No sellers found".to_string()))
     }
 
-    async fn search_remote_sellers(&self, request: &SearchRequest) -> Result<Vec<AgentInfo>> {
-        let response = self.client
-            .post(&format!("{}/search", self.endpoint))
-            .json(request)
-            .send()
-            .await?;
-
-        if response.status().is_success() {
-            let search_response: SearchResponse = response.json().await?;
-            Ok(search_response.agents)
-        } else {
-            Err(NegotiationError::Network(response.error_for_status().unwrap_err()))
-        }
-    }
-
     pub async fn update_agent_activity(&self, _agent_id: AgentId) -> Result<()> {
         // Update last_active timestamp - would need database integration
         // For now, just log the activity
@@ -157,6 +612,9 @@ impl DiscoveryService {
             category: Some(category.to_string()),
             min_reputation: None,
             payment_methods: None,
+            region: None,
+            max_response_time_ms: None,
+            rank_by_responsiveness: false,
         }).await?;
 
         Ok(sellers)
@@ -177,52 +635,933 @@ impl DiscoveryService {
 // Discovery server implementation (for standalone discovery service)
 #[derive(Clone)]
 pub struct DiscoveryServer {
-    // database: Database, // Temporarily disabled
+    database: Database,
+    auctions: AuctionService,
+    compliance: CompliancePolicy,
+    taxonomy: CategoryTaxonomy,
+    demand: DemandService,
+    coalitions: CoalitionService,
+    broadcasts: BroadcastService,
+    /// Blacklisted sellers are left out of searches
+    blocks: BlockList,
+    response_sla: ResponseSla,
+    /// Caches search results, shared with the registry's other replicas
+    shared: Option<SharedState>,
+    embeddings: Option<ProductEmbeddings>,
+    audit: AuditLog,
 }
 
 impl DiscoveryServer {
-    pub async fn new(_database_url: &str) -> Result<Self> {
-        // let database = Database::new(database_url).await?;
-        Ok(Self { /* database */ })
+    pub async fn new(database_url: &str) -> Result<Self> {
+        Ok(Self::from_database(Database::new(database_url).await?))
+    }
+
+    /// Serves the registry kept in an already open database.
+    pub fn from_database(database: Database) -> Self {
+        let auctions = AuctionService::new(database.clone());
+        let demand = DemandService::new(database.clone());
+        let coalitions = CoalitionService::new(database.clone());
+        let broadcasts = BroadcastService::new(database.clone());
+        let blocks = BlockList::new(database.clone(), BlockPolicy::default());
+        let audit = AuditLog::new(database.clone());
+        Self {
+            database,
+            auctions,
+            demand,
+            coalitions,
+            broadcasts,
+            blocks,
+            compliance: CompliancePolicy::default(),
+            taxonomy: CategoryTaxonomy::default(),
+            response_sla: ResponseSla::default(),
+            shared: None,
+            embeddings: None,
+            audit,
+        }
+    }
+
+    /// Restricts categories under the given policy; nothing is restricted by default.
+    pub fn with_compliance_policy(mut self, compliance: CompliancePolicy) -> Self {
+        self.compliance = compliance;
+        self
+    }
+
+    /// Files products under the full paths of the categories `taxonomy`
+    /// knows. Without one, categories are only tidied.
+    pub fn with_taxonomy(mut self, taxonomy: CategoryTaxonomy) -> Self {
+        self.taxonomy = taxonomy;
+        self
+    }
+
+    /// Scores agents' response times against `response_sla`.
+    pub fn with_response_sla(mut self, response_sla: ResponseSla) -> Self {
+        self.response_sla = response_sla;
+        self
+    }
+
+    /// Caches search results in `shared` for `[shared_state]
+    /// search_cache_seconds`. Every write to the registry invalidates the
+    /// cache for all replicas sharing it.
+    pub fn with_shared_state(mut self, shared: SharedState) -> Self {
+        self.shared = Some(shared);
+        self
+    }
+
+    /// Also matches product searches by embedding similarity, so products
+    /// described in other words are found too.
+    pub fn with_embeddings(mut self, embeddings: ProductEmbeddings) -> Self {
+        self.embeddings = Some(embeddings);
+        self
+    }
+
+    /// Reverse auctions and seller listings published through the registry
+    pub fn auctions(&self) -> &AuctionService {
+        &self.auctions
+    }
+
+    /// Buyers' demand signals and the sellers subscribed to them
+    pub fn demand(&self) -> &DemandService {
+        &self.demand
+    }
+
+    /// Buyer coalitions pooling demand for a product
+    pub fn coalitions(&self) -> &CoalitionService {
+        &self.coalitions
     }
 
+    /// RFQs fanned out to the sellers following their category
+    pub fn broadcasts(&self) -> &BroadcastService {
+        &self.broadcasts
+    }
+
+    /// Opens a broadcast of the RFQ to sellers following its category, filed
+    /// under the taxonomy; `broadcasts().fan_out` sends it.
+    pub async fn open_broadcast(&self, mut request: BroadcastRfqRequest) -> Result<RfqBroadcast> {
+        request.category = self.taxonomy.normalize(&request.category);
+        self.broadcasts.open(request, chrono::Utc::now()).await
+    }
+
+    /// Registers an agent with the products the compliance policy allows it
+    /// to list; the rest are withheld and recorded as violations.
     pub async fn handle_register(&self, request: RegisterRequest) -> Result<AgentInfo> {
+        if !request.products.is_empty() && !matches!(request.agent_type, AgentType::Seller) {
+            return Err(NegotiationError::Validation("Only sellers can list products".to_string()));
+        }
+
+        let now = chrono::Utc::now();
+        let agent_id = request.agent_id.unwrap_or_else(uuid::Uuid::new_v4);
+        let (products, violations) = self.compliance.screen(
+            agent_id, &request.compliance, self.file_products(request.products), ComplianceStage::Registration, now,
+        );
         let agent_info = AgentInfo {
-            id: uuid::Uuid::new_v4(),
+            id: agent_id,
             agent_type: request.agent_type,
             name: request.name,
             endpoint: request.endpoint,
             public_key: request.public_key,
             reputation_score: 100, // New agents start with neutral reputation
-            products: vec![],
+            products,
             payment_methods: request.payment_methods,
-            created_at: chrono::Utc::now(),
-            last_active: chrono::Utc::now(),
+            protocol_versions: request.protocol_versions,
+            preferred_languages: request.preferred_languages,
+            tls_fingerprint: request.tls_fingerprint.as_deref().map(tls::normalize_fingerprint),
+            created_at: now,
+            last_active: now,
         };
 
-        // self.database.create_agent(&agent_info).await?;
+        self.database.create_agent(&agent_info).await?;
+        self.database.record_product_changes(&catalog::diff_catalog(agent_id, &[], &agent_info.products, now)).await?;
+        self.database.set_agent_compliance(agent_id, &request.compliance).await?;
+        self.record_violations(&violations).await?;
+        self.invalidate_search_cache().await?;
+        self.audit.record(AuditAction::Registration, Some(agent_id), &agent_id.to_string(), &serde_json::json!({
+            "name": agent_info.name,
+            "agent_type": agent_info.agent_type,
+            "endpoint": agent_info.endpoint,
+            "public_key": agent_info.public_key,
+            "tls_fingerprint": agent_info.tls_fingerprint,
+        })).await;
         Ok(agent_info)
     }
 
-    pub async fn handle_search(&self, _request: SearchRequest) -> Result<SearchResponse> {
-        // let agents = self.database.get_agents_by_type(AgentType::Seller).await?;
+    /// Replaces a seller's catalog with the products the compliance policy
+    /// allows it to list, under the region and attestations it registered.
+    pub async fn sync_catalog(&self, agent_id: AgentId, request: CatalogSyncRequest) -> Result<CatalogSyncResponse> {
+        let agent = self.database.get_agent(agent_id).await?
+            .ok_or(NegotiationError::AgentNotFound(agent_id))?;
+        if !matches!(agent.agent_type, AgentType::Seller) {
+            return Err(NegotiationError::Validation("Only sellers can list products".to_string()));
+        }
+
+        let now = chrono::Utc::now();
+        let profile = self.database.get_agent_compliance(agent_id).await?.unwrap_or_default();
+        let (products, withheld) = self.compliance.screen(
+            agent_id, &profile, self.file_products(request.products), ComplianceStage::CatalogSync, now,
+        );
+        let listed = self.database.get_agent_products(agent_id).await?;
+        let changes = catalog::diff_catalog(agent_id, &listed, &products, now);
+        self.database.replace_agent_products(agent_id, &products, &changes).await?;
+        self.database.touch_agent(agent_id, now).await?;
+        self.record_violations(&withheld).await?;
+        self.invalidate_search_cache().await?;
+        Ok(CatalogSyncResponse { products, withheld })
+    }
+
+    /// The taxonomy's categories as a tree, for clients to browse.
+    pub fn categories(&self) -> Vec<CategoryTree> {
+        self.taxonomy.tree()
+    }
+
+    fn file_products(&self, mut products: Vec<Product>) -> Vec<Product> {
+        for product in &mut products {
+            product.category = self.taxonomy.normalize(&product.category);
+        }
+        products
+    }
+
+    /// Sellers and the products they list, filtered by category, reputation
+    /// and responsiveness. The compliance policy is applied again for each product:
+    /// a seller's attestation may have expired since it was listed, and
+    /// some categories can't be shown in the buyer's region.
+    pub async fn handle_search(&self, request: SearchRequest) -> Result<SearchResponse> {
+        let Some(shared) = &self.shared else {
+            return self.search(request).await;
+        };
+        let generation = shared.get_json::<u64>(SEARCH_GENERATION_KEY).await?.unwrap_or_default();
+        let key = format!(
+            "search:{}:{}", generation, hex::encode(Sha256::digest(serde_json::to_vec(&request)?))
+        );
+        if let Some(cached) = shared.get_json::<SearchResponse>(&key).await? {
+            return Ok(cached);
+        }
+        let response = self.search(request).await?;
+        shared.set_json(&key, &response, shared.search_cache_ttl()).await?;
+        Ok(response)
+    }
+
+    /// Bars the agent from trading and hides it from searches, until
+    /// `request.until` or for good.
+    pub async fn blacklist_agent(&self, agent_id: AgentId, request: BlacklistRequest) -> Result<AgentBlock> {
+        let block = self.blocks.blacklist(agent_id, &request.reason, request.until).await?;
+        self.invalidate_search_cache().await?;
+        Ok(block)
+    }
+
+    /// Lifts the agent's blacklisting or greylisting. Returns whether it was
+    /// blocked.
+    pub async fn unblock_agent(&self, agent_id: AgentId) -> Result<bool> {
+        let unblocked = self.blocks.unblock(agent_id).await?;
+        self.invalidate_search_cache().await?;
+        Ok(unblocked)
+    }
+
+    /// Blacklisted and greylisted agents, most recently blocked first
+    pub async fn blocked_agents(&self) -> Result<Vec<AgentBlock>> {
+        self.blocks.active_blocks().await
+    }
+
+    async fn blacklisted_agents(&self) -> Result<HashSet<AgentId>> {
+        Ok(self.blocks.active_blocks().await?
+            .into_iter()
+            .filter(|block| block.kind == BlockKind::Blacklisted)
+            .map(|block| block.agent_id)
+            .collect())
+    }
+
+    /// Starts a new cache generation, so no replica serves results from
+    /// before a write.
+    async fn invalidate_search_cache(&self) -> Result<()> {
+        if let Some(shared) = &self.shared {
+            shared.increment(SEARCH_GENERATION_KEY, SEARCH_GENERATION_TTL).await?;
+        }
+        Ok(())
+    }
+
+    async fn search(&self, request: SearchRequest) -> Result<SearchResponse> {
+        let now = chrono::Utc::now();
+        let category = request.category.as_deref();
+        let blacklisted = self.blacklisted_agents().await?;
 
-        // Mock implementation
+        let mut agents = Vec::new();
+        for mut agent in self.database.get_agents_by_type(AgentType::Seller).await? {
+            if blacklisted.contains(&agent.id) {
+                continue;
+            }
+            if request.min_reputation.is_some_and(|min| agent.reputation_score < min) {
+                continue;
+            }
+            if request.payment_methods.as_ref().is_some_and(|methods| !agent.payment_methods.iter().any(|method| methods.contains(method))) {
+                continue;
+            }
+            let response_time = self.database.get_response_stats(agent.id).await?
+                .and_then(|stats| stats.average_response_time_ms());
+            if response_time.zip(request.max_response_time_ms).is_some_and(|(average, max)| average > max) {
+                continue;
+            }
+
+            let profile = self.database.get_agent_compliance(agent.id).await?.unwrap_or_default();
+            let mut products = self.database.get_agent_products(agent.id).await?;
+            products.retain(|product| {
+                if category.is_some_and(|category| !taxonomy::category_matches(&product.category, category)) {
+                    return false;
+                }
+                let violation = self.compliance.seller_violation(agent.id, &profile, &product.category, now)
+                    .or_else(|| self.compliance.buyer_violation(&product.category, request.region.as_deref()));
+                if let Some(reason) = &violation {
+                    tracing::warn!("Withheld {} from seller {} in search: {}", product.id, agent.id, reason);
+                }
+                violation.is_none()
+            });
+
+            if category.is_some() && products.is_empty() {
+                continue;
+            }
+            agent.products = products;
+            agents.push((agent, response_time));
+        }
+
+        if request.rank_by_responsiveness {
+            agents.sort_by_key(|(_, response_time)| response_time.unwrap_or(u64::MAX));
+        }
+        let agents: Vec<AgentInfo> = agents.into_iter().map(|(agent, _)| agent).collect();
         Ok(SearchResponse {
-            agents: vec![],
-            total_count: 0,
+            total_count: agents.len() as u32,
+            agents,
         })
     }
 
-    pub async fn get_agent_info(&self, _agent_id: AgentId) -> Result<Option<AgentInfo>> {
-        // self.database.get_agent(agent_id).await
-        Ok(None)
+    /// Every product listed in the registry, with its seller, after the same
+    /// compliance screening as `handle_search` for a buyer in `region`.
+    pub async fn catalog(&self, region: Option<String>) -> Result<Vec<CatalogEntry>> {
+        let sellers = self.handle_search(SearchRequest {
+            category: None,
+            min_reputation: None,
+            payment_methods: None,
+            region,
+            max_response_time_ms: None,
+            rank_by_responsiveness: false,
+        }).await?;
+        Ok(sellers.agents.into_iter()
+            .flat_map(|seller| {
+                let (seller_id, seller_name, seller_reputation) = (seller.id, seller.name, seller.reputation_score);
+                seller.products.into_iter().map(move |product| CatalogEntry {
+                    seller_id,
+                    seller_name: seller_name.clone(),
+                    seller_reputation,
+                    product,
+                })
+            })
+            .collect())
+    }
+
+    /// Listed products matching the query's terms, category and prices,
+    /// after the same compliance screening as `handle_search` for a buyer in
+    /// the query's region, ranked with facets.
+    pub async fn search_products(&self, query: ProductSearchQuery) -> Result<ProductSearchResponse> {
+        query.validate()?;
+        let now = chrono::Utc::now();
+        let blacklisted = self.blacklisted_agents().await?;
+        let mut sellers: HashMap<AgentId, Option<(AgentInfo, ComplianceProfile)>> = HashMap::new();
+        let mut hits = Vec::new();
+        for (seller_id, product, score) in self.matching_products(&query).await? {
+            if blacklisted.contains(&seller_id) || !query.prices(&product) {
+                continue;
+            }
+            let seller = match sellers.entry(seller_id) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    let seller = match self.database.get_agent(seller_id).await? {
+                        Some(seller) => Some((seller, self.database.get_agent_compliance(seller_id).await?.unwrap_or_default())),
+                        None => None,
+                    };
+                    entry.insert(seller)
+                }
+            };
+            let Some((seller, profile)) = seller else {
+                continue;
+            };
+            let violation = self.compliance.seller_violation(seller_id, profile, &product.category, now)
+                .or_else(|| self.compliance.buyer_violation(&product.category, query.region.as_deref()));
+            if violation.is_some() {
+                continue;
+            }
+            hits.push(ProductSearchHit {
+                score,
+                listing: CatalogEntry {
+                    seller_id,
+                    seller_name: seller.name.clone(),
+                    seller_reputation: seller.reputation_score,
+                    product,
+                },
+            });
+        }
+        Ok(product_search::rank(&query, hits))
     }
 
-    pub async fn remove_agent(&self, agent_id: AgentId) -> Result<()> {
-        // This would require implementing delete operations in the database
-        // For now, we'll just log it
+    /// Products matching the query's terms with their relevance. With
+    /// embeddings, products similar enough to the query match too, and
+    /// similarity adds to every product's score.
+    async fn matching_products(&self, query: &ProductSearchQuery) -> Result<Vec<(AgentId, Product, f64)>> {
+        let terms = query.terms();
+        let (Some(embeddings), Some(q)) = (&self.embeddings, query.q.as_deref().filter(|_| !terms.is_empty())) else {
+            return self.database.search_products(&terms).await;
+        };
+        let similarities = match embeddings.similarities(q).await {
+            Ok(similarities) => similarities,
+            Err(e) => {
+                tracing::warn!("Semantic matching unavailable, searching text only: {}", e);
+                return self.database.search_products(&terms).await;
+            }
+        };
+
+        let text_scores: HashMap<(AgentId, String), f64> = self.database.search_products(&terms).await?
+            .into_iter()
+            .map(|(seller_id, product, score)| ((seller_id, product.id), score))
+            .collect();
+        Ok(self.database.search_products(&[]).await?
+            .into_iter()
+            .filter_map(|(seller_id, product, _)| {
+                let key = (seller_id, product.id.clone());
+                let text_score = text_scores.get(&key).copied();
+                let similarity = similarities.get(&key).copied();
+                if text_score.is_none() && similarity.is_none() {
+                    return None;
+                }
+                let score = text_score.unwrap_or(0.0) + similarity.unwrap_or(0.0) * embeddings.weight();
+                Some((seller_id, product, score))
+            })
+            .collect())
+    }
+
+    /// Agents that registered, synced their catalog or had a response timed
+    /// since `since`, most recently active first. Sellers come with the
+    /// products they list, screened as in `handle_search` for a buyer with no
+    /// region.
+    pub async fn active_agents(&self, since: chrono::DateTime<chrono::Utc>) -> Result<Vec<AgentInfo>> {
+        let now = chrono::Utc::now();
+        let mut agents = self.database.get_active_agents(since).await?;
+        for agent in agents.iter_mut().filter(|agent| matches!(agent.agent_type, AgentType::Seller)) {
+            let profile = self.database.get_agent_compliance(agent.id).await?.unwrap_or_default();
+            let mut products = self.database.get_agent_products(agent.id).await?;
+            products.retain(|product| {
+                self.compliance.seller_violation(agent.id, &profile, &product.category, now).is_none()
+                    && self.compliance.buyer_violation(&product.category, None).is_none()
+            });
+            agent.products = products;
+        }
+        Ok(agents)
+    }
+
+    /// Each seller's price history for a product over the last `days`.
+    pub async fn price_history(&self, product_id: &str, seller_id: Option<AgentId>, days: Option<u32>) -> Result<Vec<PriceHistory>> {
+        let days = days.unwrap_or(DEFAULT_HISTORY_DAYS);
+        if days == 0 || days > MAX_HISTORY_DAYS {
+            return Err(NegotiationError::Validation(format!(
+                "Price history covers 1 to {} days", MAX_HISTORY_DAYS
+            )));
+        }
+        let changes = self.database.get_product_history(product_id, seller_id).await?;
+        let since = chrono::Utc::now() - chrono::Duration::days(days as i64);
+        Ok(catalog::price_histories(&changes, since))
+    }
+
+    /// Products the compliance policy kept out of the registry, newest first.
+    pub async fn compliance_violations(&self, limit: i64) -> Result<Vec<ComplianceViolation>> {
+        self.database.get_compliance_violations(limit).await
+    }
+
+    async fn record_violations(&self, violations: &[ComplianceViolation]) -> Result<()> {
+        for violation in violations {
+            tracing::warn!(
+                "Withheld {} ({}) from seller {} at {:?}: {}",
+                violation.product_id, violation.category, violation.agent_id, violation.stage, violation.reason
+            );
+            self.database.create_compliance_violation(violation).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn get_agent_info(&self, agent_id: AgentId) -> Result<Option<AgentInfo>> {
+        self.database.get_agent(agent_id).await
+    }
+
+    /// Registers or replaces an agent's recovery policy. The request must be
+    /// signed with the agent's current key.
+    pub async fn set_recovery_policy(&self, agent_id: AgentId, request: RecoveryPolicyRequest) -> Result<()> {
+        let agent = self.database.get_agent(agent_id).await?
+            .ok_or(NegotiationError::AgentNotFound(agent_id))?;
+        request.verify(agent_id, &agent.public_key, chrono::Utc::now())?;
+        self.database.set_recovery_policy(agent_id, &request.policy).await
+    }
+
+    /// Replaces a lost agent key under the agent's recovery policy and records
+    /// the rotation in the registry.
+    pub async fn recover_agent_key(&self, agent_id: AgentId, request: RecoveryRequest) -> Result<KeyRotation> {
+        request.validate(chrono::Utc::now())?;
+        let agent = self.database.get_agent(agent_id).await?
+            .ok_or(NegotiationError::AgentNotFound(agent_id))?;
+        let policy = self.database.get_recovery_policy(agent_id).await?
+            .ok_or_else(|| NegotiationError::Validation(format!("No recovery policy registered for agent {}", agent_id)))?;
+
+        // A replayed request must not roll the agent back to a retired key
+        let history = self.database.get_key_rotations(agent_id).await?;
+        if request.new_public_key == agent.public_key
+            || history.iter().any(|rotation| rotation.previous_public_key == request.new_public_key)
+        {
+            return Err(NegotiationError::Validation("New key has already been used by this agent".to_string()));
+        }
+
+        let approvers = policy.authorize(agent_id, &request)?;
+        let rotation = KeyRotation {
+            id: uuid::Uuid::new_v4(),
+            agent_id,
+            previous_public_key: agent.public_key,
+            new_public_key: request.new_public_key,
+            method: policy.method(),
+            approvers,
+            rotated_at: chrono::Utc::now(),
+        };
+        self.database.rotate_agent_key(&rotation).await?;
+        self.invalidate_search_cache().await?;
+
+        tracing::info!("Agent {} key rotated via {:?} recovery", agent_id, rotation.method);
+        Ok(rotation)
+    }
+
+    pub async fn get_key_history(&self, agent_id: AgentId) -> Result<Vec<KeyRotation>> {
+        self.database.get_key_rotations(agent_id).await
+    }
+
+    /// Trust history kept by the registry: key recoveries, so counterparties
+    /// can confirm a new key belongs to the identity they know.
+    pub async fn get_trust_history(&self, agent_id: AgentId) -> Result<Vec<TrustActivity>> {
+        let rotations = self.database.get_key_rotations(agent_id).await?;
+        Ok(rotations.iter().map(KeyRotation::trust_activity).collect())
+    }
+
+    /// Folds a buyer's measurement of how long the agent took to respond
+    /// into its rolling averages.
+    pub async fn record_response_time(&self, agent_id: AgentId, report: ResponseTimeReport) -> Result<ResponseTimesView> {
+        report.validate()?;
+        self.database.get_agent(agent_id).await?
+            .ok_or(NegotiationError::AgentNotFound(agent_id))?;
+        let stats = self.database.record_response_time(agent_id, report.kind, report.response_ms).await?;
+        self.database.touch_agent(agent_id, chrono::Utc::now()).await?;
+        self.invalidate_search_cache().await?;
+        Ok(stats.view(&self.response_sla))
+    }
+
+    pub async fn response_times(&self, agent_id: AgentId) -> Result<ResponseTimesView> {
+        self.database.get_agent(agent_id).await?
+            .ok_or(NegotiationError::AgentNotFound(agent_id))?;
+        let stats = self.database.get_response_stats(agent_id).await?
+            .unwrap_or_else(|| ResponseStats::new(agent_id));
+        Ok(stats.view(&self.response_sla))
+    }
+
+    /// Removes an agent along with its listings and demand subscriptions.
+    /// Its trust history, response times and key rotations are kept, so an
+    /// agent that registers again under the same ID picks them back up.
+    /// With an `endpoint`, an agent handed over to another endpoint is left
+    /// registered there.
+    pub async fn remove_agent(&self, agent_id: AgentId, endpoint: Option<&str>) -> Result<()> {
+        let listed = self.database.get_agent_products(agent_id).await?;
+        let changes = catalog::diff_catalog(agent_id, &listed, &[], chrono::Utc::now());
+        if !self.database.delete_agent(agent_id, endpoint, &changes).await? {
+            if let Some(agent) = self.database.get_agent(agent_id).await? {
+                tracing::info!("Agent {} moved to {}; keeping its registration", agent_id, agent.endpoint);
+                return Ok(());
+            }
+            return Err(NegotiationError::AgentNotFound(agent_id));
+        }
+        self.invalidate_search_cache().await?;
         tracing::info!("Agent {} removed from discovery", agent_id);
         Ok(())
     }
-}
\ No newline at end of file
+
+    /// Moves an agent to the host taking over from it. Only an agent still
+    /// at `from_endpoint` moves, so two hosts racing to take over can't both
+    /// win.
+    pub async fn hand_over(&self, agent_id: AgentId, request: HandoverRequest) -> Result<AgentInfo> {
+        if request.to_endpoint.trim().is_empty() {
+            return Err(NegotiationError::Validation("The new endpoint must not be empty".to_string()));
+        }
+        let tls_fingerprint = request.tls_fingerprint.as_deref().map(tls::normalize_fingerprint);
+        let moved = self.database.move_agent(
+            agent_id, &request.from_endpoint, &request.to_endpoint, tls_fingerprint.as_deref(), chrono::Utc::now(),
+        ).await?;
+        if !moved {
+            let agent = self.database.get_agent(agent_id).await?
+                .ok_or(NegotiationError::AgentNotFound(agent_id))?;
+            return Err(NegotiationError::Validation(format!(
+                "Agent {} is registered at {}, not {}", agent_id, agent.endpoint, request.from_endpoint
+            )));
+        }
+        self.invalidate_search_cache().await?;
+        tracing::info!("Agent {} handed over from {} to {}", agent_id, request.from_endpoint, request.to_endpoint);
+        self.get_agent_info(agent_id).await?
+            .ok_or(NegotiationError::AgentNotFound(agent_id))
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{CategoryRestriction, ComplianceConfig};
+    use crate::responsiveness::{ResponseKind, MAX_RESPONSE_MS};
+    use std::collections::HashMap;
+    use tempfile::NamedTempFile;
+
+    fn product(id: &str, category: &str) -> Product {
+        Product {
+            id: id.to_string(),
+            name: id.to_string(),
+            description: String::new(),
+            category: category.to_string(),
+            base_price: rust_decimal::Decimal::from(40),
+            currency: "USD".to_string(),
+            stock_quantity: 10,
+            metadata: HashMap::new(),
+        }
+    }
+
+    fn search(category: Option<&str>, region: Option<&str>) -> SearchRequest {
+        SearchRequest {
+            category: category.map(str::to_string),
+            min_reputation: None,
+            payment_methods: None,
+            region: region.map(str::to_string),
+            max_response_time_ms: None,
+            rank_by_responsiveness: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_restricted_products_are_withheld_at_registration_sync_and_search() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let server = DiscoveryServer::new(&format!("sqlite://{}", temp_file.path().to_string_lossy())).await.unwrap()
+            .with_compliance_policy(CompliancePolicy::new(ComplianceConfig {
+                trusted_attestors: vec![],
+                restricted_categories: vec![
+                    CategoryRestriction { category: "Alcohol".to_string(), requires_attestation: true, allowed_regions: vec![] },
+                    CategoryRestriction { category: "Fireworks".to_string(), requires_attestation: false, allowed_regions: vec!["GB".to_string()] },
+                ],
+            }));
+
+        let seller = server.handle_register(RegisterRequest {
+            agent_id: None,
+            agent_type: AgentType::Seller,
+            name: "CornerShop".to_string(),
+            endpoint: "http://localhost:8001".to_string(),
+            public_key: "key".to_string(),
+            payment_methods: vec![PaymentMethod::Stripe],
+            protocol_versions: vec![],
+            preferred_languages: vec![],
+            products: vec![product("wine-001", "Alcohol"), product("rocket-001", "Fireworks"), product("laptop-001", "Electronics")],
+            compliance: ComplianceProfile { region: Some("GB".to_string()), attestations: vec![] },
+            tls_fingerprint: None,
+        }).await.unwrap();
+        assert_eq!(seller.products.len(), 2);
+
+        let all = server.handle_search(search(None, None)).await.unwrap();
+        assert_eq!(all.agents[0].products.len(), 1);
+        assert_eq!(server.handle_search(search(Some("fireworks"), Some("GB"))).await.unwrap().total_count, 1);
+        assert_eq!(server.handle_search(search(Some("fireworks"), Some("US"))).await.unwrap().total_count, 0);
+        let rockets = |region: &str| ProductSearchQuery { q: Some("rocket".to_string()), region: Some(region.to_string()), ..Default::default() };
+        assert_eq!(server.search_products(rockets("GB")).await.unwrap().total_count, 1);
+        assert_eq!(server.search_products(rockets("US")).await.unwrap().total_count, 0);
+
+        let synced = server.sync_catalog(seller.id, CatalogSyncRequest {
+            products: vec![product("beer-001", "alcohol"), product("phone-001", "Electronics")],
+        }).await.unwrap();
+        assert_eq!(synced.products.len(), 1);
+        assert_eq!(synced.withheld[0].stage, ComplianceStage::CatalogSync);
+        assert_eq!(server.handle_search(search(Some("Fireworks"), Some("GB"))).await.unwrap().total_count, 0);
+
+        let violations = server.compliance_violations(10).await.unwrap();
+        assert_eq!(violations.len(), 2);
+        assert!(violations.iter().all(|violation| violation.agent_id == seller.id));
+    }
+
+    #[tokio::test]
+    async fn test_products_are_filed_under_the_taxonomy_and_found_by_parent() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let taxonomy = CategoryTaxonomy::new(&crate::config::TaxonomyConfig {
+            categories: vec![
+                taxonomy::CategoryNode { name: "Electronics".to_string(), parent: None },
+                taxonomy::CategoryNode { name: "Laptops".to_string(), parent: Some("Electronics".to_string()) },
+            ],
+        }).unwrap();
+        let server = DiscoveryServer::new(&format!("sqlite://{}", temp_file.path().to_string_lossy())).await.unwrap()
+            .with_taxonomy(taxonomy)
+            .with_compliance_policy(CompliancePolicy::new(ComplianceConfig {
+                trusted_attestors: vec![],
+                restricted_categories: vec![
+                    CategoryRestriction { category: "Electronics".to_string(), requires_attestation: false, allowed_regions: vec!["GB".to_string()] },
+                ],
+            }));
+
+        let seller = server.handle_register(RegisterRequest {
+            agent_id: None,
+            agent_type: AgentType::Seller,
+            name: "LaptopShop".to_string(),
+            endpoint: "http://localhost:8001".to_string(),
+            public_key: "key".to_string(),
+            payment_methods: vec![PaymentMethod::Stripe],
+            protocol_versions: vec![],
+            preferred_languages: vec![],
+            products: vec![product("laptop-001", "laptops"), product("mug-001", "Home>Kitchen")],
+            compliance: ComplianceProfile { region: Some("GB".to_string()), attestations: vec![] },
+            tls_fingerprint: None,
+        }).await.unwrap();
+        let categories: Vec<&str> = seller.products.iter().map(|product| product.category.as_str()).collect();
+        assert_eq!(categories, ["Electronics > Laptops", "Home > Kitchen"]);
+
+        for category in ["Electronics", "laptops", "Electronics > Laptops"] {
+            let found = server.handle_search(search(Some(category), Some("GB"))).await.unwrap();
+            assert_eq!(found.agents[0].products.len(), 1, "{} didn't find the laptop", category);
+        }
+        assert_eq!(server.handle_search(search(Some("Kitchen"), Some("GB"))).await.unwrap().agents[0].products[0].id, "mug-001");
+        assert_eq!(server.handle_search(search(Some("Phones"), Some("GB"))).await.unwrap().total_count, 0);
+        // The restriction on the parent covers the laptop
+        assert_eq!(server.handle_search(search(Some("Laptops"), Some("US"))).await.unwrap().total_count, 0);
+        assert_eq!(server.categories()[0].children[0].path, "Electronics > Laptops");
+    }
+
+    #[tokio::test]
+    async fn test_catalog_syncs_record_price_history() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let server = DiscoveryServer::new(&format!("sqlite://{}", temp_file.path().to_string_lossy())).await.unwrap();
+
+        let seller = server.handle_register(RegisterRequest {
+            agent_id: None,
+            agent_type: AgentType::Seller,
+            name: "TechSeller".to_string(),
+            endpoint: "http://localhost:8001".to_string(),
+            public_key: "key".to_string(),
+            payment_methods: vec![PaymentMethod::Stripe],
+            protocol_versions: vec![],
+            preferred_languages: vec![],
+            products: vec![product("laptop-001", "Electronics")],
+            compliance: ComplianceProfile::default(),
+            tls_fingerprint: None,
+        }).await.unwrap();
+
+        let discounted = Product { base_price: rust_decimal::Decimal::from(30), ..product("laptop-001", "Electronics") };
+        server.sync_catalog(seller.id, CatalogSyncRequest { products: vec![discounted] }).await.unwrap();
+        server.sync_catalog(seller.id, CatalogSyncRequest { products: vec![] }).await.unwrap();
+
+        let histories = server.price_history("laptop-001", Some(seller.id), None).await.unwrap();
+        assert_eq!(histories.len(), 1);
+        assert_eq!(histories[0].points.len(), 3);
+        assert_eq!(histories[0].change_percent, Some(rust_decimal::Decimal::from(-25)));
+        assert!(server.price_history("laptop-001", None, Some(0)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_catalog_and_active_agents_come_from_the_registry() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let server = DiscoveryServer::new(&format!("sqlite://{}", temp_file.path().to_string_lossy())).await.unwrap();
+        let register = |agent_type, name: &str, products| RegisterRequest {
+            agent_id: None,
+            agent_type,
+            name: name.to_string(),
+            endpoint: "http://localhost:8001".to_string(),
+            public_key: "key".to_string(),
+            payment_methods: vec![PaymentMethod::Stripe],
+            protocol_versions: vec![],
+            preferred_languages: vec![],
+            products,
+            compliance: ComplianceProfile::default(),
+            tls_fingerprint: None,
+        };
+
+        let seller = server.handle_register(register(
+            AgentType::Seller, "TechSeller", vec![product("laptop-001", "Electronics"), product("phone-001", "Electronics")],
+        )).await.unwrap();
+        let buyer = server.handle_register(register(AgentType::Buyer, "Buyer", vec![])).await.unwrap();
+
+        let catalog = server.catalog(None).await.unwrap();
+        assert_eq!(catalog.len(), 2);
+        assert!(catalog.iter().all(|entry| entry.seller_id == seller.id && entry.seller_name == "TechSeller"));
+
+        let hour_ago = chrono::Utc::now() - chrono::Duration::hours(1);
+        let active = server.active_agents(hour_ago).await.unwrap();
+        assert_eq!(active.iter().map(|agent| agent.id).collect::<Vec<_>>(), vec![buyer.id, seller.id]);
+        assert_eq!(active[1].products.len(), 2);
+
+        // Syncing a catalog counts as activity
+        let checkpoint = chrono::Utc::now();
+        server.sync_catalog(seller.id, CatalogSyncRequest { products: vec![product("laptop-001", "Electronics")] }).await.unwrap();
+        let active = server.active_agents(checkpoint).await.unwrap();
+        assert_eq!(active.len(), 1);
+        assert_eq!((active[0].id, active[0].products.len()), (seller.id, 1));
+    }
+
+    #[tokio::test]
+    async fn test_search_filters_and_ranks_by_response_time() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let server = DiscoveryServer::new(&format!("sqlite://{}", temp_file.path().to_string_lossy())).await.unwrap();
+
+        let mut sellers = Vec::new();
+        for name in ["Slow", "Fast", "Untimed"] {
+            sellers.push(server.handle_register(RegisterRequest {
+                agent_id: None,
+                agent_type: AgentType::Seller,
+                name: name.to_string(),
+                endpoint: "http://localhost:8001".to_string(),
+                public_key: "key".to_string(),
+                payment_methods: vec![PaymentMethod::Stripe],
+                protocol_versions: vec![],
+                preferred_languages: vec![],
+                products: vec![product("laptop-001", "Electronics")],
+                compliance: ComplianceProfile::default(),
+                tls_fingerprint: None,
+            }).await.unwrap());
+        }
+        let report = |response_ms| ResponseTimeReport { kind: ResponseKind::Quote, response_ms };
+        server.record_response_time(sellers[0].id, report(9000)).await.unwrap();
+        let slow = server.record_response_time(sellers[0].id, report(7000)).await.unwrap();
+        assert_eq!(slow.average_response_time_ms, Some(8000));
+        assert_eq!(slow.sla_score, Some(63));
+        server.record_response_time(sellers[1].id, report(800)).await.unwrap();
+        assert!(server.record_response_time(sellers[1].id, report(MAX_RESPONSE_MS + 1)).await.is_err());
+        assert_eq!(server.response_times(sellers[2].id).await.unwrap().average_response_time_ms, None);
+
+        let names = |response: SearchResponse| response.agents.into_iter().map(|agent| agent.name).collect::<Vec<_>>();
+        let ranked = server.handle_search(SearchRequest { rank_by_responsiveness: true, ..search(None, None) }).await.unwrap();
+        assert_eq!(names(ranked), vec!["Fast", "Slow", "Untimed"]);
+        let filtered = server.handle_search(SearchRequest { max_response_time_ms: Some(5000), ..search(None, None) }).await.unwrap();
+        assert_eq!(names(filtered), vec!["Fast", "Untimed"]);
+    }
+
+    #[tokio::test]
+    async fn test_search_filters_by_stored_payment_methods() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let server = DiscoveryServer::new(&format!("sqlite://{}", temp_file.path().to_string_lossy())).await.unwrap();
+
+        for (name, payment_methods) in [("CardOnly", vec![PaymentMethod::Stripe]), ("Cod", vec![PaymentMethod::Stripe, PaymentMethod::PayOnDelivery])] {
+            server.handle_register(RegisterRequest {
+                agent_id: None,
+                agent_type: AgentType::Seller,
+                name: name.to_string(),
+                endpoint: "http://localhost:8001".to_string(),
+                public_key: "key".to_string(),
+                payment_methods,
+                protocol_versions: vec![],
+                preferred_languages: vec![],
+                products: vec![product("laptop-001", "Electronics")],
+                compliance: ComplianceProfile::default(),
+                tls_fingerprint: None,
+            }).await.unwrap();
+        }
+
+        let names = |response: SearchResponse| response.agents.into_iter().map(|agent| agent.name).collect::<Vec<_>>();
+        let on_delivery = SearchRequest { payment_methods: Some(vec![PaymentMethod::PayOnDelivery]), ..search(None, None) };
+        assert_eq!(names(server.handle_search(on_delivery).await.unwrap()), vec!["Cod"]);
+        let by_card = SearchRequest { payment_methods: Some(vec![PaymentMethod::Stripe]), ..search(None, None) };
+        assert_eq!(names(server.handle_search(by_card).await.unwrap()).len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_removed_agents_are_delisted_and_can_register_again() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let server = DiscoveryServer::new(&format!("sqlite://{}", temp_file.path().to_string_lossy())).await.unwrap()
+            .with_shared_state(SharedState::in_memory());
+        let register = |agent_id| RegisterRequest {
+            agent_id,
+            agent_type: AgentType::Seller,
+            name: "TechSeller".to_string(),
+            endpoint: "http://localhost:8001".to_string(),
+            public_key: "key".to_string(),
+            payment_methods: vec![PaymentMethod::Stripe],
+            protocol_versions: vec![],
+            preferred_languages: vec![],
+            products: vec![product("laptop-001", "Electronics")],
+            compliance: ComplianceProfile::default(),
+            tls_fingerprint: None,
+        };
+        let seller = server.handle_register(register(None)).await.unwrap();
+        assert_eq!(server.handle_search(search(None, None)).await.unwrap().total_count, 1);
+
+        server.remove_agent(seller.id, None).await.unwrap();
+        assert_eq!(server.handle_search(search(None, None)).await.unwrap().total_count, 0);
+        assert!(server.get_agent_info(seller.id).await.unwrap().is_none());
+        assert!(matches!(server.remove_agent(seller.id, None).await, Err(NegotiationError::AgentNotFound(_))));
+
+        server.handle_register(register(Some(seller.id))).await.unwrap();
+        assert_eq!(server.handle_search(search(None, None)).await.unwrap().total_count, 1);
+    }
+
+    #[tokio::test]
+    async fn test_blacklisted_sellers_are_left_out_of_searches() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let server = DiscoveryServer::new(&format!("sqlite://{}", temp_file.path().to_string_lossy())).await.unwrap()
+            .with_shared_state(SharedState::in_memory());
+        let seller = server.handle_register(RegisterRequest {
+            agent_id: None,
+            agent_type: AgentType::Seller,
+            name: "TechSeller".to_string(),
+            endpoint: "http://localhost:8001".to_string(),
+            public_key: "key".to_string(),
+            payment_methods: vec![PaymentMethod::Stripe],
+            protocol_versions: vec![],
+            preferred_languages: vec![],
+            products: vec![product("laptop-001", "Electronics")],
+            compliance: ComplianceProfile::default(),
+            tls_fingerprint: None,
+        }).await.unwrap();
+        let laptops = || ProductSearchQuery { q: Some("laptop".to_string()), ..Default::default() };
+        assert_eq!(server.handle_search(search(None, None)).await.unwrap().total_count, 1);
+
+        let request = BlacklistRequest { reason: "Fraudulent listings".to_string(), until: None };
+        server.blacklist_agent(seller.id, request).await.unwrap();
+        assert_eq!(server.handle_search(search(None, None)).await.unwrap().total_count, 0);
+        assert_eq!(server.search_products(laptops()).await.unwrap().total_count, 0);
+        assert_eq!(server.blocked_agents().await.unwrap()[0].agent_id, seller.id);
+
+        assert!(server.unblock_agent(seller.id).await.unwrap());
+        assert_eq!(server.handle_search(search(None, None)).await.unwrap().total_count, 1);
+        assert_eq!(server.search_products(laptops()).await.unwrap().total_count, 1);
+    }
+
+    #[tokio::test]
+    async fn test_handover_moves_the_registration_once() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let server = DiscoveryServer::new(&format!("sqlite://{}", temp_file.path().to_string_lossy())).await.unwrap()
+            .with_shared_state(SharedState::in_memory());
+        let seller = server.handle_register(RegisterRequest {
+            agent_id: None,
+            agent_type: AgentType::Seller,
+            name: "TechSeller".to_string(),
+            endpoint: "http://old-host:8001".to_string(),
+            public_key: "key".to_string(),
+            payment_methods: vec![PaymentMethod::Stripe],
+            protocol_versions: vec![],
+            preferred_languages: vec![],
+            products: vec![product("laptop-001", "Electronics")],
+            compliance: ComplianceProfile::default(),
+            tls_fingerprint: Some("AB:".repeat(31) + "AB"),
+        }).await.unwrap();
+        assert_eq!(seller.tls_fingerprint, Some("ab".repeat(32)));
+        let handover = |from: &str, to: &str| HandoverRequest {
+            from_endpoint: from.to_string(),
+            to_endpoint: to.to_string(),
+            tls_fingerprint: Some("cd".repeat(32)),
+        };
+
+        let moved = server.hand_over(seller.id, handover("http://old-host:8001", "http://new-host:8001")).await.unwrap();
+        assert_eq!(moved.endpoint, "http://new-host:8001");
+        assert_eq!(moved.tls_fingerprint, Some("cd".repeat(32)));
+        // A second standby taking over from the old host is too late
+        assert!(server.hand_over(seller.id, handover("http://old-host:8001", "http://other-host:8001")).await.is_err());
+
+        // The old host shutting down leaves the new registration alone
+        server.remove_agent(seller.id, Some("http://old-host:8001")).await.unwrap();
+        let agents = server.handle_search(search(None, None)).await.unwrap().agents;
+        assert_eq!(agents.iter().map(|agent| agent.endpoint.as_str()).collect::<Vec<_>>(), vec!["http://new-host:8001"]);
+        assert_eq!(agents[0].products.len(), 1);
+
+        server.remove_agent(seller.id, Some("http://new-host:8001")).await.unwrap();
+        assert!(server.get_agent_info(seller.id).await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,393 @@
+//! Embedding-based product matching.
+//!
+//! Full-text search only finds products whose names and descriptions share a
+//! buyer's words. With `[embeddings]` set, the registry also keeps an
+//! embedding of every listed product's name, category and description, and
+//! scores each product by the cosine similarity of its embedding to the
+//! query's, so "notebook computer" finds a listing titled "Laptop".
+//!
+//! Providers are pluggable through [`EmbeddingProvider`]:
+//!
+//! - `hashing` runs locally with no model: words and their character
+//!   trigrams are hashed into a fixed-size vector, which matches spelling
+//!   variants and shared word stems but not synonyms
+//! - `http` calls any OpenAI-compatible embeddings API
+//!   (`POST {endpoint}/embeddings`), e.g. a self-hosted model server
+//!
+//! Embeddings are stored with a hash of the text they were computed from and
+//! the model that computed them, so only new or edited listings are embedded
+//! again.
+
+use crate::{
+    config::{EmbeddingProviderKind, EmbeddingsConfig},
+    database::Database,
+    error::{NegotiationError, Result},
+    http::HttpClient,
+    model::Product,
+    product_search::search_terms,
+    AgentId,
+};
+use async_trait::async_trait;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{collections::HashMap, sync::Arc};
+
+/// Size of the hashing provider's vectors
+pub const DEFAULT_EMBEDDING_DIMENSIONS: usize = 256;
+
+/// Least similarity a product needs to match a query
+pub const DEFAULT_MIN_SIMILARITY: f64 = 0.3;
+
+/// Score a perfect semantic match adds, on the scale of a search term found in
+/// a product's name
+pub const DEFAULT_SEMANTIC_WEIGHT: f64 = crate::product_search::NAME_WEIGHT;
+
+/// Texts sent to a provider in one request
+const EMBEDDING_BATCH_SIZE: usize = 64;
+
+#[async_trait]
+pub trait EmbeddingProvider: Send + Sync {
+    /// Names the model, so vectors from different models are never compared
+    fn model(&self) -> String;
+
+    /// One vector per text, in order
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>>;
+}
+
+/// Hashes words and their character trigrams into a normalized vector.
+pub struct HashingEmbedder {
+    dimensions: usize,
+}
+
+impl HashingEmbedder {
+    pub fn new(dimensions: usize) -> Self {
+        Self { dimensions: dimensions.max(1) }
+    }
+
+    fn embed_one(&self, text: &str) -> Vec<f32> {
+        let mut vector = vec![0.0f32; self.dimensions];
+        for word in search_terms(text) {
+            let padded: Vec<char> = format!("#{}#", word).chars().collect();
+            let features = std::iter::once(word.clone())
+                .chain(padded.windows(3).map(|window| window.iter().collect::<String>()));
+            for feature in features {
+                let hash = fnv1a(feature.as_bytes());
+                let sign = if hash >> 63 == 0 { 1.0 } else { -1.0 };
+                vector[(hash % self.dimensions as u64) as usize] += sign;
+            }
+        }
+        normalize(&mut vector);
+        vector
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for HashingEmbedder {
+    fn model(&self) -> String {
+        format!("hashing-{}", self.dimensions)
+    }
+
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        Ok(texts.iter().map(|text| self.embed_one(text)).collect())
+    }
+}
+
+/// Calls an OpenAI-compatible embeddings API.
+pub struct HttpEmbeddingProvider {
+    client: HttpClient,
+    endpoint: String,
+    model: String,
+    api_key: Option<String>,
+}
+
+#[derive(Serialize)]
+struct EmbeddingRequest<'a> {
+    model: &'a str,
+    input: &'a [String],
+}
+
+#[derive(Deserialize)]
+struct EmbeddingResponse {
+    data: Vec<EmbeddingData>,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingData {
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+impl HttpEmbeddingProvider {
+    pub fn new(client: HttpClient, endpoint: String, model: String, api_key: Option<String>) -> Self {
+        Self {
+            client,
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            model,
+            api_key,
+        }
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for HttpEmbeddingProvider {
+    fn model(&self) -> String {
+        self.model.clone()
+    }
+
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let mut request = self.client
+            .post(format!("{}/embeddings", self.endpoint))
+            .json(&EmbeddingRequest { model: &self.model, input: texts });
+        if let Some(api_key) = &self.api_key {
+            request = request.bearer_auth(api_key);
+        }
+        let mut response: EmbeddingResponse = request.send().await?.error_for_status()?.json().await?;
+        if response.data.len() != texts.len() {
+            return Err(NegotiationError::Serialization(format!(
+                "Embeddings API returned {} vectors for {} texts", response.data.len(), texts.len()
+            )));
+        }
+        response.data.sort_by_key(|data| data.index);
+        Ok(response.data.into_iter().map(|data| data.embedding).collect())
+    }
+}
+
+/// A stored product embedding
+#[derive(Debug, Clone)]
+pub struct ProductEmbedding {
+    pub agent_id: AgentId,
+    pub product_id: String,
+    pub model: String,
+    /// Hash of the text the vector was computed from
+    pub content_hash: String,
+    pub vector: Vec<f32>,
+}
+
+/// The text a product is embedded from
+pub fn product_text(product: &Product) -> String {
+    format!("{}\n{}\n{}", product.name, product.category, product.description)
+}
+
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f64 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    let dot: f64 = a.iter().zip(b).map(|(x, y)| *x as f64 * *y as f64).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| (*x as f64).powi(2)).sum::<f64>().sqrt();
+    let norms = norm(a) * norm(b);
+    if norms == 0.0 { 0.0 } else { dot / norms }
+}
+
+/// Products ranked by similarity to `query`, most similar first, keeping
+/// those at or above `min_similarity`.
+pub async fn rank_products(
+    provider: &dyn EmbeddingProvider,
+    query: &str,
+    products: Vec<Product>,
+    min_similarity: f64,
+) -> Result<Vec<(Product, f64)>> {
+    let mut texts: Vec<String> = products.iter().map(product_text).collect();
+    texts.push(query.to_string());
+    let mut vectors = embed_batched(provider, &texts).await?;
+    let query_vector = vectors.pop().unwrap_or_default();
+    let mut ranked: Vec<(Product, f64)> = products.into_iter()
+        .zip(vectors)
+        .map(|(product, vector)| {
+            let similarity = cosine_similarity(&vector, &query_vector);
+            (product, similarity)
+        })
+        .filter(|(_, similarity)| *similarity >= min_similarity)
+        .collect();
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+    Ok(ranked)
+}
+
+/// The configured provider, or `None` when embeddings are off.
+pub fn provider_from_config(config: &EmbeddingsConfig, client: HttpClient) -> Result<Option<Arc<dyn EmbeddingProvider>>> {
+    Ok(match config.provider {
+        EmbeddingProviderKind::Off => None,
+        EmbeddingProviderKind::Hashing => Some(Arc::new(HashingEmbedder::new(config.dimensions))),
+        EmbeddingProviderKind::Http => {
+            let endpoint = config.endpoint.clone()
+                .ok_or_else(|| NegotiationError::Config("The http embedding provider needs an endpoint".to_string()))?;
+            if config.model.is_empty() {
+                return Err(NegotiationError::Config("The http embedding provider needs a model".to_string()));
+            }
+            let api_key = config.api_key_env.as_ref().and_then(|name| std::env::var(name).ok());
+            Some(Arc::new(HttpEmbeddingProvider::new(client, endpoint, config.model.clone(), api_key)))
+        }
+    })
+}
+
+/// Embeddings of the registry's listed products.
+#[derive(Clone)]
+pub struct ProductEmbeddings {
+    database: Database,
+    provider: Arc<dyn EmbeddingProvider>,
+    min_similarity: f64,
+    weight: f64,
+}
+
+impl ProductEmbeddings {
+    pub fn new(database: Database, provider: Arc<dyn EmbeddingProvider>) -> Self {
+        Self {
+            database,
+            provider,
+            min_similarity: DEFAULT_MIN_SIMILARITY,
+            weight: DEFAULT_SEMANTIC_WEIGHT,
+        }
+    }
+
+    pub fn from_config(database: Database, config: &EmbeddingsConfig, client: HttpClient) -> Result<Option<Self>> {
+        Ok(provider_from_config(config, client)?.map(|provider| Self {
+            min_similarity: config.min_similarity,
+            weight: config.weight,
+            ..Self::new(database, provider)
+        }))
+    }
+
+    /// Score a perfect match adds to a product's text relevance
+    pub fn weight(&self) -> f64 {
+        self.weight
+    }
+
+    /// Embeds listed products that are new or changed since they were last
+    /// embedded, and drops embeddings of products no longer listed. Returns
+    /// how many products were embedded.
+    pub async fn index(&self) -> Result<usize> {
+        let model = self.provider.model();
+        let stored: HashMap<(AgentId, String), ProductEmbedding> = self.database.get_product_embeddings().await?
+            .into_iter()
+            .map(|embedding| ((embedding.agent_id, embedding.product_id.clone()), embedding))
+            .collect();
+
+        let mut listed = std::collections::HashSet::new();
+        let mut stale = Vec::new();
+        for (agent_id, product, _) in self.database.search_products(&[]).await? {
+            let text = product_text(&product);
+            let content_hash = hex::encode(Sha256::digest(text.as_bytes()));
+            let key = (agent_id, product.id.clone());
+            let current = stored.get(&key)
+                .is_some_and(|embedding| embedding.model == model && embedding.content_hash == content_hash);
+            if !current {
+                stale.push((agent_id, product.id, content_hash, text));
+            }
+            listed.insert(key);
+        }
+
+        for (agent_id, product_id) in stored.keys().filter(|key| !listed.contains(*key)) {
+            self.database.delete_product_embedding(*agent_id, product_id).await?;
+        }
+
+        let texts: Vec<String> = stale.iter().map(|(_, _, _, text)| text.clone()).collect();
+        let vectors = embed_batched(self.provider.as_ref(), &texts).await?;
+        let now = Utc::now();
+        for ((agent_id, product_id, content_hash, _), vector) in stale.iter().zip(vectors) {
+            self.database.save_product_embedding(&ProductEmbedding {
+                agent_id: *agent_id,
+                product_id: product_id.clone(),
+                model: model.clone(),
+                content_hash: content_hash.clone(),
+                vector,
+            }, now).await?;
+        }
+        Ok(stale.len())
+    }
+
+    /// Similarity of each listed product to `query`, keeping those at or
+    /// above the threshold. Brings the index up to date first.
+    pub async fn similarities(&self, query: &str) -> Result<HashMap<(AgentId, String), f64>> {
+        self.index().await?;
+        let model = self.provider.model();
+        let query_vector = self.provider.embed(&[query.to_string()]).await?.pop().unwrap_or_default();
+        Ok(self.database.get_product_embeddings().await?
+            .into_iter()
+            .filter(|embedding| embedding.model == model)
+            .map(|embedding| {
+                let similarity = cosine_similarity(&embedding.vector, &query_vector);
+                ((embedding.agent_id, embedding.product_id), similarity)
+            })
+            .filter(|(_, similarity)| *similarity >= self.min_similarity)
+            .collect())
+    }
+}
+
+async fn embed_batched(provider: &dyn EmbeddingProvider, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+    let mut vectors = Vec::with_capacity(texts.len());
+    for batch in texts.chunks(EMBEDDING_BATCH_SIZE) {
+        vectors.extend(provider.embed(batch).await?);
+    }
+    Ok(vectors)
+}
+
+fn normalize(vector: &mut [f32]) {
+    let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|x| *x /= norm);
+    }
+}
+
+/// FNV-1a, so hashed features land in the same dimensions on every build
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x100000001b3))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        compliance::ComplianceProfile,
+        discovery::{DiscoveryServer, RegisterRequest},
+        model::{AgentType, PaymentMethod},
+        product_search::ProductSearchQuery,
+    };
+    use tempfile::NamedTempFile;
+
+    fn product(id: &str, name: &str, category: &str) -> Product {
+        Product {
+            id: id.to_string(),
+            name: name.to_string(),
+            description: String::new(),
+            category: category.to_string(),
+            base_price: rust_decimal::Decimal::from(40),
+            currency: "USD".to_string(),
+            stock_quantity: 10,
+            metadata: HashMap::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_search_matches_products_by_embedding() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let database = Database::new(&format!("sqlite://{}", temp_file.path().to_string_lossy())).await.unwrap();
+        let embeddings = ProductEmbeddings::new(database.clone(), Arc::new(HashingEmbedder::new(DEFAULT_EMBEDDING_DIMENSIONS)));
+        let server = DiscoveryServer::from_database(database.clone());
+        server.handle_register(RegisterRequest {
+            agent_id: None,
+            agent_type: AgentType::Seller,
+            name: "TechStore".to_string(),
+            endpoint: "http://localhost:8001".to_string(),
+            public_key: "key".to_string(),
+            payment_methods: vec![PaymentMethod::Stripe],
+            protocol_versions: vec![],
+            preferred_languages: vec![],
+            products: vec![product("laptop-001", "Gaming Laptop", "Electronics"), product("mug-001", "Coffee Mug", "Kitchen")],
+            compliance: ComplianceProfile::default(),
+            tls_fingerprint: None,
+        }).await.unwrap();
+
+        // "gamer" isn't a listed word, so text search alone misses the laptop
+        let query = ProductSearchQuery { q: Some("gamer laptops".to_string()), ..Default::default() };
+        assert_eq!(server.search_products(query.clone()).await.unwrap().total_count, 0);
+
+        let server = server.with_embeddings(embeddings.clone());
+        let response = server.search_products(query).await.unwrap();
+        assert_eq!(response.total_count, 1);
+        assert_eq!(response.results[0].listing.product.id, "laptop-001");
+
+        // Only new or edited listings are embedded again
+        assert_eq!(embeddings.index().await.unwrap(), 0);
+        assert_eq!(database.get_product_embeddings().await.unwrap().len(), 2);
+    }
+}
@@ -1,9 +1,17 @@
 use std::fmt;
 use thiserror::Error;
 use crate::AgentId;
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+};
+use serde::{ser::SerializeStruct, Serialize};
 
 pub type Result<T> = std::result::Result<T, NegotiationError>;
 
+/// What HTTP handlers return: the value, or an [`ApiError`] to answer with.
+pub type ApiResult<T> = std::result::Result<T, ApiError>;
+
 #[derive(Error, Debug)]
 pub enum NegotiationError {
     #[error("Invalid configuration: {0}")]
@@ -21,12 +29,18 @@ pub enum NegotiationError {
     #[error("Network error: {0}")]
     Network(#[from] reqwest::Error),
 
+    #[error("Circuit open for {0}")]
+    CircuitOpen(String),
+
     #[error("Serialization error: {0}")]
     Serialization(String),
 
     #[error("Payment error: {0}")]
     Payment(String),
 
+    #[error("Currency error: {0}")]
+    Currency(String),
+
     #[error("Trust validation failed: {0}")]
     Trust(String),
 
@@ -42,6 +56,10 @@ pub enum NegotiationError {
     #[error("Product not found: {0}")]
     ProductNotFound(String),
 
+    /// Any other record looked up by id, named like "Escrow hold <id>"
+    #[error("{0} not found")]
+    NotFound(String),
+
     #[error("Quote expired")]
     QuoteExpired,
 
@@ -52,6 +70,193 @@ pub enum NegotiationError {
     InvalidInput(String),
 }
 
+impl NegotiationError {
+    /// The other service couldn't be reached at all, as opposed to answering
+    /// with an error.
+    pub fn is_unreachable(&self) -> bool {
+        match self {
+            NegotiationError::Network(e) => e.is_connect() || e.is_timeout(),
+            NegotiationError::CircuitOpen(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Stable, machine-readable name of the error's kind, for callers to
+    /// branch on instead of the message.
+    pub fn code(&self) -> &'static str {
+        match self {
+            NegotiationError::Config(_) => "config",
+            NegotiationError::Auth(_) => "auth",
+            NegotiationError::Negotiation(_) => "negotiation",
+            NegotiationError::Database(_) => "database",
+            NegotiationError::Network(_) => "network",
+            NegotiationError::CircuitOpen(_) => "circuit_open",
+            NegotiationError::Serialization(_) => "serialization",
+            NegotiationError::Payment(_) => "payment",
+            NegotiationError::Currency(_) => "currency",
+            NegotiationError::Trust(_) => "trust",
+            NegotiationError::Validation(_) => "validation",
+            NegotiationError::Io(_) => "io",
+            NegotiationError::AgentNotFound(_) => "agent_not_found",
+            NegotiationError::ProductNotFound(_) => "product_not_found",
+            NegotiationError::NotFound(_) => "not_found",
+            NegotiationError::QuoteExpired => "quote_expired",
+            NegotiationError::InsufficientReputation(_) => "insufficient_reputation",
+            NegotiationError::InvalidInput(_) => "invalid_input",
+        }
+    }
+
+    /// Whether the same call may succeed if made again later: the other
+    /// service was unreachable, overloaded or failing, or the database was
+    /// too busy to answer. Anything else fails the same way every time.
+    pub fn is_retriable(&self) -> bool {
+        match self {
+            NegotiationError::Network(e) => {
+                e.is_connect() || e.is_timeout() || e.status().is_some_and(|status| {
+                    status.is_server_error() || status.as_u16() == StatusCode::TOO_MANY_REQUESTS.as_u16()
+                })
+            }
+            NegotiationError::CircuitOpen(_) => true,
+            NegotiationError::Database(e) => matches!(e, sqlx::Error::PoolTimedOut | sqlx::Error::Io(_)),
+            _ => false,
+        }
+    }
+
+    /// HTTP status an error is answered with. Mirrors the gRPC status
+    /// mapping; negotiation errors are state violations, hence 409.
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            NegotiationError::Validation(_) | NegotiationError::InvalidInput(_) | NegotiationError::Currency(_) => {
+                StatusCode::BAD_REQUEST
+            }
+            NegotiationError::Auth(_) => StatusCode::UNAUTHORIZED,
+            NegotiationError::Payment(_) => StatusCode::PAYMENT_REQUIRED,
+            NegotiationError::InsufficientReputation(_) | NegotiationError::Trust(_) => StatusCode::FORBIDDEN,
+            NegotiationError::AgentNotFound(_) | NegotiationError::ProductNotFound(_) | NegotiationError::NotFound(_) => {
+                StatusCode::NOT_FOUND
+            }
+            NegotiationError::Database(sqlx::Error::RowNotFound) => StatusCode::NOT_FOUND,
+            NegotiationError::QuoteExpired | NegotiationError::Negotiation(_) => StatusCode::CONFLICT,
+            NegotiationError::Network(e) if e.is_timeout() => StatusCode::GATEWAY_TIMEOUT,
+            NegotiationError::Network(_) => StatusCode::BAD_GATEWAY,
+            NegotiationError::CircuitOpen(_) => StatusCode::SERVICE_UNAVAILABLE,
+            NegotiationError::Config(_)
+            | NegotiationError::Database(_)
+            | NegotiationError::Serialization(_)
+            | NegotiationError::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// The error a service answered `status` and `message` for, the reverse
+    /// of [`NegotiationError::status_code`].
+    pub fn from_status(status: StatusCode, message: String) -> Self {
+        match status {
+            StatusCode::BAD_REQUEST | StatusCode::UNPROCESSABLE_ENTITY => NegotiationError::Validation(message),
+            StatusCode::UNAUTHORIZED => NegotiationError::Auth(message),
+            StatusCode::PAYMENT_REQUIRED => NegotiationError::Payment(message),
+            StatusCode::FORBIDDEN => NegotiationError::Trust(message),
+            StatusCode::NOT_FOUND => match message.strip_suffix(" not found") {
+                Some(what) => NegotiationError::NotFound(what.to_string()),
+                None => NegotiationError::NotFound(message),
+            },
+            _ => NegotiationError::Negotiation(message),
+        }
+    }
+}
+
+/// Serialized as `{"code": ..., "message": ..., "retriable": ...}`.
+impl Serialize for NegotiationError {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut error = serializer.serialize_struct("NegotiationError", 3)?;
+        error.serialize_field("code", self.code())?;
+        error.serialize_field("message", &self.to_string())?;
+        error.serialize_field("retriable", &self.is_retriable())?;
+        error.end()
+    }
+}
+
+/// An error answered over HTTP as `{"status": "error", "code": ...,
+/// "message": ..., "retriable": ...}`. Server errors are logged and answered
+/// with their status's reason alone, so database and network details stay
+/// out of responses.
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: StatusCode,
+    /// The [`NegotiationError::code`] of the error, or the status's for
+    /// errors raised over HTTP alone, e.g. `too_many_requests`
+    pub code: String,
+    pub message: String,
+    pub retriable: bool,
+    /// Extra fields merged into the body
+    pub details: serde_json::Map<String, serde_json::Value>,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        let code = status.canonical_reason().unwrap_or("error").to_ascii_lowercase().replace([' ', '-'], "_");
+        Self {
+            status,
+            code,
+            message: message.into(),
+            retriable: status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS,
+            details: serde_json::Map::new(),
+        }
+    }
+
+    /// Answers with `status` in place of the one the error maps to.
+    pub fn with_status(mut self, status: StatusCode) -> Self {
+        self.status = status;
+        self
+    }
+
+    pub fn with_code(mut self, code: &str) -> Self {
+        self.code = code.to_string();
+        self
+    }
+
+    /// Adds `key` to the response body.
+    pub fn with_detail(mut self, key: &str, value: impl Serialize) -> Self {
+        self.details.insert(key.to_string(), serde_json::to_value(value).unwrap_or_default());
+        self
+    }
+}
+
+impl From<NegotiationError> for ApiError {
+    fn from(err: NegotiationError) -> Self {
+        let status = err.status_code();
+        let message = if status.is_server_error() {
+            tracing::error!("Request failed: {}", err);
+            status.canonical_reason().unwrap_or("Error").to_string()
+        } else {
+            err.to_string()
+        };
+        Self { code: err.code().to_string(), retriable: err.is_retriable(), ..Self::new(status, message) }
+    }
+}
+
+impl From<StatusCode> for ApiError {
+    fn from(status: StatusCode) -> Self {
+        Self::new(status, status.canonical_reason().unwrap_or("Error"))
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let mut body = self.details;
+        body.insert("status".to_string(), "error".into());
+        body.insert("code".to_string(), self.code.into());
+        body.insert("message".to_string(), self.message.into());
+        body.insert("retriable".to_string(), self.retriable.into());
+        (self.status, Json(body)).into_response()
+    }
+}
+
+impl IntoResponse for NegotiationError {
+    fn into_response(self) -> Response {
+        ApiError::from(self).into_response()
+    }
+}
+
 impl From<serde_json::Error> for NegotiationError {
     fn from(err: serde_json::Error) -> Self {
         NegotiationError::Serialization(err.to_string())
@@ -74,4 +279,64 @@ impl From<std::io::Error> for NegotiationError {
     fn from(err: std::io::Error) -> Self {
         NegotiationError::Io(err.to_string())
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{routing::get, Router};
+
+    #[tokio::test]
+    async fn test_errors_answer_with_status_and_json_body() {
+        let app = Router::new()
+            .route("/quote", get(|| async { ApiResult::<()>::Err(NegotiationError::NotFound("Quote q1".to_string()).into()) }))
+            .route("/pay", get(|| async { NegotiationError::Payment("Card declined".to_string()) }))
+            .route("/db", get(|| async { NegotiationError::Database(sqlx::Error::PoolClosed) }))
+            .route("/version", get(|| async { ApiError::new(StatusCode::UPGRADE_REQUIRED, "Unsupported").with_detail("supported_versions", ["1.0"]) }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let get = |path: &str| reqwest::get(format!("{}{}", base, path));
+        let response = get("/quote").await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body, serde_json::json!({
+            "status": "error",
+            "code": "not_found",
+            "message": "Quote q1 not found",
+            "retriable": false,
+        }));
+        assert!(matches!(
+            NegotiationError::from_status(StatusCode::NOT_FOUND, "Quote q1 not found".to_string()),
+            NegotiationError::NotFound(what) if what == "Quote q1"
+        ));
+
+        assert_eq!(get("/pay").await.unwrap().status(), StatusCode::PAYMENT_REQUIRED);
+
+        // Server errors don't leak their cause
+        let response = get("/db").await.unwrap();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["message"], "Internal Server Error");
+        assert_eq!(body["code"], "database");
+
+        let body: serde_json::Value = get("/version").await.unwrap().json().await.unwrap();
+        assert_eq!(body["supported_versions"], serde_json::json!(["1.0"]));
+        assert_eq!(body["message"], "Unsupported");
+        assert_eq!(body["code"], "upgrade_required");
+    }
+
+    #[test]
+    fn test_error_codes_and_retriability() {
+        let expired = serde_json::to_value(NegotiationError::QuoteExpired).unwrap();
+        assert_eq!(expired, serde_json::json!({ "code": "quote_expired", "message": "Quote expired", "retriable": false }));
+
+        let open = NegotiationError::CircuitOpen("http://discovery:8000".to_string());
+        assert_eq!(open.code(), "circuit_open");
+        assert!(open.is_retriable());
+        assert!(NegotiationError::Database(sqlx::Error::PoolTimedOut).is_retriable());
+        assert!(!NegotiationError::Database(sqlx::Error::RowNotFound).is_retriable());
+        assert!(!NegotiationError::Validation("Quantity must be greater than 0".to_string()).is_retriable());
+        assert!(ApiError::new(StatusCode::TOO_MANY_REQUESTS, "Too many requests").retriable);
+    }
+}
@@ -0,0 +1,182 @@
+//! Negotiation event bus.
+//!
+//! Agents and the settlement service publish what happens to a negotiation
+//! on an [`EventBus`], a broadcast channel that any number of subscribers can
+//! follow for logging, metrics or UI updates. Publishing never blocks or
+//! fails: events nobody is subscribed to are dropped, and a subscriber that
+//! falls more than the bus capacity behind skips the oldest events (its
+//! receiver reports how many as `RecvError::Lagged`).
+
+use crate::{money::Money, AgentId, TransactionId};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+/// Events a subscriber can fall behind by before it starts missing them
+pub const DEFAULT_EVENT_CAPACITY: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EventKind {
+    /// A buyer sent an RFQ to a seller
+    RfqSent {
+        rfq_id: TransactionId,
+        buyer_id: AgentId,
+        seller_id: AgentId,
+        product_id: String,
+        quantity: u32,
+    },
+    /// A buyer received a quote or counter quote
+    QuoteReceived {
+        negotiation_id: TransactionId,
+        quote_id: uuid::Uuid,
+        seller_id: AgentId,
+        price: Money,
+    },
+    /// A buyer countered the seller's latest quote
+    CounterOffered {
+        negotiation_id: TransactionId,
+        price: Money,
+    },
+    /// A buyer accepted a quote, before paying for it
+    Accepted {
+        negotiation_id: TransactionId,
+        price: Money,
+    },
+    /// An accepted negotiation was paid for
+    Settled {
+        negotiation_id: TransactionId,
+        payment_id: String,
+    },
+    /// The seller warned that a quote is about to expire
+    ExpiryWarning {
+        negotiation_id: TransactionId,
+        quote_id: uuid::Uuid,
+        expires_at: DateTime<Utc>,
+    },
+    /// A quote expired before the buyer accepted it
+    Expired {
+        negotiation_id: TransactionId,
+        quote_id: uuid::Uuid,
+    },
+    /// A negotiation was still open at its RFQ deadline and expired
+    DeadlinePassed {
+        negotiation_id: TransactionId,
+        deadline: DateTime<Utc>,
+    },
+    /// The buyer confirmed the goods arrived, releasing escrow or collecting
+    /// a pay-on-delivery payment
+    DeliveryConfirmed {
+        transaction_id: TransactionId,
+        payment_id: String,
+    },
+    /// The settlement service took a payment, or released one from escrow
+    PaymentSucceeded {
+        payment_id: String,
+        transaction_id: TransactionId,
+        amount: Money,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NegotiationEvent {
+    pub occurred_at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub kind: EventKind,
+}
+
+#[derive(Debug, Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<NegotiationEvent>,
+}
+
+impl EventBus {
+    /// A bus holding up to `capacity` events for slow subscribers. Panics if
+    /// `capacity` is 0.
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        Self { sender }
+    }
+
+    /// Receives every event published from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<NegotiationEvent> {
+        self.sender.subscribe()
+    }
+
+    pub fn subscriber_count(&self) -> usize {
+        self.sender.receiver_count()
+    }
+
+    pub fn publish(&self, kind: EventKind) {
+        // Sending only fails when nobody is subscribed
+        let _ = self.sender.send(NegotiationEvent { occurred_at: Utc::now(), kind });
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new(DEFAULT_EVENT_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        database::Database,
+        settlement::{SettlementConfig, SettlementService},
+    };
+    use rust_decimal::Decimal;
+    use tempfile::NamedTempFile;
+
+    fn expired() -> EventKind {
+        EventKind::Expired { negotiation_id: uuid::Uuid::new_v4(), quote_id: uuid::Uuid::new_v4() }
+    }
+
+    #[test]
+    fn test_publishing_without_subscribers_is_a_no_op() {
+        let events = EventBus::new(2);
+        events.publish(expired());
+        assert_eq!(events.subscriber_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_payments_are_published_once_confirmed() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let database = Database::new(&format!("sqlite://{}", temp_file.path().to_string_lossy())).await.unwrap();
+        let events = EventBus::new(2);
+        let settlement = SettlementService::new(SettlementConfig {
+            stripe_secret_key: None,
+            solana_rpc_url: None,
+            escrow_service_url: None,
+            webhook_secret: None,
+            delivery_confirmation_timeout_seconds: None,
+        }, database).await.unwrap()
+            .with_event_bus(events.clone());
+        let mut receiver = events.subscribe();
+        assert_eq!(events.subscriber_count(), 1);
+
+        let amount = Money::new(Decimal::new(1999, 2), "USD");
+        let payment = settlement.create_payment(uuid::Uuid::new_v4(), uuid::Uuid::new_v4(), amount.clone(), None).await.unwrap();
+        assert!(receiver.try_recv().is_err());
+        settlement.poll_pending().await.unwrap();
+        let event = receiver.recv().await.unwrap();
+        assert_eq!(event.kind, EventKind::PaymentSucceeded {
+            payment_id: payment.payment_id,
+            transaction_id: payment.transaction_id,
+            amount,
+        });
+        assert_eq!(serde_json::to_value(&event).unwrap()["type"], "payment_succeeded");
+    }
+
+    #[tokio::test]
+    async fn test_lagging_subscribers_skip_the_oldest_events() {
+        let events = EventBus::new(2);
+        let mut receiver = events.subscribe();
+        for _ in 0..3 {
+            events.publish(expired());
+        }
+        assert!(matches!(receiver.recv().await, Err(broadcast::error::RecvError::Lagged(1))));
+        assert!(receiver.recv().await.is_ok());
+    }
+}
@@ -0,0 +1,219 @@
+//! Reminders before firm quotes lapse.
+//!
+//! A buyer names the endpoint it wants reminders at in its RFQ's metadata.
+//! When the seller answers with a firm or binding quote, it records a reminder
+//! and, `[expiry] warning_seconds` before the quote's TTL runs out, posts an
+//! [`ExpiryWarning`] to the buyer's `/quotes/expiring`. The buyer can leave the
+//! decision to whoever is watching its events, or answer automatically with a
+//! counter offer or by declining, per `[expiry] on_warning`, so deals aren't
+//! lost to quotes that lapse without anyone noticing.
+
+use crate::{
+    database::Database,
+    error::{NegotiationError, Result},
+    model::{Quote, QuoteFirmness, RFQ},
+    money::Money,
+    protocol::{CURRENT_VERSION, PROTOCOL_VERSION_HEADER},
+    webhook::WebhookSigner,
+    AgentId, TransactionId,
+};
+use chrono::{DateTime, Duration, Utc};
+use reqwest::Client;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+/// RFQ metadata key naming the buyer endpoint that takes expiry warnings
+pub const EXPIRY_NOTIFY_METADATA_KEY: &str = "expiry_notify_endpoint";
+
+pub const DEFAULT_WARNING_SECONDS: u64 = 300;
+
+pub const DEFAULT_CHECK_INTERVAL_SECONDS: u64 = 30;
+
+/// How often a buyer expires negotiations past their RFQ deadline
+pub const DEFAULT_DEADLINE_CHECK_SECONDS: u64 = 30;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExpiryWarning {
+    pub quote_id: uuid::Uuid,
+    pub rfq_id: TransactionId,
+    pub seller_id: AgentId,
+    pub price: Money,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl ExpiryWarning {
+    pub fn for_quote(quote: &Quote) -> Self {
+        Self {
+            quote_id: quote.id,
+            rfq_id: quote.rfq_id,
+            seller_id: quote.seller_id,
+            price: quote.amount(),
+            expires_at: quote.expires_at(),
+        }
+    }
+}
+
+/// What a buyer does when warned that a quote is about to lapse
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum ExpiryAction {
+    /// Only publish the warning on the buyer's event bus
+    #[default]
+    Notify,
+    /// Counter at the quoted price less `discount`, a fraction such as 0.05,
+    /// capped at the buyer's opening bid
+    Counter { discount: Decimal },
+    /// Reject the quote
+    Decline,
+}
+
+impl ExpiryAction {
+    pub fn validate(&self) -> Result<()> {
+        if let ExpiryAction::Counter { discount } = self {
+            if *discount < Decimal::ZERO || *discount >= Decimal::ONE {
+                return Err(NegotiationError::Config("Expiry counter discount must be between 0 and 1".to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Price to counter `quote` at, for `Counter`.
+    pub fn counter_price(&self, quote: &Quote, opening_bid: Decimal) -> Option<Decimal> {
+        match self {
+            ExpiryAction::Counter { discount } => {
+                Some((quote.price * (Decimal::ONE - discount)).round_dp(2).min(opening_bid))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// What a buyer did about a warning
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+pub enum ExpiryResponse {
+    Notified { negotiation_id: TransactionId },
+    Countered { negotiation_id: TransactionId, counter_offer: Decimal },
+    Declined { negotiation_id: TransactionId },
+}
+
+/// A warning a seller still has to send, and where to
+#[derive(Debug, Clone)]
+pub struct ExpiryReminder {
+    pub warning: ExpiryWarning,
+    pub endpoint: String,
+}
+
+/// Seller side: records firm quotes and warns their buyers before they lapse.
+#[derive(Clone)]
+pub struct ExpiryReminders {
+    database: Database,
+    client: Client,
+    warning: Duration,
+    signer: Option<WebhookSigner>,
+}
+
+impl ExpiryReminders {
+    pub fn new(database: Database, warning_seconds: u64) -> Self {
+        Self {
+            database,
+            client: Client::new(),
+            warning: Duration::seconds(warning_seconds as i64),
+            signer: None,
+        }
+    }
+
+    /// Signs each warning with the seller's webhook key.
+    pub fn with_signer(mut self, signer: WebhookSigner) -> Self {
+        self.signer = Some(signer);
+        self
+    }
+
+    /// Records a reminder for `quote` when it commits the seller and the
+    /// buyer asked for reminders. Returns whether one was recorded.
+    pub async fn track(&self, rfq: &RFQ, quote: &Quote) -> Result<bool> {
+        let Some(endpoint) = rfq.metadata.get(EXPIRY_NOTIFY_METADATA_KEY) else {
+            return Ok(false);
+        };
+        if matches!(quote.firmness, QuoteFirmness::Indicative) || self.warning.is_zero() {
+            return Ok(false);
+        }
+        let reminder = ExpiryReminder {
+            warning: ExpiryWarning::for_quote(quote),
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+        };
+        self.database.add_expiry_reminder(&reminder).await?;
+        Ok(true)
+    }
+
+    /// Sends the warnings for quotes lapsing within the warning period.
+    /// Reminders whose buyer can't be reached are retried on the next call
+    /// until the quote lapses. Returns how many were sent.
+    pub async fn send_due(&self, now: DateTime<Utc>) -> Result<usize> {
+        let mut sent = 0;
+        for reminder in self.database.get_due_expiry_reminders(now, now + self.warning).await? {
+            let payload = serde_json::to_vec(&reminder.warning)?;
+            let mut request = self.client
+                .post(format!("{}/quotes/expiring", reminder.endpoint))
+                .header(PROTOCOL_VERSION_HEADER, CURRENT_VERSION.to_string())
+                .header(reqwest::header::CONTENT_TYPE, "application/json");
+            if let Some(signer) = &self.signer {
+                request = request.headers(signer.headers(&payload));
+            }
+            let delivery = request
+                .body(payload)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status());
+            match delivery {
+                Ok(_) => {
+                    self.database.mark_expiry_reminder_sent(reminder.warning.quote_id, now).await?;
+                    sent += 1;
+                }
+                Err(e) => tracing::warn!(
+                    "Failed to warn {} that quote {} expires at {}: {}",
+                    reminder.endpoint, reminder.warning.quote_id, reminder.warning.expires_at, e
+                ),
+            }
+        }
+        Ok(sent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::NamedTempFile;
+
+    #[tokio::test]
+    async fn test_tracks_firm_quotes_until_warned() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let database = Database::new(&format!("sqlite://{}", temp_file.path().to_string_lossy())).await.unwrap();
+        let reminders = ExpiryReminders::new(database.clone(), 300);
+        let mut rfq = RFQ::new(uuid::Uuid::new_v4(), "laptop-001".to_string(), 1, Decimal::from(100), "USD".to_string(), Utc::now());
+        let quote = Quote::new(rfq.id, uuid::Uuid::new_v4(), Decimal::from(90), "USD".to_string(), 1, 600);
+
+        // No reminders unless the buyer asks, and none for indicative quotes
+        assert!(!reminders.track(&rfq, &quote).await.unwrap());
+        rfq.metadata.insert(EXPIRY_NOTIFY_METADATA_KEY.to_string(), "http://localhost:8002/".to_string());
+        assert!(!reminders.track(&rfq, &quote.clone().with_firmness(QuoteFirmness::Indicative)).await.unwrap());
+        assert!(reminders.track(&rfq, &quote).await.unwrap());
+
+        let now = Utc::now();
+        assert!(database.get_due_expiry_reminders(now, now + Duration::seconds(300)).await.unwrap().is_empty());
+        let due = database.get_due_expiry_reminders(now, now + Duration::seconds(900)).await.unwrap();
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].endpoint, "http://localhost:8002");
+        assert_eq!(due[0].warning, ExpiryWarning::for_quote(&quote));
+        // Lapsed quotes aren't warned about
+        assert!(database.get_due_expiry_reminders(now + Duration::seconds(900), now + Duration::seconds(1200)).await.unwrap().is_empty());
+
+        database.mark_expiry_reminder_sent(quote.id, now).await.unwrap();
+        assert!(database.get_due_expiry_reminders(now, now + Duration::seconds(900)).await.unwrap().is_empty());
+
+        let action = ExpiryAction::Counter { discount: Decimal::new(5, 2) };
+        assert_eq!(action.counter_price(&quote, Decimal::from(100)), Some(Decimal::new(8550, 2)));
+        assert_eq!(action.counter_price(&quote, Decimal::from(80)), Some(Decimal::from(80)));
+        assert!(ExpiryAction::Counter { discount: Decimal::ONE }.validate().is_err());
+    }
+}
@@ -0,0 +1,302 @@
+//! Read-only public explorer of marketplace health.
+//!
+//! Discovery serves aggregate numbers anyone may read without a token, so
+//! sites built on DCAP can show how the marketplace is doing without database
+//! access: totals of agents, products and deals, the busiest categories,
+//! recent deals stripped of who made them, and how many sellers are active.
+//! Everything goes through the privacy filter whether or not `[privacy]` is
+//! enabled, with counterparties always dropped, and each route is rate
+//! limited per client by `[explorer] rate_limit_per_minute`.
+
+use crate::{
+    analytics::{CategoryAnalytics, DEFAULT_ANALYTICS_DAYS},
+    config::{ExplorerConfig, PrivacyConfig},
+    database::Database,
+    error::Result,
+    model::AgentType,
+    privacy::{ExportedRecord, PriceBand, PrivacyFilter},
+};
+use axum::{
+    extract::State,
+    http::{Method, StatusCode},
+    response::Json,
+    routing::get,
+    Router,
+};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use tower_http::cors::{Any, CorsLayer};
+
+pub const DEFAULT_EXPLORER_RATE_LIMIT_PER_MINUTE: u32 = 60;
+pub const DEFAULT_RECENT_DEALS: usize = 20;
+pub const MAX_RECENT_DEALS: usize = 100;
+pub const DEFAULT_TOP_CATEGORIES: usize = 10;
+/// Sellers seen within this many days count as active
+pub const DEFAULT_ACTIVE_SELLER_DAYS: u32 = 7;
+
+/// Explorer routes, each with its own rate limit budget
+pub const EXPLORER_ROUTES: [&str; 4] = ["/explorer/stats", "/explorer/categories", "/explorer/deals", "/explorer/sellers"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MarketStats {
+    pub generated_at: DateTime<Utc>,
+    pub sellers: u64,
+    pub buyers: u64,
+    pub active_sellers: u64,
+    pub listed_products: u64,
+    pub deals_last_24h: u64,
+    pub deals_last_30d: u64,
+    pub average_deal_duration_seconds: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CategoryActivity {
+    pub category: String,
+    pub deal_count: u64,
+    pub average_close_price: PriceBand,
+    pub price_change_percent: Option<Decimal>,
+}
+
+impl From<CategoryAnalytics> for CategoryActivity {
+    fn from(category: CategoryAnalytics) -> Self {
+        Self {
+            category: category.category,
+            deal_count: category.deal_count,
+            average_close_price: category.average_close_price,
+            price_change_percent: category.price_change_percent,
+        }
+    }
+}
+
+/// A closed deal without its counterparties or product
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PublicDeal {
+    pub category: String,
+    pub close_price: PriceBand,
+    pub delta: Decimal,
+    pub date: NaiveDate,
+    pub duration_seconds: u64,
+    pub message_count: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SellerActivity {
+    pub active_sellers: u64,
+    pub active_since: DateTime<Utc>,
+    /// Active sellers listing products in each category, busiest first
+    pub categories: Vec<CategorySellers>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CategorySellers {
+    pub category: String,
+    pub active_sellers: u64,
+}
+
+#[derive(Clone)]
+pub struct Explorer {
+    database: Database,
+    privacy: PrivacyFilter,
+    config: ExplorerConfig,
+}
+
+impl Explorer {
+    /// Redacts with the `[privacy]` bands and group sizes, with privacy mode
+    /// forced on and without pseudonyms.
+    pub fn new(database: Database, privacy: &PrivacyConfig, config: &ExplorerConfig) -> Result<Self> {
+        let privacy = PrivacyFilter::from_config(&PrivacyConfig {
+            enabled: true,
+            pseudonym_secret: None,
+            ..privacy.clone()
+        })?;
+        Ok(Self { database, privacy, config: config.clone() })
+    }
+
+    fn active_since(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        now - Duration::days(self.config.active_seller_days as i64)
+    }
+
+    async fn analytics(&self, since: DateTime<Utc>) -> Result<crate::analytics::MarketAnalytics> {
+        let analytics = self.database.market_analytics(since, None).await?;
+        Ok(self.privacy.redact_analytics(analytics))
+    }
+
+    pub async fn stats(&self, now: DateTime<Utc>) -> Result<MarketStats> {
+        let last_day = self.analytics(now - Duration::days(1)).await?;
+        let last_month = self.analytics(now - Duration::days(DEFAULT_ANALYTICS_DAYS as i64)).await?;
+        Ok(MarketStats {
+            generated_at: now,
+            sellers: self.database.count_agents(AgentType::Seller, None).await?,
+            buyers: self.database.count_agents(AgentType::Buyer, None).await?,
+            active_sellers: self.database.count_agents(AgentType::Seller, Some(self.active_since(now))).await?,
+            listed_products: self.database.count_products().await?,
+            deals_last_24h: last_day.deal_count,
+            deals_last_30d: last_month.deal_count,
+            average_deal_duration_seconds: last_month.average_duration_seconds,
+        })
+    }
+
+    /// Categories with the most deals over the last 30 days. Categories with
+    /// too few deals to hide individual prices are left out.
+    pub async fn top_categories(&self, now: DateTime<Utc>) -> Result<Vec<CategoryActivity>> {
+        let analytics = self.analytics(now - Duration::days(DEFAULT_ANALYTICS_DAYS as i64)).await?;
+        let mut categories: Vec<CategoryActivity> = analytics.categories.into_iter().map(Into::into).collect();
+        categories.sort_by(|a, b| b.deal_count.cmp(&a.deal_count).then_with(|| a.category.cmp(&b.category)));
+        categories.truncate(self.config.top_categories);
+        Ok(categories)
+    }
+
+    /// The latest deals in categories busy enough to be listed.
+    pub async fn recent_deals(&self, now: DateTime<Utc>) -> Result<Vec<PublicDeal>> {
+        let analytics = self.analytics(now - Duration::days(DEFAULT_ANALYTICS_DAYS as i64)).await?;
+        let limit = self.config.recent_deals.min(MAX_RECENT_DEALS);
+        let deals = self.database.get_recent_deals(limit as i64).await?;
+        Ok(deals.into_iter()
+            .filter(|(_, category)| analytics.categories.iter().any(|listed| &listed.category == category))
+            .filter_map(|(record, category)| match self.privacy.export_record(&record) {
+                ExportedRecord::Redacted(record) => Some(PublicDeal {
+                    category,
+                    close_price: record.close_price,
+                    delta: record.delta,
+                    date: record.date,
+                    duration_seconds: record.duration_seconds,
+                    message_count: record.message_count,
+                }),
+                ExportedRecord::Full(_) => None,
+            })
+            .collect())
+    }
+
+    pub async fn seller_activity(&self, now: DateTime<Utc>) -> Result<SellerActivity> {
+        let active_since = self.active_since(now);
+        let categories = self.database.count_active_sellers_by_category(active_since).await?;
+        Ok(SellerActivity {
+            active_sellers: self.database.count_agents(AgentType::Seller, Some(active_since)).await?,
+            active_since,
+            categories: categories.into_iter()
+                .map(|(category, active_sellers)| CategorySellers { category, active_sellers })
+                .collect(),
+        })
+    }
+}
+
+/// The explorer routes, readable from any origin.
+pub fn router(explorer: Explorer) -> Router {
+    Router::new()
+        .route("/explorer/stats", get(stats))
+        .route("/explorer/categories", get(top_categories))
+        .route("/explorer/deals", get(recent_deals))
+        .route("/explorer/sellers", get(seller_activity))
+        .layer(CorsLayer::new().allow_origin(Any).allow_methods([Method::GET]))
+        .with_state(explorer)
+}
+
+fn respond<T>(result: Result<T>) -> std::result::Result<Json<T>, StatusCode> {
+    result.map(Json).map_err(|e| {
+        tracing::error!("Failed to serve explorer data: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
+
+async fn stats(State(explorer): State<Explorer>) -> std::result::Result<Json<MarketStats>, StatusCode> {
+    respond(explorer.stats(Utc::now()).await)
+}
+
+async fn top_categories(State(explorer): State<Explorer>) -> std::result::Result<Json<Vec<CategoryActivity>>, StatusCode> {
+    respond(explorer.top_categories(Utc::now()).await)
+}
+
+async fn recent_deals(State(explorer): State<Explorer>) -> std::result::Result<Json<Vec<PublicDeal>>, StatusCode> {
+    respond(explorer.recent_deals(Utc::now()).await)
+}
+
+async fn seller_activity(State(explorer): State<Explorer>) -> std::result::Result<Json<SellerActivity>, StatusCode> {
+    respond(explorer.seller_activity(Utc::now()).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{AgentInfo, NegotiationRecord, Product};
+    use crate::AgentId;
+    use std::collections::HashMap;
+    use tempfile::NamedTempFile;
+
+    async fn agent(database: &Database, agent_type: AgentType, category: &str, last_active: DateTime<Utc>) -> AgentId {
+        let id = uuid::Uuid::new_v4();
+        database.create_agent(&AgentInfo {
+            id,
+            agent_type,
+            name: "Agent".to_string(),
+            endpoint: "http://localhost:8001".to_string(),
+            public_key: "key".to_string(),
+            reputation_score: 50,
+            products: vec![Product {
+                id: format!("{}-001", category),
+                name: category.to_string(),
+                description: String::new(),
+                category: category.to_string(),
+                base_price: Decimal::from(100),
+                currency: "USD".to_string(),
+                stock_quantity: 10,
+                metadata: HashMap::new(),
+            }],
+            payment_methods: vec![],
+            protocol_versions: vec![],
+            preferred_languages: vec![],
+            tls_fingerprint: None,
+            created_at: last_active,
+            last_active,
+        }).await.unwrap();
+        id
+    }
+
+    #[tokio::test]
+    async fn test_explorer_publishes_anonymized_aggregates() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let database = Database::new(&format!("sqlite://{}", temp_file.path().to_string_lossy())).await.unwrap();
+        let now = Utc::now();
+        let tools = agent(&database, AgentType::Seller, "tools", now).await;
+        let toys = agent(&database, AgentType::Seller, "toys", now - Duration::days(30)).await;
+        let buyer = agent(&database, AgentType::Buyer, "unused", now).await;
+        let deal = |seller_id, product: &str, close_price: i64| NegotiationRecord {
+            buyer_id: buyer,
+            seller_id,
+            product_hash: product.to_string(),
+            opening_bid: Decimal::from(90),
+            close_price: Decimal::from(close_price),
+            delta: Decimal::from(close_price - 90),
+            timestamp: now - Duration::hours(2),
+            duration_seconds: 60,
+            message_count: 4,
+        };
+        for price in [120, 130, 140] {
+            database.add_negotiation_record(&deal(tools, "tools-001", price)).await.unwrap();
+        }
+        database.add_negotiation_record(&deal(toys, "toys-001", 55)).await.unwrap();
+
+        let config = ExplorerConfig::default();
+        let explorer = Explorer::new(database, &PrivacyConfig { min_group_size: 2, ..PrivacyConfig::default() }, &config).unwrap();
+        let stats = explorer.stats(now).await.unwrap();
+        assert_eq!((stats.sellers, stats.buyers, stats.active_sellers), (2, 1, 1));
+        assert_eq!(stats.listed_products, 3);
+        assert_eq!((stats.deals_last_24h, stats.deals_last_30d), (4, 4));
+
+        // The lone toys deal is too easy to pin on its seller to show
+        let categories = explorer.top_categories(now).await.unwrap();
+        assert_eq!(categories.len(), 1);
+        assert_eq!(categories[0].category, "tools");
+        assert_eq!(categories[0].average_close_price, PriceBand { low: Decimal::from(100), high: Decimal::from(200) });
+        let deals = explorer.recent_deals(now).await.unwrap();
+        assert_eq!(deals.len(), 3);
+        assert!(deals.iter().all(|deal| deal.category == "tools" && deal.close_price.high == Decimal::from(200)));
+        let body = serde_json::to_string(&deals).unwrap();
+        assert!(!body.contains(&tools.to_string()) && !body.contains(&buyer.to_string()));
+
+        let sellers = explorer.seller_activity(now).await.unwrap();
+        assert_eq!(sellers.active_sellers, 1);
+        assert_eq!(sellers.categories.len(), 1);
+        assert_eq!((sellers.categories[0].category.as_str(), sellers.categories[0].active_sellers), ("tools", 1));
+    }
+}
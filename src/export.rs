@@ -0,0 +1,286 @@
+//! Negotiation transcript export.
+//!
+//! Dumps negotiations, their messages, or the anonymised negotiation records
+//! to JSON Lines or CSV for offline analysis, one table at a time. Amounts are
+//! written as the decimal strings they are stored as, so nothing is lost to
+//! floating point, and statuses and message types as stored.
+
+use crate::{
+    error::{NegotiationError, Result},
+    model::NegotiationStatus,
+    AgentId,
+};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    JsonLines,
+    Csv,
+}
+
+impl FromStr for ExportFormat {
+    type Err = NegotiationError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "jsonl" | "json_lines" | "json-lines" => Ok(ExportFormat::JsonLines),
+            "csv" => Ok(ExportFormat::Csv),
+            _ => Err(NegotiationError::InvalidInput(format!("Unknown export format {} (expected jsonl or csv)", s))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportTable {
+    Negotiations,
+    Messages,
+    /// Anonymised records of closed deals
+    Records,
+}
+
+impl FromStr for ExportTable {
+    type Err = NegotiationError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "negotiations" => Ok(ExportTable::Negotiations),
+            "messages" => Ok(ExportTable::Messages),
+            "records" => Ok(ExportTable::Records),
+            _ => Err(NegotiationError::InvalidInput(format!(
+                "Unknown export table {} (expected negotiations, messages or records)", s
+            ))),
+        }
+    }
+}
+
+/// What to export. Negotiations and their messages are selected by when the
+/// negotiation opened, records by when the deal closed.
+#[derive(Debug, Clone)]
+pub struct ExportQuery {
+    pub table: ExportTable,
+    /// Inclusive
+    pub from: Option<DateTime<Utc>>,
+    /// Exclusive
+    pub to: Option<DateTime<Utc>>,
+    /// Either party
+    pub agent_id: Option<AgentId>,
+    pub product_id: Option<String>,
+    /// Not available for records, which are all closed deals
+    pub status: Option<NegotiationStatus>,
+}
+
+impl ExportQuery {
+    pub fn new(table: ExportTable) -> Self {
+        Self {
+            table,
+            from: None,
+            to: None,
+            agent_id: None,
+            product_id: None,
+            status: None,
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if let (Some(from), Some(to)) = (self.from, self.to) {
+            if from >= to {
+                return Err(NegotiationError::Validation("Export range must end after it starts".to_string()));
+            }
+        }
+        if self.table == ExportTable::Records && self.status.is_some() {
+            return Err(NegotiationError::Validation("Records can't be filtered by status".to_string()));
+        }
+        Ok(())
+    }
+}
+
+/// A date (`2024-06-01`, midnight UTC) or an RFC 3339 timestamp.
+pub fn parse_export_bound(s: &str) -> Result<DateTime<Utc>> {
+    if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        return Ok(date.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc());
+    }
+    DateTime::parse_from_rfc3339(s)
+        .map(|at| at.with_timezone(&Utc))
+        .map_err(|e| NegotiationError::InvalidInput(format!("Invalid date {}: {}", s, e)))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NegotiationExportRow {
+    pub id: String,
+    pub rfq_id: String,
+    pub quote_id: Option<String>,
+    pub buyer_id: String,
+    pub seller_id: String,
+    pub product_id: String,
+    pub quantity: u32,
+    pub opening_bid: String,
+    pub currency: String,
+    pub close_price: Option<String>,
+    pub delta: Option<String>,
+    pub status: String,
+    pub message_count: u32,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageExportRow {
+    pub id: String,
+    pub negotiation_id: String,
+    pub sender_id: String,
+    pub message_type: String,
+    pub content: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordExportRow {
+    pub buyer_id: String,
+    pub seller_id: String,
+    pub product_hash: String,
+    pub opening_bid: String,
+    pub close_price: String,
+    pub delta: String,
+    pub timestamp: DateTime<Utc>,
+    pub duration_seconds: i64,
+    pub message_count: u32,
+}
+
+/// Writes `rows` to `out`, returning how many were written. CSV output starts
+/// with a header row, even when there are no rows.
+pub fn write_rows<T: Serialize, W: Write>(rows: &[T], format: ExportFormat, header: &[&str], mut out: W) -> Result<usize> {
+    match format {
+        ExportFormat::JsonLines => {
+            for row in rows {
+                serde_json::to_writer(&mut out, row)?;
+                out.write_all(b"\n")?;
+            }
+            out.flush()?;
+        }
+        ExportFormat::Csv => {
+            let mut writer = csv::WriterBuilder::new().has_headers(false).from_writer(out);
+            writer.write_record(header).map_err(csv_error)?;
+            for row in rows {
+                writer.serialize(row).map_err(csv_error)?;
+            }
+            writer.flush()?;
+        }
+    }
+    Ok(rows.len())
+}
+
+fn csv_error(err: csv::Error) -> NegotiationError {
+    NegotiationError::Serialization(format!("Failed to write CSV: {}", err))
+}
+
+pub const NEGOTIATION_COLUMNS: &[&str] = &[
+    "id", "rfq_id", "quote_id", "buyer_id", "seller_id", "product_id", "quantity", "opening_bid", "currency",
+    "close_price", "delta", "status", "message_count", "created_at", "updated_at",
+];
+pub const MESSAGE_COLUMNS: &[&str] = &["id", "negotiation_id", "sender_id", "message_type", "content", "created_at"];
+pub const RECORD_COLUMNS: &[&str] = &[
+    "buyer_id", "seller_id", "product_hash", "opening_bid", "close_price", "delta", "timestamp",
+    "duration_seconds", "message_count",
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        database::Database,
+        model::{AgentInfo, AgentType, MessageType, Negotiation},
+    };
+    use rust_decimal::Decimal;
+    use tempfile::NamedTempFile;
+
+    async fn agent(database: &Database, agent_type: AgentType) -> AgentId {
+        let agent = AgentInfo {
+            id: uuid::Uuid::new_v4(),
+            agent_type,
+            name: "Agent".to_string(),
+            endpoint: "http://localhost:8001".to_string(),
+            public_key: "key".to_string(),
+            reputation_score: 50,
+            products: vec![],
+            payment_methods: vec![],
+            protocol_versions: vec![],
+            preferred_languages: vec![],
+            tls_fingerprint: None,
+            created_at: Utc::now(),
+            last_active: Utc::now(),
+        };
+        database.create_agent(&agent).await.unwrap();
+        agent.id
+    }
+
+    fn negotiation(buyer_id: AgentId, seller_id: AgentId, product_id: &str, status: NegotiationStatus) -> Negotiation {
+        let now = Utc::now();
+        let id = uuid::Uuid::new_v4();
+        let mut negotiation = Negotiation {
+            id,
+            rfq_id: uuid::Uuid::new_v4(),
+            quote_id: None,
+            buyer_id,
+            seller_id,
+            product_id: product_id.to_string(),
+            quantity: 2,
+            opening_bid: Decimal::new(10050, 2),
+            currency: "USD".to_string(),
+            close_price: None,
+            delta: None,
+            status,
+            messages: vec![],
+            created_at: now,
+            updated_at: now,
+            delegation_chain: vec![],
+        };
+        negotiation.add_message(buyer_id, MessageType::RFQ, "Looking for two, \"boxed\"".to_string(), now);
+        negotiation
+    }
+
+    #[tokio::test]
+    async fn test_exports_filtered_negotiations_as_jsonl_and_csv() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let database = Database::new(&format!("sqlite://{}", temp_file.path().to_string_lossy())).await.unwrap();
+        let buyer_id = agent(&database, AgentType::Buyer).await;
+        let other_buyer_id = agent(&database, AgentType::Buyer).await;
+        let seller_id = agent(&database, AgentType::Seller).await;
+        for (buyer_id, product_id, status) in [
+            (buyer_id, "laptop-001", NegotiationStatus::Settled),
+            (buyer_id, "phone-001", NegotiationStatus::Rejected),
+            (other_buyer_id, "laptop-001", NegotiationStatus::Settled),
+        ] {
+            database.create_negotiation(&negotiation(buyer_id, seller_id, product_id, status)).await.unwrap();
+        }
+
+        let mut query = ExportQuery::new(ExportTable::Negotiations);
+        query.agent_id = Some(buyer_id);
+        let mut out = Vec::new();
+        assert_eq!(database.export_negotiations(&query, ExportFormat::JsonLines, &mut out).await.unwrap(), 2);
+        let rows: Vec<NegotiationExportRow> = String::from_utf8(out).unwrap().lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert!(rows.iter().all(|row| row.buyer_id == buyer_id.to_string() && row.message_count == 1));
+        assert_eq!(rows[0].opening_bid, "100.50");
+
+        query.status = Some(NegotiationStatus::Settled);
+        query.table = ExportTable::Messages;
+        let mut out = Vec::new();
+        assert_eq!(database.export_negotiations(&query, ExportFormat::Csv, &mut out).await.unwrap(), 1);
+        let csv = String::from_utf8(out).unwrap();
+        assert!(csv.starts_with("id,negotiation_id,sender_id,message_type,content,created_at\n"));
+        assert!(csv.contains(",RFQ,\"Looking for two, \"\"boxed\"\"\","));
+
+        query.from = Some(Utc::now() + chrono::Duration::hours(1));
+        let mut out = Vec::new();
+        assert_eq!(database.export_negotiations(&query, ExportFormat::Csv, &mut out).await.unwrap(), 0);
+
+        query.table = ExportTable::Records;
+        assert!(database.export_negotiations(&query, ExportFormat::Csv, &mut Vec::new()).await.is_err());
+    }
+}
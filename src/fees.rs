@@ -0,0 +1,128 @@
+//! Marketplace fees taken from payments as they settle.
+//!
+//! Every payment is charged a commission of `rate` of its amount plus a flat
+//! fee in its currency, by the rule for its payment method or the default one.
+//! The buyer pays the gross amount; the seller is paid it net of the fee, and
+//! each collected fee is entered in the fee ledger. A refund gives back the
+//! fee in proportion to what it refunds, entered as a negative
+//! [`FeeBreakdown::reversal`].
+
+use crate::{
+    config::{FeeRule, FeesConfig},
+    model::PaymentMethod,
+    money::Money,
+    AgentId, TransactionId,
+};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+/// What a payment comes to before and after the platform's fee
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeeBreakdown {
+    /// Paid by the buyer
+    pub gross: Decimal,
+    pub fee: Decimal,
+    /// Paid out to the seller
+    pub net: Decimal,
+}
+
+impl FeeBreakdown {
+    /// The ledger entry for refunding `refunded` of the gross after
+    /// `previously_refunded` already was: the fee given back in proportion,
+    /// with every amount negative. Shares are rounded cumulatively, so
+    /// refunding all of the gross gives back exactly the fee.
+    pub fn reversal(&self, refunded: &Money, previously_refunded: Decimal) -> FeeBreakdown {
+        let share = |amount: Decimal| {
+            if self.gross.is_zero() {
+                return Decimal::ZERO;
+            }
+            Money::new(self.fee * amount / self.gross, refunded.currency.clone())
+                .round_to_minor_units()
+                .amount
+                .clamp(Decimal::ZERO, self.fee)
+        };
+        let fee = share(previously_refunded + refunded.amount) - share(previously_refunded);
+        FeeBreakdown { gross: -refunded.amount, fee: -fee, net: fee - refunded.amount }
+    }
+}
+
+/// A fee the platform collected, as entered in the fee ledger
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlatformFee {
+    pub payment_id: String,
+    pub transaction_id: TransactionId,
+    pub seller_id: AgentId,
+    pub payment_method: PaymentMethod,
+    pub currency: String,
+    #[serde(flatten)]
+    pub breakdown: FeeBreakdown,
+    pub collected_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FeeSchedule {
+    config: FeesConfig,
+}
+
+impl FeeSchedule {
+    pub fn new(config: FeesConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn rule(&self, method: &PaymentMethod) -> &FeeRule {
+        self.config.methods.get(method).unwrap_or(&self.config.default)
+    }
+
+    /// Splits `amount` paid by `method` into the fee and the seller's share.
+    /// Fees are rounded to the currency's minor unit and never exceed the amount.
+    pub fn assess(&self, method: &PaymentMethod, amount: &Money) -> FeeBreakdown {
+        let rule = self.rule(method);
+        let fee = Money::new(amount.amount * rule.rate + rule.flat, amount.currency.clone())
+            .round_to_minor_units()
+            .amount
+            .clamp(Decimal::ZERO, amount.amount);
+        FeeBreakdown { gross: amount.amount, fee, net: amount.amount - fee }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_fees_use_the_method_rule_and_stay_within_the_amount() {
+        let schedule = FeeSchedule::new(FeesConfig {
+            default: FeeRule { rate: Decimal::new(25, 3), flat: Decimal::new(30, 2) },
+            methods: HashMap::from([(PaymentMethod::Lightning, FeeRule { rate: Decimal::new(1, 2), flat: Decimal::ZERO })]),
+        });
+
+        let card = schedule.assess(&PaymentMethod::Stripe, &Money::new(Decimal::new(10001, 2), "USD"));
+        assert_eq!(card.fee, Decimal::new(280, 2));
+        assert_eq!(card.net, Decimal::new(9721, 2));
+        assert_eq!(card.gross, card.fee + card.net);
+
+        let lightning = schedule.assess(&PaymentMethod::Lightning, &Money::new(Decimal::new(5, 3), "BTC"));
+        assert_eq!(lightning.fee, Decimal::new(5, 5));
+
+        let tiny = schedule.assess(&PaymentMethod::Stripe, &Money::new(Decimal::new(10, 2), "USD"));
+        assert_eq!(tiny.fee, Decimal::new(10, 2));
+        assert_eq!(tiny.net, Decimal::ZERO);
+
+        assert_eq!(FeeSchedule::default().assess(&PaymentMethod::Escrow, &Money::new(Decimal::ONE_HUNDRED, "USD")).fee, Decimal::ZERO);
+    }
+
+    #[test]
+    fn test_refunds_give_back_the_fee_in_proportion() {
+        let fees = FeeBreakdown { gross: Decimal::from(100), fee: Decimal::new(350, 2), net: Decimal::new(9650, 2) };
+        let third = Money::new(Decimal::new(3333, 2), "USD");
+
+        let first = fees.reversal(&third, Decimal::ZERO);
+        assert_eq!(first, FeeBreakdown { gross: -third.amount, fee: Decimal::new(-117, 2), net: Decimal::new(-3216, 2) });
+        let second = fees.reversal(&third, third.amount);
+        let rest = fees.reversal(&Money::new(Decimal::new(3334, 2), "USD"), third.amount * Decimal::TWO);
+        assert_eq!(first.fee + second.fee + rest.fee, -fees.fee);
+        assert_eq!(first.gross + second.gross + rest.gross, -fees.gross);
+    }
+}
@@ -0,0 +1,244 @@
+//! Reputation shared between DCAP instances.
+//!
+//! Each instance signs attestations of the reputations it keeps itself and
+//! serves them on `/reputation/attestations`. Every `interval_seconds` it
+//! pulls the attestations of its configured peers, drops any not signed by
+//! the peer's configured key, and folds in those newer than what it already
+//! holds for the agent, so the most recent score wins wherever it was
+//! earned. Attestations are only made for locally earned reputation, so a
+//! peer's score isn't echoed back to it.
+
+use crate::{
+    config::{GossipConfig, GossipPeer},
+    error::{NegotiationError, Result},
+    http::HttpClient,
+    trust::TrustSystem,
+    AgentId,
+};
+use base64::{engine::general_purpose, Engine};
+use chrono::{DateTime, SecondsFormat, Utc};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+pub const DEFAULT_GOSSIP_INTERVAL_SECONDS: u64 = 300;
+
+const ATTESTATION_CONTEXT: &str = "dcap-reputation-attestation:v1";
+
+/// An instance's signed statement of an agent's reputation
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ReputationAttestation {
+    pub agent_id: AgentId,
+    pub score: u32,
+    /// When the score last changed at the issuer
+    pub as_of: DateTime<Utc>,
+    /// Base64 public key of the instance vouching for the score
+    pub issuer: String,
+    /// Base64 Ed25519 signature over the fields above
+    pub signature: String,
+}
+
+impl ReputationAttestation {
+    pub fn sign(key: &SigningKey, agent_id: AgentId, score: u32, as_of: DateTime<Utc>) -> Self {
+        let issuer = general_purpose::STANDARD.encode(key.verifying_key().as_bytes());
+        let signature = key.sign(&Self::message(agent_id, score, as_of, &issuer));
+        Self {
+            agent_id,
+            score,
+            as_of,
+            issuer,
+            signature: general_purpose::STANDARD.encode(signature.to_bytes()),
+        }
+    }
+
+    /// Fails unless the attestation is signed by its issuer's key.
+    pub fn verify(&self) -> Result<()> {
+        let issuer = verifying_key(&self.issuer)?;
+        let signature = general_purpose::STANDARD.decode(&self.signature).ok()
+            .and_then(|bytes| <[u8; 64]>::try_from(bytes).ok())
+            .map(|bytes| Signature::from_bytes(&bytes))
+            .ok_or_else(|| NegotiationError::Trust("Attestation signatures must be 64 bytes of base64".to_string()))?;
+        issuer.verify(&Self::message(self.agent_id, self.score, self.as_of, &self.issuer), &signature)
+            .map_err(|_| NegotiationError::Trust(format!("Attestation for agent {} has a bad signature", self.agent_id)))
+    }
+
+    fn message(agent_id: AgentId, score: u32, as_of: DateTime<Utc>, issuer: &str) -> Vec<u8> {
+        format!(
+            "{}|{}|{}|{}|{}",
+            ATTESTATION_CONTEXT, agent_id, score, as_of.to_rfc3339_opts(SecondsFormat::Nanos, true), issuer
+        ).into_bytes()
+    }
+}
+
+/// Body of `GET /reputation/attestations`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Attestations {
+    pub attestations: Vec<ReputationAttestation>,
+}
+
+/// Outcome of a pull from the peers
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct GossipReport {
+    /// Attestations newer than the reputation held, and applied
+    pub accepted: usize,
+    /// Attestations no newer than the reputation held
+    pub stale: usize,
+    /// Attestations with a bad signature or from a key other than the peer's
+    pub rejected: usize,
+    /// Peers that couldn't be reached
+    pub unreachable: Vec<String>,
+}
+
+pub struct ReputationGossip {
+    key: Option<SigningKey>,
+    peers: Vec<GossipPeer>,
+    client: HttpClient,
+}
+
+impl ReputationGossip {
+    pub fn from_config(config: &GossipConfig, client: HttpClient) -> Result<Self> {
+        let key = config.signing_key.as_deref().map(|encoded| {
+            general_purpose::STANDARD.decode(encoded.trim()).ok()
+                .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+                .map(|bytes| SigningKey::from_bytes(&bytes))
+                .ok_or_else(|| NegotiationError::Config("The gossip signing key must be 32 bytes of base64".to_string()))
+        }).transpose()?;
+        for peer in &config.peers {
+            verifying_key(&peer.public_key)
+                .map_err(|_| NegotiationError::Config(format!("Gossip peer {} has a malformed public key", peer.url)))?;
+        }
+        if config.interval_seconds == 0 {
+            return Err(NegotiationError::Config("The gossip interval must be positive".to_string()));
+        }
+        Ok(Self { key, peers: config.peers.clone(), client })
+    }
+
+    pub fn peers(&self) -> &[GossipPeer] {
+        &self.peers
+    }
+
+    /// Signed attestations of the reputations `trust` earned itself
+    pub async fn attestations(&self, trust: &TrustSystem) -> Result<Vec<ReputationAttestation>> {
+        let Some(key) = &self.key else {
+            return Err(NegotiationError::Config("No gossip signing key is configured".to_string()));
+        };
+        Ok(trust.get_all_reputations().await?
+            .into_iter()
+            .filter(|reputation| reputation.attestation.is_none())
+            .map(|reputation| ReputationAttestation::sign(key, reputation.agent_id, reputation.score, reputation.last_updated))
+            .collect())
+    }
+
+    /// Pulls every peer's attestations into `trust`, locking it only while
+    /// merging. Unreachable peers are logged and skipped until the next pull.
+    pub async fn pull(&self, trust: &Mutex<TrustSystem>) -> Result<GossipReport> {
+        let mut report = GossipReport::default();
+        for peer in &self.peers {
+            let url = format!("{}/reputation/attestations", peer.url.trim_end_matches('/'));
+            let attestations = match self.fetch(&url).await {
+                Ok(attestations) => attestations,
+                Err(e) => {
+                    tracing::warn!("Failed to pull attestations from {}: {}", peer.url, e);
+                    report.unreachable.push(peer.url.clone());
+                    continue;
+                }
+            };
+            self.merge(&mut *trust.lock().await, peer, attestations, &mut report).await?;
+        }
+        tracing::info!(
+            "Pulled reputation from {} peers: {} accepted, {} stale, {} rejected",
+            self.peers.len() - report.unreachable.len(), report.accepted, report.stale, report.rejected
+        );
+        Ok(report)
+    }
+
+    /// Applies a peer's attestations that verify, are issued by its key and
+    /// are newer than the reputation `trust` holds.
+    pub async fn merge(
+        &self,
+        trust: &mut TrustSystem,
+        peer: &GossipPeer,
+        attestations: Vec<ReputationAttestation>,
+        report: &mut GossipReport,
+    ) -> Result<()> {
+        for attestation in attestations {
+            if attestation.issuer != peer.public_key.trim() {
+                tracing::warn!("Ignoring attestation for agent {} relayed by {}", attestation.agent_id, peer.url);
+                report.rejected += 1;
+                continue;
+            }
+            if let Err(e) = attestation.verify() {
+                tracing::warn!("Ignoring attestation from {}: {}", peer.url, e);
+                report.rejected += 1;
+                continue;
+            }
+            if trust.accept_attestation(attestation).await? {
+                report.accepted += 1;
+            } else {
+                report.stale += 1;
+            }
+        }
+        Ok(())
+    }
+
+    async fn fetch(&self, url: &str) -> Result<Vec<ReputationAttestation>> {
+        let response = self.client.get(url).send().await?.error_for_status()?;
+        Ok(response.json::<Attestations>().await?.attestations)
+    }
+}
+
+fn verifying_key(encoded: &str) -> Result<VerifyingKey> {
+    general_purpose::STANDARD.decode(encoded.trim()).ok()
+        .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+        .and_then(|bytes| VerifyingKey::from_bytes(&bytes).ok())
+        .ok_or_else(|| NegotiationError::Trust("Attestation issuers must be 32 bytes of base64".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[tokio::test]
+    async fn test_newest_verified_attestation_wins() {
+        let peer_key = SigningKey::from_bytes(&rand::random());
+        let peer = GossipPeer {
+            url: "http://peer.example".to_string(),
+            public_key: general_purpose::STANDARD.encode(peer_key.verifying_key().as_bytes()),
+        };
+        let config = GossipConfig {
+            signing_key: Some(general_purpose::STANDARD.encode(SigningKey::from_bytes(&rand::random()).to_bytes())),
+            peers: vec![peer.clone()],
+            ..GossipConfig::default()
+        };
+        let gossip = ReputationGossip::from_config(&config, HttpClient::default()).unwrap();
+        let mut trust = TrustSystem::new().unwrap();
+        let (known, unknown) = (uuid::Uuid::new_v4(), uuid::Uuid::new_v4());
+        trust.update_reputation(known, 10).await.unwrap();
+        let local_score = trust.get_reputation(known).await.unwrap();
+
+        let stale = ReputationAttestation::sign(&peer_key, known, 90, Utc::now() - Duration::minutes(5));
+        let fresh = ReputationAttestation::sign(&peer_key, unknown, 80, Utc::now());
+        let mut forged = ReputationAttestation::sign(&peer_key, unknown, 80, Utc::now());
+        forged.score = 100;
+        let stranger = ReputationAttestation::sign(&SigningKey::from_bytes(&rand::random()), known, 5, Utc::now());
+
+        let mut report = GossipReport::default();
+        gossip.merge(&mut trust, &peer, vec![stale, fresh.clone(), forged, stranger], &mut report).await.unwrap();
+        assert_eq!((report.accepted, report.stale, report.rejected), (1, 1, 2));
+        assert_eq!(trust.get_reputation(known).await.unwrap(), local_score);
+        assert_eq!(trust.get_reputation(unknown).await.unwrap(), 80);
+
+        // Only locally earned reputation is attested, so the peer's isn't echoed
+        let attestations = gossip.attestations(&trust).await.unwrap();
+        assert_eq!(attestations.len(), 1);
+        assert_eq!(attestations[0].agent_id, known);
+        attestations[0].verify().unwrap();
+
+        // A newer attestation replaces the local record
+        let newer = ReputationAttestation::sign(&peer_key, known, 60, Utc::now() + Duration::seconds(1));
+        gossip.merge(&mut trust, &peer, vec![newer, fresh], &mut report).await.unwrap();
+        assert_eq!(trust.get_reputation(known).await.unwrap(), 60);
+        assert_eq!(report.stale, 2);
+    }
+}
@@ -0,0 +1,500 @@
+//! gRPC API alongside HTTP, generated from `proto/dcap.proto`.
+//!
+//! The services call the same agent and discovery logic as the HTTP handlers;
+//! only the wire format differs. Messages mirror the JSON bodies, with
+//! decimal amounts as strings so prices round-trip exactly.
+
+use crate::{
+    agent::SellerAgent,
+    audit_log::{AuditAction, AuditLog},
+    database::Database,
+    discovery::{self, DiscoveryServer},
+    error::NegotiationError,
+    language::Language,
+    model::{AgentInfo, AgentType, PaymentMethod, Product, Quote, QuoteFirmness, ShippingOption, RFQ},
+    nonce::ReplayGuard,
+    protocol::ProtocolVersion,
+    roles::Permission,
+    session::{bearer_token, SessionTokens},
+    trust::TrustSystem,
+};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tonic::{Request, Response, Status};
+
+pub mod proto {
+    tonic::include_proto!("dcap.v1");
+}
+
+use proto::{discovery_server::DiscoveryServer as DiscoveryGrpcServer, seller_server::SellerServer};
+
+impl From<NegotiationError> for Status {
+    fn from(err: NegotiationError) -> Self {
+        match err {
+            NegotiationError::Validation(_) | NegotiationError::InvalidInput(_) | NegotiationError::Currency(_) => {
+                Status::invalid_argument(err.to_string())
+            }
+            NegotiationError::Auth(_) => Status::unauthenticated(err.to_string()),
+            NegotiationError::AgentNotFound(_) | NegotiationError::ProductNotFound(_) | NegotiationError::NotFound(_) => {
+                Status::not_found(err.to_string())
+            }
+            NegotiationError::InsufficientReputation(_) | NegotiationError::Trust(_) => {
+                Status::permission_denied(err.to_string())
+            }
+            NegotiationError::QuoteExpired | NegotiationError::Negotiation(_) => Status::failed_precondition(err.to_string()),
+            NegotiationError::CircuitOpen(_) => Status::unavailable(err.to_string()),
+            _ => Status::internal(err.to_string()),
+        }
+    }
+}
+
+/// Serves quote and negotiation requests for a seller agent.
+pub struct SellerGrpc {
+    agent: Arc<SellerAgent>,
+    database: Database,
+    session_tokens: SessionTokens,
+    /// Checks buyers' agent JWTs and what their roles may do
+    auth: Arc<Mutex<TrustSystem>>,
+    replay_guard: Option<ReplayGuard>,
+    audit: AuditLog,
+}
+
+impl SellerGrpc {
+    pub fn new(agent: Arc<SellerAgent>, database: Database, session_tokens: SessionTokens, auth: Arc<Mutex<TrustSystem>>) -> Self {
+        let audit = AuditLog::new(database.clone());
+        Self { agent, database, session_tokens, auth, replay_guard: None, audit }
+    }
+
+    /// Refuses RFQs whose nonce `replay_guard` has seen before, or that
+    /// their buyer didn't sign.
+    pub fn with_replay_guard(mut self, replay_guard: ReplayGuard) -> Self {
+        self.replay_guard = Some(replay_guard);
+        self
+    }
+
+    pub fn into_service(self) -> SellerServer<Self> {
+        SellerServer::new(self)
+    }
+}
+
+#[tonic::async_trait]
+impl proto::seller_server::Seller for SellerGrpc {
+    async fn request_quote(&self, request: Request<proto::Rfq>) -> Result<Response<proto::Quote>, Status> {
+        let headers = request.metadata().clone().into_headers();
+        let token = bearer_token(&headers)
+            .ok_or_else(|| NegotiationError::Auth("Missing bearer token".to_string()))?;
+        self.auth.lock().await.authorize(token, Permission::RequestQuotes).await?;
+        let rfq = RFQ::try_from(request.into_inner())?;
+        let quote = match &self.replay_guard {
+            None => self.agent.handle_rfq(rfq).await?,
+            Some(replay_guard) => {
+                self.agent.check_rfq(replay_guard, &rfq).await?;
+                let nonce = rfq.nonce.clone();
+                match self.agent.handle_rfq(rfq).await {
+                    Ok(quote) => quote,
+                    Err(e) => {
+                        // The buyer may retry an RFQ that wasn't quoted
+                        replay_guard.release("rfq", nonce.as_deref()).await?;
+                        return Err(e.into());
+                    }
+                }
+            }
+        };
+        self.audit.record(AuditAction::Quote, Some(quote.seller_id), &quote.id.to_string(), &quote).await;
+        Ok(Response::new((&quote).into()))
+    }
+
+    async fn negotiate(&self, request: Request<proto::NegotiateRequest>) -> Result<Response<proto::Quote>, Status> {
+        let headers = request.metadata().clone().into_headers();
+        let request = request.into_inner();
+        let negotiation_id = parse_uuid(&request.negotiation_id)?;
+        let claims = self.session_tokens.authorize(&self.database, &headers, negotiation_id).await?;
+        self.auth.lock().await.roles().check(&claims.role, Permission::Negotiate)?;
+
+        let quote = self.agent.handle_negotiation(
+            negotiation_id,
+            &request.product_id,
+            request.quantity,
+            parse_decimal(&request.counter_offer)?,
+        ).await?;
+        Ok(Response::new((&quote).into()))
+    }
+}
+
+/// Serves registration and search for the discovery service.
+pub struct DiscoveryGrpc {
+    server: DiscoveryServer,
+}
+
+impl DiscoveryGrpc {
+    pub fn new(server: DiscoveryServer) -> Self {
+        Self { server }
+    }
+
+    pub fn into_service(self) -> DiscoveryGrpcServer<Self> {
+        DiscoveryGrpcServer::new(self)
+    }
+}
+
+#[tonic::async_trait]
+impl proto::discovery_server::Discovery for DiscoveryGrpc {
+    async fn register(&self, request: Request<proto::RegisterRequest>) -> Result<Response<proto::AgentInfo>, Status> {
+        let request = discovery::RegisterRequest::try_from(request.into_inner())?;
+        let agent = self.server.handle_register(request).await?;
+        Ok(Response::new((&agent).into()))
+    }
+
+    async fn search(&self, request: Request<proto::SearchRequest>) -> Result<Response<proto::SearchResponse>, Status> {
+        let request = discovery::SearchRequest::try_from(request.into_inner())?;
+        let response = self.server.handle_search(request).await?;
+        Ok(Response::new(proto::SearchResponse {
+            agents: response.agents.iter().map(Into::into).collect(),
+            total_count: response.total_count,
+        }))
+    }
+
+    async fn get_agent(&self, request: Request<proto::GetAgentRequest>) -> Result<Response<proto::AgentInfo>, Status> {
+        let agent_id = parse_uuid(&request.into_inner().agent_id)?;
+        let agent = self.server.get_agent_info(agent_id).await?
+            .ok_or(NegotiationError::AgentNotFound(agent_id))?;
+        Ok(Response::new((&agent).into()))
+    }
+}
+
+fn parse_uuid(value: &str) -> Result<uuid::Uuid, NegotiationError> {
+    uuid::Uuid::parse_str(value).map_err(|e| NegotiationError::Validation(format!("Invalid ID {:?}: {}", value, e)))
+}
+
+fn parse_decimal(value: &str) -> Result<Decimal, NegotiationError> {
+    Decimal::from_str(value).map_err(|e| NegotiationError::Validation(format!("Invalid amount {:?}: {}", value, e)))
+}
+
+fn parse_time(value: &str) -> Result<DateTime<Utc>, NegotiationError> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|time| time.with_timezone(&Utc))
+        .map_err(|e| NegotiationError::Validation(format!("Invalid timestamp {:?}: {}", value, e)))
+}
+
+fn parse_versions(values: &[String]) -> Result<Vec<ProtocolVersion>, NegotiationError> {
+    values.iter()
+        .map(|value| value.parse().map_err(|_| NegotiationError::Validation(format!("Invalid protocol version {:?}", value))))
+        .collect()
+}
+
+fn parse_languages(values: &[String]) -> Result<Vec<Language>, NegotiationError> {
+    values.iter().map(|value| value.parse()).collect()
+}
+
+fn agent_type_to_proto(agent_type: &AgentType) -> proto::AgentType {
+    match agent_type {
+        AgentType::Buyer => proto::AgentType::Buyer,
+        AgentType::Seller => proto::AgentType::Seller,
+    }
+}
+
+fn agent_type_from_proto(value: i32) -> Result<AgentType, NegotiationError> {
+    match proto::AgentType::try_from(value) {
+        Ok(proto::AgentType::Buyer) => Ok(AgentType::Buyer),
+        Ok(proto::AgentType::Seller) => Ok(AgentType::Seller),
+        _ => Err(NegotiationError::Validation("Agent type is required".to_string())),
+    }
+}
+
+fn payment_method_to_proto(method: &PaymentMethod) -> i32 {
+    let method = match method {
+        PaymentMethod::Stripe => proto::PaymentMethod::Stripe,
+        PaymentMethod::Solana => proto::PaymentMethod::Solana,
+        PaymentMethod::Escrow => proto::PaymentMethod::Escrow,
+        PaymentMethod::PayOnDelivery => proto::PaymentMethod::PayOnDelivery,
+        PaymentMethod::Lightning => proto::PaymentMethod::Lightning,
+    };
+    method as i32
+}
+
+fn payment_methods_from_proto(values: &[i32]) -> Result<Vec<PaymentMethod>, NegotiationError> {
+    values.iter()
+        .map(|value| match proto::PaymentMethod::try_from(*value) {
+            Ok(proto::PaymentMethod::Stripe) => Ok(PaymentMethod::Stripe),
+            Ok(proto::PaymentMethod::Solana) => Ok(PaymentMethod::Solana),
+            Ok(proto::PaymentMethod::Escrow) => Ok(PaymentMethod::Escrow),
+            Ok(proto::PaymentMethod::PayOnDelivery) => Ok(PaymentMethod::PayOnDelivery),
+            Ok(proto::PaymentMethod::Lightning) => Ok(PaymentMethod::Lightning),
+            _ => Err(NegotiationError::Validation(format!("Unknown payment method {}", value))),
+        })
+        .collect()
+}
+
+impl From<&RFQ> for proto::Rfq {
+    fn from(rfq: &RFQ) -> Self {
+        Self {
+            id: rfq.id.to_string(),
+            buyer_id: rfq.buyer_id.to_string(),
+            product_id: rfq.product_id.clone(),
+            quantity: rfq.quantity,
+            max_price: rfq.max_price.to_string(),
+            currency: rfq.currency.clone(),
+            delivery_location: rfq.delivery_location.clone(),
+            deadline: rfq.deadline.to_rfc3339(),
+            metadata: rfq.metadata.clone(),
+            nonce: rfq.nonce.clone(),
+            issued_at: rfq.issued_at.map(|issued_at| issued_at.to_rfc3339()),
+            signature: rfq.signature.clone(),
+        }
+    }
+}
+
+impl TryFrom<proto::Rfq> for RFQ {
+    type Error = NegotiationError;
+
+    fn try_from(rfq: proto::Rfq) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: parse_uuid(&rfq.id)?,
+            buyer_id: parse_uuid(&rfq.buyer_id)?,
+            product_id: rfq.product_id,
+            quantity: rfq.quantity,
+            max_price: parse_decimal(&rfq.max_price)?,
+            currency: rfq.currency,
+            delivery_location: rfq.delivery_location,
+            deadline: parse_time(&rfq.deadline)?,
+            metadata: rfq.metadata,
+            nonce: rfq.nonce,
+            issued_at: rfq.issued_at.as_deref().map(parse_time).transpose()?,
+            signature: rfq.signature,
+        })
+    }
+}
+
+impl From<&QuoteFirmness> for proto::QuoteFirmness {
+    fn from(firmness: &QuoteFirmness) -> Self {
+        match firmness {
+            QuoteFirmness::Indicative => Self { level: "indicative".to_string(), penalty: None },
+            QuoteFirmness::Firm => Self { level: "firm".to_string(), penalty: None },
+            QuoteFirmness::BindingWithPenalty { penalty } => Self {
+                level: "binding_with_penalty".to_string(),
+                penalty: Some(penalty.to_string()),
+            },
+        }
+    }
+}
+
+impl TryFrom<proto::QuoteFirmness> for QuoteFirmness {
+    type Error = NegotiationError;
+
+    fn try_from(firmness: proto::QuoteFirmness) -> Result<Self, Self::Error> {
+        match (firmness.level.as_str(), firmness.penalty) {
+            ("indicative", _) => Ok(Self::Indicative),
+            ("firm", _) => Ok(Self::Firm),
+            ("binding_with_penalty", Some(penalty)) => Ok(Self::BindingWithPenalty { penalty: parse_decimal(&penalty)? }),
+            (level, _) => Err(NegotiationError::Validation(format!("Invalid quote firmness {:?}", level))),
+        }
+    }
+}
+
+impl From<&Quote> for proto::Quote {
+    fn from(quote: &Quote) -> Self {
+        Self {
+            id: quote.id.to_string(),
+            rfq_id: quote.rfq_id.to_string(),
+            seller_id: quote.seller_id.to_string(),
+            price: quote.price.to_string(),
+            currency: quote.currency.clone(),
+            available_quantity: quote.available_quantity,
+            delivery_estimate: quote.delivery_estimate.clone(),
+            ttl_seconds: quote.ttl_seconds,
+            firmness: Some((&quote.firmness).into()),
+            metadata: quote.metadata.clone(),
+            created_at: quote.created_at.to_rfc3339(),
+            shipping_options: quote.shipping_options.iter().map(Into::into).collect(),
+            nonce: quote.nonce.clone(),
+            signature: quote.signature.clone(),
+        }
+    }
+}
+
+impl TryFrom<proto::Quote> for Quote {
+    type Error = NegotiationError;
+
+    fn try_from(quote: proto::Quote) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: parse_uuid(&quote.id)?,
+            rfq_id: parse_uuid(&quote.rfq_id)?,
+            seller_id: parse_uuid(&quote.seller_id)?,
+            price: parse_decimal(&quote.price)?,
+            currency: quote.currency,
+            available_quantity: quote.available_quantity,
+            delivery_estimate: quote.delivery_estimate,
+            ttl_seconds: quote.ttl_seconds,
+            // Quotes without a firmness level are firm, as over HTTP
+            firmness: quote.firmness.map(QuoteFirmness::try_from).transpose()?.unwrap_or_default(),
+            metadata: quote.metadata,
+            created_at: parse_time(&quote.created_at)?,
+            shipping_options: quote.shipping_options.into_iter().map(ShippingOption::try_from).collect::<Result<_, _>>()?,
+            nonce: quote.nonce,
+            signature: quote.signature,
+        })
+    }
+}
+
+impl From<&ShippingOption> for proto::ShippingOption {
+    fn from(option: &ShippingOption) -> Self {
+        Self {
+            carrier: option.carrier.clone(),
+            cost: option.cost.to_string(),
+            eta_days: option.eta_days,
+        }
+    }
+}
+
+impl TryFrom<proto::ShippingOption> for ShippingOption {
+    type Error = NegotiationError;
+
+    fn try_from(option: proto::ShippingOption) -> Result<Self, Self::Error> {
+        Ok(Self {
+            carrier: option.carrier,
+            cost: parse_decimal(&option.cost)?,
+            eta_days: option.eta_days,
+        })
+    }
+}
+
+impl From<&Product> for proto::Product {
+    fn from(product: &Product) -> Self {
+        Self {
+            id: product.id.clone(),
+            name: product.name.clone(),
+            description: product.description.clone(),
+            category: product.category.clone(),
+            base_price: product.base_price.to_string(),
+            currency: product.currency.clone(),
+            stock_quantity: product.stock_quantity,
+            metadata: product.metadata.clone(),
+        }
+    }
+}
+
+impl TryFrom<proto::Product> for Product {
+    type Error = NegotiationError;
+
+    fn try_from(product: proto::Product) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: product.id,
+            name: product.name,
+            description: product.description,
+            category: product.category,
+            base_price: parse_decimal(&product.base_price)?,
+            currency: product.currency,
+            stock_quantity: product.stock_quantity,
+            metadata: product.metadata,
+        })
+    }
+}
+
+impl From<&AgentInfo> for proto::AgentInfo {
+    fn from(agent: &AgentInfo) -> Self {
+        Self {
+            id: agent.id.to_string(),
+            agent_type: agent_type_to_proto(&agent.agent_type) as i32,
+            name: agent.name.clone(),
+            endpoint: agent.endpoint.clone(),
+            public_key: agent.public_key.clone(),
+            reputation_score: agent.reputation_score,
+            products: agent.products.iter().map(Into::into).collect(),
+            payment_methods: agent.payment_methods.iter().map(payment_method_to_proto).collect(),
+            protocol_versions: agent.protocol_versions.iter().map(ToString::to_string).collect(),
+            preferred_languages: agent.preferred_languages.iter().map(ToString::to_string).collect(),
+            tls_fingerprint: agent.tls_fingerprint.clone(),
+            created_at: agent.created_at.to_rfc3339(),
+            last_active: agent.last_active.to_rfc3339(),
+        }
+    }
+}
+
+impl TryFrom<proto::AgentInfo> for AgentInfo {
+    type Error = NegotiationError;
+
+    fn try_from(agent: proto::AgentInfo) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: parse_uuid(&agent.id)?,
+            agent_type: agent_type_from_proto(agent.agent_type)?,
+            name: agent.name,
+            endpoint: agent.endpoint,
+            public_key: agent.public_key,
+            reputation_score: agent.reputation_score,
+            products: agent.products.into_iter().map(Product::try_from).collect::<Result<_, _>>()?,
+            payment_methods: payment_methods_from_proto(&agent.payment_methods)?,
+            protocol_versions: parse_versions(&agent.protocol_versions)?,
+            preferred_languages: parse_languages(&agent.preferred_languages)?,
+            tls_fingerprint: agent.tls_fingerprint,
+            created_at: parse_time(&agent.created_at)?,
+            last_active: parse_time(&agent.last_active)?,
+        })
+    }
+}
+
+impl TryFrom<proto::RegisterRequest> for discovery::RegisterRequest {
+    type Error = NegotiationError;
+
+    fn try_from(request: proto::RegisterRequest) -> Result<Self, Self::Error> {
+        Ok(Self {
+            agent_id: request.agent_id.as_deref().map(parse_uuid).transpose()?,
+            agent_type: agent_type_from_proto(request.agent_type)?,
+            name: request.name,
+            endpoint: request.endpoint,
+            public_key: request.public_key,
+            payment_methods: payment_methods_from_proto(&request.payment_methods)?,
+            protocol_versions: parse_versions(&request.protocol_versions)?,
+            preferred_languages: parse_languages(&request.preferred_languages)?,
+            products: vec![],
+            compliance: Default::default(),
+            tls_fingerprint: request.tls_fingerprint,
+        })
+    }
+}
+
+impl TryFrom<proto::SearchRequest> for discovery::SearchRequest {
+    type Error = NegotiationError;
+
+    fn try_from(request: proto::SearchRequest) -> Result<Self, Self::Error> {
+        let payment_methods = payment_methods_from_proto(&request.payment_methods)?;
+        Ok(Self {
+            category: request.category,
+            min_reputation: request.min_reputation,
+            payment_methods: if payment_methods.is_empty() { None } else { Some(payment_methods) },
+            region: request.region,
+            max_response_time_ms: request.max_response_time_ms,
+            rank_by_responsiveness: request.rank_by_responsiveness,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quotes_round_trip_exactly() {
+        let quote = Quote::new(uuid::Uuid::new_v4(), uuid::Uuid::new_v4(), Decimal::new(249999, 2), "USD".to_string(), 2, 3600)
+            .with_firmness(QuoteFirmness::BindingWithPenalty { penalty: Decimal::new(5000, 2) })
+            .with_shipping_options(vec![ShippingOption { carrier: "DHL".to_string(), cost: Decimal::new(1999, 2), eta_days: 2 }]);
+
+        let message = proto::Quote::from(&quote);
+        assert_eq!(message.price, "2499.99");
+        let decoded = Quote::try_from(message.clone()).unwrap();
+        assert_eq!(decoded.id, quote.id);
+        assert_eq!(decoded.price, quote.price);
+        assert_eq!(decoded.firmness, quote.firmness);
+        assert_eq!(decoded.shipping_options, quote.shipping_options);
+        assert_eq!(decoded.created_at, quote.created_at);
+
+        // Messages without a firmness level are treated as firm
+        let legacy = proto::Quote { firmness: None, ..message.clone() };
+        assert_eq!(Quote::try_from(legacy).unwrap().firmness, QuoteFirmness::Firm);
+
+        let malformed = proto::Quote { price: "cheap".to_string(), ..message };
+        let status = Status::from(Quote::try_from(malformed).unwrap_err());
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+    }
+}
@@ -0,0 +1,287 @@
+//! Moving an agent to another host without downtime.
+//!
+//! The new host starts as a warm standby under the old host's agent ID. It
+//! pulls the state the old host exports from `GET /admin/state`, imports it
+//! and starts serving, while buyers are still routed to the old host. It then
+//! asks the registry to hand the agent over, which moves the registered
+//! endpoint from the old host to the new one in a single update: buyers find
+//! one host or the other, never neither. The old host can then be stopped;
+//! its deregistration names its own endpoint, so it leaves the new
+//! registration alone.
+//!
+//! Negotiations, quotes and sessions live in the shared database. What is
+//! exported is the state agents only hold in memory: a buyer's open
+//! negotiations and agreements, and a seller's catalog and counters. The
+//! buyer's access, refresh and session tokens stay behind; the new host is
+//! issued its own. Only the response tokens of the buyer's demand signals
+//! travel, as discovery can't issue them again.
+//!
+//! The export is only served to admin tokens. Both hosts need the same
+//! `[trust]` configuration, so the new host signs its own with
+//! [`handover_token`].
+
+use crate::{
+    agent::PendingSettlement,
+    agreement::SupplyAgreement,
+    discovery::HandoverRequest,
+    error::{NegotiationError, Result},
+    metrics::SellerCounters,
+    model::{Negotiation, Product, Quote},
+    protocol::{ProtocolVersion, CURRENT_VERSION, PROTOCOL_VERSION_HEADER},
+    roles::Role,
+    trust::TrustSystem,
+    AgentId, TransactionId,
+};
+use chrono::{DateTime, Duration, Utc};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// Version of the exported state format; other versions are refused
+pub const STATE_FORMAT_VERSION: u32 = 1;
+
+/// A buyer's in-memory state, as handed to the host taking over from it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BuyerState {
+    pub format_version: u32,
+    pub agent_id: AgentId,
+    /// Where the buyer ran when it was exported
+    pub endpoint: String,
+    pub exported_at: DateTime<Utc>,
+    pub negotiations: Vec<Negotiation>,
+    pub latest_quotes: HashMap<TransactionId, Quote>,
+    pub negotiated_versions: HashMap<AgentId, ProtocolVersion>,
+    /// Response token for each demand signal the buyer published
+    pub demand_tokens: HashMap<Uuid, String>,
+    pub agreements: Vec<SupplyAgreement>,
+    /// RFQ deadline of each negotiation; exports that predate deadlines have none
+    #[serde(default)]
+    pub deadlines: HashMap<TransactionId, DateTime<Utc>>,
+    /// Accepted negotiations waiting on their payment's confirmation
+    #[serde(default)]
+    pub pending_settlements: HashMap<TransactionId, PendingSettlement>,
+}
+
+/// A seller's in-memory state, as handed to the host taking over from it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SellerState {
+    pub format_version: u32,
+    pub agent_id: AgentId,
+    /// Where the seller ran when it was exported
+    pub endpoint: String,
+    pub exported_at: DateTime<Utc>,
+    /// The catalog, with any changes made since the seller started
+    pub products: Vec<Product>,
+    pub counters: SellerCounters,
+}
+
+/// Refuses state exported in another format or by another agent.
+pub fn check_state(format_version: u32, exported_by: AgentId, agent_id: AgentId) -> Result<()> {
+    if format_version != STATE_FORMAT_VERSION {
+        return Err(NegotiationError::Validation(format!(
+            "State format {} isn't supported; expected {}", format_version, STATE_FORMAT_VERSION
+        )));
+    }
+    if exported_by != agent_id {
+        return Err(NegotiationError::Validation(format!(
+            "State was exported by agent {}, not {}", exported_by, agent_id
+        )));
+    }
+    Ok(())
+}
+
+/// The registry handover taking an agent from `from_endpoint` to
+/// `to_endpoint`.
+pub fn handover_request(from_endpoint: &str, to_endpoint: &str) -> Result<HandoverRequest> {
+    if from_endpoint == to_endpoint {
+        return Err(NegotiationError::Validation(format!("Agent is already at {}", to_endpoint)));
+    }
+    Ok(HandoverRequest {
+        from_endpoint: from_endpoint.to_string(),
+        to_endpoint: to_endpoint.to_string(),
+        tls_fingerprint: None,
+    })
+}
+
+/// A short-lived admin token for fetching an agent's state from its old
+/// host.
+pub fn handover_token(trust: &TrustSystem) -> Result<String> {
+    trust.generate_role_jwt("handover", Role::Admin, Duration::minutes(5))
+}
+
+/// Fetches the state exported by the agent running at `endpoint`, with an
+/// admin token from [`handover_token`].
+pub async fn fetch_state<T: DeserializeOwned>(endpoint: &str, admin_token: &str) -> Result<T> {
+    let response = reqwest::Client::new()
+        .get(format!("{}/admin/state", endpoint.trim_end_matches('/')))
+        .bearer_auth(admin_token)
+        .header(PROTOCOL_VERSION_HEADER, CURRENT_VERSION.to_string())
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(response.json().await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        agent::{BuyerAgent, BuyerAgentConfig, LLMConfig, SellerAgent, SellerAgentConfig},
+        database::Database,
+        discovery::DiscoveryService,
+        events::EventKind,
+        metrics::Metrics,
+        model::{NegotiationStatus, RFQ},
+        settlement::{SettlementConfig, SettlementService},
+        trust::TrustSystem,
+    };
+    use rust_decimal::Decimal;
+    use tempfile::NamedTempFile;
+
+    async fn seller(agent_id: AgentId, endpoint: &str, products: Vec<Product>) -> (SellerAgent, Metrics) {
+        let metrics = Metrics::new();
+        let config = SellerAgentConfig {
+            agent_id,
+            name: "TechSeller".to_string(),
+            endpoint: endpoint.to_string(),
+            products,
+            payment_methods: vec![],
+            llm_config: LLMConfig { model: "gpt-4".to_string(), api_key: String::new(), max_tokens: 1000, temperature: 0.7 },
+            calendar: Default::default(),
+            pricing: Default::default(),
+            quote_ttl: Default::default(),
+            quote_firmness: Default::default(),
+            locale: Default::default(),
+            preferred_languages: vec![],
+            compliance: Default::default(),
+            templates: Default::default(),
+            shipping: Default::default(),
+            tax: Default::default(),
+        };
+        let agent = SellerAgent::new(config, DiscoveryService::new(String::new()), TrustSystem::new().unwrap()).await.unwrap()
+            .with_metrics(metrics.clone());
+        (agent, metrics)
+    }
+
+    fn product(id: &str) -> Product {
+        Product {
+            id: id.to_string(),
+            name: id.to_string(),
+            description: String::new(),
+            category: "Electronics".to_string(),
+            base_price: Decimal::from(100),
+            currency: "USD".to_string(),
+            stock_quantity: 5,
+            metadata: HashMap::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_standby_takes_on_the_exported_seller_state() {
+        let agent_id = AgentId::new_v4();
+        let (old, old_metrics) = seller(agent_id, "http://old-host:8001", vec![product("laptop-001"), product("mouse-001")]).await;
+        old_metrics.rfq_received();
+        old_metrics.rfq_received();
+        old_metrics.quote_issued();
+
+        // The state crosses hosts as JSON
+        let state: SellerState = serde_json::from_str(&serde_json::to_string(&old.export_state()).unwrap()).unwrap();
+        let (standby, standby_metrics) = seller(agent_id, "http://new-host:8001", vec![product("laptop-001")]).await;
+        standby.import_state(state.clone()).await.unwrap();
+        standby.import_state(state.clone()).await.unwrap();
+        assert_eq!(standby.products().len(), 2);
+        assert_eq!(standby_metrics.seller_counters(), SellerCounters { rfqs_received: 2, quotes_issued: 1 });
+        assert_eq!(handover_request(&state.endpoint, "http://new-host:8001").unwrap().from_endpoint, "http://old-host:8001");
+
+        let (other, _) = seller(AgentId::new_v4(), "http://new-host:8001", vec![]).await;
+        assert!(other.import_state(state.clone()).await.is_err());
+        assert!(standby.import_state(SellerState { format_version: STATE_FORMAT_VERSION + 1, ..state }).await.is_err());
+        assert!(handover_request("http://new-host:8001", "http://new-host:8001").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_state_is_only_exported_to_admins() {
+        use crate::roles::{self, Permission};
+        use axum::{routing::get, Json, Router};
+        use std::sync::Arc;
+        use tokio::sync::RwLock;
+
+        let agent_id = AgentId::new_v4();
+        let (old, _) = seller(agent_id, "http://old-host:8001", vec![product("laptop-001")]).await;
+        let exported = old.export_state();
+        let trust = TrustSystem::new().unwrap();
+        let admin_token = handover_token(&trust).unwrap();
+        let agent_token = trust.generate_jwt(agent_id).await.unwrap();
+        let administer = Router::new().route("/admin/state", get(move || async move { Json(exported) }));
+        let app: Router = roles::require_permission(administer, Arc::new(RwLock::new(trust)), Permission::Administer);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        assert!(fetch_state::<SellerState>(&endpoint, "").await.is_err());
+        assert!(fetch_state::<SellerState>(&endpoint, &agent_token).await.is_err());
+        let state: SellerState = fetch_state(&endpoint, &admin_token).await.unwrap();
+        assert_eq!(state.agent_id, agent_id);
+    }
+
+    #[tokio::test]
+    async fn test_imported_negotiations_expire_at_their_deadline() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let database = Database::new(&format!("sqlite://{}", temp_file.path().to_string_lossy())).await.unwrap();
+        let settlement = SettlementService::new(SettlementConfig {
+            stripe_secret_key: None,
+            solana_rpc_url: None,
+            escrow_service_url: None,
+            webhook_secret: None,
+            delivery_confirmation_timeout_seconds: None,
+        }, database).await.unwrap();
+        let agent_id = AgentId::new_v4();
+        let config = BuyerAgentConfig {
+            agent_id,
+            name: "TechBuyer".to_string(),
+            endpoint: "http://buyer:8003".to_string(),
+            max_concurrent_negotiations: 5,
+            default_ttl_hours: 24,
+            llm_config: LLMConfig { model: "gpt-4".to_string(), api_key: String::new(), max_tokens: 1000, temperature: 0.7 },
+            currency: "USD".to_string(),
+            locale: Default::default(),
+            preferred_languages: vec![],
+            region: None,
+            max_response_time_ms: None,
+            payment_method: None,
+        };
+        let mut buyer = BuyerAgent::new(config, DiscoveryService::new(String::new()), TrustSystem::new().unwrap(), settlement).await.unwrap();
+        let mut events = buyer.events().subscribe();
+
+        let now = Utc::now();
+        let negotiation = |deadline: DateTime<Utc>| {
+            let rfq = RFQ::new(agent_id, "laptop-001".to_string(), 1, Decimal::from(1000), "USD".to_string(), deadline);
+            let mut negotiation = Negotiation::new(rfq, AgentId::new_v4());
+            negotiation.status = NegotiationStatus::Quoted;
+            (negotiation, deadline)
+        };
+        let (overdue, overdue_deadline) = negotiation(now - chrono::Duration::minutes(1));
+        let (open, open_deadline) = negotiation(now + chrono::Duration::hours(1));
+        buyer.import_state(BuyerState {
+            format_version: STATE_FORMAT_VERSION,
+            agent_id,
+            endpoint: "http://old-buyer:8003".to_string(),
+            exported_at: now,
+            negotiations: vec![overdue.clone(), open.clone()],
+            latest_quotes: HashMap::new(),
+            negotiated_versions: HashMap::new(),
+            demand_tokens: HashMap::new(),
+            agreements: vec![],
+            deadlines: HashMap::from([(overdue.id, overdue_deadline), (open.id, open_deadline)]),
+            pending_settlements: HashMap::new(),
+        }).unwrap();
+
+        assert_eq!(buyer.expire_overdue(now).await, vec![overdue.id]);
+        assert_eq!(buyer.get_negotiation(overdue.id).unwrap().status, NegotiationStatus::Expired);
+        assert_eq!(buyer.get_negotiation(open.id).unwrap().status, NegotiationStatus::Quoted);
+        assert_eq!(buyer.get_active_negotiations().len(), 2);
+        assert_eq!(events.recv().await.unwrap().kind, EventKind::DeadlinePassed { negotiation_id: overdue.id, deadline: overdue_deadline });
+        assert!(buyer.expire_overdue(now).await.is_empty());
+        assert!(buyer.accept_quote(overdue.id).await.is_err());
+    }
+}
@@ -0,0 +1,325 @@
+//! Outbound HTTP with retries and circuit breakers.
+//!
+//! Discovery, the buyer agent and the anchoring gateway reach other services
+//! through an [`HttpClient`] instead of a bare `reqwest::Client`, so a dropped
+//! connection or a restarting pod doesn't fail the call outright:
+//!
+//! - a request that never reached the server is retried whatever its method;
+//!   one that timed out or got a 5xx or 429 back is only retried when its
+//!   method is idempotent or it carries an `Idempotency-Key`, so a POST is
+//!   never acted on twice
+//! - retries back off exponentially from `initial_backoff_ms` up to
+//!   `max_backoff_ms`, with jitter, and honour a `Retry-After` up to that cap
+//! - each endpoint (scheme, host and port) has its own circuit breaker: after
+//!   `failure_threshold` failures in a row, calls to it fail fast with
+//!   [`NegotiationError::CircuitOpen`] for `open_seconds`, after which one
+//!   trial call decides whether it closes again
+//!
+//! [`HttpClient::with_tls`] presents the service's certificate to the
+//! endpoints it calls and pins the certificates agents registered with
+//! discovery.
+
+use crate::{
+    config::{HttpConfig, TlsConfig},
+    error::{NegotiationError, Result},
+    idempotency::IDEMPOTENCY_KEY_HEADER,
+    tls::{CertificatePins, TlsIdentity},
+};
+use rand::Rng;
+use reqwest::{header::{HeaderMap, HeaderName, HeaderValue, RETRY_AFTER}, Client, IntoUrl, Request, RequestBuilder, Response, StatusCode};
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+pub const DEFAULT_MAX_RETRIES: u32 = 2;
+pub const DEFAULT_INITIAL_BACKOFF_MS: u64 = 100;
+pub const DEFAULT_MAX_BACKOFF_MS: u64 = 2_000;
+pub const DEFAULT_TIMEOUT_MS: u64 = 10_000;
+pub const DEFAULT_CONNECT_TIMEOUT_MS: u64 = 3_000;
+pub const DEFAULT_FAILURE_THRESHOLD: u32 = 5;
+pub const DEFAULT_OPEN_SECONDS: u64 = 30;
+
+#[derive(Debug, Default)]
+struct Breaker {
+    /// Failures in a row
+    failures: u32,
+    open_until: Option<Instant>,
+}
+
+/// A `reqwest::Client` that retries transient failures. Clones share the
+/// connection pool and circuit breakers.
+#[derive(Debug, Clone)]
+pub struct HttpClient {
+    client: Client,
+    config: HttpConfig,
+    breakers: Arc<Mutex<HashMap<String, Breaker>>>,
+    pins: CertificatePins,
+    /// Of the certificate presented to servers, with TLS set up
+    tls_fingerprint: Option<String>,
+}
+
+impl Default for HttpClient {
+    fn default() -> Self {
+        Self::new(&HttpConfig::default())
+    }
+}
+
+impl HttpClient {
+    pub fn new(config: &HttpConfig) -> Self {
+        let client = Self::builder(config).build().unwrap_or_default();
+        Self {
+            client,
+            config: config.clone(),
+            breakers: Arc::new(Mutex::new(HashMap::new())),
+            pins: CertificatePins::default(),
+            tls_fingerprint: None,
+        }
+    }
+
+    fn builder(config: &HttpConfig) -> reqwest::ClientBuilder {
+        Client::builder()
+            .timeout(Duration::from_millis(config.timeout_ms))
+            .connect_timeout(Duration::from_millis(config.connect_timeout_ms))
+    }
+
+    /// Presents the configured certificate to servers, and only trusts
+    /// servers with a pinned certificate at hosts that have one.
+    pub fn with_tls(mut self, tls: &TlsConfig) -> Result<Self> {
+        let identity = TlsIdentity::load(tls)?;
+        self.client = Self::builder(&self.config)
+            .use_preconfigured_tls(identity.client_config(tls, self.pins.clone())?)
+            .build()?;
+        self.tls_fingerprint = Some(identity.fingerprint());
+        Ok(self)
+    }
+
+    /// With `[server.tls]` set, as [`with_tls`](Self::with_tls)
+    pub fn for_server(http: &HttpConfig, tls: Option<&TlsConfig>) -> Result<Self> {
+        match tls {
+            Some(tls) => Self::new(http).with_tls(tls),
+            None => Ok(Self::new(http)),
+        }
+    }
+
+    pub fn tls_fingerprint(&self) -> Option<&str> {
+        self.tls_fingerprint.as_deref()
+    }
+
+    /// Only trusts the host of `endpoint` with the certificate `fingerprint`
+    /// from now on, or another one pinned for it.
+    pub fn pin_certificate(&self, endpoint: &str, fingerprint: &str) -> Result<()> {
+        self.pins.pin(endpoint, fingerprint)
+    }
+
+    pub fn get<U: IntoUrl>(&self, url: U) -> HttpRequest {
+        self.request(self.client.get(url))
+    }
+
+    pub fn post<U: IntoUrl>(&self, url: U) -> HttpRequest {
+        self.request(self.client.post(url))
+    }
+
+    pub fn put<U: IntoUrl>(&self, url: U) -> HttpRequest {
+        self.request(self.client.put(url))
+    }
+
+    pub fn delete<U: IntoUrl>(&self, url: U) -> HttpRequest {
+        self.request(self.client.delete(url))
+    }
+
+    fn request(&self, builder: RequestBuilder) -> HttpRequest {
+        HttpRequest { http: self.clone(), builder }
+    }
+
+    /// Whether calls to `endpoint` are currently failing fast
+    pub fn is_open(&self, endpoint: &str) -> bool {
+        let breakers = self.breakers.lock().unwrap();
+        breakers.get(endpoint)
+            .and_then(|breaker| breaker.open_until)
+            .is_some_and(|until| Instant::now() < until)
+    }
+
+    fn check_breaker(&self, endpoint: &str) -> Result<()> {
+        if self.is_open(endpoint) {
+            return Err(NegotiationError::CircuitOpen(endpoint.to_string()));
+        }
+        Ok(())
+    }
+
+    fn record(&self, endpoint: &str, failed: bool) {
+        let mut breakers = self.breakers.lock().unwrap();
+        if !failed {
+            breakers.remove(endpoint);
+            return;
+        }
+        let breaker = breakers.entry(endpoint.to_string()).or_default();
+        breaker.failures += 1;
+        if breaker.failures >= self.config.failure_threshold.max(1) {
+            if breaker.open_until.is_none() {
+                tracing::warn!("Circuit to {} opened after {} failures", endpoint, breaker.failures);
+            }
+            breaker.open_until = Some(Instant::now() + Duration::from_secs(self.config.open_seconds));
+        }
+    }
+
+    /// Delay before retry number `attempt` (from 0): exponential with jitter
+    /// over its upper half, stretched to a server's `Retry-After`.
+    fn backoff(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        let max = self.config.max_backoff_ms.max(self.config.initial_backoff_ms);
+        let ceiling = self.config.initial_backoff_ms.saturating_mul(1u64 << attempt.min(20)).min(max);
+        let delay = Duration::from_millis(rand::thread_rng().gen_range(ceiling / 2..=ceiling));
+        match retry_after {
+            Some(retry_after) => delay.max(retry_after.min(Duration::from_millis(max))),
+            None => delay,
+        }
+    }
+
+    async fn execute(&self, request: Request) -> Result<Response> {
+        let endpoint = request.url().origin().ascii_serialization();
+        let idempotent = request.method().is_idempotent() || request.headers().contains_key(IDEMPOTENCY_KEY_HEADER);
+        let mut request = request;
+        let mut attempt = 0;
+        loop {
+            self.check_breaker(&endpoint)?;
+            let retry = (attempt < self.config.max_retries).then(|| request.try_clone()).flatten();
+            let result = self.client.execute(request).await;
+
+            let (failed, retriable, retry_after) = match &result {
+                Ok(response) => {
+                    let status = response.status();
+                    let retry_after = response.headers().get(RETRY_AFTER)
+                        .and_then(|value| value.to_str().ok())
+                        .and_then(|value| value.parse().ok())
+                        .map(Duration::from_secs);
+                    let transient = status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS;
+                    (status.is_server_error(), transient && idempotent, retry_after)
+                }
+                Err(e) => (true, e.is_connect() || (e.is_timeout() && idempotent), None),
+            };
+            self.record(&endpoint, failed);
+
+            match retry {
+                Some(next) if retriable => {
+                    let delay = self.backoff(attempt, retry_after);
+                    tracing::debug!("Retrying request to {} in {:?} (attempt {})", endpoint, delay, attempt + 1);
+                    tokio::time::sleep(delay).await;
+                    request = next;
+                    attempt += 1;
+                }
+                _ => return Ok(result?),
+            }
+        }
+    }
+}
+
+/// A request being built on an [`HttpClient`]; `send` applies its retry
+/// policy.
+#[derive(Debug)]
+pub struct HttpRequest {
+    http: HttpClient,
+    builder: RequestBuilder,
+}
+
+impl HttpRequest {
+    pub fn header<K, V>(mut self, key: K, value: V) -> Self
+    where
+        HeaderName: TryFrom<K>,
+        <HeaderName as TryFrom<K>>::Error: Into<axum::http::Error>,
+        HeaderValue: TryFrom<V>,
+        <HeaderValue as TryFrom<V>>::Error: Into<axum::http::Error>,
+    {
+        self.builder = self.builder.header(key, value);
+        self
+    }
+
+    pub fn headers(mut self, headers: HeaderMap) -> Self {
+        self.builder = self.builder.headers(headers);
+        self
+    }
+
+    pub fn bearer_auth<T: Display>(mut self, token: T) -> Self {
+        self.builder = self.builder.bearer_auth(token);
+        self
+    }
+
+    pub fn json<T: Serialize + ?Sized>(mut self, json: &T) -> Self {
+        self.builder = self.builder.json(json);
+        self
+    }
+
+    pub fn body<T: Into<reqwest::Body>>(mut self, body: T) -> Self {
+        self.builder = self.builder.body(body);
+        self
+    }
+
+    pub fn query<T: Serialize + ?Sized>(mut self, query: &T) -> Self {
+        self.builder = self.builder.query(query);
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.builder = self.builder.timeout(timeout);
+        self
+    }
+
+    pub async fn send(self) -> Result<Response> {
+        let request = self.builder.build()?;
+        self.http.execute(request).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{http::StatusCode as AxumStatus, routing::{get, post}, Router};
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn config(max_retries: u32, failure_threshold: u32) -> HttpConfig {
+        HttpConfig {
+            max_retries,
+            initial_backoff_ms: 1,
+            max_backoff_ms: 5,
+            failure_threshold,
+            ..HttpConfig::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retries_idempotent_requests_and_opens_circuit() {
+        let calls = Arc::new(AtomicU32::new(0));
+        let counter = calls.clone();
+        let flaky = move || {
+            let counter = counter.clone();
+            async move {
+                if counter.fetch_add(1, Ordering::SeqCst) < 2 { AxumStatus::SERVICE_UNAVAILABLE } else { AxumStatus::OK }
+            }
+        };
+        let app = Router::new()
+            .route("/flaky", get(flaky))
+            .route("/down", post(|| async { AxumStatus::SERVICE_UNAVAILABLE }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        // A GET is retried past two 503s
+        let http = HttpClient::new(&config(2, 10));
+        let response = http.get(format!("{}/flaky", base)).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        // A POST isn't, and repeated failures open the endpoint's circuit
+        let http = HttpClient::new(&config(2, 2));
+        for _ in 0..2 {
+            let response = http.post(format!("{}/down", base)).send().await.unwrap();
+            assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        }
+        assert!(http.is_open(&base));
+        let error = http.get(format!("{}/flaky", base)).send().await.unwrap_err();
+        assert!(matches!(error, NegotiationError::CircuitOpen(_)));
+        assert!(error.is_unreachable());
+    }
+}
@@ -0,0 +1,300 @@
+//! Replay of retried requests by `Idempotency-Key`.
+//!
+//! A buyer that times out waiting for `/quote` or `/negotiate/:id` can't tell
+//! whether the seller acted on the request, so retrying it could open a
+//! second negotiation or send a second counter offer. Requests carrying an
+//! `Idempotency-Key` header are claimed in the database along with a hash of
+//! their body before they run, and the response is stored once they finish:
+//!
+//! - a retry with the same key and body gets the stored response back, marked
+//!   with `Idempotent-Replayed: true`, without running again
+//! - a retry while the first request is still running gets 409 Conflict
+//! - reusing a key with a different body gets 422 Unprocessable Entity
+//!
+//! Only requests whose sender was authenticated first, and given a
+//! [`Caller`], are replayed, and keys are scoped to the caller as well as the
+//! method and path, so nobody is handed someone else's response. Only
+//! successful responses are stored: a request refused or failed can be
+//! retried for real, e.g. with a refreshed token. Keys are forgotten after
+//! `[server] idempotency_ttl_hours`.
+
+use crate::{
+    config::ServerConfig,
+    database::Database,
+    error::{ApiError, Result},
+    security::DEFAULT_MAX_BODY_BYTES,
+};
+use axum::{
+    body::{to_bytes, Body},
+    extract::{Request, State},
+    http::{header, HeaderValue, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    Router,
+};
+use chrono::{DateTime, Duration, Utc};
+use sha2::{Digest, Sha256};
+
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+pub const IDEMPOTENT_REPLAYED_HEADER: &str = "idempotent-replayed";
+
+pub const DEFAULT_IDEMPOTENCY_TTL_HOURS: u64 = 24;
+/// A claim whose request hasn't finished by then is taken to have been
+/// abandoned, e.g. by a crashed replica
+pub const PENDING_TIMEOUT_SECONDS: i64 = 60;
+const MAX_KEY_LENGTH: usize = 255;
+
+/// Who sent a request, put in its extensions by the authentication that
+/// runs before [`idempotent`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Caller(pub String);
+
+/// A claimed key, with the response once the request finished
+#[derive(Debug, Clone)]
+pub struct IdempotencyRecord {
+    pub scope: String,
+    pub key: String,
+    pub request_hash: String,
+    pub status_code: Option<u16>,
+    pub response_body: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// What to do with a request carrying an idempotency key
+#[derive(Debug)]
+pub enum Claim {
+    /// First time the key is seen; run the request
+    New(DateTime<Utc>),
+    /// Answered before; replay the response
+    Replay(u16, String),
+    InProgress,
+    /// The key was used for a different request
+    Mismatch,
+}
+
+#[derive(Clone)]
+pub struct IdempotencyStore {
+    database: Database,
+    ttl: Duration,
+}
+
+impl IdempotencyStore {
+    pub fn new(database: Database) -> Self {
+        Self { database, ttl: Duration::hours(DEFAULT_IDEMPOTENCY_TTL_HOURS as i64) }
+    }
+
+    pub fn from_config(database: Database, config: &ServerConfig) -> Self {
+        let hours = config.idempotency_ttl_hours.unwrap_or(DEFAULT_IDEMPOTENCY_TTL_HOURS);
+        Self { database, ttl: Duration::hours(hours as i64) }
+    }
+
+    /// Claims `key` for a request whose body hashes to `request_hash`, or says
+    /// why it can't be. Expired and abandoned claims are taken over.
+    pub async fn claim(&self, scope: &str, key: &str, request_hash: &str) -> Result<Claim> {
+        let now = Utc::now();
+        if self.database.claim_idempotency_key(scope, key, request_hash, now).await? {
+            return Ok(Claim::New(now));
+        }
+        let Some(record) = self.database.get_idempotency_record(scope, key).await? else {
+            // Released between the insert and the lookup
+            return Ok(Claim::InProgress);
+        };
+
+        let abandoned = record.status_code.is_none()
+            && record.created_at < now - Duration::seconds(PENDING_TIMEOUT_SECONDS);
+        if record.created_at < now - self.ttl || abandoned {
+            self.database.release_idempotency_key(scope, key, record.created_at).await?;
+            return Ok(if self.database.claim_idempotency_key(scope, key, request_hash, now).await? {
+                Claim::New(now)
+            } else {
+                Claim::InProgress
+            });
+        }
+
+        if record.request_hash != request_hash {
+            return Ok(Claim::Mismatch);
+        }
+        Ok(match (record.status_code, record.response_body) {
+            (Some(status), Some(body)) => Claim::Replay(status, body),
+            _ => Claim::InProgress,
+        })
+    }
+
+    pub async fn complete(&self, scope: &str, key: &str, status: u16, body: &str) -> Result<()> {
+        self.database.complete_idempotency_key(scope, key, status, body).await
+    }
+
+    pub async fn release(&self, scope: &str, key: &str, claimed_at: DateTime<Utc>) -> Result<()> {
+        self.database.release_idempotency_key(scope, key, claimed_at).await
+    }
+
+    /// Forgets keys older than the TTL, returning how many.
+    pub async fn purge_expired(&self) -> Result<u64> {
+        self.database.delete_idempotency_keys_before(Utc::now() - self.ttl).await
+    }
+}
+
+pub fn request_hash(body: &[u8]) -> String {
+    hex::encode(Sha256::digest(body))
+}
+
+/// Replays responses to retried requests on every route of `router`.
+/// Requests without an `Idempotency-Key` or a [`Caller`] pass through
+/// untouched, so authenticate them in a layer added after this one.
+pub fn idempotent<S: Clone + Send + Sync + 'static>(router: Router<S>, store: IdempotencyStore) -> Router<S> {
+    router.route_layer(middleware::from_fn_with_state(store, replay_duplicates))
+}
+
+fn error(status: StatusCode, message: &str) -> Response {
+    ApiError::new(status, message).into_response()
+}
+
+async fn replay_duplicates(State(store): State<IdempotencyStore>, request: Request, next: Next) -> Response {
+    let Some(key) = request.headers().get(IDEMPOTENCY_KEY_HEADER) else {
+        return next.run(request).await;
+    };
+    let key = match key.to_str() {
+        Ok(key) if !key.trim().is_empty() && key.len() <= MAX_KEY_LENGTH => key.to_string(),
+        _ => return error(StatusCode::BAD_REQUEST, "Invalid Idempotency-Key"),
+    };
+    let Some(Caller(caller)) = request.extensions().get::<Caller>().cloned() else {
+        return next.run(request).await;
+    };
+    let scope = format!("{} {} {}", request.method(), request.uri().path(), caller);
+
+    let (parts, body) = request.into_parts();
+    let Ok(body) = to_bytes(body, DEFAULT_MAX_BODY_BYTES).await else {
+        return error(StatusCode::PAYLOAD_TOO_LARGE, "Request body too large");
+    };
+    let request = Request::from_parts(parts, Body::from(body.clone()));
+
+    let claimed_at = match store.claim(&scope, &key, &request_hash(&body)).await {
+        Ok(Claim::New(claimed_at)) => claimed_at,
+        Ok(Claim::Replay(status, body)) => {
+            tracing::info!("Replaying response to {} for Idempotency-Key {}", scope, key);
+            let mut response = Response::new(Body::from(body));
+            *response.status_mut() = StatusCode::from_u16(status).unwrap_or(StatusCode::OK);
+            let headers = response.headers_mut();
+            headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("application/json"));
+            headers.insert(IDEMPOTENT_REPLAYED_HEADER, HeaderValue::from_static("true"));
+            return response;
+        }
+        Ok(Claim::InProgress) => {
+            return error(StatusCode::CONFLICT, "A request with this Idempotency-Key is still in progress");
+        }
+        Ok(Claim::Mismatch) => {
+            return error(StatusCode::UNPROCESSABLE_ENTITY, "Idempotency-Key was already used for a different request");
+        }
+        Err(e) => {
+            // Better to risk a duplicate than to refuse the request
+            tracing::error!("Failed to claim Idempotency-Key {} for {}: {}", key, scope, e);
+            return next.run(request).await;
+        }
+    };
+
+    let response = next.run(request).await;
+    let (parts, body) = response.into_parts();
+    let body = match to_bytes(body, usize::MAX).await {
+        Ok(body) => body,
+        Err(e) => {
+            tracing::error!("Failed to read response to {}: {}", scope, e);
+            if let Err(e) = store.release(&scope, &key, claimed_at).await {
+                tracing::error!("Failed to release Idempotency-Key {}: {}", key, e);
+            }
+            return error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to read response");
+        }
+    };
+
+    let stored = if parts.status.is_success() {
+        store.complete(&scope, &key, parts.status.as_u16(), &String::from_utf8_lossy(&body)).await
+    } else {
+        store.release(&scope, &key, claimed_at).await
+    };
+    if let Err(e) = stored {
+        tracing::error!("Failed to store response for Idempotency-Key {}: {}", key, e);
+    }
+    Response::from_parts(parts, Body::from(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{routing::post, Json};
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+    use tempfile::NamedTempFile;
+
+    #[tokio::test]
+    async fn test_replays_retried_requests() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let database = Database::new(&format!("sqlite://{}", temp_file.path().to_string_lossy())).await.unwrap();
+        let store = IdempotencyStore::new(database);
+
+        let calls = Arc::new(AtomicU32::new(0));
+        let counter = calls.clone();
+        let router = Router::new().route("/quote", post(move |body: String| {
+            let counter = counter.clone();
+            async move {
+                let call = counter.fetch_add(1, Ordering::SeqCst);
+                if body == "expired token" {
+                    return StatusCode::UNAUTHORIZED.into_response();
+                }
+                Json(serde_json::json!({ "quote": call, "rfq": body })).into_response()
+            }
+        }));
+        // Stands in for authentication, taking the caller's word for who they are
+        let router = idempotent(router, store.clone()).route_layer(middleware::from_fn(|mut request: Request, next: Next| async move {
+            if let Some(caller) = request.headers().get("x-caller").and_then(|caller| caller.to_str().ok()) {
+                let caller = Caller(caller.to_string());
+                request.extensions_mut().insert(caller);
+            }
+            next.run(request).await
+        }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+
+        let client = reqwest::Client::new();
+        let quote = |caller: Option<&str>, key: Option<&str>, body: &str| {
+            let mut request = client.post(format!("{}/quote", base)).body(body.to_string());
+            if let Some(caller) = caller {
+                request = request.header("x-caller", caller);
+            }
+            if let Some(key) = key {
+                request = request.header(IDEMPOTENCY_KEY_HEADER, key);
+            }
+            async move { request.send().await.unwrap() }
+        };
+
+        let first = quote(Some("buyer"), Some("rfq-1"), "laptop").await;
+        assert!(!first.headers().contains_key(IDEMPOTENT_REPLAYED_HEADER));
+        let first: serde_json::Value = first.json().await.unwrap();
+
+        let retry = quote(Some("buyer"), Some("rfq-1"), "laptop").await;
+        assert_eq!(retry.headers()[IDEMPOTENT_REPLAYED_HEADER], "true");
+        assert_eq!(retry.json::<serde_json::Value>().await.unwrap(), first);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        assert_eq!(quote(Some("buyer"), Some("rfq-1"), "phone").await.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        quote(Some("buyer"), Some("rfq-2"), "laptop").await;
+        quote(Some("buyer"), None, "laptop").await;
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        // Nobody else, authenticated or not, gets the buyer's response
+        let other = quote(Some("intruder"), Some("rfq-1"), "laptop").await;
+        assert!(!other.headers().contains_key(IDEMPOTENT_REPLAYED_HEADER));
+        let anonymous = quote(None, Some("rfq-1"), "laptop").await;
+        assert!(!anonymous.headers().contains_key(IDEMPOTENT_REPLAYED_HEADER));
+        assert_eq!(calls.load(Ordering::SeqCst), 5);
+
+        // Refusals aren't kept, so a retry with a fresh token goes through
+        assert_eq!(quote(Some("buyer"), Some("rfq-4"), "expired token").await.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(quote(Some("buyer"), Some("rfq-4"), "expired token").await.headers().get(IDEMPOTENT_REPLAYED_HEADER), None);
+        assert_eq!(calls.load(Ordering::SeqCst), 7);
+
+        // A claim whose request never finished blocks retries until it's abandoned
+        store.database.claim_idempotency_key("POST /quote buyer", "rfq-3", &request_hash(b"laptop"), Utc::now()).await.unwrap();
+        assert_eq!(quote(Some("buyer"), Some("rfq-3"), "laptop").await.status(), StatusCode::CONFLICT);
+        assert_eq!(store.purge_expired().await.unwrap(), 0);
+    }
+}
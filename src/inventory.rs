@@ -0,0 +1,261 @@
+//! Seller stock and the reservations held against it.
+//!
+//! Quoting an RFQ reserves its quantity, so two buyers can't both be quoted
+//! the last units. The reservation is committed when the deal settles, taking
+//! the units off hand, and released when the buyer rejects the quote or the
+//! quote lapses. Every change is a single guarded SQL update, so concurrent
+//! RFQs can't reserve more than is on hand, and a reservation is resolved at
+//! most once.
+
+use crate::{
+    database::Database,
+    error::{NegotiationError, Result},
+    model::{Product, RFQ},
+    AgentId, TransactionId,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// A product's stock: units on hand, and how many of them are reserved
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StockLevel {
+    pub seller_id: AgentId,
+    pub product_id: String,
+    pub on_hand: u32,
+    pub reserved: u32,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl StockLevel {
+    /// Units a new quote can still reserve
+    pub fn available(&self) -> u32 {
+        self.on_hand.saturating_sub(self.reserved)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReservationStatus {
+    /// Held for an open quote
+    Held,
+    /// The deal settled and the units left stock
+    Committed,
+    /// Rejected or lapsed; the units are available again
+    Released,
+}
+
+/// Stock held for the quotes on one RFQ
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StockReservation {
+    pub rfq_id: TransactionId,
+    pub seller_id: AgentId,
+    pub product_id: String,
+    pub buyer_id: AgentId,
+    pub quantity: u32,
+    pub status: ReservationStatus,
+    /// Released automatically after this unless committed first
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    pub resolved_at: Option<DateTime<Utc>>,
+}
+
+/// How a buyer's deal ended, as reported to the seller
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StockOutcome {
+    Settled,
+    Released,
+}
+
+/// What a buyer posts to a seller's `/negotiate/{id}/stock`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StockUpdate {
+    pub rfq_id: TransactionId,
+    pub outcome: StockOutcome,
+}
+
+#[derive(Clone)]
+pub struct InventoryService {
+    database: Database,
+}
+
+impl InventoryService {
+    pub fn new(database: Database) -> Self {
+        Self { database }
+    }
+
+    /// Starts tracking `products` at their configured stock. Products already
+    /// tracked keep their current levels, so a restarted seller doesn't
+    /// forget what it sold.
+    pub async fn track(&self, seller_id: AgentId, products: &[Product], now: DateTime<Utc>) -> Result<()> {
+        self.database.track_stock(seller_id, products, now).await
+    }
+
+    /// Adds `quantity` units to a tracked product.
+    pub async fn restock(&self, seller_id: AgentId, product_id: &str, quantity: u32, now: DateTime<Utc>) -> Result<StockLevel> {
+        if !self.database.restock_product(seller_id, product_id, quantity, now).await? {
+            return Err(NegotiationError::ProductNotFound(product_id.to_string()));
+        }
+        self.level(seller_id, product_id).await
+    }
+
+    /// Sets a product's units on hand, such as after a stock count. Units
+    /// reserved for open quotes can't be counted away.
+    pub async fn set_on_hand(&self, seller_id: AgentId, product_id: &str, on_hand: u32, now: DateTime<Utc>) -> Result<StockLevel> {
+        if !self.database.set_stock_on_hand(seller_id, product_id, on_hand, now).await? {
+            let reserved = self.level(seller_id, product_id).await?.reserved;
+            return Err(NegotiationError::Validation(format!(
+                "{} units of {} are reserved for open quotes", reserved, product_id
+            )));
+        }
+        self.level(seller_id, product_id).await
+    }
+
+    pub async fn level(&self, seller_id: AgentId, product_id: &str) -> Result<StockLevel> {
+        self.database.get_stock_level(seller_id, product_id).await?
+            .ok_or_else(|| NegotiationError::ProductNotFound(product_id.to_string()))
+    }
+
+    /// Reserves the RFQ's quantity until `expires_at`. A later quote on the
+    /// same RFQ, such as an answer to a counter offer, extends the
+    /// reservation it already holds instead of reserving again.
+    pub async fn reserve(&self, seller_id: AgentId, rfq: &RFQ, expires_at: DateTime<Utc>, now: DateTime<Utc>) -> Result<StockReservation> {
+        if let Some(mut held) = self.database.get_stock_reservation(rfq.id).await? {
+            if held.status != ReservationStatus::Held || held.seller_id != seller_id {
+                return Err(NegotiationError::Validation(format!("RFQ {} is no longer open", rfq.id)));
+            }
+            if expires_at > held.expires_at {
+                self.database.extend_stock_reservation(rfq.id, expires_at).await?;
+                held.expires_at = expires_at;
+            }
+            return Ok(held);
+        }
+
+        let reservation = StockReservation {
+            rfq_id: rfq.id,
+            seller_id,
+            product_id: rfq.product_id.clone(),
+            buyer_id: rfq.buyer_id,
+            quantity: rfq.quantity,
+            status: ReservationStatus::Held,
+            expires_at,
+            created_at: now,
+            resolved_at: None,
+        };
+        if !self.database.reserve_stock(&reservation).await? {
+            return Err(NegotiationError::Validation("Insufficient stock".to_string()));
+        }
+        Ok(reservation)
+    }
+
+    /// Takes the reserved units off hand once the buyer's deal settles.
+    pub async fn commit(&self, rfq_id: TransactionId, buyer_id: AgentId, now: DateTime<Utc>) -> Result<StockReservation> {
+        self.resolve(rfq_id, buyer_id, ReservationStatus::Committed, now).await
+    }
+
+    /// Returns the reserved units to stock when the buyer rejects the quote.
+    pub async fn release(&self, rfq_id: TransactionId, buyer_id: AgentId, now: DateTime<Utc>) -> Result<StockReservation> {
+        self.resolve(rfq_id, buyer_id, ReservationStatus::Released, now).await
+    }
+
+    /// Releases reservations whose quotes lapsed, returning how many.
+    pub async fn release_expired(&self, now: DateTime<Utc>) -> Result<usize> {
+        let mut released = 0;
+        for reservation in self.database.get_expired_stock_reservations(now).await? {
+            if self.database.resolve_stock_reservation(reservation.rfq_id, ReservationStatus::Released, now).await?.is_some() {
+                released += 1;
+            }
+        }
+        Ok(released)
+    }
+
+    async fn resolve(&self, rfq_id: TransactionId, buyer_id: AgentId, status: ReservationStatus, now: DateTime<Utc>) -> Result<StockReservation> {
+        let reservation = self.database.get_stock_reservation(rfq_id).await?
+            .ok_or_else(|| NegotiationError::Validation(format!("No stock reserved for RFQ {}", rfq_id)))?;
+        if reservation.buyer_id != buyer_id {
+            return Err(NegotiationError::Auth("Stock is reserved for another buyer".to_string()));
+        }
+        self.database.resolve_stock_reservation(rfq_id, status, now).await?
+            .ok_or_else(|| NegotiationError::Validation(format!("Stock for RFQ {} is already {:?}", rfq_id, reservation.status)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use rust_decimal::Decimal;
+    use std::collections::HashMap;
+    use tempfile::NamedTempFile;
+
+    fn product(stock_quantity: u32) -> Product {
+        Product {
+            id: "laptop-001".to_string(),
+            name: "Laptop".to_string(),
+            description: "Laptop".to_string(),
+            category: "Electronics".to_string(),
+            base_price: Decimal::from(1000),
+            currency: "USD".to_string(),
+            stock_quantity,
+            metadata: HashMap::new(),
+        }
+    }
+
+    fn rfq(quantity: u32) -> RFQ {
+        RFQ::new(AgentId::new_v4(), "laptop-001".to_string(), quantity, Decimal::from(5000), "USD".to_string(), Utc::now() + Duration::hours(1))
+    }
+
+    async fn inventory(stock_quantity: u32) -> (InventoryService, AgentId, NamedTempFile) {
+        let temp_file = NamedTempFile::new().unwrap();
+        let database = Database::new(&format!("sqlite://{}", temp_file.path().to_string_lossy())).await.unwrap();
+        let inventory = InventoryService::new(database);
+        let seller_id = AgentId::new_v4();
+        inventory.track(seller_id, &[product(stock_quantity)], Utc::now()).await.unwrap();
+        (inventory, seller_id, temp_file)
+    }
+
+    #[tokio::test]
+    async fn test_reservations_commit_release_and_lapse() {
+        let (inventory, seller_id, _db) = inventory(10).await;
+        let now = Utc::now();
+        let (settled, rejected, lapsed) = (rfq(4), rfq(3), rfq(3));
+        for rfq in [&settled, &rejected, &lapsed] {
+            inventory.reserve(seller_id, rfq, now + Duration::hours(1), now).await.unwrap();
+        }
+        assert!(inventory.reserve(seller_id, &rfq(1), now + Duration::hours(1), now).await.is_err());
+        // Quoting the same RFQ again holds no more stock
+        inventory.reserve(seller_id, &settled, now + Duration::hours(2), now).await.unwrap();
+        assert_eq!(inventory.level(seller_id, "laptop-001").await.unwrap().available(), 0);
+
+        assert!(inventory.commit(settled.id, AgentId::new_v4(), now).await.is_err());
+        inventory.commit(settled.id, settled.buyer_id, now).await.unwrap();
+        assert!(inventory.release(settled.id, settled.buyer_id, now).await.is_err());
+        inventory.release(rejected.id, rejected.buyer_id, now).await.unwrap();
+        assert_eq!(inventory.release_expired(now + Duration::minutes(90)).await.unwrap(), 1);
+
+        let level = inventory.level(seller_id, "laptop-001").await.unwrap();
+        assert_eq!((level.on_hand, level.reserved), (6, 0));
+        // Restarting keeps what was sold
+        inventory.track(seller_id, &[product(10)], now).await.unwrap();
+        assert_eq!(inventory.level(seller_id, "laptop-001").await.unwrap().on_hand, 6);
+        assert_eq!(inventory.restock(seller_id, "laptop-001", 4, now).await.unwrap().available(), 10);
+
+        inventory.reserve(seller_id, &rfq(5), now + Duration::hours(1), now).await.unwrap();
+        assert!(inventory.set_on_hand(seller_id, "laptop-001", 4, now).await.is_err());
+        assert_eq!(inventory.set_on_hand(seller_id, "laptop-001", 7, now).await.unwrap().available(), 2);
+        assert_eq!(inventory.set_on_hand(seller_id, "mouse-001", 3, now).await.unwrap().available(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_reservations_never_oversell() {
+        let (inventory, seller_id, _db) = inventory(10).await;
+        let now = Utc::now();
+        let attempts = (0..25).map(|_| {
+            let inventory = inventory.clone();
+            tokio::spawn(async move { inventory.reserve(seller_id, &rfq(1), now + Duration::hours(1), now).await.is_ok() })
+        });
+        let reserved = futures::future::join_all(attempts).await.into_iter().filter(|ok| *ok.as_ref().unwrap()).count();
+        assert_eq!(reserved, 10);
+        assert_eq!(inventory.level(seller_id, "laptop-001").await.unwrap().reserved, 10);
+    }
+}
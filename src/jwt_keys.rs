@@ -0,0 +1,251 @@
+//! Ed25519 keys agent JWTs are signed with.
+//!
+//! Agent JWTs carry the agent's reputation, so anyone holding the issuer's
+//! public keys can check a reputation claim offline. Each token names the
+//! key it was signed with in its `kid` header, which lets issuers rotate
+//! keys: a new key signs from the moment it's made active, while tokens
+//! signed by the previous one keep verifying until that key is removed.
+//! Public keys are published as a JWKS, e.g. on the discovery service's
+//! `/.well-known/jwks.json`.
+
+use crate::{
+    config::JwtKeyConfig,
+    error::{NegotiationError, Result},
+    http::HttpClient,
+};
+use base64::{engine::general_purpose, Engine};
+use ed25519_dalek::{SigningKey, VerifyingKey};
+use jsonwebtoken::{
+    decode, encode,
+    jwk::{
+        AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, JwkSet, KeyAlgorithm, OctetKeyPairParameters,
+        OctetKeyPairType, PublicKeyUse,
+    },
+    Algorithm, DecodingKey, EncodingKey, Header, Validation,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use sha2::{Digest, Sha256};
+
+/// PKCS#8 v1 wrapping of an Ed25519 secret key, ahead of its 32 bytes
+const PKCS8_ED25519_PREFIX: [u8; 16] = [
+    0x30, 0x2e, 0x02, 0x01, 0x00, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x04, 0x22, 0x04, 0x20,
+];
+
+#[derive(Clone)]
+struct JwtKey {
+    kid: String,
+    verifying_key: VerifyingKey,
+    /// Only keys this process issues tokens with have one
+    signing_key: Option<SigningKey>,
+    /// Learned from another issuer's JWKS rather than configured
+    remote: bool,
+}
+
+#[derive(Clone, Default)]
+pub struct JwtKeyring {
+    keys: Vec<JwtKey>,
+    active: Option<String>,
+}
+
+impl JwtKeyring {
+    /// Loads the configured keys. New tokens are signed with `active`, or the
+    /// last key holding a private key.
+    pub fn from_config(keys: &[JwtKeyConfig], active: Option<&str>) -> Result<Self> {
+        let mut keyring = Self::default();
+        for config in keys {
+            let signing_key = config.private_key.as_deref().map(signing_key).transpose()?;
+            let verifying_key = match (&signing_key, config.public_key.as_deref()) {
+                (Some(signing_key), None) => signing_key.verifying_key(),
+                (Some(signing_key), Some(public_key)) => {
+                    if verifying_key(public_key)? != signing_key.verifying_key() {
+                        return Err(NegotiationError::Config("A JWT key's public key doesn't match its private key".to_string()));
+                    }
+                    signing_key.verifying_key()
+                }
+                (None, Some(public_key)) => verifying_key(public_key)?,
+                (None, None) => return Err(NegotiationError::Config("JWT keys need a private or public key".to_string())),
+            };
+            let kid = config.kid.clone().unwrap_or_else(|| thumbprint(&verifying_key));
+            if keyring.contains(&kid) {
+                return Err(NegotiationError::Config(format!("JWT key {} is configured twice", kid)));
+            }
+            keyring.keys.push(JwtKey { kid, verifying_key, signing_key, remote: false });
+        }
+
+        keyring.active = match active {
+            Some(kid) => match keyring.keys.iter().find(|key| key.kid == kid) {
+                Some(key) if key.signing_key.is_some() => Some(kid.to_string()),
+                Some(_) => return Err(NegotiationError::Config(format!("Active JWT key {} has no private key", kid))),
+                None => return Err(NegotiationError::Config(format!("Active JWT key {} isn't configured", kid))),
+            },
+            None => keyring.keys.iter().rev().find(|key| key.signing_key.is_some()).map(|key| key.kid.clone()),
+        };
+        Ok(keyring)
+    }
+
+    /// A new key, for `dcap keygen`
+    pub fn generate() -> JwtKeyConfig {
+        let key = SigningKey::from_bytes(&rand::random());
+        JwtKeyConfig {
+            kid: Some(thumbprint(&key.verifying_key())),
+            private_key: Some(general_purpose::STANDARD.encode(key.to_bytes())),
+            public_key: Some(general_purpose::STANDARD.encode(key.verifying_key().to_bytes())),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    pub fn contains(&self, kid: &str) -> bool {
+        self.keys.iter().any(|key| key.kid == kid)
+    }
+
+    /// Key new tokens are signed with, if this process issues any
+    pub fn active_kid(&self) -> Option<&str> {
+        self.active.as_deref()
+    }
+
+    pub fn sign<T: Serialize>(&self, claims: &T) -> Result<String> {
+        let key = self.active.as_deref()
+            .and_then(|kid| self.keys.iter().find(|key| key.kid == kid))
+            .and_then(|key| key.signing_key.as_ref().map(|signing_key| (&key.kid, signing_key)));
+        let Some((kid, signing_key)) = key else {
+            return Err(NegotiationError::Config("No JWT signing key is configured".to_string()));
+        };
+
+        let mut header = Header::new(Algorithm::EdDSA);
+        header.kid = Some(kid.clone());
+        let der = [PKCS8_ED25519_PREFIX.as_slice(), signing_key.as_bytes()].concat();
+        encode(&header, claims, &EncodingKey::from_ed_der(&der))
+            .map_err(|e| NegotiationError::Auth(format!("Failed to sign JWT: {}", e)))
+    }
+
+    /// Checks a token against the key its `kid` names.
+    pub fn verify<T: DeserializeOwned>(&self, kid: &str, token: &str, validation: &Validation) -> Result<T> {
+        let key = self.keys.iter().find(|key| key.kid == kid)
+            .ok_or_else(|| NegotiationError::Auth(format!("Unknown JWT signing key {}", kid)))?;
+        decode::<T>(token, &DecodingKey::from_ed_der(key.verifying_key.as_bytes()), validation)
+            .map(|data| data.claims)
+            .map_err(|e| NegotiationError::Auth(format!("Invalid JWT: {}", e)))
+    }
+
+    /// Public halves of the configured keys, to publish
+    pub fn jwks(&self) -> JwkSet {
+        JwkSet {
+            keys: self.keys.iter().filter(|key| !key.remote).map(|key| Jwk {
+                common: CommonParameters {
+                    public_key_use: Some(PublicKeyUse::Signature),
+                    key_algorithm: Some(KeyAlgorithm::EdDSA),
+                    key_id: Some(key.kid.clone()),
+                    ..CommonParameters::default()
+                },
+                algorithm: AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+                    key_type: OctetKeyPairType::OctetKeyPair,
+                    curve: EllipticCurve::Ed25519,
+                    x: general_purpose::URL_SAFE_NO_PAD.encode(key.verifying_key.as_bytes()),
+                }),
+            }).collect(),
+        }
+    }
+
+    /// Replaces the keys learned from another issuer with those in `jwks`.
+    /// Keys other than Ed25519 ones with a `kid` are skipped, and configured
+    /// keys win over published ones with the same `kid`.
+    pub fn set_remote(&mut self, jwks: &JwkSet) {
+        self.keys.retain(|key| !key.remote);
+        for jwk in &jwks.keys {
+            let (Some(kid), AlgorithmParameters::OctetKeyPair(params)) = (&jwk.common.key_id, &jwk.algorithm) else {
+                continue;
+            };
+            if params.curve != EllipticCurve::Ed25519 || self.contains(kid) {
+                continue;
+            }
+            let verifying_key = general_purpose::URL_SAFE_NO_PAD.decode(&params.x).ok()
+                .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+                .and_then(|bytes| VerifyingKey::from_bytes(&bytes).ok());
+            match verifying_key {
+                Some(verifying_key) => self.keys.push(JwtKey { kid: kid.clone(), verifying_key, signing_key: None, remote: true }),
+                None => tracing::warn!("Skipping malformed JWK {}", kid),
+            }
+        }
+    }
+}
+
+/// Fetches an issuer's published keys.
+pub async fn fetch_jwks(client: &HttpClient, url: &str) -> Result<JwkSet> {
+    let response = client.get(url).send().await?.error_for_status()?;
+    Ok(response.json().await?)
+}
+
+/// RFC 7638 thumbprint of an Ed25519 public key, the default `kid`
+fn thumbprint(key: &VerifyingKey) -> String {
+    let x = general_purpose::URL_SAFE_NO_PAD.encode(key.as_bytes());
+    let canonical = format!(r#"{{"crv":"Ed25519","kty":"OKP","x":"{}"}}"#, x);
+    general_purpose::URL_SAFE_NO_PAD.encode(Sha256::digest(canonical.as_bytes()))
+}
+
+fn signing_key(encoded: &str) -> Result<SigningKey> {
+    let bytes: [u8; 32] = general_purpose::STANDARD.decode(encoded.trim())
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| NegotiationError::Config("JWT private keys must be 32 bytes of base64".to_string()))?;
+    Ok(SigningKey::from_bytes(&bytes))
+}
+
+fn verifying_key(encoded: &str) -> Result<VerifyingKey> {
+    general_purpose::STANDARD.decode(encoded.trim())
+        .ok()
+        .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+        .and_then(|bytes| VerifyingKey::from_bytes(&bytes).ok())
+        .ok_or_else(|| NegotiationError::Config("JWT public keys must be 32 bytes of base64".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::TrustConfig, trust::{JWTClaims, TrustSystem}};
+    use jsonwebtoken::decode_header;
+
+    #[tokio::test]
+    async fn test_rotated_keys_keep_verifying_and_publish_offline() {
+        let old_key = JwtKeyring::generate();
+        let new_key = JwtKeyring::generate();
+        let config = |keys: Vec<JwtKeyConfig>| TrustConfig {
+            jwt_secret: Some("test-secret".to_string()),
+            signing_keys: keys,
+            ..TrustConfig::default()
+        };
+        let agent_id = uuid::Uuid::new_v4();
+
+        let mut issuer = TrustSystem::from_config(&config(vec![old_key.clone()])).unwrap();
+        let old_token = issuer.generate_jwt(agent_id).await.unwrap();
+        assert_eq!(decode_header(&old_token).unwrap().kid, old_key.kid);
+
+        // Rotate: the new key signs, the old one only verifies
+        let retired = JwtKeyConfig { private_key: None, ..old_key.clone() };
+        let mut rotated = TrustSystem::from_config(&config(vec![retired, new_key.clone()])).unwrap();
+        let new_token = rotated.generate_jwt(agent_id).await.unwrap();
+        assert_eq!(decode_header(&new_token).unwrap().kid, new_key.kid);
+        assert_eq!(rotated.validate_jwt(&old_token).await.unwrap().sub, agent_id.to_string());
+        assert!(issuer.validate_jwt(&new_token).await.is_err());
+
+        // A third party checks tokens with the published keys alone
+        let published: JwkSet = serde_json::from_value(serde_json::to_value(rotated.jwks()).unwrap()).unwrap();
+        let mut verifier = JwtKeyring::default();
+        verifier.set_remote(&published);
+        let kid = new_key.kid.as_deref().unwrap();
+        let claims: JWTClaims = verifier.verify(kid, &new_token, &Validation::new(Algorithm::EdDSA)).unwrap();
+        assert_eq!(claims.sub, agent_id.to_string());
+        assert!(verifier.jwks().keys.is_empty());
+
+        // HS256 agent JWTs aren't accepted once keys are set up
+        let legacy = TrustSystem::new().unwrap().generate_jwt(agent_id).await.unwrap();
+        assert!(rotated.validate_jwt(&legacy).await.is_err());
+        assert_eq!(rotated.token_subjects().subject(&new_token), Some(agent_id));
+        assert_eq!(rotated.token_subjects().subject(&legacy), None);
+
+        let unknown_active = TrustConfig { active_signing_key: Some("missing".to_string()), ..config(vec![new_key]) };
+        assert!(TrustSystem::from_config(&unknown_active).is_err());
+    }
+}
@@ -0,0 +1,185 @@
+//! Language negotiation for negotiation messages.
+//!
+//! Agents advertise the languages they write in, most preferred first. Before
+//! messaging a counterparty an agent picks the first of its languages the
+//! other side also lists, and renders the free text of the negotiation in it:
+//! RFQ summaries, quote and counter offer notes, and the instructions given to
+//! an LLM drafting them. Prices, quantities and product IDs stay canonical,
+//! both in the structured offer fields and inside the text, so nothing a deal
+//! depends on is ever translated.
+
+use crate::{
+    error::{NegotiationError, Result},
+    locale::Locale,
+    money::Money,
+};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Language spoken when agents share no other, and assumed for agents that
+/// predate language negotiation
+pub const DEFAULT_LANGUAGE: Language = Language::En;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Language {
+    #[default]
+    En,
+    De,
+    Fr,
+    Es,
+    Pt,
+    Ja,
+}
+
+impl Language {
+    /// ISO 639-1 code
+    pub fn code(&self) -> &'static str {
+        match self {
+            Language::En => "en",
+            Language::De => "de",
+            Language::Fr => "fr",
+            Language::Es => "es",
+            Language::Pt => "pt",
+            Language::Ja => "ja",
+        }
+    }
+
+    /// English name, for LLM instructions
+    pub fn name(&self) -> &'static str {
+        match self {
+            Language::En => "English",
+            Language::De => "German",
+            Language::Fr => "French",
+            Language::Es => "Spanish",
+            Language::Pt => "Portuguese",
+            Language::Ja => "Japanese",
+        }
+    }
+
+    pub fn rfq_summary(&self, product_id: &str, quantity: u32) -> String {
+        match self {
+            Language::En => format!("RFQ for {} x {}", product_id, quantity),
+            Language::De => format!("Angebotsanfrage für {} x {}", product_id, quantity),
+            Language::Fr => format!("Demande de devis pour {} x {}", product_id, quantity),
+            Language::Es => format!("Solicitud de cotización para {} x {}", product_id, quantity),
+            Language::Pt => format!("Pedido de cotação para {} x {}", product_id, quantity),
+            Language::Ja => format!("{} x {} の見積依頼", product_id, quantity),
+        }
+    }
+
+    pub fn quote(&self, price: &Money) -> String {
+        match self {
+            Language::En => format!("Quote of {}", price),
+            Language::De => format!("Angebot über {}", price),
+            Language::Fr => format!("Devis de {}", price),
+            Language::Es => format!("Cotización de {}", price),
+            Language::Pt => format!("Cotação de {}", price),
+            Language::Ja => format!("{} の見積", price),
+        }
+    }
+
+    pub fn counter_offer(&self, price: &Money) -> String {
+        match self {
+            Language::En => format!("Counter offer of {}", price),
+            Language::De => format!("Gegenangebot über {}", price),
+            Language::Fr => format!("Contre-offre de {}", price),
+            Language::Es => format!("Contraoferta de {}", price),
+            Language::Pt => format!("Contraproposta de {}", price),
+            Language::Ja => format!("{} の対案", price),
+        }
+    }
+
+    /// Instruction for an LLM drafting messages to the counterparty.
+    pub fn llm_instruction(&self) -> String {
+        format!(
+            "Write every message to the counterparty in {}. Keep prices, currency codes, quantities and product IDs exactly as given; do not translate or reformat them.",
+            self.name()
+        )
+    }
+}
+
+/// Picks the first of `local`'s languages that `remote` also lists, falling
+/// back to `DEFAULT_LANGUAGE`. An empty remote list means the peer predates
+/// language negotiation and writes in the default.
+pub fn negotiate_language(local: &[Language], remote: &[Language]) -> Language {
+    let default = [DEFAULT_LANGUAGE];
+    let remote = if remote.is_empty() { &default[..] } else { remote };
+    local.iter()
+        .find(|language| remote.contains(language))
+        .copied()
+        .unwrap_or(DEFAULT_LANGUAGE)
+}
+
+/// Languages an agent writes in: those configured, or its locale's language.
+pub fn preferred_languages(configured: &[Language], locale: Locale) -> Vec<Language> {
+    if configured.is_empty() {
+        vec![locale.language()]
+    } else {
+        configured.to_vec()
+    }
+}
+
+impl fmt::Display for Language {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+impl FromStr for Language {
+    type Err = NegotiationError;
+
+    /// Takes a language code or a full tag such as `de-AT`, whose region is ignored.
+    fn from_str(s: &str) -> Result<Self> {
+        let code = s.split(['-', '_']).next().unwrap_or_default().to_ascii_lowercase();
+        match code.as_str() {
+            "en" => Ok(Language::En),
+            "de" => Ok(Language::De),
+            "fr" => Ok(Language::Fr),
+            "es" => Ok(Language::Es),
+            "pt" => Ok(Language::Pt),
+            "ja" => Ok(Language::Ja),
+            _ => Err(NegotiationError::Validation(format!("Unsupported language: {}", s))),
+        }
+    }
+}
+
+impl TryFrom<String> for Language {
+    type Error = NegotiationError;
+
+    fn try_from(value: String) -> Result<Self> {
+        value.parse()
+    }
+}
+
+impl From<Language> for String {
+    fn from(language: Language) -> Self {
+        language.code().to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal::Decimal;
+
+    #[test]
+    fn test_negotiates_shared_language_and_keeps_prices_canonical() {
+        let buyer = [Language::De, Language::Fr, Language::En];
+        assert_eq!(negotiate_language(&buyer, &[Language::Fr, Language::De]), Language::De);
+        assert_eq!(negotiate_language(&buyer, &[Language::Ja, Language::Fr]), Language::Fr);
+        assert_eq!(negotiate_language(&buyer, &[]), Language::En);
+        assert_eq!(negotiate_language(&[Language::Ja], &[Language::Pt]), DEFAULT_LANGUAGE);
+
+        assert_eq!("de-AT".parse::<Language>().unwrap(), Language::De);
+        assert!("xx".parse::<Language>().is_err());
+        assert_eq!(preferred_languages(&[], Locale::PtBr), vec![Language::Pt]);
+        let languages: Vec<Language> = serde_json::from_str(r#"["fr", "EN"]"#).unwrap();
+        assert_eq!(languages, vec![Language::Fr, Language::En]);
+
+        let price = Money::new(Decimal::new(129999, 2), "EUR".to_string());
+        assert_eq!(Language::De.counter_offer(&price), "Gegenangebot über 1299.99 EUR");
+        assert_eq!(Language::Ja.quote(&price), "1299.99 EUR の見積");
+    }
+}
@@ -10,23 +10,102 @@
 //! - **Settlement**: Stripe, Solana, or pay-on-delivery escrow
 //! - **Trust/Reputation**: Signed JWT + SQLite ledger to prevent sybil attacks
 //! - **MCP Server**: Custom implementation for standardized LLM-to-LLM communication
+//! - **gRPC** (`grpc` feature): Quote, negotiate and discovery RPCs alongside HTTP
 
+pub mod admin;
 pub mod agent;
+pub mod agreement;
+pub mod analytics;
+pub mod anchoring;
+pub mod anomaly;
+pub mod artifacts;
+pub mod auction;
+pub mod audit_bundle;
+pub mod audit_log;
+pub mod blocklist;
+pub mod broadcast;
+pub mod calendar;
+pub mod cancellation;
+pub mod catalog;
+pub mod coalition;
+pub mod comparison;
+pub mod compliance;
+pub mod concession;
 pub mod config;
+pub mod currency;
+pub mod database;
+pub mod dead_letter;
+pub mod delegation;
+pub mod demand;
 pub mod discovery;
+pub mod embeddings;
 pub mod error;
+pub mod events;
+pub mod expiry;
+pub mod explorer;
+pub mod export;
+pub mod fees;
+pub mod gossip;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod handover;
+pub mod http;
+pub mod idempotency;
+pub mod inventory;
+pub mod jwt_keys;
+pub mod language;
+pub mod lifecycle;
+#[cfg(feature = "lightning")]
+pub mod lightning;
+pub mod locale;
+pub mod metrics;
 pub mod model;
+pub mod money;
+pub mod nonce;
+pub mod obligation;
+pub mod order;
+pub mod pricing;
+pub mod privacy;
+pub mod product_search;
+pub mod protocol;
+pub mod quote_ttl;
+pub mod responsiveness;
+pub mod roles;
+pub mod recovery;
+pub mod registry;
+pub mod reload;
+pub mod replay;
+pub mod reputation_snapshot;
+pub mod scenario;
+pub mod runtime;
+pub mod security;
+pub mod seller_cache;
+pub mod session;
 pub mod settlement;
+pub mod shared_state;
+pub mod shipping;
+pub mod solana_escrow;
+pub mod strategy;
+pub mod strategy_bench;
+pub mod tax;
+pub mod taxonomy;
+pub mod templates;
+pub mod telemetry;
+pub mod tls;
 pub mod trust;
+pub mod validation;
+pub mod webhook;
 pub mod mcp;
 
 pub use agent::{BuyerAgent, SellerAgent};
 pub use config::AppConfig;
+pub use database::Database;
 pub use discovery::{DiscoveryService, RegisterRequest, SearchRequest};
 pub use error::{NegotiationError, Result};
 pub use model::{NegotiationRecord, Product, Quote, RFQ, PaymentMethod};
 pub use settlement::SettlementService;
 pub use trust::{TrustSystem, ReputationScore};
+pub use webhook::verify_webhook;
 
 
 pub type TransactionId = uuid::Uuid;
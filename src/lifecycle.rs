@@ -0,0 +1,230 @@
+//! Startup ordering, readiness and pod identity for containerized services.
+//!
+//! Deployed as separate Kubernetes services, the binaries can start in any
+//! order. Each one waits for its database and, for agents, the discovery
+//! registry, for up to `[lifecycle] startup_timeout_seconds` instead of
+//! failing on the first refused connection. `/health` answers as soon as the
+//! HTTP server is up and serves as the liveness probe; `/ready` only answers
+//! 200 once the service has finished starting, which for agents means they
+//! are registered with discovery, so no traffic is routed to a seller the
+//! registry doesn't know yet.
+//!
+//! Agent identity can come from the pod: [`PodIdentity`] reads environment
+//! variables and the labels file a downward API volume provides, so a
+//! StatefulSet can give each seller a stable agent ID and name.
+
+use crate::{
+    config::LifecycleConfig,
+    database::Database,
+    error::{NegotiationError, Result},
+    AgentId,
+};
+use axum::{extract::State, http::StatusCode, response::Json, routing::get, Router};
+use reqwest::Client;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_STARTUP_TIMEOUT_SECONDS: u64 = 60;
+
+/// Where a downward API volume usually mounts the pod's labels
+pub const DEFAULT_POD_LABELS_PATH: &str = "/etc/podinfo/labels";
+
+/// Pod label naming the agent's ID
+pub const AGENT_ID_LABEL: &str = "dcap.io/agent-id";
+
+/// Pod label naming the agent
+pub const AGENT_NAME_LABEL: &str = "dcap.io/agent-name";
+
+/// Pod label naming the region a seller sells from
+pub const REGION_LABEL: &str = "dcap.io/region";
+
+/// First wait between startup attempts; doubles up to `MAX_RETRY_INTERVAL`
+const INITIAL_RETRY_INTERVAL: Duration = Duration::from_millis(250);
+const MAX_RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Whether a service has finished starting. Clones share the flag.
+#[derive(Clone, Default)]
+pub struct Readiness {
+    ready: Arc<AtomicBool>,
+}
+
+impl Readiness {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn mark_ready(&self) {
+        self.ready.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::SeqCst)
+    }
+
+    /// Adds the `/ready` route to `router`.
+    pub fn route(&self, router: Router) -> Router {
+        router.merge(Router::new().route("/ready", get(ready)).with_state(self.clone()))
+    }
+}
+
+async fn ready(State(readiness): State<Readiness>) -> (StatusCode, Json<serde_json::Value>) {
+    if readiness.is_ready() {
+        (StatusCode::OK, Json(serde_json::json!({"status": "ready"})))
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, Json(serde_json::json!({"status": "starting"})))
+    }
+}
+
+/// Retries `attempt` with backoff until it succeeds or `timeout` passes, and
+/// then returns its last error.
+async fn retry<T, F, Fut>(what: &str, timeout: Duration, mut attempt: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<T>>,
+{
+    let deadline = Instant::now() + timeout;
+    let mut interval = INITIAL_RETRY_INTERVAL;
+    loop {
+        match attempt().await {
+            Ok(value) => return Ok(value),
+            Err(e) if Instant::now() + interval < deadline => {
+                tracing::info!("Waiting for {}: {}", what, e);
+                tokio::time::sleep(interval).await;
+                interval = (interval * 2).min(MAX_RETRY_INTERVAL);
+            }
+            Err(e) => {
+                tracing::error!("Gave up waiting for {} after {:?}", what, timeout);
+                return Err(e);
+            }
+        }
+    }
+}
+
+/// Opens the database, waiting for it to become reachable.
+pub async fn wait_for_database(database_url: &str, config: &LifecycleConfig) -> Result<Database> {
+    retry("database", config.startup_timeout(), || Database::new(database_url)).await
+}
+
+/// Waits until the discovery registry at `endpoint` answers its health check.
+pub async fn wait_for_discovery(endpoint: &str, config: &LifecycleConfig) -> Result<()> {
+    let client = Client::new();
+    let url = format!("{}/health", endpoint.trim_end_matches('/'));
+    retry("discovery", config.startup_timeout(), || async {
+        client.get(&url).timeout(MAX_RETRY_INTERVAL).send().await?.error_for_status()?;
+        Ok(())
+    }).await
+}
+
+/// Agent identity provided by the pod. Every field is optional; agents fall
+/// back to their defaults for whatever the pod doesn't set.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PodIdentity {
+    /// `DCAP_AGENT_ID`, or the `dcap.io/agent-id` label
+    pub agent_id: Option<AgentId>,
+    /// `DCAP_AGENT_NAME`, the `dcap.io/agent-name` label, or `POD_NAME`
+    pub name: Option<String>,
+    /// `DCAP_ADVERTISED_ENDPOINT`, or `http://<POD_IP>:<port>`
+    pub advertised_endpoint: Option<String>,
+    pub pod_ip: Option<String>,
+    /// `DCAP_REGION`, or the `dcap.io/region` label
+    pub region: Option<String>,
+    /// All of the pod's labels
+    pub labels: HashMap<String, String>,
+}
+
+impl PodIdentity {
+    /// Reads the identity from the process environment and the labels file
+    /// at `[lifecycle] pod_labels_path`, which may be missing.
+    pub fn from_env(config: &LifecycleConfig) -> Result<Self> {
+        let labels = match std::fs::read_to_string(&config.pod_labels_path) {
+            Ok(contents) => parse_labels(&contents),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(NegotiationError::Io(format!("Failed to read {}: {}", config.pod_labels_path, e))),
+        };
+        Self::from_sources(|name| std::env::var(name).ok().filter(|value| !value.is_empty()), labels)
+    }
+
+    fn from_sources(env: impl Fn(&str) -> Option<String>, labels: HashMap<String, String>) -> Result<Self> {
+        let agent_id = match env("DCAP_AGENT_ID").or_else(|| labels.get(AGENT_ID_LABEL).cloned()) {
+            Some(id) => Some(id.parse::<AgentId>()
+                .map_err(|e| NegotiationError::Config(format!("Invalid agent ID {}: {}", id, e)))?),
+            None => None,
+        };
+        Ok(Self {
+            agent_id,
+            name: env("DCAP_AGENT_NAME")
+                .or_else(|| labels.get(AGENT_NAME_LABEL).cloned())
+                .or_else(|| env("POD_NAME")),
+            advertised_endpoint: env("DCAP_ADVERTISED_ENDPOINT"),
+            pod_ip: env("POD_IP"),
+            region: env("DCAP_REGION").or_else(|| labels.get(REGION_LABEL).cloned()),
+            labels,
+        })
+    }
+
+    /// The endpoint other agents should call, when the pod knows it.
+    pub fn endpoint(&self, port: u16) -> Option<String> {
+        self.advertised_endpoint.clone()
+            .or_else(|| self.pod_ip.as_ref().map(|ip| format!("http://{}:{}", ip, port)))
+    }
+}
+
+/// Parses a downward API labels file: one `key="value"` per line, with the
+/// value quoted and escaped like a Go string.
+fn parse_labels(contents: &str) -> HashMap<String, String> {
+    contents.lines()
+        .filter_map(|line| {
+            let (key, value) = line.split_once('=')?;
+            let value = value.trim();
+            let value = match serde_json::from_str::<String>(value) {
+                Ok(unquoted) => unquoted,
+                Err(_) => value.trim_matches('"').to_string(),
+            };
+            Some((key.trim().to_string(), value))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pod_identity_prefers_env_over_labels() {
+        let agent_id = AgentId::new_v4();
+        let labels = parse_labels(&format!(
+            "app=\"seller\"\n{}=\"{}\"\n{}=\"Acme \\\"East\\\"\"\n{}=\"eu-west\"\n",
+            AGENT_ID_LABEL, agent_id, AGENT_NAME_LABEL, REGION_LABEL,
+        ));
+        assert_eq!(labels["app"], "seller");
+        assert_eq!(labels[AGENT_NAME_LABEL], "Acme \"East\"");
+
+        let env = HashMap::from([("POD_NAME", "seller-0"), ("POD_IP", "10.1.2.3"), ("DCAP_REGION", "us-east")]);
+        let identity = PodIdentity::from_sources(|name| env.get(name).map(|value| value.to_string()), labels).unwrap();
+        assert_eq!(identity.agent_id, Some(agent_id));
+        assert_eq!(identity.name.as_deref(), Some("Acme \"East\""));
+        assert_eq!(identity.region.as_deref(), Some("us-east"));
+        assert_eq!(identity.endpoint(8001).as_deref(), Some("http://10.1.2.3:8001"));
+
+        let identity = PodIdentity::from_sources(|name| (name == "POD_NAME").then(|| "seller-1".to_string()), HashMap::new()).unwrap();
+        assert_eq!((identity.agent_id, identity.name.as_deref(), identity.endpoint(8001)), (None, Some("seller-1"), None));
+        assert!(PodIdentity::from_sources(|name| (name == "DCAP_AGENT_ID").then(|| "seller-1".to_string()), HashMap::new()).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_readiness_and_startup_timeout() {
+        let readiness = Readiness::new();
+        let (status, _) = ready(State(readiness.clone())).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        readiness.mark_ready();
+        let (status, _) = ready(State(readiness)).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let config = LifecycleConfig { startup_timeout_seconds: 1, ..LifecycleConfig::default() };
+        let started = Instant::now();
+        assert!(wait_for_discovery("http://127.0.0.1:9", &config).await.is_err());
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,331 @@
+//! Lightning Network payments through an LND node's REST API.
+//!
+//! Accepting a quote paid over Lightning creates an invoice on the node for
+//! the quoted amount, converted to bitcoin. The buyer pays its BOLT11
+//! `payment_request` from any wallet, and the settlement service learns of it
+//! from the node's invoice subscription, or by looking the invoice up when
+//! polling. Invoice states map onto payment statuses:
+//!
+//! | Invoice state | Payment status |
+//! |---------------|----------------|
+//! | `OPEN`        | unchanged      |
+//! | `ACCEPTED`    | `processing`   |
+//! | `SETTLED`     | `succeeded`    |
+//! | `CANCELED`    | `failed`       |
+
+use crate::{
+    currency::CurrencyConverter,
+    error::{NegotiationError, Result},
+    money::Money,
+    settlement::PaymentStatus,
+};
+use base64::{engine::general_purpose, Engine};
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+use serde::{Deserialize, Serialize};
+
+/// How long buyers have to pay an invoice (1 hour)
+pub const DEFAULT_INVOICE_EXPIRY_SECONDS: u64 = 3600;
+
+const MSAT_PER_BTC: i64 = 100_000_000_000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LightningConfig {
+    /// LND REST endpoint, e.g. "https://localhost:8080"
+    pub rest_url: String,
+    /// Hex-encoded macaroon allowed to create and read invoices
+    pub macaroon_hex: String,
+    /// PEM certificate the node serves, usually self-signed
+    pub tls_cert_path: Option<String>,
+    pub invoice_expiry_seconds: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum InvoiceState {
+    Open,
+    /// A hold invoice whose HTLCs are locked in but not yet settled
+    Accepted,
+    Settled,
+    Canceled,
+}
+
+impl InvoiceState {
+    /// The payment status an invoice in this state means; `None` while it's
+    /// still waiting to be paid.
+    pub fn payment_status(&self) -> Option<PaymentStatus> {
+        match self {
+            InvoiceState::Open => None,
+            InvoiceState::Accepted => Some(PaymentStatus::Processing),
+            InvoiceState::Settled => Some(PaymentStatus::Succeeded),
+            InvoiceState::Canceled => Some(PaymentStatus::Failed),
+        }
+    }
+}
+
+/// An invoice as the node reports it, with `r_hash` hex-encoded.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Invoice {
+    pub r_hash: String,
+    pub payment_request: String,
+    pub value_msat: u64,
+    pub state: InvoiceState,
+}
+
+/// LND encodes bytes as base64 and 64-bit integers as strings.
+#[derive(Debug, Deserialize)]
+struct LndInvoice {
+    r_hash: String,
+    #[serde(default)]
+    payment_request: String,
+    #[serde(default)]
+    value_msat: Option<String>,
+    #[serde(default = "open")]
+    state: InvoiceState,
+}
+
+fn open() -> InvoiceState {
+    InvoiceState::Open
+}
+
+impl TryFrom<LndInvoice> for Invoice {
+    type Error = NegotiationError;
+
+    fn try_from(invoice: LndInvoice) -> Result<Self> {
+        let r_hash = general_purpose::STANDARD.decode(&invoice.r_hash)
+            .map_err(|e| NegotiationError::Payment(format!("Invalid invoice hash from LND: {}", e)))?;
+        let value_msat = invoice.value_msat.as_deref().unwrap_or("0").parse()
+            .map_err(|e| NegotiationError::Payment(format!("Invalid invoice amount from LND: {}", e)))?;
+        Ok(Invoice {
+            r_hash: hex::encode(r_hash),
+            payment_request: invoice.payment_request,
+            value_msat,
+            state: invoice.state,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct SubscriptionUpdate {
+    result: LndInvoice,
+}
+
+/// Payment ID of the payment an invoice was created for.
+pub fn payment_id(r_hash: &str) -> String {
+    format!("ln_{}", r_hash)
+}
+
+/// Invoice hash a Lightning payment ID refers to.
+pub fn r_hash(payment_id: &str) -> Result<&str> {
+    payment_id.strip_prefix("ln_")
+        .ok_or_else(|| NegotiationError::Validation(format!("{} isn't a Lightning payment", payment_id)))
+}
+
+/// Talks to an LND node over REST. Invoice creation isn't retried, so a
+/// dropped response never leaves the buyer with two invoices to pay.
+#[derive(Clone)]
+pub struct LndClient {
+    client: reqwest::Client,
+    config: LightningConfig,
+    converter: CurrencyConverter,
+}
+
+impl LndClient {
+    pub fn new(config: LightningConfig) -> Result<Self> {
+        let mut builder = reqwest::Client::builder();
+        if let Some(path) = &config.tls_cert_path {
+            let pem = std::fs::read(path)
+                .map_err(|e| NegotiationError::Config(format!("Failed to read LND certificate {}: {}", path, e)))?;
+            let certificate = reqwest::Certificate::from_pem(&pem)
+                .map_err(|e| NegotiationError::Config(format!("Invalid LND certificate {}: {}", path, e)))?;
+            builder = builder.add_root_certificate(certificate);
+        }
+        let client = builder.build()
+            .map_err(|e| NegotiationError::Config(format!("Failed to build LND client: {}", e)))?;
+        Ok(Self {
+            client,
+            config,
+            converter: CurrencyConverter::default(),
+        })
+    }
+
+    /// Converts amounts quoted in other currencies to bitcoin with `converter`.
+    pub fn with_currency_converter(mut self, converter: CurrencyConverter) -> Self {
+        self.converter = converter;
+        self
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.config.rest_url.trim_end_matches('/'), path)
+    }
+
+    fn get(&self, path: &str) -> reqwest::RequestBuilder {
+        self.client.get(self.url(path)).header("Grpc-Metadata-macaroon", &self.config.macaroon_hex)
+    }
+
+    /// `amount` in millisatoshis, rounded to the nearest one.
+    pub async fn amount_msat(&self, amount: &Money) -> Result<u64> {
+        let btc = self.converter.convert(amount, "BTC").await?;
+        (btc.amount * Decimal::from(MSAT_PER_BTC)).round().to_u64()
+            .filter(|msat| *msat > 0)
+            .ok_or_else(|| NegotiationError::Validation(format!("Can't invoice {} over Lightning", amount)))
+    }
+
+    /// Creates an invoice for `amount` on the node.
+    pub async fn create_invoice(&self, amount: &Money, memo: &str) -> Result<Invoice> {
+        let value_msat = self.amount_msat(amount).await?;
+        let created: LndInvoice = self.client
+            .post(self.url("/v1/invoices"))
+            .header("Grpc-Metadata-macaroon", &self.config.macaroon_hex)
+            .json(&serde_json::json!({
+                "memo": memo,
+                "value_msat": value_msat.to_string(),
+                "expiry": self.config.invoice_expiry_seconds.to_string(),
+            }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(Invoice {
+            value_msat,
+            ..created.try_into()?
+        })
+    }
+
+    /// Looks up an invoice by its hex-encoded hash.
+    pub async fn lookup_invoice(&self, r_hash: &str) -> Result<Invoice> {
+        let invoice: LndInvoice = self.get(&format!("/v1/invoice/{}", r_hash))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        invoice.try_into()
+    }
+
+    /// Follows updates to the node's invoices as they're added and paid.
+    pub async fn subscribe_invoices(&self) -> Result<InvoiceSubscription> {
+        let response = self.get("/v1/invoices/subscribe").send().await?.error_for_status()?;
+        Ok(InvoiceSubscription {
+            response,
+            buffer: Vec::new(),
+        })
+    }
+}
+
+/// The node's stream of invoice updates, one JSON object per line.
+pub struct InvoiceSubscription {
+    response: reqwest::Response,
+    buffer: Vec<u8>,
+}
+
+impl InvoiceSubscription {
+    /// The next invoice update; `None` once the node closes the stream.
+    pub async fn next(&mut self) -> Result<Option<Invoice>> {
+        loop {
+            if let Some(end) = self.buffer.iter().position(|byte| *byte == b'\n') {
+                let line: Vec<u8> = self.buffer.drain(..=end).collect();
+                if line.iter().all(u8::is_ascii_whitespace) {
+                    continue;
+                }
+                let update: SubscriptionUpdate = serde_json::from_slice(&line)?;
+                return update.result.try_into().map(Some);
+            }
+            match self.response.chunk().await? {
+                Some(chunk) => self.buffer.extend_from_slice(&chunk),
+                None => return Ok(None),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{currency::FixedRateProvider, database::Database, model::PaymentMethod, settlement::{SettlementConfig, SettlementService}};
+    use axum::{extract::Path, routing::{get, post}, Json, Router};
+    use std::{str::FromStr, sync::Arc};
+    use tempfile::NamedTempFile;
+
+    const R_HASH: [u8; 4] = [0xde, 0xad, 0xbe, 0xef];
+
+    fn lnd_invoice(state: &str) -> serde_json::Value {
+        serde_json::json!({
+            "r_hash": general_purpose::STANDARD.encode(R_HASH),
+            "payment_request": "lnbc1mock",
+            "value_msat": "25000000",
+            "state": state,
+        })
+    }
+
+    /// A node whose invoice is looked up as open and then reported settled by
+    /// the subscription.
+    async fn mock_lnd() -> String {
+        let app = Router::new()
+            .route("/v1/invoices", post(|Json(body): Json<serde_json::Value>| async move {
+                assert_eq!(body["value_msat"], "25000000");
+                Json(serde_json::json!({ "r_hash": general_purpose::STANDARD.encode(R_HASH), "payment_request": "lnbc1mock" }))
+            }))
+            .route("/v1/invoice/:r_hash", get(|Path(r_hash): Path<String>| async move {
+                assert_eq!(r_hash, "deadbeef");
+                Json(lnd_invoice("OPEN"))
+            }))
+            .route("/v1/invoices/subscribe", get(|| async {
+                format!("{}\n\n{}\n", serde_json::json!({ "result": lnd_invoice("ACCEPTED") }), serde_json::json!({ "result": lnd_invoice("SETTLED") }))
+            }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{}", addr)
+    }
+
+    fn client(rest_url: String) -> LndClient {
+        let rates = FixedRateProvider::new("USD").with_rate("BTC", Decimal::from_str("0.00001").unwrap());
+        LndClient::new(LightningConfig {
+            rest_url,
+            macaroon_hex: "00".to_string(),
+            tls_cert_path: None,
+            invoice_expiry_seconds: DEFAULT_INVOICE_EXPIRY_SECONDS,
+        }).unwrap().with_currency_converter(CurrencyConverter::new(Arc::new(rates)))
+    }
+
+    #[tokio::test]
+    async fn test_invoices_settle_lightning_payments() {
+        let lnd = client(mock_lnd().await);
+        // $25 at 0.00001 BTC to the dollar is 25,000 sats
+        assert_eq!(lnd.amount_msat(&Money::new(Decimal::from(25), "USD")).await.unwrap(), 25_000_000);
+
+        let temp_file = NamedTempFile::new().unwrap();
+        let database = Database::new(&format!("sqlite://{}", temp_file.path().to_string_lossy())).await.unwrap();
+        let settlement = SettlementService::new(SettlementConfig {
+            stripe_secret_key: None,
+            solana_rpc_url: None,
+            escrow_service_url: None,
+            webhook_secret: None,
+            delivery_confirmation_timeout_seconds: None,
+        }, database).await.unwrap().with_lightning(lnd);
+
+        let (buyer_id, seller_id) = (uuid::Uuid::new_v4(), uuid::Uuid::new_v4());
+        let payment = settlement.create_delegated_payment(buyer_id, seller_id, Money::new(Decimal::from(25), "USD"), None, vec![], PaymentMethod::Lightning)
+            .await.unwrap();
+        assert_eq!(payment.payment_id, "ln_deadbeef");
+        assert_eq!(payment.invoice.as_deref(), Some("lnbc1mock"));
+        assert_eq!(payment.status, PaymentStatus::AwaitingConfirmation);
+
+        // Unpaid invoices leave the payment waiting
+        assert_eq!(settlement.poll_payment(&payment.payment_id).await.unwrap().status, PaymentStatus::AwaitingConfirmation);
+
+        settlement.watch_lightning_invoices().await.unwrap();
+        assert_eq!(settlement.get_payment_status(&payment.payment_id).await.unwrap(), PaymentStatus::Succeeded);
+    }
+
+    #[test]
+    fn test_invoice_states_map_to_payment_statuses() {
+        assert_eq!(InvoiceState::Open.payment_status(), None);
+        assert_eq!(InvoiceState::Accepted.payment_status(), Some(PaymentStatus::Processing));
+        assert_eq!(InvoiceState::Settled.payment_status(), Some(PaymentStatus::Succeeded));
+        assert_eq!(InvoiceState::Canceled.payment_status(), Some(PaymentStatus::Failed));
+        assert_eq!(r_hash(&payment_id("deadbeef")).unwrap(), "deadbeef");
+        assert!(r_hash("stripe_1").is_err());
+    }
+}
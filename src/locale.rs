@@ -0,0 +1,215 @@
+//! Locale-aware price and quantity formatting.
+//!
+//! Used wherever a price is shown to a person or an LLM: invoices, CLI output
+//! and prompt variables. Each agent carries its own locale, so the same
+//! amount renders as `$1,299.99` for one agent and `1.299,99 $` for another.
+
+use crate::{
+    error::{NegotiationError, Result},
+    language::Language,
+    money::{minor_units, Money},
+};
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Locale {
+    #[default]
+    EnUs,
+    EnGb,
+    DeDe,
+    FrFr,
+    EsEs,
+    PtBr,
+    JaJp,
+}
+
+/// Number separators and currency placement for a locale
+struct Conventions {
+    decimal: char,
+    group: char,
+    symbol_first: bool,
+    symbol_space: bool,
+}
+
+impl Locale {
+    pub fn tag(&self) -> &'static str {
+        match self {
+            Locale::EnUs => "en-US",
+            Locale::EnGb => "en-GB",
+            Locale::DeDe => "de-DE",
+            Locale::FrFr => "fr-FR",
+            Locale::EsEs => "es-ES",
+            Locale::PtBr => "pt-BR",
+            Locale::JaJp => "ja-JP",
+        }
+    }
+
+    /// Language of the locale, the one an agent writes in unless it lists others.
+    pub fn language(&self) -> Language {
+        match self {
+            Locale::EnUs | Locale::EnGb => Language::En,
+            Locale::DeDe => Language::De,
+            Locale::FrFr => Language::Fr,
+            Locale::EsEs => Language::Es,
+            Locale::PtBr => Language::Pt,
+            Locale::JaJp => Language::Ja,
+        }
+    }
+
+    fn conventions(&self) -> Conventions {
+        match self {
+            Locale::EnUs | Locale::EnGb | Locale::JaJp => Conventions { decimal: '.', group: ',', symbol_first: true, symbol_space: false },
+            Locale::DeDe | Locale::EsEs => Conventions { decimal: ',', group: '.', symbol_first: false, symbol_space: true },
+            Locale::FrFr => Conventions { decimal: ',', group: '\u{202f}', symbol_first: false, symbol_space: true },
+            Locale::PtBr => Conventions { decimal: ',', group: '.', symbol_first: true, symbol_space: true },
+        }
+    }
+}
+
+impl fmt::Display for Locale {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.tag())
+    }
+}
+
+impl FromStr for Locale {
+    type Err = NegotiationError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.replace('_', "-").to_ascii_lowercase().as_str() {
+            "en-us" | "en" => Ok(Locale::EnUs),
+            "en-gb" => Ok(Locale::EnGb),
+            "de-de" | "de" => Ok(Locale::DeDe),
+            "fr-fr" | "fr" => Ok(Locale::FrFr),
+            "es-es" | "es" => Ok(Locale::EsEs),
+            "pt-br" | "pt" => Ok(Locale::PtBr),
+            "ja-jp" | "ja" => Ok(Locale::JaJp),
+            _ => Err(NegotiationError::Validation(format!("Unsupported locale: {}", s))),
+        }
+    }
+}
+
+impl TryFrom<String> for Locale {
+    type Error = NegotiationError;
+
+    fn try_from(value: String) -> Result<Self> {
+        value.parse()
+    }
+}
+
+impl From<Locale> for String {
+    fn from(locale: Locale) -> Self {
+        locale.tag().to_string()
+    }
+}
+
+/// Display symbol for a currency, falling back to the ISO code.
+pub fn currency_symbol(currency: &str) -> &str {
+    match currency {
+        "USD" => "$",
+        "EUR" => "€",
+        "GBP" => "£",
+        "JPY" => "¥",
+        "BRL" => "R$",
+        "INR" => "₹",
+        other => other,
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PriceFormatter {
+    locale: Locale,
+}
+
+impl PriceFormatter {
+    pub fn new(locale: Locale) -> Self {
+        Self { locale }
+    }
+
+    pub fn locale(&self) -> Locale {
+        self.locale
+    }
+
+    /// Formats an amount with grouping, the currency's minor units and symbol.
+    pub fn format_price(&self, amount: Decimal, currency: &str) -> String {
+        let conventions = self.locale.conventions();
+        let number = self.format_number(amount.abs(), minor_units(currency));
+        let symbol = currency_symbol(currency);
+        // ISO codes used in place of a symbol are always spaced off
+        let space = if conventions.symbol_space || symbol == currency { "\u{a0}" } else { "" };
+        let sign = if amount.is_sign_negative() && !amount.is_zero() { "-" } else { "" };
+
+        if conventions.symbol_first {
+            format!("{}{}{}{}", sign, symbol, space, number)
+        } else {
+            format!("{}{}{}{}", sign, number, space, symbol)
+        }
+    }
+
+    pub fn format_money(&self, money: &Money) -> String {
+        self.format_price(money.amount, &money.currency)
+    }
+
+    /// Formats a price per unit of measure, e.g. `$12.50/kg`.
+    pub fn format_unit_price(&self, amount: Decimal, currency: &str, unit: &str) -> String {
+        format!("{}/{}", self.format_price(amount, currency), unit)
+    }
+
+    pub fn format_quantity(&self, quantity: u32) -> String {
+        self.format_number(Decimal::from(quantity), 0)
+    }
+
+    pub fn format_number(&self, value: Decimal, decimals: u32) -> String {
+        let conventions = self.locale.conventions();
+        let rounded = value.round_dp_with_strategy(decimals, RoundingStrategy::MidpointAwayFromZero);
+        let rendered = format!("{:.*}", decimals as usize, rounded);
+        let (integer, fraction) = match rendered.split_once('.') {
+            Some((integer, fraction)) => (integer, Some(fraction)),
+            None => (rendered.as_str(), None),
+        };
+
+        let mut grouped = String::new();
+        for (i, digit) in integer.chars().enumerate() {
+            if i > 0 && (integer.len() - i) % 3 == 0 {
+                grouped.push(conventions.group);
+            }
+            grouped.push(digit);
+        }
+
+        match fraction {
+            Some(fraction) => format!("{}{}{}", grouped, conventions.decimal, fraction),
+            None => grouped,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn dec(value: &str) -> Decimal {
+        Decimal::from_str(value).unwrap()
+    }
+
+    #[test]
+    fn test_price_formatting_per_locale() {
+        assert_eq!(PriceFormatter::new(Locale::EnUs).format_price(dec("1299.99"), "USD"), "$1,299.99");
+        assert_eq!(PriceFormatter::new(Locale::DeDe).format_price(dec("1299.99"), "EUR"), "1.299,99\u{a0}€");
+        assert_eq!(PriceFormatter::new(Locale::FrFr).format_price(dec("1299.99"), "EUR"), "1\u{202f}299,99\u{a0}€");
+        assert_eq!(PriceFormatter::new(Locale::JaJp).format_price(dec("150000.0"), "JPY"), "¥150,000");
+        assert_eq!(PriceFormatter::new(Locale::EnUs).format_price(dec("12.5"), "CHF"), "CHF\u{a0}12.50");
+        assert_eq!(PriceFormatter::new(Locale::EnGb).format_unit_price(dec("3.2"), "GBP", "kg"), "£3.20/kg");
+    }
+
+    #[test]
+    fn test_locale_parsing() {
+        assert_eq!("de_DE".parse::<Locale>().unwrap(), Locale::DeDe);
+        assert_eq!(Locale::PtBr.to_string(), "pt-BR");
+        assert!("xx-XX".parse::<Locale>().is_err());
+    }
+}
@@ -4,18 +4,34 @@
 //! LLM-to-LLM commerce workflows within the DCAP ecosystem.
 
 use crate::{
-    config::AppConfig,
-    discovery::{DiscoveryService, RegisterRequest, SearchRequest},
+    analytics::{self, AnalyticsQuery},
+    anomaly::AnomalyQuery,
+    audit_log::AuditAction,
+    config::{AppConfig, McpConfig},
+    database::Database,
+    compliance::CompliancePolicy,
+    discovery::{DiscoveryServer, DiscoveryService, RegisterRequest, SearchRequest},
     error::{NegotiationError, Result},
+    language::{self, Language},
+    locale::{Locale, PriceFormatter},
     model::{PaymentMethod, AgentType},
-    settlement::SettlementService,
+    privacy::PrivacyFilter,
+    roles::Permission,
+    settlement::{PaymentRequest, SettlementService},
+    taxonomy::CategoryTaxonomy,
     trust::TrustSystem,
-    AgentId,
+    AgentId, TransactionId,
 };
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
+use futures::future::BoxFuture;
 use std::collections::HashMap;
+use std::future::Future;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::time::Duration;
+use tokio::sync::{RwLock, Semaphore};
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
 use chrono::Utc;
 
 /// MCP Server for Negotiation Agents
@@ -24,98 +40,229 @@ pub struct NegotiationMcpServer {
     discovery: Arc<RwLock<DiscoveryService>>,
     trust_system: Arc<RwLock<TrustSystem>>,
     settlement: Arc<RwLock<SettlementService>>,
+    /// Registry the catalog and active agents are read from
+    registry: DiscoveryServer,
+    database: Database,
+    privacy: PrivacyFilter,
+    /// Tools added with `register_tool`
+    custom_tools: Arc<CustomTools>,
+    /// Cancelled to stop `run`
+    shutdown: CancellationToken,
 }
 
+/// Handler of a tool added with `NegotiationMcpServer::register_tool`: takes
+/// the call's arguments and returns its result
+pub type ToolHandler = Arc<dyn Fn(serde_json::Value) -> BoxFuture<'static, Result<serde_json::Value>> + Send + Sync>;
+
+/// Most recent records served by `negotiation://history`
+const HISTORY_LIMIT: i64 = 1000;
+
+/// How recently an agent must have been active to be served by `agent://active`
+const ACTIVE_WINDOW_HOURS: i64 = 24;
+
+/// Largest MCP request read from a connection
+const MAX_REQUEST_BYTES: usize = 1024 * 1024;
+
+/// Size of the pieces a streamed response is sent in
+const STREAM_CHUNK_BYTES: usize = 64 * 1024;
+
+pub const DEFAULT_MCP_MAX_CONNECTIONS: usize = 256;
+pub const DEFAULT_MCP_IDLE_TIMEOUT_SECONDS: u64 = 300;
+pub const DEFAULT_MCP_WRITE_TIMEOUT_SECONDS: u64 = 30;
+pub const DEFAULT_MCP_SHUTDOWN_GRACE_SECONDS: u64 = 30;
+
 impl NegotiationMcpServer {
     /// Create a new MCP server instance
     pub async fn new() -> Result<Self> {
         let config = AppConfig::load("config.toml").unwrap_or_default();
+        let database = Database::new(config.get_database_url()).await?;
+        let privacy = PrivacyFilter::from_config(&config.privacy)?;
+        let registry = DiscoveryServer::from_database(database.clone())
+            .with_compliance_policy(CompliancePolicy::new(config.compliance.clone()))
+            .with_taxonomy(CategoryTaxonomy::new(&config.taxonomy)?);
 
         Ok(Self {
             discovery: Arc::new(RwLock::new(DiscoveryService::new("http://localhost:8000".to_string()))),
-            trust_system: Arc::new(RwLock::new(TrustSystem::new()?)),
+            trust_system: Arc::new(RwLock::new(TrustSystem::from_config(&config.trust)?)),
             settlement: Arc::new(RwLock::new(SettlementService::new(crate::settlement::SettlementConfig {
-            stripe_secret_key: None,
-            solana_rpc_url: None,
-            escrow_service_url: None,
-        }).await?)),
+            stripe_secret_key: config.settlement.stripe_secret_key.clone(),
+            solana_rpc_url: config.settlement.solana_rpc_url.clone(),
+            escrow_service_url: config.settlement.escrow_service_url.clone(),
+            webhook_secret: config.settlement.webhook_secret.clone(),
+            delivery_confirmation_timeout_seconds: config.settlement.delivery_confirmation_timeout_seconds,
+        }, database.clone()).await?)),
+            registry,
+            database,
+            privacy,
             config,
+            custom_tools: Arc::new(CustomTools::default()),
+            shutdown: CancellationToken::new(),
         })
     }
 
-    /// Run the MCP server
+    /// Cancel the returned token to shut the server down; see `run`.
+    pub fn shutdown_token(&self) -> CancellationToken {
+        self.shutdown.clone()
+    }
+
+    /// Adds a tool for MCP clients to call, listed by `tools/list` after the
+    /// built-in ones. `schema` is the JSON Schema of the tool's arguments;
+    /// its top-level `description` describes the tool. Tools must be
+    /// registered before `run`, and can't replace a built-in tool or one
+    /// already registered.
+    pub fn register_tool<F, Fut>(&mut self, name: impl Into<String>, schema: serde_json::Value, handler: F) -> Result<()>
+    where
+        F: Fn(serde_json::Value) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<serde_json::Value>> + Send + 'static,
+    {
+        let handler: ToolHandler = Arc::new(move |arguments| Box::pin(handler(arguments)));
+        Arc::make_mut(&mut self.custom_tools).register(name.into(), schema, handler)
+    }
+
+    /// Run the MCP server until its shutdown token is cancelled. At most
+    /// `[mcp] max_connections` clients are served at once. On shutdown the
+    /// server stops accepting, each connection finishes the request it's
+    /// serving and closes, and connections still busy after
+    /// `shutdown_grace_seconds` are dropped.
     pub async fn run(&self, listener: tokio::net::TcpListener) -> Result<()> {
-        // Simple MCP server implementation over TCP
+        let limits = ConnectionLimits::new(&self.config.mcp, self.shutdown.clone());
+        let context = ConnectionContext {
+            discovery: self.discovery.clone(),
+            trust_system: self.trust_system.clone(),
+            settlement: self.settlement.clone(),
+            registry: self.registry.clone(),
+            database: self.database.clone(),
+            privacy: self.privacy.clone(),
+            locale: self.config.locale,
+            custom_tools: self.custom_tools.clone(),
+        };
+        let slots = Arc::new(Semaphore::new(self.config.mcp.max_connections.max(1)));
+        let mut connections = JoinSet::new();
+
         loop {
-            let (socket, addr) = listener.accept().await?;
-
-            let discovery = self.discovery.clone();
-            let trust_system = self.trust_system.clone();
-            let settlement = self.settlement.clone();
-
-            tokio::spawn(async move {
-                if let Err(e) = Self::handle_connection(
-                    socket,
-                    discovery,
-                    trust_system,
-                    settlement,
-                ).await {
+            let permit = tokio::select! {
+                _ = self.shutdown.cancelled() => break,
+                permit = slots.clone().acquire_owned() => permit.expect("connection slots are never closed"),
+            };
+            let (socket, addr) = tokio::select! {
+                _ = self.shutdown.cancelled() => break,
+                accepted = listener.accept() => accepted?,
+            };
+            while connections.try_join_next().is_some() {}
+
+            let context = context.clone();
+            let limits = limits.clone();
+
+            connections.spawn(async move {
+                let _permit = permit;
+                if let Err(e) = Self::handle_connection(socket, context, limits).await {
                     eprintln!("Connection error from {}: {}", addr, e);
                 }
             });
         }
+
+        tracing::info!("MCP server shutting down, draining {} connections", connections.len());
+        let grace = Duration::from_secs(self.config.mcp.shutdown_grace_seconds);
+        let drained = tokio::time::timeout(grace, async {
+            while connections.join_next().await.is_some() {}
+        }).await;
+        if drained.is_err() {
+            tracing::warn!("Dropping {} MCP connections still busy after {:?}", connections.len(), grace);
+            connections.shutdown().await;
+        }
+        Ok(())
     }
 
-    async fn handle_connection(
-        mut socket: tokio::net::TcpStream,
-        discovery: Arc<RwLock<DiscoveryService>>,
-        trust_system: Arc<RwLock<TrustSystem>>,
-        settlement: Arc<RwLock<SettlementService>>,
-    ) -> Result<()> {
-        use tokio::io::{AsyncReadExt, AsyncWriteExt};
-
-        let mut buffer = [0; 1024];
-        let n = socket.read(&mut buffer).await?;
-        let request = String::from_utf8_lossy(&buffer[..n]);
-
-        // Parse MCP request
-        let mcp_request: McpRequest = serde_json::from_str(&request)?;
-
-        // Handle request
-        let response = match mcp_request.method.as_str() {
-            "tools/call" => {
-                Self::handle_tool_call(
-                    mcp_request.params,
-                    discovery,
-                    trust_system,
-                    settlement,
-                ).await
-            },
-            "resources/read" => {
-                Self::handle_resource_read(
-                    mcp_request.params,
-                    discovery,
-                    trust_system,
-                ).await
-            },
-            "prompts/get" => {
-                Self::handle_prompt_get(
-                    mcp_request.params,
-                ).await
-            },
-            _ => {
-                Err(NegotiationError::InvalidInput("Unknown MCP method".into()))
+    /// Serves requests from a connection until the client closes it, it's
+    /// idle for too long or the server shuts down. Requests and responses are
+    /// newline-delimited JSON, one per line, so a client can send any number
+    /// of requests over one connection.
+    async fn handle_connection<S>(socket: S, context: ConnectionContext, limits: ConnectionLimits) -> Result<()>
+    where
+        S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+    {
+        let (reader, mut writer) = tokio::io::split(socket);
+        let mut reader = tokio::io::BufReader::new(reader);
+        let mut frame = Vec::new();
+
+        loop {
+            let read = tokio::select! {
+                _ = limits.shutdown.cancelled() => break,
+                read = tokio::time::timeout(limits.idle_timeout, read_frame(&mut reader, &mut frame)) => read,
+            };
+            match read {
+                Ok(read) => if !read? {
+                    break;
+                },
+                Err(_) => {
+                    tracing::debug!("Closing MCP connection idle for {:?}", limits.idle_timeout);
+                    break;
+                }
+            }
+            if frame.iter().all(u8::is_ascii_whitespace) {
+                continue;
             }
-        };
 
-        // Send response
-        let mcp_response = McpResponse {
-            id: mcp_request.id,
-            result: response.map_err(|e| e.to_string()),
-        };
+            // A malformed request is answered without dropping the connection
+            let mcp_request: McpRequest = match serde_json::from_slice(&frame) {
+                Ok(request) => request,
+                Err(e) => {
+                    let mcp_response = McpResponse {
+                        id: String::new(),
+                        result: Err(format!("Invalid MCP request: {}", e)),
+                    };
+                    limits.send(&mut writer, &mcp_response, false).await?;
+                    continue;
+                }
+            };
 
-        let response_json = serde_json::to_string(&mcp_response)?;
-        socket.write_all(response_json.as_bytes()).await?;
+            // Handle request
+            let response = match mcp_request.method.as_str() {
+                "tools/call" => {
+                    Self::handle_tool_call(
+                        mcp_request.params,
+                        context.discovery.clone(),
+                        context.trust_system.clone(),
+                        context.settlement.clone(),
+                        context.database.clone(),
+                        context.locale,
+                        &context.custom_tools,
+                    ).await
+                },
+                "tools/list" => {
+                    let mut tools = tool_definitions();
+                    tools.extend(context.custom_tools.definitions());
+                    Ok(serde_json::json!({ "tools": tools }))
+                },
+                "resources/list" => {
+                    Ok(serde_json::json!({ "resources": resource_definitions() }))
+                },
+                "resources/read" => {
+                    Self::handle_resource_read(
+                        mcp_request.params,
+                        context.registry.clone(),
+                        context.trust_system.clone(),
+                        context.database.clone(),
+                        context.privacy.clone(),
+                    ).await
+                },
+                "prompts/get" => {
+                    Self::handle_prompt_get(
+                        mcp_request.params,
+                    ).await
+                },
+                _ => {
+                    Err(NegotiationError::InvalidInput("Unknown MCP method".into()))
+                }
+            };
+
+            // Send response
+            let mcp_response = McpResponse {
+                id: mcp_request.id,
+                result: response.map_err(|e| e.to_string()),
+            };
+            limits.send(&mut writer, &mcp_response, mcp_request.stream).await?;
+        }
 
         Ok(())
     }
@@ -125,6 +272,9 @@ impl NegotiationMcpServer {
         discovery: Arc<RwLock<DiscoveryService>>,
         trust_system: Arc<RwLock<TrustSystem>>,
         settlement: Arc<RwLock<SettlementService>>,
+        database: Database,
+        locale: Locale,
+        custom_tools: &CustomTools,
     ) -> Result<serde_json::Value> {
         let tool_call: ToolCall = serde_json::from_value(params)?;
 
@@ -142,6 +292,9 @@ impl NegotiationMcpServer {
                     reputation_score: 50,
                     products: vec![],
                     payment_methods: request.payment_methods,
+                    protocol_versions: request.protocol_versions,
+                    preferred_languages: request.preferred_languages,
+                    tls_fingerprint: None,
                     created_at: chrono::Utc::now(),
                     last_active: chrono::Utc::now(),
                 };
@@ -154,28 +307,85 @@ impl NegotiationMcpServer {
                 let result = discovery.search_sellers(request).await?;
                 Ok(serde_json::to_value(result)?)
             },
+            "get_price_history" => {
+                let history_req: PriceHistoryRequest = serde_json::from_value(tool_call.arguments)?;
+                let discovery = discovery.read().await;
+                let histories = discovery.price_history(&history_req.product_id, history_req.seller_id, history_req.days).await?;
+                Ok(serde_json::to_value(histories)?)
+            },
+            "refresh_token" => {
+                let refresh_req: RefreshTokenRequest = serde_json::from_value(tool_call.arguments)?;
+                let mut trust_system = trust_system.write().await;
+                let tokens = trust_system.refresh_tokens(&refresh_req.refresh_token).await?;
+                Ok(serde_json::to_value(tokens)?)
+            },
             "get_reputation" => {
                 let rep_req: ReputationRequest = serde_json::from_value(tool_call.arguments)?;
                 let trust_system = trust_system.read().await;
                 let score = trust_system.get_reputation(rep_req.agent_id).await?;
                 Ok(serde_json::to_value(score)?)
             },
+            "format_price" => {
+                let format_req: FormatPriceRequest = serde_json::from_value(tool_call.arguments)?;
+                let formatter = PriceFormatter::new(format_req.locale.unwrap_or(locale));
+                Ok(serde_json::json!({
+                    "locale": formatter.locale(),
+                    "formatted": formatter.format_price(format_req.amount, &format_req.currency),
+                }))
+            },
+            "negotiate_language" => {
+                let language_req: LanguageRequest = serde_json::from_value(tool_call.arguments)?;
+                let language = language::negotiate_language(&language_req.languages, &language_req.counterparty_languages);
+                Ok(serde_json::json!({
+                    "language": language,
+                    "name": language.name(),
+                    "instruction": language.llm_instruction(),
+                }))
+            },
+            "create_payment" => {
+                let payment_req: CreatePaymentRequest = serde_json::from_value(tool_call.arguments)?;
+                let payment = payment_req.payment;
+                authorize_session(&trust_system, &database, &payment_req.session_token, payment.transaction_id, &[payment.buyer_id]).await?;
+                let settlement = settlement.read().await;
+                let result = settlement.process_payment(payment).await?;
+                Ok(serde_json::to_value(result)?)
+            },
+            "release_escrow" => {
+                let release_req: ReleaseEscrowRequest = serde_json::from_value(tool_call.arguments)?;
+                let settlement = settlement.read().await;
+                let escrow_hold = settlement.get_escrow(release_req.escrow_id).await?;
+                authorize_session(&trust_system, &database, &release_req.session_token, escrow_hold.transaction_id, &[escrow_hold.buyer_id]).await?;
+                let result = settlement.release_escrow(release_req.escrow_id).await?;
+                Ok(serde_json::to_value(result)?)
+            },
+            "payment_status" => {
+                let status_req: PaymentStatusRequest = serde_json::from_value(tool_call.arguments)?;
+                let settlement = settlement.read().await;
+                let payment = settlement.get_payment(&status_req.payment_id).await?;
+                Ok(serde_json::to_value(payment.to_result())?)
+            },
             "update_reputation" => {
                 let update_req: ReputationUpdateRequest = serde_json::from_value(tool_call.arguments)?;
                 let mut trust_system = trust_system.write().await;
-                trust_system.update_reputation(update_req.agent_id, update_req.score_change).await?;
+                trust_system.authorize(&update_req.access_token, Permission::AdjustReputation).await?;
+                trust_system.record_reputation_change(update_req.agent_id, update_req.score_change, update_req.deal_value).await?;
+                settlement.read().await.audit_log()
+                    .record(AuditAction::ReputationChange, None, &update_req.agent_id.to_string(), &update_req).await;
                 Ok(serde_json::to_value("Reputation updated")?)
             },
-            _ => {
-                Err(NegotiationError::InvalidInput(format!("Unknown tool: {}", tool_call.name)))
-            }
+            _ => match custom_tools.handler(&tool_call.name) {
+                Some(handler) => handler(tool_call.arguments).await,
+                None => Err(NegotiationError::InvalidInput(format!("Unknown tool: {}", tool_call.name))),
+            },
         }
     }
 
     async fn handle_resource_read(
         params: serde_json::Value,
-        discovery: Arc<RwLock<DiscoveryService>>,
+        registry: DiscoveryServer,
         trust_system: Arc<RwLock<TrustSystem>>,
+        database: Database,
+        privacy: PrivacyFilter,
     ) -> Result<serde_json::Value> {
         let resource_req: ResourceRequest = serde_json::from_value(params)?;
 
@@ -186,124 +396,40 @@ impl NegotiationMcpServer {
                 Ok(serde_json::to_value(reputations)?)
             },
             "product://catalog" => {
-                // Mock product catalog for now
-                let mock_catalog = vec![
-                    crate::model::Product {
-                        id: "laptop-001".into(),
-                        name: "Gaming Laptop Pro".into(),
-                        description: "High-performance gaming laptop with RTX 4080".into(),
-                        category: "Electronics".into(),
-                        base_price: 2499.99,
-                        currency: "USD".into(),
-                        stock_quantity: 15,
-                        metadata: std::collections::HashMap::new(),
-                    },
-                    crate::model::Product {
-                        id: "keyboard-002".into(),
-                        name: "Mechanical Keyboard RGB".into(),
-                        description: "Premium mechanical keyboard with RGB lighting".into(),
-                        category: "Electronics".into(),
-                        base_price: 129.99,
-                        currency: "USD".into(),
-                        stock_quantity: 50,
-                        metadata: std::collections::HashMap::new(),
-                    },
-                    crate::model::Product {
-                        id: "monitor-003".into(),
-                        name: "4K Monitor 27\"".into(),
-                        description: "Ultra HD 27-inch monitor with HDR support".into(),
-                        category: "Electronics".into(),
-                        base_price: 399.99,
-                        currency: "USD".into(),
-                        stock_quantity: 25,
-                        metadata: std::collections::HashMap::new(),
-                    },
-                ];
-                Ok(serde_json::to_value(mock_catalog)?)
+                let catalog = registry.catalog(None).await?;
+                Ok(serde_json::json!({
+                    "products": catalog,
+                    "total_count": catalog.len(),
+                }))
             },
             "agent://active" => {
-                // Mock active agents
-                let mock_agents = vec![
-                    crate::model::AgentInfo {
-                        id: AgentId::new_v4(),
-                        agent_type: crate::model::AgentType::Seller,
-                        name: "TechStore Pro".into(),
-                        endpoint: "http://localhost:8001".into(),
-                        public_key: "mock_public_key_1".into(),
-                        reputation_score: 85,
-                        products: vec![],
-                        payment_methods: vec![crate::model::PaymentMethod::Stripe],
-                        created_at: chrono::Utc::now(),
-                        last_active: chrono::Utc::now(),
-                    },
-                    crate::model::AgentInfo {
-                        id: AgentId::new_v4(),
-                        agent_type: crate::model::AgentType::Seller,
-                        name: "GadgetHub".into(),
-                        endpoint: "http://localhost:8002".into(),
-                        public_key: "mock_public_key_2".into(),
-                        reputation_score: 72,
-                        products: vec![],
-                        payment_methods: vec![crate::model::PaymentMethod::Stripe, crate::model::PaymentMethod::Escrow],
-                        created_at: chrono::Utc::now(),
-                        last_active: chrono::Utc::now(),
-                    },
-                ];
-                Ok(serde_json::to_value(mock_agents)?)
+                let since = Utc::now() - chrono::Duration::hours(ACTIVE_WINDOW_HOURS);
+                let agents = registry.active_agents(since).await?;
+                Ok(serde_json::json!({
+                    "agents": agents,
+                    "total_count": agents.len(),
+                    "active_since": since,
+                }))
             },
             "negotiation://history" => {
-                // Mock negotiation history
-                let mock_history = serde_json::json!({
-                    "negotiations": [
-                        {
-                            "id": "neg-001",
-                            "product_id": "laptop-001",
-                            "buyer_id": "buyer-123",
-                            "seller_id": "seller-456",
-                            "initial_price": 2499.99,
-                            "final_price": 2299.99,
-                            "status": "completed",
-                            "timestamp": "2024-01-15T10:30:00Z"
-                        },
-                        {
-                            "id": "neg-002",
-                            "product_id": "keyboard-002",
-                            "buyer_id": "buyer-789",
-                            "seller_id": "seller-456",
-                            "initial_price": 129.99,
-                            "final_price": 119.99,
-                            "status": "completed",
-                            "timestamp": "2024-01-15T14:20:00Z"
-                        }
-                    ],
-                    "total_count": 2
-                });
-                Ok(mock_history)
+                // Redacted when privacy mode is on
+                let records = database.get_negotiation_records(HISTORY_LIMIT).await?;
+                Ok(serde_json::json!({
+                    "negotiations": privacy.export_records(&records),
+                    "total_count": records.len(),
+                    "redacted": privacy.is_enabled(),
+                }))
             },
             "market://analytics" => {
-                // Mock market analytics
-                let mock_analytics = serde_json::json!({
-                    "categories": {
-                        "Electronics": {
-                            "total_volume": 1250000.00,
-                            "average_price": 456.78,
-                            "trend": "increasing",
-                            "volatility": 0.12
-                        },
-                        "Accessories": {
-                            "total_volume": 345000.00,
-                            "average_price": 89.99,
-                            "trend": "stable",
-                            "volatility": 0.08
-                        }
-                    },
-                    "top_products": [
-                        {"id": "laptop-001", "volume": 450000.00},
-                        {"id": "keyboard-002", "volume": 125000.00},
-                        {"id": "monitor-003", "volume": 98000.00}
-                    ]
-                });
-                Ok(mock_analytics)
+                let analytics = analytics::market_analytics(&database, &AnalyticsQuery::default(), &privacy).await?;
+                Ok(serde_json::to_value(analytics)?)
+            },
+            "market://anomalies" => {
+                let anomalies = database.get_anomalies(&AnomalyQuery::default()).await?;
+                Ok(serde_json::json!({
+                    "anomalies": anomalies,
+                    "total_count": anomalies.len(),
+                }))
             },
             _ => {
                 Ok(serde_json::json!({"error": "Resource not found", "uri": resource_req.uri}))
@@ -346,12 +472,351 @@ impl NegotiationMcpServer {
     }
 }
 
+/// What a connection's requests are served from
+#[derive(Clone)]
+struct ConnectionContext {
+    discovery: Arc<RwLock<DiscoveryService>>,
+    trust_system: Arc<RwLock<TrustSystem>>,
+    settlement: Arc<RwLock<SettlementService>>,
+    registry: DiscoveryServer,
+    database: Database,
+    privacy: PrivacyFilter,
+    locale: Locale,
+    custom_tools: Arc<CustomTools>,
+}
+
+/// Timeouts of a connection, and the token that shuts it down
+#[derive(Clone)]
+struct ConnectionLimits {
+    idle_timeout: Duration,
+    write_timeout: Duration,
+    shutdown: CancellationToken,
+}
+
+impl ConnectionLimits {
+    fn new(config: &McpConfig, shutdown: CancellationToken) -> Self {
+        Self {
+            idle_timeout: Duration::from_secs(config.idle_timeout_seconds.max(1)),
+            write_timeout: Duration::from_secs(config.write_timeout_seconds.max(1)),
+            shutdown,
+        }
+    }
+
+    /// Writes `response`, giving up on a client that doesn't read it in time.
+    async fn send<W>(&self, writer: &mut W, response: &McpResponse, stream: bool) -> Result<()>
+    where
+        W: tokio::io::AsyncWrite + Unpin,
+    {
+        tokio::time::timeout(self.write_timeout, write_response(writer, response, stream, STREAM_CHUNK_BYTES)).await
+            .map_err(|_| NegotiationError::Io(format!("Timed out writing MCP response {}", response.id)))?
+    }
+}
+
+/// Reads the next newline-terminated request into `frame`. Returns false
+/// once the client has closed the connection.
+async fn read_frame<R>(reader: &mut R, frame: &mut Vec<u8>) -> Result<bool>
+where
+    R: tokio::io::AsyncBufRead + Unpin,
+{
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt};
+
+    frame.clear();
+    let mut limited = (&mut *reader).take(MAX_REQUEST_BYTES as u64 + 1);
+    let n = limited.read_until(b'\n', frame).await.map_err(|e| NegotiationError::Io(e.to_string()))?;
+    if n > MAX_REQUEST_BYTES {
+        return Err(NegotiationError::InvalidInput(format!("MCP request exceeds {} bytes", MAX_REQUEST_BYTES)));
+    }
+    Ok(n > 0)
+}
+
+/// Writes `response` as one line, or, when the client asked for a stream,
+/// as `McpChunk` lines of at most `chunk_bytes` of the response's JSON each.
+async fn write_response<W>(writer: &mut W, response: &McpResponse, stream: bool, chunk_bytes: usize) -> Result<()>
+where
+    W: tokio::io::AsyncWrite + Unpin,
+{
+    use tokio::io::AsyncWriteExt;
+
+    let response_json = serde_json::to_string(response)?;
+    let mut frames = Vec::new();
+    if stream {
+        let mut rest = response_json.as_str();
+        let mut seq = 0;
+        loop {
+            let mut split = rest.len().min(chunk_bytes.max(4));
+            while !rest.is_char_boundary(split) {
+                split -= 1;
+            }
+            let (data, remaining) = rest.split_at(split);
+            rest = remaining;
+            frames.push(serde_json::to_string(&McpChunk {
+                id: response.id.clone(),
+                seq,
+                data: data.to_string(),
+                done: rest.is_empty(),
+            })?);
+            if rest.is_empty() {
+                break;
+            }
+            seq += 1;
+        }
+    } else {
+        frames.push(response_json);
+    }
+
+    for frame in frames {
+        writer.write_all(frame.as_bytes()).await.map_err(|e| NegotiationError::Io(e.to_string()))?;
+        writer.write_all(b"\n").await.map_err(|e| NegotiationError::Io(e.to_string()))?;
+    }
+    writer.flush().await.map_err(|e| NegotiationError::Io(e.to_string()))
+}
+
+/// Checks that `session_token` is a live session token for the negotiation
+/// held by one of `parties`, as the settlement service's HTTP routes do.
+async fn authorize_session(
+    trust_system: &RwLock<TrustSystem>,
+    database: &Database,
+    session_token: &str,
+    negotiation_id: TransactionId,
+    parties: &[AgentId],
+) -> Result<AgentId> {
+    let session_tokens = trust_system.read().await.session_tokens();
+    session_tokens.authorize_token(database, session_token, negotiation_id).await?
+        .require_party(parties)
+}
+
+/// A tool listed by `tools/list`, with a JSON Schema for its arguments
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ToolDefinition {
+    name: String,
+    description: String,
+    input_schema: serde_json::Value,
+}
+
+impl ToolDefinition {
+    fn new(name: &str, description: &str, input_schema: serde_json::Value) -> Self {
+        Self {
+            name: name.into(),
+            description: description.into(),
+            input_schema,
+        }
+    }
+}
+
+/// A resource listed by `resources/list`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ResourceDefinition {
+    uri: String,
+    name: String,
+    description: String,
+    mime_type: String,
+}
+
+impl ResourceDefinition {
+    fn new(uri: &str, name: &str, description: &str) -> Self {
+        Self {
+            uri: uri.into(),
+            name: name.into(),
+            description: description.into(),
+            mime_type: "application/json".into(),
+        }
+    }
+}
+
+fn resource_definitions() -> Vec<ResourceDefinition> {
+    vec![
+        ResourceDefinition::new("agent://reputations", "Agent reputations", "Reputation scores of known agents"),
+        ResourceDefinition::new(
+            "product://catalog",
+            "Product catalog",
+            "Every product sellers have registered, with the seller's ID, name and reputation, after compliance screening",
+        ),
+        ResourceDefinition::new(
+            "agent://active",
+            "Active agents",
+            "Buyers and sellers active in the last 24 hours, most recently active first",
+        ),
+        ResourceDefinition::new(
+            "negotiation://history",
+            "Negotiation history",
+            "The most recent completed-deal records, redacted in privacy mode",
+        ),
+        ResourceDefinition::new(
+            "market://analytics",
+            "Market analytics",
+            "Deal volume, price deltas and trends, and seller win rates over the last 30 days, redacted in privacy mode",
+        ),
+        ResourceDefinition::new(
+            "market://anomalies",
+            "Market anomalies",
+            "The most recently flagged reputation spikes, outlying quotes and wash trading between agent pairs",
+        ),
+    ]
+}
+
+/// Tools downstream crates added to the server, by name
+#[derive(Clone, Default)]
+struct CustomTools {
+    tools: HashMap<String, (ToolDefinition, ToolHandler)>,
+}
+
+impl CustomTools {
+    fn register(&mut self, name: String, schema: serde_json::Value, handler: ToolHandler) -> Result<()> {
+        if name.is_empty() {
+            return Err(NegotiationError::Config("MCP tool name cannot be empty".to_string()));
+        }
+        if self.tools.contains_key(&name) || tool_definitions().iter().any(|tool| tool.name == name) {
+            return Err(NegotiationError::Config(format!("MCP tool {} is already registered", name)));
+        }
+        if !schema.is_object() {
+            return Err(NegotiationError::Config(format!("Schema of MCP tool {} must be a JSON object", name)));
+        }
+
+        let description = schema.get("description").and_then(serde_json::Value::as_str).unwrap_or_default();
+        let definition = ToolDefinition::new(&name, description, schema.clone());
+        self.tools.insert(name, (definition, handler));
+        Ok(())
+    }
+
+    fn handler(&self, name: &str) -> Option<ToolHandler> {
+        self.tools.get(name).map(|(_, handler)| handler.clone())
+    }
+
+    /// Definitions sorted by name, so `tools/list` is stable
+    fn definitions(&self) -> Vec<ToolDefinition> {
+        let mut definitions: Vec<ToolDefinition> = self.tools.values().map(|(definition, _)| definition.clone()).collect();
+        definitions.sort_by(|a, b| a.name.cmp(&b.name));
+        definitions
+    }
+}
+
+fn tool_definitions() -> Vec<ToolDefinition> {
+    let uuid = serde_json::json!({"type": "string", "format": "uuid"});
+    let agent_id = serde_json::json!({"type": "string", "format": "uuid", "description": "Agent ID"});
+    let amount = serde_json::json!({"type": "number", "minimum": 0});
+    let payment_method = serde_json::json!({"type": "string", "enum": ["stripe", "solana", "escrow", "pay_on_delivery", "lightning"]});
+    let languages = serde_json::json!({
+        "type": "array",
+        "items": {"type": "string", "enum": ["en", "de", "fr", "es", "pt", "ja"]},
+        "description": "ISO 639-1 language codes, most preferred first",
+    });
+    let session_token = serde_json::json!({
+        "type": "string",
+        "description": "The buyer's session token for the negotiation",
+    });
+    vec![
+        ToolDefinition::new("register_agent", "Register a buyer or seller with the discovery service", serde_json::json!({
+            "type": "object",
+            "properties": {
+                "agent_type": {"type": "string", "enum": ["buyer", "seller"]},
+                "name": {"type": "string"},
+                "endpoint": {"type": "string", "format": "uri", "description": "Base URL the agent serves its API at"},
+                "public_key": {"type": "string", "description": "The agent's base64-encoded public key"},
+                "payment_methods": {"type": "array", "items": payment_method},
+                "protocol_versions": {"type": "array", "items": {"type": "string"}, "description": "Protocol versions spoken, e.g. \"1.0\""},
+                "preferred_languages": languages,
+            },
+            "required": ["agent_type", "name", "endpoint", "public_key", "payment_methods"],
+        })),
+        ToolDefinition::new("search_agents", "Search sellers by category, reputation and responsiveness", serde_json::json!({
+            "type": "object",
+            "properties": {
+                "category": {"type": "string"},
+                "min_reputation": {"type": "integer", "minimum": 0, "maximum": 100},
+                "payment_methods": {"type": "array", "items": payment_method, "description": "Sellers accepting any of these"},
+                "region": {"type": "string", "description": "ISO 3166 country code the buyer is in, for compliance screening"},
+                "max_response_time_ms": {"type": "integer", "minimum": 0, "description": "Leave out sellers slower to quote on average"},
+                "rank_by_responsiveness": {"type": "boolean", "description": "Fastest sellers first instead of most reputable"},
+            },
+        })),
+        ToolDefinition::new("get_price_history", "A product's price history per seller", serde_json::json!({
+            "type": "object",
+            "properties": {
+                "product_id": {"type": "string"},
+                "seller_id": {"type": "string", "format": "uuid", "description": "Only this seller's prices"},
+                "days": {"type": "integer", "minimum": 1, "description": "How far back to look"},
+            },
+            "required": ["product_id"],
+        })),
+        ToolDefinition::new("refresh_token", "Trade a refresh token for a new token pair", serde_json::json!({
+            "type": "object",
+            "properties": {"refresh_token": {"type": "string"}},
+            "required": ["refresh_token"],
+        })),
+        ToolDefinition::new("get_reputation", "An agent's reputation score", serde_json::json!({
+            "type": "object",
+            "properties": {"agent_id": agent_id},
+            "required": ["agent_id"],
+        })),
+        ToolDefinition::new("update_reputation", "Adjust an agent's reputation score", serde_json::json!({
+            "type": "object",
+            "properties": {
+                "access_token": {"type": "string", "description": "An operator's JWT with the admin role"},
+                "agent_id": agent_id,
+                "score_change": {"type": "integer", "description": "Points to add, or subtract when negative"},
+                "deal_value": {"type": "string", "description": "Value of the deal behind the change, which scales it"},
+            },
+            "required": ["access_token", "agent_id", "score_change"],
+        })),
+        ToolDefinition::new("format_price", "Render an amount in a locale", serde_json::json!({
+            "type": "object",
+            "properties": {
+                "amount": amount,
+                "currency": {"type": "string", "description": "ISO 4217 code"},
+                "locale": {"type": "string", "description": "e.g. de-DE; the server's locale when omitted"},
+            },
+            "required": ["amount", "currency"],
+        })),
+        ToolDefinition::new("negotiate_language", "Pick the language to negotiate in with a counterparty", serde_json::json!({
+            "type": "object",
+            "properties": {"languages": languages.clone(), "counterparty_languages": languages},
+            "required": ["languages"],
+        })),
+        ToolDefinition::new("create_payment", "Pay for a negotiated deal; needs the buyer's session token for the negotiation", serde_json::json!({
+            "type": "object",
+            "properties": {
+                "session_token": session_token.clone(),
+                "transaction_id": {"type": "string", "format": "uuid", "description": "The negotiation being paid for"},
+                "buyer_id": uuid,
+                "seller_id": uuid,
+                "amount": amount,
+                "currency": {"type": "string", "description": "ISO 4217 code"},
+                "payment_method": payment_method,
+                "description": {"type": "string"},
+                "metadata": {"type": "object", "additionalProperties": {"type": "string"}},
+                "idempotency_key": {"type": "string", "description": "Retries with the same key return the original payment"},
+            },
+            "required": [
+                "session_token", "transaction_id", "buyer_id", "seller_id", "amount", "currency",
+                "payment_method", "description", "metadata",
+            ],
+        })),
+        ToolDefinition::new("release_escrow", "Release an escrow hold to the seller once delivery is confirmed; needs the buyer's session token", serde_json::json!({
+            "type": "object",
+            "properties": {"escrow_id": uuid, "session_token": session_token},
+            "required": ["escrow_id", "session_token"],
+        })),
+        ToolDefinition::new("payment_status", "A payment's status, amount and completion time", serde_json::json!({
+            "type": "object",
+            "properties": {"payment_id": {"type": "string"}},
+            "required": ["payment_id"],
+        })),
+    ]
+}
+
 // MCP Request/Response types
 #[derive(Debug, Serialize, Deserialize)]
 struct McpRequest {
     id: String,
     method: String,
+    /// Absent for `tools/list` and `resources/list`
+    #[serde(default)]
     params: serde_json::Value,
+    /// Send the response as a series of `McpChunk`s, for large resources
+    #[serde(default)]
+    stream: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -360,6 +825,17 @@ struct McpResponse {
     result: std::result::Result<serde_json::Value, String>,
 }
 
+/// A piece of a streamed response. Joining the `data` of a response's
+/// chunks in `seq` order gives the JSON of its `McpResponse`.
+#[derive(Debug, Serialize, Deserialize)]
+struct McpChunk {
+    id: String,
+    seq: usize,
+    data: String,
+    /// Set on the last chunk
+    done: bool,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 struct ToolCall {
     name: String,
@@ -381,10 +857,66 @@ struct ReputationRequest {
     agent_id: AgentId,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+struct RefreshTokenRequest {
+    refresh_token: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct PriceHistoryRequest {
+    product_id: String,
+    seller_id: Option<AgentId>,
+    /// Defaults to the registry's window
+    days: Option<u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct FormatPriceRequest {
+    amount: Decimal,
+    currency: String,
+    /// Defaults to the server's configured locale
+    locale: Option<Locale>,
+}
+
+/// Arguments of `create_payment`: a payment request plus the buyer's
+/// session token for the negotiation being paid for
+#[derive(Debug, Serialize, Deserialize)]
+struct CreatePaymentRequest {
+    session_token: String,
+    #[serde(flatten)]
+    payment: PaymentRequest,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ReleaseEscrowRequest {
+    escrow_id: uuid::Uuid,
+    /// The buyer's session token for the escrowed negotiation
+    session_token: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct PaymentStatusRequest {
+    payment_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct LanguageRequest {
+    /// The caller's languages, most preferred first
+    languages: Vec<Language>,
+    /// Empty for agents that predate language negotiation
+    #[serde(default)]
+    counterparty_languages: Vec<Language>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 struct ReputationUpdateRequest {
+    /// JWT whose role may adjust reputations
+    #[serde(skip_serializing)]
+    access_token: String,
     agent_id: AgentId,
     score_change: i32,
+    #[serde(default)]
+    deal_value: Option<Decimal>,
 }
 
 // MCP Prompts
@@ -412,9 +944,9 @@ impl NegotiationPrompt {
 You are a negotiation agent for {{product_name}} in the {{category}} category.
 
 Market Context:
-- Current market price: ${{market_price}}
-- Your reservation price: ${{reservation_price}}
-- Buyer's maximum price: ${{buyer_max_price}}
+- Current market price: {{market_price}}
+- Your reservation price: {{reservation_price}}
+- Buyer's maximum price: {{buyer_max_price}}
 - Your reputation score: {{reputation_score}}/100
 
 Generate a negotiation strategy that:
@@ -439,17 +971,17 @@ Strategy:
                 },
                 PromptVariable {
                     name: "market_price".into(),
-                    description: "Current market price".into(),
+                    description: "Current market price (locale-formatted, see format_price)".into(),
                     required: true,
                 },
                 PromptVariable {
                     name: "reservation_price".into(),
-                    description: "Your minimum acceptable price".into(),
+                    description: "Your minimum acceptable price, at least the product's price floor (locale-formatted, see format_price)".into(),
                     required: true,
                 },
                 PromptVariable {
                     name: "buyer_max_price".into(),
-                    description: "Buyer's maximum willing price".into(),
+                    description: "Buyer's maximum willing price (locale-formatted, see format_price)".into(),
                     required: true,
                 },
                 PromptVariable {
@@ -585,10 +1117,10 @@ Market Analysis:
 You are a negotiation agent responding to an offer for {{product_name}}.
 
 Current Negotiation State:
-- Original asking price: ${{original_price}}
-- Buyer's offer: ${{buyer_offer}}
-- Your minimum acceptable price: ${{min_price}}
-- Market average: ${{market_price}}
+- Original asking price: {{original_price}}
+- Buyer's offer: {{buyer_offer}}
+- Your minimum acceptable price: {{min_price}}
+- Market average: {{market_price}}
 - Urgency level: {{urgency_level}}
 - Buyer's reputation: {{buyer_reputation}}/100
 
@@ -598,6 +1130,9 @@ Generate a counter-offer that:
 3. Maintains good relationship with the buyer
 4. Considers market conditions and urgency
 5. May include value-added terms (free shipping, warranty, etc.)
+6. Never goes below your minimum acceptable price; the seller refuses counter offers below its price floor
+
+Write the response in {{language}}. Keep prices, currency codes, quantities and product IDs exactly as given; do not translate or reformat them.
 
 Counter-Offer Response:
 "#.into(),
@@ -609,22 +1144,22 @@ Counter-Offer Response:
                 },
                 PromptVariable {
                     name: "original_price".into(),
-                    description: "Original asking price".into(),
+                    description: "Original asking price (locale-formatted, see format_price)".into(),
                     required: true,
                 },
                 PromptVariable {
                     name: "buyer_offer".into(),
-                    description: "Buyer's current offer".into(),
+                    description: "Buyer's current offer (locale-formatted, see format_price)".into(),
                     required: true,
                 },
                 PromptVariable {
                     name: "min_price".into(),
-                    description: "Your minimum acceptable price".into(),
+                    description: "Your minimum acceptable price (locale-formatted, see format_price)".into(),
                     required: true,
                 },
                 PromptVariable {
                     name: "market_price".into(),
-                    description: "Current market price".into(),
+                    description: "Current market price (locale-formatted, see format_price)".into(),
                     required: true,
                 },
                 PromptVariable {
@@ -637,6 +1172,11 @@ Counter-Offer Response:
                     description: "Buyer's reputation score (0-100)".into(),
                     required: true,
                 },
+                PromptVariable {
+                    name: "language".into(),
+                    description: "Language negotiated with the counterparty (see negotiate_language); English when left out".into(),
+                    required: false,
+                },
             ],
         }
     }
@@ -662,6 +1202,8 @@ Generate a professional communication message that:
 4. Moves the negotiation forward constructively
 5. Includes specific details and next steps
 
+Write the message in {{language}}. Keep prices, currency codes, quantities and product IDs exactly as given; do not translate or reformat them.
+
 {{agent_role}} Message:
 "#.into(),
             variables: vec![
@@ -705,6 +1247,11 @@ Generate a professional communication message that:
                     description: "Desired communication tone".into(),
                     required: true,
                 },
+                PromptVariable {
+                    name: "language".into(),
+                    description: "Language negotiated with the counterparty (see negotiate_language); English when left out".into(),
+                    required: false,
+                },
             ],
         }
     }
@@ -792,4 +1339,289 @@ Trust Assessment Report:
             ],
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::roles::Role;
+    use tempfile::NamedTempFile;
+
+    #[tokio::test]
+    async fn test_settlement_tools_require_the_buyers_session_token() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let database = Database::new(&format!("sqlite://{}", temp_file.path().to_string_lossy())).await.unwrap();
+        let mut trust = TrustSystem::new().unwrap();
+        let (buyer_id, seller_id, negotiation_id) = (AgentId::new_v4(), AgentId::new_v4(), uuid::Uuid::new_v4());
+        let buyer_token = trust.generate_jwt(buyer_id).await.unwrap();
+        let session_token = trust.issue_session_token(&buyer_token, negotiation_id).await.unwrap();
+        let seller_token = trust.generate_jwt(seller_id).await.unwrap();
+        let seller_session_token = trust.issue_session_token(&seller_token, negotiation_id).await.unwrap();
+        let admin_token = trust.generate_role_jwt("ops@example.com", Role::Admin, chrono::Duration::minutes(5)).unwrap();
+
+        let discovery = Arc::new(RwLock::new(DiscoveryService::new("http://localhost:8000".to_string())));
+        let trust_system = Arc::new(RwLock::new(trust));
+        let settlement = Arc::new(RwLock::new(SettlementService::new(crate::settlement::SettlementConfig {
+            stripe_secret_key: None,
+            solana_rpc_url: None,
+            escrow_service_url: None,
+            webhook_secret: None,
+            delivery_confirmation_timeout_seconds: None,
+        }, database.clone()).await.unwrap()));
+        let custom_tools = CustomTools::default();
+        let call = |name: &str, arguments: serde_json::Value| {
+            NegotiationMcpServer::handle_tool_call(
+                serde_json::json!({"name": name, "arguments": arguments}),
+                discovery.clone(),
+                trust_system.clone(),
+                settlement.clone(),
+                database.clone(),
+                Locale::default(),
+                &custom_tools,
+            )
+        };
+        let payment = |session_token: &str| serde_json::json!({
+            "session_token": session_token,
+            "transaction_id": negotiation_id,
+            "buyer_id": buyer_id,
+            "seller_id": seller_id,
+            "amount": 250.0,
+            "currency": "USD",
+            "payment_method": "escrow",
+            "description": "Laptop",
+            "metadata": {},
+        });
+
+        assert!(matches!(call("create_payment", payment(&seller_session_token)).await, Err(NegotiationError::Auth(_))));
+        let created = call("create_payment", payment(&session_token)).await.unwrap();
+        let payment_id = created["payment_id"].as_str().unwrap().to_string();
+        let status = call("payment_status", serde_json::json!({"payment_id": payment_id})).await.unwrap();
+        assert_eq!(status["status"], "pending");
+
+        // Funds stay in escrow until delivery is confirmed
+        let escrow_id = payment_id.trim_start_matches("escrow_");
+        let release = |session_token: &str| serde_json::json!({"escrow_id": escrow_id, "session_token": session_token});
+        assert!(matches!(call("release_escrow", release(&seller_session_token)).await, Err(NegotiationError::Auth(_))));
+        assert!(matches!(call("release_escrow", release(&session_token)).await, Err(NegotiationError::Payment(_))));
+
+        // Only operators adjust reputations by hand
+        let adjustment = |access_token: &str| serde_json::json!({"access_token": access_token, "agent_id": seller_id, "score_change": 5});
+        assert!(matches!(call("update_reputation", adjustment(&seller_token)).await, Err(NegotiationError::Trust(_))));
+        assert!(call("update_reputation", adjustment(&admin_token)).await.is_ok());
+
+        let listed: Vec<String> = tool_definitions().into_iter().map(|tool| tool.name).collect();
+        assert!(["create_payment", "release_escrow", "payment_status"].iter().all(|name| listed.iter().any(|tool| tool == name)));
+    }
+
+    #[tokio::test]
+    async fn test_registered_tools_are_called_and_listed() {
+        let mut custom_tools = CustomTools::default();
+        let handler: ToolHandler = Arc::new(|arguments| Box::pin(async move {
+            Ok(serde_json::json!({"booking": format!("{}-001", arguments["carrier"].as_str().unwrap_or("unknown"))}))
+        }));
+        let schema = serde_json::json!({
+            "description": "Book a shipment",
+            "type": "object",
+            "properties": {"carrier": {"type": "string"}},
+        });
+        custom_tools.register("book_shipment".to_string(), schema.clone(), handler.clone()).unwrap();
+        assert!(custom_tools.register("book_shipment".to_string(), schema.clone(), handler.clone()).is_err());
+        assert!(custom_tools.register("create_payment".to_string(), schema.clone(), handler.clone()).is_err());
+        assert!(custom_tools.register("track_shipment".to_string(), serde_json::json!("string"), handler).is_err());
+
+        let temp_file = NamedTempFile::new().unwrap();
+        let database = Database::new(&format!("sqlite://{}", temp_file.path().to_string_lossy())).await.unwrap();
+        let settlement = SettlementService::new(crate::settlement::SettlementConfig {
+            stripe_secret_key: None,
+            solana_rpc_url: None,
+            escrow_service_url: None,
+            webhook_secret: None,
+            delivery_confirmation_timeout_seconds: None,
+        }, database.clone()).await.unwrap();
+        let result = NegotiationMcpServer::handle_tool_call(
+            serde_json::json!({"name": "book_shipment", "arguments": {"carrier": "dhl"}}),
+            Arc::new(RwLock::new(DiscoveryService::new("http://localhost:8000".to_string()))),
+            Arc::new(RwLock::new(TrustSystem::new().unwrap())),
+            Arc::new(RwLock::new(settlement)),
+            database,
+            Locale::default(),
+            &custom_tools,
+        ).await.unwrap();
+        assert_eq!(result["booking"], "dhl-001");
+
+        let definitions = custom_tools.definitions();
+        assert_eq!(definitions.len(), 1);
+        assert_eq!((definitions[0].name.as_str(), definitions[0].description.as_str()), ("book_shipment", "Book a shipment"));
+    }
+
+    #[tokio::test]
+    async fn test_listed_tools_and_resources_are_served() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let database = Database::new(&format!("sqlite://{}", temp_file.path().to_string_lossy())).await.unwrap();
+        let trust_system = Arc::new(RwLock::new(TrustSystem::new().unwrap()));
+
+        for resource in resource_definitions() {
+            let result = NegotiationMcpServer::handle_resource_read(
+                serde_json::json!({"uri": resource.uri}),
+                DiscoveryServer::from_database(database.clone()),
+                trust_system.clone(),
+                database.clone(),
+                PrivacyFilter::from_config(&crate::config::PrivacyConfig::default()).unwrap(),
+            ).await.unwrap();
+            assert!(result.get("error").is_none(), "{} isn't served", resource.uri);
+        }
+
+        let settlement = Arc::new(RwLock::new(SettlementService::new(crate::settlement::SettlementConfig {
+            stripe_secret_key: None,
+            solana_rpc_url: None,
+            escrow_service_url: None,
+            webhook_secret: None,
+            delivery_confirmation_timeout_seconds: None,
+        }, database.clone()).await.unwrap()));
+        let discovery = Arc::new(RwLock::new(DiscoveryService::new("http://127.0.0.1:9".to_string())));
+        for tool in tool_definitions() {
+            let schema = tool.input_schema.as_object().unwrap();
+            assert_eq!(schema["type"], "object");
+            let properties = schema["properties"].as_object().unwrap();
+            for required in schema.get("required").and_then(|required| required.as_array()).into_iter().flatten() {
+                assert!(properties.contains_key(required.as_str().unwrap()), "{} requires unknown {}", tool.name, required);
+            }
+
+            // Every listed tool is dispatched, even when its arguments are refused
+            let result = NegotiationMcpServer::handle_tool_call(
+                serde_json::json!({"name": tool.name, "arguments": {}}),
+                discovery.clone(),
+                trust_system.clone(),
+                settlement.clone(),
+                database.clone(),
+                Locale::default(),
+                &CustomTools::default(),
+            ).await;
+            assert!(!matches!(result, Err(NegotiationError::InvalidInput(message)) if message.starts_with("Unknown tool")));
+        }
+
+        let request: McpRequest = serde_json::from_str(r#"{"id": "1", "method": "resources/list"}"#).unwrap();
+        assert!(request.params.is_null());
+    }
+
+    #[tokio::test]
+    async fn test_connection_serves_framed_requests_and_streams_responses() {
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+
+        let temp_file = NamedTempFile::new().unwrap();
+        let database = Database::new(&format!("sqlite://{}", temp_file.path().to_string_lossy())).await.unwrap();
+        let settlement = SettlementService::new(crate::settlement::SettlementConfig {
+            stripe_secret_key: None,
+            solana_rpc_url: None,
+            escrow_service_url: None,
+            webhook_secret: None,
+            delivery_confirmation_timeout_seconds: None,
+        }, database.clone()).await.unwrap();
+        let (client, server) = tokio::io::duplex(4096);
+        let context = ConnectionContext {
+            discovery: Arc::new(RwLock::new(DiscoveryService::new("http://127.0.0.1:9".to_string()))),
+            trust_system: Arc::new(RwLock::new(TrustSystem::new().unwrap())),
+            settlement: Arc::new(RwLock::new(settlement)),
+            registry: DiscoveryServer::from_database(database.clone()),
+            database,
+            privacy: PrivacyFilter::from_config(&crate::config::PrivacyConfig::default()).unwrap(),
+            locale: Locale::default(),
+            custom_tools: Arc::new(CustomTools::default()),
+        };
+        let connection = tokio::spawn(NegotiationMcpServer::handle_connection(
+            server,
+            context,
+            ConnectionLimits::new(&McpConfig::default(), CancellationToken::new()),
+        ));
+
+        let (reader, mut writer) = tokio::io::split(client);
+        let large = serde_json::json!({
+            "id": "1", "method": "prompts/get", "params": {"name": "negotiation_strategy", "padding": "x".repeat(8192)},
+        });
+        let requests = format!("{}\nnot json\n{}\n", large, r#"{"id": "3", "method": "tools/list", "stream": true}"#);
+        writer.write_all(requests.as_bytes()).await.unwrap();
+        writer.shutdown().await.unwrap();
+
+        let mut lines = tokio::io::BufReader::new(reader).lines();
+        let first: McpResponse = serde_json::from_str(&lines.next_line().await.unwrap().unwrap()).unwrap();
+        assert_eq!(first.id, "1");
+        assert!(first.result.is_ok());
+        let invalid: McpResponse = serde_json::from_str(&lines.next_line().await.unwrap().unwrap()).unwrap();
+        assert!(invalid.result.is_err());
+
+        let mut streamed = String::new();
+        while let Some(line) = lines.next_line().await.unwrap() {
+            let chunk: McpChunk = serde_json::from_str(&line).unwrap();
+            assert_eq!(chunk.id, "3");
+            streamed.push_str(&chunk.data);
+            if chunk.done {
+                break;
+            }
+        }
+        let listed: McpResponse = serde_json::from_str(&streamed).unwrap();
+        assert!(listed.result.unwrap()["tools"].as_array().is_some_and(|tools| !tools.is_empty()));
+        connection.await.unwrap().unwrap();
+
+        let response = McpResponse { id: "4".to_string(), result: Ok(serde_json::json!({"note": "réservé ".repeat(50)})) };
+        let mut written = Vec::new();
+        write_response(&mut written, &response, true, 16).await.unwrap();
+        let chunks: Vec<McpChunk> = String::from_utf8(written).unwrap().lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert!(chunks.len() > 1);
+        assert!(chunks.iter().enumerate().all(|(seq, chunk)| chunk.seq == seq && chunk.done == (seq == chunks.len() - 1)));
+        let joined: String = chunks.iter().map(|chunk| chunk.data.as_str()).collect();
+        assert_eq!(serde_json::from_str::<McpResponse>(&joined).unwrap().result.unwrap(), response.result.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_run_limits_connections_and_drains_on_shutdown() {
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+
+        let temp_file = NamedTempFile::new().unwrap();
+        let database = Database::new(&format!("sqlite://{}", temp_file.path().to_string_lossy())).await.unwrap();
+        let mut config = AppConfig::default();
+        config.mcp.max_connections = 1;
+        let server = NegotiationMcpServer {
+            discovery: Arc::new(RwLock::new(DiscoveryService::new("http://127.0.0.1:9".to_string()))),
+            trust_system: Arc::new(RwLock::new(TrustSystem::new().unwrap())),
+            settlement: Arc::new(RwLock::new(SettlementService::new(crate::settlement::SettlementConfig {
+                stripe_secret_key: None,
+                solana_rpc_url: None,
+                escrow_service_url: None,
+                webhook_secret: None,
+                delivery_confirmation_timeout_seconds: None,
+            }, database.clone()).await.unwrap())),
+            registry: DiscoveryServer::from_database(database.clone()),
+            database,
+            privacy: PrivacyFilter::from_config(&crate::config::PrivacyConfig::default()).unwrap(),
+            config,
+            custom_tools: Arc::new(CustomTools::default()),
+            shutdown: CancellationToken::new(),
+        };
+        let shutdown = server.shutdown_token();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let running = tokio::spawn(async move { server.run(listener).await });
+
+        let request = b"{\"id\": \"1\", \"method\": \"resources/list\"}\n";
+        let mut first = tokio::io::BufReader::new(tokio::net::TcpStream::connect(addr).await.unwrap());
+        first.get_mut().write_all(request).await.unwrap();
+        let mut line = String::new();
+        first.read_line(&mut line).await.unwrap();
+        assert!(line.contains("product://catalog"));
+
+        // The second client isn't served while the first holds the only slot
+        let mut second = tokio::io::BufReader::new(tokio::net::TcpStream::connect(addr).await.unwrap());
+        second.get_mut().write_all(request).await.unwrap();
+        let mut line = String::new();
+        assert!(tokio::time::timeout(Duration::from_millis(200), second.read_line(&mut line)).await.is_err());
+        drop(first);
+        tokio::time::timeout(Duration::from_secs(5), second.read_line(&mut line)).await.unwrap().unwrap();
+        assert!(line.contains("product://catalog"));
+
+        shutdown.cancel();
+        tokio::time::timeout(Duration::from_secs(5), running).await.unwrap().unwrap().unwrap();
+        let mut line = String::new();
+        assert_eq!(second.read_line(&mut line).await.unwrap(), 0);
+    }
+}
@@ -0,0 +1,224 @@
+//! Prometheus metrics.
+//!
+//! Services share a [`Metrics`] registry: seller agents count RFQs and the
+//! quotes they issue and the prices their floors blocked, the settlement service times payments and counts the
+//! ones that fail, and buyers record how long negotiations took to settle.
+//! Every router counts the requests it serves. With `[metrics] enabled` the
+//! registry is served in the Prometheus text format at `GET /metrics`.
+
+use crate::{
+    error::{NegotiationError, Result},
+    model::PaymentMethod,
+    pricing::FloorRule,
+};
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::{header, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use chrono::{DateTime, Utc};
+use prometheus::{
+    exponential_buckets, Encoder, Histogram, HistogramOpts, IntCounter, IntCounterVec, Opts, Registry, TextEncoder,
+};
+use serde::{Deserialize, Serialize};
+
+/// A seller's running totals, carried over when it moves to another host
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SellerCounters {
+    pub rfqs_received: u64,
+    pub quotes_issued: u64,
+}
+
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Registry,
+    rfqs_received: IntCounter,
+    quotes_issued: IntCounter,
+    price_floor_violations: IntCounterVec,
+    negotiation_duration: Histogram,
+    settlement_latency: Histogram,
+    payment_failures: IntCounterVec,
+    http_requests: IntCounterVec,
+    rate_limited_requests: IntCounterVec,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let registry = Registry::new_custom(Some("dcap".to_string()), None)
+            .expect("metric prefix is valid");
+        let rfqs_received = IntCounter::new("rfqs_received_total", "RFQs received by sellers")
+            .expect("metric options are valid");
+        let quotes_issued = IntCounter::new("quotes_issued_total", "Quotes and counter quotes issued by sellers")
+            .expect("metric options are valid");
+        let price_floor_violations = IntCounterVec::new(
+            Opts::new("price_floor_violations_total", "Seller prices raised to or blocked at a product's price floor"),
+            &["rule"],
+        ).expect("metric options are valid");
+        // 1s to roughly a day
+        let negotiation_duration = Histogram::with_opts(
+            HistogramOpts::new("negotiation_duration_seconds", "Time from a negotiation opening to its settlement")
+                .buckets(exponential_buckets(1.0, 4.0, 9).expect("buckets are valid")),
+        ).expect("metric options are valid");
+        // 5ms to roughly 20s
+        let settlement_latency = Histogram::with_opts(
+            HistogramOpts::new("settlement_latency_seconds", "Time taken to process a payment")
+                .buckets(exponential_buckets(0.005, 2.0, 13).expect("buckets are valid")),
+        ).expect("metric options are valid");
+        let payment_failures = IntCounterVec::new(
+            Opts::new("payment_failures_total", "Payments that failed or were declined"),
+            &["method"],
+        ).expect("metric options are valid");
+        let http_requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "HTTP requests served"),
+            &["method", "path", "status"],
+        ).expect("metric options are valid");
+        let rate_limited_requests = IntCounterVec::new(
+            Opts::new("rate_limited_requests_total", "Requests refused for exceeding a rate limit"),
+            &["path", "client"],
+        ).expect("metric options are valid");
+
+        for collector in [
+            Box::new(rfqs_received.clone()) as Box<dyn prometheus::core::Collector>,
+            Box::new(quotes_issued.clone()),
+            Box::new(price_floor_violations.clone()),
+            Box::new(negotiation_duration.clone()),
+            Box::new(settlement_latency.clone()),
+            Box::new(payment_failures.clone()),
+            Box::new(http_requests.clone()),
+            Box::new(rate_limited_requests.clone()),
+        ] {
+            registry.register(collector).expect("metric names are unique");
+        }
+
+        Self {
+            registry,
+            rfqs_received,
+            quotes_issued,
+            price_floor_violations,
+            negotiation_duration,
+            settlement_latency,
+            payment_failures,
+            http_requests,
+            rate_limited_requests,
+        }
+    }
+
+    pub fn rfq_received(&self) {
+        self.rfqs_received.inc();
+    }
+
+    pub fn quote_issued(&self) {
+        self.quotes_issued.inc();
+    }
+
+    pub fn price_floor_violated(&self, rule: FloorRule) {
+        self.price_floor_violations.with_label_values(&[rule.as_str()]).inc();
+    }
+
+    pub fn seller_counters(&self) -> SellerCounters {
+        SellerCounters {
+            rfqs_received: self.rfqs_received.get(),
+            quotes_issued: self.quotes_issued.get(),
+        }
+    }
+
+    /// Raises the seller's totals to at least `counters`, so they don't
+    /// drop when the seller moves hosts. Restoring twice counts nothing twice.
+    pub fn restore_seller_counters(&self, counters: SellerCounters) {
+        self.rfqs_received.inc_by(counters.rfqs_received.saturating_sub(self.rfqs_received.get()));
+        self.quotes_issued.inc_by(counters.quotes_issued.saturating_sub(self.quotes_issued.get()));
+    }
+
+    /// Records a negotiation opened at `opened_at` settling now.
+    pub fn negotiation_settled(&self, opened_at: DateTime<Utc>) {
+        let elapsed = (Utc::now() - opened_at).to_std().unwrap_or_default();
+        self.negotiation_duration.observe(elapsed.as_secs_f64());
+    }
+
+    pub fn observe_settlement_latency(&self, elapsed: std::time::Duration) {
+        self.settlement_latency.observe(elapsed.as_secs_f64());
+    }
+
+    pub fn payment_failed(&self, method: &PaymentMethod) {
+        let method = format!("{:?}", method).to_lowercase();
+        self.payment_failures.with_label_values(&[&method]).inc();
+    }
+
+    /// Counts a request refused with 429 on the route `path`. `client` is
+    /// "agent" when the request carried a valid token and "ip" otherwise, so
+    /// agent IDs and addresses don't multiply series.
+    pub fn request_rate_limited(&self, path: &str, client: &str) {
+        self.rate_limited_requests.with_label_values(&[path, client]).inc();
+    }
+
+    /// The registry in the Prometheus text exposition format.
+    pub fn render(&self) -> Result<String> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)
+            .map_err(|e| NegotiationError::Serialization(format!("Failed to encode metrics: {}", e)))?;
+        String::from_utf8(buffer)
+            .map_err(|e| NegotiationError::Serialization(format!("Failed to encode metrics: {}", e)))
+    }
+
+    /// Counts the requests `router` serves and adds `GET /metrics` to it.
+    pub fn instrument(&self, router: Router) -> Router {
+        router
+            .route_layer(middleware::from_fn_with_state(self.clone(), track_requests))
+            .route("/metrics", get(serve_metrics).with_state(self.clone()))
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+async fn serve_metrics(State(metrics): State<Metrics>) -> Response {
+    match metrics.render() {
+        Ok(body) => ([(header::CONTENT_TYPE, prometheus::TEXT_FORMAT)], body).into_response(),
+        Err(e) => {
+            tracing::error!("{}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Counts requests by method, route and status. Routes are labelled by their
+/// pattern (`/quote/:rfq_id`) so IDs don't multiply series.
+async fn track_requests(State(metrics): State<Metrics>, request: Request, next: Next) -> Response {
+    let method = request.method().to_string();
+    let path = request.extensions().get::<MatchedPath>()
+        .map_or_else(String::new, |path| path.as_str().to_string());
+    let response = next.run(request).await;
+    metrics.http_requests
+        .with_label_values(&[&method, &path, response.status().as_str()])
+        .inc();
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metrics_render_in_text_format() {
+        let metrics = Metrics::new();
+        metrics.rfq_received();
+        metrics.rfq_received();
+        metrics.quote_issued();
+        metrics.payment_failed(&PaymentMethod::Solana);
+        metrics.observe_settlement_latency(std::time::Duration::from_millis(30));
+        metrics.negotiation_settled(Utc::now() - chrono::Duration::minutes(2));
+
+        let body = metrics.render().unwrap();
+        assert!(body.contains("dcap_rfqs_received_total 2"));
+        assert!(body.contains("dcap_quotes_issued_total 1"));
+        assert!(body.contains("dcap_payment_failures_total{method=\"solana\"} 1"));
+        assert!(body.contains("dcap_settlement_latency_seconds_bucket{le=\"0.04\"} 1"));
+        assert!(body.contains("dcap_negotiation_duration_seconds_count 1"));
+    }
+}
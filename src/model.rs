@@ -1,5 +1,11 @@
-use crate::{AgentId, NegotiationError, Result, TransactionId};
+use crate::{
+    language::Language, money::Money, nonce::{self, Stamped}, protocol::ProtocolVersion, tax::TaxBreakdown,
+    validation::{FieldErrors, Validate},
+    webhook::WebhookSigner,
+    AgentId, NegotiationError, Result, TransactionId,
+};
 use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use uuid::Uuid;
@@ -14,6 +20,15 @@ pub struct AgentInfo {
     pub reputation_score: u32,
     pub products: Vec<Product>,
     pub payment_methods: Vec<PaymentMethod>,
+    #[serde(default)]
+    pub protocol_versions: Vec<ProtocolVersion>,
+    /// Languages the agent writes negotiation messages in, most preferred first
+    #[serde(default)]
+    pub preferred_languages: Vec<Language>,
+    /// Hex SHA-256 fingerprint of the agent's TLS certificate, for
+    /// counterparties to pin
+    #[serde(default)]
+    pub tls_fingerprint: Option<String>,
     pub created_at: DateTime<Utc>,
     pub last_active: DateTime<Utc>,
 }
@@ -31,7 +46,7 @@ pub struct Product {
     pub name: String,
     pub description: String,
     pub category: String,
-    pub base_price: f64,
+    pub base_price: Decimal,
     pub currency: String,
     pub stock_quantity: u32,
     pub metadata: HashMap<String, String>,
@@ -43,11 +58,31 @@ pub struct RFQ {
     pub buyer_id: AgentId,
     pub product_id: String,
     pub quantity: u32,
-    pub max_price: f64,
+    pub max_price: Decimal,
     pub currency: String,
     pub delivery_location: Option<String>,
     pub deadline: DateTime<Utc>,
     pub metadata: HashMap<String, String>,
+    /// Random value the seller refuses to see twice, set when the RFQ is sent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<String>,
+    /// When the RFQ was sent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub issued_at: Option<DateTime<Utc>>,
+    /// The buyer's signature over the rest of the RFQ, nonce included
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+}
+
+/// What a buyer wants quoted when asking several sellers at once
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProductSpec {
+    pub product_id: String,
+    /// Narrows seller discovery to a category
+    pub category: Option<String>,
+    pub quantity: u32,
+    pub max_price: Decimal,
+    pub delivery_location: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -55,13 +90,47 @@ pub struct Quote {
     pub id: TransactionId,
     pub rfq_id: TransactionId,
     pub seller_id: AgentId,
-    pub price: f64,
+    pub price: Decimal,
     pub currency: String,
     pub available_quantity: u32,
     pub delivery_estimate: Option<String>,
     pub ttl_seconds: u32,
+    /// Quotes from sellers that predate firmness levels are treated as firm
+    #[serde(default)]
+    pub firmness: QuoteFirmness,
+    /// Ways the seller can ship the order to the RFQ's delivery location
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub shipping_options: Vec<ShippingOption>,
     pub metadata: HashMap<String, String>,
     pub created_at: DateTime<Utc>,
+    /// Random value the buyer refuses to see twice
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<String>,
+    /// The seller's signature over the rest of the quote, nonce included
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+}
+
+/// A way of shipping a quoted order, priced in the quote's currency
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ShippingOption {
+    pub carrier: String,
+    pub cost: Decimal,
+    /// Days from dispatch to delivery
+    pub eta_days: u32,
+}
+
+/// How far a seller commits to a quote
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+#[serde(tag = "level", rename_all = "snake_case")]
+pub enum QuoteFirmness {
+    /// A price guide only; it has to be firmed up before it can be accepted
+    Indicative,
+    /// Honoured if accepted within its TTL; reneging costs reputation
+    #[default]
+    Firm,
+    /// As firm, and reneging also triggers a penalty in the quote's currency
+    BindingWithPenalty { penalty: Decimal },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -73,13 +142,20 @@ pub struct Negotiation {
     pub seller_id: AgentId,
     pub product_id: String,
     pub quantity: u32,
-    pub opening_bid: f64,
-    pub close_price: Option<f64>,
-    pub delta: Option<f64>,
+    pub opening_bid: Decimal,
+    /// Buyer's currency; opening bid, close price and delta are all in it
+    #[serde(default = "crate::currency::default_currency")]
+    pub currency: String,
+    pub close_price: Option<Decimal>,
+    pub delta: Option<Decimal>,
     pub status: NegotiationStatus,
     pub messages: Vec<NegotiationMessage>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    /// Principals the buyer negotiates for under delegated authority, followed
+    /// by the buyer itself; empty when it acts for itself
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub delegation_chain: Vec<AgentId>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -92,6 +168,8 @@ pub enum NegotiationStatus {
     Rejected,
     Expired,
     Settled,
+    /// Called off between acceptance and settlement
+    Cancelled,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -120,20 +198,44 @@ pub struct NegotiationRecord {
     pub buyer_id: AgentId,
     pub seller_id: AgentId,
     pub product_hash: String,
-    pub opening_bid: f64,
-    pub close_price: f64,
-    pub delta: f64,
+    pub opening_bid: Decimal,
+    pub close_price: Decimal,
+    pub delta: Decimal,
     pub timestamp: DateTime<Utc>,
     pub duration_seconds: u64,
     pub message_count: u32,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
 #[serde(rename_all = "lowercase")]
 pub enum PaymentMethod {
     Stripe,
     Solana,
     Escrow,
+    /// Charged once the buyer confirms delivery
+    #[serde(rename = "pay_on_delivery")]
+    PayOnDelivery,
+    /// Bitcoin over the Lightning Network; needs the `lightning` feature
+    Lightning,
+}
+
+impl Product {
+    pub fn unit_price(&self) -> Money {
+        Money::new(self.base_price, self.currency.clone())
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        self.field_errors().into_result()
+    }
+}
+
+impl Validate for Product {
+    fn validate_fields(&self, errors: &mut FieldErrors) {
+        errors.check(!self.id.trim().is_empty(), "id", "Product ID must not be empty");
+        errors.check(!self.name.trim().is_empty(), "name", "Product name must not be empty");
+        errors.check(self.base_price > Decimal::ZERO, "base_price", "Base price must be greater than 0");
+        errors.currency("currency", &self.currency);
+    }
 }
 
 impl RFQ {
@@ -141,7 +243,7 @@ impl RFQ {
         buyer_id: AgentId,
         product_id: String,
         quantity: u32,
-        max_price: f64,
+        max_price: Decimal,
         currency: String,
         deadline: DateTime<Utc>,
     ) -> Self {
@@ -155,20 +257,86 @@ impl RFQ {
             delivery_location: None,
             deadline,
             metadata: HashMap::new(),
+            nonce: None,
+            issued_at: None,
+            signature: None,
         }
     }
 
+    /// A copy with a fresh nonce, issued now and signed by the buyer's
+    /// `signer`, for sending to sellers.
+    pub fn stamped(&self, signer: &WebhookSigner) -> Result<Self> {
+        let mut rfq = Self {
+            nonce: Some(nonce::generate()),
+            issued_at: Some(Utc::now()),
+            signature: None,
+            ..self.clone()
+        };
+        rfq.signature = Some(signer.sign_stamped(&rfq)?);
+        Ok(rfq)
+    }
+
+    /// Buyer's maximum price for the whole request
+    pub fn budget(&self) -> Money {
+        Money::new(self.max_price, self.currency.clone())
+    }
+
     pub fn validate(&self) -> Result<()> {
-        if self.quantity == 0 {
-            return Err(NegotiationError::Validation("Quantity must be greater than 0".to_string()));
-        }
-        if self.max_price <= 0.0 {
-            return Err(NegotiationError::Validation("Max price must be greater than 0".to_string()));
+        self.field_errors().into_result()
+    }
+}
+
+impl Stamped for RFQ {
+    fn nonce(&self) -> Option<&str> {
+        self.nonce.as_deref()
+    }
+
+    fn issued_at(&self) -> Option<DateTime<Utc>> {
+        self.issued_at
+    }
+
+    fn signature(&self) -> Option<&str> {
+        self.signature.as_deref()
+    }
+}
+
+impl Validate for RFQ {
+    fn validate_fields(&self, errors: &mut FieldErrors) {
+        errors.check(!self.product_id.trim().is_empty(), "product_id", "Product ID must not be empty");
+        errors.check(self.quantity > 0, "quantity", "Quantity must be greater than 0");
+        errors.check(self.max_price > Decimal::ZERO, "max_price", "Max price must be greater than 0");
+        errors.currency("currency", &self.currency);
+        errors.check(self.deadline > Utc::now(), "deadline", "Deadline must be in the future");
+    }
+}
+
+impl Validate for Quote {
+    fn validate_fields(&self, errors: &mut FieldErrors) {
+        errors.check(self.price > Decimal::ZERO, "price", "Price must be greater than 0");
+        errors.currency("currency", &self.currency);
+        errors.check(self.available_quantity > 0, "available_quantity", "Available quantity must be greater than 0");
+        errors.check(self.ttl_seconds > 0, "ttl_seconds", "TTL must be greater than 0");
+        if let QuoteFirmness::BindingWithPenalty { penalty } = self.firmness {
+            errors.check(penalty > Decimal::ZERO, "firmness.penalty", "Binding quote penalty must be greater than 0");
         }
-        if self.deadline <= Utc::now() {
-            return Err(NegotiationError::Validation("Deadline must be in the future".to_string()));
+        for option in &self.shipping_options {
+            errors.check(option.cost >= Decimal::ZERO, "shipping_options.cost", "Shipping cost must not be negative");
+            errors.check(!option.carrier.trim().is_empty(), "shipping_options.carrier", "Carrier must not be empty");
         }
-        Ok(())
+    }
+}
+
+impl Stamped for Quote {
+    fn nonce(&self) -> Option<&str> {
+        self.nonce.as_deref()
+    }
+
+    fn issued_at(&self) -> Option<DateTime<Utc>> {
+        Some(self.created_at)
+    }
+
+    fn signature(&self) -> Option<&str> {
+        self.signature.as_deref()
     }
 }
 
@@ -176,7 +344,7 @@ impl Quote {
     pub fn new(
         rfq_id: TransactionId,
         seller_id: AgentId,
-        price: f64,
+        price: Decimal,
         currency: String,
         available_quantity: u32,
         ttl_seconds: u32,
@@ -190,26 +358,95 @@ impl Quote {
             available_quantity,
             delivery_estimate: None,
             ttl_seconds,
+            firmness: QuoteFirmness::default(),
+            shipping_options: vec![],
             metadata: HashMap::new(),
             created_at: Utc::now(),
+            nonce: Some(nonce::generate()),
+            signature: None,
+        }
+    }
+
+    /// A copy with a fresh nonce, signed by the seller's `signer`, for
+    /// sending to the buyer.
+    pub fn stamped(&self, signer: &WebhookSigner) -> Result<Self> {
+        let mut quote = Self {
+            nonce: Some(nonce::generate()),
+            signature: None,
+            ..self.clone()
+        };
+        quote.signature = Some(signer.sign_stamped(&quote)?);
+        Ok(quote)
+    }
+
+    pub fn amount(&self) -> Money {
+        Money::new(self.price, self.currency.clone())
+    }
+
+    /// Taxes the seller added to the price, none if it added none
+    pub fn tax(&self) -> Result<TaxBreakdown> {
+        Ok(TaxBreakdown::from_metadata(&self.metadata)?.unwrap_or_default())
+    }
+
+    /// The cheapest shipping option, the fastest of those tied
+    pub fn shipping(&self) -> Option<&ShippingOption> {
+        self.shipping_options.iter().min_by(|a, b| a.cost.cmp(&b.cost).then(a.eta_days.cmp(&b.eta_days)))
+    }
+
+    /// What the buyer pays, the landed cost: the price, the taxes on it and
+    /// the cheapest shipping
+    pub fn total(&self) -> Result<Money> {
+        let shipping = self.shipping().map_or(Decimal::ZERO, |option| option.cost);
+        Ok(Money::new(self.price + self.tax()?.total() + shipping, self.currency.clone()))
+    }
+
+    /// Delivery time in days parsed from the free-form estimate, e.g. "3 days",
+    /// "2-4 days" or "1 week". Ranges take the upper bound. Without an
+    /// estimate, the cheapest shipping option's ETA.
+    pub fn delivery_days(&self) -> Option<u32> {
+        let Some(estimate) = &self.delivery_estimate else {
+            return self.shipping().map(|option| option.eta_days);
+        };
+        let estimate = estimate.to_lowercase();
+        let days = estimate
+            .split(|c: char| !c.is_ascii_digit())
+            .filter_map(|number| number.parse::<u32>().ok())
+            .max()?;
+        if estimate.contains("week") {
+            Some(days.saturating_mul(7))
+        } else {
+            Some(days)
         }
     }
 
+    pub fn expires_at(&self) -> DateTime<Utc> {
+        self.created_at + chrono::Duration::seconds(self.ttl_seconds as i64)
+    }
+
     pub fn is_expired(&self) -> bool {
-        Utc::now() > self.created_at + chrono::Duration::seconds(self.ttl_seconds as i64)
+        Utc::now() > self.expires_at()
     }
 
     pub fn validate(&self) -> Result<()> {
-        if self.price <= 0.0 {
-            return Err(NegotiationError::Validation("Price must be greater than 0".to_string()));
-        }
-        if self.available_quantity == 0 {
-            return Err(NegotiationError::Validation("Available quantity must be greater than 0".to_string()));
-        }
-        if self.ttl_seconds == 0 {
-            return Err(NegotiationError::Validation("TTL must be greater than 0".to_string()));
+        self.field_errors().into_result()
+    }
+
+    pub fn with_firmness(mut self, firmness: QuoteFirmness) -> Self {
+        self.firmness = firmness;
+        self
+    }
+
+    pub fn with_shipping_options(mut self, shipping_options: Vec<ShippingOption>) -> Self {
+        self.shipping_options = shipping_options;
+        self
+    }
+
+    /// Penalty owed if the seller reneges on an accepted binding quote
+    pub fn penalty(&self) -> Option<Money> {
+        match self.firmness {
+            QuoteFirmness::BindingWithPenalty { penalty } => Some(Money::new(penalty, self.currency.clone())),
+            _ => None,
         }
-        Ok(())
     }
 }
 
@@ -224,15 +461,34 @@ impl Negotiation {
             product_id: rfq.product_id,
             quantity: rfq.quantity,
             opening_bid: rfq.max_price,
+            currency: rfq.currency,
             close_price: None,
             delta: None,
             status: NegotiationStatus::Pending,
             messages: vec![],
             created_at: Utc::now(),
             updated_at: Utc::now(),
+            delegation_chain: vec![],
         }
     }
 
+    pub fn with_delegation_chain(mut self, chain: Vec<AgentId>) -> Self {
+        self.delegation_chain = chain;
+        self
+    }
+
+    /// Records a message exchanged in this negotiation, stamped `at`.
+    pub fn add_message(&mut self, sender_id: AgentId, message_type: MessageType, content: String, at: DateTime<Utc>) {
+        self.messages.push(NegotiationMessage {
+            id: Uuid::new_v4(),
+            negotiation_id: self.id,
+            sender_id,
+            content,
+            message_type,
+            created_at: at,
+        });
+    }
+
     pub fn add_quote(&mut self, quote: &Quote) -> Result<()> {
         if self.quote_id.is_some() {
             return Err(NegotiationError::Negotiation("Quote already exists for this negotiation".to_string()));
@@ -243,7 +499,18 @@ impl Negotiation {
         Ok(())
     }
 
-    pub fn accept(&mut self, final_price: f64) -> Result<()> {
+    /// Replaces the current quote with the seller's answer to a counter offer.
+    pub fn add_counter_quote(&mut self, quote: &Quote) -> Result<()> {
+        if self.status != NegotiationStatus::Quoted && self.status != NegotiationStatus::Negotiating {
+            return Err(NegotiationError::Negotiation("Cannot counter negotiation in current state".to_string()));
+        }
+        self.quote_id = Some(quote.id);
+        self.status = NegotiationStatus::Negotiating;
+        self.updated_at = Utc::now();
+        Ok(())
+    }
+
+    pub fn accept(&mut self, final_price: Decimal) -> Result<()> {
         if self.status != NegotiationStatus::Quoted && self.status != NegotiationStatus::Negotiating {
             return Err(NegotiationError::Negotiation("Cannot accept negotiation in current state".to_string()));
         }
@@ -263,6 +530,38 @@ impl Negotiation {
         Ok(())
     }
 
+    /// Ends a negotiation still open when its RFQ deadline passes.
+    pub fn expire(&mut self) -> Result<()> {
+        if !matches!(self.status, NegotiationStatus::Pending | NegotiationStatus::Quoted | NegotiationStatus::Negotiating) {
+            return Err(NegotiationError::Negotiation("Only open negotiations can expire".to_string()));
+        }
+        self.status = NegotiationStatus::Expired;
+        self.updated_at = Utc::now();
+        Ok(())
+    }
+
+    /// Calls off an accepted deal before it settles.
+    pub fn cancel(&mut self) -> Result<()> {
+        if self.status != NegotiationStatus::Accepted {
+            return Err(NegotiationError::Negotiation("Only accepted, unsettled negotiations can be cancelled".to_string()));
+        }
+        self.status = NegotiationStatus::Cancelled;
+        self.updated_at = Utc::now();
+        Ok(())
+    }
+
+    /// Replaces the terms of an accepted deal with renegotiated ones.
+    pub fn amend(&mut self, close_price: Decimal, quantity: u32) -> Result<()> {
+        if self.status != NegotiationStatus::Accepted {
+            return Err(NegotiationError::Negotiation("Only accepted, unsettled negotiations can be renegotiated".to_string()));
+        }
+        self.close_price = Some(close_price);
+        self.delta = Some(close_price - self.opening_bid);
+        self.quantity = quantity;
+        self.updated_at = Utc::now();
+        Ok(())
+    }
+
     pub fn settle(&mut self) -> Result<()> {
         if self.status != NegotiationStatus::Accepted {
             return Err(NegotiationError::Negotiation("Cannot settle unaccepted negotiation".to_string()));
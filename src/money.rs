@@ -0,0 +1,132 @@
+//! Exact monetary amounts.
+//!
+//! Amounts are `rust_decimal::Decimal` so negotiation deltas and settlement
+//! totals never pick up binary floating point error. On the wire they are
+//! still plain JSON numbers (and strings are accepted), so payloads written
+//! before the switch from `f64` deserialize unchanged.
+
+use crate::error::{NegotiationError, Result};
+use rust_decimal::prelude::{FromPrimitive, ToPrimitive};
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Digits after the decimal point in the currency's minor unit.
+pub fn minor_units(currency: &str) -> u32 {
+    match currency {
+        "JPY" | "KRW" | "VND" | "CLP" => 0,
+        "BHD" | "KWD" | "OMR" => 3,
+        // Satoshis
+        "BTC" => 8,
+        // Lamports
+        "SOL" => 9,
+        _ => 2,
+    }
+}
+
+/// Converts a float from an external source (exchange-rate APIs, legacy rows)
+/// into a decimal.
+pub fn decimal_from_f64(value: f64) -> Result<Decimal> {
+    Decimal::from_f64(value)
+        .ok_or_else(|| NegotiationError::Validation(format!("Amount is not representable as a decimal: {}", value)))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Money {
+    pub amount: Decimal,
+    pub currency: String,
+}
+
+impl Money {
+    pub fn new(amount: Decimal, currency: impl Into<String>) -> Self {
+        Self {
+            amount,
+            currency: currency.into(),
+        }
+    }
+
+    pub fn zero(currency: impl Into<String>) -> Self {
+        Self::new(Decimal::ZERO, currency)
+    }
+
+    pub fn is_positive(&self) -> bool {
+        self.amount > Decimal::ZERO
+    }
+
+    pub fn checked_add(&self, other: &Money) -> Result<Money> {
+        self.ensure_same_currency(other)?;
+        Ok(Money::new(self.amount + other.amount, self.currency.clone()))
+    }
+
+    pub fn checked_sub(&self, other: &Money) -> Result<Money> {
+        self.ensure_same_currency(other)?;
+        Ok(Money::new(self.amount - other.amount, self.currency.clone()))
+    }
+
+    pub fn times(&self, factor: Decimal) -> Money {
+        Money::new(self.amount * factor, self.currency.clone())
+    }
+
+    /// Rounds half away from zero to the currency's minor unit.
+    pub fn round_to_minor_units(&self) -> Money {
+        let amount = self.amount
+            .round_dp_with_strategy(minor_units(&self.currency), RoundingStrategy::MidpointAwayFromZero);
+        Money::new(amount, self.currency.clone())
+    }
+
+    /// Amount in minor units (cents), as payment processors expect.
+    pub fn to_minor_units(&self) -> Result<i64> {
+        let scale = Decimal::from(10i64.pow(minor_units(&self.currency)));
+        (self.round_to_minor_units().amount * scale).to_i64()
+            .ok_or_else(|| NegotiationError::Validation(format!("Amount out of range: {}", self)))
+    }
+
+    fn ensure_same_currency(&self, other: &Money) -> Result<()> {
+        if self.currency == other.currency {
+            Ok(())
+        } else {
+            Err(NegotiationError::Currency(format!(
+                "Currency mismatch: {} vs {}", self.currency, other.currency
+            )))
+        }
+    }
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.amount, self.currency)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn usd(amount: &str) -> Money {
+        Money::new(Decimal::from_str(amount).unwrap(), "USD")
+    }
+
+    #[test]
+    fn test_arithmetic_is_exact() {
+        let total = usd("0.1").checked_add(&usd("0.2")).unwrap();
+        assert_eq!(total, usd("0.3"));
+        assert_eq!(usd("2499.99").times(Decimal::from(3)), usd("7499.97"));
+        assert!(usd("1").checked_add(&Money::new(Decimal::ONE, "EUR")).is_err());
+    }
+
+    #[test]
+    fn test_minor_unit_rounding() {
+        assert_eq!(usd("10.005").round_to_minor_units(), usd("10.01"));
+        assert_eq!(usd("19.99").to_minor_units().unwrap(), 1999);
+        assert_eq!(Money::new(Decimal::from_str("1500.4").unwrap(), "JPY").to_minor_units().unwrap(), 1500);
+    }
+
+    #[test]
+    fn test_legacy_json_numbers_deserialize() {
+        let money: Money = serde_json::from_str(r#"{"amount": 2499.99, "currency": "USD"}"#).unwrap();
+        assert_eq!(money, usd("2499.99"));
+        let money: Money = serde_json::from_str(r#"{"amount": "2499.99", "currency": "USD"}"#).unwrap();
+        assert_eq!(money, usd("2499.99"));
+    }
+}
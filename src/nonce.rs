@@ -0,0 +1,216 @@
+//! Replay protection for RFQs and quotes.
+//!
+//! Buyers send every RFQ with a fresh `nonce` and the time it was
+//! `issued_at`; sellers give every quote a nonce alongside its `created_at`.
+//! Both are [`Stamped`]: signed, nonce and time included, with the sender's
+//! agent key, so a captured message can't be stamped again and passed off as
+//! new. A [`ReplayGuard`] checks the signature against the key the sender
+//! registered, then claims the nonce in the shared state for `[server]
+//! replay_window_seconds`, so a message submitted again is refused by every
+//! replica, and refuses messages issued outside that window so nonces don't
+//! have to be kept for good. Clocks may disagree by `clock_skew_seconds`
+//! either way. Unsigned messages from peers that predate signing are only
+//! let through when `require_nonces` is turned off.
+
+use crate::{
+    config::ServerConfig,
+    error::{NegotiationError, Result},
+    recovery,
+    shared_state::SharedState,
+};
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+
+pub const DEFAULT_REPLAY_WINDOW_SECONDS: u64 = 300;
+pub const DEFAULT_CLOCK_SKEW_SECONDS: u64 = 30;
+const MAX_NONCE_LENGTH: usize = 128;
+const CLAIMED: &str = "1";
+
+/// A random 128-bit nonce, hex encoded
+pub fn generate() -> String {
+    hex::encode(rand::random::<[u8; 16]>())
+}
+
+/// A message sent with a nonce and issue time, signed by its sender
+pub trait Stamped: Serialize {
+    fn nonce(&self) -> Option<&str>;
+    fn issued_at(&self) -> Option<DateTime<Utc>>;
+    /// Base64 Ed25519 signature over [`signed_payload`](Self::signed_payload)
+    fn signature(&self) -> Option<&str>;
+
+    /// What's signed: the message's JSON without its `signature`, with keys
+    /// in order so sender and receiver agree on the bytes.
+    fn signed_payload(&self) -> Result<Vec<u8>> {
+        let mut message = serde_json::to_value(self)?;
+        if let Some(fields) = message.as_object_mut() {
+            fields.remove("signature");
+        }
+        Ok(serde_json::to_vec(&message)?)
+    }
+}
+
+#[derive(Clone)]
+pub struct ReplayGuard {
+    state: SharedState,
+    window: Duration,
+    skew: Duration,
+    required: bool,
+}
+
+impl ReplayGuard {
+    pub fn new(state: SharedState) -> Self {
+        Self {
+            state,
+            window: Duration::seconds(DEFAULT_REPLAY_WINDOW_SECONDS as i64),
+            skew: Duration::seconds(DEFAULT_CLOCK_SKEW_SECONDS as i64),
+            required: false,
+        }
+    }
+
+    pub fn from_config(state: SharedState, config: &ServerConfig) -> Self {
+        Self {
+            state,
+            window: Duration::seconds(config.replay_window_seconds.unwrap_or(DEFAULT_REPLAY_WINDOW_SECONDS) as i64),
+            skew: Duration::seconds(config.clock_skew_seconds.unwrap_or(DEFAULT_CLOCK_SKEW_SECONDS) as i64),
+            required: config.require_nonces,
+        }
+    }
+
+    /// Claims `nonce` for a message of kind `scope` issued at `issued_at`.
+    /// Fails if it was claimed before or the message is too old or too far
+    /// in the future to tell.
+    pub async fn check(&self, scope: &str, nonce: Option<&str>, issued_at: Option<DateTime<Utc>>) -> Result<()> {
+        self.check_at(scope, nonce, issued_at, Utc::now()).await
+    }
+
+    /// [`check`](Self::check) against the clock reading `now`.
+    pub async fn check_at(
+        &self,
+        scope: &str,
+        nonce: Option<&str>,
+        issued_at: Option<DateTime<Utc>>,
+        now: DateTime<Utc>,
+    ) -> Result<()> {
+        let (Some(nonce), Some(issued_at)) = (nonce, issued_at) else {
+            if self.required {
+                return Err(NegotiationError::Auth(format!("The {} has no nonce and issue time", scope)));
+            }
+            return Ok(());
+        };
+        if nonce.is_empty() || nonce.len() > MAX_NONCE_LENGTH {
+            return Err(NegotiationError::Validation(format!(
+                "Nonces must be 1 to {} characters", MAX_NONCE_LENGTH
+            )));
+        }
+        if issued_at > now + self.skew {
+            return Err(NegotiationError::Auth(format!("The {} was issued in the future, at {}", scope, issued_at)));
+        }
+        if issued_at < now - self.window - self.skew {
+            return Err(NegotiationError::Auth(format!("The {} was issued at {}, too long ago", scope, issued_at)));
+        }
+
+        // Kept until the message would be refused as too old anyway
+        let ttl = (issued_at + self.window + self.skew * 2 - now).to_std().unwrap_or_default();
+        if !self.state.set_if_absent(&key(scope, nonce), CLAIMED, ttl).await? {
+            return Err(NegotiationError::Auth(format!("The {} with nonce {} was already received", scope, nonce)));
+        }
+        Ok(())
+    }
+
+    /// Checks that `message` was signed with the sender's `public_key`, then
+    /// claims its nonce as [`check`](Self::check) does. Unsigned messages
+    /// are refused unless nonces aren't required.
+    pub async fn check_signed<M: Stamped>(&self, scope: &str, message: &M, public_key: Option<&str>) -> Result<()> {
+        self.check_signed_at(scope, message, public_key, Utc::now()).await
+    }
+
+    /// [`check_signed`](Self::check_signed) against the clock reading `now`.
+    pub async fn check_signed_at<M: Stamped>(
+        &self,
+        scope: &str,
+        message: &M,
+        public_key: Option<&str>,
+        now: DateTime<Utc>,
+    ) -> Result<()> {
+        match (message.signature(), public_key) {
+            (Some(signature), Some(public_key)) => {
+                recovery::verify_signature(public_key, &message.signed_payload()?, signature)
+                    .map_err(|_| NegotiationError::Auth(format!("The {}'s signature doesn't match its sender's key", scope)))?;
+            }
+            (Some(_), None) => {
+                return Err(NegotiationError::Auth(format!("The {}'s sender has no key to check its signature with", scope)));
+            }
+            (None, _) if self.required => {
+                return Err(NegotiationError::Auth(format!("The {} isn't signed", scope)));
+            }
+            (None, _) => {}
+        }
+        self.check_at(scope, message.nonce(), message.issued_at(), now).await
+    }
+
+    /// Gives a claimed nonce back, e.g. when handling the message failed and
+    /// the sender may retry it.
+    pub async fn release(&self, scope: &str, nonce: Option<&str>) -> Result<()> {
+        if let Some(nonce) = nonce {
+            self.state.delete_if(&key(scope, nonce), CLAIMED).await?;
+        }
+        Ok(())
+    }
+}
+
+fn key(scope: &str, nonce: &str) -> String {
+    format!("nonce:{}:{}", scope, nonce)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{model::RFQ, webhook::WebhookSigner};
+    use rust_decimal::Decimal;
+
+    #[tokio::test]
+    async fn test_replays_and_stale_messages_are_refused() {
+        let guard = ReplayGuard::new(SharedState::in_memory());
+        let now = Utc::now();
+        let nonce = generate();
+
+        guard.check_at("rfq", Some(&nonce), Some(now), now).await.unwrap();
+        assert!(guard.check_at("rfq", Some(&nonce), Some(now), now).await.is_err());
+        // Nonces are claimed per kind of message
+        guard.check_at("quote", Some(&nonce), Some(now), now).await.unwrap();
+        // Given back, the nonce can be used again
+        guard.release("rfq", Some(&nonce)).await.unwrap();
+        guard.check_at("rfq", Some(&nonce), Some(now), now).await.unwrap();
+
+        // Clocks may disagree by the skew either way
+        guard.check_at("rfq", Some(&generate()), Some(now + Duration::seconds(20)), now).await.unwrap();
+        assert!(guard.check_at("rfq", Some(&generate()), Some(now + Duration::minutes(5)), now).await.is_err());
+        guard.check_at("rfq", Some(&generate()), Some(now - Duration::seconds(320)), now).await.unwrap();
+        assert!(guard.check_at("rfq", Some(&generate()), Some(now - Duration::minutes(10)), now).await.is_err());
+
+        // Older peers send no nonce, which is only refused when nonces are required
+        guard.check_at("rfq", None, None, now).await.unwrap();
+        let strict = ReplayGuard { required: true, ..guard.clone() };
+        assert!(strict.check_at("rfq", None, None, now).await.is_err());
+        assert!(strict.check_at("rfq", Some(""), Some(now), now).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_signed_messages_cant_be_stamped_again() {
+        let guard = ReplayGuard { required: true, ..ReplayGuard::new(SharedState::in_memory()) };
+        let buyer = WebhookSigner::generate(uuid::Uuid::new_v4());
+        let key = buyer.public_key();
+        let rfq = RFQ::new(buyer.agent_id(), "laptop".to_string(), 1, Decimal::ONE_HUNDRED, "USD".to_string(), Utc::now());
+        let now = Utc::now();
+
+        let sent = rfq.stamped(&buyer).unwrap();
+        guard.check_signed_at("rfq", &sent, Some(&key), now).await.unwrap();
+        // Captured and sent again under a new nonce, the signature no longer fits
+        let restamped = RFQ { nonce: Some(generate()), issued_at: Some(now), ..sent.clone() };
+        assert!(guard.check_signed_at("rfq", &restamped, Some(&key), now).await.is_err());
+        let stranger = WebhookSigner::generate(buyer.agent_id()).public_key();
+        assert!(guard.check_signed_at("rfq", &rfq.stamped(&buyer).unwrap(), Some(&stranger), now).await.is_err());
+        assert!(guard.check_signed_at("rfq", &rfq.stamped(&buyer).unwrap(), None, now).await.is_err());
+        assert!(guard.check_signed_at("rfq", &RFQ { signature: None, ..rfq.stamped(&buyer).unwrap() }, Some(&key), now).await.is_err());
+    }
+}
@@ -0,0 +1,200 @@
+//! Obligations sellers take on when a firm or binding quote is accepted, and
+//! the stakes they can post to back them.
+//!
+//! Accepting a firm quote opens an obligation with no penalty; accepting a
+//! `BindingWithPenalty` quote opens one for the quoted penalty. It is
+//! fulfilled once the seller delivers, or breached if they renege: the seller
+//! loses reputation and, for binding quotes, as much of the penalty as their
+//! stake in that currency covers is slashed.
+
+use crate::{
+    database::Database,
+    error::{NegotiationError, Result},
+    model::{Negotiation, Quote, QuoteFirmness},
+    money::Money,
+    AgentId, TransactionId,
+};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+/// Reputation lost for reneging on an accepted firm quote
+pub const FIRM_RENEGE_REPUTATION_PENALTY: i32 = -10;
+/// Reputation lost for reneging on an accepted binding quote
+pub const BINDING_RENEGE_REPUTATION_PENALTY: i32 = -20;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ObligationStatus {
+    Open,
+    Fulfilled,
+    Breached,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PenaltyObligation {
+    pub id: uuid::Uuid,
+    pub negotiation_id: TransactionId,
+    pub quote_id: TransactionId,
+    pub seller_id: AgentId,
+    pub buyer_id: AgentId,
+    pub penalty: Decimal,
+    pub currency: String,
+    pub status: ObligationStatus,
+    /// Amount taken from the seller's stake when the obligation was breached
+    pub slashed: Decimal,
+    pub breach_reason: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub resolved_at: Option<DateTime<Utc>>,
+}
+
+impl PenaltyObligation {
+    pub fn penalty_amount(&self) -> Money {
+        Money::new(self.penalty, self.currency.clone())
+    }
+
+    /// Only binding quotes carry a penalty
+    pub fn is_binding(&self) -> bool {
+        self.penalty > Decimal::ZERO
+    }
+
+    /// Reputation the seller loses for breaching this obligation
+    pub fn reputation_penalty(&self) -> i32 {
+        if self.is_binding() {
+            BINDING_RENEGE_REPUTATION_PENALTY
+        } else {
+            FIRM_RENEGE_REPUTATION_PENALTY
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ObligationService {
+    database: Database,
+}
+
+impl ObligationService {
+    pub fn new(database: Database) -> Self {
+        Self { database }
+    }
+
+    /// Opens an obligation for an accepted quote. Indicative quotes commit
+    /// the seller to nothing and return `None`.
+    pub async fn open(&self, negotiation: &Negotiation, quote: &Quote) -> Result<Option<PenaltyObligation>> {
+        if quote.firmness == QuoteFirmness::Indicative {
+            return Ok(None);
+        }
+        let penalty = quote.penalty().unwrap_or_else(|| Money::zero(quote.currency.clone()));
+
+        let obligation = PenaltyObligation {
+            id: uuid::Uuid::new_v4(),
+            negotiation_id: negotiation.id,
+            quote_id: quote.id,
+            seller_id: quote.seller_id,
+            buyer_id: negotiation.buyer_id,
+            penalty: penalty.amount,
+            currency: penalty.currency,
+            status: ObligationStatus::Open,
+            slashed: Decimal::ZERO,
+            breach_reason: None,
+            created_at: Utc::now(),
+            resolved_at: None,
+        };
+        self.database.create_obligation(&obligation).await?;
+        tracing::info!("Opened obligation for negotiation {} with a {} penalty", negotiation.id, obligation.penalty_amount());
+        Ok(Some(obligation))
+    }
+
+    pub async fn get(&self, negotiation_id: TransactionId) -> Result<Option<PenaltyObligation>> {
+        self.database.get_obligation(negotiation_id).await
+    }
+
+    /// Marks the seller as having honoured the quote.
+    pub async fn fulfil(&self, negotiation_id: TransactionId) -> Result<PenaltyObligation> {
+        let mut obligation = self.open_obligation(negotiation_id).await?;
+        obligation.status = ObligationStatus::Fulfilled;
+        obligation.resolved_at = Some(Utc::now());
+        self.database.update_obligation(&obligation).await?;
+        Ok(obligation)
+    }
+
+    /// Records that the seller reneged and slashes their stake, up to the
+    /// penalty. Applying the reputation penalty is left to the caller.
+    pub async fn breach(&self, negotiation_id: TransactionId, reason: &str) -> Result<PenaltyObligation> {
+        let mut obligation = self.open_obligation(negotiation_id).await?;
+        obligation.status = ObligationStatus::Breached;
+        obligation.breach_reason = Some(reason.to_string());
+        obligation.resolved_at = Some(Utc::now());
+        obligation.slashed = self.database.breach_obligation(&obligation).await?;
+
+        if obligation.is_binding() && obligation.slashed < obligation.penalty {
+            tracing::warn!(
+                "Seller {} stake covered {} of the {} penalty for negotiation {}",
+                obligation.seller_id, obligation.slashed, obligation.penalty_amount(), negotiation_id
+            );
+        }
+        Ok(obligation)
+    }
+
+    /// Adds to an agent's stake and returns the new balance.
+    pub async fn deposit_stake(&self, agent_id: AgentId, amount: &Money) -> Result<Money> {
+        if amount.amount <= Decimal::ZERO {
+            return Err(NegotiationError::Validation("Stake deposit must be greater than 0".to_string()));
+        }
+        let balance = self.database.deposit_stake(agent_id, amount).await?;
+        Ok(Money::new(balance, amount.currency.clone()))
+    }
+
+    pub async fn get_stake(&self, agent_id: AgentId, currency: &str) -> Result<Money> {
+        let balance = self.database.get_stake(agent_id, currency).await?;
+        Ok(Money::new(balance, currency))
+    }
+
+    async fn open_obligation(&self, negotiation_id: TransactionId) -> Result<PenaltyObligation> {
+        let obligation = self.get(negotiation_id).await?
+            .ok_or_else(|| NegotiationError::Validation(format!("No obligation for negotiation {}", negotiation_id)))?;
+        if obligation.status != ObligationStatus::Open {
+            return Err(NegotiationError::Validation(format!(
+                "Obligation for negotiation {} is already {:?}", negotiation_id, obligation.status
+            )));
+        }
+        Ok(obligation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::RFQ;
+    use tempfile::NamedTempFile;
+
+    #[tokio::test]
+    async fn test_breach_slashes_stake_up_to_penalty() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let database = Database::new(&format!("sqlite://{}", temp_file.path().to_string_lossy())).await.unwrap();
+        let obligations = ObligationService::new(database);
+
+        let seller_id = uuid::Uuid::new_v4();
+        let rfq = RFQ::new(uuid::Uuid::new_v4(), "laptop-001".to_string(), 1, Decimal::from(2500), "USD".to_string(), Utc::now() + chrono::Duration::hours(1));
+        let negotiation = Negotiation::new(rfq, seller_id);
+        let quote = Quote::new(negotiation.rfq_id, seller_id, Decimal::from(2400), "USD".to_string(), 1, 3600)
+            .with_firmness(QuoteFirmness::BindingWithPenalty { penalty: Decimal::from(100) });
+
+        // Indicative quotes commit the seller to nothing
+        let indicative = quote.clone().with_firmness(QuoteFirmness::Indicative);
+        assert!(obligations.open(&negotiation, &indicative).await.unwrap().is_none());
+
+        obligations.deposit_stake(seller_id, &Money::new(Decimal::from(60), "USD")).await.unwrap();
+        obligations.open(&negotiation, &quote).await.unwrap().unwrap();
+
+        let breached = obligations.breach(negotiation.id, "Withdrew after acceptance").await.unwrap();
+        assert_eq!(breached.status, ObligationStatus::Breached);
+        assert_eq!(breached.reputation_penalty(), BINDING_RENEGE_REPUTATION_PENALTY);
+        assert_eq!(breached.slashed, Decimal::from(60));
+        assert_eq!(obligations.get_stake(seller_id, "USD").await.unwrap().amount, Decimal::ZERO);
+
+        // A resolved obligation can't be breached or fulfilled again
+        assert!(obligations.breach(negotiation.id, "again").await.is_err());
+        assert!(obligations.fulfil(negotiation.id).await.is_err());
+    }
+}
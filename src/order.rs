@@ -0,0 +1,149 @@
+//! Orders for paid negotiations and their fulfillment.
+//!
+//! An order opens once the buyer's payment for a negotiation is placed,
+//! including one held in escrow or due on delivery, and moves from
+//! `Processing` to `Shipped` when the seller posts a carrier and tracking
+//! number, then to `Delivered` when the buyer confirms receipt. Sellers may
+//! correct the tracking number until the order is delivered.
+//!
+//! The settlement service drives payments off these transitions: shipping an
+//! escrow-paid order starts the escrow's confirmation window, and delivering
+//! it releases the hold or collects a pay-on-delivery payment.
+
+use crate::{
+    database::Database,
+    error::{NegotiationError, Result},
+    model::Negotiation,
+    settlement::ShipmentProof,
+    AgentId, TransactionId,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FulfillmentStatus {
+    Processing,
+    Shipped,
+    Delivered,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Order {
+    pub id: uuid::Uuid,
+    pub negotiation_id: TransactionId,
+    pub payment_id: String,
+    pub buyer_id: AgentId,
+    pub seller_id: AgentId,
+    pub product_id: String,
+    pub quantity: u32,
+    pub status: FulfillmentStatus,
+    /// Carrier and tracking number, once shipped
+    pub shipment: Option<ShipmentProof>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub shipped_at: Option<DateTime<Utc>>,
+    pub delivered_at: Option<DateTime<Utc>>,
+}
+
+impl Order {
+    /// Records the shipment, or corrects the tracking of one already shipped.
+    pub fn ship(&mut self, shipment: ShipmentProof) -> Result<()> {
+        if shipment.tracking_number.trim().is_empty() {
+            return Err(NegotiationError::Validation("Tracking number must not be empty".to_string()));
+        }
+        let now = Utc::now();
+        match self.status {
+            FulfillmentStatus::Processing => {
+                self.status = FulfillmentStatus::Shipped;
+                self.shipped_at = Some(now);
+            }
+            FulfillmentStatus::Shipped => {}
+            FulfillmentStatus::Delivered => {
+                return Err(NegotiationError::Validation(format!("Order {} is already delivered", self.id)));
+            }
+        }
+        self.shipment = Some(shipment);
+        self.updated_at = now;
+        Ok(())
+    }
+
+    pub fn deliver(&mut self) -> Result<()> {
+        if self.status != FulfillmentStatus::Shipped {
+            return Err(NegotiationError::Validation(format!(
+                "Order {} is {:?}; only shipped orders can be delivered", self.id, self.status
+            )));
+        }
+        let now = Utc::now();
+        self.status = FulfillmentStatus::Delivered;
+        self.delivered_at = Some(now);
+        self.updated_at = now;
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+pub struct OrderService {
+    database: Database,
+}
+
+impl OrderService {
+    pub fn new(database: Database) -> Self {
+        Self { database }
+    }
+
+    /// Opens the order for a negotiation paid by `payment_id`. Opening it
+    /// again returns the order already open.
+    pub async fn open(&self, negotiation: &Negotiation, payment_id: &str) -> Result<Order> {
+        if let Some(order) = self.for_negotiation(negotiation.id).await? {
+            return Ok(order);
+        }
+        let now = Utc::now();
+        let order = Order {
+            id: uuid::Uuid::new_v4(),
+            negotiation_id: negotiation.id,
+            payment_id: payment_id.to_string(),
+            buyer_id: negotiation.buyer_id,
+            seller_id: negotiation.seller_id,
+            product_id: negotiation.product_id.clone(),
+            quantity: negotiation.quantity,
+            status: FulfillmentStatus::Processing,
+            shipment: None,
+            created_at: now,
+            updated_at: now,
+            shipped_at: None,
+            delivered_at: None,
+        };
+        self.database.create_order(&order).await?;
+        tracing::info!("Opened order {} for negotiation {}", order.id, negotiation.id);
+        Ok(order)
+    }
+
+    pub async fn get(&self, order_id: uuid::Uuid) -> Result<Order> {
+        self.database.get_order(order_id).await?
+            .ok_or_else(|| NegotiationError::NotFound(format!("Order {}", order_id)))
+    }
+
+    pub async fn for_negotiation(&self, negotiation_id: TransactionId) -> Result<Option<Order>> {
+        self.database.get_negotiation_order(negotiation_id).await
+    }
+
+    pub async fn for_payment(&self, payment_id: &str) -> Result<Option<Order>> {
+        self.database.get_payment_order(payment_id).await
+    }
+
+    /// A buyer's orders, newest first
+    pub async fn for_buyer(&self, buyer_id: AgentId) -> Result<Vec<Order>> {
+        self.database.get_buyer_orders(buyer_id).await
+    }
+
+    /// Pay-on-delivery orders shipped at or before `cutoff` and not yet
+    /// confirmed delivered
+    pub async fn uncollected_shipped_before(&self, cutoff: DateTime<Utc>) -> Result<Vec<Order>> {
+        self.database.get_uncollected_delivery_orders(cutoff).await
+    }
+
+    pub async fn update(&self, order: &Order) -> Result<()> {
+        self.database.update_order(order).await
+    }
+}
@@ -0,0 +1,429 @@
+//! Seller pricing policies.
+//!
+//! A policy turns the context of an RFQ (product, quantity, buyer reputation,
+//! time of day) into a multiplier on the product's base price. Rules compose
+//! by multiplying their factors, and rule sets can be configured per product
+//! or category in config.toml.
+//!
+//! [`PriceFloors`] bound what the rules may produce. A product's minimum
+//! advertised price (MAP) lifts the prices a seller publishes: quotes to RFQs,
+//! demand offers, rate cards and listings. Its hard floor also bounds counter
+//! quotes, which are rejected rather than lifted so a negotiation never
+//! settles below it, however the offer was drafted.
+
+use crate::{
+    calendar::BusinessCalendar,
+    config::{PriceFloorConfig, PriceFloorsConfig, PricingConfig, PricingRuleConfig},
+    error::{NegotiationError, Result},
+    model::Product,
+    money::Money,
+};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+
+/// What a policy may look at when pricing a quote.
+pub struct PricingContext<'a> {
+    pub product: &'a Product,
+    pub quantity: u32,
+    pub buyer_reputation: u32,
+    pub at: DateTime<Utc>,
+    pub calendar: &'a BusinessCalendar,
+}
+
+pub trait PricingPolicy: Send + Sync {
+    /// Multiplier applied to the base price of the order.
+    fn factor(&self, context: &PricingContext) -> Decimal;
+}
+
+/// Uses the multiplier of the highest threshold reached.
+#[derive(Debug, Clone)]
+struct Tiers(Vec<(u32, Decimal)>);
+
+impl Tiers {
+    fn new(mut tiers: Vec<(u32, Decimal)>) -> Result<Self> {
+        for (_, multiplier) in &tiers {
+            validate_multiplier(*multiplier)?;
+        }
+        tiers.sort_by_key(|(threshold, _)| *threshold);
+        Ok(Self(tiers))
+    }
+
+    fn multiplier(&self, value: u32) -> Decimal {
+        self.0.iter()
+            .rev()
+            .find(|(threshold, _)| value >= *threshold)
+            .map_or(Decimal::ONE, |(_, multiplier)| *multiplier)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct VolumeTiers(Tiers);
+
+impl PricingPolicy for VolumeTiers {
+    fn factor(&self, context: &PricingContext) -> Decimal {
+        self.0.multiplier(context.quantity)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ReputationDiscount(Tiers);
+
+impl PricingPolicy for ReputationDiscount {
+    fn factor(&self, context: &PricingContext) -> Decimal {
+        self.0.multiplier(context.buyer_reputation)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct InventoryPressure {
+    min_stock: u32,
+    multiplier: Decimal,
+}
+
+impl PricingPolicy for InventoryPressure {
+    fn factor(&self, context: &PricingContext) -> Decimal {
+        if context.product.stock_quantity >= self.min_stock {
+            self.multiplier
+        } else {
+            Decimal::ONE
+        }
+    }
+}
+
+/// Judged in the seller's timezone via its business calendar.
+#[derive(Debug, Clone)]
+pub struct TimeOfDay {
+    business_hours: Decimal,
+    after_hours: Decimal,
+}
+
+impl PricingPolicy for TimeOfDay {
+    fn factor(&self, context: &PricingContext) -> Decimal {
+        if context.calendar.is_business_hours(context.at) {
+            self.business_hours
+        } else {
+            self.after_hours
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Flat(Decimal);
+
+impl PricingPolicy for Flat {
+    fn factor(&self, _context: &PricingContext) -> Decimal {
+        self.0
+    }
+}
+
+/// Multiplies the factors of its rules.
+#[derive(Default)]
+pub struct CompositePolicy {
+    rules: Vec<Box<dyn PricingPolicy>>,
+}
+
+impl CompositePolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_rule(mut self, rule: impl PricingPolicy + 'static) -> Self {
+        self.rules.push(Box::new(rule));
+        self
+    }
+
+    pub fn from_rules(rules: &[PricingRuleConfig]) -> Result<Self> {
+        let mut policy = Self::new();
+        for rule in rules {
+            policy.rules.push(rule_from_config(rule)?);
+        }
+        Ok(policy)
+    }
+}
+
+impl PricingPolicy for CompositePolicy {
+    fn factor(&self, context: &PricingContext) -> Decimal {
+        self.rules.iter().map(|rule| rule.factor(context)).product()
+    }
+}
+
+/// Picks the rule set for a product: by product id, then category, then the
+/// default set.
+pub struct ConfiguredPricingPolicy {
+    default: CompositePolicy,
+    categories: HashMap<String, CompositePolicy>,
+    products: HashMap<String, CompositePolicy>,
+}
+
+impl ConfiguredPricingPolicy {
+    pub fn from_config(config: &PricingConfig) -> Result<Self> {
+        let build = |sets: &HashMap<String, Vec<PricingRuleConfig>>| {
+            sets.iter()
+                .map(|(key, rules)| Ok((key.clone(), CompositePolicy::from_rules(rules)?)))
+                .collect::<Result<HashMap<_, _>>>()
+        };
+
+        Ok(Self {
+            default: CompositePolicy::from_rules(&config.default)?,
+            categories: build(&config.categories)?,
+            products: build(&config.products)?,
+        })
+    }
+}
+
+impl PricingPolicy for ConfiguredPricingPolicy {
+    fn factor(&self, context: &PricingContext) -> Decimal {
+        self.products.get(&context.product.id)
+            .or_else(|| self.categories.get(&context.product.category))
+            .unwrap_or(&self.default)
+            .factor(context)
+    }
+}
+
+/// Which floor a price broke
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FloorRule {
+    MinPrice,
+    MapPrice,
+}
+
+impl FloorRule {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FloorRule::MinPrice => "min_price",
+            FloorRule::MapPrice => "map_price",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PriceFloor {
+    min_price: Option<Decimal>,
+    map_price: Option<Decimal>,
+}
+
+impl PriceFloor {
+    fn from_config(config: &PriceFloorConfig) -> Result<Self> {
+        for price in config.min_price.iter().chain(&config.map_price) {
+            if price.is_sign_negative() {
+                return Err(NegotiationError::Config(format!("Price floor must not be negative: {}", price)));
+            }
+        }
+        Ok(Self {
+            min_price: config.min_price,
+            map_price: config.map_price,
+        })
+    }
+
+    /// Lowest unit price a published price may show, and the rule setting it.
+    fn advertised(&self) -> Option<(Decimal, FloorRule)> {
+        match (self.min_price, self.map_price) {
+            (Some(min_price), Some(map_price)) if min_price > map_price => Some((min_price, FloorRule::MinPrice)),
+            (_, Some(map_price)) => Some((map_price, FloorRule::MapPrice)),
+            (Some(min_price), None) => Some((min_price, FloorRule::MinPrice)),
+            (None, None) => None,
+        }
+    }
+}
+
+/// Price floors by product id, then category.
+#[derive(Debug, Clone, Default)]
+pub struct PriceFloors {
+    categories: HashMap<String, PriceFloor>,
+    products: HashMap<String, PriceFloor>,
+}
+
+impl PriceFloors {
+    pub fn from_config(config: &PriceFloorsConfig) -> Result<Self> {
+        let build = |floors: &HashMap<String, PriceFloorConfig>| {
+            floors.iter()
+                .map(|(key, floor)| Ok((key.clone(), PriceFloor::from_config(floor)?)))
+                .collect::<Result<HashMap<_, _>>>()
+        };
+
+        Ok(Self {
+            categories: build(&config.categories)?,
+            products: build(&config.products)?,
+        })
+    }
+
+    pub fn for_product(&self, product: &Product) -> Option<&PriceFloor> {
+        self.products.get(&product.id)
+            .or_else(|| self.categories.get(&product.category))
+    }
+
+    /// Raises a published price for `quantity` units to the product's MAP or
+    /// hard floor, whichever is higher. Returns the broken rule when it had
+    /// to.
+    pub fn advertise(&self, product: &Product, quantity: u32, price: Money) -> (Money, Option<FloorRule>) {
+        let Some((unit_minimum, rule)) = self.for_product(product).and_then(PriceFloor::advertised) else {
+            return (price, None);
+        };
+        let minimum = Money::new(unit_minimum * Decimal::from(quantity), price.currency.clone()).round_to_minor_units();
+        if price.amount >= minimum.amount {
+            return (price, None);
+        }
+        tracing::warn!(
+            product_id = %product.id, %quantity, price = %price.amount, minimum = %minimum.amount, rule = rule.as_str(),
+            "Raised a price below the product's floor"
+        );
+        (minimum, Some(rule))
+    }
+
+    /// Rejects an offer of `price` for `quantity` units below the product's
+    /// hard floor.
+    pub fn check(&self, product: &Product, quantity: u32, price: Decimal) -> Result<()> {
+        let Some(unit_minimum) = self.for_product(product).and_then(|floor| floor.min_price) else {
+            return Ok(());
+        };
+        let minimum = unit_minimum * Decimal::from(quantity);
+        if price >= minimum {
+            return Ok(());
+        }
+        tracing::warn!(
+            product_id = %product.id, %quantity, %price, %minimum, rule = FloorRule::MinPrice.as_str(),
+            "Blocked an offer below the product's floor"
+        );
+        Err(NegotiationError::Negotiation(format!(
+            "{} {} is below the price floor for {} x{}", price, product.currency, product.id, quantity
+        )))
+    }
+}
+
+fn rule_from_config(rule: &PricingRuleConfig) -> Result<Box<dyn PricingPolicy>> {
+    Ok(match rule {
+        PricingRuleConfig::VolumeTiers { tiers } => Box::new(VolumeTiers(Tiers::new(
+            tiers.iter().map(|tier| (tier.min_quantity, tier.multiplier)).collect(),
+        )?)),
+        PricingRuleConfig::ReputationDiscount { tiers } => Box::new(ReputationDiscount(Tiers::new(
+            tiers.iter().map(|tier| (tier.min_reputation, tier.multiplier)).collect(),
+        )?)),
+        PricingRuleConfig::InventoryPressure { min_stock, multiplier } => Box::new(InventoryPressure {
+            min_stock: *min_stock,
+            multiplier: validate_multiplier(*multiplier)?,
+        }),
+        PricingRuleConfig::TimeOfDay { business_hours, after_hours } => Box::new(TimeOfDay {
+            business_hours: validate_multiplier(*business_hours)?,
+            after_hours: validate_multiplier(*after_hours)?,
+        }),
+        PricingRuleConfig::Flat { multiplier } => Box::new(Flat(validate_multiplier(*multiplier)?)),
+    })
+}
+
+fn validate_multiplier(multiplier: Decimal) -> Result<Decimal> {
+    if multiplier > Decimal::ZERO {
+        Ok(multiplier)
+    } else {
+        Err(NegotiationError::Config(format!("Pricing multiplier must be positive: {}", multiplier)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{CalendarConfig, VolumeTierConfig};
+
+    fn product(category: &str, stock_quantity: u32) -> Product {
+        Product {
+            id: "laptop-001".to_string(),
+            name: "Gaming Laptop".to_string(),
+            description: String::new(),
+            category: category.to_string(),
+            base_price: Decimal::from(1000),
+            currency: "USD".to_string(),
+            stock_quantity,
+            metadata: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_default_policy_matches_previous_pricing() {
+        let policy = ConfiguredPricingPolicy::from_config(&PricingConfig::default()).unwrap();
+        let calendar = BusinessCalendar::from_config(&CalendarConfig::default()).unwrap();
+        let product = product("Electronics", 100);
+        // Saturday, so outside business hours
+        let at = DateTime::parse_from_rfc3339("2026-01-10T12:00:00Z").unwrap().with_timezone(&Utc);
+        let context = |quantity, buyer_reputation| PricingContext {
+            product: &product,
+            quantity,
+            buyer_reputation,
+            at,
+            calendar: &calendar,
+        };
+
+        assert_eq!(policy.factor(&context(1, 60)), Decimal::new(101, 2));
+        assert_eq!(policy.factor(&context(20, 90)), Decimal::new(95, 2) * Decimal::new(98, 2) * Decimal::new(101, 2));
+    }
+
+    #[test]
+    fn test_category_and_product_overrides() {
+        let mut config = PricingConfig::default();
+        config.categories.insert("Electronics".to_string(), vec![
+            PricingRuleConfig::InventoryPressure { min_stock: 50, multiplier: Decimal::new(90, 2) },
+        ]);
+        config.products.insert("laptop-001".to_string(), vec![
+            PricingRuleConfig::VolumeTiers {
+                tiers: vec![
+                    VolumeTierConfig { min_quantity: 5, multiplier: Decimal::new(97, 2) },
+                    VolumeTierConfig { min_quantity: 10, multiplier: Decimal::new(93, 2) },
+                ],
+            },
+        ]);
+        let policy = ConfiguredPricingPolicy::from_config(&config).unwrap();
+        let calendar = BusinessCalendar::from_config(&CalendarConfig::default()).unwrap();
+
+        let laptop = product("Electronics", 100);
+        let mut phone = product("Electronics", 100);
+        phone.id = "phone-001".to_string();
+        let context = |product, quantity| PricingContext {
+            product,
+            quantity,
+            buyer_reputation: 60,
+            at: Utc::now(),
+            calendar: &calendar,
+        };
+
+        assert_eq!(policy.factor(&context(&laptop, 12)), Decimal::new(93, 2));
+        assert_eq!(policy.factor(&context(&laptop, 6)), Decimal::new(97, 2));
+        assert_eq!(policy.factor(&context(&phone, 1)), Decimal::new(90, 2));
+
+        config.default.push(PricingRuleConfig::Flat { multiplier: Decimal::ZERO });
+        assert!(ConfiguredPricingPolicy::from_config(&config).is_err());
+    }
+
+    #[test]
+    fn test_price_floors() {
+        let mut config = PriceFloorsConfig::default();
+        config.categories.insert("Electronics".to_string(), PriceFloorConfig {
+            min_price: Some(Decimal::from(800)),
+            map_price: Some(Decimal::from(950)),
+        });
+        config.products.insert("phone-001".to_string(), PriceFloorConfig {
+            min_price: Some(Decimal::from(500)),
+            map_price: None,
+        });
+        let floors = PriceFloors::from_config(&config).unwrap();
+        let laptop = product("Electronics", 100);
+        let mut phone = product("Electronics", 100);
+        phone.id = "phone-001".to_string();
+        let usd = |amount: i64| Money::new(Decimal::from(amount), "USD");
+
+        // Published prices are lifted to MAP, but negotiation may go down to the floor
+        assert_eq!(floors.advertise(&laptop, 2, usd(1800)), (usd(1900), Some(FloorRule::MapPrice)));
+        assert_eq!(floors.advertise(&laptop, 2, usd(2000)), (usd(2000), None));
+        assert!(floors.check(&laptop, 2, Decimal::from(1700)).is_ok());
+        assert!(floors.check(&laptop, 2, Decimal::from(1500)).is_err());
+
+        // The product's own floor replaces its category's
+        assert_eq!(floors.advertise(&phone, 1, usd(450)), (usd(500), Some(FloorRule::MinPrice)));
+        assert!(floors.check(&phone, 1, Decimal::from(600)).is_ok());
+
+        let mut books = product("Books", 100);
+        books.id = "book-001".to_string();
+        assert_eq!(floors.advertise(&books, 1, usd(1)), (usd(1), None));
+
+        config.products.insert("book-001".to_string(), PriceFloorConfig { min_price: Some(Decimal::from(-1)), map_price: None });
+        assert!(PriceFloors::from_config(&config).is_err());
+    }
+}
@@ -0,0 +1,290 @@
+//! Privacy mode for exported records and public analytics.
+//!
+//! With privacy mode on, counterparties are replaced by pseudonyms (or
+//! dropped), prices are reported as bands and deltas are rounded, so operators
+//! can share market data without exposing individual contract terms. Market
+//! reports also suppress products with too few deals to hide behind, and can
+//! add Laplace noise to deal counts.
+
+use crate::{
+    analytics::MarketAnalytics,
+    config::PrivacyConfig,
+    error::{NegotiationError, Result},
+    model::NegotiationRecord,
+    AgentId,
+};
+use chrono::NaiveDate;
+use hmac::{Hmac, Mac};
+use rand::Rng;
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PriceBand {
+    pub low: Decimal,
+    pub high: Decimal,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RedactedRecord {
+    /// Stable pseudonym, or absent when no pseudonym secret is configured
+    pub buyer: Option<String>,
+    pub seller: Option<String>,
+    pub product_hash: String,
+    pub opening_bid: PriceBand,
+    pub close_price: PriceBand,
+    pub delta: Decimal,
+    pub date: NaiveDate,
+    pub duration_seconds: u64,
+    pub message_count: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ExportedRecord {
+    Full(NegotiationRecord),
+    Redacted(RedactedRecord),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProductSummary {
+    pub product_hash: String,
+    pub deal_count: u64,
+    /// Exact average when privacy mode is off (`low == high`)
+    pub average_close_price: PriceBand,
+    pub average_delta: Decimal,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MarketReport {
+    pub products: Vec<ProductSummary>,
+    /// Products left out for having fewer than `min_group_size` deals
+    pub suppressed_products: usize,
+    pub redacted: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct PrivacyFilter {
+    config: PrivacyConfig,
+}
+
+impl PrivacyFilter {
+    pub fn from_config(config: &PrivacyConfig) -> Result<Self> {
+        if config.price_band_width <= Decimal::ZERO || config.delta_rounding <= Decimal::ZERO {
+            return Err(NegotiationError::Config(
+                "Privacy price_band_width and delta_rounding must be positive".to_string()
+            ));
+        }
+        if let Some(epsilon) = config.count_noise_epsilon {
+            if !(epsilon.is_finite() && epsilon > 0.0) {
+                return Err(NegotiationError::Config(format!("count_noise_epsilon must be positive: {}", epsilon)));
+            }
+        }
+        Ok(Self { config: config.clone() })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    pub fn export_record(&self, record: &NegotiationRecord) -> ExportedRecord {
+        if !self.config.enabled {
+            return ExportedRecord::Full(record.clone());
+        }
+
+        ExportedRecord::Redacted(RedactedRecord {
+            buyer: self.pseudonym(record.buyer_id),
+            seller: self.pseudonym(record.seller_id),
+            product_hash: record.product_hash.clone(),
+            opening_bid: self.band(record.opening_bid),
+            close_price: self.band(record.close_price),
+            delta: self.round_delta(record.delta),
+            date: record.timestamp.date_naive(),
+            duration_seconds: record.duration_seconds,
+            message_count: record.message_count,
+        })
+    }
+
+    pub fn export_records(&self, records: &[NegotiationRecord]) -> Vec<ExportedRecord> {
+        records.iter().map(|record| self.export_record(record)).collect()
+    }
+
+    /// Per-product deal counts and averages.
+    pub fn market_report(&self, records: &[NegotiationRecord]) -> MarketReport {
+        let mut groups: BTreeMap<&str, Vec<&NegotiationRecord>> = BTreeMap::new();
+        for record in records {
+            groups.entry(record.product_hash.as_str()).or_default().push(record);
+        }
+
+        let mut products = Vec::new();
+        let mut suppressed_products = 0;
+        for (product_hash, deals) in groups {
+            if self.config.enabled && deals.len() < self.config.min_group_size {
+                suppressed_products += 1;
+                continue;
+            }
+
+            let count = Decimal::from(deals.len());
+            let average_close = deals.iter().map(|deal| deal.close_price).sum::<Decimal>() / count;
+            let average_delta = deals.iter().map(|deal| deal.delta).sum::<Decimal>() / count;
+
+            products.push(if self.config.enabled {
+                ProductSummary {
+                    product_hash: product_hash.to_string(),
+                    deal_count: self.noisy_count(deals.len()),
+                    average_close_price: self.band(average_close),
+                    average_delta: self.round_delta(average_delta),
+                }
+            } else {
+                ProductSummary {
+                    product_hash: product_hash.to_string(),
+                    deal_count: deals.len() as u64,
+                    average_close_price: PriceBand { low: average_close, high: average_close },
+                    average_delta,
+                }
+            });
+        }
+
+        MarketReport {
+            products,
+            suppressed_products,
+            redacted: self.config.enabled,
+        }
+    }
+
+    /// Bands prices, rounds deltas and adds count noise to market analytics,
+    /// leaves out small categories, and pseudonymises sellers (or drops them
+    /// without a pseudonym secret). Unchanged when privacy mode is off.
+    pub fn redact_analytics(&self, mut analytics: MarketAnalytics) -> MarketAnalytics {
+        if !self.config.enabled {
+            return analytics;
+        }
+
+        let categories = std::mem::take(&mut analytics.categories);
+        for mut category in categories {
+            if (category.deal_count as usize) < self.config.min_group_size {
+                analytics.suppressed_categories += 1;
+                continue;
+            }
+            category.deal_count = self.noisy_count(category.deal_count as usize);
+            category.average_close_price = self.band(category.average_close_price.low);
+            category.average_delta = self.round_delta(category.average_delta);
+            // Single days can hold a handful of deals, so only the overall change is kept
+            category.trend.clear();
+            analytics.categories.push(category);
+        }
+        analytics.sellers = std::mem::take(&mut analytics.sellers).into_iter()
+            .filter_map(|mut seller| {
+                seller.seller = self.pseudonym(AgentId::parse_str(&seller.seller).ok()?)?;
+                Some(seller)
+            })
+            .collect();
+        analytics.deal_count = self.noisy_count(analytics.deal_count as usize);
+        analytics.average_delta = self.round_delta(analytics.average_delta);
+        analytics.redacted = true;
+        analytics
+    }
+
+    fn band(&self, price: Decimal) -> PriceBand {
+        let width = self.config.price_band_width;
+        let low = (price / width).floor() * width;
+        PriceBand { low, high: low + width }
+    }
+
+    fn round_delta(&self, delta: Decimal) -> Decimal {
+        let step = self.config.delta_rounding;
+        (delta / step).round_dp_with_strategy(0, RoundingStrategy::MidpointAwayFromZero) * step
+    }
+
+    fn pseudonym(&self, agent_id: AgentId) -> Option<String> {
+        let secret = self.config.pseudonym_secret.as_ref()?;
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).ok()?;
+        mac.update(agent_id.as_bytes());
+        let digest = hex::encode(mac.finalize().into_bytes());
+        Some(format!("agent_{}", &digest[..16]))
+    }
+
+    /// Adds Laplace noise scaled to `1 / epsilon` (a count changes by at most
+    /// one per deal), when configured.
+    fn noisy_count(&self, count: usize) -> u64 {
+        let epsilon = match self.config.count_noise_epsilon {
+            Some(epsilon) => epsilon,
+            None => return count as u64,
+        };
+        let u: f64 = rand::thread_rng().gen_range(-0.5..0.5);
+        let noise = -(1.0 / epsilon) * u.signum() * (1.0 - 2.0 * u.abs()).ln();
+        (count as f64 + noise).round().max(0.0) as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn record(product_hash: &str, close_price: i64, buyer_id: AgentId) -> NegotiationRecord {
+        NegotiationRecord {
+            buyer_id,
+            seller_id: uuid::Uuid::new_v4(),
+            product_hash: product_hash.to_string(),
+            opening_bid: Decimal::from(2500),
+            close_price: Decimal::from(close_price),
+            delta: Decimal::from(close_price - 2500),
+            timestamp: Utc::now(),
+            duration_seconds: 120,
+            message_count: 6,
+        }
+    }
+
+    fn privacy_config() -> PrivacyConfig {
+        PrivacyConfig {
+            enabled: true,
+            pseudonym_secret: Some("test-secret".to_string()),
+            min_group_size: 2,
+            ..PrivacyConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_records_are_redacted() {
+        let filter = PrivacyFilter::from_config(&privacy_config()).unwrap();
+        let buyer_id = uuid::Uuid::new_v4();
+
+        let first = match filter.export_record(&record("laptop-001", 2263, buyer_id)) {
+            ExportedRecord::Redacted(redacted) => redacted,
+            ExportedRecord::Full(_) => panic!("record was not redacted"),
+        };
+        assert_eq!(first.close_price, PriceBand { low: Decimal::from(2200), high: Decimal::from(2300) });
+        assert_eq!(first.delta, Decimal::from(-240));
+        assert!(!first.buyer.as_ref().unwrap().contains(&buyer_id.to_string()));
+
+        // Pseudonyms are stable so an agent's deals can still be grouped
+        let second = match filter.export_record(&record("laptop-001", 2300, buyer_id)) {
+            ExportedRecord::Redacted(redacted) => redacted,
+            ExportedRecord::Full(_) => panic!("record was not redacted"),
+        };
+        assert_eq!(first.buyer, second.buyer);
+        assert_ne!(first.seller, second.seller);
+
+        let disabled = PrivacyFilter::from_config(&PrivacyConfig::default()).unwrap();
+        assert!(matches!(disabled.export_record(&record("laptop-001", 2263, buyer_id)), ExportedRecord::Full(_)));
+    }
+
+    #[test]
+    fn test_market_report_suppresses_small_groups() {
+        let filter = PrivacyFilter::from_config(&privacy_config()).unwrap();
+        let records = vec![
+            record("laptop-001", 2250, uuid::Uuid::new_v4()),
+            record("laptop-001", 2350, uuid::Uuid::new_v4()),
+            record("monitor-003", 399, uuid::Uuid::new_v4()),
+        ];
+
+        let report = filter.market_report(&records);
+        assert_eq!(report.suppressed_products, 1);
+        assert_eq!(report.products.len(), 1);
+        assert_eq!(report.products[0].deal_count, 2);
+        assert_eq!(report.products[0].average_close_price, PriceBand { low: Decimal::from(2300), high: Decimal::from(2400) });
+    }
+}
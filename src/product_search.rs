@@ -0,0 +1,252 @@
+//! Full-text product search across the registry.
+//!
+//! Buyers search product names and descriptions, optionally narrowed to a
+//! category and a price range, and get listings ranked by relevance with
+//! facets counting what the search matched. On SQLite the registry keeps an
+//! FTS5 index of every listed product and ranks matches by BM25; on
+//! PostgreSQL terms are matched with `LIKE` and ranked by [`text_score`].
+//! Facets are counted before the category filter, so buyers can see which
+//! other categories their terms match.
+
+use crate::{
+    catalog::CatalogEntry,
+    error::{NegotiationError, Result},
+    model::Product,
+    taxonomy::category_matches,
+};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+/// Results returned when the query doesn't set a limit
+pub const DEFAULT_SEARCH_LIMIT: usize = 20;
+
+/// Most results returned for one query
+pub const MAX_SEARCH_LIMIT: usize = 100;
+
+/// Weight of a term found in a product's name, relative to its description
+pub const NAME_WEIGHT: f64 = 10.0;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProductSearchQuery {
+    /// Words to look for in product names and descriptions; every product
+    /// matches when absent
+    #[serde(default)]
+    pub q: Option<String>,
+    /// Products in this category or one under it
+    #[serde(default)]
+    pub category: Option<String>,
+    #[serde(default)]
+    pub min_price: Option<Decimal>,
+    #[serde(default)]
+    pub max_price: Option<Decimal>,
+    /// Only products listed in this currency
+    #[serde(default)]
+    pub currency: Option<String>,
+    /// Region the buyer is searching from; region-restricted categories are
+    /// hidden when absent
+    #[serde(default)]
+    pub region: Option<String>,
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+impl ProductSearchQuery {
+    /// The query's search terms, lowercased, without punctuation
+    pub fn terms(&self) -> Vec<String> {
+        self.q.as_deref().map(search_terms).unwrap_or_default()
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.min_price.zip(self.max_price).is_some_and(|(min, max)| min > max) {
+            return Err(NegotiationError::Validation("Minimum price is above the maximum".to_string()));
+        }
+        if self.limit == Some(0) {
+            return Err(NegotiationError::Validation("Limit must be greater than 0".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Whether a product passes the price and currency filters
+    pub fn prices(&self, product: &Product) -> bool {
+        self.currency.as_ref().is_none_or(|currency| currency.eq_ignore_ascii_case(&product.currency))
+            && self.min_price.is_none_or(|min| product.base_price >= min)
+            && self.max_price.is_none_or(|max| product.base_price <= max)
+    }
+
+    fn limit(&self) -> usize {
+        self.limit.unwrap_or(DEFAULT_SEARCH_LIMIT).min(MAX_SEARCH_LIMIT)
+    }
+}
+
+/// A product matching a search, with its seller
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProductSearchHit {
+    /// Higher is more relevant; 0 when the query has no terms
+    pub score: f64,
+    #[serde(flatten)]
+    pub listing: CatalogEntry,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FacetCount {
+    pub value: String,
+    pub count: usize,
+}
+
+/// Lowest and highest price matched in one currency
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PriceRange {
+    pub currency: String,
+    pub min: Decimal,
+    pub max: Decimal,
+}
+
+/// What the search matched, before the category filter and the limit
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SearchFacets {
+    /// Most matches first
+    pub categories: Vec<FacetCount>,
+    pub price_ranges: Vec<PriceRange>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProductSearchResponse {
+    /// Most relevant first, then cheapest
+    pub results: Vec<ProductSearchHit>,
+    /// Matches in the category, before the limit
+    pub total_count: usize,
+    pub facets: SearchFacets,
+}
+
+/// Splits a query into lowercase words, dropping punctuation, so nothing a
+/// buyer types is read as index syntax.
+pub fn search_terms(query: &str) -> Vec<String> {
+    query.split(|c: char| !c.is_alphanumeric())
+        .filter(|term| !term.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// An FTS5 query matching every term, each as a word prefix.
+pub fn fts_query(terms: &[String]) -> String {
+    terms.iter()
+        .map(|term| format!("\"{}\"*", term.replace('"', "\"\"")))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Relevance of a product to `terms` where the index can't rank: each term
+/// found in the name counts [`NAME_WEIGHT`], in the description 1.
+pub fn text_score(product: &Product, terms: &[String]) -> f64 {
+    let name = product.name.to_lowercase();
+    let description = product.description.to_lowercase();
+    terms.iter()
+        .map(|term| {
+            let in_name = if name.contains(term.as_str()) { NAME_WEIGHT } else { 0.0 };
+            let in_description = if description.contains(term.as_str()) { 1.0 } else { 0.0 };
+            in_name + in_description
+        })
+        .sum()
+}
+
+/// Counts facets over every match, then keeps the most relevant listings in
+/// the query's category.
+pub fn rank(query: &ProductSearchQuery, mut hits: Vec<ProductSearchHit>) -> ProductSearchResponse {
+    let mut categories: Vec<FacetCount> = Vec::new();
+    let mut price_ranges: Vec<PriceRange> = Vec::new();
+    for hit in &hits {
+        let product = &hit.listing.product;
+        match categories.iter_mut().find(|facet| facet.value == product.category) {
+            Some(facet) => facet.count += 1,
+            None => categories.push(FacetCount { value: product.category.clone(), count: 1 }),
+        }
+        match price_ranges.iter_mut().find(|range| range.currency == product.currency) {
+            Some(range) => {
+                range.min = range.min.min(product.base_price);
+                range.max = range.max.max(product.base_price);
+            }
+            None => price_ranges.push(PriceRange {
+                currency: product.currency.clone(),
+                min: product.base_price,
+                max: product.base_price,
+            }),
+        }
+    }
+    categories.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.value.cmp(&b.value)));
+    price_ranges.sort_by(|a, b| a.currency.cmp(&b.currency));
+
+    if let Some(category) = query.category.as_deref() {
+        hits.retain(|hit| category_matches(&hit.listing.product.category, category));
+    }
+    hits.sort_by(|a, b| {
+        b.score.total_cmp(&a.score)
+            .then_with(|| a.listing.product.base_price.cmp(&b.listing.product.base_price))
+    });
+    let total_count = hits.len();
+    hits.truncate(query.limit());
+
+    ProductSearchResponse {
+        results: hits,
+        total_count,
+        facets: SearchFacets { categories, price_ranges },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn hit(name: &str, category: &str, price: i64, score: f64) -> ProductSearchHit {
+        ProductSearchHit {
+            score,
+            listing: CatalogEntry {
+                seller_id: uuid::Uuid::new_v4(),
+                seller_name: "TechSeller".to_string(),
+                seller_reputation: 100,
+                product: Product {
+                    id: name.to_lowercase(),
+                    name: name.to_string(),
+                    description: String::new(),
+                    category: category.to_string(),
+                    base_price: Decimal::from(price),
+                    currency: "USD".to_string(),
+                    stock_quantity: 1,
+                    metadata: HashMap::new(),
+                },
+            },
+        }
+    }
+
+    #[test]
+    fn test_queries_are_sanitized_and_scored() {
+        assert_eq!(search_terms("Gaming  laptop, RTX-4080!"), vec!["gaming", "laptop", "rtx", "4080"]);
+        assert_eq!(fts_query(&search_terms("laptop OR \"mouse\" NEAR(")), "\"laptop\"* \"or\"* \"mouse\"* \"near\"*");
+
+        let laptop = hit("Gaming Laptop", "Electronics", 2500, 0.0).listing.product;
+        let bag = Product { description: "Fits any laptop".to_string(), ..hit("Bag", "Accessories", 40, 0.0).listing.product };
+        let terms = search_terms("laptop");
+        assert!(text_score(&laptop, &terms) > text_score(&bag, &terms));
+        assert_eq!(text_score(&bag, &search_terms("phone")), 0.0);
+    }
+
+    #[test]
+    fn test_facets_count_every_category_before_filtering() {
+        let query = ProductSearchQuery { category: Some("electronics".to_string()), limit: Some(1), ..Default::default() };
+        let response = rank(&query, vec![
+            hit("Laptop", "Electronics", 2500, 1.0),
+            hit("Laptop Bag", "Accessories", 40, 0.5),
+            hit("Budget Laptop", "Electronics", 900, 1.0),
+        ]);
+        assert_eq!(response.total_count, 2);
+        assert_eq!(response.results[0].listing.product.name, "Budget Laptop");
+        assert_eq!(response.facets.categories, vec![
+            FacetCount { value: "Electronics".to_string(), count: 2 },
+            FacetCount { value: "Accessories".to_string(), count: 1 },
+        ]);
+        assert_eq!(response.facets.price_ranges, vec![
+            PriceRange { currency: "USD".to_string(), min: Decimal::from(40), max: Decimal::from(2500) },
+        ]);
+        assert!(ProductSearchQuery { min_price: Some(Decimal::TEN), max_price: Some(Decimal::ONE), ..Default::default() }.validate().is_err());
+    }
+}
@@ -0,0 +1,156 @@
+//! Wire protocol versioning for agent-to-agent communication.
+//!
+//! Every request carries an `x-dcap-protocol-version` header. Agents advertise
+//! the versions they speak through discovery, and both sides settle on the
+//! highest version they have in common. Peers that omit the header are
+//! treated as speaking the legacy 1.0 protocol.
+
+use crate::error::{ApiError, NegotiationError, Result};
+use axum::{
+    extract::Request,
+    http::{HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+pub const PROTOCOL_VERSION_HEADER: &str = "x-dcap-protocol-version";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ProtocolVersion {
+    pub major: u16,
+    pub minor: u16,
+}
+
+/// Version spoken by peers that predate version negotiation
+pub const LEGACY_VERSION: ProtocolVersion = ProtocolVersion::new(1, 0);
+
+/// Version this build prefers
+pub const CURRENT_VERSION: ProtocolVersion = ProtocolVersion::new(1, 1);
+
+/// Compatibility matrix: every version this build can speak
+pub const SUPPORTED_VERSIONS: &[ProtocolVersion] = &[
+    ProtocolVersion::new(1, 0),
+    ProtocolVersion::new(1, 1),
+];
+
+impl ProtocolVersion {
+    pub const fn new(major: u16, minor: u16) -> Self {
+        Self { major, minor }
+    }
+
+    /// Minor versions within the same major are wire compatible.
+    pub fn is_compatible_with(&self, other: &ProtocolVersion) -> bool {
+        self.major == other.major
+    }
+
+    pub fn is_supported(&self) -> bool {
+        SUPPORTED_VERSIONS.iter().any(|v| v.is_compatible_with(self))
+    }
+}
+
+impl fmt::Display for ProtocolVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+impl FromStr for ProtocolVersion {
+    type Err = NegotiationError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (major, minor) = s.trim().split_once('.')
+            .ok_or_else(|| NegotiationError::Validation(format!("Invalid protocol version: {}", s)))?;
+        let parse = |part: &str| part.parse::<u16>()
+            .map_err(|_| NegotiationError::Validation(format!("Invalid protocol version: {}", s)));
+        Ok(Self::new(parse(major)?, parse(minor)?))
+    }
+}
+
+impl TryFrom<String> for ProtocolVersion {
+    type Error = NegotiationError;
+
+    fn try_from(value: String) -> Result<Self> {
+        value.parse()
+    }
+}
+
+impl From<ProtocolVersion> for String {
+    fn from(version: ProtocolVersion) -> Self {
+        version.to_string()
+    }
+}
+
+pub fn supported_versions() -> Vec<ProtocolVersion> {
+    SUPPORTED_VERSIONS.to_vec()
+}
+
+/// Picks the highest version both sides can speak. Within a shared major the
+/// lower of the two minors wins, since newer minors only add optional fields.
+/// An empty remote list means the peer predates negotiation.
+pub fn negotiate_version(local: &[ProtocolVersion], remote: &[ProtocolVersion]) -> Option<ProtocolVersion> {
+    let legacy = [LEGACY_VERSION];
+    let remote = if remote.is_empty() { &legacy[..] } else { remote };
+
+    local.iter()
+        .flat_map(|l| remote.iter().filter(move |r| l.is_compatible_with(r)).map(move |r| (*l).min(*r)))
+        .max()
+}
+
+/// Axum middleware that validates the peer's protocol version header and
+/// echoes back the version this server will answer with.
+pub async fn protocol_version_layer(request: Request, next: Next) -> Response {
+    let requested = match request.headers().get(PROTOCOL_VERSION_HEADER) {
+        Some(value) => match value.to_str().ok().and_then(|v| v.parse::<ProtocolVersion>().ok()) {
+            Some(version) => version,
+            None => {
+                return ApiError::new(StatusCode::BAD_REQUEST, "Malformed protocol version header")
+                    .with_detail("supported_versions", supported_versions())
+                    .into_response();
+            }
+        },
+        None => LEGACY_VERSION,
+    };
+
+    let negotiated = match negotiate_version(SUPPORTED_VERSIONS, &[requested]) {
+        Some(version) => version,
+        None => {
+            tracing::warn!("Rejected request with unsupported protocol version {}", requested);
+            return ApiError::new(StatusCode::UPGRADE_REQUIRED, format!("Unsupported protocol version: {}", requested))
+                .with_detail("supported_versions", supported_versions())
+                .into_response();
+        }
+    };
+
+    let mut response = next.run(request).await;
+    if let Ok(value) = HeaderValue::from_str(&negotiated.to_string()) {
+        response.headers_mut().insert(PROTOCOL_VERSION_HEADER, value);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_version_parsing() {
+        assert_eq!("1.1".parse::<ProtocolVersion>().unwrap(), ProtocolVersion::new(1, 1));
+        assert!("1".parse::<ProtocolVersion>().is_err());
+        assert!("a.b".parse::<ProtocolVersion>().is_err());
+        assert_eq!(ProtocolVersion::new(2, 3).to_string(), "2.3");
+    }
+
+    #[test]
+    fn test_negotiation_picks_highest_common_version() {
+        let local = [ProtocolVersion::new(1, 0), ProtocolVersion::new(1, 2), ProtocolVersion::new(2, 0)];
+
+        assert_eq!(negotiate_version(&local, &[ProtocolVersion::new(1, 1)]), Some(ProtocolVersion::new(1, 1)));
+        assert_eq!(negotiate_version(&local, &[ProtocolVersion::new(2, 4)]), Some(ProtocolVersion::new(2, 0)));
+        assert_eq!(negotiate_version(&local, &[]), Some(LEGACY_VERSION));
+        assert_eq!(negotiate_version(&local, &[ProtocolVersion::new(3, 0)]), None);
+    }
+}
@@ -0,0 +1,148 @@
+//! How long a seller's quotes stay open.
+//!
+//! A product's quotes last `[quote_ttl] products.<id>` seconds, failing that
+//! `categories.<name>` (short for volatile categories whose prices move
+//! quickly), failing that `quote_seconds`. Counter quotes last
+//! `counter_seconds`, never longer than the product's quote TTL. Either is
+//! then stretched by the buyer's trust level multiplier, so highly trusted
+//! buyers get longer to decide, and finally by the business calendar while
+//! the seller is closed.
+
+use crate::{
+    config::QuoteTtlConfig,
+    error::{NegotiationError, Result},
+    model::Product,
+    trust::TrustLevel,
+};
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+use std::collections::HashMap;
+
+pub const DEFAULT_QUOTE_TTL_SECONDS: u32 = 3600;
+pub const DEFAULT_COUNTER_TTL_SECONDS: u32 = 1800;
+/// TTL multiplier for highly trusted buyers
+pub const DEFAULT_HIGHLY_TRUSTED_MULTIPLIER: Decimal = Decimal::from_parts(2, 0, 0, false, 0);
+
+#[derive(Debug, Clone)]
+pub struct QuoteTtlPolicy {
+    quote_seconds: u32,
+    counter_seconds: u32,
+    categories: HashMap<String, u32>,
+    products: HashMap<String, u32>,
+    trust_multipliers: HashMap<TrustLevel, Decimal>,
+}
+
+impl QuoteTtlPolicy {
+    pub fn from_config(config: &QuoteTtlConfig) -> Result<Self> {
+        let seconds = [("quote_seconds", config.quote_seconds), ("counter_seconds", config.counter_seconds)].into_iter()
+            .chain(config.categories.iter().map(|(category, seconds)| (category.as_str(), *seconds)))
+            .chain(config.products.iter().map(|(product_id, seconds)| (product_id.as_str(), *seconds)));
+        for (name, seconds) in seconds {
+            if seconds == 0 {
+                return Err(NegotiationError::Config(format!("Quote TTL for {} must be greater than 0", name)));
+            }
+        }
+        if let Some((level, multiplier)) = config.trust_multipliers.iter().find(|(_, multiplier)| **multiplier <= Decimal::ZERO) {
+            return Err(NegotiationError::Config(format!(
+                "Quote TTL multiplier for {:?} buyers must be positive, got {}", level, multiplier
+            )));
+        }
+
+        Ok(Self {
+            quote_seconds: config.quote_seconds,
+            counter_seconds: config.counter_seconds,
+            categories: config.categories.clone(),
+            products: config.products.clone(),
+            trust_multipliers: config.trust_multipliers.clone(),
+        })
+    }
+
+    /// The product's quote TTL before the buyer's trust is considered.
+    pub fn product_seconds(&self, product: &Product) -> u32 {
+        self.products.get(&product.id)
+            .or_else(|| self.categories.get(&product.category))
+            .copied()
+            .unwrap_or(self.quote_seconds)
+    }
+
+    /// TTL of a quote for `product` to a buyer with `buyer_reputation`.
+    pub fn quote_seconds(&self, product: &Product, buyer_reputation: u32) -> u32 {
+        self.for_buyer(self.product_seconds(product), buyer_reputation)
+    }
+
+    /// TTL of a counter quote for `product` to a buyer with `buyer_reputation`.
+    pub fn counter_seconds(&self, product: &Product, buyer_reputation: u32) -> u32 {
+        self.for_buyer(self.counter_seconds.min(self.product_seconds(product)), buyer_reputation)
+    }
+
+    /// TTL of a counter quote that names no product, to a buyer with
+    /// `buyer_reputation`.
+    pub fn default_counter_seconds(&self, buyer_reputation: u32) -> u32 {
+        self.for_buyer(self.counter_seconds, buyer_reputation)
+    }
+
+    fn for_buyer(&self, seconds: u32, buyer_reputation: u32) -> u32 {
+        match self.trust_multipliers.get(&TrustLevel::from(buyer_reputation)) {
+            Some(multiplier) => (Decimal::from(seconds) * multiplier).round().to_u32().unwrap_or(seconds).max(1),
+            None => seconds,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn product(id: &str, category: &str) -> Product {
+        Product {
+            id: id.to_string(),
+            name: id.to_string(),
+            description: String::new(),
+            category: category.to_string(),
+            base_price: Decimal::from(100),
+            currency: "USD".to_string(),
+            stock_quantity: 10,
+            metadata: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_ttls_by_product_category_and_trust() {
+        let config: QuoteTtlConfig = toml::from_str(r#"
+            quote_seconds = 3600
+            counter_seconds = 1800
+
+            [categories]
+            Crypto = 300
+
+            [products]
+            laptop-001 = 7200
+
+            [trust_multipliers]
+            highly_trusted = 2
+            untrusted = 0.5
+        "#).unwrap();
+        let policy = QuoteTtlPolicy::from_config(&config).unwrap();
+        let laptop = product("laptop-001", "Crypto");
+        let token = product("token-001", "Crypto");
+        let phone = product("phone-001", "Electronics");
+
+        assert_eq!(policy.quote_seconds(&laptop, 60), 7200);
+        assert_eq!(policy.quote_seconds(&token, 60), 300);
+        assert_eq!(policy.quote_seconds(&phone, 60), 3600);
+        assert_eq!(policy.quote_seconds(&phone, 95), 7200);
+        assert_eq!(policy.quote_seconds(&phone, 10), 1800);
+
+        // Counter quotes never outlast the product's quotes
+        assert_eq!(policy.counter_seconds(&phone, 60), 1800);
+        assert_eq!(policy.counter_seconds(&token, 60), 300);
+        assert_eq!(policy.counter_seconds(&token, 95), 600);
+        assert_eq!(policy.default_counter_seconds(95), 3600);
+
+        let mut invalid = config.clone();
+        invalid.categories.insert("Electronics".to_string(), 0);
+        assert!(QuoteTtlPolicy::from_config(&invalid).is_err());
+        let mut invalid = config;
+        invalid.trust_multipliers.insert(TrustLevel::Trusted, Decimal::ZERO);
+        assert!(QuoteTtlPolicy::from_config(&invalid).is_err());
+    }
+}
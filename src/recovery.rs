@@ -0,0 +1,288 @@
+//! Recovery of lost agent signing keys.
+//!
+//! Operators register a recovery policy while they still hold the agent's key:
+//! either a single offline recovery key or a quorum of guardian keys. If the
+//! agent key is later lost, a rotation signed under that policy replaces it.
+//! Every rotation stays in the registry, so counterparties can follow the
+//! chain of keys back to the identity they originally dealt with.
+
+use crate::{
+    error::{NegotiationError, Result},
+    trust::{TrustActivity, TrustActivityType},
+    AgentId,
+};
+use base64::{engine::general_purpose, Engine};
+use chrono::{DateTime, Duration, Utc};
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// How long a signed recovery or policy request stays valid
+pub const MAX_REQUEST_AGE_SECONDS: i64 = 600;
+
+/// Tolerated clock skew for requests stamped slightly in the future
+const MAX_CLOCK_SKEW_SECONDS: i64 = 60;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "method", rename_all = "snake_case")]
+pub enum RecoveryPolicy {
+    /// A single offline key that may authorize a rotation on its own
+    RecoveryKey { public_key: String },
+    /// Any `threshold` of the guardian keys must sign the rotation
+    SocialQuorum { guardians: Vec<String>, threshold: usize },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecoveryMethod {
+    RecoveryKey,
+    SocialQuorum,
+}
+
+/// Base64 ed25519 signature made by `public_key`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecoverySignature {
+    pub public_key: String,
+    pub signature: String,
+}
+
+/// Registers or replaces an agent's recovery policy. Must be signed by the
+/// agent's current key over [`policy_message`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecoveryPolicyRequest {
+    pub policy: RecoveryPolicy,
+    pub issued_at: DateTime<Utc>,
+    pub signature: String,
+}
+
+/// Replaces a lost agent key. Signed by the recovery key or guardians over
+/// [`recovery_message`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecoveryRequest {
+    pub new_public_key: String,
+    pub issued_at: DateTime<Utc>,
+    pub signatures: Vec<RecoverySignature>,
+}
+
+/// A key change recorded in the registry.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyRotation {
+    pub id: uuid::Uuid,
+    pub agent_id: AgentId,
+    pub previous_public_key: String,
+    pub new_public_key: String,
+    pub method: RecoveryMethod,
+    pub approvers: Vec<String>,
+    pub rotated_at: DateTime<Utc>,
+}
+
+impl RecoveryPolicy {
+    pub fn method(&self) -> RecoveryMethod {
+        match self {
+            RecoveryPolicy::RecoveryKey { .. } => RecoveryMethod::RecoveryKey,
+            RecoveryPolicy::SocialQuorum { .. } => RecoveryMethod::SocialQuorum,
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            RecoveryPolicy::RecoveryKey { public_key } => {
+                decode_public_key(public_key)?;
+            }
+            RecoveryPolicy::SocialQuorum { guardians, threshold } => {
+                for guardian in guardians {
+                    decode_public_key(guardian)?;
+                }
+                let unique: HashSet<_> = guardians.iter().collect();
+                if unique.len() != guardians.len() {
+                    return Err(NegotiationError::Validation("Duplicate guardian keys".to_string()));
+                }
+                if *threshold == 0 || *threshold > guardians.len() {
+                    return Err(NegotiationError::Validation(format!(
+                        "Quorum threshold {} must be between 1 and {}", threshold, guardians.len()
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the request's signatures against this policy and returns the
+    /// keys that approved it.
+    pub fn authorize(&self, agent_id: AgentId, request: &RecoveryRequest) -> Result<Vec<String>> {
+        let message = recovery_message(agent_id, &request.new_public_key, request.issued_at);
+        let (trusted, required): (Vec<&String>, usize) = match self {
+            RecoveryPolicy::RecoveryKey { public_key } => (vec![public_key], 1),
+            RecoveryPolicy::SocialQuorum { guardians, threshold } => (guardians.iter().collect(), *threshold),
+        };
+
+        let mut approvers: Vec<String> = Vec::new();
+        for signature in &request.signatures {
+            if !trusted.contains(&&signature.public_key) || approvers.contains(&signature.public_key) {
+                continue;
+            }
+            if verify_signature(&signature.public_key, &message, &signature.signature).is_ok() {
+                approvers.push(signature.public_key.clone());
+            }
+        }
+
+        if approvers.len() < required {
+            return Err(NegotiationError::Auth(format!(
+                "Recovery requires {} valid signature(s), got {}", required, approvers.len()
+            )));
+        }
+        Ok(approvers)
+    }
+}
+
+impl RecoveryPolicyRequest {
+    /// Verifies the request was signed by the agent's current key.
+    pub fn verify(&self, agent_id: AgentId, current_public_key: &str, now: DateTime<Utc>) -> Result<()> {
+        check_freshness(self.issued_at, now)?;
+        self.policy.validate()?;
+        let message = policy_message(agent_id, &self.policy, self.issued_at)?;
+        verify_signature(current_public_key, &message, &self.signature)
+    }
+}
+
+impl RecoveryRequest {
+    pub fn validate(&self, now: DateTime<Utc>) -> Result<()> {
+        check_freshness(self.issued_at, now)?;
+        decode_public_key(&self.new_public_key)?;
+        Ok(())
+    }
+}
+
+impl KeyRotation {
+    /// Trust history entry showing the identity carried over to the new key.
+    pub fn trust_activity(&self) -> TrustActivity {
+        TrustActivity {
+            id: self.id,
+            agent_id: self.agent_id,
+            activity_type: TrustActivityType::KeyRecovery,
+            score_change: 0,
+            reason: format!(
+                "Signing key rotated via {:?} recovery from {} to {}",
+                self.method, self.previous_public_key, self.new_public_key
+            ),
+            related_agent_id: None,
+            timestamp: self.rotated_at,
+        }
+    }
+}
+
+/// Bytes signed to authorize rotating `agent_id` to `new_public_key`.
+pub fn recovery_message(agent_id: AgentId, new_public_key: &str, issued_at: DateTime<Utc>) -> Vec<u8> {
+    format!("dcap-key-recovery:v1:{}:{}:{}", agent_id, new_public_key, issued_at.timestamp()).into_bytes()
+}
+
+/// Bytes signed by the current agent key to register `policy`.
+pub fn policy_message(agent_id: AgentId, policy: &RecoveryPolicy, issued_at: DateTime<Utc>) -> Result<Vec<u8>> {
+    Ok(format!(
+        "dcap-recovery-policy:v1:{}:{}:{}",
+        agent_id, serde_json::to_string(policy)?, issued_at.timestamp()
+    ).into_bytes())
+}
+
+fn check_freshness(issued_at: DateTime<Utc>, now: DateTime<Utc>) -> Result<()> {
+    if issued_at > now + Duration::seconds(MAX_CLOCK_SKEW_SECONDS) {
+        return Err(NegotiationError::Validation("Request is dated in the future".to_string()));
+    }
+    if now - issued_at > Duration::seconds(MAX_REQUEST_AGE_SECONDS) {
+        return Err(NegotiationError::Validation("Request has expired".to_string()));
+    }
+    Ok(())
+}
+
+fn decode_public_key(public_key: &str) -> Result<VerifyingKey> {
+    let bytes: [u8; 32] = general_purpose::STANDARD.decode(public_key)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| NegotiationError::Validation(format!("Invalid ed25519 public key: {}", public_key)))?;
+    VerifyingKey::from_bytes(&bytes)
+        .map_err(|_| NegotiationError::Validation(format!("Invalid ed25519 public key: {}", public_key)))
+}
+
+/// Whether `public_key` is a base64 Ed25519 key, rather than a placeholder.
+pub(crate) fn is_public_key(public_key: &str) -> bool {
+    decode_public_key(public_key).is_ok()
+}
+
+pub(crate) fn verify_signature(public_key: &str, message: &[u8], signature: &str) -> Result<()> {
+    let key = decode_public_key(public_key)?;
+    let bytes: [u8; 64] = general_purpose::STANDARD.decode(signature)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| NegotiationError::Auth("Malformed signature".to_string()))?;
+    key.verify(message, &Signature::from_bytes(&bytes))
+        .map_err(|_| NegotiationError::Auth("Signature verification failed".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+
+    fn signing_key(seed: u8) -> SigningKey {
+        SigningKey::from_bytes(&[seed; 32])
+    }
+
+    fn public_key(key: &SigningKey) -> String {
+        general_purpose::STANDARD.encode(key.verifying_key().to_bytes())
+    }
+
+    fn sign(key: &SigningKey, message: &[u8]) -> RecoverySignature {
+        RecoverySignature {
+            public_key: public_key(key),
+            signature: general_purpose::STANDARD.encode(key.sign(message).to_bytes()),
+        }
+    }
+
+    #[test]
+    fn test_social_quorum_threshold() {
+        let agent_id = uuid::Uuid::new_v4();
+        let guardians: Vec<SigningKey> = (1..=3).map(signing_key).collect();
+        let policy = RecoveryPolicy::SocialQuorum {
+            guardians: guardians.iter().map(public_key).collect(),
+            threshold: 2,
+        };
+        policy.validate().unwrap();
+
+        let new_key = public_key(&signing_key(9));
+        let issued_at = Utc::now();
+        let message = recovery_message(agent_id, &new_key, issued_at);
+        let mut request = RecoveryRequest {
+            new_public_key: new_key,
+            issued_at,
+            signatures: vec![sign(&guardians[0], &message), sign(&guardians[0], &message)],
+        };
+        request.validate(Utc::now()).unwrap();
+
+        // The same guardian signing twice does not reach the quorum
+        assert!(policy.authorize(agent_id, &request).is_err());
+
+        request.signatures.push(sign(&guardians[2], &message));
+        assert_eq!(policy.authorize(agent_id, &request).unwrap().len(), 2);
+
+        // Signatures for another agent don't transfer
+        assert!(policy.authorize(uuid::Uuid::new_v4(), &request).is_err());
+    }
+
+    #[test]
+    fn test_policy_request_signed_by_current_key() {
+        let agent_id = uuid::Uuid::new_v4();
+        let agent_key = signing_key(1);
+        let policy = RecoveryPolicy::RecoveryKey { public_key: public_key(&signing_key(2)) };
+        let issued_at = Utc::now();
+        let message = policy_message(agent_id, &policy, issued_at).unwrap();
+        let request = RecoveryPolicyRequest {
+            policy,
+            issued_at,
+            signature: sign(&agent_key, &message).signature,
+        };
+
+        assert!(request.verify(agent_id, &public_key(&agent_key), Utc::now()).is_ok());
+        assert!(request.verify(agent_id, &public_key(&signing_key(3)), Utc::now()).is_err());
+        assert!(request.verify(agent_id, &public_key(&agent_key), Utc::now() + Duration::hours(1)).is_err());
+    }
+}
@@ -0,0 +1,636 @@
+//! Where agents register and find sellers.
+//!
+//! By default agents register with the discovery service over HTTP and ask
+//! it for sellers. A [`Registry`] replaces that lookup, chosen with
+//! `[discovery] registry`:
+//!
+//! - `http`: the discovery service at the agent's `--discovery-endpoint`.
+//! - `dns`: the sellers behind a DNS name, such as a Kubernetes headless
+//!   service. An SRV name (`_http._tcp.sellers.dcap.svc.cluster.local`)
+//!   gives each pod's host and port; a `host:port` name gives every
+//!   address behind it. Each seller is asked to describe itself at
+//!   `/agent`, but its key, reputation and certificate fingerprint are
+//!   taken from a list of [`TrustedAgent`]s configured out of band, and
+//!   sellers missing from it are left out. A headless service only lists
+//!   ready pods, so sellers appear once started and drop out as they stop,
+//!   without registering.
+//! - `static`: a JSON file of agents in the form `/agent` serves them, such
+//!   as one mounted from a ConfigMap, read again on each lookup.
+//!
+//! Neither `dns` nor `static` registries take registrations: agents are
+//! listed by their deployment rather than by asking. Auctions, demand
+//! signals, listings and the other marketplace features still go to the
+//! discovery service.
+
+use crate::{
+    compliance::ComplianceProfile,
+    config::DiscoveryConfig,
+    discovery::{DiscoveryService, RegisterRequest, SearchRequest, SearchResponse},
+    error::{NegotiationError, Result},
+    http::HttpClient,
+    model::{AgentInfo, AgentType},
+    protocol::{CURRENT_VERSION, PROTOCOL_VERSION_HEADER},
+    AgentId,
+};
+use async_trait::async_trait;
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
+use tokio::net::UdpSocket;
+
+/// Where nameservers for SRV lookups are read from
+pub const RESOLV_CONF: &str = "/etc/resolv.conf";
+const DNS_TIMEOUT: Duration = Duration::from_secs(5);
+/// UDP payload advertised with EDNS, enough for a few dozen SRV records
+const MAX_DNS_PAYLOAD: usize = 4096;
+const SRV_TYPE: u16 = 33;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RegistryKind {
+    #[default]
+    Http,
+    Dns,
+    Static,
+}
+
+#[async_trait]
+pub trait Registry: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Lists the agent, with the region and attestations the registry
+    /// checks before listing products in restricted categories.
+    async fn register(&self, agent: AgentInfo, compliance: ComplianceProfile) -> Result<()>;
+
+    /// Removes the agent registered at `endpoint`.
+    async fn deregister(&self, agent_id: AgentId, endpoint: &str) -> Result<()>;
+
+    /// Sellers matching `request` as far as the registry filters them; the
+    /// caller applies the request's filters again.
+    async fn search_sellers(&self, request: &SearchRequest) -> Result<Vec<AgentInfo>>;
+
+    async fn get_agent(&self, agent_id: AgentId) -> Result<AgentInfo>;
+}
+
+/// The registry `config` names. `endpoint` is the discovery service's, for
+/// the `http` registry.
+pub fn from_config(config: &DiscoveryConfig, endpoint: &str, client: HttpClient) -> Result<Arc<dyn Registry>> {
+    Ok(match config.registry {
+        RegistryKind::Http => Arc::new(HttpRegistry::new(endpoint.to_string(), client)),
+        RegistryKind::Dns => {
+            let name = config.dns_name.as_ref().ok_or_else(|| {
+                NegotiationError::Config("[discovery] registry = \"dns\" needs a dns_name".to_string())
+            })?;
+            let trusted_agents = config.trusted_agents_file.as_ref().ok_or_else(|| {
+                NegotiationError::Config("[discovery] registry = \"dns\" needs a trusted_agents_file".to_string())
+            })?;
+            Arc::new(DnsRegistry::new(name.clone(), client, trusted_agents))
+        }
+        RegistryKind::Static => {
+            let path = config.static_file.as_ref().ok_or_else(|| {
+                NegotiationError::Config("[discovery] registry = \"static\" needs a static_file".to_string())
+            })?;
+            Arc::new(StaticRegistry::new(path))
+        }
+    })
+}
+
+/// The discovery service's agent registry. Nothing is registered or found
+/// without an endpoint.
+pub struct HttpRegistry {
+    endpoint: String,
+    client: HttpClient,
+}
+
+impl HttpRegistry {
+    pub fn new(endpoint: String, client: HttpClient) -> Self {
+        Self { endpoint, client }
+    }
+}
+
+#[async_trait]
+impl Registry for HttpRegistry {
+    fn name(&self) -> &'static str {
+        "http"
+    }
+
+    async fn register(&self, agent: AgentInfo, compliance: ComplianceProfile) -> Result<()> {
+        if self.endpoint.is_empty() {
+            return Ok(());
+        }
+        let request = RegisterRequest {
+            agent_id: Some(agent.id),
+            agent_type: agent.agent_type,
+            name: agent.name,
+            endpoint: agent.endpoint,
+            public_key: agent.public_key,
+            payment_methods: agent.payment_methods,
+            protocol_versions: agent.protocol_versions,
+            preferred_languages: agent.preferred_languages,
+            products: agent.products,
+            compliance,
+            tls_fingerprint: agent.tls_fingerprint.or_else(|| self.client.tls_fingerprint().map(str::to_string)),
+        };
+
+        let response = self.client
+            .post(format!("{}/register", self.endpoint))
+            .header(PROTOCOL_VERSION_HEADER, CURRENT_VERSION.to_string())
+            .json(&request)
+            .send()
+            .await?;
+        DiscoveryService::service_response::<serde_json::Value>(response).await?;
+        Ok(())
+    }
+
+    async fn deregister(&self, agent_id: AgentId, endpoint: &str) -> Result<()> {
+        let response = self.client
+            .delete(format!("{}/agents/{}", self.endpoint, agent_id))
+            .query(&[("endpoint", endpoint)])
+            .header(PROTOCOL_VERSION_HEADER, CURRENT_VERSION.to_string())
+            .send()
+            .await?;
+        DiscoveryService::service_response::<serde_json::Value>(response).await?;
+        Ok(())
+    }
+
+    async fn search_sellers(&self, request: &SearchRequest) -> Result<Vec<AgentInfo>> {
+        if self.endpoint.is_empty() {
+            return Ok(Vec::new());
+        }
+        let response = self.client
+            .post(format!("{}/search", self.endpoint))
+            .header(PROTOCOL_VERSION_HEADER, CURRENT_VERSION.to_string())
+            .json(request)
+            .send()
+            .await?;
+
+        if response.status().is_success() {
+            let search_response: SearchResponse = response.json().await?;
+            Ok(search_response.agents)
+        } else {
+            Err(NegotiationError::Network(response.error_for_status().unwrap_err()))
+        }
+    }
+
+    async fn get_agent(&self, agent_id: AgentId) -> Result<AgentInfo> {
+        if !self.endpoint.is_empty() {
+            let response = self.client
+                .get(format!("{}/agents/{}", self.endpoint, agent_id))
+                .header(PROTOCOL_VERSION_HEADER, CURRENT_VERSION.to_string())
+                .send()
+                .await?;
+
+            if response.status().is_success() {
+                return response.json().await.map_err(Into::into);
+            }
+        }
+
+        Err(NegotiationError::AgentNotFound(agent_id))
+    }
+}
+
+/// Agents listed in a JSON file, read on every lookup so edits apply
+/// without a restart
+pub struct StaticRegistry {
+    path: PathBuf,
+}
+
+impl StaticRegistry {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    fn agents(&self) -> Result<Vec<AgentInfo>> {
+        let listing = std::fs::read_to_string(&self.path).map_err(|e| {
+            NegotiationError::Io(format!("Failed to read registry file {}: {}", self.path.display(), e))
+        })?;
+        Ok(serde_json::from_str(&listing)?)
+    }
+}
+
+#[async_trait]
+impl Registry for StaticRegistry {
+    fn name(&self) -> &'static str {
+        "static"
+    }
+
+    async fn register(&self, agent: AgentInfo, _compliance: ComplianceProfile) -> Result<()> {
+        tracing::debug!("Not registering {}: agents are listed in {}", agent.id, self.path.display());
+        Ok(())
+    }
+
+    async fn deregister(&self, _agent_id: AgentId, _endpoint: &str) -> Result<()> {
+        Ok(())
+    }
+
+    async fn search_sellers(&self, _request: &SearchRequest) -> Result<Vec<AgentInfo>> {
+        Ok(self.agents()?.into_iter().filter(|agent| matches!(agent.agent_type, AgentType::Seller)).collect())
+    }
+
+    async fn get_agent(&self, agent_id: AgentId) -> Result<AgentInfo> {
+        self.agents()?.into_iter()
+            .find(|agent| agent.id == agent_id)
+            .ok_or(NegotiationError::AgentNotFound(agent_id))
+    }
+}
+
+/// What the `dns` registry trusts about an agent, rather than taking the
+/// agent's word for it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrustedAgent {
+    pub id: AgentId,
+    pub public_key: String,
+    pub reputation_score: u32,
+    /// Certificate fingerprint to pin when calling the agent over TLS
+    #[serde(default)]
+    pub tls_fingerprint: Option<String>,
+}
+
+/// Sellers behind a DNS name, each describing itself at `/agent`
+pub struct DnsRegistry {
+    name: String,
+    client: HttpClient,
+    /// `https` when agents are set up with TLS
+    scheme: &'static str,
+    /// JSON file of [`TrustedAgent`]s, read on every lookup
+    trusted_agents: PathBuf,
+}
+
+impl DnsRegistry {
+    /// `name` is an SRV name, starting with `_`, or a `host:port`. Only the
+    /// agents listed in `trusted_agents` are found.
+    pub fn new(name: String, client: HttpClient, trusted_agents: impl Into<PathBuf>) -> Self {
+        let scheme = if client.tls_fingerprint().is_some() { "https" } else { "http" };
+        Self { name, client, scheme, trusted_agents: trusted_agents.into() }
+    }
+
+    fn trusted_agents(&self) -> Result<Vec<TrustedAgent>> {
+        let listing = std::fs::read_to_string(&self.trusted_agents).map_err(|e| {
+            NegotiationError::Io(format!("Failed to read trusted agents {}: {}", self.trusted_agents.display(), e))
+        })?;
+        Ok(serde_json::from_str(&listing)?)
+    }
+
+    async fn endpoints(&self) -> Result<Vec<String>> {
+        if self.name.starts_with('_') {
+            let records = lookup_srv(&self.name).await?;
+            return Ok(records.iter()
+                .map(|record| format!("{}://{}:{}", self.scheme, record.target, record.port))
+                .collect());
+        }
+        let addresses = tokio::net::lookup_host(&self.name).await
+            .map_err(|e| NegotiationError::Io(format!("Failed to resolve {}: {}", self.name, e)))?;
+        Ok(addresses.map(|address| format!("{}://{}", self.scheme, address)).collect())
+    }
+
+    /// Every trusted agent that answers, listed at the endpoint it was
+    /// found at with the key, reputation and fingerprint it's trusted with.
+    /// Pods that don't answer or aren't trusted are left out.
+    async fn agents(&self) -> Result<Vec<AgentInfo>> {
+        let trusted = self.trusted_agents()?;
+        let endpoints = self.endpoints().await?;
+        let described = join_all(endpoints.iter().map(|endpoint| self.describe(endpoint))).await;
+        Ok(endpoints.into_iter().zip(described)
+            .filter_map(|(endpoint, agent)| {
+                let agent = agent
+                    .inspect_err(|e| tracing::warn!("Skipping {} found through {}: {}", endpoint, self.name, e))
+                    .ok()?;
+                let Some(trust) = trusted.iter().find(|trust| trust.id == agent.id) else {
+                    tracing::warn!("Skipping agent {} at {}: it isn't among the trusted agents", agent.id, endpoint);
+                    return None;
+                };
+                Some(AgentInfo {
+                    endpoint,
+                    public_key: trust.public_key.clone(),
+                    reputation_score: trust.reputation_score,
+                    tls_fingerprint: trust.tls_fingerprint.clone(),
+                    ..agent
+                })
+            })
+            .collect())
+    }
+
+    async fn describe(&self, endpoint: &str) -> Result<AgentInfo> {
+        let response = self.client
+            .get(format!("{}/agent", endpoint))
+            .header(PROTOCOL_VERSION_HEADER, CURRENT_VERSION.to_string())
+            .send()
+            .await?;
+        Ok(response.error_for_status()?.json().await?)
+    }
+}
+
+#[async_trait]
+impl Registry for DnsRegistry {
+    fn name(&self) -> &'static str {
+        "dns"
+    }
+
+    async fn register(&self, agent: AgentInfo, _compliance: ComplianceProfile) -> Result<()> {
+        tracing::debug!("Not registering {}: agents are found through {}", agent.id, self.name);
+        Ok(())
+    }
+
+    async fn deregister(&self, _agent_id: AgentId, _endpoint: &str) -> Result<()> {
+        Ok(())
+    }
+
+    async fn search_sellers(&self, _request: &SearchRequest) -> Result<Vec<AgentInfo>> {
+        Ok(self.agents().await?.into_iter().filter(|agent| matches!(agent.agent_type, AgentType::Seller)).collect())
+    }
+
+    async fn get_agent(&self, agent_id: AgentId) -> Result<AgentInfo> {
+        self.agents().await?.into_iter()
+            .find(|agent| agent.id == agent_id)
+            .ok_or(NegotiationError::AgentNotFound(agent_id))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct SrvRecord {
+    priority: u16,
+    weight: u16,
+    port: u16,
+    target: String,
+}
+
+/// SRV records for `name`, asked of each nameserver in [`RESOLV_CONF`] in
+/// turn until one answers. `name` is not expanded with the search domains.
+async fn lookup_srv(name: &str) -> Result<Vec<SrvRecord>> {
+    let resolv_conf = std::fs::read_to_string(RESOLV_CONF)
+        .map_err(|e| NegotiationError::Io(format!("Failed to read {}: {}", RESOLV_CONF, e)))?;
+    let nameservers: Vec<SocketAddr> = nameservers(&resolv_conf).into_iter()
+        .map(|nameserver| (nameserver, 53).into())
+        .collect();
+    lookup_srv_at(&nameservers, name).await
+}
+
+async fn lookup_srv_at(nameservers: &[SocketAddr], name: &str) -> Result<Vec<SrvRecord>> {
+    let mut failure = NegotiationError::Config(format!("No nameserver in {}", RESOLV_CONF));
+    for nameserver in nameservers {
+        match query_srv(*nameserver, name).await {
+            Ok(records) => return Ok(records),
+            Err(e) => {
+                tracing::debug!("Nameserver {} didn't answer for {}: {}", nameserver, name, e);
+                failure = e;
+            }
+        }
+    }
+    Err(failure)
+}
+
+async fn query_srv(nameserver: SocketAddr, name: &str) -> Result<Vec<SrvRecord>> {
+    let local: SocketAddr = match nameserver.ip() {
+        IpAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        IpAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
+    let io_error = |e: std::io::Error| NegotiationError::Io(format!("DNS lookup of {} failed: {}", name, e));
+
+    let socket = UdpSocket::bind(local).await.map_err(io_error)?;
+    socket.connect(nameserver).await.map_err(io_error)?;
+    let id = rand::random();
+    socket.send(&srv_query(id, name)?).await.map_err(io_error)?;
+    let mut response = vec![0; MAX_DNS_PAYLOAD];
+    let length = tokio::time::timeout(DNS_TIMEOUT, socket.recv(&mut response)).await
+        .map_err(|_| NegotiationError::Io(format!("DNS lookup of {} timed out", name)))?
+        .map_err(io_error)?;
+    parse_srv_response(id, &response[..length])
+}
+
+fn nameservers(resolv_conf: &str) -> Vec<IpAddr> {
+    resolv_conf.lines()
+        .filter_map(|line| line.trim().strip_prefix("nameserver"))
+        .filter_map(|address| address.trim().parse().ok())
+        .collect()
+}
+
+fn srv_query(id: u16, name: &str) -> Result<Vec<u8>> {
+    let mut query = Vec::with_capacity(64);
+    query.extend_from_slice(&id.to_be_bytes());
+    // Recursion desired; one question, and an EDNS record among the additionals
+    query.extend_from_slice(&[0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 1]);
+    for label in name.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(NegotiationError::Config(format!("Invalid DNS name {}", name)));
+        }
+        query.push(label.len() as u8);
+        query.extend_from_slice(label.as_bytes());
+    }
+    query.push(0);
+    query.extend_from_slice(&SRV_TYPE.to_be_bytes());
+    query.extend_from_slice(&[0, 1]);
+    // OPT record raising the UDP payload limit from 512 bytes
+    let payload = (MAX_DNS_PAYLOAD as u16).to_be_bytes();
+    query.extend_from_slice(&[0, 0, 41, payload[0], payload[1], 0, 0, 0, 0, 0, 0]);
+    Ok(query)
+}
+
+fn malformed() -> NegotiationError {
+    NegotiationError::Io("Malformed DNS response".to_string())
+}
+
+fn read_u16(message: &[u8], at: usize) -> Result<u16> {
+    let bytes = message.get(at..at + 2).ok_or_else(malformed)?;
+    Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+/// The name at `at`, following compression pointers, and the position
+/// just after it.
+fn read_name(message: &[u8], mut at: usize) -> Result<(String, usize)> {
+    let mut labels = Vec::new();
+    let mut end = None;
+    // Bounds the pointers followed, so a looping response can't hang us
+    for _ in 0..message.len() {
+        let length = *message.get(at).ok_or_else(malformed)? as usize;
+        if length & 0xC0 == 0xC0 {
+            let low = *message.get(at + 1).ok_or_else(malformed)? as usize;
+            end.get_or_insert(at + 2);
+            at = ((length & 0x3F) << 8) | low;
+        } else if length == 0 {
+            return Ok((labels.join("."), end.unwrap_or(at + 1)));
+        } else {
+            let label = message.get(at + 1..at + 1 + length).ok_or_else(malformed)?;
+            labels.push(String::from_utf8_lossy(label).into_owned());
+            at += 1 + length;
+        }
+    }
+    Err(malformed())
+}
+
+/// SRV records in the answer to query `id`, most preferred first. A name
+/// that doesn't exist has none.
+fn parse_srv_response(id: u16, message: &[u8]) -> Result<Vec<SrvRecord>> {
+    if read_u16(message, 0)? != id {
+        return Err(NegotiationError::Io("DNS response doesn't match the query".to_string()));
+    }
+    let flags = read_u16(message, 2)?;
+    match flags & 0x000F {
+        0 => {}
+        3 => return Ok(Vec::new()),
+        rcode => return Err(NegotiationError::Io(format!("DNS lookup failed with response code {}", rcode))),
+    }
+    if flags & 0x0200 != 0 {
+        tracing::warn!("DNS response truncated; some agents may be missing");
+    }
+
+    let mut at = 12;
+    for _ in 0..read_u16(message, 4)? {
+        at = read_name(message, at)?.1 + 4;
+    }
+    let mut records = Vec::new();
+    for _ in 0..read_u16(message, 6)? {
+        at = read_name(message, at)?.1;
+        let kind = read_u16(message, at)?;
+        let data = at + 10;
+        at = data + read_u16(message, at + 8)? as usize;
+        if at > message.len() {
+            return Err(malformed());
+        }
+        if kind == SRV_TYPE {
+            let (target, _) = read_name(message, data + 6)?;
+            // "." means the service isn't offered
+            if !target.is_empty() {
+                records.push(SrvRecord {
+                    priority: read_u16(message, data)?,
+                    weight: read_u16(message, data + 2)?,
+                    port: read_u16(message, data + 4)?,
+                    target,
+                });
+            }
+        }
+    }
+    records.sort_by_key(|record| (record.priority, std::cmp::Reverse(record.weight)));
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::PaymentMethod;
+    use axum::{routing::get, Json, Router};
+    use chrono::Utc;
+
+    fn seller(endpoint: &str) -> AgentInfo {
+        AgentInfo {
+            id: uuid::Uuid::new_v4(),
+            agent_type: AgentType::Seller,
+            name: "TechSeller".to_string(),
+            endpoint: endpoint.to_string(),
+            public_key: "key".to_string(),
+            reputation_score: 100,
+            products: Vec::new(),
+            payment_methods: vec![PaymentMethod::Stripe],
+            protocol_versions: Vec::new(),
+            preferred_languages: Vec::new(),
+            tls_fingerprint: None,
+            created_at: Utc::now(),
+            last_active: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_parses_srv_answers_with_compressed_names() {
+        let id = 0x1234;
+        let mut response = srv_query(id, "_http._tcp.sellers.local").unwrap();
+        // Answer flags, with two answers and no additionals
+        response[2..12].copy_from_slice(&[0x81, 0x80, 0, 1, 0, 2, 0, 0, 0, 0]);
+        response.truncate(response.len() - 11);
+        // The question's name starts at 12; "sellers.local" at 12 + 11
+        for (priority, port, pod) in [(10u16, 8001u16, "seller-1"), (0, 8002, "seller-0")] {
+            response.extend_from_slice(&[0xC0, 12, 0, 33, 0, 1, 0, 0, 0, 30]);
+            response.extend_from_slice(&((6 + 1 + pod.len() + 2) as u16).to_be_bytes());
+            response.extend_from_slice(&priority.to_be_bytes());
+            response.extend_from_slice(&[0, 10]);
+            response.extend_from_slice(&port.to_be_bytes());
+            response.push(pod.len() as u8);
+            response.extend_from_slice(pod.as_bytes());
+            response.extend_from_slice(&[0xC0, 23]);
+        }
+
+        let records = parse_srv_response(id, &response).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0], SrvRecord { priority: 0, weight: 10, port: 8002, target: "seller-0.sellers.local".to_string() });
+        assert_eq!(records[1].target, "seller-1.sellers.local");
+        assert!(parse_srv_response(id + 1, &response).is_err());
+        assert!(parse_srv_response(id, &response[..response.len() - 3]).is_err());
+        assert_eq!(
+            nameservers("search dcap.svc.cluster.local\nnameserver 10.96.0.10\nnameserver 10.96.0.11\n"),
+            vec!["10.96.0.10".parse::<IpAddr>().unwrap(), "10.96.0.11".parse().unwrap()]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_srv_lookups_move_on_to_the_next_nameserver() {
+        let down = std::net::UdpSocket::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let nameserver = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let up = nameserver.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut query = vec![0; MAX_DNS_PAYLOAD];
+            let (length, from) = nameserver.recv_from(&mut query).await.unwrap();
+            // Answer with no records, leaving out the EDNS record
+            let mut response = query[..length - 11].to_vec();
+            response[2..12].copy_from_slice(&[0x81, 0x80, 0, 1, 0, 0, 0, 0, 0, 0]);
+            nameserver.send_to(&response, from).await.unwrap();
+        });
+
+        let records = lookup_srv_at(&[down, up], "_http._tcp.sellers.local").await.unwrap();
+        assert!(records.is_empty());
+        assert!(lookup_srv_at(&[down], "_http._tcp.sellers.local").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_dns_registry_lists_sellers_at_the_address_found() {
+        let described = AgentInfo {
+            tls_fingerprint: Some("self-reported".to_string()),
+            ..seller("http://localhost:8001")
+        };
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let app = Router::new().route("/agent", get({
+            let described = described.clone();
+            move || async move { Json(described) }
+        }));
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let trusted = tempfile::NamedTempFile::new().unwrap();
+        let trust = TrustedAgent {
+            id: described.id,
+            public_key: "trusted-key".to_string(),
+            reputation_score: 70,
+            tls_fingerprint: None,
+        };
+        std::fs::write(trusted.path(), serde_json::to_string(&[trust]).unwrap()).unwrap();
+        let registry = DnsRegistry::new(address.to_string(), HttpClient::default(), trusted.path());
+        let request = SearchRequest {
+            category: None,
+            min_reputation: None,
+            payment_methods: None,
+            region: None,
+            max_response_time_ms: None,
+            rank_by_responsiveness: false,
+        };
+        let sellers = registry.search_sellers(&request).await.unwrap();
+        assert_eq!(sellers.len(), 1);
+        assert_eq!(sellers[0].endpoint, format!("http://{}", address));
+        // What the seller says about itself is only taken for what it sells
+        assert_eq!(sellers[0].public_key, "trusted-key");
+        assert_eq!(sellers[0].reputation_score, 70);
+        assert_eq!(sellers[0].tls_fingerprint, None);
+        assert_eq!(registry.get_agent(described.id).await.unwrap().name, "TechSeller");
+
+        let untrusted = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(untrusted.path(), "[]").unwrap();
+        let registry = DnsRegistry::new(address.to_string(), HttpClient::default(), untrusted.path());
+        assert!(registry.search_sellers(&request).await.unwrap().is_empty());
+
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), serde_json::to_string(&[seller("http://seller-0:8001")]).unwrap()).unwrap();
+        let listed = StaticRegistry::new(file.path()).search_sellers(&request).await.unwrap();
+        assert_eq!(listed[0].endpoint, "http://seller-0:8001");
+    }
+}
@@ -0,0 +1,292 @@
+//! Layered configuration, reloaded while services run.
+//!
+//! A [`ConfigSource`] builds an [`AppConfig`] from three layers, each
+//! overriding the one before:
+//!
+//! 1. the TOML config file, or the defaults when there is none;
+//! 2. environment variables named `DCAP__` followed by the field's path,
+//!    sections separated by `__`: `DCAP__TRUST__MIN_REPUTATION_THRESHOLD=60`;
+//! 3. `--set` flags taking a dotted path: `--set logging.level=debug`.
+//!
+//! Values are read as TOML, so numbers, booleans, arrays and inline tables
+//! work as they would in the file; anything that isn't valid TOML is taken
+//! as a string, so `DCAP__LOGGING__LEVEL=debug` needs no quotes. Quote a
+//! string that would otherwise read as a number, e.g. `'"12345"'`.
+//!
+//! A [`ConfigReloader`] loads the layers again on SIGHUP or when the file
+//! changes and publishes the result on a watch channel. Services subscribe
+//! for the settings they can apply in place, such as a seller's pricing
+//! and the reputation it asks of buyers; everything else still takes a
+//! restart. A config that fails to load or validate is logged and ignored,
+//! leaving the last good one in force.
+
+use crate::{
+    config::{AppConfig, ReloadConfig},
+    error::{NegotiationError, Result},
+};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+use tokio::{sync::watch, task::JoinHandle};
+
+/// Prefix of the environment variables overriding config fields
+pub const ENV_PREFIX: &str = "DCAP__";
+pub const DEFAULT_WATCH_INTERVAL_SECONDS: u64 = 5;
+
+/// Where a service's config comes from
+#[derive(Debug, Clone, Default)]
+pub struct ConfigSource {
+    path: Option<PathBuf>,
+    overrides: Vec<String>,
+}
+
+impl ConfigSource {
+    /// Starts from the file at `path`, or from the defaults without one.
+    pub fn new<P: AsRef<Path>>(path: Option<P>) -> Self {
+        Self {
+            path: path.map(|path| path.as_ref().to_path_buf()),
+            overrides: Vec::new(),
+        }
+    }
+
+    /// Applies `key=value` pairs from `--set` flags over the file and
+    /// environment.
+    pub fn with_overrides(mut self, overrides: Vec<String>) -> Self {
+        self.overrides = overrides;
+        self
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    pub fn load(&self) -> Result<AppConfig> {
+        self.load_with_env(std::env::vars())
+    }
+
+    fn load_with_env(&self, env: impl IntoIterator<Item = (String, String)>) -> Result<AppConfig> {
+        let mut layered = match &self.path {
+            Some(path) => {
+                let text = std::fs::read_to_string(path)
+                    .map_err(|e| NegotiationError::Config(format!("Failed to read config file: {}", e)))?;
+                toml::from_str::<toml::Table>(&text)
+                    .map_err(|e| NegotiationError::Config(format!("Failed to parse config file: {}", e)))?
+            }
+            None => toml::Table::try_from(AppConfig::default())
+                .map_err(|e| NegotiationError::Config(format!("Failed to serialize default config: {}", e)))?,
+        };
+
+        for (name, value) in env {
+            if let Some(field) = name.strip_prefix(ENV_PREFIX) {
+                let keys: Vec<String> = field.split("__").map(str::to_ascii_lowercase).collect();
+                set_field(&mut layered, &keys, &value)
+                    .map_err(|e| NegotiationError::Config(format!("{}: {}", name, e)))?;
+            }
+        }
+
+        for assignment in &self.overrides {
+            let (key, value) = assignment.split_once('=').ok_or_else(|| {
+                NegotiationError::Config(format!("Expected key=value, got {}", assignment))
+            })?;
+            let keys: Vec<String> = key.trim().split('.').map(str::to_string).collect();
+            set_field(&mut layered, &keys, value)
+                .map_err(|e| NegotiationError::Config(format!("--set {}: {}", assignment, e)))?;
+        }
+
+        toml::Value::Table(layered).try_into()
+            .map_err(|e| NegotiationError::Config(format!("Failed to parse config: {}", e)))
+    }
+
+    fn modified(&self) -> Option<SystemTime> {
+        let path = self.path.as_ref()?;
+        std::fs::metadata(path).ok()?.modified().ok()
+    }
+}
+
+/// Sets the field at `keys`, creating any sections missing on the way.
+fn set_field(table: &mut toml::Table, keys: &[String], value: &str) -> std::result::Result<(), String> {
+    if keys.iter().any(|key| key.is_empty()) {
+        return Err("empty key".to_string());
+    }
+    let Some((field, sections)) = keys.split_last() else {
+        return Err("empty key".to_string());
+    };
+    let mut table = table;
+    for section in sections {
+        table = match table.entry(section.clone()).or_insert(toml::Value::Table(toml::Table::new())) {
+            toml::Value::Table(inner) => inner,
+            _ => return Err(format!("{} is not a section", section)),
+        };
+    }
+    table.insert(field.clone(), parse_value(value));
+    Ok(())
+}
+
+fn parse_value(raw: &str) -> toml::Value {
+    toml::from_str::<toml::Table>(&format!("value = {}", raw))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(raw.to_string()))
+}
+
+/// Publishes each new config loaded from a [`ConfigSource`]
+#[derive(Clone)]
+pub struct ConfigReloader {
+    source: Arc<ConfigSource>,
+    sender: Arc<watch::Sender<Arc<AppConfig>>>,
+}
+
+impl ConfigReloader {
+    /// Starts out publishing `config`, already loaded from `source`.
+    pub fn new(source: ConfigSource, config: AppConfig) -> Self {
+        let (sender, _) = watch::channel(Arc::new(config));
+        Self {
+            source: Arc::new(source),
+            sender: Arc::new(sender),
+        }
+    }
+
+    /// Sees the current config, and is told of each new one.
+    pub fn subscribe(&self) -> watch::Receiver<Arc<AppConfig>> {
+        self.sender.subscribe()
+    }
+
+    pub fn current(&self) -> Arc<AppConfig> {
+        self.sender.borrow().clone()
+    }
+
+    /// Loads the layers again and publishes the config if anything in it
+    /// changed, returning whether it did. Fails, publishing nothing, if
+    /// the config doesn't load or validate.
+    pub fn reload(&self) -> Result<bool> {
+        let config = self.source.load()?;
+        config.validate()?;
+        let fingerprint = serde_json::to_value(&config).ok();
+        Ok(self.sender.send_if_modified(|current| {
+            if fingerprint.is_some() && serde_json::to_value(current.as_ref()).ok() == fingerprint {
+                return false;
+            }
+            *current = Arc::new(config);
+            true
+        }))
+    }
+
+    /// Reloads whenever the process gets a SIGHUP, and when the config
+    /// file's modification time changes, checked as often as `config`
+    /// says.
+    pub fn watch(&self, config: &ReloadConfig) -> JoinHandle<()> {
+        let reloader = self.clone();
+        let interval_seconds = config.watch_interval_seconds;
+        tokio::spawn(async move {
+            let mut hangups = Hangups::new();
+            let mut interval = tokio::time::interval(Duration::from_secs(interval_seconds.max(1)));
+            let mut modified = reloader.source.modified();
+            loop {
+                tokio::select! {
+                    _ = hangups.recv() => tracing::info!("Reloading configuration on SIGHUP"),
+                    _ = interval.tick(), if interval_seconds > 0 => {
+                        let latest = reloader.source.modified();
+                        if latest == modified {
+                            continue;
+                        }
+                        modified = latest;
+                    }
+                }
+                match reloader.reload() {
+                    Ok(true) => tracing::info!("Configuration reloaded"),
+                    Ok(false) => tracing::debug!("Configuration unchanged"),
+                    Err(e) => tracing::warn!("Keeping the current configuration: {}", e),
+                }
+            }
+        })
+    }
+}
+
+/// SIGHUPs sent to the process; never fires where there are none
+struct Hangups {
+    #[cfg(unix)]
+    signal: Option<tokio::signal::unix::Signal>,
+}
+
+impl Hangups {
+    fn new() -> Self {
+        #[cfg(unix)]
+        {
+            let signal = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())
+                .inspect_err(|e| tracing::error!("Failed to listen for SIGHUP: {}", e))
+                .ok();
+            Self { signal }
+        }
+        #[cfg(not(unix))]
+        Self {}
+    }
+
+    async fn recv(&mut self) {
+        #[cfg(unix)]
+        if let Some(signal) = &mut self.signal {
+            if signal.recv().await.is_some() {
+                return;
+            }
+        }
+        std::future::pending::<()>().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_flags_override_env_which_overrides_the_file() {
+        let file = NamedTempFile::new().unwrap();
+        let mut config = AppConfig::default();
+        config.logging.level = "warn".to_string();
+        std::fs::write(file.path(), toml::to_string(&config).unwrap()).unwrap();
+
+        let env = [
+            ("DCAP__SERVER__PORT", "9000"),
+            ("DCAP__TRUST__MIN_REPUTATION_THRESHOLD", "60"),
+            ("DCAP__LOGGING__LEVEL", "info"),
+            ("DCAP_ADMIN_TOKEN", "not a field"),
+        ];
+        let source = ConfigSource::new(Some(file.path()))
+            .with_overrides(vec!["trust.min_reputation_threshold=70".to_string()]);
+        let loaded = source
+            .load_with_env(env.iter().map(|(name, value)| (name.to_string(), value.to_string())))
+            .unwrap();
+        assert_eq!(loaded.server.port, 9000);
+        assert_eq!(loaded.logging.level, "info");
+        assert_eq!(loaded.trust.min_reputation_threshold, Some(70));
+
+        let bad = ConfigSource::new(Some(file.path())).with_overrides(vec!["server.port=high".to_string()]);
+        assert!(bad.load_with_env(Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_reload_publishes_only_valid_changes() {
+        let file = NamedTempFile::new().unwrap();
+        let mut config = AppConfig::default();
+        config.trust.jwt_secret = Some("test-secret".to_string());
+        std::fs::write(file.path(), toml::to_string(&config).unwrap()).unwrap();
+        let source = ConfigSource::new(Some(file.path()));
+        let reloader = ConfigReloader::new(source.clone(), source.load().unwrap());
+        let mut updates = reloader.subscribe();
+
+        assert!(!reloader.reload().unwrap());
+        assert!(!updates.has_changed().unwrap());
+
+        config.trust.min_reputation_threshold = Some(65);
+        std::fs::write(file.path(), toml::to_string(&config).unwrap()).unwrap();
+        assert!(reloader.reload().unwrap());
+        assert!(updates.has_changed().unwrap());
+        assert_eq!(updates.borrow_and_update().trust.min_reputation_threshold, Some(65));
+
+        config.server.port = 0;
+        std::fs::write(file.path(), toml::to_string(&config).unwrap()).unwrap();
+        assert!(reloader.reload().is_err());
+        assert_eq!(reloader.current().server.port, 8000);
+    }
+}
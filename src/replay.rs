@@ -0,0 +1,329 @@
+//! Negotiation replays for support and dispute handling.
+//!
+//! [`replay`] reads everything the database holds about one negotiation: the
+//! RFQ that opened it, every message, the quotes sellers sent, deal changes,
+//! the seller's obligation, escrow holds, payments and the record it closed
+//! with. It puts them on one timeline in time order. Reputation changes aren't
+//! stored per negotiation, so the timeline derives them from the outcome by
+//! the rules the buyer agent applies. Served by the settlement service's
+//! `/admin/negotiations/:negotiation_id/replay` endpoint and `dcap replay`.
+
+use crate::{
+    agent::{REJECTED_QUOTE_REPUTATION, SETTLED_BUYER_REPUTATION, SETTLED_SELLER_REPUTATION},
+    cancellation::{ChangeStatus, DealChange},
+    database::Database,
+    error::Result,
+    model::{Negotiation, NegotiationMessage, NegotiationRecord, NegotiationStatus, Quote},
+    obligation::{ObligationStatus, PenaltyObligation},
+    settlement::{EscrowHold, PaymentRecord, ShipmentProof},
+    AgentId, TransactionId,
+};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+/// One negotiation's history, oldest event first
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NegotiationReplay {
+    /// The negotiation as it stands, without its messages, which are on the timeline
+    pub negotiation: Negotiation,
+    pub timeline: Vec<TimelineEntry>,
+    pub generated_at: DateTime<Utc>,
+}
+
+impl NegotiationReplay {
+    /// Net reputation change per agent over the negotiation.
+    pub fn reputation_changes(&self) -> Vec<(AgentId, i32)> {
+        let mut changes: Vec<(AgentId, i32)> = Vec::new();
+        for entry in &self.timeline {
+            if let ReplayEvent::Reputation { agent_id, change, .. } = &entry.event {
+                match changes.iter_mut().find(|(id, _)| id == agent_id) {
+                    Some((_, total)) => *total += change,
+                    None => changes.push((*agent_id, *change)),
+                }
+            }
+        }
+        changes
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimelineEntry {
+    pub at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub event: ReplayEvent,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ReplayEvent {
+    /// The buyer's RFQ opened the negotiation
+    Rfq {
+        rfq_id: TransactionId,
+        buyer_id: AgentId,
+        seller_id: AgentId,
+        product_id: String,
+        quantity: u32,
+        opening_bid: Decimal,
+        currency: String,
+    },
+    Message { message: NegotiationMessage },
+    Quote { quote: Quote },
+    DealChangeRequested { change: DealChange },
+    DealChangeResolved { change_id: uuid::Uuid, status: ChangeStatus },
+    /// The seller committed to a firm or binding quote
+    ObligationOpened { obligation: PenaltyObligation },
+    ObligationResolved {
+        status: ObligationStatus,
+        slashed: Decimal,
+        breach_reason: Option<String>,
+    },
+    EscrowHeld { escrow: EscrowHold },
+    Shipped { escrow_id: uuid::Uuid, shipment_proof: Option<ShipmentProof> },
+    DeliveryConfirmed { escrow_id: uuid::Uuid },
+    Payment { payment: PaymentRecord },
+    PaymentCompleted { payment_id: String },
+    /// The negotiation reached its final status. Settled deals also carry the
+    /// negotiation record, with the ID anchoring proofs refer to.
+    Closed {
+        status: NegotiationStatus,
+        close_price: Option<Decimal>,
+        delta: Option<Decimal>,
+        record_id: Option<i64>,
+        record: Option<NegotiationRecord>,
+    },
+    Reputation { agent_id: AgentId, change: i32, reason: String },
+}
+
+/// Reconstructs the negotiation's timeline, or `None` if there's no such
+/// negotiation.
+pub async fn replay(database: &Database, negotiation_id: TransactionId) -> Result<Option<NegotiationReplay>> {
+    let Some(negotiation) = database.get_negotiation(negotiation_id).await? else {
+        return Ok(None);
+    };
+
+    let mut timeline = vec![TimelineEntry {
+        at: negotiation.created_at,
+        event: ReplayEvent::Rfq {
+            rfq_id: negotiation.rfq_id,
+            buyer_id: negotiation.buyer_id,
+            seller_id: negotiation.seller_id,
+            product_id: negotiation.product_id.clone(),
+            quantity: negotiation.quantity,
+            opening_bid: negotiation.opening_bid,
+            currency: negotiation.currency.clone(),
+        },
+    }];
+    let mut push = |at, event| timeline.push(TimelineEntry { at, event });
+
+    for message in negotiation.messages.iter().cloned() {
+        push(message.created_at, ReplayEvent::Message { message });
+    }
+    for quote in database.get_quotes_for_rfq(negotiation.rfq_id, None).await? {
+        push(quote.created_at, ReplayEvent::Quote { quote });
+    }
+
+    for change in database.get_deal_changes(negotiation_id).await? {
+        if let (Some(resolved_at), ChangeStatus::Approved | ChangeStatus::Declined) = (change.resolved_at, &change.status) {
+            push(resolved_at, ReplayEvent::DealChangeResolved { change_id: change.id, status: change.status });
+        }
+        if let (ChangeStatus::Approved, Some(penalised)) = (&change.status, change.penalised) {
+            if change.reputation_penalty > 0 {
+                push(change.resolved_at.unwrap_or(change.created_at), ReplayEvent::Reputation {
+                    agent_id: penalised,
+                    change: -(change.reputation_penalty as i32),
+                    reason: "deal change".to_string(),
+                });
+            }
+        }
+        push(change.created_at, ReplayEvent::DealChangeRequested { change });
+    }
+
+    if let Some(obligation) = database.get_obligation(negotiation_id).await? {
+        if let Some(resolved_at) = obligation.resolved_at {
+            push(resolved_at, ReplayEvent::ObligationResolved {
+                status: obligation.status.clone(),
+                slashed: obligation.slashed,
+                breach_reason: obligation.breach_reason.clone(),
+            });
+            if obligation.status == ObligationStatus::Breached {
+                push(resolved_at, ReplayEvent::Reputation {
+                    agent_id: obligation.seller_id,
+                    change: obligation.reputation_penalty(),
+                    reason: "reneged on quote".to_string(),
+                });
+            }
+        }
+        push(obligation.created_at, ReplayEvent::ObligationOpened { obligation });
+    }
+
+    for escrow in database.get_escrow_holds_for_transaction(negotiation_id).await? {
+        if let Some(shipped_at) = escrow.delivery.shipped_at {
+            push(shipped_at, ReplayEvent::Shipped { escrow_id: escrow.id, shipment_proof: escrow.delivery.shipment_proof.clone() });
+        }
+        if let Some(confirmed_at) = escrow.delivery.confirmed_at {
+            push(confirmed_at, ReplayEvent::DeliveryConfirmed { escrow_id: escrow.id });
+        }
+        push(escrow.created_at, ReplayEvent::EscrowHeld { escrow });
+    }
+    for payment in database.get_payments_for_transaction(negotiation_id).await? {
+        if let Some(completed_at) = payment.completed_at {
+            push(completed_at, ReplayEvent::PaymentCompleted { payment_id: payment.payment_id.clone() });
+        }
+        push(payment.created_at, ReplayEvent::Payment { payment });
+    }
+
+    match negotiation.status {
+        NegotiationStatus::Settled => {
+            let (record_id, record) = database.get_negotiation_record_for(&negotiation).await?.unzip();
+            push(negotiation.updated_at, closed(&negotiation, record_id, record));
+            push(negotiation.updated_at, ReplayEvent::Reputation {
+                agent_id: negotiation.seller_id,
+                change: SETTLED_SELLER_REPUTATION,
+                reason: "settled".to_string(),
+            });
+            push(negotiation.updated_at, ReplayEvent::Reputation {
+                agent_id: negotiation.buyer_id,
+                change: SETTLED_BUYER_REPUTATION,
+                reason: "settled".to_string(),
+            });
+        }
+        NegotiationStatus::Rejected => {
+            push(negotiation.updated_at, closed(&negotiation, None, None));
+            push(negotiation.updated_at, ReplayEvent::Reputation {
+                agent_id: negotiation.seller_id,
+                change: REJECTED_QUOTE_REPUTATION,
+                reason: "quote rejected".to_string(),
+            });
+        }
+        NegotiationStatus::Expired | NegotiationStatus::Cancelled => {
+            push(negotiation.updated_at, closed(&negotiation, None, None));
+        }
+        _ => {}
+    }
+
+    // Stable, so events at the same instant keep the order above
+    timeline.sort_by_key(|entry| entry.at);
+    Ok(Some(NegotiationReplay {
+        negotiation,
+        timeline,
+        generated_at: Utc::now(),
+    }))
+}
+
+fn closed(negotiation: &Negotiation, record_id: Option<i64>, record: Option<NegotiationRecord>) -> ReplayEvent {
+    ReplayEvent::Closed {
+        status: negotiation.status.clone(),
+        close_price: negotiation.close_price,
+        delta: negotiation.delta,
+        record_id,
+        record,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{AgentInfo, AgentType, MessageType, QuoteFirmness, ShippingOption};
+    use chrono::Duration;
+    use std::collections::HashMap;
+    use tempfile::NamedTempFile;
+
+    async fn agent(database: &Database, agent_type: AgentType) -> AgentId {
+        let agent = AgentInfo {
+            id: uuid::Uuid::new_v4(),
+            agent_type,
+            name: "Agent".to_string(),
+            endpoint: "http://localhost:8001".to_string(),
+            public_key: "key".to_string(),
+            reputation_score: 50,
+            products: vec![],
+            payment_methods: vec![],
+            protocol_versions: vec![],
+            preferred_languages: vec![],
+            tls_fingerprint: None,
+            created_at: Utc::now(),
+            last_active: Utc::now(),
+        };
+        database.create_agent(&agent).await.unwrap();
+        agent.id
+    }
+
+    #[tokio::test]
+    async fn test_replay_orders_a_settled_negotiation() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let database = Database::new(&format!("sqlite://{}", temp_file.path().to_string_lossy())).await.unwrap();
+        let buyer_id = agent(&database, AgentType::Buyer).await;
+        let seller_id = agent(&database, AgentType::Seller).await;
+        let opened = Utc::now() - Duration::minutes(10);
+
+        let mut negotiation = Negotiation {
+            id: uuid::Uuid::new_v4(),
+            rfq_id: uuid::Uuid::new_v4(),
+            quote_id: None,
+            buyer_id,
+            seller_id,
+            product_id: "laptop-001".to_string(),
+            quantity: 2,
+            opening_bid: Decimal::new(900, 0),
+            currency: "USD".to_string(),
+            close_price: None,
+            delta: None,
+            status: NegotiationStatus::Pending,
+            messages: vec![],
+            created_at: opened,
+            updated_at: opened,
+            delegation_chain: vec![],
+        };
+        negotiation.add_message(buyer_id, MessageType::RFQ, "Two laptops".to_string(), opened);
+        database.create_negotiation(&negotiation).await.unwrap();
+
+        let quote = Quote {
+            id: uuid::Uuid::new_v4(),
+            rfq_id: negotiation.rfq_id,
+            seller_id,
+            price: Decimal::new(1000, 0),
+            currency: "USD".to_string(),
+            available_quantity: 5,
+            delivery_estimate: None,
+            ttl_seconds: 300,
+            firmness: QuoteFirmness::default(),
+            shipping_options: vec![ShippingOption { carrier: "UPS".to_string(), cost: Decimal::new(1250, 2), eta_days: 3 }],
+            metadata: HashMap::new(),
+            created_at: opened + Duration::minutes(1),
+            nonce: None,
+            signature: None,
+        };
+        database.create_quote(&quote).await.unwrap();
+        negotiation.add_message(buyer_id, MessageType::CounterOffer, "950?".to_string(), opened + Duration::minutes(2));
+        database.create_negotiation_message(negotiation.messages.last().unwrap()).await.unwrap();
+
+        negotiation.add_quote(&quote).unwrap();
+        negotiation.accept(Decimal::new(950, 0)).unwrap();
+        negotiation.settle().unwrap();
+        database.update_negotiation(&negotiation).await.unwrap();
+        database.add_negotiation_record(&negotiation.to_record().unwrap()).await.unwrap();
+
+        let replay = replay(&database, negotiation.id).await.unwrap().unwrap();
+        let events: Vec<_> = replay.timeline.iter()
+            .map(|entry| serde_json::to_value(entry).unwrap()["event"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(events, ["rfq", "message", "quote", "message", "closed", "reputation", "reputation"]);
+        assert!(replay.timeline.windows(2).all(|pair| pair[0].at <= pair[1].at));
+        match &replay.timeline[2].event {
+            ReplayEvent::Quote { quote: replayed } => assert_eq!(replayed.shipping_options, quote.shipping_options),
+            other => panic!("expected the quote, got {:?}", other),
+        }
+        match &replay.timeline[4].event {
+            ReplayEvent::Closed { status, record_id, record, .. } => {
+                assert_eq!(*status, NegotiationStatus::Settled);
+                assert!(record_id.is_some());
+                assert_eq!(record.as_ref().unwrap().close_price, Decimal::new(950, 0));
+            }
+            other => panic!("expected the negotiation to close, got {:?}", other),
+        }
+        assert_eq!(replay.reputation_changes(), [(seller_id, SETTLED_SELLER_REPUTATION), (buyer_id, SETTLED_BUYER_REPUTATION)]);
+
+        assert!(super::replay(&database, uuid::Uuid::new_v4()).await.unwrap().is_none());
+    }
+}
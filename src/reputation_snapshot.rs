@@ -0,0 +1,133 @@
+//! Signed snapshots of a trust system's reputations.
+//!
+//! [`TrustSystem::export_snapshot`](crate::trust::TrustSystem::export_snapshot)
+//! writes every fresh reputation record, with the changes behind it, and the
+//! trust activities logged in process into one JSON document signed with the
+//! operator's Ed25519 key. Importing one into another trust system, e.g. to
+//! migrate a registry or bootstrap a new one, checks the signature against
+//! the signer the operator expects and merges it in: a reputation is only
+//! taken if it's newer than the one held for the agent, and activities
+//! already held are skipped.
+
+use crate::{
+    error::{NegotiationError, Result},
+    recovery::verify_signature,
+    trust::{ReputationScore, TrustActivity},
+};
+use base64::{engine::general_purpose, Engine};
+use chrono::{DateTime, Utc};
+use ed25519_dalek::{Signer, SigningKey};
+use serde::{Deserialize, Serialize};
+
+/// Version of the snapshot format; other versions are refused
+pub const SNAPSHOT_FORMAT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotContents {
+    pub reputations: Vec<ReputationScore>,
+    /// Oldest first
+    pub activities: Vec<TrustActivity>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReputationSnapshot {
+    pub format_version: u32,
+    pub generated_at: DateTime<Utc>,
+    /// Base64 Ed25519 key of the operator who signed the snapshot
+    pub signer: String,
+    pub contents: SnapshotContents,
+    /// Base64 signature over the canonical JSON of the fields above
+    pub signature: String,
+}
+
+/// What an import merged in
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotImport {
+    /// Reputations newer than those held, and taken
+    pub reputations_imported: usize,
+    /// Reputations no newer than those held
+    pub reputations_kept: usize,
+    /// Activities not held before
+    pub activities_imported: usize,
+}
+
+impl ReputationSnapshot {
+    pub fn sign(key: &SigningKey, contents: SnapshotContents) -> Result<Self> {
+        let mut snapshot = Self {
+            format_version: SNAPSHOT_FORMAT_VERSION,
+            generated_at: Utc::now(),
+            signer: general_purpose::STANDARD.encode(key.verifying_key().to_bytes()),
+            contents,
+            signature: String::new(),
+        };
+        snapshot.signature = general_purpose::STANDARD.encode(key.sign(&snapshot.message()?).to_bytes());
+        Ok(snapshot)
+    }
+
+    /// Fails unless the snapshot is in a known format and signed by
+    /// `expected_signer`, a base64 public key.
+    pub fn verify(&self, expected_signer: &str) -> Result<()> {
+        if self.format_version != SNAPSHOT_FORMAT_VERSION {
+            return Err(NegotiationError::Validation(format!(
+                "Unsupported snapshot format version {}", self.format_version
+            )));
+        }
+        if self.signer != expected_signer.trim() {
+            return Err(NegotiationError::Auth(format!("Snapshot is signed by {}, not the expected signer", self.signer)));
+        }
+        verify_signature(&self.signer, &self.message()?, &self.signature)
+    }
+
+    /// Canonical JSON of everything but the signature, with object keys
+    /// sorted and no whitespace
+    fn message(&self) -> Result<Vec<u8>> {
+        let mut value = serde_json::to_value(self)?;
+        if let Some(fields) = value.as_object_mut() {
+            fields.remove("signature");
+        }
+        Ok(serde_json::to_vec(&value)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trust::TrustSystem;
+
+    #[tokio::test]
+    async fn test_snapshot_bootstraps_a_new_trust_system() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let signer = general_purpose::STANDARD.encode(key.verifying_key().to_bytes());
+        let (buyer, seller, newcomer) = (uuid::Uuid::new_v4(), uuid::Uuid::new_v4(), uuid::Uuid::new_v4());
+
+        let mut source = TrustSystem::new().unwrap();
+        source.seed_reputation(buyer, 70).await.unwrap();
+        source.record_successful_transaction(buyer, seller, None).await.unwrap();
+        let snapshot = source.export_snapshot(&key).await.unwrap();
+        assert_eq!(snapshot.contents.reputations.len(), 2);
+        assert_eq!(snapshot.contents.activities.len(), 4);
+
+        // Survives being written out and read back
+        let snapshot: ReputationSnapshot = serde_json::from_slice(&serde_json::to_vec_pretty(&snapshot).unwrap()).unwrap();
+        let mut target = TrustSystem::new().unwrap();
+        target.update_reputation(newcomer, 5).await.unwrap();
+        let imported = target.import_snapshot(&snapshot, &signer).await.unwrap();
+        assert_eq!(imported.reputations_imported, 2);
+        assert_eq!(imported.activities_imported, 4);
+        for agent_id in [buyer, seller] {
+            assert_eq!(target.get_reputation(agent_id).await.unwrap(), source.get_reputation(agent_id).await.unwrap());
+        }
+        assert_eq!(target.get_reputation(newcomer).await.unwrap(), 5);
+        assert_eq!(target.get_reputation_history(buyer).await.unwrap().len(), 2);
+
+        // Importing again changes nothing
+        let again = target.import_snapshot(&snapshot, &signer).await.unwrap();
+        assert_eq!(again, SnapshotImport { reputations_kept: 2, ..SnapshotImport::default() });
+
+        let mut tampered = snapshot.clone();
+        tampered.contents.reputations[0].baseline = Some(100);
+        assert!(target.import_snapshot(&tampered, &signer).await.is_err());
+        let stranger = general_purpose::STANDARD.encode(SigningKey::from_bytes(&[8; 32]).verifying_key().to_bytes());
+        assert!(target.import_snapshot(&snapshot, &stranger).await.is_err());
+    }
+}
@@ -0,0 +1,218 @@
+//! Seller response times.
+//!
+//! Buyers time how long sellers take to quote an RFQ and to answer a counter
+//! offer, from the timestamps of the messages in each negotiation, and report
+//! them to the registry. The registry keeps a rolling average of each per
+//! agent, scores it against a response-time SLA, and lets buyers filter and
+//! rank sellers by it.
+
+use crate::{
+    error::{NegotiationError, Result},
+    model::{MessageType, NegotiationMessage},
+    AgentId,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Responses the rolling averages roughly cover; older ones fade out
+pub const ROLLING_WINDOW: u32 = 10;
+
+/// Longest response time the registry accepts, a day
+pub const MAX_RESPONSE_MS: u64 = 86_400_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResponseKind {
+    /// From an RFQ to the seller's quote
+    Quote,
+    /// From a counter offer to the seller's counter quote
+    Counter,
+}
+
+/// One response time a buyer measured
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResponseTimeReport {
+    pub kind: ResponseKind,
+    pub response_ms: u64,
+}
+
+impl ResponseTimeReport {
+    pub fn validate(&self) -> Result<()> {
+        if self.response_ms > MAX_RESPONSE_MS {
+            return Err(NegotiationError::Validation(format!(
+                "Response times over {} ms aren't accepted", MAX_RESPONSE_MS
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Times a seller has to respond in to meet its SLA
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ResponseSla {
+    pub quote_ms: u64,
+    pub counter_ms: u64,
+}
+
+impl Default for ResponseSla {
+    fn default() -> Self {
+        Self {
+            quote_ms: 5_000,
+            counter_ms: 10_000,
+        }
+    }
+}
+
+/// An agent's rolling average response times
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResponseStats {
+    pub agent_id: AgentId,
+    pub quote_samples: u32,
+    pub average_quote_ms: f64,
+    pub counter_samples: u32,
+    pub average_counter_ms: f64,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl ResponseStats {
+    pub fn new(agent_id: AgentId) -> Self {
+        Self {
+            agent_id,
+            quote_samples: 0,
+            average_quote_ms: 0.0,
+            counter_samples: 0,
+            average_counter_ms: 0.0,
+            updated_at: Utc::now(),
+        }
+    }
+
+    /// Folds a response into the rolling average: a plain mean over the
+    /// first `ROLLING_WINDOW` responses, then an exponential one.
+    pub fn record(&mut self, kind: ResponseKind, response_ms: u64) {
+        let (samples, average) = match kind {
+            ResponseKind::Quote => (&mut self.quote_samples, &mut self.average_quote_ms),
+            ResponseKind::Counter => (&mut self.counter_samples, &mut self.average_counter_ms),
+        };
+        *samples = samples.saturating_add(1);
+        let weight = 1.0 / (*samples).min(ROLLING_WINDOW) as f64;
+        *average += (response_ms as f64 - *average) * weight;
+        self.updated_at = Utc::now();
+    }
+
+    pub fn average_ms(&self, kind: ResponseKind) -> Option<u64> {
+        match kind {
+            ResponseKind::Quote if self.quote_samples > 0 => Some(self.average_quote_ms.round() as u64),
+            ResponseKind::Counter if self.counter_samples > 0 => Some(self.average_counter_ms.round() as u64),
+            _ => None,
+        }
+    }
+
+    /// Both averages, weighted by how many responses each covers
+    pub fn average_response_time_ms(&self) -> Option<u64> {
+        let samples = self.quote_samples as f64 + self.counter_samples as f64;
+        if samples == 0.0 {
+            return None;
+        }
+        let total = self.average_quote_ms * self.quote_samples as f64
+            + self.average_counter_ms * self.counter_samples as f64;
+        Some((total / samples).round() as u64)
+    }
+
+    pub fn view(self, sla: &ResponseSla) -> ResponseTimesView {
+        ResponseTimesView {
+            average_response_time_ms: self.average_response_time_ms(),
+            sla_score: self.sla_score(sla),
+            stats: self,
+        }
+    }
+
+    /// 0-100: full marks while the averages are within the SLA, falling in
+    /// proportion as they run over it. `None` before any response is timed.
+    pub fn sla_score(&self, sla: &ResponseSla) -> Option<u32> {
+        let score = |average: f64, target: u64| if average <= target as f64 { 100.0 } else { 100.0 * target as f64 / average };
+        let samples = self.quote_samples as f64 + self.counter_samples as f64;
+        if samples == 0.0 {
+            return None;
+        }
+        let total = score(self.average_quote_ms, sla.quote_ms) * self.quote_samples as f64
+            + score(self.average_counter_ms, sla.counter_ms) * self.counter_samples as f64;
+        Some((total / samples).round() as u32)
+    }
+}
+
+/// An agent's response times as the registry serves them
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResponseTimesView {
+    #[serde(flatten)]
+    pub stats: ResponseStats,
+    pub average_response_time_ms: Option<u64>,
+    pub sla_score: Option<u32>,
+}
+
+/// The time `seller_id` took to answer the buyer, when the latest message is
+/// the seller's quote and the one before it the RFQ or counter offer it
+/// answers.
+pub fn last_response(messages: &[NegotiationMessage], seller_id: AgentId) -> Option<(ResponseKind, u64)> {
+    let [.., request, reply] = messages else {
+        return None;
+    };
+    if reply.sender_id != seller_id || !matches!(reply.message_type, MessageType::Quote) || request.sender_id == seller_id {
+        return None;
+    }
+    let kind = match request.message_type {
+        MessageType::RFQ => ResponseKind::Quote,
+        MessageType::CounterOffer => ResponseKind::Counter,
+        _ => return None,
+    };
+    let elapsed = (reply.created_at - request.created_at).num_milliseconds().max(0) as u64;
+    Some((kind, elapsed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(sender_id: AgentId, message_type: MessageType, at: DateTime<Utc>) -> NegotiationMessage {
+        NegotiationMessage {
+            id: uuid::Uuid::new_v4(),
+            negotiation_id: uuid::Uuid::new_v4(),
+            sender_id,
+            content: String::new(),
+            message_type,
+            created_at: at,
+        }
+    }
+
+    #[test]
+    fn test_response_times_roll_into_sla_score() {
+        let buyer_id = uuid::Uuid::new_v4();
+        let seller_id = uuid::Uuid::new_v4();
+        let start = Utc::now();
+        let mut messages = vec![
+            message(buyer_id, MessageType::RFQ, start),
+            message(seller_id, MessageType::Quote, start + chrono::Duration::milliseconds(1200)),
+        ];
+        assert_eq!(last_response(&messages, seller_id), Some((ResponseKind::Quote, 1200)));
+        messages.push(message(buyer_id, MessageType::CounterOffer, start + chrono::Duration::seconds(5)));
+        assert_eq!(last_response(&messages, seller_id), None);
+        messages.push(message(seller_id, MessageType::Quote, start + chrono::Duration::seconds(25)));
+        assert_eq!(last_response(&messages, seller_id), Some((ResponseKind::Counter, 20_000)));
+
+        let mut stats = ResponseStats::new(seller_id);
+        assert_eq!(stats.sla_score(&ResponseSla::default()), None);
+        stats.record(ResponseKind::Quote, 1000);
+        stats.record(ResponseKind::Quote, 3000);
+        assert_eq!(stats.average_ms(ResponseKind::Quote), Some(2000));
+        stats.record(ResponseKind::Counter, 20_000);
+        assert_eq!(stats.average_response_time_ms(), Some(8000));
+        // Quotes within the SLA, the counter at twice its target
+        assert_eq!(stats.sla_score(&ResponseSla::default()), Some(83));
+
+        // Past the window, old responses fade rather than count equally
+        for _ in 0..100 {
+            stats.record(ResponseKind::Quote, 500);
+        }
+        assert_eq!(stats.average_ms(ResponseKind::Quote), Some(500));
+    }
+}
@@ -0,0 +1,235 @@
+//! Roles carried in JWTs and the permissions they grant.
+//!
+//! Every JWT names a [`Role`] in its `role` claim: agents are issued tokens
+//! as buyers or sellers, operators as admins, and dashboards or auditors as
+//! observers, who can look but not act. A [`RolePolicy`] says which
+//! [`Permission`]s each role holds; the defaults below can be overridden per
+//! role under `[trust.roles]`. [`require_permission`] guards a router's
+//! routes with one permission, answering 401 without a valid token and 403
+//! when its role lacks the permission, and hands the checked claims to the
+//! handlers as an `Extension<JWTClaims>`.
+//!
+//! Sellers ask for `request_quotes` on `/quote`. Negotiation session tokens
+//! carry the role of the agent JWT they were traded for, so sellers check
+//! `negotiate` and the settlement service `settle` against it without the
+//! agent JWT.
+//!
+//! Tokens issued before roles carry `role = "agent"`. They are held to the
+//! `agent` entry of `[trust.roles]`, which grants nothing by default.
+
+use crate::{
+    error::{ApiResult, NegotiationError, Result},
+    session::bearer_token,
+    trust::TrustSystem,
+};
+use axum::{
+    extract::{Request, State},
+    middleware::{self, Next},
+    response::Response,
+    Router,
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fmt, str::FromStr, sync::Arc};
+use tokio::sync::RwLock;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Buyer,
+    Seller,
+    Admin,
+    Observer,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Buyer => "buyer",
+            Role::Seller => "seller",
+            Role::Admin => "admin",
+            Role::Observer => "observer",
+        }
+    }
+
+    /// What the role may do unless `[trust.roles]` says otherwise
+    pub fn default_permissions(&self) -> Vec<Permission> {
+        use Permission::*;
+        match self {
+            Role::Buyer => vec![RequestQuotes, Negotiate, Settle],
+            Role::Seller => vec![AnswerRfqs, Negotiate, Settle, ManageCatalog],
+            Role::Admin => vec![ViewMarket, Administer, AdjustReputation, ManageCatalog],
+            Role::Observer => vec![ViewMarket],
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Role {
+    type Err = NegotiationError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "buyer" => Ok(Role::Buyer),
+            "seller" => Ok(Role::Seller),
+            "admin" => Ok(Role::Admin),
+            "observer" => Ok(Role::Observer),
+            _ => Err(NegotiationError::Validation(format!("Unknown role {}", s))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    /// Send RFQs and publish demand signals
+    RequestQuotes,
+    /// Quote on RFQs and answer demand signals
+    AnswerRfqs,
+    Negotiate,
+    Settle,
+    /// Read market statistics, anomalies and negotiation histories
+    ViewMarket,
+    /// Block agents, expire quotes, refund escrow holds and replay dead letters
+    Administer,
+    /// Change a reputation by hand rather than through a deal's outcome
+    AdjustReputation,
+    /// Add, change and remove a seller's products
+    ManageCatalog,
+}
+
+impl Permission {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Permission::RequestQuotes => "request_quotes",
+            Permission::AnswerRfqs => "answer_rfqs",
+            Permission::Negotiate => "negotiate",
+            Permission::Settle => "settle",
+            Permission::ViewMarket => "view_market",
+            Permission::Administer => "administer",
+            Permission::AdjustReputation => "adjust_reputation",
+            Permission::ManageCatalog => "manage_catalog",
+        }
+    }
+}
+
+impl fmt::Display for Permission {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Which permissions each role holds
+#[derive(Debug, Clone)]
+pub struct RolePolicy {
+    permissions: HashMap<String, Vec<Permission>>,
+}
+
+impl Default for RolePolicy {
+    fn default() -> Self {
+        let roles = [Role::Buyer, Role::Seller, Role::Admin, Role::Observer];
+        Self {
+            permissions: roles.iter().map(|role| (role.to_string(), role.default_permissions())).collect(),
+        }
+    }
+}
+
+impl RolePolicy {
+    /// The default policy with `overrides` replacing the permissions of the
+    /// roles they name.
+    pub fn from_config(overrides: &HashMap<String, Vec<Permission>>) -> Self {
+        let mut policy = Self::default();
+        for (role, permissions) in overrides {
+            policy.permissions.insert(role.clone(), permissions.clone());
+        }
+        policy
+    }
+
+    pub fn allows(&self, role: &str, permission: Permission) -> bool {
+        self.permissions.get(role).is_some_and(|permissions| permissions.contains(&permission))
+    }
+
+    /// Fails unless `role`, as a token carries it, holds `permission`.
+    pub fn check(&self, role: &str, permission: Permission) -> Result<()> {
+        if self.allows(role, permission) {
+            Ok(())
+        } else {
+            Err(NegotiationError::Trust(format!("The {} role may not {}", role, permission)))
+        }
+    }
+}
+
+struct PermissionGate {
+    trust: Arc<RwLock<TrustSystem>>,
+    permission: Permission,
+}
+
+/// Guards every route of `router` with `permission`. Add it before
+/// `with_state`, after the routes it guards.
+pub fn require_permission<S>(router: Router<S>, trust: Arc<RwLock<TrustSystem>>, permission: Permission) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    let gate = Arc::new(PermissionGate { trust, permission });
+    router.route_layer(middleware::from_fn_with_state(gate, enforce))
+}
+
+async fn enforce(State(gate): State<Arc<PermissionGate>>, mut request: Request, next: Next) -> ApiResult<Response> {
+    let token = bearer_token(request.headers())
+        .ok_or_else(|| NegotiationError::Auth("Missing bearer token".to_string()))?;
+    let claims = gate.trust.read().await.authorize(token, gate.permission).await
+        .inspect_err(|e| tracing::warn!("Refused {} {}: {}", request.method(), request.uri().path(), e))?;
+    request.extensions_mut().insert(claims);
+    Ok(next.run(request).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trust::JWTClaims;
+    use chrono::Duration;
+
+    #[test]
+    fn test_config_overrides_a_roles_permissions() {
+        let policy = RolePolicy::default();
+        assert!(policy.allows("seller", Permission::AnswerRfqs));
+        assert!(!policy.allows("buyer", Permission::AnswerRfqs));
+        assert!(!policy.allows("observer", Permission::Negotiate));
+        assert!(!policy.allows("agent", Permission::ViewMarket));
+
+        let overrides = HashMap::from([
+            ("observer".to_string(), vec![]),
+            ("agent".to_string(), vec![Permission::ViewMarket]),
+        ]);
+        let policy = RolePolicy::from_config(&overrides);
+        assert!(!policy.allows("observer", Permission::ViewMarket));
+        assert!(policy.allows("agent", Permission::ViewMarket));
+        assert!(policy.allows("admin", Permission::AdjustReputation));
+    }
+
+    #[tokio::test]
+    async fn test_middleware_checks_the_tokens_role() {
+        use axum::{http::StatusCode, routing::get, Extension};
+
+        let trust = TrustSystem::new().unwrap();
+        let admin = trust.generate_role_jwt("ops@example.com", Role::Admin, Duration::minutes(5)).unwrap();
+        let observer = trust.generate_role_jwt("dashboard", Role::Observer, Duration::minutes(5)).unwrap();
+        let router = Router::new()
+            .route("/", get(|Extension(claims): Extension<JWTClaims>| async move { claims.sub }));
+        let router = require_permission(router, Arc::new(RwLock::new(trust)), Permission::Administer);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, router).await });
+
+        let client = reqwest::Client::new();
+        let response = client.get(&base).bearer_auth(&admin).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.text().await.unwrap(), "ops@example.com");
+        assert_eq!(client.get(&base).bearer_auth(&observer).send().await.unwrap().status(), StatusCode::FORBIDDEN);
+        assert_eq!(client.get(&base).send().await.unwrap().status(), StatusCode::UNAUTHORIZED);
+    }
+}
@@ -0,0 +1,240 @@
+//! Signal handling and graceful shutdown shared by the service binaries.
+//!
+//! On Ctrl-C or SIGTERM, [`serve`] stops accepting connections and lets the
+//! requests in progress finish, so a negotiation round or payment that had
+//! started isn't cut off halfway. Requests still running after `[server]
+//! shutdown_grace_seconds` are dropped. Once it returns, the binary runs its
+//! own shutdown hooks, such as deregistering from discovery and closing the
+//! database, before exiting.
+//!
+//! With `[server.tls]` set, connections are served over TLS, with the
+//! client's certificate passed to handlers as a
+//! [`PeerCertificate`](crate::tls::PeerCertificate).
+
+use crate::{
+    config::ServerConfig,
+    error::{NegotiationError, Result},
+    tls::{self, PeerCertificate, TlsIdentity},
+};
+use axum::{extract::ConnectInfo, Router};
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo},
+    server::{conn::auto, graceful::GracefulShutdown},
+};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio_rustls::TlsAcceptor;
+use tokio_util::sync::CancellationToken;
+use tower::Service;
+
+pub const DEFAULT_SHUTDOWN_GRACE_SECONDS: u64 = 30;
+
+/// Resolves when the process is asked to stop, by Ctrl-C or SIGTERM.
+pub async fn shutdown_signal() {
+    let interrupt = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::error!("Failed to listen for Ctrl-C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                tracing::error!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = interrupt => {},
+        _ = terminate => {},
+    }
+    tracing::info!("Shutdown requested");
+}
+
+/// Serves `app` until a shutdown signal, then drains it. Peer addresses are
+/// available to middleware such as `security::rate_limit`.
+pub async fn serve(listener: TcpListener, app: Router, config: &ServerConfig) -> Result<()> {
+    let grace = Duration::from_secs(config.shutdown_grace_seconds.unwrap_or(DEFAULT_SHUTDOWN_GRACE_SECONDS));
+    match &config.tls {
+        Some(tls) => {
+            let acceptor = TlsAcceptor::from(Arc::new(TlsIdentity::load(tls)?.server_config(tls)?));
+            serve_tls_until(listener, app, acceptor, grace, shutdown_signal()).await
+        }
+        None => serve_until(listener, app, grace, shutdown_signal()).await,
+    }
+}
+
+async fn serve_until(
+    listener: TcpListener,
+    app: Router,
+    grace: Duration,
+    signal: impl std::future::Future<Output = ()>,
+) -> Result<()> {
+    let draining = CancellationToken::new();
+    let server = axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown(draining.clone().cancelled_owned());
+    let server = async { server.await.map_err(|e| NegotiationError::Io(e.to_string())) };
+    tokio::pin!(server);
+
+    tokio::select! {
+        result = &mut server => return result,
+        _ = signal => {},
+    }
+    draining.cancel();
+    match tokio::time::timeout(grace, server).await {
+        Ok(result) => result,
+        Err(_) => {
+            tracing::warn!("Dropping requests still in progress after {:?}", grace);
+            Ok(())
+        }
+    }
+}
+
+async fn serve_tls_until(
+    listener: TcpListener,
+    app: Router,
+    acceptor: TlsAcceptor,
+    grace: Duration,
+    signal: impl std::future::Future<Output = ()>,
+) -> Result<()> {
+    let connections = GracefulShutdown::new();
+    tokio::pin!(signal);
+    loop {
+        let (stream, addr) = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    tracing::warn!("Failed to accept connection: {}", e);
+                    continue;
+                }
+            },
+            _ = &mut signal => break,
+        };
+        let (acceptor, app, watcher) = (acceptor.clone(), app.clone(), connections.watcher());
+        tokio::spawn(async move {
+            let stream = match acceptor.accept(stream).await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    tracing::debug!("TLS handshake with {} failed: {}", addr, e);
+                    return;
+                }
+            };
+            let peer = stream.get_ref().1.peer_certificates()
+                .and_then(|certificates| certificates.first())
+                .map(|certificate| PeerCertificate { fingerprint: tls::fingerprint(certificate) });
+            let service = hyper::service::service_fn(move |mut request: hyper::Request<hyper::body::Incoming>| {
+                request.extensions_mut().insert(ConnectInfo(addr));
+                if let Some(peer) = &peer {
+                    request.extensions_mut().insert(peer.clone());
+                }
+                app.clone().call(request)
+            });
+            let builder = auto::Builder::new(TokioExecutor::new());
+            if let Err(e) = watcher.watch(builder.serve_connection(TokioIo::new(stream), service)).await {
+                tracing::debug!("Connection from {} ended: {}", addr, e);
+            }
+        });
+    }
+
+    drop(listener);
+    if tokio::time::timeout(grace, connections.shutdown()).await.is_err() {
+        tracing::warn!("Dropping requests still in progress after {:?}", grace);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::TlsConfig, http::HttpClient};
+    use axum::{routing::get, Extension};
+
+    #[tokio::test]
+    async fn test_serve_finishes_requests_in_progress_on_shutdown() {
+        use tokio::sync::Notify;
+
+        // The handler holds the request open until it's let go after shutdown
+        let (started, release) = (Arc::new(Notify::new()), Arc::new(Notify::new()));
+        let app = Router::new().route("/slow", get({
+            let (started, release) = (started.clone(), release.clone());
+            move || async move {
+                started.notify_one();
+                release.notified().await;
+                "done"
+            }
+        }));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let stop = CancellationToken::new();
+        let server = tokio::spawn(serve_until(listener, app, Duration::from_secs(5), stop.clone().cancelled_owned()));
+
+        let request = tokio::spawn(reqwest::get(format!("http://{}/slow", addr)));
+        started.notified().await;
+        stop.cancel();
+        // The listener closes once shutdown has begun
+        while tokio::net::TcpStream::connect(addr).await.is_ok() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        release.notify_one();
+        let response = request.await.unwrap().unwrap();
+        assert_eq!(response.text().await.unwrap(), "done");
+        server.await.unwrap().unwrap();
+        assert!(reqwest::get(format!("http://{}/slow", addr)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_tls_admits_pinned_peers_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let identity = |name: &str| {
+            let tls = TlsConfig {
+                cert_path: dir.path().join(format!("{}.crt", name)).to_string_lossy().to_string(),
+                key_path: dir.path().join(format!("{}.key", name)).to_string_lossy().to_string(),
+                generate: true,
+                ..TlsConfig::default()
+            };
+            let fingerprint = TlsIdentity::load(&tls).unwrap().fingerprint();
+            (tls, fingerprint)
+        };
+        let (mut server_tls, server_fingerprint) = identity("seller");
+        let (buyer_tls, buyer_fingerprint) = identity("buyer");
+        let (stranger_tls, _) = identity("stranger");
+        server_tls.require_client_cert = true;
+        server_tls.pinned_fingerprints = vec![buyer_fingerprint.to_uppercase()];
+
+        let app = Router::new().route("/peer", get(|Extension(peer): Extension<PeerCertificate>| async move { peer.fingerprint }));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("https://localhost:{}/peer", listener.local_addr().unwrap().port());
+        let acceptor = TlsAcceptor::from(Arc::new(TlsIdentity::load(&server_tls).unwrap().server_config(&server_tls).unwrap()));
+        let stop = CancellationToken::new();
+        let server = tokio::spawn(serve_tls_until(listener, app, acceptor, Duration::from_secs(5), stop.clone().cancelled_owned()));
+
+        let client = |tls: &TlsConfig, pin: &str| {
+            let client = HttpClient::default().with_tls(tls).unwrap();
+            client.pin_certificate(&url, pin).unwrap();
+            client
+        };
+        let buyer = client(&buyer_tls, &server_fingerprint);
+        assert_eq!(buyer.tls_fingerprint(), Some(buyer_fingerprint.as_str()));
+        let response = buyer.get(&url).send().await.unwrap();
+        assert_eq!(response.text().await.unwrap(), buyer_fingerprint);
+
+        // The server isn't the one pinned
+        assert!(client(&buyer_tls, &buyer_fingerprint).get(&url).send().await.is_err());
+        // The client isn't one the server pinned
+        assert!(client(&stranger_tls, &server_fingerprint).get(&url).send().await.is_err());
+
+        stop.cancel();
+        server.await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,477 @@
+//! Multi-seller marketplace scenarios, run in process.
+//!
+//! [`MarketScenario::run`] serves a discovery registry and one seller per
+//! [`ScenarioSeller`] on loopback ports, each pricing with its own
+//! `[pricing]` rules, then has one buyer work through the scenario's RFQs
+//! over the real protocol:
+//!
+//! 1. each RFQ goes out to every seller at once and the quotes are ranked
+//! 2. the buyer counters the best quote and accepts the seller's answer,
+//!    which settles the deal
+//! 3. the other quotes are rejected
+//!
+//! Agents share one reputation store, so the [`ScenarioReport`] shows the
+//! reputation every deal earned or cost alongside the quotes and prices. The
+//! same run is the `dcap scenario` demo and an acceptance test of the
+//! protocol surface.
+
+use crate::{
+    agent::{BuyerAgent, BuyerAgentConfig, LLMConfig, SellerAgent, SellerAgentConfig},
+    comparison::SellerFailure,
+    config::{PricingConfig, PricingRuleConfig, ReputationTierConfig, VolumeTierConfig},
+    database::Database,
+    discovery::{DiscoveryServer, DiscoveryService, RegisterRequest, SearchRequest, SearchResponse},
+    error::{ApiResult, NegotiationError, Result},
+    inventory::{InventoryService, StockOutcome, StockUpdate},
+    model::{AgentInfo, NegotiationStatus, PaymentMethod, Product, ProductSpec, Quote, RFQ},
+    money::Money,
+    protocol,
+    responsiveness::{ResponseTimeReport, ResponseTimesView},
+    session::{bearer_token, SessionClaims, SessionTokens},
+    settlement::{SettlementConfig, SettlementService},
+    shared_state::SharedState,
+    trust::TrustSystem,
+    validation::Valid,
+    AgentId, TransactionId,
+};
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    middleware,
+    response::Json,
+    routing::{get, post},
+    Router,
+};
+use chrono::Utc;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, sync::Arc};
+use tokio::{net::TcpListener, task::JoinHandle};
+
+/// Counter offer as a share of the best quote when the scenario doesn't say
+pub const DEFAULT_COUNTER_OFFER_RATIO: Decimal = Decimal::from_parts(95, 0, 0, false, 2);
+
+/// A seller in a scenario: its catalog and the pricing rules it quotes with
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScenarioSeller {
+    pub name: String,
+    pub products: Vec<Product>,
+    #[serde(default)]
+    pub pricing: PricingConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MarketScenario {
+    pub sellers: Vec<ScenarioSeller>,
+    /// RFQs the buyer sends, in order; each goes to every seller
+    pub rfqs: Vec<ProductSpec>,
+    /// Counter offer as a share of the best quote's price
+    pub counter_offer_ratio: Decimal,
+    /// Reputation every agent starts with
+    pub starting_reputation: u32,
+}
+
+impl MarketScenario {
+    /// Three sellers of the same catalog: one discounting volume, one
+    /// discounting trusted buyers, one charging a premium. The buyer wants
+    /// five laptops and ten monitors.
+    pub fn competitive() -> Self {
+        let rules = |rules: Vec<PricingRuleConfig>| PricingConfig { default: rules, ..PricingConfig::default() };
+        let seller = |name: &str, pricing: PricingConfig| ScenarioSeller {
+            name: name.to_string(),
+            products: vec![
+                product("laptop-001", "Business Laptop", Decimal::from(1000), 20),
+                product("monitor-001", "27\" Monitor", Decimal::from(300), 50),
+            ],
+            pricing,
+        };
+        Self {
+            sellers: vec![
+                seller("Volume Direct", rules(vec![PricingRuleConfig::VolumeTiers {
+                    tiers: vec![
+                        VolumeTierConfig { min_quantity: 5, multiplier: Decimal::new(90, 2) },
+                        VolumeTierConfig { min_quantity: 10, multiplier: Decimal::new(85, 2) },
+                    ],
+                }])),
+                seller("Trusted Traders", rules(vec![PricingRuleConfig::ReputationDiscount {
+                    tiers: vec![ReputationTierConfig { min_reputation: 70, multiplier: Decimal::new(92, 2) }],
+                }])),
+                seller("Premium Goods", rules(vec![PricingRuleConfig::Flat { multiplier: Decimal::new(110, 2) }])),
+            ],
+            rfqs: vec![
+                spec("laptop-001", 5, Decimal::from(6000)),
+                spec("monitor-001", 10, Decimal::from(3500)),
+            ],
+            counter_offer_ratio: DEFAULT_COUNTER_OFFER_RATIO,
+            starting_reputation: 75,
+        }
+    }
+
+    /// Runs the scenario against `database`, which holds the registry,
+    /// payments and obligations. The servers stop when it returns.
+    pub async fn run(&self, database: Database) -> Result<ScenarioReport> {
+        if self.sellers.is_empty() || self.rfqs.is_empty() {
+            return Err(NegotiationError::Validation("A scenario needs sellers and RFQs".to_string()));
+        }
+        let shared = SharedState::in_memory();
+        let trust = || Ok::<_, NegotiationError>(TrustSystem::new()?.with_shared_cache(shared.clone()));
+        let mut servers = Servers::default();
+
+        let registry = DiscoveryServer::from_database(database.clone());
+        let discovery_endpoint = servers.serve(discovery_router(registry)).await?;
+        let inventory = InventoryService::new(database.clone());
+
+        let mut agents = Vec::new();
+        for seller in &self.sellers {
+            let listener = TcpListener::bind("127.0.0.1:0").await?;
+            let config = seller_config(seller, format!("http://{}", listener.local_addr()?));
+            inventory.track(config.agent_id, &config.products, Utc::now()).await?;
+            let agent = Arc::new(SellerAgent::new(config.clone(), DiscoveryService::new(discovery_endpoint.clone()), trust()?).await?
+                .with_inventory(inventory.clone()));
+            servers.spawn(listener, seller_router(agent.clone(), trust()?.session_tokens()));
+            agent.register().await?;
+            agents.push((config.agent_id, config.name));
+        }
+        let buyer_id = AgentId::new_v4();
+        agents.push((buyer_id, "Buyer".to_string()));
+
+        let mut reputations = trust()?;
+        for (agent_id, _) in &agents {
+            reputations.seed_reputation(*agent_id, self.starting_reputation).await?;
+        }
+
+        let settlement = SettlementService::new(SettlementConfig {
+            stripe_secret_key: None,
+            solana_rpc_url: None,
+            escrow_service_url: None,
+            webhook_secret: None,
+            delivery_confirmation_timeout_seconds: None,
+        }, database).await?;
+        let mut buyer = BuyerAgent::new(buyer_config(buyer_id), DiscoveryService::new(discovery_endpoint), trust()?, settlement).await?;
+
+        let mut deals = Vec::new();
+        for rfq in &self.rfqs {
+            deals.push(self.negotiate(&mut buyer, rfq).await?);
+        }
+
+        let mut changes = Vec::new();
+        for (agent_id, name) in agents {
+            changes.push(ReputationChange {
+                agent_id,
+                name,
+                before: self.starting_reputation,
+                after: reputations.get_reputation(agent_id).await?,
+            });
+        }
+        Ok(ScenarioReport { deals, reputations: changes })
+    }
+
+    async fn negotiate(&self, buyer: &mut BuyerAgent, spec: &ProductSpec) -> Result<ScenarioDeal> {
+        let comparison = buyer.request_quotes_from_all(spec.clone(), self.sellers.len()).await?;
+        let best = comparison.best().cloned()
+            .ok_or_else(|| NegotiationError::Negotiation(format!("No seller quoted {}", spec.product_id)))?;
+
+        let counter_offer = (best.price.amount * self.counter_offer_ratio).round_dp(2);
+        let countered_price = match buyer.negotiate(best.negotiation_id, counter_offer).await {
+            Ok(()) => buyer.latest_quote(best.negotiation_id).map(Quote::amount).map(|price| price.amount),
+            Err(e) => {
+                tracing::info!("{} declined a counter offer of {}: {}", best.seller_name, counter_offer, e);
+                None
+            }
+        };
+        buyer.accept_quote(best.negotiation_id).await?;
+        buyer.confirm_settlements().await?;
+        for other in comparison.ranked.iter().skip(1) {
+            buyer.reject_quote(other.negotiation_id).await?;
+        }
+
+        let negotiation = buyer.get_negotiation(best.negotiation_id)
+            .ok_or_else(|| NegotiationError::NotFound("Negotiation".to_string()))?;
+        Ok(ScenarioDeal {
+            product_id: spec.product_id.clone(),
+            quantity: spec.quantity,
+            quotes: comparison.ranked.iter()
+                .map(|quote| ScenarioQuote { seller_name: quote.seller_name.clone(), price: quote.price.clone(), score: quote.score })
+                .collect(),
+            failures: comparison.failures,
+            seller_name: best.seller_name,
+            quoted_price: best.price,
+            counter_offer,
+            countered_price,
+            final_price: negotiation.close_price,
+            status: negotiation.status.clone(),
+        })
+    }
+}
+
+/// What a scenario run did
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScenarioReport {
+    pub deals: Vec<ScenarioDeal>,
+    /// Every agent's reputation before and after the run, sellers first
+    pub reputations: Vec<ReputationChange>,
+}
+
+impl ScenarioReport {
+    /// Whether every RFQ ended in a settled deal
+    pub fn all_settled(&self) -> bool {
+        self.deals.iter().all(|deal| deal.status == NegotiationStatus::Settled)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScenarioDeal {
+    pub product_id: String,
+    pub quantity: u32,
+    /// Quotes received, best first
+    pub quotes: Vec<ScenarioQuote>,
+    pub failures: Vec<SellerFailure>,
+    /// Seller of the best quote, who got the deal
+    pub seller_name: String,
+    pub quoted_price: Money,
+    pub counter_offer: Decimal,
+    /// Seller's answer to the counter offer; `None` when it declined
+    pub countered_price: Option<Decimal>,
+    pub final_price: Option<Decimal>,
+    pub status: NegotiationStatus,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScenarioQuote {
+    pub seller_name: String,
+    pub price: Money,
+    pub score: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReputationChange {
+    pub agent_id: AgentId,
+    pub name: String,
+    pub before: u32,
+    pub after: u32,
+}
+
+fn product(id: &str, name: &str, base_price: Decimal, stock_quantity: u32) -> Product {
+    Product {
+        id: id.to_string(),
+        name: name.to_string(),
+        description: String::new(),
+        category: "Electronics".to_string(),
+        base_price,
+        currency: "USD".to_string(),
+        stock_quantity,
+        metadata: HashMap::new(),
+    }
+}
+
+fn spec(product_id: &str, quantity: u32, max_price: Decimal) -> ProductSpec {
+    ProductSpec {
+        product_id: product_id.to_string(),
+        category: Some("Electronics".to_string()),
+        quantity,
+        max_price,
+        delivery_location: None,
+    }
+}
+
+fn llm_config() -> LLMConfig {
+    LLMConfig { model: "gpt-4".to_string(), api_key: String::new(), max_tokens: 1000, temperature: 0.7 }
+}
+
+fn seller_config(seller: &ScenarioSeller, endpoint: String) -> SellerAgentConfig {
+    SellerAgentConfig {
+        agent_id: AgentId::new_v4(),
+        name: seller.name.clone(),
+        endpoint,
+        products: seller.products.clone(),
+        payment_methods: vec![PaymentMethod::Stripe],
+        llm_config: llm_config(),
+        calendar: Default::default(),
+        pricing: seller.pricing.clone(),
+        quote_ttl: Default::default(),
+        quote_firmness: Default::default(),
+        locale: Default::default(),
+        preferred_languages: vec![],
+        compliance: Default::default(),
+        templates: Default::default(),
+        tax: Default::default(),
+        shipping: Default::default(),
+    }
+}
+
+fn buyer_config(agent_id: AgentId) -> BuyerAgentConfig {
+    BuyerAgentConfig {
+        agent_id,
+        name: "Buyer".to_string(),
+        endpoint: String::new(),
+        max_concurrent_negotiations: 10,
+        default_ttl_hours: 24,
+        llm_config: llm_config(),
+        currency: "USD".to_string(),
+        locale: Default::default(),
+        preferred_languages: vec![],
+        region: None,
+        max_response_time_ms: None,
+        payment_method: None,
+    }
+}
+
+/// Servers a run started, stopped when it's done
+#[derive(Default)]
+struct Servers(Vec<JoinHandle<()>>);
+
+impl Servers {
+    /// Serves `router` on a loopback port, returning its base URL.
+    async fn serve(&mut self, router: Router) -> Result<String> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let endpoint = format!("http://{}", listener.local_addr()?);
+        self.spawn(listener, router);
+        Ok(endpoint)
+    }
+
+    fn spawn(&mut self, listener: TcpListener, router: Router) {
+        self.0.push(tokio::spawn(async move {
+            if let Err(e) = axum::serve(listener, router).await {
+                tracing::error!("Scenario server failed: {}", e);
+            }
+        }));
+    }
+}
+
+impl Drop for Servers {
+    fn drop(&mut self) {
+        for server in &self.0 {
+            server.abort();
+        }
+    }
+}
+
+/// The registry routes buyers and sellers use during a negotiation
+fn discovery_router(registry: DiscoveryServer) -> Router {
+    Router::new()
+        .route("/register", post(register_agent))
+        .route("/search", post(search_agents))
+        .route("/agents/:agent_id", get(get_agent))
+        .route("/agents/:agent_id/response-times", post(report_response_time))
+        .layer(middleware::from_fn(protocol::protocol_version_layer))
+        .with_state(registry)
+}
+
+async fn register_agent(State(registry): State<DiscoveryServer>, Valid(request): Valid<RegisterRequest>) -> ApiResult<Json<AgentInfo>> {
+    Ok(Json(registry.handle_register(request).await?))
+}
+
+async fn search_agents(State(registry): State<DiscoveryServer>, Json(request): Json<SearchRequest>) -> ApiResult<Json<SearchResponse>> {
+    Ok(Json(registry.handle_search(request).await?))
+}
+
+async fn get_agent(State(registry): State<DiscoveryServer>, Path(agent_id): Path<AgentId>) -> ApiResult<Json<AgentInfo>> {
+    let agent = registry.get_agent_info(agent_id).await?.ok_or(NegotiationError::AgentNotFound(agent_id))?;
+    Ok(Json(agent))
+}
+
+async fn report_response_time(
+    State(registry): State<DiscoveryServer>,
+    Path(agent_id): Path<AgentId>,
+    Json(report): Json<ResponseTimeReport>,
+) -> ApiResult<Json<ResponseTimesView>> {
+    Ok(Json(registry.record_response_time(agent_id, report).await?))
+}
+
+#[derive(Clone)]
+struct SellerState {
+    agent: Arc<SellerAgent>,
+    session_tokens: SessionTokens,
+}
+
+/// A seller's protocol routes, quoting and countering with the library's
+/// pricing rather than the seller service's fixed responses
+fn seller_router(agent: Arc<SellerAgent>, session_tokens: SessionTokens) -> Router {
+    Router::new()
+        .route("/products", get(list_products))
+        .route("/quote", post(handle_quote))
+        .route("/negotiate/:negotiation_id", post(handle_negotiation))
+        .route("/negotiate/:negotiation_id/stock", post(report_stock))
+        .layer(middleware::from_fn(protocol::protocol_version_layer))
+        .with_state(SellerState { agent, session_tokens })
+}
+
+fn authorize_session(state: &SellerState, headers: &HeaderMap, negotiation_id: TransactionId) -> Result<SessionClaims> {
+    let token = bearer_token(headers).ok_or_else(|| NegotiationError::Auth("Missing session token".to_string()))?;
+    state.session_tokens.decode(token, negotiation_id)
+}
+
+async fn list_products(State(state): State<SellerState>) -> Json<Vec<Product>> {
+    Json(state.agent.products())
+}
+
+async fn handle_quote(State(state): State<SellerState>, Valid(rfq): Valid<RFQ>) -> ApiResult<Json<Quote>> {
+    Ok(Json(state.agent.handle_rfq(rfq).await?))
+}
+
+#[derive(Deserialize)]
+struct CounterOffer {
+    counter_offer: Decimal,
+    product_id: String,
+    quantity: u32,
+}
+
+async fn handle_negotiation(
+    State(state): State<SellerState>,
+    Path(negotiation_id): Path<TransactionId>,
+    headers: HeaderMap,
+    Json(offer): Json<CounterOffer>,
+) -> ApiResult<Json<Quote>> {
+    authorize_session(&state, &headers, negotiation_id)?;
+    let quote = state.agent.handle_negotiation(negotiation_id, &offer.product_id, offer.quantity, offer.counter_offer).await?;
+    Ok(Json(quote))
+}
+
+async fn report_stock(
+    State(state): State<SellerState>,
+    Path(negotiation_id): Path<TransactionId>,
+    headers: HeaderMap,
+    Json(update): Json<StockUpdate>,
+) -> ApiResult<StatusCode> {
+    let buyer_id = authorize_session(&state, &headers, negotiation_id)?.agent_id()?;
+    match update.outcome {
+        StockOutcome::Settled => state.agent.commit_stock(update.rfq_id, buyer_id).await?,
+        StockOutcome::Released => state.agent.release_stock(update.rfq_id, buyer_id).await?,
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trust::ReputationWeights;
+    use tempfile::NamedTempFile;
+
+    #[tokio::test]
+    async fn test_competitive_scenario_settles_with_the_best_seller() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let database = Database::new(&format!("sqlite://{}", temp_file.path().to_string_lossy())).await.unwrap();
+        let report = MarketScenario::competitive().run(database).await.unwrap();
+
+        assert!(report.all_settled());
+        for deal in &report.deals {
+            assert_eq!(deal.quotes.len(), 3, "{:?}", deal.failures);
+            assert_eq!(deal.seller_name, "Volume Direct");
+            let prices: Vec<_> = deal.quotes.iter().map(|quote| quote.price.amount).collect();
+            assert!(prices.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", prices);
+            assert!(deal.final_price.unwrap() <= deal.counter_offer);
+        }
+        assert_eq!(report.deals[0].quoted_price.amount, Decimal::from(4500));
+        assert_eq!(report.deals[1].quoted_price.amount, Decimal::from(2550));
+
+        // Each deal pays the winner and the buyer, scaled by its value;
+        // losing quotes cost their sellers
+        let weights = ReputationWeights::default();
+        let volume: f64 = report.deals.iter().map(|deal| weights.value_weight(deal.final_price)).sum();
+        let after: HashMap<_, _> = report.reputations.iter().map(|change| (change.name.as_str(), change.after)).collect();
+        assert_eq!(after["Volume Direct"], (75.0 + 5.0 * volume.sqrt()).round() as u32);
+        assert_eq!(after["Trusted Traders"], (75.0 - 4.0 / 2f64.sqrt()).round() as u32);
+        assert_eq!(after["Premium Goods"], (75.0 - 4.0 / 2f64.sqrt()).round() as u32);
+        assert_eq!(after["Buyer"], (75.0 + 3.0 * volume.sqrt()).round() as u32);
+    }
+}
@@ -0,0 +1,311 @@
+//! HTTP hardening shared by the Axum servers.
+//!
+//! [`harden`] wraps a router so that every response carries headers telling
+//! browsers not to sniff, frame or cache it, request bodies have to be JSON
+//! and no larger than `[server] max_body_bytes`, and, when
+//! `[server] allowed_origins` is set, state-changing requests a browser sends
+//! from any other origin are refused so a web page can't forge calls on an
+//! agent's behalf. Agents don't send `Origin`, so the check doesn't affect them.
+//! [`rate_limit`] additionally caps how many requests each client may make
+//! per minute, overall and per route, counted in the shared state so replicas
+//! enforce one budget. A client is the agent whose token a request carries,
+//! or its address when it carries none.
+
+use crate::{
+    config::ServerConfig,
+    error::ApiError,
+    metrics::Metrics,
+    session::{bearer_token, TokenSubjects},
+    shared_state::{RateLimiter, SharedState},
+};
+use axum::{
+    extract::{ConnectInfo, DefaultBodyLimit, MatchedPath, Request, State},
+    http::{
+        header::{self, HeaderMap, HeaderName, HeaderValue},
+        Method, StatusCode,
+    },
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    Router,
+};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+pub const DEFAULT_MAX_BODY_BYTES: usize = 1_048_576;
+
+const SECURITY_HEADERS: [(HeaderName, &str); 5] = [
+    (header::X_CONTENT_TYPE_OPTIONS, "nosniff"),
+    (header::X_FRAME_OPTIONS, "DENY"),
+    (header::REFERRER_POLICY, "no-referrer"),
+    (header::CONTENT_SECURITY_POLICY, "default-src 'none'; frame-ancestors 'none'"),
+    (header::CACHE_CONTROL, "no-store"),
+];
+
+struct SecurityPolicy {
+    max_body_bytes: usize,
+    allowed_origins: Vec<String>,
+}
+
+impl SecurityPolicy {
+    fn from_config(config: &ServerConfig) -> Self {
+        Self {
+            max_body_bytes: config.max_body_bytes.unwrap_or(DEFAULT_MAX_BODY_BYTES),
+            allowed_origins: config.allowed_origins.iter()
+                .map(|origin| origin.trim_end_matches('/').to_ascii_lowercase())
+                .collect(),
+        }
+    }
+
+    /// Why a request is refused, if it is.
+    fn screen(&self, method: &Method, headers: &HeaderMap) -> Option<(StatusCode, &'static str)> {
+        let state_changing = !matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS);
+        if state_changing && !self.allowed_origins.is_empty() {
+            if let Some(origin) = headers.get(header::ORIGIN) {
+                let allowed = origin.to_str().is_ok_and(|origin| {
+                    self.allowed_origins.contains(&origin.trim_end_matches('/').to_ascii_lowercase())
+                });
+                if !allowed {
+                    return Some((StatusCode::FORBIDDEN, "Origin not allowed"));
+                }
+            }
+        }
+
+        let content_length = headers.get(header::CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<usize>().ok());
+        if content_length.is_some_and(|length| length > self.max_body_bytes) {
+            return Some((StatusCode::PAYLOAD_TOO_LARGE, "Request body too large"));
+        }
+        let has_body = content_length.is_some_and(|length| length > 0)
+            || headers.contains_key(header::TRANSFER_ENCODING);
+        if has_body && !is_json(headers) {
+            return Some((StatusCode::UNSUPPORTED_MEDIA_TYPE, "Request bodies must be application/json"));
+        }
+        None
+    }
+}
+
+fn is_json(headers: &HeaderMap) -> bool {
+    let Some(content_type) = headers.get(header::CONTENT_TYPE).and_then(|value| value.to_str().ok()) else {
+        return false;
+    };
+    let mime = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+    mime == "application/json" || (mime.starts_with("application/") && mime.ends_with("+json"))
+}
+
+/// Applies the security headers, body rules and origin check to every route
+/// of `router`, including its fallback.
+pub fn harden(router: Router, config: &ServerConfig) -> Router {
+    let policy = Arc::new(SecurityPolicy::from_config(config));
+    router
+        .layer(DefaultBodyLimit::max(policy.max_body_bytes))
+        .layer(middleware::from_fn_with_state(policy, enforce))
+}
+
+async fn enforce(State(policy): State<Arc<SecurityPolicy>>, request: Request, next: Next) -> Response {
+    let mut response = match policy.screen(request.method(), request.headers()) {
+        Some((status, message)) => {
+            tracing::warn!("Refused {} {}: {}", request.method(), request.uri().path(), message);
+            ApiError::new(status, message).into_response()
+        }
+        None => next.run(request).await,
+    };
+
+    let headers = response.headers_mut();
+    for (name, value) in SECURITY_HEADERS {
+        headers.entry(name).or_insert(HeaderValue::from_static(value));
+    }
+    response
+}
+
+struct ClientLimit {
+    /// Budget across all routes
+    overall: Option<RateLimiter>,
+    /// Budgets of single routes, by route pattern
+    routes: HashMap<String, RateLimiter>,
+    trust_forwarded_for: bool,
+    subjects: Option<TokenSubjects>,
+    metrics: Option<Metrics>,
+}
+
+impl ClientLimit {
+    /// The client a request is counted against: the agent a valid bearer
+    /// token was issued to, else the first `X-Forwarded-For` address when the
+    /// service sits behind a trusted proxy, and otherwise the peer address,
+    /// which is only known when the server was started with
+    /// `into_make_service_with_connect_info`. Also says whether it's an agent.
+    fn client(&self, request: &Request) -> (String, bool) {
+        let agent = self.subjects.as_ref()
+            .zip(bearer_token(request.headers()))
+            .and_then(|(subjects, token)| subjects.subject(token));
+        if let Some(agent_id) = agent {
+            return (format!("agent:{}", agent_id), true);
+        }
+
+        let forwarded = self.trust_forwarded_for
+            .then(|| request.headers().get("x-forwarded-for")?.to_str().ok()?.split(',').next().map(str::trim))
+            .flatten()
+            .filter(|client| !client.is_empty());
+        let address = match forwarded {
+            Some(client) => client.to_string(),
+            None => request.extensions().get::<ConnectInfo<SocketAddr>>()
+                .map(|ConnectInfo(addr)| addr.ip().to_string())
+                .unwrap_or_else(|| "unknown".to_string()),
+        };
+        (address, false)
+    }
+
+    /// How long `client` has to wait, if it's over the route's budget or the
+    /// overall one.
+    async fn check(&self, path: &str, client: &str) -> crate::error::Result<Option<std::time::Duration>> {
+        if let Some(limiter) = self.routes.get(path) {
+            if let Some(retry_after) = limiter.check(&format!("{}:{}", path, client)).await? {
+                return Ok(Some(retry_after));
+            }
+        }
+        match &self.overall {
+            Some(limiter) => limiter.check(client).await,
+            None => Ok(None),
+        }
+    }
+}
+
+/// Refuses requests from clients over `[server] rate_limit_per_minute`, or
+/// over the `rate_limit_routes` budget of the route they call, with 429 Too
+/// Many Requests. With `subjects`, agents presenting a valid token are
+/// counted by agent rather than address; with `metrics`, refusals are
+/// counted. Leaves `router` alone when no limit is set.
+pub fn rate_limit(
+    router: Router,
+    config: &ServerConfig,
+    shared: SharedState,
+    subjects: Option<TokenSubjects>,
+    metrics: Option<Metrics>,
+) -> Router {
+    if config.rate_limit_per_minute.is_none() && config.rate_limit_routes.is_empty() {
+        return router;
+    }
+    let limit = Arc::new(ClientLimit {
+        overall: config.rate_limit_per_minute.map(|limit| RateLimiter::per_minute(shared.clone(), limit)),
+        routes: config.rate_limit_routes.iter()
+            .map(|(path, limit)| (path.clone(), RateLimiter::per_minute(shared.clone(), *limit)))
+            .collect(),
+        trust_forwarded_for: config.trust_forwarded_for,
+        subjects,
+        metrics,
+    });
+    router.layer(middleware::from_fn_with_state(limit, enforce_rate_limit))
+}
+
+async fn enforce_rate_limit(State(limit): State<Arc<ClientLimit>>, request: Request, next: Next) -> Response {
+    let (client, is_agent) = limit.client(&request);
+    let path = request.extensions().get::<MatchedPath>()
+        .map_or_else(String::new, |path| path.as_str().to_string());
+    match limit.check(&path, &client).await {
+        Ok(None) => next.run(request).await,
+        Ok(Some(retry_after)) => {
+            tracing::warn!("Rate limited {} on {} {}", client, request.method(), request.uri().path());
+            if let Some(metrics) = &limit.metrics {
+                metrics.request_rate_limited(&path, if is_agent { "agent" } else { "ip" });
+            }
+            let mut response = ApiError::new(StatusCode::TOO_MANY_REQUESTS, "Too many requests").into_response();
+            response.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from(retry_after.as_secs().max(1)));
+            response
+        }
+        Err(e) => {
+            // An unreachable store shouldn't take the service down with it
+            tracing::error!("Failed to check rate limit for {}: {}", client, e);
+            next.run(request).await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(HeaderName, &str)]) -> HeaderMap {
+        pairs.iter()
+            .map(|(name, value)| (name.clone(), HeaderValue::from_str(value).unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_screens_origin_content_type_and_size() {
+        let policy = SecurityPolicy::from_config(&ServerConfig {
+            max_body_bytes: Some(1024),
+            allowed_origins: vec!["https://dashboard.example.com/".to_string()],
+            ..ServerConfig::default()
+        });
+        let json = (header::CONTENT_TYPE, "application/json; charset=utf-8");
+
+        // Agents send no Origin
+        assert_eq!(policy.screen(&Method::POST, &headers(&[json.clone(), (header::CONTENT_LENGTH, "10")])), None);
+        assert_eq!(policy.screen(&Method::POST, &headers(&[
+            json.clone(), (header::CONTENT_LENGTH, "10"), (header::ORIGIN, "https://Dashboard.example.com"),
+        ])), None);
+        assert_eq!(policy.screen(&Method::POST, &headers(&[
+            json.clone(), (header::CONTENT_LENGTH, "10"), (header::ORIGIN, "https://evil.example"),
+        ])).map(|(status, _)| status), Some(StatusCode::FORBIDDEN));
+        // Reads aren't origin checked
+        assert_eq!(policy.screen(&Method::GET, &headers(&[(header::ORIGIN, "https://evil.example")])), None);
+
+        assert_eq!(policy.screen(&Method::POST, &headers(&[
+            (header::CONTENT_TYPE, "application/x-www-form-urlencoded"), (header::CONTENT_LENGTH, "10"),
+        ])).map(|(status, _)| status), Some(StatusCode::UNSUPPORTED_MEDIA_TYPE));
+        assert_eq!(policy.screen(&Method::POST, &headers(&[(header::CONTENT_LENGTH, "0")])), None);
+        assert_eq!(policy.screen(&Method::POST, &headers(&[json, (header::CONTENT_LENGTH, "2048")]))
+            .map(|(status, _)| status), Some(StatusCode::PAYLOAD_TOO_LARGE));
+    }
+
+    #[tokio::test]
+    async fn test_rate_limits_routes_per_agent() {
+        use axum::routing::{get, post};
+        use jsonwebtoken::{encode, EncodingKey, Header};
+
+        let config = ServerConfig {
+            rate_limit_routes: HashMap::from([("/quote/:rfq_id".to_string(), 1)]),
+            ..ServerConfig::default()
+        };
+        let metrics = Metrics::new();
+        let router = Router::new()
+            .route("/quote/:rfq_id", post(|| async { "quoted" }))
+            .route("/health", get(|| async { "ok" }));
+        let router = rate_limit(router, &config, SharedState::in_memory(), Some(TokenSubjects::new("secret")), Some(metrics.clone()));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, router.into_make_service_with_connect_info::<SocketAddr>()).await });
+
+        let token = |agent_id: uuid::Uuid, secret: &[u8]| {
+            let claims = serde_json::json!({ "sub": agent_id.to_string(), "exp": chrono::Utc::now().timestamp() + 60 });
+            encode(&Header::default(), &claims, &EncodingKey::from_secret(secret)).unwrap()
+        };
+        let client = reqwest::Client::new();
+        let quote = |rfq: u32, token: Option<String>| {
+            let request = client.post(format!("{}/quote/{}", base, rfq));
+            let request = match token {
+                Some(token) => request.bearer_auth(token),
+                None => request,
+            };
+            async move { request.send().await.unwrap() }
+        };
+
+        let first = token(uuid::Uuid::new_v4(), b"secret");
+        assert_eq!(quote(1, Some(first.clone())).await.status(), StatusCode::OK);
+        // The budget covers the route, not one RFQ
+        let refused = quote(2, Some(first)).await;
+        assert_eq!(refused.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(refused.headers().contains_key(header::RETRY_AFTER));
+
+        // Other agents have budgets of their own; a forged token counts as its address
+        assert_eq!(quote(1, Some(token(uuid::Uuid::new_v4(), b"secret"))).await.status(), StatusCode::OK);
+        assert_eq!(quote(1, Some(token(uuid::Uuid::new_v4(), b"guess"))).await.status(), StatusCode::OK);
+        assert_eq!(quote(1, None).await.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(client.get(format!("{}/health", base)).send().await.unwrap().status(), StatusCode::OK);
+
+        let body = metrics.render().unwrap();
+        assert!(body.contains("dcap_rate_limited_requests_total{client=\"agent\",path=\"/quote/:rfq_id\"} 1"));
+        assert!(body.contains("dcap_rate_limited_requests_total{client=\"ip\",path=\"/quote/:rfq_id\"} 1"));
+    }
+}
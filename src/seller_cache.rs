@@ -0,0 +1,167 @@
+//! Sellers and products the buyer has seen before.
+//!
+//! Every seller discovery returns, and every product list a seller serves, is
+//! kept in the database, so when the discovery service can't be reached the
+//! buyer can keep negotiating with the sellers it already knows about instead
+//! of failing outright. Entries older than the staleness threshold are still
+//! used, with a warning, since a seller's endpoint or catalogue may have
+//! changed since it was cached.
+
+use crate::{
+    database::Database,
+    error::Result,
+    model::{AgentInfo, AgentType, Product},
+    AgentId,
+};
+use chrono::{DateTime, Duration, Utc};
+
+pub const DEFAULT_STALE_AFTER_SECONDS: i64 = 3600;
+
+#[derive(Debug, Clone)]
+pub struct CachedSeller {
+    pub info: AgentInfo,
+    pub seen_at: DateTime<Utc>,
+}
+
+impl CachedSeller {
+    pub fn age(&self, now: DateTime<Utc>) -> Duration {
+        now - self.seen_at
+    }
+}
+
+#[derive(Clone)]
+pub struct SellerCache {
+    database: Database,
+    stale_after: Duration,
+}
+
+impl SellerCache {
+    pub fn new(database: Database) -> Self {
+        Self {
+            database,
+            stale_after: Duration::seconds(DEFAULT_STALE_AFTER_SECONDS),
+        }
+    }
+
+    pub fn with_stale_after(mut self, stale_after: Duration) -> Self {
+        self.stale_after = stale_after;
+        self
+    }
+
+    pub async fn remember_sellers(&self, sellers: &[AgentInfo]) -> Result<()> {
+        let now = Utc::now();
+        for seller in sellers.iter().filter(|seller| matches!(seller.agent_type, AgentType::Seller)) {
+            self.database.cache_seller(seller, now).await?;
+            if !seller.products.is_empty() {
+                self.database.cache_seller_products(seller.id, &seller.products, now).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Replaces the products cached for a seller that is already cached.
+    pub async fn remember_products(&self, seller_id: AgentId, products: &[Product]) -> Result<()> {
+        self.database.cache_seller_products(seller_id, products, Utc::now()).await
+    }
+
+    /// Cached sellers offering something in `category`, or all of them.
+    pub async fn sellers(&self, category: Option<&str>) -> Result<Vec<AgentInfo>> {
+        let sellers = self.database.get_cached_sellers().await?;
+        Ok(sellers.into_iter()
+            .filter(|seller| category.is_none_or(|category| {
+                seller.info.products.iter().any(|product| product.category == category)
+            }))
+            .map(|seller| self.checked(seller))
+            .collect())
+    }
+
+    pub async fn seller(&self, seller_id: AgentId) -> Result<Option<AgentInfo>> {
+        Ok(self.database.get_cached_seller(seller_id).await?.map(|seller| self.checked(seller)))
+    }
+
+    /// The cached seller offering `product_id`, with the product as last seen.
+    pub async fn product(&self, product_id: &str) -> Result<Option<(AgentInfo, Product)>> {
+        let sellers = self.database.get_cached_sellers().await?;
+        Ok(sellers.into_iter()
+            .find_map(|seller| {
+                let product = seller.info.products.iter().find(|product| product.id == product_id)?.clone();
+                Some((seller, product))
+            })
+            .map(|(seller, product)| (self.checked(seller), product)))
+    }
+
+    fn checked(&self, seller: CachedSeller) -> AgentInfo {
+        let age = seller.age(Utc::now());
+        if age > self.stale_after {
+            tracing::warn!(
+                "Using cached seller {} ({}) last seen {} minutes ago; its endpoint or products may have changed",
+                seller.info.name, seller.info.id, age.num_minutes()
+            );
+        }
+        seller.info
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal::Decimal;
+    use std::collections::HashMap;
+    use tempfile::NamedTempFile;
+
+    fn product(id: &str, category: &str) -> Product {
+        Product {
+            id: id.to_string(),
+            name: id.to_string(),
+            description: String::new(),
+            category: category.to_string(),
+            base_price: Decimal::new(100, 0),
+            currency: "USD".to_string(),
+            stock_quantity: 10,
+            metadata: HashMap::new(),
+        }
+    }
+
+    fn seller(products: Vec<Product>) -> AgentInfo {
+        AgentInfo {
+            id: uuid::Uuid::new_v4(),
+            agent_type: AgentType::Seller,
+            name: "Seller".to_string(),
+            endpoint: "http://localhost:8001".to_string(),
+            public_key: "key".to_string(),
+            reputation_score: 50,
+            products,
+            payment_methods: vec![],
+            protocol_versions: vec![],
+            preferred_languages: vec![],
+            tls_fingerprint: None,
+            created_at: Utc::now(),
+            last_active: Utc::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_remembers_sellers_and_their_products() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let database = Database::new(&format!("sqlite://{}", temp_file.path().to_string_lossy())).await.unwrap();
+        let cache = SellerCache::new(database).with_stale_after(Duration::zero());
+
+        let laptops = seller(vec![product("laptop-001", "electronics")]);
+        let mut phones = seller(vec![]);
+        cache.remember_sellers(&[laptops.clone(), phones.clone()]).await.unwrap();
+        cache.remember_products(phones.id, &[product("phone-001", "electronics")]).await.unwrap();
+
+        // Discovery listing the seller without products keeps the cached ones
+        phones.endpoint = "http://localhost:8002".to_string();
+        cache.remember_sellers(&[phones.clone()]).await.unwrap();
+        let cached = cache.seller(phones.id).await.unwrap().unwrap();
+        assert_eq!(cached.endpoint, "http://localhost:8002");
+        assert_eq!(cached.products[0].id, "phone-001");
+
+        assert_eq!(cache.sellers(Some("electronics")).await.unwrap().len(), 2);
+        assert!(cache.sellers(Some("furniture")).await.unwrap().is_empty());
+        let (found, product) = cache.product("laptop-001").await.unwrap().unwrap();
+        assert_eq!((found.id, product.id.as_str()), (laptops.id, "laptop-001"));
+        assert!(cache.product("desk-001").await.unwrap().is_none());
+    }
+}
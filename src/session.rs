@@ -0,0 +1,223 @@
+//! Negotiation-scoped session tokens.
+//!
+//! An agent trades its access token (an agent JWT) for a token that is only
+//! good for one negotiation, and presents that on `/negotiate/:id` and
+//! settlement calls. A leaked session token can't be used on other
+//! negotiations, can't be passed off as an agent JWT, and can be revoked per
+//! negotiation without rotating the agent's credentials.
+
+use crate::{
+    database::Database,
+    error::{NegotiationError, Result},
+    jwt_keys::JwtKeyring,
+    trust::JWTClaims,
+    AgentId, TransactionId,
+};
+use axum::http::{header::AUTHORIZATION, HeaderMap};
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use hmac::{Hmac, Mac};
+use jsonwebtoken::{decode, decode_header, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::sync::Arc;
+
+pub const DEFAULT_SESSION_TTL_MINUTES: i64 = 30;
+
+/// Session tokens are signed with a key derived from the JWT secret, so agent
+/// JWTs and session tokens never verify as each other
+const SESSION_KEY_CONTEXT: &[u8] = b"dcap-negotiation-session:v1";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionClaims {
+    /// Agent the token was issued to
+    pub sub: String,
+    /// `negotiation:{id}`
+    pub aud: String,
+    pub negotiation_id: TransactionId,
+    /// Role of the agent JWT the token was traded for
+    pub role: String,
+    pub jti: uuid::Uuid,
+    pub iat: usize,
+    pub exp: usize,
+}
+
+impl SessionClaims {
+    pub fn agent_id(&self) -> Result<AgentId> {
+        AgentId::parse_str(&self.sub).map_err(|_| NegotiationError::Auth("Invalid session subject".to_string()))
+    }
+
+    pub fn issued_at(&self) -> DateTime<Utc> {
+        Utc.timestamp_opt(self.iat as i64, 0).single().unwrap_or_default()
+    }
+
+    /// Fails unless the token holder is one of the given parties.
+    pub fn require_party(&self, parties: &[AgentId]) -> Result<AgentId> {
+        let agent_id = self.agent_id()?;
+        if parties.contains(&agent_id) {
+            Ok(agent_id)
+        } else {
+            Err(NegotiationError::Auth("Session token holder is not a party to this negotiation".to_string()))
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct SessionTokens {
+    key: Vec<u8>,
+    ttl: Duration,
+}
+
+impl SessionTokens {
+    pub fn new(jwt_secret: &str) -> Self {
+        let mut mac = Hmac::<Sha256>::new_from_slice(jwt_secret.as_bytes())
+            .expect("HMAC accepts keys of any length");
+        mac.update(SESSION_KEY_CONTEXT);
+        Self {
+            key: mac.finalize().into_bytes().to_vec(),
+            ttl: Duration::minutes(DEFAULT_SESSION_TTL_MINUTES),
+        }
+    }
+
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Issues a token for one negotiation from an already validated agent JWT.
+    /// The token never outlives the agent JWT it was derived from.
+    pub fn issue(&self, agent_claims: &JWTClaims, negotiation_id: TransactionId) -> Result<String> {
+        let now = Utc::now();
+        let claims = SessionClaims {
+            sub: agent_claims.sub.clone(),
+            aud: audience(negotiation_id),
+            negotiation_id,
+            role: agent_claims.role.clone(),
+            jti: uuid::Uuid::new_v4(),
+            iat: now.timestamp() as usize,
+            exp: ((now + self.ttl).timestamp() as usize).min(agent_claims.exp),
+        };
+
+        encode(&Header::default(), &claims, &EncodingKey::from_secret(&self.key))
+            .map_err(|e| NegotiationError::Auth(format!("Failed to issue session token: {}", e)))
+    }
+
+    /// Checks the signature, expiry and negotiation scope of a token.
+    pub fn decode(&self, token: &str, negotiation_id: TransactionId) -> Result<SessionClaims> {
+        let mut validation = Validation::new(Algorithm::HS256);
+        validation.set_audience(&[audience(negotiation_id)]);
+
+        let claims = decode::<SessionClaims>(token, &DecodingKey::from_secret(&self.key), &validation)
+            .map(|data| data.claims)
+            .map_err(|e| NegotiationError::Auth(format!("Invalid session token: {}", e)))?;
+        if claims.negotiation_id != negotiation_id {
+            return Err(NegotiationError::Auth("Session token is for another negotiation".to_string()));
+        }
+        Ok(claims)
+    }
+
+    /// Validates the bearer token of a request and checks it hasn't been
+    /// revoked.
+    pub async fn authorize(&self, database: &Database, headers: &HeaderMap, negotiation_id: TransactionId) -> Result<SessionClaims> {
+        let token = bearer_token(headers)
+            .ok_or_else(|| NegotiationError::Auth("Missing session token".to_string()))?;
+        self.authorize_token(database, token, negotiation_id).await
+    }
+
+    /// Validates a token passed outside an HTTP request, such as an MCP tool
+    /// argument, and checks it hasn't been revoked.
+    pub async fn authorize_token(&self, database: &Database, token: &str, negotiation_id: TransactionId) -> Result<SessionClaims> {
+        let claims = self.decode(token, negotiation_id)?;
+        if database.is_session_token_revoked(negotiation_id, claims.jti, claims.issued_at()).await? {
+            return Err(NegotiationError::Auth("Session token has been revoked".to_string()));
+        }
+        Ok(claims)
+    }
+}
+
+/// Tells which agent presented a bearer token, so per-agent budgets can be
+/// kept. Accepts agent JWTs and session tokens whose signature and expiry
+/// check out, for any negotiation.
+#[derive(Clone)]
+pub struct TokenSubjects {
+    /// HS256 key of agent JWTs, unless they're signed with `jwt_keys`
+    agent_key: Option<Vec<u8>>,
+    jwt_keys: Option<Arc<RwLock<JwtKeyring>>>,
+    sessions: SessionTokens,
+}
+
+impl TokenSubjects {
+    pub fn new(jwt_secret: &str) -> Self {
+        Self {
+            agent_key: Some(jwt_secret.as_bytes().to_vec()),
+            jwt_keys: None,
+            sessions: SessionTokens::new(jwt_secret),
+        }
+    }
+
+    /// Checks agent JWTs against `keys` instead of the JWT secret.
+    pub fn with_jwt_keys(mut self, keys: Arc<RwLock<JwtKeyring>>) -> Self {
+        self.agent_key = None;
+        self.jwt_keys = Some(keys);
+        self
+    }
+
+    pub fn subject(&self, token: &str) -> Option<AgentId> {
+        #[derive(Deserialize)]
+        struct Subject {
+            sub: String,
+        }
+
+        let header = decode_header(token).ok()?;
+        let mut validation = Validation::new(header.alg);
+        validation.validate_aud = false;
+        let subject = match (header.alg, header.kid, &self.jwt_keys) {
+            (Algorithm::EdDSA, Some(kid), Some(keys)) => keys.read().verify::<Subject>(&kid, token, &validation).ok(),
+            (Algorithm::HS256, _, _) => [self.agent_key.as_ref(), Some(&self.sessions.key)].into_iter()
+                .flatten()
+                .find_map(|key| decode::<Subject>(token, &DecodingKey::from_secret(key), &validation).ok())
+                .map(|data| data.claims),
+            _ => None,
+        };
+        subject.and_then(|subject| AgentId::parse_str(&subject.sub).ok())
+    }
+}
+
+pub fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers.get(AUTHORIZATION)?
+        .to_str().ok()?
+        .strip_prefix("Bearer ")
+}
+
+fn audience(negotiation_id: TransactionId) -> String {
+    format!("negotiation:{}", negotiation_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trust::TrustSystem;
+
+    #[tokio::test]
+    async fn test_session_token_is_scoped_to_its_negotiation() {
+        let mut trust = TrustSystem::new().unwrap();
+        let agent_id = uuid::Uuid::new_v4();
+        let agent_token = trust.generate_jwt(agent_id).await.unwrap();
+        let agent_claims = trust.validate_jwt(&agent_token).await.unwrap();
+
+        let tokens = trust.session_tokens();
+        let negotiation_id = uuid::Uuid::new_v4();
+        let token = trust.issue_session_token(&agent_token, negotiation_id).await.unwrap();
+
+        let claims = tokens.decode(&token, negotiation_id).unwrap();
+        assert_eq!(claims.require_party(&[agent_id]).unwrap(), agent_id);
+        assert!(claims.require_party(&[uuid::Uuid::new_v4()]).is_err());
+        assert!(claims.exp <= agent_claims.exp);
+        assert_eq!(claims.role, agent_claims.role);
+
+        assert!(tokens.decode(&token, uuid::Uuid::new_v4()).is_err());
+        assert!(SessionTokens::new("other-secret").decode(&token, negotiation_id).is_err());
+        // The agent JWT itself is not accepted as a session token
+        assert!(tokens.decode(&agent_token, negotiation_id).is_err());
+    }
+}
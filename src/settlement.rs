@@ -1,71 +1,444 @@
 use crate::{
+    audit_log::{AuditAction, AuditLog},
+    cancellation::{DealChangeService, Party},
+    concession::ConcessionService,
+    config::{CancellationConfig, FeesConfig},
+    database::Database,
+    dead_letter::{DeadLetter, DeadLetterKind, DeadLetterQueue, DeadLetterStatus, ReputationUpdatePayload},
     error::{NegotiationError, Result},
-    model::PaymentMethod,
+    events::{EventBus, EventKind},
+    fees::{FeeBreakdown, FeeSchedule, PlatformFee},
+    metrics::Metrics,
+    model::{Negotiation, PaymentMethod, Quote, ShippingOption},
+    money::Money,
+    obligation::ObligationService,
+    order::{FulfillmentStatus, Order, OrderService},
+    trust::TrustSystem,
+    shipping::SHIPPING_METADATA_KEY,
+    solana_escrow::{OnChainEscrow, Pubkey, SolanaEscrow, BUYER_WALLET_METADATA, SELLER_WALLET_METADATA},
+    tax::{
+        TaxBreakdown, TaxCalculator, TaxContext, DELIVERY_LOCATION_METADATA_KEY, TAX_CATEGORY_METADATA_KEY,
+        TAX_METADATA_KEY,
+    },
+    validation::{FieldErrors, Validate},
     AgentId, TransactionId,
 };
-use chrono::{Duration, Utc};
+#[cfg(feature = "lightning")]
+use crate::lightning::{self, LndClient};
+use chrono::{DateTime, Duration, Utc};
+use hmac::{Hmac, Mac};
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use sha2::Sha256;
+use std::{collections::HashMap, str::FromStr, sync::Arc};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SettlementConfig {
     pub stripe_secret_key: Option<String>,
     pub solana_rpc_url: Option<String>,
     pub escrow_service_url: Option<String>,
+    pub webhook_secret: Option<String>,
+    pub delivery_confirmation_timeout_seconds: Option<u64>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Default window after shipment before delivery is auto-confirmed (3 days)
+const DEFAULT_DELIVERY_CONFIRMATION_TIMEOUT_SECONDS: u64 = 3 * 24 * 3600;
+
+/// Maximum allowed skew between a webhook's signed timestamp and our clock (5 minutes)
+const WEBHOOK_TOLERANCE_SECONDS: i64 = 300;
+
+/// Payment ID prefix of the placeholder holding an idempotency key while its
+/// payment is made
+const IDEMPOTENCY_CLAIM_PREFIX: &str = "claim_";
+/// How long a retry waits for the payment its key is held for
+const IDEMPOTENCY_CLAIM_WAIT: std::time::Duration = std::time::Duration::from_secs(30);
+const IDEMPOTENCY_CLAIM_POLL: std::time::Duration = std::time::Duration::from_millis(100);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PaymentRequest {
     pub transaction_id: TransactionId,
     pub buyer_id: AgentId,
     pub seller_id: AgentId,
-    pub amount: f64,
+    pub amount: Decimal,
     pub currency: String,
     pub payment_method: PaymentMethod,
     pub description: String,
     pub metadata: HashMap<String, String>,
+    /// Retries carrying the same key return the original payment instead of charging again
+    #[serde(default)]
+    pub idempotency_key: Option<String>,
+    /// Principals the buyer pays for under delegated authority, followed by
+    /// the buyer itself
+    #[serde(default)]
+    pub delegation_chain: Vec<AgentId>,
+}
+
+impl Validate for PaymentRequest {
+    fn validate_fields(&self, errors: &mut FieldErrors) {
+        errors.check(self.amount > Decimal::ZERO, "amount", "Amount must be greater than 0");
+        errors.currency("currency", &self.currency);
+        errors.check(self.buyer_id != self.seller_id, "seller_id", "Buyer and seller must differ");
+        if let Some(key) = &self.idempotency_key {
+            errors.check(!key.trim().is_empty(), "idempotency_key", "Idempotency key must not be empty");
+        }
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PaymentResult {
     pub success: bool,
     pub payment_id: String,
     pub transaction_id: TransactionId,
-    pub amount: f64,
+    pub amount: Decimal,
     pub currency: String,
     pub status: PaymentStatus,
     pub created_at: chrono::DateTime<chrono::Utc>,
     pub completed_at: Option<chrono::DateTime<chrono::Utc>>,
     pub error_message: Option<String>,
+    /// BOLT11 invoice the buyer pays, for Lightning payments
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub invoice: Option<String>,
+    /// Unsigned base64 transaction funding an on-chain escrow, for the
+    /// buyer's wallet to sign and send
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub funding_transaction: Option<String>,
+    /// What the buyer pays and the seller is paid after the platform's fee
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fees: Option<FeeBreakdown>,
+    /// Taxes included in the amount, line by line
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tax: Option<TaxBreakdown>,
+}
+
+impl PaymentResult {
+    /// Whether the provider has confirmed the funds moved.
+    pub fn is_confirmed(&self) -> bool {
+        self.status == PaymentStatus::Succeeded
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Where a payment is in its lifecycle. Card and on-chain payments are
+/// submitted as `AwaitingConfirmation` and only reach `Succeeded` or `Failed`
+/// once the provider confirms, by webhook or when polled; escrow holds stay
+/// `Pending` until released, once funded when placed on chain, and
+/// pay-on-delivery payments stay `Created` until the buyer confirms delivery.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "snake_case")]
 pub enum PaymentStatus {
+    Created,
+    AwaitingConfirmation,
     Pending,
     Processing,
     Succeeded,
     Failed,
     Cancelled,
+    PartiallyRefunded,
     Refunded,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl PaymentStatus {
+    /// Whether the provider has yet to say how the payment went.
+    pub fn awaits_confirmation(&self) -> bool {
+        matches!(self, PaymentStatus::AwaitingConfirmation | PaymentStatus::Processing)
+    }
+
+    /// Whether the payment has yet to succeed or fail, counting ones deferred
+    /// until delivery.
+    pub fn is_outstanding(&self) -> bool {
+        *self == PaymentStatus::Created || self.awaits_confirmation()
+    }
+
+    pub fn can_transition_to(&self, next: &PaymentStatus) -> bool {
+        use PaymentStatus::*;
+        matches!(
+            (self, next),
+            (Created, AwaitingConfirmation | Pending | Processing | Succeeded | Failed | Cancelled)
+                | (AwaitingConfirmation | Processing, AwaitingConfirmation | Processing | Pending | Succeeded | Failed | Cancelled)
+                | (Pending, Succeeded | Cancelled | Refunded)
+                | (Succeeded | PartiallyRefunded, PartiallyRefunded | Refunded)
+        )
+    }
+}
+
+/// Persisted payment, as stored in the payments table
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PaymentRecord {
+    pub payment_id: String,
+    pub transaction_id: TransactionId,
+    pub buyer_id: AgentId,
+    pub seller_id: AgentId,
+    pub payment_method: PaymentMethod,
+    pub amount: Decimal,
+    pub currency: String,
+    pub status: PaymentStatus,
+    pub idempotency_key: Option<String>,
+    pub error_message: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub delegation_chain: Vec<AgentId>,
+    /// For a refund, the payment it gives money back from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refund_of: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refund_reason: Option<RefundReason>,
+    /// Taxes included in the amount
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tax: Option<TaxBreakdown>,
+}
+
+impl PaymentRecord {
+    pub fn from_result(request: &PaymentRequest, result: &PaymentResult) -> Self {
+        Self {
+            payment_id: result.payment_id.clone(),
+            transaction_id: result.transaction_id,
+            buyer_id: request.buyer_id,
+            seller_id: request.seller_id,
+            payment_method: request.payment_method.clone(),
+            amount: result.amount,
+            currency: result.currency.clone(),
+            status: result.status.clone(),
+            idempotency_key: request.idempotency_key.clone(),
+            error_message: result.error_message.clone(),
+            created_at: result.created_at,
+            updated_at: Utc::now(),
+            completed_at: result.completed_at,
+            delegation_chain: request.delegation_chain.clone(),
+            refund_of: None,
+            refund_reason: None,
+            tax: result.tax.clone(),
+        }
+    }
+
+    pub fn to_result(&self) -> PaymentResult {
+        PaymentResult {
+            success: !matches!(self.status, PaymentStatus::Failed | PaymentStatus::Cancelled),
+            payment_id: self.payment_id.clone(),
+            transaction_id: self.transaction_id,
+            amount: self.amount,
+            currency: self.currency.clone(),
+            status: self.status.clone(),
+            created_at: self.created_at,
+            completed_at: self.completed_at,
+            error_message: self.error_message.clone(),
+            invoice: None,
+            funding_transaction: None,
+            fees: None,
+            tax: self.tax.clone(),
+        }
+    }
+
+    /// Moves the payment to a new status, stamping completion for terminal states.
+    /// Moving to the status it already has is a no-op.
+    pub fn transition(&mut self, status: PaymentStatus) -> Result<()> {
+        if status == self.status {
+            return Ok(());
+        }
+        if !self.status.can_transition_to(&status) {
+            return Err(NegotiationError::Payment(format!(
+                "Payment {} can't move from {:?} to {:?}", self.payment_id, self.status, status
+            )));
+        }
+        let now = Utc::now();
+        if matches!(status, PaymentStatus::Succeeded | PaymentStatus::Refunded) && self.completed_at.is_none() {
+            self.completed_at = Some(now);
+        }
+        self.status = status;
+        self.updated_at = now;
+        Ok(())
+    }
+
+    /// Whether a retried request matches the one this payment was created for.
+    fn matches_request(&self, request: &PaymentRequest) -> bool {
+        self.buyer_id == request.buyer_id
+            && self.seller_id == request.seller_id
+            && self.amount == request.amount
+            && self.currency == request.currency
+            && self.payment_method == request.payment_method
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RefundReason {
+    /// The goods never arrived
+    NotDelivered,
+    /// The goods arrived damaged or not as described
+    NotAsDescribed,
+    /// The buyer was charged more than once
+    DuplicateCharge,
+    /// The seller gives money back of their own accord, e.g. for a late delivery
+    Goodwill,
+}
+
+impl RefundReason {
+    pub fn may_be_raised_by(&self, party: Party) -> bool {
+        *self != Self::Goodwill || party == Party::Seller
+    }
+
+    /// Whether the seller is at fault, and loses reputation for the refund
+    pub fn penalises_seller(&self) -> bool {
+        matches!(self, Self::NotDelivered | Self::NotAsDescribed)
+    }
+}
+
+impl FromStr for RefundReason {
+    type Err = NegotiationError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        serde_json::from_value(serde_json::Value::String(s.to_string()))
+            .map_err(|_| NegotiationError::InvalidInput(format!("Unknown refund reason: {}", s)))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RefundRequest {
+    /// Amount to give back; all that hasn't been refunded yet when omitted
+    #[serde(default)]
+    pub amount: Option<Decimal>,
+    pub reason: RefundReason,
+}
+
+impl Validate for RefundRequest {
+    fn validate_fields(&self, errors: &mut FieldErrors) {
+        if let Some(amount) = self.amount {
+            errors.check(amount > Decimal::ZERO, "amount", "Amount must be greater than 0");
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Refund {
+    /// The refund, as recorded in the payments table
+    pub refund: PaymentRecord,
+    /// The refunded payment as it now stands
+    pub payment: PaymentRecord,
+    /// Reputation the seller loses for it, for the caller's trust system to apply
+    pub reputation_penalty: u32,
+}
+
+/// Stripe webhook event envelope
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StripeWebhookEvent {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub event_type: String,
+    pub created: i64,
+    pub data: StripeEventData,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StripeEventData {
+    pub object: serde_json::Value,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WebhookEventKind {
+    PaymentSucceeded,
+    PaymentFailed,
+    PaymentProcessing,
+    PaymentCanceled,
+    ChargeRefunded,
+    Unhandled,
+}
+
+impl StripeWebhookEvent {
+    pub fn kind(&self) -> WebhookEventKind {
+        match self.event_type.as_str() {
+            "payment_intent.succeeded" => WebhookEventKind::PaymentSucceeded,
+            "payment_intent.payment_failed" => WebhookEventKind::PaymentFailed,
+            "payment_intent.processing" => WebhookEventKind::PaymentProcessing,
+            "payment_intent.canceled" => WebhookEventKind::PaymentCanceled,
+            "charge.refunded" => WebhookEventKind::ChargeRefunded,
+            _ => WebhookEventKind::Unhandled,
+        }
+    }
+
+    /// Our payment id, taken from the object's metadata when we set it, otherwise
+    /// the Stripe object id.
+    pub fn payment_id(&self) -> Option<&str> {
+        self.data.object.get("metadata")
+            .and_then(|metadata| metadata.get("payment_id"))
+            .and_then(|id| id.as_str())
+            .or_else(|| self.data.object.get("id").and_then(|id| id.as_str()))
+    }
+
+    /// Payment state this event moves the referenced payment into, if any.
+    pub fn payment_status(&self) -> Option<PaymentStatus> {
+        match self.kind() {
+            WebhookEventKind::PaymentSucceeded => Some(PaymentStatus::Succeeded),
+            WebhookEventKind::PaymentFailed => Some(PaymentStatus::Failed),
+            WebhookEventKind::PaymentProcessing => Some(PaymentStatus::Processing),
+            WebhookEventKind::PaymentCanceled => Some(PaymentStatus::Cancelled),
+            WebhookEventKind::ChargeRefunded => Some(PaymentStatus::Refunded),
+            WebhookEventKind::Unhandled => None,
+        }
+    }
+}
+
+/// Verifies a `Stripe-Signature` header (`t=<timestamp>,v1=<hex hmac>,...`)
+/// against the raw payload: HMAC-SHA256 over `"{t}.{payload}"` keyed with the
+/// endpoint secret, with the timestamp required to be within the tolerance.
+pub fn verify_stripe_signature(
+    payload: &str,
+    signature_header: &str,
+    secret: &str,
+    now: DateTime<Utc>,
+) -> Result<()> {
+    let mut timestamp = None;
+    let mut signatures = Vec::new();
+    for part in signature_header.split(',') {
+        match part.trim().split_once('=') {
+            Some(("t", value)) => timestamp = value.parse::<i64>().ok(),
+            Some(("v1", value)) => signatures.push(value),
+            _ => {}
+        }
+    }
+
+    let timestamp = timestamp
+        .ok_or_else(|| NegotiationError::Payment("Webhook signature missing timestamp".to_string()))?;
+    if signatures.is_empty() {
+        return Err(NegotiationError::Payment("Webhook signature missing v1 signature".to_string()));
+    }
+    if (now.timestamp() - timestamp).abs() > WEBHOOK_TOLERANCE_SECONDS {
+        return Err(NegotiationError::Payment("Webhook timestamp outside tolerance".to_string()));
+    }
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .map_err(|e| NegotiationError::Payment(format!("Invalid webhook secret: {}", e)))?;
+    mac.update(format!("{}.{}", timestamp, payload).as_bytes());
+
+    let valid = signatures.iter()
+        .filter_map(|signature| hex::decode(signature).ok())
+        .any(|signature| mac.clone().verify_slice(&signature).is_ok());
+
+    if valid {
+        Ok(())
+    } else {
+        Err(NegotiationError::Payment("Invalid webhook signature".to_string()))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EscrowHold {
     pub id: uuid::Uuid,
     pub transaction_id: TransactionId,
     pub buyer_id: AgentId,
     pub seller_id: AgentId,
-    pub amount: f64,
+    pub amount: Decimal,
     pub currency: String,
     pub hold_duration_seconds: u64,
     pub created_at: chrono::DateTime<chrono::Utc>,
     pub expires_at: chrono::DateTime<chrono::Utc>,
     pub status: EscrowStatus,
-    pub release_conditions: Vec<String>,
+    pub delivery: DeliveryConfirmation,
+    /// The escrow account, for holds placed on chain
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_chain: Option<OnChainEscrow>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "snake_case")]
 pub enum EscrowStatus {
     Active,
@@ -74,182 +447,1357 @@ pub enum EscrowStatus {
     Expired,
 }
 
+/// Delivery progress gating escrow release: the seller ships, then the buyer
+/// confirms receipt or the confirmation window lapses.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryStatus {
+    AwaitingShipment,
+    Shipped,
+    Confirmed,
+    AutoConfirmed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShipmentProof {
+    pub carrier: String,
+    pub tracking_number: String,
+    pub proof_url: Option<String>,
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeliveryConfirmation {
+    pub status: DeliveryStatus,
+    pub shipment_proof: Option<ShipmentProof>,
+    pub shipped_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub confirmed_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub auto_confirm_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl DeliveryConfirmation {
+    pub fn new() -> Self {
+        Self {
+            status: DeliveryStatus::AwaitingShipment,
+            shipment_proof: None,
+            shipped_at: None,
+            confirmed_at: None,
+            auto_confirm_at: None,
+        }
+    }
+
+    pub fn is_confirmed(&self) -> bool {
+        matches!(self.status, DeliveryStatus::Confirmed | DeliveryStatus::AutoConfirmed)
+    }
+}
+
+impl Default for DeliveryConfirmation {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EscrowHold {
+    pub fn mark_shipped(&mut self, proof: ShipmentProof, confirmation_timeout: Duration) -> Result<()> {
+        if self.status != EscrowStatus::Active {
+            return Err(NegotiationError::Payment("Escrow hold is not active".to_string()));
+        }
+        if self.delivery.status != DeliveryStatus::AwaitingShipment {
+            return Err(NegotiationError::Payment("Shipment already recorded for this escrow".to_string()));
+        }
+        let now = Utc::now();
+        self.delivery.status = DeliveryStatus::Shipped;
+        self.delivery.shipment_proof = Some(proof);
+        self.delivery.shipped_at = Some(now);
+        self.delivery.auto_confirm_at = Some(now + confirmation_timeout);
+        Ok(())
+    }
+
+    pub fn confirm_delivery(&mut self) -> Result<()> {
+        if self.status != EscrowStatus::Active {
+            return Err(NegotiationError::Payment("Escrow hold is not active".to_string()));
+        }
+        if self.delivery.status != DeliveryStatus::Shipped {
+            return Err(NegotiationError::Payment("Delivery can only be confirmed after shipment".to_string()));
+        }
+        self.delivery.status = DeliveryStatus::Confirmed;
+        self.delivery.confirmed_at = Some(Utc::now());
+        Ok(())
+    }
+
+    /// Auto-confirms delivery if the buyer let the confirmation window lapse.
+    /// Returns true when the state changed.
+    pub fn auto_confirm_if_due(&mut self) -> bool {
+        let due = self.delivery.auto_confirm_at.is_some_and(|at| Utc::now() >= at);
+        if self.status == EscrowStatus::Active && self.delivery.status == DeliveryStatus::Shipped && due {
+            self.delivery.status = DeliveryStatus::AutoConfirmed;
+            self.delivery.confirmed_at = Some(Utc::now());
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Whether a request won its idempotency key, with the placeholder it holds
+/// the key with, or lost it to a request that made the payment
+enum IdempotencyClaim {
+    Won(Option<PaymentRecord>),
+    Lost(PaymentResult),
+}
+
+/// The escrow hold an escrow payment is for.
+fn escrow_id(payment_id: &str) -> Option<uuid::Uuid> {
+    payment_id.strip_prefix("escrow_").and_then(|id| uuid::Uuid::parse_str(id).ok())
+}
+
 #[derive(Clone)]
 pub struct SettlementService {
     config: SettlementConfig,
+    database: Database,
+    dead_letters: DeadLetterQueue,
+    audit: AuditLog,
+    obligations: ObligationService,
+    orders: OrderService,
+    deal_changes: DealChangeService,
+    concessions: ConcessionService,
+    events: EventBus,
+    metrics: Metrics,
+    refund_reputation_penalty: u32,
+    fee_schedule: FeeSchedule,
+    tax: Option<Arc<dyn TaxCalculator>>,
+    #[cfg(feature = "lightning")]
+    lightning: Option<LndClient>,
+    solana_escrow: Option<SolanaEscrow>,
 }
 
 impl SettlementService {
-    pub async fn new(config: SettlementConfig) -> Result<Self> {
+    pub async fn new(config: SettlementConfig, database: Database) -> Result<Self> {
         Ok(Self {
             config,
+            dead_letters: DeadLetterQueue::new(database.clone()),
+            audit: AuditLog::new(database.clone()),
+            obligations: ObligationService::new(database.clone()),
+            orders: OrderService::new(database.clone()),
+            deal_changes: DealChangeService::new(database.clone(), CancellationConfig::default()),
+            concessions: ConcessionService::new(database.clone()),
+            events: EventBus::default(),
+            metrics: Metrics::default(),
+            refund_reputation_penalty: CancellationConfig::default().refund_reputation_penalty,
+            fee_schedule: FeeSchedule::default(),
+            tax: None,
+            #[cfg(feature = "lightning")]
+            lightning: None,
+            solana_escrow: None,
+            database,
         })
     }
 
+    pub fn dead_letters(&self) -> &DeadLetterQueue {
+        &self.dead_letters
+    }
+
+    pub fn audit_log(&self) -> &AuditLog {
+        &self.audit
+    }
+
+    pub fn obligations(&self) -> &ObligationService {
+        &self.obligations
+    }
+
+    pub fn orders(&self) -> &OrderService {
+        &self.orders
+    }
+
+    pub fn deal_changes(&self) -> &DealChangeService {
+        &self.deal_changes
+    }
+
+    pub fn concessions(&self) -> &ConcessionService {
+        &self.concessions
+    }
+
+    pub fn with_cancellation_config(mut self, config: CancellationConfig) -> Self {
+        self.refund_reputation_penalty = config.refund_reputation_penalty;
+        self.deal_changes = DealChangeService::new(self.database.clone(), config);
+        self
+    }
+
+    /// Takes the marketplace fees in `config` from payments as they settle.
+    pub fn with_fees(mut self, config: FeesConfig) -> Self {
+        self.fee_schedule = FeeSchedule::new(config);
+        self
+    }
+
+    /// Checks the taxes payments carry with `tax` before charging them.
+    pub fn with_tax_calculator(mut self, tax: Arc<dyn TaxCalculator>) -> Self {
+        self.tax = Some(tax);
+        self
+    }
+
+    /// Takes Lightning payments through the LND node behind `client`.
+    #[cfg(feature = "lightning")]
+    pub fn with_lightning(mut self, client: LndClient) -> Self {
+        self.lightning = Some(client);
+        self
+    }
+
+    /// Places escrow holds on chain through the Solana escrow program.
+    pub fn with_solana_escrow(mut self, solana_escrow: SolanaEscrow) -> Self {
+        self.solana_escrow = Some(solana_escrow);
+        self
+    }
+
+    /// Publishes succeeded payments on `events` instead of a bus of its own.
+    pub fn with_event_bus(mut self, events: EventBus) -> Self {
+        self.events = events;
+        self
+    }
+
+    pub fn events(&self) -> &EventBus {
+        &self.events
+    }
+
+    /// Records payment latency and failures in `metrics`.
+    pub fn with_metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    fn delivery_confirmation_timeout(&self) -> Duration {
+        let seconds = self.config.delivery_confirmation_timeout_seconds
+            .unwrap_or(DEFAULT_DELIVERY_CONFIRMATION_TIMEOUT_SECONDS);
+        Duration::seconds(seconds as i64)
+    }
+
     pub async fn create_payment(
         &self,
         buyer_id: AgentId,
         seller_id: AgentId,
-        amount: f64,
-        currency: String,
+        amount: Money,
+        idempotency_key: Option<String>,
+    ) -> Result<PaymentResult> {
+        self.create_delegated_payment(buyer_id, seller_id, amount, idempotency_key, vec![], PaymentMethod::Stripe).await
+    }
+
+    /// As `create_payment`, for a buyer paying by `payment_method` under
+    /// delegated authority; `delegation_chain` is recorded with the payment.
+    pub async fn create_delegated_payment(
+        &self,
+        buyer_id: AgentId,
+        seller_id: AgentId,
+        amount: Money,
+        idempotency_key: Option<String>,
+        delegation_chain: Vec<AgentId>,
+        payment_method: PaymentMethod,
     ) -> Result<PaymentResult> {
         let transaction_id = uuid::Uuid::new_v4();
         let payment_request = PaymentRequest {
             transaction_id,
             buyer_id,
             seller_id,
-            amount,
-            currency,
-            payment_method: PaymentMethod::Stripe, // Default to Stripe
+            amount: amount.amount,
+            currency: amount.currency,
+            payment_method,
             description: "Marketplace transaction".to_string(),
             metadata: HashMap::new(),
+            idempotency_key,
+            delegation_chain,
         };
 
         self.process_payment(payment_request).await
     }
 
+    /// Charges the buyer of an accepted quote its landed cost: the price, the
+    /// taxes the seller added, which are recorded with the payment, and the
+    /// cheapest shipping.
+    pub async fn create_quote_payment(
+        &self,
+        negotiation: &Negotiation,
+        quote: &Quote,
+        delegation_chain: Vec<AgentId>,
+        payment_method: PaymentMethod,
+    ) -> Result<PaymentResult> {
+        let total = quote.total()?;
+        let mut metadata: HashMap<_, _> = quote.metadata.iter()
+            .filter(|(key, _)| [TAX_METADATA_KEY, TAX_CATEGORY_METADATA_KEY, DELIVERY_LOCATION_METADATA_KEY].contains(&key.as_str()))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        if let Some(shipping) = quote.shipping() {
+            metadata.insert(SHIPPING_METADATA_KEY.to_string(), serde_json::to_string(shipping)?);
+        }
+        self.process_payment(PaymentRequest {
+            transaction_id: uuid::Uuid::new_v4(),
+            buyer_id: negotiation.buyer_id,
+            seller_id: negotiation.seller_id,
+            amount: total.amount,
+            currency: total.currency,
+            payment_method,
+            description: "Marketplace transaction".to_string(),
+            metadata,
+            idempotency_key: Some(format!("negotiation-{}", negotiation.id)),
+            delegation_chain,
+        }).await
+    }
+
+    #[tracing::instrument(skip_all, fields(transaction_id = %request.transaction_id, method = ?request.payment_method))]
     pub async fn process_payment(&self, request: PaymentRequest) -> Result<PaymentResult> {
+        self.settle_payment(request, true).await
+    }
+
+    /// Makes and stores a payment once per idempotency key. A payment that
+    /// fails is dead-lettered when `dead_letter_failures` is set; replays
+    /// clear it so a failed replay stays on its own dead letter.
+    async fn settle_payment(&self, request: PaymentRequest, dead_letter_failures: bool) -> Result<PaymentResult> {
+        if let Some(existing) = self.find_idempotent_payment(&request).await? {
+            return Ok(existing);
+        }
+
+        let tax = self.check_tax(&request)?;
+        let claim = match self.claim_idempotency_key(&request).await? {
+            IdempotencyClaim::Won(claim) => claim,
+            IdempotencyClaim::Lost(existing) => return Ok(existing),
+        };
+        let started = std::time::Instant::now();
+        let result = self.execute_payment(&request).await.map(|payment| PaymentResult { tax, ..payment });
+        self.metrics.observe_settlement_latency(started.elapsed());
+        match &result {
+            Ok(payment) => {
+                if payment.status == PaymentStatus::Failed {
+                    self.metrics.payment_failed(&request.payment_method);
+                }
+                let mut stored = self.store_payment(&request, payment, claim.as_ref()).await?;
+                let record = PaymentRecord::from_result(&request, &stored);
+                if stored.status == PaymentStatus::Succeeded {
+                    stored.fees = Some(self.collect_fee(&record).await?.breakdown);
+                    self.publish_payment_succeeded(&stored);
+                } else {
+                    stored.fees = Some(self.payment_fees(&record).await?);
+                }
+                Ok(stored)
+            }
+            Err(e) => {
+                // Nothing was charged, so a retry may have the key
+                if let Some(claim) = &claim {
+                    self.database.delete_payment(&claim.payment_id).await?;
+                }
+                self.metrics.payment_failed(&request.payment_method);
+                if dead_letter_failures {
+                    self.dead_letters.record(DeadLetterKind::Settlement, &request, &e.to_string()).await;
+                }
+                result
+            }
+        }
+    }
+
+    /// The taxes included in a payment, checked against what the tax
+    /// calculator charges on the price, without taxes and shipping, when the
+    /// service has one.
+    fn check_tax(&self, request: &PaymentRequest) -> Result<Option<TaxBreakdown>> {
+        let Some(tax) = TaxBreakdown::from_metadata(&request.metadata)? else {
+            return Ok(None);
+        };
+        let shipping = match request.metadata.get(SHIPPING_METADATA_KEY) {
+            Some(json) => serde_json::from_str::<ShippingOption>(json)?.cost,
+            None => Decimal::ZERO,
+        };
+        let subtotal = Money::new(request.amount - tax.total() - shipping, request.currency.clone());
+        if let (Some(calculator), Some(context)) = (&self.tax, TaxContext::from_metadata(&request.metadata, &subtotal)) {
+            let due = calculator.calculate(&context)?;
+            if due != tax {
+                return Err(NegotiationError::Validation(format!(
+                    "Payment includes {} of tax but {} is due",
+                    Money::new(tax.total(), request.currency.clone()),
+                    Money::new(due.total(), request.currency.clone()),
+                )));
+            }
+        }
+        Ok(Some(tax))
+    }
+
+    /// Returns the stored payment for a retried idempotency key, rejecting reuse
+    /// of a key with different payment parameters. Waits for a payment still
+    /// being made under the key.
+    async fn find_idempotent_payment(&self, request: &PaymentRequest) -> Result<Option<PaymentResult>> {
+        let Some(key) = request.idempotency_key.as_deref() else {
+            return Ok(None);
+        };
+
+        let deadline = tokio::time::Instant::now() + IDEMPOTENCY_CLAIM_WAIT;
+        loop {
+            match self.database.get_payment_by_idempotency_key(key).await? {
+                Some(existing) if !existing.matches_request(request) => {
+                    return Err(NegotiationError::Validation(format!(
+                        "Idempotency key {} was already used with different payment parameters", key
+                    )));
+                }
+                Some(existing) if existing.payment_id.starts_with(IDEMPOTENCY_CLAIM_PREFIX) => {
+                    if tokio::time::Instant::now() >= deadline {
+                        return Err(NegotiationError::Payment(format!("Payment for idempotency key {} is still being made", key)));
+                    }
+                    tokio::time::sleep(IDEMPOTENCY_CLAIM_POLL).await;
+                }
+                Some(existing) => {
+                    tracing::info!("Idempotent replay of payment {} for key {}", existing.payment_id, key);
+                    let mut result = existing.to_result();
+                    result.fees = Some(self.payment_fees(&existing).await?);
+                    return Ok(Some(result));
+                }
+                None => return Ok(None),
+            }
+        }
+    }
+
+    /// Records a placeholder under the request's idempotency key before any
+    /// money moves, so only one of concurrent retries goes on to charge the
+    /// buyer; the others get the payment it makes.
+    async fn claim_idempotency_key(&self, request: &PaymentRequest) -> Result<IdempotencyClaim> {
+        if request.idempotency_key.is_none() {
+            return Ok(IdempotencyClaim::Won(None));
+        }
+        let now = Utc::now();
+        let placeholder = PaymentRecord::from_result(request, &PaymentResult {
+            success: true,
+            payment_id: format!("{}{}", IDEMPOTENCY_CLAIM_PREFIX, uuid::Uuid::new_v4()),
+            transaction_id: request.transaction_id,
+            amount: request.amount,
+            currency: request.currency.clone(),
+            status: PaymentStatus::Created,
+            created_at: now,
+            completed_at: None,
+            error_message: None,
+            invoice: None,
+            funding_transaction: None,
+            fees: None,
+            tax: None,
+        });
+        if self.database.create_payment(&placeholder).await? {
+            return Ok(IdempotencyClaim::Won(Some(placeholder)));
+        }
+
+        match self.find_idempotent_payment(request).await? {
+            Some(existing) => Ok(IdempotencyClaim::Lost(existing)),
+            None => Err(NegotiationError::Payment("Payment was abandoned by a concurrent request; retry it".to_string())),
+        }
+    }
+
+    /// Persists a processed payment, in place of the placeholder claiming its
+    /// idempotency key when there is one.
+    async fn store_payment(&self, request: &PaymentRequest, payment: &PaymentResult, claim: Option<&PaymentRecord>) -> Result<PaymentResult> {
+        let record = PaymentRecord::from_result(request, payment);
+        let stored = match claim {
+            Some(claim) => self.database.replace_payment(&claim.payment_id, &record).await?,
+            None => self.database.create_payment(&record).await?,
+        };
+        if !stored {
+            return Err(NegotiationError::Payment(format!("Failed to record payment {}", payment.payment_id)));
+        }
+        self.audit.record(AuditAction::Payment, Some(record.buyer_id), &record.payment_id, &record).await;
+        Ok(payment.clone())
+    }
+
+    pub async fn get_payment(&self, payment_id: &str) -> Result<PaymentRecord> {
+        self.database.get_payment(payment_id).await?
+            .ok_or_else(|| NegotiationError::NotFound(format!("Payment {}", payment_id)))
+    }
+
+    async fn execute_payment(&self, request: &PaymentRequest) -> Result<PaymentResult> {
         match request.payment_method {
-            PaymentMethod::Stripe => self.process_stripe_payment(&request).await,
-            PaymentMethod::Solana => self.process_solana_payment(&request).await,
-            PaymentMethod::Escrow => self.process_escrow_payment(&request).await,
+            PaymentMethod::Stripe => self.process_stripe_payment(request).await,
+            PaymentMethod::Solana => self.process_solana_payment(request).await,
+            PaymentMethod::Escrow => self.process_escrow_payment(request).await,
+            PaymentMethod::PayOnDelivery => Ok(self.defer_until_delivery(request)),
+            PaymentMethod::Lightning => self.process_lightning_payment(request).await,
+        }
+    }
+
+    /// Records a pay-on-delivery payment without charging the buyer yet.
+    fn defer_until_delivery(&self, request: &PaymentRequest) -> PaymentResult {
+        tracing::info!("Deferring payment for transaction {} until delivery", request.transaction_id);
+        PaymentResult {
+            success: true,
+            payment_id: format!("pod_{}", uuid::Uuid::new_v4()),
+            transaction_id: request.transaction_id,
+            amount: request.amount,
+            currency: request.currency.clone(),
+            status: PaymentStatus::Created,
+            created_at: Utc::now(),
+            completed_at: None,
+            error_message: None,
+            invoice: None,
+            funding_transaction: None,
+            fees: None,
+            tax: None,
+        }
+    }
+
+    /// Buyer confirms a pay-on-delivery order arrived, which collects the
+    /// payment. It then succeeds once the collection is confirmed.
+    pub async fn confirm_payment_delivery(&self, payment_id: &str, buyer_id: AgentId) -> Result<PaymentRecord> {
+        let mut payment = self.get_payment(payment_id).await?;
+        if payment.buyer_id != buyer_id {
+            return Err(NegotiationError::Auth("Only the buyer can confirm delivery".to_string()));
+        }
+        if payment.payment_method != PaymentMethod::PayOnDelivery {
+            return Err(NegotiationError::Validation(format!("Payment {} isn't paid on delivery", payment_id)));
         }
+        if payment.status != PaymentStatus::Created {
+            return Err(NegotiationError::Payment(format!("Payment {} is already {:?}", payment_id, payment.status)));
+        }
+
+        self.events.publish(EventKind::DeliveryConfirmed {
+            transaction_id: payment.transaction_id,
+            payment_id: payment.payment_id.clone(),
+        });
+        // Mock collection through the buyer's card on file
+        self.apply_transition(&mut payment, PaymentStatus::AwaitingConfirmation).await?;
+        Ok(payment)
     }
 
     async fn process_stripe_payment(&self, request: &PaymentRequest) -> Result<PaymentResult> {
         // Mock Stripe payment processing
-        tracing::info!("Processing mock Stripe payment: ${} {}", request.amount, request.currency);
+        // A destination charge: Stripe keeps the application fee for the
+        // platform and transfers the rest to the seller's account
+        let amount = Money::new(request.amount, request.currency.clone());
+        let fees = self.fee_schedule.assess(&PaymentMethod::Stripe, &amount);
+        tracing::info!(
+            "Processing mock Stripe payment: {} ({} minor units, application fee {})",
+            amount, amount.to_minor_units()?, Money::new(fees.fee, amount.currency.clone()).to_minor_units()?
+        );
 
+        // The PaymentIntent is confirmed later, by webhook or when polled
         Ok(PaymentResult {
             success: true,
             payment_id: format!("stripe_{}", uuid::Uuid::new_v4()),
             transaction_id: request.transaction_id,
             amount: request.amount,
             currency: request.currency.clone(),
-            status: PaymentStatus::Succeeded,
+            status: PaymentStatus::AwaitingConfirmation,
             created_at: Utc::now(),
-            completed_at: Some(Utc::now()),
+            completed_at: None,
             error_message: None,
+            invoice: None,
+            funding_transaction: None,
+            fees: Some(fees),
+            tax: None,
         })
     }
 
     async fn process_solana_payment(&self, request: &PaymentRequest) -> Result<PaymentResult> {
         // Placeholder for Solana payment processing
-        // This would integrate with Solana RPC to create and verify transactions
+        // This would integrate with Solana RPC to submit the transaction; it
+        // is confirmed once finalized
         Ok(PaymentResult {
             success: true,
             payment_id: format!("sol_{}", uuid::Uuid::new_v4()),
             transaction_id: request.transaction_id,
             amount: request.amount,
             currency: request.currency.clone(),
-            status: PaymentStatus::Succeeded,
+            status: PaymentStatus::AwaitingConfirmation,
             created_at: Utc::now(),
-            completed_at: Some(Utc::now()),
+            completed_at: None,
             error_message: None,
+            invoice: None,
+            funding_transaction: None,
+            fees: None,
+            tax: None,
         })
     }
 
-    async fn process_escrow_payment(&self, request: &PaymentRequest) -> Result<PaymentResult> {
-        // Create an escrow hold
-        let escrow_hold = EscrowHold {
-            id: uuid::Uuid::new_v4(),
-            transaction_id: request.transaction_id,
-            buyer_id: request.buyer_id,
-            seller_id: request.seller_id,
-            amount: request.amount,
-            currency: request.currency.clone(),
-            hold_duration_seconds: 7 * 24 * 3600, // 7 days
-            created_at: Utc::now(),
-            expires_at: Utc::now() + Duration::days(7),
-            status: EscrowStatus::Active,
-            release_conditions: vec![
-                "Delivery confirmed".to_string(),
-                "Quality verified".to_string(),
-            ],
-        };
-
-        // Store the escrow hold (would typically go to database)
-        tracing::info!("Created escrow hold: {}", escrow_hold.id);
+    /// Creates an invoice for the buyer to pay; the payment succeeds once
+    /// the node reports it settled.
+    #[cfg(feature = "lightning")]
+    async fn process_lightning_payment(&self, request: &PaymentRequest) -> Result<PaymentResult> {
+        let client = self.lightning_client()?;
+        let amount = Money::new(request.amount, request.currency.clone());
+        let invoice = client.create_invoice(&amount, &format!("DCAP transaction {}", request.transaction_id)).await?;
+        tracing::info!("Created Lightning invoice for {} ({} msat)", amount, invoice.value_msat);
 
         Ok(PaymentResult {
             success: true,
-            payment_id: format!("escrow_{}", escrow_hold.id),
+            payment_id: lightning::payment_id(&invoice.r_hash),
             transaction_id: request.transaction_id,
             amount: request.amount,
             currency: request.currency.clone(),
-            status: PaymentStatus::Pending,
+            status: PaymentStatus::AwaitingConfirmation,
             created_at: Utc::now(),
             completed_at: None,
             error_message: None,
+            invoice: Some(invoice.payment_request),
+            funding_transaction: None,
+            fees: None,
+            tax: None,
         })
     }
 
+    #[cfg(not(feature = "lightning"))]
+    async fn process_lightning_payment(&self, _request: &PaymentRequest) -> Result<PaymentResult> {
+        Err(NegotiationError::Payment("Lightning payments need the `lightning` feature".to_string()))
+    }
+
+    #[cfg(feature = "lightning")]
+    fn lightning_client(&self) -> Result<&LndClient> {
+        self.lightning.as_ref()
+            .ok_or_else(|| NegotiationError::Payment("No Lightning node is configured".to_string()))
+    }
+
+    /// Follows the Lightning node's invoice updates, moving each Lightning
+    /// payment on as its invoice is paid or cancelled. Returns when the node
+    /// closes the subscription.
+    #[cfg(feature = "lightning")]
+    pub async fn watch_lightning_invoices(&self) -> Result<()> {
+        let mut subscription = self.lightning_client()?.subscribe_invoices().await?;
+        while let Some(invoice) = subscription.next().await? {
+            let Some(status) = invoice.state.payment_status() else {
+                continue;
+            };
+            // Invoices created outside DCAP have no payment
+            let Some(mut payment) = self.database.get_payment(&lightning::payment_id(&invoice.r_hash)).await? else {
+                continue;
+            };
+            if payment.status.awaits_confirmation() {
+                self.apply_transition(&mut payment, status).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn process_escrow_payment(&self, request: &PaymentRequest) -> Result<PaymentResult> {
+        // Create an escrow hold
+        let mut escrow_hold = EscrowHold {
+            id: uuid::Uuid::new_v4(),
+            transaction_id: request.transaction_id,
+            buyer_id: request.buyer_id,
+            seller_id: request.seller_id,
+            amount: request.amount,
+            currency: request.currency.clone(),
+            hold_duration_seconds: 7 * 24 * 3600, // 7 days
+            created_at: Utc::now(),
+            expires_at: Utc::now() + Duration::days(7),
+            status: EscrowStatus::Active,
+            delivery: DeliveryConfirmation::new(),
+            on_chain: None,
+        };
+
+        // On chain, the hold only stands once the buyer funds it
+        let mut funding_transaction = None;
+        if let Some(solana) = &self.solana_escrow {
+            let wallet = |key: &str| request.metadata.get(key)
+                .ok_or_else(|| NegotiationError::Validation(format!("On-chain escrow needs the {} in the payment metadata", key)))
+                .and_then(|wallet| wallet.parse::<Pubkey>());
+            let (buyer_wallet, seller_wallet) = (wallet(BUYER_WALLET_METADATA)?, wallet(SELLER_WALLET_METADATA)?);
+            let lamports = solana.lamports(&Money::new(request.amount, request.currency.clone())).await?;
+            let initialize = solana.initialize_instruction(escrow_hold.id, buyer_wallet, seller_wallet, lamports, escrow_hold.expires_at)?;
+            funding_transaction = Some(solana.funding_transaction(buyer_wallet, initialize).await?);
+            escrow_hold.on_chain = Some(OnChainEscrow {
+                address: solana.escrow_address(escrow_hold.id)?,
+                buyer_wallet,
+                seller_wallet,
+                lamports,
+                funded: false,
+                settlement_signature: None,
+            });
+        }
+
+        self.database.create_escrow_hold(&escrow_hold).await?;
+        tracing::info!("Created escrow hold: {}", escrow_hold.id);
+
+        Ok(PaymentResult {
+            success: true,
+            payment_id: format!("escrow_{}", escrow_hold.id),
+            transaction_id: request.transaction_id,
+            amount: request.amount,
+            currency: request.currency.clone(),
+            status: if funding_transaction.is_some() { PaymentStatus::AwaitingConfirmation } else { PaymentStatus::Pending },
+            created_at: Utc::now(),
+            completed_at: None,
+            error_message: None,
+            invoice: None,
+            funding_transaction,
+            fees: None,
+            tax: None,
+        })
+    }
+
+    /// An on-chain escrow payment is held once its account is funded.
+    async fn check_escrow_funding(&self, payment: &PaymentRecord) -> Result<Option<PaymentStatus>> {
+        let (Some(solana), Some(escrow_id)) = (&self.solana_escrow, escrow_id(&payment.payment_id)) else {
+            return Ok(None);
+        };
+        let mut escrow_hold = self.get_escrow(escrow_id).await?;
+        let Some(on_chain) = escrow_hold.on_chain.as_mut() else {
+            return Ok(None);
+        };
+        if !solana.is_funded(on_chain).await? {
+            return Ok(None);
+        }
+        on_chain.funded = true;
+        self.database.update_escrow_hold(&escrow_hold).await?;
+        tracing::info!("Escrow hold {} funded on chain", escrow_id);
+        Ok(Some(PaymentStatus::Pending))
+    }
+
+    /// Pays an on-chain escrow out, to the seller net of the platform's fee
+    /// when it's `Released` and otherwise back to the buyer, recording the
+    /// confirmed transaction. Holds kept off chain need nothing.
+    async fn settle_on_chain(&self, escrow_hold: &mut EscrowHold, outcome: EscrowStatus) -> Result<()> {
+        let Some(on_chain) = escrow_hold.on_chain.as_mut() else {
+            return Ok(());
+        };
+        if !on_chain.funded {
+            return Err(NegotiationError::Payment(format!("Escrow hold {} hasn't been funded", escrow_hold.id)));
+        }
+        let solana = self.solana_escrow.as_ref()
+            .ok_or_else(|| NegotiationError::Payment("No Solana escrow program is configured".to_string()))?;
+        let instruction = match outcome {
+            EscrowStatus::Released => {
+                let fees = self.fee_schedule.assess(
+                    &PaymentMethod::Escrow, &Money::new(escrow_hold.amount, escrow_hold.currency.clone()),
+                );
+                solana.release_instruction(on_chain, on_chain.fee_lamports(&fees))
+            }
+            _ => solana.refund_instruction(on_chain),
+        };
+        on_chain.settlement_signature = Some(solana.send_and_confirm(instruction).await?);
+        Ok(())
+    }
+
+    /// Gives buyers back the funds of holds that expired before the seller
+    /// shipped. Returns the holds expired.
+    pub async fn expire_escrows(&self) -> Result<Vec<EscrowHold>> {
+        let mut expired = Vec::new();
+        for mut escrow_hold in self.database.get_expired_escrow_holds(Utc::now()).await? {
+            // An on-chain hold the buyer never funded has nothing to give back
+            let funded = escrow_hold.on_chain.as_ref().is_none_or(|on_chain| on_chain.funded);
+            if funded {
+                self.settle_on_chain(&mut escrow_hold, EscrowStatus::Expired).await?;
+            }
+            escrow_hold.status = EscrowStatus::Expired;
+            self.database.update_escrow_hold(&escrow_hold).await?;
+            if let Some(mut payment) = self.database.get_payment(&format!("escrow_{}", escrow_hold.id)).await? {
+                self.apply_transition(&mut payment, if funded { PaymentStatus::Refunded } else { PaymentStatus::Failed }).await?;
+            }
+            tracing::info!("Escrow hold {} expired before shipment", escrow_hold.id);
+            expired.push(escrow_hold);
+        }
+        Ok(expired)
+    }
+
+    /// Gives the buyer back an active hold's funds whatever the delivery
+    /// stands at, for operators settling a dispute.
+    pub async fn refund_escrow(&self, escrow_id: uuid::Uuid) -> Result<EscrowHold> {
+        let mut escrow_hold = self.get_escrow(escrow_id).await?;
+        if escrow_hold.status != EscrowStatus::Active {
+            return Err(NegotiationError::Payment("Escrow hold is not active".to_string()));
+        }
+
+        self.settle_on_chain(&mut escrow_hold, EscrowStatus::Refunded).await?;
+        escrow_hold.status = EscrowStatus::Refunded;
+        self.database.update_escrow_hold(&escrow_hold).await?;
+        if let Some(mut payment) = self.database.get_payment(&format!("escrow_{}", escrow_id)).await? {
+            self.apply_transition(&mut payment, PaymentStatus::Refunded).await?;
+            self.audit.record(AuditAction::Payment, None, &payment.payment_id, &payment).await;
+        }
+        tracing::info!("Refunded escrow hold {} to buyer {}", escrow_id, escrow_hold.buyer_id);
+        Ok(escrow_hold)
+    }
+
+    pub async fn get_escrow(&self, escrow_id: uuid::Uuid) -> Result<EscrowHold> {
+        self.database.get_escrow_hold(escrow_id).await?
+            .ok_or_else(|| NegotiationError::NotFound(format!("Escrow hold {}", escrow_id)))
+    }
+
+    /// Seller posts proof of shipment, starting the buyer's confirmation window.
+    pub async fn submit_shipment_proof(
+        &self,
+        escrow_id: uuid::Uuid,
+        seller_id: AgentId,
+        proof: ShipmentProof,
+    ) -> Result<EscrowHold> {
+        let mut escrow_hold = self.get_escrow(escrow_id).await?;
+        if escrow_hold.seller_id != seller_id {
+            return Err(NegotiationError::Auth("Only the seller can post shipment proof".to_string()));
+        }
+
+        escrow_hold.mark_shipped(proof, self.delivery_confirmation_timeout())?;
+        self.database.update_escrow_hold(&escrow_hold).await?;
+        self.sync_escrow_order(&escrow_hold).await?;
+        tracing::info!("Shipment recorded for escrow hold: {}", escrow_id);
+
+        Ok(escrow_hold)
+    }
+
+    /// Buyer confirms receipt of the goods.
+    pub async fn confirm_delivery(&self, escrow_id: uuid::Uuid, buyer_id: AgentId) -> Result<EscrowHold> {
+        let mut escrow_hold = self.get_escrow(escrow_id).await?;
+        if escrow_hold.buyer_id != buyer_id {
+            return Err(NegotiationError::Auth("Only the buyer can confirm delivery".to_string()));
+        }
+
+        escrow_hold.confirm_delivery()?;
+        self.database.update_escrow_hold(&escrow_hold).await?;
+        self.sync_escrow_order(&escrow_hold).await?;
+        tracing::info!("Delivery confirmed for escrow hold: {}", escrow_id);
+
+        Ok(escrow_hold)
+    }
+
+    /// Auto-confirms every shipped escrow whose confirmation window has lapsed.
+    pub async fn auto_confirm_deliveries(&self) -> Result<Vec<EscrowHold>> {
+        let mut confirmed = Vec::new();
+        for mut escrow_hold in self.database.get_escrow_holds_awaiting_confirmation(Utc::now()).await? {
+            if escrow_hold.auto_confirm_if_due() {
+                self.database.update_escrow_hold(&escrow_hold).await?;
+                self.sync_escrow_order(&escrow_hold).await?;
+                tracing::info!("Delivery auto-confirmed for escrow hold: {}", escrow_hold.id);
+                confirmed.push(escrow_hold);
+            }
+        }
+        Ok(confirmed)
+    }
+
+    /// Brings the order paid into an escrow in line with the escrow's
+    /// delivery, for shipments and confirmations made on the escrow itself.
+    async fn sync_escrow_order(&self, escrow_hold: &EscrowHold) -> Result<()> {
+        let Some(mut order) = self.orders.for_payment(&format!("escrow_{}", escrow_hold.id)).await? else {
+            return Ok(());
+        };
+        let mut changed = false;
+        if let (FulfillmentStatus::Processing, Some(proof)) = (&order.status, &escrow_hold.delivery.shipment_proof) {
+            order.ship(proof.clone())?;
+            changed = true;
+        }
+        if escrow_hold.delivery.is_confirmed() && order.status == FulfillmentStatus::Shipped {
+            order.deliver()?;
+            changed = true;
+        }
+        if changed {
+            self.orders.update(&order).await?;
+        }
+        Ok(())
+    }
+
+    /// Confirms delivery of every pay-on-delivery order shipped longer ago
+    /// than the buyer's confirmation window, collecting its payment, as
+    /// escrow holds are auto-confirmed.
+    pub async fn auto_confirm_payment_deliveries(&self) -> Result<Vec<Order>> {
+        let mut delivered = Vec::new();
+        for mut order in self.orders.uncollected_shipped_before(Utc::now() - self.delivery_confirmation_timeout()).await? {
+            order.deliver()?;
+            self.confirm_payment_delivery(&order.payment_id, order.buyer_id).await?;
+            self.orders.update(&order).await?;
+            tracing::info!("Delivery auto-confirmed for order {}", order.id);
+            delivered.push(order);
+        }
+        Ok(delivered)
+    }
+
+    /// Seller ships an order, or corrects its tracking. Shipping an order
+    /// paid into escrow, or on delivery, starts the buyer's window to confirm
+    /// delivery.
+    pub async fn ship_order(&self, order_id: uuid::Uuid, seller_id: AgentId, shipment: ShipmentProof) -> Result<Order> {
+        let mut order = self.orders.get(order_id).await?;
+        if order.seller_id != seller_id {
+            return Err(NegotiationError::Auth("Only the seller can ship an order".to_string()));
+        }
+        order.ship(shipment.clone())?;
+
+        if let Some(escrow_id) = escrow_id(&order.payment_id) {
+            let mut escrow_hold = self.get_escrow(escrow_id).await?;
+            if escrow_hold.delivery.status == DeliveryStatus::AwaitingShipment {
+                escrow_hold.mark_shipped(shipment, self.delivery_confirmation_timeout())?;
+            } else {
+                escrow_hold.delivery.shipment_proof = Some(shipment);
+            }
+            self.database.update_escrow_hold(&escrow_hold).await?;
+        }
+        self.orders.update(&order).await?;
+        tracing::info!("Order {} shipped", order_id);
+        Ok(order)
+    }
+
+    /// Buyer confirms an order arrived. Its escrow hold is released to the
+    /// seller, or its pay-on-delivery payment collected.
+    pub async fn confirm_order_delivery(&self, order_id: uuid::Uuid, buyer_id: AgentId) -> Result<Order> {
+        let mut order = self.orders.get(order_id).await?;
+        if order.buyer_id != buyer_id {
+            return Err(NegotiationError::Auth("Only the buyer can confirm delivery".to_string()));
+        }
+        order.deliver()?;
+
+        match escrow_id(&order.payment_id) {
+            Some(escrow_id) => {
+                if self.get_escrow(escrow_id).await?.delivery.status == DeliveryStatus::Shipped {
+                    self.confirm_delivery(escrow_id, buyer_id).await?;
+                }
+                self.orders.update(&order).await?;
+                self.release_escrow(escrow_id).await?;
+            }
+            None => {
+                let payment = self.get_payment(&order.payment_id).await?;
+                if payment.payment_method == PaymentMethod::PayOnDelivery && payment.status == PaymentStatus::Created {
+                    self.confirm_payment_delivery(&payment.payment_id, buyer_id).await?;
+                }
+                self.orders.update(&order).await?;
+            }
+        }
+        tracing::info!("Order {} delivered", order_id);
+        Ok(order)
+    }
+
     pub async fn release_escrow(&self, escrow_id: uuid::Uuid) -> Result<PaymentResult> {
+        let mut escrow_hold = self.get_escrow(escrow_id).await?;
+        if escrow_hold.status != EscrowStatus::Active {
+            return Err(NegotiationError::Payment("Escrow hold is not active".to_string()));
+        }
+
+        escrow_hold.auto_confirm_if_due();
+        if !escrow_hold.delivery.is_confirmed() {
+            return Err(NegotiationError::Payment("Delivery has not been confirmed".to_string()));
+        }
+
         // Release funds from escrow to seller
         tracing::info!("Releasing escrow hold: {}", escrow_id);
+        self.settle_on_chain(&mut escrow_hold, EscrowStatus::Released).await?;
+        escrow_hold.status = EscrowStatus::Released;
+        self.database.update_escrow_hold(&escrow_hold).await?;
 
-        Ok(PaymentResult {
+        let fees = match self.database.get_payment(&format!("escrow_{}", escrow_id)).await? {
+            Some(mut payment) => {
+                payment.transition(PaymentStatus::Succeeded)?;
+                self.database.update_payment(&payment).await?;
+                self.collect_fee(&payment).await?.breakdown
+            }
+            None => self.fee_schedule.assess(
+                &PaymentMethod::Escrow, &Money::new(escrow_hold.amount, escrow_hold.currency.clone()),
+            ),
+        };
+
+        let mut released = PaymentResult {
             success: true,
             payment_id: format!("escrow_release_{}", escrow_id),
-            transaction_id: uuid::Uuid::new_v4(),
-            amount: 0.0, // Would get from database
-            currency: "USD".to_string(),
+            transaction_id: escrow_hold.transaction_id,
+            amount: escrow_hold.amount,
+            currency: escrow_hold.currency,
             status: PaymentStatus::Succeeded,
             created_at: Utc::now(),
             completed_at: Some(Utc::now()),
             error_message: None,
-        })
+            invoice: None,
+            funding_transaction: None,
+            fees: None,
+            tax: None,
+        };
+        released.fees = Some(fees);
+        self.publish_payment_succeeded(&released);
+        Ok(released)
     }
 
-    pub async fn refund_payment(&self, payment_id: &str) -> Result<PaymentResult> {
-        // This would handle refunds for different payment methods
-        tracing::info!("Processing refund for payment: {}", payment_id);
+    /// The fee collected from a payment, or the one it will be charged when
+    /// it settles.
+    pub async fn payment_fees(&self, payment: &PaymentRecord) -> Result<FeeBreakdown> {
+        match self.database.get_platform_fee(&payment.payment_id).await? {
+            Some(fee) => Ok(fee.breakdown),
+            None => Ok(self.fee_schedule.assess(&payment.payment_method, &Money::new(payment.amount, payment.currency.clone()))),
+        }
+    }
 
-        Ok(PaymentResult {
-            success: true,
-            payment_id: payment_id.to_string(),
-            transaction_id: uuid::Uuid::new_v4(),
-            amount: 0.0,
-            currency: "USD".to_string(),
-            status: PaymentStatus::Refunded,
-            created_at: Utc::now(),
-            completed_at: Some(Utc::now()),
+    /// Fees collected from a seller's payments, oldest first
+    pub async fn get_seller_fees(&self, seller_id: AgentId) -> Result<Vec<PlatformFee>> {
+        self.database.get_seller_platform_fees(seller_id).await
+    }
+
+    /// Takes the platform's fee from a payment that settled, entering it in
+    /// the fee ledger. Fees are only collected once per payment.
+    async fn collect_fee(&self, payment: &PaymentRecord) -> Result<PlatformFee> {
+        if let Some(fee) = self.database.get_platform_fee(&payment.payment_id).await? {
+            return Ok(fee);
+        }
+        let fee = PlatformFee {
+            payment_id: payment.payment_id.clone(),
+            transaction_id: payment.transaction_id,
+            seller_id: payment.seller_id,
+            payment_method: payment.payment_method.clone(),
+            currency: payment.currency.clone(),
+            breakdown: self.fee_schedule.assess(&payment.payment_method, &Money::new(payment.amount, payment.currency.clone())),
+            collected_at: Utc::now(),
+        };
+        if self.database.record_platform_fee(&fee).await? {
+            tracing::info!(
+                "Collected {} fee from payment {}", Money::new(fee.breakdown.fee, fee.currency.clone()), fee.payment_id
+            );
+            return Ok(fee);
+        }
+        self.database.get_platform_fee(&payment.payment_id).await?
+            .ok_or_else(|| NegotiationError::Payment(format!("Failed to record fee for payment {}", payment.payment_id)))
+    }
+
+    /// Gives back the share of a payment's collected fee that `refund`
+    /// refunds, entering it in the fee ledger against the refund. Payments
+    /// no fee was collected from have none to give back.
+    async fn reverse_fee(&self, payment: &PaymentRecord, refund: &PaymentRecord, previously_refunded: Decimal) -> Result<()> {
+        let Some(collected) = self.database.get_platform_fee(&payment.payment_id).await? else {
+            return Ok(());
+        };
+        let reversal = PlatformFee {
+            payment_id: refund.payment_id.clone(),
+            breakdown: collected.breakdown.reversal(&Money::new(refund.amount, payment.currency.clone()), previously_refunded),
+            collected_at: Utc::now(),
+            ..collected
+        };
+        if self.database.record_platform_fee(&reversal).await? {
+            tracing::info!(
+                "Gave back {} fee on refund {}", Money::new(-reversal.breakdown.fee, reversal.currency.clone()), reversal.payment_id
+            );
+        }
+        Ok(())
+    }
+
+    fn publish_payment_succeeded(&self, payment: &PaymentResult) {
+        self.events.publish(EventKind::PaymentSucceeded {
+            payment_id: payment.payment_id.clone(),
+            transaction_id: payment.transaction_id,
+            amount: Money::new(payment.amount, payment.currency.clone()),
+        });
+    }
+
+    /// The payment a buyer made for an accepted negotiation
+    pub async fn get_negotiation_payment(&self, negotiation_id: TransactionId) -> Result<PaymentRecord> {
+        self.database.get_payment_by_idempotency_key(&format!("negotiation-{}", negotiation_id)).await?
+            .ok_or_else(|| NegotiationError::NotFound(format!("Payment for negotiation {}", negotiation_id)))
+    }
+
+    /// Asks for `request.amount` of a payment back, or all that hasn't been
+    /// refunded yet. A refund the seller gives, and only the seller may
+    /// give one out of goodwill, goes through straight away; one the buyer
+    /// asks for waits `Pending` until the seller or an operator approves it
+    /// with [`Self::approve_refund`]. Refunds the seller is at fault for
+    /// cost them reputation once given.
+    pub async fn refund_payment(&self, payment_id: &str, requested_by: AgentId, request: RefundRequest) -> Result<Refund> {
+        let payment = self.get_payment(payment_id).await?;
+        let party = if requested_by == payment.buyer_id {
+            Party::Buyer
+        } else if requested_by == payment.seller_id {
+            Party::Seller
+        } else {
+            return Err(NegotiationError::Auth("Not a party to this payment".to_string()));
+        };
+        if !request.reason.may_be_raised_by(party) {
+            return Err(NegotiationError::Validation(format!("{:?} refunds can't be given by the {:?}", request.reason, party)));
+        }
+        if payment.refund_of.is_some() {
+            return Err(NegotiationError::Validation("Refunds can't be refunded".to_string()));
+        }
+        if !matches!(payment.status, PaymentStatus::Succeeded | PaymentStatus::PartiallyRefunded | PaymentStatus::Pending) {
+            return Err(NegotiationError::Payment(format!("Payment {} is {:?} and can't be refunded", payment_id, payment.status)));
+        }
+
+        let refunded: Decimal = self.get_refunds(payment_id).await?.iter()
+            .filter(|refund| refund.status != PaymentStatus::Cancelled)
+            .map(|refund| refund.amount)
+            .sum();
+        let remaining = payment.amount - refunded;
+        let amount = request.amount.unwrap_or(remaining);
+        if amount <= Decimal::ZERO {
+            return Err(NegotiationError::Validation("Refund amount must be greater than 0".to_string()));
+        }
+        if payment.status == PaymentStatus::Pending && amount != payment.amount {
+            return Err(NegotiationError::Validation("Escrow holds can only be refunded in full".to_string()));
+        }
+
+        let now = Utc::now();
+        let refund = PaymentRecord {
+            payment_id: format!("refund_{}", uuid::Uuid::new_v4()),
+            transaction_id: payment.transaction_id,
+            buyer_id: payment.buyer_id,
+            seller_id: payment.seller_id,
+            payment_method: payment.payment_method.clone(),
+            amount,
+            currency: payment.currency.clone(),
+            status: PaymentStatus::Pending,
+            idempotency_key: None,
             error_message: None,
-        })
+            created_at: now,
+            updated_at: now,
+            completed_at: None,
+            delegation_chain: vec![],
+            refund_of: Some(payment.payment_id.clone()),
+            refund_reason: Some(request.reason),
+            tax: None,
+        };
+        // What's left is checked again as the refund is recorded, so
+        // refunds asked for at once can't add up to more than was paid
+        if !self.database.create_refund(&refund, payment.amount).await? {
+            return Err(NegotiationError::Validation(format!(
+                "Refund of {} exceeds what's left to refund", Money::new(amount, payment.currency.clone())
+            )));
+        }
+        self.audit.record(AuditAction::Payment, Some(requested_by), &refund.payment_id, &refund).await;
+
+        match party {
+            Party::Seller => self.give_refund(refund, Some(requested_by)).await,
+            Party::Buyer => {
+                tracing::info!("Refund {} of payment {} awaits approval ({:?})", refund.payment_id, payment_id, request.reason);
+                Ok(Refund { refund, payment, reputation_penalty: 0 })
+            }
+        }
     }
 
-    pub async fn get_payment_status(&self, payment_id: &str) -> Result<PaymentStatus> {
-        // This would query the payment status from the respective payment processor
-        tracing::info!("Checking payment status for: {}", payment_id);
+    /// Gives a refund the buyer asked for, on the say-so of the seller or of
+    /// an operator (`None`).
+    pub async fn approve_refund(&self, refund_id: &str, approved_by: Option<AgentId>) -> Result<Refund> {
+        let refund = self.pending_refund(refund_id).await?;
+        self.give_refund(refund, approved_by).await
+    }
+
+    /// Turns down a refund the buyer asked for, freeing what it held of the
+    /// payment.
+    pub async fn decline_refund(&self, refund_id: &str, declined_by: Option<AgentId>) -> Result<PaymentRecord> {
+        let mut refund = self.pending_refund(refund_id).await?;
+        refund.transition(PaymentStatus::Cancelled)?;
+        if !self.database.update_payment_from(&refund, &PaymentStatus::Pending).await? {
+            return Err(NegotiationError::Payment(format!("Refund {} has already been decided", refund_id)));
+        }
+        self.audit.record(AuditAction::Payment, declined_by, &refund.payment_id, &refund).await;
+        tracing::info!("Declined refund {}", refund_id);
+        Ok(refund)
+    }
+
+    async fn pending_refund(&self, refund_id: &str) -> Result<PaymentRecord> {
+        let refund = self.get_payment(refund_id).await?;
+        if refund.refund_of.is_none() {
+            return Err(NegotiationError::NotFound(format!("Refund {}", refund_id)));
+        }
+        if refund.status != PaymentStatus::Pending {
+            return Err(NegotiationError::Payment(format!("Refund {} is {:?}", refund_id, refund.status)));
+        }
+        Ok(refund)
+    }
+
+    /// Pays a pending refund out. Escrow holds give back what they hold;
+    /// other payments are refunded through a mock of their provider.
+    async fn give_refund(&self, mut refund: PaymentRecord, given_by: Option<AgentId>) -> Result<Refund> {
+        let payment_id = refund.refund_of.clone()
+            .ok_or_else(|| NegotiationError::Validation("Not a refund".to_string()))?;
+        let mut payment = self.get_payment(&payment_id).await?;
+
+        // Claiming the refund first means it's only paid out once
+        let pending = refund.clone();
+        refund.transition(PaymentStatus::Succeeded)?;
+        if !self.database.update_payment_from(&refund, &PaymentStatus::Pending).await? {
+            return Err(NegotiationError::Payment(format!("Refund {} has already been decided", refund.payment_id)));
+        }
+        let escrow_hold = match self.refund_escrow_hold(&payment).await {
+            Ok(escrow_hold) => escrow_hold,
+            Err(e) => {
+                self.database.update_payment_from(&pending, &PaymentStatus::Succeeded).await?;
+                return Err(e);
+            }
+        };
+        self.audit.record(AuditAction::Payment, given_by, &refund.payment_id, &refund).await;
+
+        let given: Decimal = self.get_refunds(&payment_id).await?.iter()
+            .filter(|refund| refund.status == PaymentStatus::Succeeded)
+            .map(|refund| refund.amount)
+            .sum();
+        payment.transition(if given >= payment.amount { PaymentStatus::Refunded } else { PaymentStatus::PartiallyRefunded })?;
+        self.database.update_payment(&payment).await?;
+        if let Some(escrow_hold) = &escrow_hold {
+            self.database.update_escrow_hold(escrow_hold).await?;
+        }
+        self.reverse_fee(&payment, &refund, given - refund.amount).await?;
+        tracing::info!("Refunded {} of payment {} ({:?})", Money::new(refund.amount, payment.currency.clone()), payment_id, refund.refund_reason);
+
+        let reputation_penalty = match refund.refund_reason {
+            Some(reason) if reason.penalises_seller() => self.refund_reputation_penalty,
+            _ => 0,
+        };
+        Ok(Refund { refund, payment, reputation_penalty })
+    }
+
+    /// Gives back the funds of the escrow hold behind a payment, if there is
+    /// one, as long as it's still active.
+    async fn refund_escrow_hold(&self, payment: &PaymentRecord) -> Result<Option<EscrowHold>> {
+        let Some(escrow_id) = escrow_id(&payment.payment_id) else {
+            return Ok(None);
+        };
+        let mut escrow_hold = self.get_escrow(escrow_id).await?;
+        if escrow_hold.status != EscrowStatus::Active {
+            return Err(NegotiationError::Payment("Escrow hold is not active".to_string()));
+        }
+        self.settle_on_chain(&mut escrow_hold, EscrowStatus::Refunded).await?;
+        escrow_hold.status = EscrowStatus::Refunded;
+        Ok(Some(escrow_hold))
+    }
+
+    /// Refunds given back from a payment, oldest first
+    pub async fn get_refunds(&self, payment_id: &str) -> Result<Vec<PaymentRecord>> {
+        self.database.get_refunds(payment_id).await
+    }
+
+    /// Moves a payment on and records it, announcing it once it succeeds.
+    async fn apply_transition(&self, payment: &mut PaymentRecord, status: PaymentStatus) -> Result<()> {
+        if payment.status == status {
+            return Ok(());
+        }
+        payment.transition(status)?;
+        self.database.update_payment(payment).await?;
+        tracing::info!("Payment {} transitioned to {:?}", payment.payment_id, payment.status);
+        match payment.status {
+            PaymentStatus::Succeeded => {
+                let mut result = payment.to_result();
+                result.fees = Some(self.collect_fee(payment).await?.breakdown);
+                self.publish_payment_succeeded(&result);
+            }
+            PaymentStatus::Failed => self.metrics.payment_failed(&payment.payment_method),
+            _ => {}
+        }
+        Ok(())
+    }
 
-        // Mock implementation
-        Ok(PaymentStatus::Succeeded)
+    /// Asks the payment's provider how it went; `None` while still outstanding.
+    async fn check_confirmation(&self, payment: &PaymentRecord) -> Result<Option<PaymentStatus>> {
+        match payment.payment_method {
+            // Mock providers confirm on the first check. Live ones would
+            // retrieve the PaymentIntent or the transaction's finality here.
+            PaymentMethod::Stripe | PaymentMethod::Solana | PaymentMethod::PayOnDelivery => Ok(Some(PaymentStatus::Succeeded)),
+            #[cfg(feature = "lightning")]
+            PaymentMethod::Lightning => {
+                let invoice = self.lightning_client()?.lookup_invoice(lightning::r_hash(&payment.payment_id)?).await?;
+                Ok(invoice.state.payment_status())
+            }
+            #[cfg(not(feature = "lightning"))]
+            PaymentMethod::Lightning => Ok(None),
+            // Escrow holds complete on release instead
+            PaymentMethod::Escrow => self.check_escrow_funding(payment).await,
+        }
+    }
+
+    /// Checks a payment awaiting confirmation with its provider, returning it
+    /// as it now stands.
+    pub async fn poll_payment(&self, payment_id: &str) -> Result<PaymentRecord> {
+        let mut payment = self.get_payment(payment_id).await?;
+        if payment.status.awaits_confirmation() {
+            if let Some(status) = self.check_confirmation(&payment).await? {
+                self.apply_transition(&mut payment, status).await?;
+            }
+        }
+        Ok(payment)
+    }
+
+    /// Checks every payment awaiting confirmation with its provider, for
+    /// those whose webhooks never arrived. Returns the payments that moved on.
+    pub async fn poll_pending(&self) -> Result<Vec<PaymentRecord>> {
+        let mut confirmed = Vec::new();
+        for mut payment in self.database.get_payments_awaiting_confirmation().await? {
+            if let Some(status) = self.check_confirmation(&payment).await? {
+                self.apply_transition(&mut payment, status).await?;
+                confirmed.push(payment);
+            }
+        }
+        Ok(confirmed)
+    }
+
+    pub async fn get_payment_status(&self, payment_id: &str) -> Result<PaymentStatus> {
+        Ok(self.get_payment(payment_id).await?.status)
     }
 
-    pub async fn create_payment_intent(&self, amount: f64, currency: &str) -> Result<String> {
+    pub async fn create_payment_intent(&self, amount: &Money) -> Result<String> {
         // Mock payment intent creation
+        tracing::info!("Creating payment intent for {}", amount);
         Ok(format!("pi_mock_{}", uuid::Uuid::new_v4()))
     }
 
-    pub async fn handle_webhook(&self, payload: &str, signature: &str) -> Result<()> {
-        // This would handle webhooks from payment processors
-        tracing::info!("Processing webhook with signature: {}", signature);
+    pub async fn handle_webhook(&self, payload: &str, signature: &str) -> Result<StripeWebhookEvent> {
+        let secret = self.config.webhook_secret.as_deref()
+            .ok_or_else(|| NegotiationError::Config("Webhook secret not configured".to_string()))?;
+        verify_stripe_signature(payload, signature, secret, Utc::now())?;
 
-        // Validate webhook signature
-        if !self.validate_webhook_signature(payload, signature).await? {
-            return Err(NegotiationError::Payment("Invalid webhook signature".to_string()));
+        let event: StripeWebhookEvent = serde_json::from_str(payload)?;
+        tracing::info!("Processing webhook event {} ({})", event.id, event.event_type);
+
+        // The event is authentic from here on, so failures are worth replaying
+        if let Err(e) = self.apply_webhook_event(&event).await {
+            self.dead_letters.record(DeadLetterKind::Webhook, &event, &e.to_string()).await;
+            return Err(e);
         }
 
-        // Process webhook event
-        tracing::debug!("Webhook payload: {}", payload);
+        Ok(event)
+    }
 
+    async fn apply_webhook_event(&self, event: &StripeWebhookEvent) -> Result<()> {
+        match (event.payment_id(), event.payment_status()) {
+            (Some(payment_id), Some(status)) => {
+                let mut payment = self.get_payment(payment_id).await?;
+                if payment.status != status && !payment.status.can_transition_to(&status) {
+                    // Stripe doesn't guarantee events arrive in order
+                    tracing::info!("Ignoring {} for payment {}, already {:?}", event.event_type, payment_id, payment.status);
+                    return Ok(());
+                }
+                self.apply_transition(&mut payment, status).await?;
+            }
+            _ => tracing::debug!("Ignoring webhook event type: {}", event.event_type),
+        }
         Ok(())
     }
 
-    async fn validate_webhook_signature(&self, _payload: &str, _signature: &str) -> Result<bool> {
-        // This would validate webhook signatures using Stripe's webhook signing
-        // For now, return true for testing
-        Ok(true)
+    /// Re-runs a dead-lettered workflow. Reputation updates need the caller's
+    /// trust system since reputation is held by the agents. Payments go back
+    /// through the idempotency check, keyed by the dead letter when they had
+    /// no key, so one whose first attempt went through isn't charged again.
+    pub async fn replay_dead_letter(
+        &self,
+        dead_letter_id: uuid::Uuid,
+        trust: Option<&mut TrustSystem>,
+    ) -> Result<DeadLetter> {
+        let mut dead_letter = self.dead_letters.get(dead_letter_id).await?
+            .ok_or_else(|| NegotiationError::NotFound(format!("Dead letter {}", dead_letter_id)))?;
+        if dead_letter.status != DeadLetterStatus::Pending {
+            return Err(NegotiationError::Validation(format!("Dead letter {} is not pending", dead_letter_id)));
+        }
+
+        let outcome = match dead_letter.kind {
+            DeadLetterKind::Settlement => {
+                let mut request: PaymentRequest = serde_json::from_value(dead_letter.payload.clone())?;
+                if request.idempotency_key.is_none() {
+                    request.idempotency_key = Some(format!("dead-letter-{}", dead_letter.id));
+                }
+                self.settle_payment(request, false).await.map(|_| ())
+            }
+            DeadLetterKind::Webhook => {
+                let event: StripeWebhookEvent = serde_json::from_value(dead_letter.payload.clone())?;
+                self.apply_webhook_event(&event).await
+            }
+            DeadLetterKind::ReputationUpdate => {
+                let update: ReputationUpdatePayload = serde_json::from_value(dead_letter.payload.clone())?;
+                match trust {
+                    Some(trust) => {
+                        trust.record_reputation_change(update.agent_id, update.score_change, update.deal_value).await?;
+                        self.audit.record(AuditAction::ReputationChange, None, &update.agent_id.to_string(), &update).await;
+                        Ok(())
+                    }
+                    None => Err(NegotiationError::Trust(
+                        "Reputation updates must be replayed by an agent holding a trust system".to_string(),
+                    )),
+                }
+            }
+        };
+
+        match outcome {
+            Ok(()) => {
+                self.dead_letters.mark_replayed(&mut dead_letter).await?;
+                tracing::info!("Replayed dead letter {}", dead_letter.id);
+                Ok(dead_letter)
+            }
+            Err(e) => {
+                self.dead_letters.mark_failed(&mut dead_letter, &e.to_string()).await?;
+                Err(e)
+            }
+        }
     }
 
     fn map_payment_status(&self, success: bool) -> PaymentStatus {
@@ -267,6 +1815,8 @@ impl SettlementService {
             PaymentMethod::Stripe,
             PaymentMethod::Solana,
             PaymentMethod::Escrow,
+            PaymentMethod::PayOnDelivery,
+            PaymentMethod::Lightning,
         ])
     }
 
@@ -274,7 +1824,494 @@ impl SettlementService {
         match method {
             PaymentMethod::Stripe => Ok(self.config.stripe_secret_key.is_some()),
             PaymentMethod::Solana => Ok(self.config.solana_rpc_url.is_some()),
-            PaymentMethod::Escrow => Ok(self.config.escrow_service_url.is_some()),
+            PaymentMethod::Escrow => Ok(self.config.escrow_service_url.is_some() || self.solana_escrow.is_some()),
+            // Collected through the same card processor
+            PaymentMethod::PayOnDelivery => Ok(self.config.stripe_secret_key.is_some()),
+            #[cfg(feature = "lightning")]
+            PaymentMethod::Lightning => Ok(self.lightning.is_some()),
+            #[cfg(not(feature = "lightning"))]
+            PaymentMethod::Lightning => Ok(false),
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::{FeeRule, JurisdictionTaxConfig, TaxConfig},
+        model::RFQ,
+        tax::FlatRateTaxCalculator,
+    };
+    use tempfile::NamedTempFile;
+
+    async fn test_service() -> (SettlementService, NamedTempFile) {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db_url = format!("sqlite://{}", temp_file.path().to_string_lossy());
+        let database = Database::new(&db_url).await.unwrap();
+        let config = SettlementConfig {
+            stripe_secret_key: None,
+            solana_rpc_url: None,
+            escrow_service_url: None,
+            webhook_secret: None,
+            delivery_confirmation_timeout_seconds: None,
+        };
+        (SettlementService::new(config, database).await.unwrap(), temp_file)
+    }
+
+    fn escrow_request(buyer_id: AgentId, seller_id: AgentId) -> PaymentRequest {
+        PaymentRequest {
+            transaction_id: uuid::Uuid::new_v4(),
+            buyer_id,
+            seller_id,
+            amount: Decimal::from(250),
+            currency: "USD".to_string(),
+            payment_method: PaymentMethod::Escrow,
+            description: "Escrow test".to_string(),
+            metadata: HashMap::new(),
+            idempotency_key: None,
+            delegation_chain: vec![],
+        }
+    }
+
+    fn shipment_proof() -> ShipmentProof {
+        ShipmentProof {
+            carrier: "UPS".to_string(),
+            tracking_number: "1Z999".to_string(),
+            proof_url: None,
+            notes: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_escrow_release_requires_delivery_confirmation() {
+        let (settlement, _db_file) = test_service().await;
+        let buyer_id = uuid::Uuid::new_v4();
+        let seller_id = uuid::Uuid::new_v4();
+
+        let payment = settlement.process_payment(escrow_request(buyer_id, seller_id)).await.unwrap();
+        let escrow_id = uuid::Uuid::parse_str(payment.payment_id.trim_start_matches("escrow_")).unwrap();
+
+        assert!(settlement.release_escrow(escrow_id).await.is_err());
+        assert!(settlement.submit_shipment_proof(escrow_id, buyer_id, shipment_proof()).await.is_err());
+
+        let shipped = settlement.submit_shipment_proof(escrow_id, seller_id, shipment_proof()).await.unwrap();
+        assert_eq!(shipped.delivery.status, DeliveryStatus::Shipped);
+        assert!(settlement.release_escrow(escrow_id).await.is_err());
+
+        let confirmed = settlement.confirm_delivery(escrow_id, buyer_id).await.unwrap();
+        assert_eq!(confirmed.delivery.status, DeliveryStatus::Confirmed);
+
+        let released = settlement.release_escrow(escrow_id).await.unwrap();
+        assert_eq!(released.amount, Decimal::from(250));
+        assert_eq!(settlement.get_escrow(escrow_id).await.unwrap().status, EscrowStatus::Released);
+    }
+
+    #[tokio::test]
+    async fn test_order_fulfillment_releases_escrow() {
+        let (settlement, _db_file) = test_service().await;
+        let buyer_id = uuid::Uuid::new_v4();
+        let seller_id = uuid::Uuid::new_v4();
+        let rfq = RFQ::new(buyer_id, "laptop-001".to_string(), 2, Decimal::from(500), "USD".to_string(), Utc::now() + Duration::hours(1));
+        let negotiation = Negotiation::new(rfq, seller_id);
+
+        let payment = settlement.process_payment(escrow_request(buyer_id, seller_id)).await.unwrap();
+        let escrow_id = escrow_id(&payment.payment_id).unwrap();
+        let order = settlement.orders().open(&negotiation, &payment.payment_id).await.unwrap();
+        assert_eq!(settlement.orders().open(&negotiation, &payment.payment_id).await.unwrap().id, order.id);
+        assert_eq!((order.status, order.quantity), (FulfillmentStatus::Processing, 2));
+        assert!(settlement.confirm_order_delivery(order.id, buyer_id).await.is_err());
+
+        assert!(settlement.ship_order(order.id, buyer_id, shipment_proof()).await.is_err());
+        let shipped = settlement.ship_order(order.id, seller_id, shipment_proof()).await.unwrap();
+        assert_eq!(shipped.status, FulfillmentStatus::Shipped);
+        assert_eq!(settlement.get_escrow(escrow_id).await.unwrap().delivery.status, DeliveryStatus::Shipped);
+
+        // Correcting the tracking number keeps the order shipped
+        let corrected = ShipmentProof { tracking_number: "1Z1000".to_string(), ..shipment_proof() };
+        settlement.ship_order(order.id, seller_id, corrected).await.unwrap();
+        let escrow_hold = settlement.get_escrow(escrow_id).await.unwrap();
+        assert_eq!(escrow_hold.delivery.shipment_proof.unwrap().tracking_number, "1Z1000");
+
+        assert!(settlement.confirm_order_delivery(order.id, seller_id).await.is_err());
+        let delivered = settlement.confirm_order_delivery(order.id, buyer_id).await.unwrap();
+        assert_eq!(delivered.status, FulfillmentStatus::Delivered);
+        assert_eq!(settlement.get_escrow(escrow_id).await.unwrap().status, EscrowStatus::Released);
+        assert_eq!(settlement.get_payment(&payment.payment_id).await.unwrap().status, PaymentStatus::Succeeded);
+
+        let orders = settlement.orders().for_buyer(buyer_id).await.unwrap();
+        assert_eq!(orders.len(), 1);
+        assert_eq!(orders[0].shipment.as_ref().unwrap().tracking_number, "1Z1000");
+        assert!(settlement.ship_order(order.id, seller_id, shipment_proof()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_idempotent_payment_processing() {
+        let (settlement, _db_file) = test_service().await;
+        let mut request = escrow_request(uuid::Uuid::new_v4(), uuid::Uuid::new_v4());
+        request.idempotency_key = Some("negotiation-1".to_string());
+        request.delegation_chain = vec![uuid::Uuid::new_v4(), request.buyer_id];
+
+        let first = settlement.process_payment(request.clone()).await.unwrap();
+        let retry = settlement.process_payment(request.clone()).await.unwrap();
+        assert_eq!(first.payment_id, retry.payment_id);
+        assert_eq!(settlement.get_payment_status(&first.payment_id).await.unwrap(), first.status);
+        assert_eq!(settlement.get_payment(&first.payment_id).await.unwrap().delegation_chain, request.delegation_chain);
+
+        request.amount = Decimal::from(500);
+        assert!(settlement.process_payment(request).await.is_err());
+        assert!(settlement.get_payment_status("stripe_missing").await.is_err());
+
+        // Concurrent retries make the payment once between them
+        let mut request = escrow_request(uuid::Uuid::new_v4(), uuid::Uuid::new_v4());
+        request.idempotency_key = Some("negotiation-2".to_string());
+        let retries = futures::future::join_all((0..4).map(|_| settlement.process_payment(request.clone()))).await;
+        let payment_ids: std::collections::HashSet<_> = retries.into_iter().map(|result| result.unwrap().payment_id).collect();
+        assert_eq!(payment_ids.len(), 1);
+        assert_eq!(settlement.database.get_payments_for_transaction(request.transaction_id).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_dead_letter_replay() {
+        let (settlement, _db_file) = test_service().await;
+        let agent_id = uuid::Uuid::new_v4();
+        let payload = ReputationUpdatePayload { agent_id, score_change: 5, deal_value: None };
+        settlement.dead_letters().record(DeadLetterKind::ReputationUpdate, &payload, "trust store offline").await;
+
+        let pending = settlement.dead_letters().list(Some(DeadLetterStatus::Pending), 10).await.unwrap();
+        assert_eq!(pending.len(), 1);
+        let dead_letter_id = pending[0].id;
+
+        assert!(settlement.replay_dead_letter(dead_letter_id, None).await.is_err());
+        let retried = settlement.dead_letters().get(dead_letter_id).await.unwrap().unwrap();
+        assert_eq!(retried.attempts, 2);
+        assert_eq!(retried.status, DeadLetterStatus::Pending);
+
+        let mut trust = TrustSystem::new().unwrap();
+        let replayed = settlement.replay_dead_letter(dead_letter_id, Some(&mut trust)).await.unwrap();
+        assert_eq!(replayed.status, DeadLetterStatus::Replayed);
+        assert_eq!(trust.get_reputation(agent_id).await.unwrap(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_replayed_payment_is_stored_once() {
+        let (settlement, _db_file) = test_service().await;
+
+        // The first attempt went through but was dead-lettered anyway
+        let mut request = escrow_request(uuid::Uuid::new_v4(), uuid::Uuid::new_v4());
+        request.idempotency_key = Some("negotiation-3".to_string());
+        let first = settlement.process_payment(request.clone()).await.unwrap();
+        settlement.dead_letters().record(DeadLetterKind::Settlement, &request, "provider timed out").await;
+        let dead_letter_id = settlement.dead_letters().list(Some(DeadLetterStatus::Pending), 10).await.unwrap()[0].id;
+        settlement.replay_dead_letter(dead_letter_id, None).await.unwrap();
+        let payments = settlement.database.get_payments_for_transaction(request.transaction_id).await.unwrap();
+        assert_eq!(payments.len(), 1);
+        assert_eq!(payments[0].payment_id, first.payment_id);
+
+        // A payment without a key is keyed by its dead letter
+        let request = escrow_request(uuid::Uuid::new_v4(), uuid::Uuid::new_v4());
+        settlement.dead_letters().record(DeadLetterKind::Settlement, &request, "provider down").await;
+        let dead_letter_id = settlement.dead_letters().list(Some(DeadLetterStatus::Pending), 10).await.unwrap()[0].id;
+        let replayed = settlement.replay_dead_letter(dead_letter_id, None).await.unwrap();
+        assert_eq!(replayed.status, DeadLetterStatus::Replayed);
+        let payments = settlement.database.get_payments_for_transaction(request.transaction_id).await.unwrap();
+        assert_eq!(payments.len(), 1);
+        assert!(settlement.dead_letters().list(Some(DeadLetterStatus::Pending), 10).await.unwrap().is_empty());
+    }
+
+    fn sign(payload: &str, secret: &str, timestamp: i64) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(format!("{}.{}", timestamp, payload).as_bytes());
+        format!("t={},v1={}", timestamp, hex::encode(mac.finalize().into_bytes()))
+    }
+
+    #[test]
+    fn test_stripe_signature_verification() {
+        let payload = r#"{"id":"evt_1","type":"payment_intent.succeeded","created":0,"data":{"object":{"id":"pi_1"}}}"#;
+        let now = Utc::now();
+        let header = sign(payload, "whsec_test", now.timestamp());
+
+        assert!(verify_stripe_signature(payload, &header, "whsec_test", now).is_ok());
+        assert!(verify_stripe_signature(payload, &header, "whsec_other", now).is_err());
+        assert!(verify_stripe_signature("{}", &header, "whsec_test", now).is_err());
+        assert!(verify_stripe_signature(payload, &header, "whsec_test", now + Duration::minutes(10)).is_err());
+        assert!(verify_stripe_signature(payload, "v1=deadbeef", "whsec_test", now).is_err());
+
+        let event: StripeWebhookEvent = serde_json::from_str(payload).unwrap();
+        assert_eq!(event.kind(), WebhookEventKind::PaymentSucceeded);
+        assert_eq!(event.payment_id(), Some("pi_1"));
+        assert_eq!(event.payment_status(), Some(PaymentStatus::Succeeded));
+    }
+
+    #[tokio::test]
+    async fn test_payments_succeed_only_once_confirmed() {
+        let (mut settlement, _db_file) = test_service().await;
+        settlement.config.webhook_secret = Some("whsec_test".to_string());
+        let mut receiver = settlement.events().subscribe();
+        let amount = Money::new(Decimal::from(40), "USD");
+
+        let by_webhook = settlement.create_payment(uuid::Uuid::new_v4(), uuid::Uuid::new_v4(), amount.clone(), None).await.unwrap();
+        assert_eq!(by_webhook.status, PaymentStatus::AwaitingConfirmation);
+        assert!(by_webhook.success && !by_webhook.is_confirmed());
+        assert!(receiver.try_recv().is_err());
+
+        let webhook = |event_type: &str| {
+            let payload = serde_json::json!({
+                "id": "evt_1", "type": event_type, "created": 0,
+                "data": {"object": {"id": "pi_1", "metadata": {"payment_id": by_webhook.payment_id}}},
+            }).to_string();
+            let signature = sign(&payload, "whsec_test", Utc::now().timestamp());
+            (payload, signature)
+        };
+        let (payload, signature) = webhook("payment_intent.succeeded");
+        settlement.handle_webhook(&payload, &signature).await.unwrap();
+        assert_eq!(settlement.get_payment_status(&by_webhook.payment_id).await.unwrap(), PaymentStatus::Succeeded);
+        assert!(matches!(receiver.try_recv().unwrap().kind, EventKind::PaymentSucceeded { .. }));
+
+        // A late event can't move a confirmed payment back
+        let (payload, signature) = webhook("payment_intent.processing");
+        settlement.handle_webhook(&payload, &signature).await.unwrap();
+        assert_eq!(settlement.get_payment_status(&by_webhook.payment_id).await.unwrap(), PaymentStatus::Succeeded);
+
+        let by_poll = settlement.create_payment(uuid::Uuid::new_v4(), uuid::Uuid::new_v4(), amount, None).await.unwrap();
+        let confirmed = settlement.poll_pending().await.unwrap();
+        assert_eq!(confirmed.iter().map(|payment| payment.payment_id.as_str()).collect::<Vec<_>>(), [by_poll.payment_id.as_str()]);
+        assert!(settlement.poll_pending().await.unwrap().is_empty());
+
+        let mut payment = settlement.get_payment(&by_poll.payment_id).await.unwrap();
+        assert!(payment.completed_at.is_some());
+        assert!(payment.transition(PaymentStatus::Failed).is_err());
+        assert!(payment.transition(PaymentStatus::Refunded).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_pay_on_delivery_charges_once_delivered() {
+        let (settlement, _db_file) = test_service().await;
+        let mut receiver = settlement.events().subscribe();
+        let (buyer_id, seller_id) = (uuid::Uuid::new_v4(), uuid::Uuid::new_v4());
+        let amount = Money::new(Decimal::from(75), "USD");
+
+        let deferred = settlement.create_delegated_payment(buyer_id, seller_id, amount, None, vec![], PaymentMethod::PayOnDelivery).await.unwrap();
+        assert_eq!(deferred.status, PaymentStatus::Created);
+        assert!(deferred.success && !deferred.is_confirmed() && deferred.status.is_outstanding());
+        // Nothing is collected before delivery
+        assert!(settlement.poll_pending().await.unwrap().is_empty());
+
+        assert!(settlement.confirm_payment_delivery(&deferred.payment_id, seller_id).await.is_err());
+        let collecting = settlement.confirm_payment_delivery(&deferred.payment_id, buyer_id).await.unwrap();
+        assert_eq!(collecting.status, PaymentStatus::AwaitingConfirmation);
+        assert!(matches!(receiver.try_recv().unwrap().kind, EventKind::DeliveryConfirmed { payment_id, .. } if payment_id == deferred.payment_id));
+        assert!(settlement.confirm_payment_delivery(&deferred.payment_id, buyer_id).await.is_err());
+
+        assert_eq!(settlement.poll_payment(&deferred.payment_id).await.unwrap().status, PaymentStatus::Succeeded);
+        assert_eq!(settlement.get_payment(&deferred.payment_id).await.unwrap().payment_method, PaymentMethod::PayOnDelivery);
+
+        let card = settlement.create_payment(buyer_id, seller_id, Money::new(Decimal::from(5), "USD"), None).await.unwrap();
+        assert!(settlement.confirm_payment_delivery(&card.payment_id, buyer_id).await.is_err());
+
+        // A buyer who never confirms is charged once the window lapses
+        let unconfirmed = settlement.create_delegated_payment(buyer_id, seller_id, Money::new(Decimal::from(40), "USD"), None, vec![], PaymentMethod::PayOnDelivery).await.unwrap();
+        let rfq = RFQ::new(buyer_id, "laptop-001".to_string(), 1, Decimal::from(40), "USD".to_string(), Utc::now() + Duration::hours(1));
+        let order = settlement.orders().open(&Negotiation::new(rfq, seller_id), &unconfirmed.payment_id).await.unwrap();
+        let mut shipped = settlement.ship_order(order.id, seller_id, shipment_proof()).await.unwrap();
+        assert!(settlement.auto_confirm_payment_deliveries().await.unwrap().is_empty());
+        shipped.shipped_at = Some(Utc::now() - settlement.delivery_confirmation_timeout() - Duration::minutes(1));
+        settlement.orders().update(&shipped).await.unwrap();
+        let delivered = settlement.auto_confirm_payment_deliveries().await.unwrap();
+        assert_eq!(delivered.iter().map(|order| order.id).collect::<Vec<_>>(), [order.id]);
+        assert_eq!(settlement.orders().get(order.id).await.unwrap().status, FulfillmentStatus::Delivered);
+        assert_eq!(settlement.get_payment(&unconfirmed.payment_id).await.unwrap().status, PaymentStatus::AwaitingConfirmation);
+        assert!(settlement.auto_confirm_payment_deliveries().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_quote_payments_charge_checked_taxes() {
+        let (settlement, _db_file) = test_service().await;
+        let config = TaxConfig {
+            jurisdictions: HashMap::from([("DE".to_string(), JurisdictionTaxConfig {
+                name: Some("VAT".to_string()),
+                rate: Decimal::new(19, 2),
+                categories: HashMap::new(),
+            })]),
+            ..TaxConfig::default()
+        };
+        let calculator = FlatRateTaxCalculator::from_config(&config).unwrap();
+        let settlement = settlement.with_tax_calculator(Arc::new(calculator.clone()));
+        let mut rfq = RFQ::new(uuid::Uuid::new_v4(), "laptop-001".to_string(), 1, Decimal::from(500), "EUR".to_string(), Utc::now() + Duration::hours(1));
+        rfq.delivery_location = Some("DE".to_string());
+        let quote_for = |negotiation: &Negotiation, tax_config: &TaxConfig| {
+            let mut quote = Quote::new(negotiation.rfq_id, negotiation.seller_id, Decimal::from(200), "EUR".to_string(), 1, 60)
+                .with_shipping_options(vec![ShippingOption { carrier: "DHL".to_string(), cost: Decimal::from(15), eta_days: 2 }]);
+            let amount = quote.amount();
+            let context = TaxContext { category: "electronics", delivery_location: rfq.delivery_location.as_deref(), amount: &amount };
+            let tax = FlatRateTaxCalculator::from_config(tax_config).unwrap().calculate(&context).unwrap();
+            tax.write_metadata(&context, &mut quote.metadata).unwrap();
+            quote
+        };
+
+        let negotiation = Negotiation::new(rfq.clone(), uuid::Uuid::new_v4());
+        let quote = quote_for(&negotiation, &config);
+        let payment = settlement.create_quote_payment(&negotiation, &quote, vec![], PaymentMethod::Stripe).await.unwrap();
+        // Price, 19% VAT on the price and shipping
+        assert_eq!(payment.amount, Decimal::from(253));
+        assert_eq!(payment.tax.as_ref().unwrap().lines[0].name, "VAT");
+        let stored = settlement.get_negotiation_payment(negotiation.id).await.unwrap();
+        assert_eq!(stored.tax, payment.tax);
+
+        // A seller quoting less tax than is due can't be paid
+        let negotiation = Negotiation::new(rfq.clone(), uuid::Uuid::new_v4());
+        let untaxed = quote_for(&negotiation, &TaxConfig::default());
+        assert!(matches!(
+            settlement.create_quote_payment(&negotiation, &untaxed, vec![], PaymentMethod::Stripe).await,
+            Err(NegotiationError::Validation(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_fees_are_collected_once_payments_settle() {
+        let (settlement, _db_file) = test_service().await;
+        let settlement = settlement.with_fees(FeesConfig {
+            default: FeeRule { rate: Decimal::new(3, 2), flat: Decimal::new(50, 2) },
+            methods: HashMap::from([(PaymentMethod::PayOnDelivery, FeeRule { rate: Decimal::new(5, 2), flat: Decimal::ZERO })]),
+        });
+        let (buyer_id, seller_id) = (uuid::Uuid::new_v4(), uuid::Uuid::new_v4());
+        let amount = Money::new(Decimal::from(100), "USD");
+
+        let card = settlement.create_payment(buyer_id, seller_id, amount.clone(), Some("fees-1".to_string())).await.unwrap();
+        let expected = FeeBreakdown { gross: Decimal::from(100), fee: Decimal::new(350, 2), net: Decimal::new(9650, 2) };
+        assert_eq!(card.fees, Some(expected.clone()));
+        // Nothing is collected until the payment is confirmed
+        assert!(settlement.get_seller_fees(seller_id).await.unwrap().is_empty());
+
+        settlement.poll_payment(&card.payment_id).await.unwrap();
+        let pod = settlement.create_delegated_payment(buyer_id, seller_id, amount.clone(), None, vec![], PaymentMethod::PayOnDelivery).await.unwrap();
+        settlement.confirm_payment_delivery(&pod.payment_id, buyer_id).await.unwrap();
+        settlement.poll_payment(&pod.payment_id).await.unwrap();
+
+        let ledger = settlement.get_seller_fees(seller_id).await.unwrap();
+        assert_eq!(ledger.iter().map(|fee| fee.payment_id.as_str()).collect::<Vec<_>>(), [card.payment_id.as_str(), pod.payment_id.as_str()]);
+        assert_eq!(ledger[0].breakdown, expected);
+        assert_eq!(ledger[1].breakdown.net, Decimal::from(95));
+
+        // Replays report the collected fee without collecting it again
+        let replayed = settlement.create_payment(buyer_id, seller_id, amount, Some("fees-1".to_string())).await.unwrap();
+        assert_eq!(replayed.fees, Some(expected));
+        assert_eq!(settlement.get_seller_fees(seller_id).await.unwrap().len(), 2);
+
+        // Refunding half the card payment gives back half its fee
+        let goodwill = RefundRequest { amount: Some(Decimal::from(50)), reason: RefundReason::Goodwill };
+        let refunded = settlement.refund_payment(&card.payment_id, seller_id, goodwill).await.unwrap();
+        let ledger = settlement.get_seller_fees(seller_id).await.unwrap();
+        assert_eq!(ledger[2].payment_id, refunded.refund.payment_id);
+        assert_eq!(ledger[2].breakdown, FeeBreakdown { gross: Decimal::from(-50), fee: Decimal::new(-175, 2), net: Decimal::new(-4825, 2) });
+    }
+
+    #[tokio::test]
+    async fn test_partial_and_full_refunds() {
+        let (settlement, _db_file) = test_service().await;
+        let (buyer_id, seller_id) = (uuid::Uuid::new_v4(), uuid::Uuid::new_v4());
+        let payment = settlement.create_payment(buyer_id, seller_id, Money::new(Decimal::from(100), "USD"), None).await.unwrap();
+        let refund = |amount: Option<i64>, reason| RefundRequest { amount: amount.map(Decimal::from), reason };
+
+        // Nothing can be refunded before the payment is confirmed
+        assert!(settlement.refund_payment(&payment.payment_id, buyer_id, refund(None, RefundReason::NotDelivered)).await.is_err());
+        settlement.poll_pending().await.unwrap();
+
+        // What the buyer asks for waits on the seller, holding its share meanwhile
+        let asked = settlement.refund_payment(&payment.payment_id, buyer_id, refund(Some(30), RefundReason::NotAsDescribed)).await.unwrap();
+        assert_eq!(asked.refund.status, PaymentStatus::Pending);
+        assert_eq!(asked.payment.status, PaymentStatus::Succeeded);
+        assert_eq!(asked.reputation_penalty, 0);
+        assert!(settlement.refund_payment(&payment.payment_id, buyer_id, refund(Some(71), RefundReason::NotDelivered)).await.is_err());
+
+        let partial = settlement.approve_refund(&asked.refund.payment_id, Some(seller_id)).await.unwrap();
+        assert_eq!(partial.refund.status, PaymentStatus::Succeeded);
+        assert_eq!(partial.payment.status, PaymentStatus::PartiallyRefunded);
+        assert_eq!(partial.refund.refund_of.as_deref(), Some(payment.payment_id.as_str()));
+        assert_eq!(partial.reputation_penalty, CancellationConfig::default().refund_reputation_penalty);
+        assert!(settlement.approve_refund(&asked.refund.payment_id, None).await.is_err());
+
+        let declined = settlement.refund_payment(&payment.payment_id, buyer_id, refund(Some(70), RefundReason::NotDelivered)).await.unwrap();
+        assert_eq!(settlement.decline_refund(&declined.refund.payment_id, None).await.unwrap().status, PaymentStatus::Cancelled);
+        assert!(settlement.refund_payment(&payment.payment_id, buyer_id, refund(None, RefundReason::Goodwill)).await.is_err());
+        assert!(settlement.refund_payment(&payment.payment_id, uuid::Uuid::new_v4(), refund(None, RefundReason::NotDelivered)).await.is_err());
+
+        // Goodwill from the seller leaves their reputation alone
+        let rest = settlement.refund_payment(&payment.payment_id, seller_id, refund(None, RefundReason::Goodwill)).await.unwrap();
+        assert_eq!(rest.refund.amount, Decimal::from(70));
+        assert_eq!(rest.payment.status, PaymentStatus::Refunded);
+        assert_eq!(rest.reputation_penalty, 0);
+        assert!(settlement.refund_payment(&rest.refund.payment_id, seller_id, refund(None, RefundReason::Goodwill)).await.is_err());
+
+        let refunds = settlement.get_refunds(&payment.payment_id).await.unwrap();
+        assert_eq!(refunds.iter().map(|refund| refund.refund_reason).collect::<Vec<_>>(), [
+            Some(RefundReason::NotAsDescribed), Some(RefundReason::NotDelivered), Some(RefundReason::Goodwill),
+        ]);
+        assert!(settlement.refund_payment(&payment.payment_id, buyer_id, refund(Some(1), RefundReason::DuplicateCharge)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_refunds_cannot_exceed_the_payment() {
+        let (settlement, _db_file) = test_service().await;
+        let (buyer_id, seller_id) = (uuid::Uuid::new_v4(), uuid::Uuid::new_v4());
+        let payment = settlement.create_payment(buyer_id, seller_id, Money::new(Decimal::from(100), "USD"), None).await.unwrap();
+        settlement.poll_pending().await.unwrap();
+
+        let requests = (0..4).map(|_| {
+            settlement.refund_payment(&payment.payment_id, buyer_id, RefundRequest {
+                amount: Some(Decimal::from(60)),
+                reason: RefundReason::DuplicateCharge,
+            })
+        });
+        let recorded = futures::future::join_all(requests).await.into_iter().filter(Result::is_ok).count();
+        assert_eq!(recorded, 1);
+        assert_eq!(settlement.get_refunds(&payment.payment_id).await.unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_delivery_auto_confirms_after_timeout() {
+        let mut escrow_hold = EscrowHold {
+            id: uuid::Uuid::new_v4(),
+            transaction_id: uuid::Uuid::new_v4(),
+            buyer_id: uuid::Uuid::new_v4(),
+            seller_id: uuid::Uuid::new_v4(),
+            amount: Decimal::from(10),
+            currency: "USD".to_string(),
+            hold_duration_seconds: 3600,
+            created_at: Utc::now(),
+            expires_at: Utc::now() + Duration::hours(1),
+            status: EscrowStatus::Active,
+            delivery: DeliveryConfirmation::new(),
+            on_chain: None,
+        };
+
+        assert!(!escrow_hold.auto_confirm_if_due());
+        escrow_hold.mark_shipped(shipment_proof(), Duration::seconds(-1)).unwrap();
+        assert!(escrow_hold.auto_confirm_if_due());
+        assert_eq!(escrow_hold.delivery.status, DeliveryStatus::AutoConfirmed);
+    }
+
+    #[tokio::test]
+    async fn test_unshipped_escrows_expire_back_to_the_buyer() {
+        let (settlement, db_file) = test_service().await;
+        let (buyer_id, seller_id) = (uuid::Uuid::new_v4(), uuid::Uuid::new_v4());
+        let unshipped = settlement.process_payment(escrow_request(buyer_id, seller_id)).await.unwrap();
+        let shipped = settlement.process_payment(escrow_request(buyer_id, seller_id)).await.unwrap();
+        let escrow_id = |payment: &PaymentResult| escrow_id(&payment.payment_id).unwrap();
+        settlement.submit_shipment_proof(escrow_id(&shipped), seller_id, shipment_proof()).await.unwrap();
+        assert!(settlement.expire_escrows().await.unwrap().is_empty());
+
+        // Expiry is fixed when a hold is placed, so age both in the database
+        let pool = sqlx::AnyPool::connect(&format!("sqlite://{}", db_file.path().to_string_lossy())).await.unwrap();
+        sqlx::query("UPDATE escrow_holds SET expires_at = $1")
+            .bind((Utc::now() - Duration::minutes(1)).to_rfc3339())
+            .execute(&pool)
+            .await
+            .unwrap();
+        let expired = settlement.expire_escrows().await.unwrap();
+        assert_eq!(expired.iter().map(|hold| hold.id).collect::<Vec<_>>(), [escrow_id(&unshipped)]);
+        assert_eq!(settlement.get_escrow(escrow_id(&unshipped)).await.unwrap().status, EscrowStatus::Expired);
+        assert_eq!(settlement.get_payment_status(&unshipped.payment_id).await.unwrap(), PaymentStatus::Refunded);
+        assert_eq!(settlement.get_escrow(escrow_id(&shipped)).await.unwrap().status, EscrowStatus::Active);
+    }
+}
@@ -0,0 +1,389 @@
+//! Cache, rate-limit and lock state shared between replicas.
+//!
+//! A single registry or seller keeps this state in process. Run several
+//! replicas behind a load balancer and each would keep its own reputation
+//! cache, search cache and rate-limit counters, and two replicas could act on
+//! the same negotiation at once. With `[shared_state] redis_url` set, and the
+//! crate built with the `redis` feature, the state lives in Redis instead, so
+//! every replica sees the same cache entries, counts requests against the same
+//! limits and takes the same per-negotiation locks.
+
+use crate::{
+    config::SharedStateConfig,
+    error::{NegotiationError, Result},
+    TransactionId,
+};
+use async_trait::async_trait;
+use parking_lot::Mutex;
+use serde::{de::DeserializeOwned, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_KEY_PREFIX: &str = "dcap:";
+pub const DEFAULT_SEARCH_CACHE_SECONDS: u64 = 10;
+pub const DEFAULT_LOCK_TTL_MS: u64 = 30_000;
+pub const DEFAULT_LOCK_WAIT_MS: u64 = 5_000;
+
+/// How often a waiting lock is retried
+const LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Key-value store with expiring entries, atomic counters and
+/// compare-and-delete, enough to build caches, rate limits and locks on.
+#[async_trait]
+pub trait SharedStore: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    async fn get(&self, key: &str) -> Result<Option<String>>;
+
+    async fn set(&self, key: &str, value: &str, ttl: Duration) -> Result<()>;
+
+    /// Adds one to the counter at `key`, which starts at zero and expires
+    /// `ttl` after it was created. Returns the new count.
+    async fn increment(&self, key: &str, ttl: Duration) -> Result<u64>;
+
+    /// Sets `key` unless it is already set. Returns whether it was set.
+    async fn set_if_absent(&self, key: &str, value: &str, ttl: Duration) -> Result<bool>;
+
+    /// Deletes `key` if it still holds `value`. Returns whether it was deleted.
+    async fn delete_if(&self, key: &str, value: &str) -> Result<bool>;
+}
+
+/// State kept in process, for services that run as a single replica.
+#[derive(Default)]
+pub struct MemoryStore {
+    entries: Mutex<HashMap<String, (String, Instant)>>,
+}
+
+impl MemoryStore {
+    fn live<'a>(entries: &'a mut HashMap<String, (String, Instant)>, key: &str, now: Instant) -> Option<&'a mut (String, Instant)> {
+        if entries.get(key).is_some_and(|(_, expires_at)| *expires_at <= now) {
+            entries.remove(key);
+        }
+        entries.get_mut(key)
+    }
+}
+
+#[async_trait]
+impl SharedStore for MemoryStore {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<String>> {
+        let mut entries = self.entries.lock();
+        Ok(Self::live(&mut entries, key, Instant::now()).map(|(value, _)| value.clone()))
+    }
+
+    async fn set(&self, key: &str, value: &str, ttl: Duration) -> Result<()> {
+        self.entries.lock().insert(key.to_string(), (value.to_string(), Instant::now() + ttl));
+        Ok(())
+    }
+
+    async fn increment(&self, key: &str, ttl: Duration) -> Result<u64> {
+        let now = Instant::now();
+        let mut entries = self.entries.lock();
+        let count = match Self::live(&mut entries, key, now) {
+            Some((value, _)) => {
+                let count = value.parse::<u64>().unwrap_or_default() + 1;
+                *value = count.to_string();
+                count
+            }
+            None => {
+                entries.insert(key.to_string(), ("1".to_string(), now + ttl));
+                1
+            }
+        };
+        Ok(count)
+    }
+
+    async fn set_if_absent(&self, key: &str, value: &str, ttl: Duration) -> Result<bool> {
+        let now = Instant::now();
+        let mut entries = self.entries.lock();
+        if Self::live(&mut entries, key, now).is_some() {
+            return Ok(false);
+        }
+        entries.insert(key.to_string(), (value.to_string(), now + ttl));
+        Ok(true)
+    }
+
+    async fn delete_if(&self, key: &str, value: &str) -> Result<bool> {
+        let mut entries = self.entries.lock();
+        let held = Self::live(&mut entries, key, Instant::now()).is_some_and(|(held, _)| held == value);
+        if held {
+            entries.remove(key);
+        }
+        Ok(held)
+    }
+}
+
+/// State kept in Redis, shared by every replica pointed at the same server.
+#[cfg(feature = "redis")]
+pub struct RedisStore {
+    connection: redis::aio::ConnectionManager,
+    prefix: String,
+}
+
+#[cfg(feature = "redis")]
+impl RedisStore {
+    pub async fn connect(url: &str, prefix: &str) -> Result<Self> {
+        let client = redis::Client::open(url).map_err(redis_error)?;
+        let connection = redis::aio::ConnectionManager::new(client).await.map_err(redis_error)?;
+        Ok(Self {
+            connection,
+            prefix: prefix.to_string(),
+        })
+    }
+
+    fn key(&self, key: &str) -> String {
+        format!("{}{}", self.prefix, key)
+    }
+}
+
+#[cfg(feature = "redis")]
+fn redis_error(err: redis::RedisError) -> NegotiationError {
+    NegotiationError::Io(format!("Redis: {}", err))
+}
+
+#[cfg(feature = "redis")]
+fn millis(ttl: Duration) -> u64 {
+    (ttl.as_millis() as u64).max(1)
+}
+
+#[cfg(feature = "redis")]
+#[async_trait]
+impl SharedStore for RedisStore {
+    fn name(&self) -> &'static str {
+        "redis"
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<String>> {
+        redis::cmd("GET").arg(self.key(key))
+            .query_async(&mut self.connection.clone()).await
+            .map_err(redis_error)
+    }
+
+    async fn set(&self, key: &str, value: &str, ttl: Duration) -> Result<()> {
+        redis::cmd("SET").arg(self.key(key)).arg(value).arg("PX").arg(millis(ttl))
+            .query_async(&mut self.connection.clone()).await
+            .map_err(redis_error)
+    }
+
+    async fn increment(&self, key: &str, ttl: Duration) -> Result<u64> {
+        // Only the first increment starts the expiry clock
+        redis::Script::new(
+            "local count = redis.call('INCR', KEYS[1]) \
+             if count == 1 then redis.call('PEXPIRE', KEYS[1], ARGV[1]) end \
+             return count",
+        )
+        .key(self.key(key))
+        .arg(millis(ttl))
+        .invoke_async(&mut self.connection.clone()).await
+        .map_err(redis_error)
+    }
+
+    async fn set_if_absent(&self, key: &str, value: &str, ttl: Duration) -> Result<bool> {
+        let reply: Option<String> = redis::cmd("SET").arg(self.key(key)).arg(value).arg("NX").arg("PX").arg(millis(ttl))
+            .query_async(&mut self.connection.clone()).await
+            .map_err(redis_error)?;
+        Ok(reply.is_some())
+    }
+
+    async fn delete_if(&self, key: &str, value: &str) -> Result<bool> {
+        let deleted: u64 = redis::Script::new(
+            "if redis.call('GET', KEYS[1]) == ARGV[1] then return redis.call('DEL', KEYS[1]) else return 0 end",
+        )
+        .key(self.key(key))
+        .arg(value)
+        .invoke_async(&mut self.connection.clone()).await
+        .map_err(redis_error)?;
+        Ok(deleted == 1)
+    }
+}
+
+/// Handle on the shared store, with the caching, counting and locking built
+/// on it.
+#[derive(Clone)]
+pub struct SharedState {
+    store: Arc<dyn SharedStore>,
+    lock_ttl: Duration,
+    lock_wait: Duration,
+    search_cache_ttl: Duration,
+}
+
+impl SharedState {
+    pub fn new(store: Arc<dyn SharedStore>) -> Self {
+        Self {
+            store,
+            lock_ttl: Duration::from_millis(DEFAULT_LOCK_TTL_MS),
+            lock_wait: Duration::from_millis(DEFAULT_LOCK_WAIT_MS),
+            search_cache_ttl: Duration::from_secs(DEFAULT_SEARCH_CACHE_SECONDS),
+        }
+    }
+
+    pub fn in_memory() -> Self {
+        Self::new(Arc::new(MemoryStore::default()))
+    }
+
+    /// Connects to Redis when `redis_url` is set, and keeps the state in
+    /// process otherwise.
+    pub async fn from_config(config: &SharedStateConfig) -> Result<Self> {
+        let store: Arc<dyn SharedStore> = match &config.redis_url {
+            #[cfg(feature = "redis")]
+            Some(url) => Arc::new(RedisStore::connect(url, &config.key_prefix).await?),
+            #[cfg(not(feature = "redis"))]
+            Some(_) => {
+                return Err(NegotiationError::Config(
+                    "[shared_state] redis_url is set but DCAP was built without the redis feature".to_string(),
+                ));
+            }
+            None => Arc::new(MemoryStore::default()),
+        };
+        Ok(Self {
+            store,
+            lock_ttl: Duration::from_millis(config.lock_ttl_ms),
+            lock_wait: Duration::from_millis(config.lock_wait_ms),
+            search_cache_ttl: Duration::from_secs(config.search_cache_seconds),
+        })
+    }
+
+    pub fn backend_name(&self) -> &'static str {
+        self.store.name()
+    }
+
+    /// How long discovery search results are cached
+    pub fn search_cache_ttl(&self) -> Duration {
+        self.search_cache_ttl
+    }
+
+    /// The cached value at `key`. Entries that no longer deserialize, e.g.
+    /// written by an older version, are treated as missing.
+    pub async fn get_json<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>> {
+        Ok(self.store.get(key).await?.and_then(|value| serde_json::from_str(&value).ok()))
+    }
+
+    pub async fn set_json<T: Serialize>(&self, key: &str, value: &T, ttl: Duration) -> Result<()> {
+        self.store.set(key, &serde_json::to_string(value)?, ttl).await
+    }
+
+    pub async fn increment(&self, key: &str, ttl: Duration) -> Result<u64> {
+        self.store.increment(key, ttl).await
+    }
+
+    /// Sets `key` unless it is already set. Returns whether it was set.
+    pub async fn set_if_absent(&self, key: &str, value: &str, ttl: Duration) -> Result<bool> {
+        self.store.set_if_absent(key, value, ttl).await
+    }
+
+    /// Deletes `key` if it still holds `value`. Returns whether it was deleted.
+    pub async fn delete_if(&self, key: &str, value: &str) -> Result<bool> {
+        self.store.delete_if(key, value).await
+    }
+
+    /// Takes the lock named `name`, waiting up to `[shared_state]
+    /// lock_wait_ms` for whoever holds it. The lock lapses after
+    /// `lock_ttl_ms` if its holder never releases it, e.g. because the
+    /// replica died.
+    pub async fn lock(&self, name: &str) -> Result<SharedLock> {
+        let key = format!("lock:{}", name);
+        let token = uuid::Uuid::new_v4().to_string();
+        let deadline = Instant::now() + self.lock_wait;
+        loop {
+            if self.store.set_if_absent(&key, &token, self.lock_ttl).await? {
+                return Ok(SharedLock { store: self.store.clone(), key, token });
+            }
+            if Instant::now() >= deadline {
+                return Err(NegotiationError::Negotiation(format!("{} is locked by another request", name)));
+            }
+            tokio::time::sleep(LOCK_RETRY_INTERVAL).await;
+        }
+    }
+
+    /// Serializes work on a negotiation across replicas.
+    pub async fn lock_negotiation(&self, negotiation_id: TransactionId) -> Result<SharedLock> {
+        self.lock(&format!("negotiation:{}", negotiation_id)).await
+    }
+}
+
+/// A held lock. Release it when done; if it is dropped instead, it lapses
+/// after its TTL.
+pub struct SharedLock {
+    store: Arc<dyn SharedStore>,
+    key: String,
+    token: String,
+}
+
+impl SharedLock {
+    /// Releases the lock, unless it already lapsed and was taken by someone
+    /// else.
+    pub async fn release(self) -> Result<()> {
+        if !self.store.delete_if(&self.key, &self.token).await? {
+            tracing::warn!("Lock {} lapsed before it was released", self.key);
+        }
+        Ok(())
+    }
+}
+
+/// Fixed-window request limit per client, counted in the shared store so
+/// every replica enforces the same budget.
+#[derive(Clone)]
+pub struct RateLimiter {
+    state: SharedState,
+    limit: u64,
+    window: Duration,
+}
+
+impl RateLimiter {
+    pub fn new(state: SharedState, limit: u32, window: Duration) -> Self {
+        Self {
+            state,
+            limit: limit as u64,
+            window,
+        }
+    }
+
+    pub fn per_minute(state: SharedState, limit: u32) -> Self {
+        Self::new(state, limit, Duration::from_secs(60))
+    }
+
+    /// Counts a request from `client`. Returns how long until the client may
+    /// retry when it is over its limit.
+    pub async fn check(&self, client: &str) -> Result<Option<Duration>> {
+        let window_ms = self.window.as_millis().max(1) as u64;
+        let now_ms = chrono::Utc::now().timestamp_millis() as u64;
+        let window = now_ms / window_ms;
+        let count = self.state.increment(&format!("ratelimit:{}:{}", client, window), self.window).await?;
+        if count <= self.limit {
+            return Ok(None);
+        }
+        Ok(Some(Duration::from_millis((window + 1) * window_ms - now_ms)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_memory_store_caches_counts_and_locks() {
+        let state = SharedState::in_memory();
+        state.set_json("reputation:a", &42u32, Duration::from_secs(60)).await.unwrap();
+        assert_eq!(state.get_json::<u32>("reputation:a").await.unwrap(), Some(42));
+        state.set_json("reputation:b", &7u32, Duration::ZERO).await.unwrap();
+        assert_eq!(state.get_json::<u32>("reputation:b").await.unwrap(), None);
+
+        let limiter = RateLimiter::new(state.clone(), 2, Duration::from_secs(3600));
+        assert_eq!(limiter.check("10.0.0.1").await.unwrap(), None);
+        assert_eq!(limiter.check("10.0.0.1").await.unwrap(), None);
+        assert!(limiter.check("10.0.0.1").await.unwrap().is_some());
+        assert_eq!(limiter.check("10.0.0.2").await.unwrap(), None);
+
+        let negotiation_id = uuid::Uuid::new_v4();
+        let state = SharedState { lock_wait: Duration::from_millis(100), ..state };
+        let lock = state.lock_negotiation(negotiation_id).await.unwrap();
+        assert!(state.lock_negotiation(negotiation_id).await.is_err());
+        assert!(state.lock_negotiation(uuid::Uuid::new_v4()).await.is_ok());
+        lock.release().await.unwrap();
+        state.lock_negotiation(negotiation_id).await.unwrap().release().await.unwrap();
+    }
+}
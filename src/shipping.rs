@@ -0,0 +1,158 @@
+//! Shipping options sellers quote alongside their prices.
+//!
+//! A [`ShippingEstimator`] prices shipping an order to the RFQ's delivery
+//! location. Sellers attach the options to their quotes, buyers rank quotes
+//! by landed cost (price, taxes and the cheapest shipping) and pay for the
+//! cheapest option on acceptance.
+//!
+//! [`ConfiguredShipping`] quotes each configured carrier that serves the
+//! delivery location, at a base cost plus a cost per unit. Carriers limited
+//! to some locations serve those and the places within them: `US` covers
+//! `US-CA`. Carriers without locations ship anywhere, including to RFQs that
+//! give no location. Sellers without carriers quote no shipping.
+
+use crate::{
+    config::{CarrierConfig, ShippingConfig},
+    error::{NegotiationError, Result},
+    model::{Product, ShippingOption},
+};
+use rust_decimal::Decimal;
+
+/// Payment metadata holding the shipping option paid for, as JSON
+pub const SHIPPING_METADATA_KEY: &str = "shipping";
+
+/// What an estimator may look at when pricing shipping.
+pub struct ShippingContext<'a> {
+    pub product: &'a Product,
+    pub quantity: u32,
+    pub delivery_location: Option<&'a str>,
+}
+
+pub trait ShippingEstimator: Send + Sync {
+    /// Options for shipping the order, in the quote's currency; none leaves
+    /// shipping out of the quote. Fails if the seller can't ship there.
+    fn estimate(&self, context: &ShippingContext) -> Result<Vec<ShippingOption>>;
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ConfiguredShipping {
+    carriers: Vec<CarrierConfig>,
+}
+
+impl ConfiguredShipping {
+    pub fn from_config(config: &ShippingConfig) -> Result<Self> {
+        for carrier in &config.carriers {
+            if carrier.base_cost < Decimal::ZERO || carrier.per_unit_cost < Decimal::ZERO {
+                return Err(NegotiationError::Config(format!("Shipping costs for {} must not be negative", carrier.carrier)));
+            }
+        }
+        Ok(Self { carriers: config.carriers.clone() })
+    }
+}
+
+/// Whether a carrier limited to `locations` delivers to `location`.
+fn serves(locations: &[String], location: Option<&str>) -> bool {
+    if locations.is_empty() {
+        return true;
+    }
+    let Some(location) = location.map(|location| location.trim().to_uppercase()) else {
+        return false;
+    };
+    locations.iter().map(|served| served.trim().to_uppercase()).any(|served| {
+        location == served || location.strip_prefix(&served).is_some_and(|rest| rest.starts_with('-'))
+    })
+}
+
+impl ShippingEstimator for ConfiguredShipping {
+    fn estimate(&self, context: &ShippingContext) -> Result<Vec<ShippingOption>> {
+        let options: Vec<_> = self.carriers.iter()
+            .filter(|carrier| serves(&carrier.locations, context.delivery_location))
+            .map(|carrier| ShippingOption {
+                carrier: carrier.carrier.clone(),
+                cost: carrier.base_cost + carrier.per_unit_cost * Decimal::from(context.quantity),
+                eta_days: carrier.eta_days,
+            })
+            .collect();
+        if options.is_empty() && !self.carriers.is_empty() {
+            return Err(NegotiationError::Validation(format!(
+                "No carrier delivers to {}", context.delivery_location.unwrap_or("an unspecified location")
+            )));
+        }
+        Ok(options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn carrier(name: &str, base_cost: i64, eta_days: u32, locations: &[&str]) -> CarrierConfig {
+        CarrierConfig {
+            carrier: name.to_string(),
+            base_cost: Decimal::from(base_cost),
+            per_unit_cost: Decimal::new(50, 2),
+            eta_days,
+            locations: locations.iter().map(|location| location.to_string()).collect(),
+        }
+    }
+
+    /// UPS everywhere, USPS in the US and DHL in Germany
+    fn shipping() -> ConfiguredShipping {
+        ConfiguredShipping::from_config(&ShippingConfig {
+            carriers: vec![carrier("UPS", 12, 3, &[]), carrier("USPS", 5, 7, &["us"]), carrier("DHL", 20, 2, &["DE"])],
+        }).unwrap()
+    }
+
+    fn product() -> Product {
+        Product {
+            id: "laptop-001".to_string(),
+            name: "Laptop".to_string(),
+            description: String::new(),
+            category: "electronics".to_string(),
+            base_price: Decimal::from(900),
+            currency: "USD".to_string(),
+            stock_quantity: 10,
+            metadata: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_carriers_quote_where_they_deliver() {
+        let product = product();
+        let carriers = |delivery_location| {
+            shipping().estimate(&ShippingContext { product: &product, quantity: 4, delivery_location }).unwrap()
+                .into_iter().map(|option| option.carrier).collect::<Vec<_>>()
+        };
+
+        assert_eq!(carriers(Some("US-CA")), ["UPS", "USPS"]);
+        assert_eq!(carriers(Some("USA")), ["UPS"]);
+        assert_eq!(carriers(Some("de")), ["UPS", "DHL"]);
+        assert_eq!(carriers(None), ["UPS"]);
+    }
+
+    #[test]
+    fn test_cost_adds_a_per_unit_charge() {
+        let product = product();
+        let options = shipping().estimate(&ShippingContext { product: &product, quantity: 4, delivery_location: Some("US") }).unwrap();
+        assert_eq!(options[1], ShippingOption { carrier: "USPS".to_string(), cost: Decimal::from(7), eta_days: 7 });
+    }
+
+    #[test]
+    fn test_locations_no_carrier_serves_are_refused() {
+        let product = product();
+        let germany_only = ConfiguredShipping::from_config(&ShippingConfig { carriers: vec![carrier("DHL", 20, 2, &["DE"])] }).unwrap();
+        assert!(germany_only.estimate(&ShippingContext { product: &product, quantity: 1, delivery_location: Some("FR") }).is_err());
+    }
+
+    #[test]
+    fn test_no_carriers_quote_nothing() {
+        let product = product();
+        assert!(ConfiguredShipping::default().estimate(&ShippingContext { product: &product, quantity: 1, delivery_location: None }).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_costs_must_not_be_negative() {
+        assert!(ConfiguredShipping::from_config(&ShippingConfig { carriers: vec![carrier("UPS", -1, 3, &[])] }).is_err());
+    }
+}
@@ -0,0 +1,655 @@
+//! On-chain escrow through a Solana escrow program.
+//!
+//! Each escrow hold gets an account derived from the program and the hold's
+//! ID that holds the buyer's lamports. The program takes three instructions:
+//!
+//! - `Initialize`, signed by the buyer: moves the lamports from the buyer's
+//!   wallet into the escrow account and records the seller, the arbiter and
+//!   when the hold expires
+//! - `Release`, signed by the arbiter: pays the platform's fee, in lamports,
+//!   to its fee wallet and the rest of the escrow out to the seller
+//! - `Refund`, signed by the arbiter: gives it back to the buyer, after a
+//!   dispute or once the hold expires
+//!
+//! The settlement service is the arbiter. It can't sign for the buyer, so it
+//! hands them an unsigned funding transaction to sign and send, as Solana Pay
+//! transaction requests do, and watches the escrow account's balance to see
+//! it funded. Release and refund transactions it signs and sends itself,
+//! waiting until they're confirmed.
+
+use crate::{
+    currency::CurrencyConverter,
+    error::{NegotiationError, Result},
+    fees::FeeBreakdown,
+    http::HttpClient,
+    money::Money,
+};
+use base64::{engine::general_purpose, Engine};
+use chrono::{DateTime, Utc};
+use ed25519_dalek::{Signer, SigningKey, VerifyingKey};
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize, Serializer};
+use sha2::{Digest, Sha256};
+use std::{fmt, str::FromStr, time::Duration};
+
+/// Payment metadata naming the wallet the buyer funds the escrow from
+pub const BUYER_WALLET_METADATA: &str = "buyer_wallet";
+/// Payment metadata naming the wallet the seller is paid out to
+pub const SELLER_WALLET_METADATA: &str = "seller_wallet";
+
+/// How long to wait for release and refund transactions to be confirmed
+pub const DEFAULT_CONFIRMATION_TIMEOUT_SECONDS: u64 = 60;
+
+const LAMPORTS_PER_SOL: i64 = 1_000_000_000;
+const CONFIRMATION_POLL_INTERVAL: Duration = Duration::from_millis(500);
+const BASE58_ALPHABET: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SolanaEscrowConfig {
+    pub rpc_url: String,
+    /// Address of the deployed escrow program
+    pub program_id: String,
+    /// Solana CLI keypair file (a JSON array of 64 bytes) the service signs
+    /// releases and refunds with as arbiter
+    pub arbiter_keypair_path: String,
+    /// Wallet releases pay the platform's fee into; the arbiter's when unset
+    #[serde(default)]
+    pub fee_wallet: Option<String>,
+    pub confirmation_timeout_seconds: u64,
+}
+
+/// A Solana account address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Pubkey(pub [u8; 32]);
+
+/// The system program, which owns plain wallet accounts
+pub const SYSTEM_PROGRAM_ID: Pubkey = Pubkey([0; 32]);
+
+impl fmt::Display for Pubkey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&base58_encode(&self.0))
+    }
+}
+
+impl FromStr for Pubkey {
+    type Err = NegotiationError;
+
+    fn from_str(address: &str) -> Result<Self> {
+        base58_decode(address)
+            .and_then(|bytes| bytes.try_into().ok())
+            .map(Pubkey)
+            .ok_or_else(|| NegotiationError::Validation(format!("Invalid Solana address: {}", address)))
+    }
+}
+
+impl Serialize for Pubkey {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Pubkey {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+fn base58_encode(bytes: &[u8]) -> String {
+    let mut digits: Vec<u8> = Vec::new();
+    for byte in bytes {
+        let mut carry = *byte as u32;
+        for digit in digits.iter_mut() {
+            carry += (*digit as u32) << 8;
+            *digit = (carry % 58) as u8;
+            carry /= 58;
+        }
+        while carry > 0 {
+            digits.push((carry % 58) as u8);
+            carry /= 58;
+        }
+    }
+    let zeros = bytes.iter().take_while(|byte| **byte == 0).count();
+    std::iter::repeat_n('1', zeros)
+        .chain(digits.iter().rev().map(|digit| BASE58_ALPHABET[*digit as usize] as char))
+        .collect()
+}
+
+fn base58_decode(encoded: &str) -> Option<Vec<u8>> {
+    let mut bytes: Vec<u8> = Vec::new();
+    for character in encoded.bytes() {
+        let mut carry = BASE58_ALPHABET.iter().position(|c| *c == character)? as u32;
+        for byte in bytes.iter_mut() {
+            carry += (*byte as u32) * 58;
+            *byte = carry as u8;
+            carry >>= 8;
+        }
+        while carry > 0 {
+            bytes.push(carry as u8);
+            carry >>= 8;
+        }
+    }
+    let zeros = encoded.bytes().take_while(|c| *c == b'1').count();
+    Some(std::iter::repeat_n(0, zeros).chain(bytes.into_iter().rev()).collect())
+}
+
+/// The program-derived address for `seeds`, with its bump seed. Derived
+/// addresses lie off the ed25519 curve, so no private key can sign for them.
+pub fn find_program_address(seeds: &[&[u8]], program_id: &Pubkey) -> Result<(Pubkey, u8)> {
+    for bump in (0..=u8::MAX).rev() {
+        let mut hasher = Sha256::new();
+        for seed in seeds {
+            hasher.update(seed);
+        }
+        hasher.update([bump]);
+        hasher.update(program_id.0);
+        hasher.update(b"ProgramDerivedAddress");
+        let address: [u8; 32] = hasher.finalize().into();
+        if VerifyingKey::from_bytes(&address).is_err() {
+            return Ok((Pubkey(address), bump));
+        }
+    }
+    Err(NegotiationError::Payment("No program address found for the escrow".to_string()))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccountMeta {
+    pub pubkey: Pubkey,
+    pub is_signer: bool,
+    pub is_writable: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instruction {
+    pub program_id: Pubkey,
+    pub accounts: Vec<AccountMeta>,
+    pub data: Vec<u8>,
+}
+
+/// Instructions the escrow program takes, Borsh-encoded as a one-byte tag
+/// followed by the fields in little-endian order.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EscrowInstruction {
+    Initialize {
+        escrow_id: uuid::Uuid,
+        lamports: u64,
+        expires_at: i64,
+    },
+    Release {
+        fee_lamports: u64,
+    },
+    Refund,
+}
+
+impl EscrowInstruction {
+    pub fn data(&self) -> Vec<u8> {
+        match self {
+            EscrowInstruction::Initialize { escrow_id, lamports, expires_at } => {
+                let mut data = vec![0];
+                data.extend_from_slice(escrow_id.as_bytes());
+                data.extend_from_slice(&lamports.to_le_bytes());
+                data.extend_from_slice(&expires_at.to_le_bytes());
+                data
+            }
+            EscrowInstruction::Release { fee_lamports } => {
+                let mut data = vec![1];
+                data.extend_from_slice(&fee_lamports.to_le_bytes());
+                data
+            }
+            EscrowInstruction::Refund => vec![2],
+        }
+    }
+}
+
+fn encode_length(out: &mut Vec<u8>, mut length: usize) {
+    // Solana's compact-u16: seven bits at a time, low bits first
+    loop {
+        let byte = (length & 0x7f) as u8;
+        length >>= 7;
+        if length == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+/// Serializes a legacy transaction message paid for by `payer`. Accounts are
+/// ordered signers first, writable before read-only, as the runtime expects.
+pub fn compile_message(payer: &Pubkey, instructions: &[Instruction], recent_blockhash: &[u8; 32]) -> Vec<u8> {
+    let mut accounts = vec![AccountMeta { pubkey: *payer, is_signer: true, is_writable: true }];
+    let metas = instructions.iter().flat_map(|instruction| {
+        instruction.accounts.iter().cloned()
+            .chain(std::iter::once(AccountMeta { pubkey: instruction.program_id, is_signer: false, is_writable: false }))
+    });
+    for meta in metas {
+        match accounts.iter_mut().find(|account| account.pubkey == meta.pubkey) {
+            Some(account) => {
+                account.is_signer |= meta.is_signer;
+                account.is_writable |= meta.is_writable;
+            }
+            None => accounts.push(meta),
+        }
+    }
+    // Stable, so the payer stays first
+    accounts.sort_by_key(|account| (!account.is_signer, !account.is_writable));
+
+    let count = |signer: bool, writable: bool| {
+        accounts.iter().filter(|account| account.is_signer == signer && account.is_writable == writable).count() as u8
+    };
+    let required_signatures = accounts.iter().filter(|account| account.is_signer).count() as u8;
+    let mut message = vec![required_signatures, count(true, false), count(false, false)];
+    encode_length(&mut message, accounts.len());
+    for account in &accounts {
+        message.extend_from_slice(&account.pubkey.0);
+    }
+    message.extend_from_slice(recent_blockhash);
+
+    let index = |pubkey: &Pubkey| accounts.iter().position(|account| account.pubkey == *pubkey).unwrap_or_default() as u8;
+    encode_length(&mut message, instructions.len());
+    for instruction in instructions {
+        message.push(index(&instruction.program_id));
+        encode_length(&mut message, instruction.accounts.len());
+        message.extend(instruction.accounts.iter().map(|account| index(&account.pubkey)));
+        encode_length(&mut message, instruction.data.len());
+        message.extend_from_slice(&instruction.data);
+    }
+    message
+}
+
+/// A transaction as sent over RPC: its signatures, then the message. Signers
+/// that haven't signed yet leave their signature zeroed.
+pub fn serialize_transaction(signatures: &[[u8; 64]], message: &[u8]) -> Vec<u8> {
+    let mut transaction = Vec::new();
+    encode_length(&mut transaction, signatures.len());
+    for signature in signatures {
+        transaction.extend_from_slice(signature);
+    }
+    transaction.extend_from_slice(message);
+    transaction
+}
+
+/// The escrow account of a hold placed on chain, stored with the hold.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OnChainEscrow {
+    pub address: Pubkey,
+    pub buyer_wallet: Pubkey,
+    pub seller_wallet: Pubkey,
+    pub lamports: u64,
+    /// Whether the buyer's funding transaction has landed
+    pub funded: bool,
+    /// Confirmed release or refund transaction
+    pub settlement_signature: Option<String>,
+}
+
+impl OnChainEscrow {
+    /// The platform's share of the escrow in lamports, for `fees` assessed
+    /// on the hold's amount.
+    pub fn fee_lamports(&self, fees: &FeeBreakdown) -> u64 {
+        if fees.gross <= Decimal::ZERO {
+            return 0;
+        }
+        (Decimal::from(self.lamports) * fees.fee / fees.gross).round().to_u64()
+            .unwrap_or_default()
+            .min(self.lamports)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcResponse<T> {
+    result: Option<T>,
+    error: Option<RpcError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcError {
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct WithContext<T> {
+    value: T,
+}
+
+#[derive(Debug, Deserialize)]
+struct LatestBlockhash {
+    blockhash: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SignatureStatus {
+    confirmation_status: Option<String>,
+    err: Option<serde_json::Value>,
+}
+
+/// Places escrow holds on chain through the escrow program, as its arbiter.
+#[derive(Clone)]
+pub struct SolanaEscrow {
+    client: HttpClient,
+    rpc_url: String,
+    program_id: Pubkey,
+    arbiter: SigningKey,
+    fee_wallet: Option<Pubkey>,
+    converter: CurrencyConverter,
+    confirmation_timeout: Duration,
+}
+
+impl SolanaEscrow {
+    pub fn new(config: &SolanaEscrowConfig) -> Result<Self> {
+        let keypair = std::fs::read_to_string(&config.arbiter_keypair_path)
+            .map_err(|e| NegotiationError::Config(format!("Failed to read arbiter keypair {}: {}", config.arbiter_keypair_path, e)))?;
+        let keypair: Vec<u8> = serde_json::from_str(&keypair)?;
+        let secret: [u8; 32] = keypair.get(..32).and_then(|secret| secret.try_into().ok())
+            .ok_or_else(|| NegotiationError::Config("Arbiter keypair must hold 64 bytes".to_string()))?;
+        Ok(Self {
+            client: HttpClient::default(),
+            rpc_url: config.rpc_url.clone(),
+            program_id: config.program_id.parse()?,
+            arbiter: SigningKey::from_bytes(&secret),
+            fee_wallet: config.fee_wallet.as_deref().map(str::parse).transpose()?,
+            converter: CurrencyConverter::default(),
+            confirmation_timeout: Duration::from_secs(config.confirmation_timeout_seconds),
+        })
+    }
+
+    /// Converts amounts quoted in other currencies to SOL with `converter`.
+    pub fn with_currency_converter(mut self, converter: CurrencyConverter) -> Self {
+        self.converter = converter;
+        self
+    }
+
+    pub fn arbiter(&self) -> Pubkey {
+        Pubkey(self.arbiter.verifying_key().to_bytes())
+    }
+
+    /// Where releases pay the platform's fee
+    pub fn fee_wallet(&self) -> Pubkey {
+        self.fee_wallet.unwrap_or_else(|| self.arbiter())
+    }
+
+    pub fn escrow_address(&self, escrow_id: uuid::Uuid) -> Result<Pubkey> {
+        find_program_address(&[b"escrow", escrow_id.as_bytes()], &self.program_id).map(|(address, _)| address)
+    }
+
+    /// `amount` in lamports, rounded to the nearest one.
+    pub async fn lamports(&self, amount: &Money) -> Result<u64> {
+        let sol = self.converter.convert(amount, "SOL").await?;
+        (sol.amount * Decimal::from(LAMPORTS_PER_SOL)).round().to_u64()
+            .filter(|lamports| *lamports > 0)
+            .ok_or_else(|| NegotiationError::Validation(format!("Can't escrow {} on Solana", amount)))
+    }
+
+    pub fn initialize_instruction(
+        &self,
+        escrow_id: uuid::Uuid,
+        buyer: Pubkey,
+        seller: Pubkey,
+        lamports: u64,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Instruction> {
+        Ok(Instruction {
+            program_id: self.program_id,
+            accounts: vec![
+                AccountMeta { pubkey: buyer, is_signer: true, is_writable: true },
+                AccountMeta { pubkey: seller, is_signer: false, is_writable: false },
+                AccountMeta { pubkey: self.arbiter(), is_signer: false, is_writable: false },
+                AccountMeta { pubkey: self.escrow_address(escrow_id)?, is_signer: false, is_writable: true },
+                AccountMeta { pubkey: SYSTEM_PROGRAM_ID, is_signer: false, is_writable: false },
+            ],
+            data: EscrowInstruction::Initialize { escrow_id, lamports, expires_at: expires_at.timestamp() }.data(),
+        })
+    }
+
+    /// Pays the escrow out to `recipients`: the seller and the fee wallet
+    /// for a release, the buyer for a refund.
+    fn payout_instruction(&self, escrow: &OnChainEscrow, instruction: EscrowInstruction, recipients: &[Pubkey]) -> Instruction {
+        let mut accounts = vec![
+            AccountMeta { pubkey: self.arbiter(), is_signer: true, is_writable: true },
+            AccountMeta { pubkey: escrow.address, is_signer: false, is_writable: true },
+        ];
+        accounts.extend(recipients.iter().map(|&pubkey| AccountMeta { pubkey, is_signer: false, is_writable: true }));
+        Instruction {
+            program_id: self.program_id,
+            accounts,
+            data: instruction.data(),
+        }
+    }
+
+    /// Pays the seller the escrow net of `fee_lamports`, which go to the fee
+    /// wallet.
+    pub fn release_instruction(&self, escrow: &OnChainEscrow, fee_lamports: u64) -> Instruction {
+        self.payout_instruction(escrow, EscrowInstruction::Release { fee_lamports }, &[escrow.seller_wallet, self.fee_wallet()])
+    }
+
+    pub fn refund_instruction(&self, escrow: &OnChainEscrow) -> Instruction {
+        self.payout_instruction(escrow, EscrowInstruction::Refund, &[escrow.buyer_wallet])
+    }
+
+    /// The base64 transaction the buyer signs to fund an escrow, paying its
+    /// fees too.
+    pub async fn funding_transaction(&self, buyer: Pubkey, initialize: Instruction) -> Result<String> {
+        let message = compile_message(&buyer, &[initialize], &self.latest_blockhash().await?);
+        Ok(general_purpose::STANDARD.encode(serialize_transaction(&[[0; 64]], &message)))
+    }
+
+    /// Whether the escrow account holds the full amount.
+    pub async fn is_funded(&self, escrow: &OnChainEscrow) -> Result<bool> {
+        let balance: WithContext<u64> = self.call("getBalance", serde_json::json!([escrow.address.to_string()])).await?;
+        Ok(balance.value >= escrow.lamports)
+    }
+
+    /// Signs `instruction` as the arbiter, sends it and waits for it to be
+    /// confirmed, returning its signature.
+    pub async fn send_and_confirm(&self, instruction: Instruction) -> Result<String> {
+        let message = compile_message(&self.arbiter(), &[instruction], &self.latest_blockhash().await?);
+        let signature = self.arbiter.sign(&message).to_bytes();
+        let transaction = general_purpose::STANDARD.encode(serialize_transaction(&[signature], &message));
+        let signature: String = self.call("sendTransaction", serde_json::json!([transaction, {"encoding": "base64"}])).await?;
+
+        let deadline = tokio::time::Instant::now() + self.confirmation_timeout;
+        while tokio::time::Instant::now() < deadline {
+            let statuses: WithContext<Vec<Option<SignatureStatus>>> =
+                self.call("getSignatureStatuses", serde_json::json!([[signature]])).await?;
+            if let Some(Some(status)) = statuses.value.into_iter().next() {
+                if let Some(err) = status.err {
+                    return Err(NegotiationError::Payment(format!("Escrow transaction {} failed: {}", signature, err)));
+                }
+                if matches!(status.confirmation_status.as_deref(), Some("confirmed" | "finalized")) {
+                    return Ok(signature);
+                }
+            }
+            tokio::time::sleep(CONFIRMATION_POLL_INTERVAL).await;
+        }
+        Err(NegotiationError::Payment(format!("Escrow transaction {} wasn't confirmed in time", signature)))
+    }
+
+    async fn latest_blockhash(&self) -> Result<[u8; 32]> {
+        let latest: WithContext<LatestBlockhash> = self.call("getLatestBlockhash", serde_json::json!([])).await?;
+        Ok(latest.value.blockhash.parse::<Pubkey>()?.0)
+    }
+
+    async fn call<T: DeserializeOwned>(&self, method: &str, params: serde_json::Value) -> Result<T> {
+        let response: RpcResponse<T> = self.client
+            .post(&self.rpc_url)
+            .json(&serde_json::json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        match (response.result, response.error) {
+            (_, Some(error)) => Err(NegotiationError::Payment(format!("Solana {} failed: {}", method, error.message))),
+            (Some(result), None) => Ok(result),
+            (None, None) => Err(NegotiationError::Payment(format!("Solana {} returned nothing", method))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::{FeeRule, FeesConfig},
+        currency::FixedRateProvider,
+        database::Database,
+        model::PaymentMethod,
+        settlement::{EscrowStatus, PaymentRequest, PaymentStatus, RefundReason, RefundRequest, SettlementConfig, SettlementService, ShipmentProof},
+    };
+    use axum::{extract::State, routing::post, Json, Router};
+    use ed25519_dalek::{Signature, Verifier};
+    use std::{collections::HashMap, sync::{Arc, Mutex}};
+    use tempfile::NamedTempFile;
+
+    #[derive(Clone, Default)]
+    struct MockChain {
+        balance: Arc<Mutex<u64>>,
+        sent: Arc<Mutex<Vec<Vec<u8>>>>,
+    }
+
+    async fn rpc(State(chain): State<MockChain>, Json(request): Json<serde_json::Value>) -> Json<serde_json::Value> {
+        let result = match request["method"].as_str().unwrap() {
+            "getLatestBlockhash" => serde_json::json!({ "value": { "blockhash": Pubkey([7; 32]).to_string() } }),
+            "getBalance" => serde_json::json!({ "value": *chain.balance.lock().unwrap() }),
+            "sendTransaction" => {
+                let transaction = general_purpose::STANDARD.decode(request["params"][0].as_str().unwrap()).unwrap();
+                chain.sent.lock().unwrap().push(transaction);
+                serde_json::json!("5sig")
+            }
+            "getSignatureStatuses" => serde_json::json!({ "value": [{ "confirmationStatus": "confirmed", "err": null }] }),
+            method => panic!("unexpected RPC method {}", method),
+        };
+        Json(serde_json::json!({ "jsonrpc": "2.0", "id": 1, "result": result }))
+    }
+
+    async fn mock_chain() -> (MockChain, String) {
+        let chain = MockChain::default();
+        let app = Router::new().route("/", post(rpc)).with_state(chain.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (chain, format!("http://{}", addr))
+    }
+
+    fn escrow(rpc_url: String, keypair_file: &NamedTempFile) -> SolanaEscrow {
+        std::fs::write(keypair_file.path(), serde_json::to_string(&[9u8; 64].to_vec()).unwrap()).unwrap();
+        let rates = FixedRateProvider::new("USD").with_rate("SOL", Decimal::from_str("0.01").unwrap());
+        SolanaEscrow::new(&SolanaEscrowConfig {
+            rpc_url,
+            program_id: Pubkey([3; 32]).to_string(),
+            arbiter_keypair_path: keypair_file.path().to_string_lossy().to_string(),
+            fee_wallet: Some(Pubkey([4; 32]).to_string()),
+            confirmation_timeout_seconds: DEFAULT_CONFIRMATION_TIMEOUT_SECONDS,
+        }).unwrap().with_currency_converter(CurrencyConverter::new(Arc::new(rates)))
+    }
+
+    fn shipment_proof() -> ShipmentProof {
+        ShipmentProof { carrier: "UPS".to_string(), tracking_number: "1Z999".to_string(), proof_url: None, notes: None }
+    }
+
+    #[test]
+    fn test_encodings_match_solana() {
+        assert_eq!(SYSTEM_PROGRAM_ID.to_string(), "11111111111111111111111111111111");
+        let token_program = "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA";
+        assert_eq!(token_program.parse::<Pubkey>().unwrap().to_string(), token_program);
+        assert!("0OIl".parse::<Pubkey>().is_err());
+
+        let encoded = |length| {
+            let mut out = Vec::new();
+            encode_length(&mut out, length);
+            out
+        };
+        assert_eq!(encoded(0x7f), vec![0x7f]);
+        assert_eq!(encoded(0x80), vec![0x80, 0x01]);
+        assert_eq!(encoded(0x3fff), vec![0xff, 0x7f]);
+
+        let (address, _) = find_program_address(&[b"escrow"], &Pubkey([3; 32])).unwrap();
+        assert!(VerifyingKey::from_bytes(&address.0).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_escrow_is_funded_and_released_on_chain() {
+        let (chain, rpc_url) = mock_chain().await;
+        let keypair_file = NamedTempFile::new().unwrap();
+        let solana = escrow(rpc_url, &keypair_file);
+        let arbiter = solana.arbiter();
+
+        let temp_file = NamedTempFile::new().unwrap();
+        let database = Database::new(&format!("sqlite://{}", temp_file.path().to_string_lossy())).await.unwrap();
+        let settlement = SettlementService::new(SettlementConfig {
+            stripe_secret_key: None,
+            solana_rpc_url: None,
+            escrow_service_url: None,
+            webhook_secret: None,
+            delivery_confirmation_timeout_seconds: None,
+        }, database).await.unwrap().with_solana_escrow(solana).with_fees(FeesConfig {
+            default: FeeRule { rate: Decimal::new(2, 2), flat: Decimal::ZERO },
+            methods: HashMap::new(),
+        });
+
+        let (buyer_id, seller_id) = (uuid::Uuid::new_v4(), uuid::Uuid::new_v4());
+        let (buyer_wallet, seller_wallet) = (Pubkey([1; 32]), Pubkey([2; 32]));
+        let request = |metadata: HashMap<String, String>| PaymentRequest {
+            transaction_id: uuid::Uuid::new_v4(),
+            buyer_id,
+            seller_id,
+            amount: Decimal::from(250),
+            currency: "USD".to_string(),
+            payment_method: PaymentMethod::Escrow,
+            description: "Escrow test".to_string(),
+            metadata,
+            idempotency_key: None,
+            delegation_chain: vec![],
+        };
+        assert!(settlement.process_payment(request(HashMap::new())).await.is_err());
+
+        let wallets = HashMap::from([
+            (BUYER_WALLET_METADATA.to_string(), buyer_wallet.to_string()),
+            (SELLER_WALLET_METADATA.to_string(), seller_wallet.to_string()),
+        ]);
+        let payment = settlement.process_payment(request(wallets.clone())).await.unwrap();
+        assert_eq!(payment.status, PaymentStatus::AwaitingConfirmation);
+        let escrow_id = uuid::Uuid::parse_str(payment.payment_id.trim_start_matches("escrow_")).unwrap();
+        let hold = settlement.get_escrow(escrow_id).await.unwrap();
+        let on_chain = hold.on_chain.clone().unwrap();
+        // $250 at 0.01 SOL to the dollar
+        assert_eq!(on_chain.lamports, 2_500_000_000);
+
+        // An unsigned transaction for the buyer, who pays its fees
+        let funding = general_purpose::STANDARD.decode(payment.funding_transaction.unwrap()).unwrap();
+        assert_eq!((funding[0], &funding[1..65]), (1, &[0u8; 64][..]));
+        assert_eq!(&funding[65..68], &[1, 0, 4]);
+        assert_eq!(&funding[69..101], &buyer_wallet.0);
+
+        // Nothing moves until the escrow account holds the full amount
+        assert_eq!(settlement.poll_payment(&payment.payment_id).await.unwrap().status, PaymentStatus::AwaitingConfirmation);
+        *chain.balance.lock().unwrap() = on_chain.lamports;
+        assert_eq!(settlement.poll_payment(&payment.payment_id).await.unwrap().status, PaymentStatus::Pending);
+
+        settlement.submit_shipment_proof(escrow_id, seller_id, shipment_proof()).await.unwrap();
+        settlement.confirm_delivery(escrow_id, buyer_id).await.unwrap();
+        settlement.release_escrow(escrow_id).await.unwrap();
+
+        let sent = chain.sent.lock().unwrap().pop().unwrap();
+        let message = &sent[65..];
+        let signature = Signature::from_bytes(sent[1..65].try_into().unwrap());
+        VerifyingKey::from_bytes(&arbiter.0).unwrap().verify(message, &signature).unwrap();
+        // The seller is paid net of the 2% fee, which goes to the fee wallet
+        let fee_lamports = 50_000_000u64;
+        let mut release = vec![4, 4, 0, 1, 2, 3, 9, 1];
+        release.extend_from_slice(&fee_lamports.to_le_bytes());
+        assert!(message.ends_with(&release));
+        let accounts: Vec<&[u8]> = message[4..4 + 32 * message[3] as usize].chunks(32).collect();
+        assert_eq!(accounts[2..4], [&seller_wallet.0[..], &[4u8; 32][..]]);
+        let released = settlement.get_escrow(escrow_id).await.unwrap();
+        assert_eq!(released.status, EscrowStatus::Released);
+        assert_eq!(released.on_chain.unwrap().settlement_signature.as_deref(), Some("5sig"));
+
+        // A disputed hold goes back to the buyer on chain
+        let disputed = settlement.process_payment(request(wallets)).await.unwrap();
+        settlement.poll_payment(&disputed.payment_id).await.unwrap();
+        let refund = RefundRequest { reason: RefundReason::NotDelivered, amount: None };
+        let asked = settlement.refund_payment(&disputed.payment_id, buyer_id, refund).await.unwrap();
+        settlement.approve_refund(&asked.refund.payment_id, Some(seller_id)).await.unwrap();
+        assert_eq!(*chain.sent.lock().unwrap().pop().unwrap().last().unwrap(), 2);
+    }
+}
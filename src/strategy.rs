@@ -0,0 +1,257 @@
+//! Automated buyer negotiation strategies.
+//!
+//! A strategy decides the buyer's next counter offer from the offers made so
+//! far. Offers move from the buyer's target price toward its reservation price
+//! (the RFQ's max price); `BuyerAgent::auto_negotiate` drives the rounds.
+
+use crate::{
+    concession::{ConcessionCurve, ConcessionMetrics},
+    error::{NegotiationError, Result},
+    money::decimal_from_f64,
+};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+/// Counter offers sent before the buyer must accept or walk away
+pub const DEFAULT_MAX_ROUNDS: u32 = 10;
+
+/// Offers and asks so far, all in the buyer's currency.
+#[derive(Debug, Clone)]
+pub struct OfferContext {
+    /// Counter offers already sent
+    pub round: u32,
+    pub max_rounds: u32,
+    /// Price the buyer opens with and would ideally pay
+    pub target_price: Decimal,
+    /// Most the buyer will pay
+    pub reservation_price: Decimal,
+    /// Seller asks, oldest first
+    pub seller_offers: Vec<Decimal>,
+    /// Buyer counter offers, oldest first
+    pub buyer_offers: Vec<Decimal>,
+    /// Percent the seller's list price moved over the registry's default
+    /// history window, when known; negative when the price dropped
+    pub recent_price_change: Option<Decimal>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decision {
+    Counter(Decimal),
+    Accept,
+    Reject,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+pub enum NegotiationOutcome {
+    Accepted { price: Decimal, rounds: u32 },
+    Rejected { last_ask: Decimal, rounds: u32 },
+}
+
+impl OfferContext {
+    pub fn latest_ask(&self) -> Option<Decimal> {
+        self.seller_offers.last().copied()
+    }
+
+    /// The offers so far as a concession curve.
+    pub fn concession_curve(&self) -> ConcessionCurve {
+        ConcessionCurve::from_offers(&self.seller_offers, &self.buyer_offers)
+    }
+
+    /// How far each side has conceded so far, measured against the latest ask.
+    pub fn concession_metrics(&self) -> ConcessionMetrics {
+        self.concession_curve().metrics(None)
+    }
+
+    /// Fraction of the negotiation used up, from 0 to 1.
+    pub fn time_fraction(&self) -> f64 {
+        if self.max_rounds == 0 {
+            return 1.0;
+        }
+        (self.round as f64 / self.max_rounds as f64).min(1.0)
+    }
+
+    fn clamp(&self, offer: Decimal) -> Decimal {
+        offer.max(self.target_price).min(self.reservation_price)
+    }
+}
+
+pub trait NegotiationStrategy: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// The buyer's next counter offer, before clamping to the target and
+    /// reservation prices.
+    fn next_offer(&self, context: &OfferContext) -> Result<Decimal>;
+
+    /// Accepts once the seller asks no more than the buyer would offer,
+    /// counters while rounds remain, and on the last round accepts anything
+    /// within the reservation price.
+    fn decide(&self, context: &OfferContext) -> Result<Decision> {
+        decide(context, |context| self.next_offer(context))
+    }
+}
+
+/// The decision rule of [`NegotiationStrategy::decide`], for offers that
+/// come from elsewhere, such as a language model. `next_offer` is only asked
+/// while rounds remain.
+pub fn decide(context: &OfferContext, next_offer: impl FnOnce(&OfferContext) -> Result<Decimal>) -> Result<Decision> {
+    let ask = context.latest_ask()
+        .ok_or_else(|| NegotiationError::Negotiation("No seller offer to respond to".to_string()))?;
+
+    if context.round >= context.max_rounds {
+        return Ok(if ask <= context.reservation_price { Decision::Accept } else { Decision::Reject });
+    }
+
+    let offer = context.clamp(next_offer(context)?);
+    if ask <= offer {
+        Ok(Decision::Accept)
+    } else {
+        Ok(Decision::Counter(offer))
+    }
+}
+
+/// Concedes from target to reservation as `t^(1/beta)` of the rounds elapse.
+/// `beta` of 1 concedes linearly, above 1 concedes early.
+#[derive(Debug, Clone)]
+pub struct TimeDependentConcession {
+    beta: f64,
+}
+
+impl TimeDependentConcession {
+    pub fn new(beta: f64) -> Result<Self> {
+        if !(beta.is_finite() && beta > 0.0) {
+            return Err(NegotiationError::Validation(format!("Concession beta must be positive: {}", beta)));
+        }
+        Ok(Self { beta })
+    }
+
+    pub fn linear() -> Self {
+        Self { beta: 1.0 }
+    }
+}
+
+impl NegotiationStrategy for TimeDependentConcession {
+    fn name(&self) -> &str {
+        "time_dependent"
+    }
+
+    fn next_offer(&self, context: &OfferContext) -> Result<Decimal> {
+        concession_offer(context, self.beta)
+    }
+}
+
+/// Holds close to the target price until the final rounds.
+#[derive(Debug, Clone)]
+pub struct Boulware {
+    beta: f64,
+}
+
+impl Boulware {
+    pub const DEFAULT_BETA: f64 = 0.2;
+
+    pub fn new() -> Self {
+        Self { beta: Self::DEFAULT_BETA }
+    }
+}
+
+impl Default for Boulware {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NegotiationStrategy for Boulware {
+    fn name(&self) -> &str {
+        "boulware"
+    }
+
+    fn next_offer(&self, context: &OfferContext) -> Result<Decimal> {
+        concession_offer(context, self.beta)
+    }
+}
+
+/// Mirrors the seller: each counter offer rises by as much as the seller's
+/// last ask came down.
+#[derive(Debug, Clone, Default)]
+pub struct TitForTat;
+
+impl NegotiationStrategy for TitForTat {
+    fn name(&self) -> &str {
+        "tit_for_tat"
+    }
+
+    fn next_offer(&self, context: &OfferContext) -> Result<Decimal> {
+        let last_offer = match context.buyer_offers.last() {
+            Some(offer) => *offer,
+            None => return Ok(context.target_price),
+        };
+
+        let seller_concession = match context.seller_offers.as_slice() {
+            [.., previous, latest] => (*previous - *latest).max(Decimal::ZERO),
+            _ => Decimal::ZERO,
+        };
+        Ok(last_offer + seller_concession)
+    }
+}
+
+/// Parses a strategy name as used on the command line.
+pub fn strategy_from_name(name: &str) -> Result<Box<dyn NegotiationStrategy>> {
+    match name {
+        "linear" | "time_dependent" => Ok(Box::new(TimeDependentConcession::linear())),
+        "conceder" => Ok(Box::new(TimeDependentConcession::new(3.0)?)),
+        "boulware" => Ok(Box::new(Boulware::new())),
+        "tit_for_tat" | "tft" => Ok(Box::new(TitForTat)),
+        other => Err(NegotiationError::Validation(format!("Unknown negotiation strategy: {}", other))),
+    }
+}
+
+fn concession_offer(context: &OfferContext, beta: f64) -> Result<Decimal> {
+    let concession = decimal_from_f64(context.time_fraction().powf(1.0 / beta))?;
+    Ok(context.target_price + (context.reservation_price - context.target_price) * concession)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context(round: u32, seller_offers: &[i64], buyer_offers: &[i64]) -> OfferContext {
+        OfferContext {
+            round,
+            max_rounds: 4,
+            target_price: Decimal::from(60),
+            reservation_price: Decimal::from(100),
+            seller_offers: seller_offers.iter().map(|p| Decimal::from(*p)).collect(),
+            buyer_offers: buyer_offers.iter().map(|p| Decimal::from(*p)).collect(),
+            recent_price_change: None,
+        }
+    }
+
+    #[test]
+    fn test_time_dependent_concession() {
+        let linear = TimeDependentConcession::linear();
+        assert_eq!(linear.decide(&context(0, &[120], &[])).unwrap(), Decision::Counter(Decimal::from(60)));
+        assert_eq!(linear.decide(&context(2, &[120], &[])).unwrap(), Decision::Counter(Decimal::from(80)));
+
+        // Boulware barely moves at the halfway point
+        let offer = Boulware::new().next_offer(&context(2, &[120], &[])).unwrap();
+        assert!(offer < Decimal::from(62));
+
+        // Final round: accept within reservation, otherwise walk away
+        assert_eq!(linear.decide(&context(4, &[95], &[])).unwrap(), Decision::Accept);
+        assert_eq!(linear.decide(&context(4, &[105], &[])).unwrap(), Decision::Reject);
+    }
+
+    #[test]
+    fn test_tit_for_tat_mirrors_seller_concession() {
+        let strategy = TitForTat;
+        assert_eq!(strategy.decide(&context(0, &[120], &[])).unwrap(), Decision::Counter(Decimal::from(60)));
+        assert_eq!(strategy.decide(&context(1, &[120, 110], &[60])).unwrap(), Decision::Counter(Decimal::from(70)));
+        // Seller holding firm gets no concession back
+        assert_eq!(strategy.decide(&context(2, &[110, 110], &[70])).unwrap(), Decision::Counter(Decimal::from(70)));
+        // Ask at or below what we'd offer is accepted
+        assert_eq!(strategy.decide(&context(2, &[110, 70], &[70])).unwrap(), Decision::Accept);
+
+        let metrics = context(2, &[120, 110, 110], &[60, 70]).concession_metrics();
+        assert_eq!((metrics.rounds, metrics.final_gap), (2, Some(Decimal::from(40))));
+    }
+}
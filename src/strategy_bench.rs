@@ -0,0 +1,318 @@
+//! Benchmarks for buyer negotiation strategies.
+//!
+//! Every contender, a configured strategy or a language model, negotiates the
+//! same generated scenarios against a simulated seller, and the runs are
+//! summarised per contender: how often it closed, how much of the buyer's
+//! budget it kept, and how many rounds it took. Scenarios come from a seed, so
+//! two benchmarks with the same seed and settings are comparable.
+
+use crate::{
+    config::LLMConfig,
+    error::{NegotiationError, Result},
+    money::decimal_from_f64,
+    strategy::{self, strategy_from_name, Decision, NegotiationStrategy, OfferContext},
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use reqwest::Client;
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Scenarios a benchmark runs when the config doesn't say
+pub const DEFAULT_BENCH_SCENARIOS: usize = 200;
+
+pub const DEFAULT_BENCH_SEED: u64 = 42;
+
+/// Where language models are reached when the config names no `api_base`
+pub const DEFAULT_LLM_API_BASE: &str = "https://api.openai.com/v1";
+
+/// One negotiation to run: the seller's list and reserve prices, the buyer's
+/// target and reservation prices, and how quickly the seller concedes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Scenario {
+    pub list_price: Decimal,
+    /// Least the seller takes
+    pub seller_reserve: Decimal,
+    pub target_price: Decimal,
+    /// Most the buyer pays
+    pub reservation_price: Decimal,
+    /// Seller concession exponent; below 1 holds firm, above 1 concedes early
+    pub seller_beta: f64,
+}
+
+impl Scenario {
+    /// Scenarios drawn from `seed`. Some leave no room for a deal, where the
+    /// buyer's reservation price is under the seller's reserve; walking away
+    /// from those is the right outcome.
+    pub fn generate(seed: u64, count: usize) -> Result<Vec<Self>> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        (0..count)
+            .map(|_| {
+                let list_price = rng.gen_range(100.0..1000.0);
+                let reservation_price = list_price * rng.gen_range(0.7..1.05);
+                Ok(Self {
+                    list_price: cents(list_price)?,
+                    seller_reserve: cents(list_price * rng.gen_range(0.6..0.95))?,
+                    target_price: cents(reservation_price * rng.gen_range(0.5..0.8))?,
+                    reservation_price: cents(reservation_price)?,
+                    seller_beta: [0.3, 1.0, 3.0][rng.gen_range(0..3)],
+                })
+            })
+            .collect()
+    }
+}
+
+fn cents(value: f64) -> Result<Decimal> {
+    Ok(decimal_from_f64(value)?.round_dp(2))
+}
+
+/// Seller conceding from its list price to its reserve over the rounds, who
+/// takes any counter offer at or above what it would ask next.
+#[derive(Debug, Clone)]
+pub struct SimulatedSeller<'a> {
+    scenario: &'a Scenario,
+    max_rounds: u32,
+}
+
+impl<'a> SimulatedSeller<'a> {
+    pub fn new(scenario: &'a Scenario, max_rounds: u32) -> Self {
+        Self { scenario, max_rounds }
+    }
+
+    /// The seller's ask after `round` counter offers.
+    pub fn ask(&self, round: u32) -> Result<Decimal> {
+        let elapsed = (round as f64 / self.max_rounds.max(1) as f64).min(1.0);
+        let concession = decimal_from_f64(elapsed.powf(1.0 / self.scenario.seller_beta))?;
+        let scenario = self.scenario;
+        Ok((scenario.list_price - (scenario.list_price - scenario.seller_reserve) * concession).round_dp(2))
+    }
+}
+
+/// A strategy in the benchmark.
+pub enum Contender {
+    /// A strategy under the name it was configured by
+    Strategy(String, Box<dyn NegotiationStrategy>),
+    Llm(LlmNegotiator),
+}
+
+impl Contender {
+    pub fn name(&self) -> String {
+        match self {
+            Self::Strategy(name, _) => name.clone(),
+            Self::Llm(llm) => format!("llm:{}", llm.config.model),
+        }
+    }
+
+    async fn decide(&self, context: &OfferContext) -> Result<Decision> {
+        match self {
+            Self::Strategy(_, strategy) => strategy.decide(context),
+            Self::Llm(llm) if context.round < context.max_rounds => {
+                let offer = llm.next_offer(context).await?;
+                strategy::decide(context, |_| Ok(offer))
+            }
+            Self::Llm(_) => strategy::decide(context, |context| Ok(context.reservation_price)),
+        }
+    }
+}
+
+/// Asks an OpenAI-compatible chat completions API for each counter offer.
+pub struct LlmNegotiator {
+    config: LLMConfig,
+    client: Client,
+}
+
+impl LlmNegotiator {
+    pub fn new(config: LLMConfig) -> Result<Self> {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(config.timeout_seconds.unwrap_or(30)))
+            .build()?;
+        Ok(Self { config, client })
+    }
+
+    async fn next_offer(&self, context: &OfferContext) -> Result<Decimal> {
+        let prompt = format!(
+            "You are a buyer negotiating a price. You would ideally pay {} and will pay at most {}. \
+             Round {} of {}. Seller asks so far: {:?}. Your counter offers so far: {:?}. \
+             Reply with your next counter offer as a number only.",
+            context.target_price, context.reservation_price, context.round + 1, context.max_rounds,
+            context.seller_offers, context.buyer_offers,
+        );
+        let mut request = self.client
+            .post(format!("{}/chat/completions", self.config.api_base.as_deref().unwrap_or(DEFAULT_LLM_API_BASE)))
+            .json(&serde_json::json!({
+                "model": self.config.model,
+                "messages": [{ "role": "user", "content": prompt }],
+                "max_tokens": self.config.max_tokens,
+                "temperature": self.config.temperature,
+            }));
+        if let Some(api_key) = &self.config.api_key {
+            request = request.bearer_auth(api_key);
+        }
+        let response: serde_json::Value = request.send().await?.error_for_status()?.json().await?;
+        let reply = response["choices"][0]["message"]["content"].as_str().unwrap_or_default();
+        parse_offer(reply)
+            .ok_or_else(|| NegotiationError::Negotiation(format!("Model {} replied without an offer: {}", self.config.model, reply)))
+    }
+}
+
+/// The first number in a model's reply, ignoring currency symbols and
+/// thousands separators.
+fn parse_offer(reply: &str) -> Option<Decimal> {
+    reply.split(|c: char| !(c.is_ascii_digit() || c == '.' || c == ','))
+        .map(|word| word.replace(',', ""))
+        .map(|word| word.trim_matches('.').to_string())
+        .find(|word| !word.is_empty())
+        .and_then(|word| word.parse().ok())
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunResult {
+    /// Price agreed, if the negotiation closed
+    pub price: Option<Decimal>,
+    pub rounds: u32,
+}
+
+/// Negotiates one scenario between `contender` and a simulated seller.
+pub async fn run(contender: &Contender, scenario: &Scenario, max_rounds: u32) -> Result<RunResult> {
+    let seller = SimulatedSeller::new(scenario, max_rounds);
+    let mut context = OfferContext {
+        round: 0,
+        max_rounds,
+        target_price: scenario.target_price,
+        reservation_price: scenario.reservation_price,
+        seller_offers: vec![seller.ask(0)?],
+        buyer_offers: vec![],
+        recent_price_change: None,
+    };
+
+    loop {
+        match contender.decide(&context).await? {
+            Decision::Accept => return Ok(RunResult { price: context.latest_ask(), rounds: context.round }),
+            Decision::Reject => return Ok(RunResult { price: None, rounds: context.round }),
+            Decision::Counter(offer) => {
+                let offer = offer.round_dp(2);
+                context.round += 1;
+                context.buyer_offers.push(offer);
+                let ask = seller.ask(context.round)?;
+                if offer >= ask {
+                    return Ok(RunResult { price: Some(offer), rounds: context.round });
+                }
+                context.seller_offers.push(ask);
+            }
+        }
+    }
+}
+
+/// How one contender did over every scenario.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StrategyReport {
+    pub strategy: String,
+    pub negotiations: usize,
+    pub closed: usize,
+    /// Closed deals among the negotiations that ran, from 0 to 1
+    pub close_rate: f64,
+    /// Buyer's reservation price minus the agreed price, as a percentage of
+    /// the reservation price, averaged over closed deals
+    pub average_surplus_percent: f64,
+    pub average_rounds: f64,
+    /// Negotiations that failed to run, such as model calls that errored
+    pub errors: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BenchReport {
+    pub seed: u64,
+    pub scenarios: usize,
+    pub max_rounds: u32,
+    pub strategies: Vec<StrategyReport>,
+}
+
+/// Runs every contender over the same scenarios.
+pub async fn bench(contenders: &[Contender], scenarios: &[Scenario], seed: u64, max_rounds: u32) -> BenchReport {
+    let mut strategies = Vec::with_capacity(contenders.len());
+    for contender in contenders {
+        let mut results = Vec::with_capacity(scenarios.len());
+        let mut errors = 0;
+        for scenario in scenarios {
+            match run(contender, scenario, max_rounds).await {
+                Ok(result) => results.push((scenario, result)),
+                Err(e) => {
+                    tracing::warn!("{} failed a benchmark negotiation: {}", contender.name(), e);
+                    errors += 1;
+                }
+            }
+        }
+        strategies.push(summarise(contender.name(), &results, errors));
+    }
+    BenchReport { seed, scenarios: scenarios.len(), max_rounds, strategies }
+}
+
+fn summarise(strategy: String, results: &[(&Scenario, RunResult)], errors: usize) -> StrategyReport {
+    let surpluses: Vec<f64> = results.iter()
+        .filter_map(|(scenario, result)| {
+            let price = result.price?;
+            ((scenario.reservation_price - price) / scenario.reservation_price * Decimal::from(100)).to_f64()
+        })
+        .collect();
+    let average = |values: &[f64]| if values.is_empty() { 0.0 } else { values.iter().sum::<f64>() / values.len() as f64 };
+    let rounds: Vec<f64> = results.iter().map(|(_, result)| result.rounds as f64).collect();
+
+    StrategyReport {
+        strategy,
+        negotiations: results.len(),
+        closed: surpluses.len(),
+        close_rate: if results.is_empty() { 0.0 } else { surpluses.len() as f64 / results.len() as f64 },
+        average_surplus_percent: average(&surpluses),
+        average_rounds: average(&rounds),
+        errors,
+    }
+}
+
+/// Contenders for strategy names as used on the command line, followed by
+/// one per language model.
+pub fn contenders(strategies: &[String], models: &[LLMConfig]) -> Result<Vec<Contender>> {
+    let mut contenders = strategies.iter()
+        .map(|name| Ok(Contender::Strategy(name.clone(), strategy_from_name(name)?)))
+        .collect::<Result<Vec<_>>>()?;
+    for model in models {
+        contenders.push(Contender::Llm(LlmNegotiator::new(model.clone())?));
+    }
+    Ok(contenders)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_bench_compares_strategies_on_the_same_scenarios() {
+        let scenarios = Scenario::generate(7, 50).unwrap();
+        assert_eq!(Scenario::generate(7, 50).unwrap()[0].list_price, scenarios[0].list_price);
+
+        let names = ["conceder", "boulware"].map(String::from);
+        let report = bench(&contenders(&names, &[]).unwrap(), &scenarios, 7, 10).await;
+        let [conceder, boulware] = &report.strategies[..] else { panic!("expected two reports") };
+        assert_eq!((conceder.negotiations, conceder.errors), (50, 0));
+        // Conceding early closes more deals, holding out keeps more surplus
+        assert!(conceder.close_rate >= boulware.close_rate);
+        assert!(boulware.average_surplus_percent > conceder.average_surplus_percent);
+        assert!(boulware.average_rounds > conceder.average_rounds);
+    }
+
+    #[tokio::test]
+    async fn test_seller_takes_counter_at_its_next_ask() {
+        let scenario = Scenario {
+            list_price: Decimal::from(100),
+            seller_reserve: Decimal::from(80),
+            target_price: Decimal::from(50),
+            reservation_price: Decimal::from(90),
+            seller_beta: 1.0,
+        };
+        assert_eq!(SimulatedSeller::new(&scenario, 4).ask(2).unwrap(), Decimal::from(90));
+
+        let linear = Contender::Strategy("linear".to_string(), strategy_from_name("linear").unwrap());
+        let result = run(&linear, &scenario, 4).await.unwrap();
+        assert_eq!(result, RunResult { price: Some(Decimal::from(80)), rounds: 4 });
+        assert_eq!(parse_offer("I offer $1,234.50."), Some(Decimal::new(123450, 2)));
+    }
+}
@@ -0,0 +1,223 @@
+//! Sales tax and VAT on quotes and payments.
+//!
+//! A [`TaxCalculator`] works out the taxes on a sale from the product's
+//! category and where it's delivered. Sellers add the line items to their
+//! quotes' metadata, buyers pay them on top of the quoted price, and the
+//! settlement service checks them against its own calculator before charging
+//! and records them with the payment.
+//!
+//! [`FlatRateTaxCalculator`] charges one rate per jurisdiction, or per
+//! category within it. Delivery locations are matched most specific first:
+//! `US-CA-SF` is taxed as `US-CA` if that's configured, otherwise as `US`.
+
+use crate::{
+    config::TaxConfig,
+    error::{NegotiationError, Result},
+    money::Money,
+};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Quote and payment metadata holding the tax line items, as JSON
+pub const TAX_METADATA_KEY: &str = "tax";
+/// Quote and payment metadata holding the category the sale was taxed as
+pub const TAX_CATEGORY_METADATA_KEY: &str = "tax_category";
+/// Quote and payment metadata holding where the goods are delivered
+pub const DELIVERY_LOCATION_METADATA_KEY: &str = "delivery_location";
+
+/// What a calculator may look at when taxing a sale.
+pub struct TaxContext<'a> {
+    pub category: &'a str,
+    pub delivery_location: Option<&'a str>,
+    /// Price before tax
+    pub amount: &'a Money,
+}
+
+impl<'a> TaxContext<'a> {
+    /// The sale described by the tax metadata of a quote or payment, if it
+    /// was taxed.
+    pub fn from_metadata(metadata: &'a HashMap<String, String>, amount: &'a Money) -> Option<Self> {
+        Some(Self {
+            category: metadata.get(TAX_CATEGORY_METADATA_KEY)?,
+            delivery_location: metadata.get(DELIVERY_LOCATION_METADATA_KEY).map(String::as_str),
+            amount,
+        })
+    }
+}
+
+pub trait TaxCalculator: Send + Sync {
+    fn calculate(&self, context: &TaxContext) -> Result<TaxBreakdown>;
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TaxLine {
+    /// e.g. "VAT"
+    pub name: String,
+    pub jurisdiction: String,
+    pub rate: Decimal,
+    pub amount: Decimal,
+}
+
+/// Taxes charged on a sale, line by line
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TaxBreakdown {
+    pub lines: Vec<TaxLine>,
+}
+
+impl TaxBreakdown {
+    pub fn total(&self) -> Decimal {
+        self.lines.iter().map(|line| line.amount).sum()
+    }
+
+    /// Records the line items and what they were calculated for in `metadata`.
+    pub fn write_metadata(&self, context: &TaxContext, metadata: &mut HashMap<String, String>) -> Result<()> {
+        metadata.insert(TAX_METADATA_KEY.to_string(), serde_json::to_string(self)?);
+        metadata.insert(TAX_CATEGORY_METADATA_KEY.to_string(), context.category.to_string());
+        if let Some(location) = context.delivery_location {
+            metadata.insert(DELIVERY_LOCATION_METADATA_KEY.to_string(), location.to_string());
+        }
+        Ok(())
+    }
+
+    /// The line items recorded in `metadata`, if any were.
+    pub fn from_metadata(metadata: &HashMap<String, String>) -> Result<Option<Self>> {
+        metadata.get(TAX_METADATA_KEY)
+            .map(|json| serde_json::from_str(json).map_err(|e| NegotiationError::Validation(format!("Invalid tax metadata: {}", e))))
+            .transpose()
+    }
+}
+
+/// Taxes sales at the configured rate for the delivery location and category.
+#[derive(Debug, Clone)]
+pub struct FlatRateTaxCalculator {
+    config: TaxConfig,
+}
+
+impl FlatRateTaxCalculator {
+    pub fn from_config(config: &TaxConfig) -> Result<Self> {
+        let rates = std::iter::once(config.rate).chain(config.jurisdictions.values().flat_map(|jurisdiction| {
+            std::iter::once(jurisdiction.rate).chain(jurisdiction.categories.values().copied())
+        }));
+        for rate in rates {
+            if rate < Decimal::ZERO || rate >= Decimal::ONE {
+                return Err(NegotiationError::Config(format!("Tax rate {} must be at least 0 and below 1", rate)));
+            }
+        }
+        let mut config = config.clone();
+        config.jurisdictions = config.jurisdictions.into_iter()
+            .map(|(name, jurisdiction)| (name.trim().to_uppercase(), jurisdiction))
+            .collect();
+        Ok(Self { config })
+    }
+
+    /// The configured jurisdiction covering `location`, most specific first.
+    fn jurisdiction(&self, location: &str) -> Option<&str> {
+        let location = location.trim().to_uppercase();
+        let mut candidate = location.as_str();
+        loop {
+            if let Some((name, _)) = self.config.jurisdictions.get_key_value(candidate) {
+                return Some(name);
+            }
+            candidate = &candidate[..candidate.rfind('-')?];
+        }
+    }
+}
+
+impl TaxCalculator for FlatRateTaxCalculator {
+    fn calculate(&self, context: &TaxContext) -> Result<TaxBreakdown> {
+        let (jurisdiction, name, rate) = match context.delivery_location.and_then(|location| self.jurisdiction(location)) {
+            Some(jurisdiction) => {
+                let config = &self.config.jurisdictions[jurisdiction];
+                let rate = config.categories.get(context.category).copied().unwrap_or(config.rate);
+                (jurisdiction, config.name.as_deref().unwrap_or(&self.config.name), rate)
+            }
+            None => ("default", self.config.name.as_str(), self.config.rate),
+        };
+        if rate.is_zero() {
+            return Ok(TaxBreakdown::default());
+        }
+
+        Ok(TaxBreakdown {
+            lines: vec![TaxLine {
+                name: name.to_string(),
+                jurisdiction: jurisdiction.to_string(),
+                rate,
+                amount: context.amount.times(rate).round_to_minor_units().amount,
+            }],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::JurisdictionTaxConfig;
+
+    /// VAT in Germany, with books taxed less, and sales tax in California
+    fn calculator() -> FlatRateTaxCalculator {
+        FlatRateTaxCalculator::from_config(&TaxConfig {
+            name: "Sales tax".to_string(),
+            rate: Decimal::ZERO,
+            jurisdictions: HashMap::from([
+                ("DE".to_string(), JurisdictionTaxConfig {
+                    name: Some("VAT".to_string()),
+                    rate: Decimal::new(19, 2),
+                    categories: HashMap::from([("books".to_string(), Decimal::new(7, 2))]),
+                }),
+                ("US-CA".to_string(), JurisdictionTaxConfig {
+                    name: None,
+                    rate: Decimal::new(725, 4),
+                    categories: HashMap::new(),
+                }),
+            ]),
+        }).unwrap()
+    }
+
+    fn tax(category: &str, delivery_location: Option<&str>) -> TaxBreakdown {
+        let amount = Money::new(Decimal::new(9999, 2), "EUR");
+        calculator().calculate(&TaxContext { category, delivery_location, amount: &amount }).unwrap()
+    }
+
+    #[test]
+    fn test_jurisdictions_tax_at_their_rate() {
+        let vat = tax("electronics", Some("de"));
+        assert_eq!(vat.lines[0].name, "VAT");
+        assert_eq!(vat.total(), Decimal::new(1900, 2));
+    }
+
+    #[test]
+    fn test_categories_override_the_jurisdictions_rate() {
+        assert_eq!(tax("books", Some("DE")).total(), Decimal::new(700, 2));
+    }
+
+    #[test]
+    fn test_locations_within_a_jurisdiction_take_its_tax_and_default_name() {
+        let sales_tax = tax("electronics", Some("US-CA-SF"));
+        assert_eq!((sales_tax.lines[0].jurisdiction.as_str(), sales_tax.lines[0].name.as_str()), ("US-CA", "Sales tax"));
+        assert_eq!(sales_tax.total(), Decimal::new(725, 2));
+    }
+
+    #[test]
+    fn test_other_and_unknown_locations_are_untaxed() {
+        assert!(tax("electronics", Some("US-NY")).lines.is_empty());
+        assert!(tax("electronics", None).lines.is_empty());
+    }
+
+    #[test]
+    fn test_breakdown_round_trips_through_metadata() {
+        let amount = Money::new(Decimal::new(9999, 2), "EUR");
+        let context = TaxContext { category: "electronics", delivery_location: Some("de"), amount: &amount };
+        let vat = calculator().calculate(&context).unwrap();
+
+        let mut metadata = HashMap::new();
+        vat.write_metadata(&context, &mut metadata).unwrap();
+        assert_eq!(TaxBreakdown::from_metadata(&metadata).unwrap(), Some(vat));
+        assert_eq!(TaxContext::from_metadata(&metadata, &amount).unwrap().delivery_location, Some("de"));
+    }
+
+    #[test]
+    fn test_rates_must_be_below_one() {
+        assert!(FlatRateTaxCalculator::from_config(&TaxConfig { rate: Decimal::ONE, ..TaxConfig::default() }).is_err());
+    }
+}
@@ -0,0 +1,185 @@
+//! Hierarchical product categories.
+//!
+//! A category is a path from a top-level category down, written with `>`
+//! between levels: `Electronics > Laptops`. The registry rewrites the
+//! categories sellers list products under into full paths when it knows
+//! them from `[taxonomy]`, so a seller listing under `Laptops` and one
+//! listing under `electronics>laptops` end up in the same place. Categories
+//! outside the taxonomy are kept as the seller wrote them, tidied.
+//!
+//! A category matches the paths it appears in as a run of levels: searching
+//! `Electronics` finds `Electronics > Laptops > Gaming`, and so does
+//! `Laptops` or `Laptops > Gaming`, but not `Electronics > Phones`.
+
+use crate::{
+    config::TaxonomyConfig,
+    demand::normalize_category,
+    error::{NegotiationError, Result},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Written between the levels of a category path
+pub const CATEGORY_SEPARATOR: char = '>';
+
+/// A category and the one it falls under, as listed in `[taxonomy]`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CategoryNode {
+    pub name: String,
+    /// None for a top-level category
+    #[serde(default)]
+    pub parent: Option<String>,
+}
+
+/// A category with the categories under it, as served to clients
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CategoryTree {
+    pub name: String,
+    /// Full path of the category
+    pub path: String,
+    pub children: Vec<CategoryTree>,
+}
+
+/// The categories the registry knows, by their normalized name
+#[derive(Debug, Clone, Default)]
+pub struct CategoryTaxonomy {
+    paths: HashMap<String, Vec<String>>,
+}
+
+impl CategoryTaxonomy {
+    /// Fails on a category listed twice, a parent that isn't listed, or a
+    /// category that falls under itself.
+    pub fn new(config: &TaxonomyConfig) -> Result<Self> {
+        let mut parents: HashMap<String, &CategoryNode> = HashMap::new();
+        for node in &config.categories {
+            let key = normalize_category(&node.name);
+            if key.is_empty() || key.contains(CATEGORY_SEPARATOR) {
+                return Err(NegotiationError::Config(format!("Invalid taxonomy category: {:?}", node.name)));
+            }
+            if parents.insert(key, node).is_some() {
+                return Err(NegotiationError::Config(format!("Taxonomy category {} is listed twice", node.name)));
+            }
+        }
+
+        let mut paths = HashMap::new();
+        for (key, node) in &parents {
+            let mut path = vec![node.name.trim().to_string()];
+            let mut parent = node.parent.as_deref();
+            while let Some(name) = parent {
+                let parent_node = parents.get(&normalize_category(name)).ok_or_else(|| {
+                    NegotiationError::Config(format!("Taxonomy category {} has unknown parent {}", node.name, name))
+                })?;
+                if path.len() > parents.len() {
+                    return Err(NegotiationError::Config(format!("Taxonomy category {} falls under itself", node.name)));
+                }
+                path.insert(0, parent_node.name.trim().to_string());
+                parent = parent_node.parent.as_deref();
+            }
+            paths.insert(key.clone(), path);
+        }
+        Ok(Self { paths })
+    }
+
+    /// The full path of a category the taxonomy knows, found by its last
+    /// level; anything else tidied into `A > B` form.
+    pub fn normalize(&self, category: &str) -> String {
+        let levels = category_levels(category);
+        let known = levels.last().and_then(|level| self.paths.get(&normalize_category(level)));
+        match known {
+            Some(path) => path.join(" > "),
+            None => levels.join(" > "),
+        }
+    }
+
+    /// The taxonomy's top-level categories with everything under them, by name.
+    pub fn tree(&self) -> Vec<CategoryTree> {
+        let mut paths: Vec<&Vec<String>> = self.paths.values().collect();
+        paths.sort_by_key(|path| path.len());
+        let mut roots: Vec<CategoryTree> = Vec::new();
+        for path in paths {
+            let mut level = &mut roots;
+            for depth in 0..path.len() {
+                let position = match level.iter().position(|node| node.name == path[depth]) {
+                    Some(position) => position,
+                    None => {
+                        level.push(CategoryTree {
+                            name: path[depth].clone(),
+                            path: path[..=depth].join(" > "),
+                            children: Vec::new(),
+                        });
+                        level.sort_by(|a, b| a.name.cmp(&b.name));
+                        level.iter().position(|node| node.name == path[depth]).unwrap_or_default()
+                    }
+                };
+                level = &mut level[position].children;
+            }
+        }
+        roots
+    }
+}
+
+/// Whether `category` is `wanted` or falls under it; both may be partial
+/// paths. Compared case-insensitively.
+pub fn category_matches(category: &str, wanted: &str) -> bool {
+    let category: Vec<String> = category_levels(category).iter().map(|level| normalize_category(level)).collect();
+    let wanted: Vec<String> = category_levels(wanted).iter().map(|level| normalize_category(level)).collect();
+    !wanted.is_empty() && category.windows(wanted.len()).any(|levels| levels == wanted.as_slice())
+}
+
+fn category_levels(category: &str) -> Vec<&str> {
+    category.split(CATEGORY_SEPARATOR)
+        .map(str::trim)
+        .filter(|level| !level.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(name: &str, parent: Option<&str>) -> CategoryNode {
+        CategoryNode { name: name.to_string(), parent: parent.map(str::to_string) }
+    }
+
+    #[test]
+    fn test_normalize_and_match_category_paths() {
+        let taxonomy = CategoryTaxonomy::new(&TaxonomyConfig {
+            categories: vec![
+                node("Electronics", None),
+                node("Laptops", Some("Electronics")),
+                node("Gaming Laptops", Some("laptops")),
+                node("Phones", Some("Electronics")),
+            ],
+        }).unwrap();
+
+        assert_eq!(taxonomy.normalize(" laptops "), "Electronics > Laptops");
+        assert_eq!(taxonomy.normalize("electronics>gaming laptops"), "Electronics > Laptops > Gaming Laptops");
+        assert_eq!(taxonomy.normalize("Home >  Kitchen"), "Home > Kitchen");
+
+        let gaming = taxonomy.normalize("Gaming Laptops");
+        assert!(category_matches(&gaming, "Electronics"));
+        assert!(category_matches(&gaming, "laptops"));
+        assert!(category_matches(&gaming, "Electronics > Laptops"));
+        assert!(!category_matches(&gaming, "Phones"));
+        assert!(!category_matches(&gaming, "Electronics > Gaming Laptops"));
+        assert!(!category_matches("Laptops", "Electronics > Laptops"));
+        assert!(!category_matches(&gaming, " > "));
+
+        let tree = taxonomy.tree();
+        assert_eq!(tree.len(), 1);
+        assert_eq!(tree[0].children.iter().map(|child| child.path.as_str()).collect::<Vec<_>>(), ["Electronics > Laptops", "Electronics > Phones"]);
+        assert_eq!(tree[0].children[0].children[0].name, "Gaming Laptops");
+    }
+
+    #[test]
+    fn test_invalid_taxonomies_are_rejected() {
+        for categories in [
+            vec![node("Laptops", Some("Electronics"))],
+            vec![node("Laptops", None), node("laptops", None)],
+            vec![node("A", Some("B")), node("B", Some("A"))],
+            vec![node("A > B", None)],
+        ] {
+            assert!(CategoryTaxonomy::new(&TaxonomyConfig { categories }).is_err());
+        }
+    }
+}
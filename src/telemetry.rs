@@ -0,0 +1,175 @@
+//! Distributed tracing.
+//!
+//! Services log through `tracing` and, with `[logging] otlp_endpoint` set,
+//! export their spans over OTLP/HTTP to a collector such as Jaeger. Trace
+//! context travels between services in W3C `traceparent` headers: agents add
+//! [`trace_headers`] to the requests they send, and routers wrapped with
+//! [`trace_requests`] continue the caller's trace, so one negotiation can be
+//! followed from the buyer through the seller to settlement.
+
+use crate::{
+    config::LoggingConfig,
+    error::{NegotiationError, Result},
+};
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, MatchedPath, Request},
+    http::{request::Parts, HeaderMap},
+    middleware::{self, Next},
+    response::Response,
+    Router,
+};
+use opentelemetry::{global, trace::TracerProvider as _, Context, KeyValue};
+use opentelemetry_http::{HeaderExtractor, HeaderInjector};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{
+    propagation::TraceContextPropagator,
+    runtime,
+    trace::{Sampler, TracerProvider},
+    Resource,
+};
+use std::convert::Infallible;
+use tracing::Instrument;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+
+/// Flushes spans still waiting to be exported when dropped, so hold it for
+/// the life of the service.
+pub struct Telemetry {
+    provider: Option<TracerProvider>,
+}
+
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        if let Some(provider) = self.provider.take() {
+            if let Err(e) = provider.shutdown() {
+                eprintln!("Failed to flush spans: {}", e);
+            }
+        }
+    }
+}
+
+/// Installs the global subscriber for `service_name`, logging at
+/// `config.level` unless `RUST_LOG` says otherwise.
+pub fn init(service_name: &str, config: &LoggingConfig) -> Result<Telemetry> {
+    global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let filter = EnvFilter::try_from_default_env()
+        .or_else(|_| EnvFilter::try_new(&config.level))
+        .map_err(|e| NegotiationError::Config(format!("Invalid log level: {}", e)))?;
+    let provider = config.otlp_endpoint.as_deref()
+        .map(|endpoint| tracer_provider(service_name, endpoint, config.trace_sample_ratio))
+        .transpose()?;
+    let otel = provider.as_ref()
+        .map(|provider| tracing_opentelemetry::layer().with_tracer(provider.tracer(service_name.to_string())));
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer())
+        .with(otel)
+        .try_init()
+        .map_err(|e| NegotiationError::Config(format!("Failed to install tracing subscriber: {}", e)))?;
+
+    Ok(Telemetry { provider })
+}
+
+fn tracer_provider(service_name: &str, endpoint: &str, sample_ratio: Option<f64>) -> Result<TracerProvider> {
+    let sample_ratio = sample_ratio.unwrap_or(1.0);
+    if !(0.0..=1.0).contains(&sample_ratio) {
+        return Err(NegotiationError::Config("trace_sample_ratio must be between 0.0 and 1.0".to_string()));
+    }
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .with_endpoint(endpoint)
+        .build()
+        .map_err(|e| NegotiationError::Config(format!("Failed to create OTLP exporter: {}", e)))?;
+
+    Ok(TracerProvider::builder()
+        .with_batch_exporter(exporter, runtime::Tokio)
+        // Keep or drop a trace as a whole, as its first service decided
+        .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(sample_ratio))))
+        .with_resource(Resource::new([KeyValue::new("service.name", service_name.to_string())]))
+        .build())
+}
+
+/// Trace context of the current span, to send along with an outgoing request.
+/// Empty when spans aren't being exported.
+pub fn trace_headers() -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    let context = tracing::Span::current().context();
+    global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&context, &mut HeaderInjector(&mut headers))
+    });
+    headers
+}
+
+/// The caller's trace context, from the request's `traceparent` header.
+pub struct RemoteContext(pub Context);
+
+impl RemoteContext {
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        Self(global::get_text_map_propagator(|propagator| propagator.extract(&HeaderExtractor(headers))))
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for RemoteContext {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> std::result::Result<Self, Self::Rejection> {
+        Ok(Self::from_headers(&parts.headers))
+    }
+}
+
+/// Serves every request of `router` in a span that continues the caller's
+/// trace.
+pub fn trace_requests(router: Router) -> Router {
+    router.route_layer(middleware::from_fn(continue_trace))
+}
+
+async fn continue_trace(RemoteContext(parent): RemoteContext, request: Request, next: Next) -> Response {
+    let path = request.extensions().get::<MatchedPath>()
+        .map_or_else(String::new, |path| path.as_str().to_string());
+    let span = tracing::info_span!(
+        "request",
+        otel.name = %format!("{} {}", request.method(), path),
+        otel.kind = "server",
+        http.method = %request.method(),
+        http.route = %path,
+        http.status_code = tracing::field::Empty,
+    );
+    span.set_parent(parent);
+
+    let response = next.run(request).instrument(span.clone()).await;
+    span.record("http.status_code", response.status().as_u16());
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::TraceContextExt;
+
+    #[test]
+    fn test_trace_context_round_trips_through_headers() {
+        global::set_text_map_propagator(TraceContextPropagator::new());
+        let provider = TracerProvider::builder().build();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+
+        tracing::subscriber::with_default(subscriber, || {
+            // Nothing to propagate outside a span
+            assert!(trace_headers().is_empty());
+
+            let span = tracing::info_span!("negotiate");
+            let _entered = span.enter();
+            let headers = trace_headers();
+            assert!(headers.contains_key("traceparent"));
+
+            let RemoteContext(remote) = RemoteContext::from_headers(&headers);
+            let remote_span = remote.span();
+            assert!(remote_span.span_context().is_remote());
+            assert_eq!(remote_span.span_context().trace_id(), span.context().span().span_context().trace_id());
+        });
+    }
+}
@@ -0,0 +1,242 @@
+//! Message templates for routine seller communications.
+//!
+//! Order confirmations, refusals and shipping notices say the same thing every
+//! time, so sellers write them from a catalog of templates instead of asking
+//! an LLM. Each kind of message has a built-in template per language, which
+//! `[templates]` can replace:
+//!
+//! ```toml
+//! [templates.out_of_stock]
+//! en = "Sorry, {product_id} is sold out. {seller} will restock soon."
+//! de = "{product_id} ist leider ausverkauft."
+//! ```
+//!
+//! Templates name variables in braces; each kind takes the variables listed
+//! by [`TemplateKind::variables`], and a template using any other is refused
+//! when the catalog is built. As with the rest of the negotiation's text,
+//! prices, quantities and product IDs are substituted as given, never
+//! translated.
+
+use crate::{
+    config::TemplatesConfig,
+    error::{NegotiationError, Result},
+    language::Language,
+    model::{MessageType, NegotiationMessage},
+    AgentId, TransactionId,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TemplateKind {
+    /// The seller confirms an order once the buyer's deal settles
+    Acceptance,
+    /// A counter offer below what the seller will take
+    PriceTooLow,
+    /// Not enough stock for the quantity asked
+    OutOfStock,
+    /// The buyer's reputation is below the seller's threshold
+    LowReputation,
+    /// The order is on its way
+    Shipped,
+}
+
+impl TemplateKind {
+    pub const ALL: [TemplateKind; 5] = [
+        TemplateKind::Acceptance,
+        TemplateKind::PriceTooLow,
+        TemplateKind::OutOfStock,
+        TemplateKind::LowReputation,
+        TemplateKind::Shipped,
+    ];
+
+    /// Variables a template of this kind can use
+    pub fn variables(&self) -> &'static [&'static str] {
+        match self {
+            TemplateKind::Acceptance => &["seller", "reference"],
+            TemplateKind::PriceTooLow => &["seller", "product_id", "price"],
+            TemplateKind::OutOfStock => &["seller", "product_id", "quantity"],
+            TemplateKind::LowReputation => &["seller"],
+            TemplateKind::Shipped => &["seller", "reference", "carrier", "tracking_number"],
+        }
+    }
+
+    pub fn message_type(&self) -> MessageType {
+        match self {
+            TemplateKind::Acceptance => MessageType::Accept,
+            TemplateKind::PriceTooLow | TemplateKind::OutOfStock | TemplateKind::LowReputation => MessageType::Reject,
+            TemplateKind::Shipped => MessageType::Info,
+        }
+    }
+
+    fn built_in(&self, language: Language) -> &'static str {
+        match (self, language) {
+            (TemplateKind::Acceptance, Language::En) => "{seller} confirms your order {reference}. Thank you for your business.",
+            (TemplateKind::Acceptance, Language::De) => "{seller} bestätigt Ihre Bestellung {reference}. Vielen Dank für Ihren Auftrag.",
+            (TemplateKind::Acceptance, Language::Fr) => "{seller} confirme votre commande {reference}. Merci de votre confiance.",
+            (TemplateKind::Acceptance, Language::Es) => "{seller} confirma su pedido {reference}. Gracias por su compra.",
+            (TemplateKind::Acceptance, Language::Pt) => "{seller} confirma o seu pedido {reference}. Obrigado pela preferência.",
+            (TemplateKind::Acceptance, Language::Ja) => "{seller} はご注文 {reference} を承りました。ありがとうございます。",
+            (TemplateKind::PriceTooLow, Language::En) => "{seller} can't accept {price} for {product_id}.",
+            (TemplateKind::PriceTooLow, Language::De) => "{seller} kann {price} für {product_id} nicht annehmen.",
+            (TemplateKind::PriceTooLow, Language::Fr) => "{seller} ne peut pas accepter {price} pour {product_id}.",
+            (TemplateKind::PriceTooLow, Language::Es) => "{seller} no puede aceptar {price} por {product_id}.",
+            (TemplateKind::PriceTooLow, Language::Pt) => "{seller} não pode aceitar {price} por {product_id}.",
+            (TemplateKind::PriceTooLow, Language::Ja) => "{seller} は {product_id} の {price} をお受けできません。",
+            (TemplateKind::OutOfStock, Language::En) => "{seller} doesn't have {quantity} x {product_id} in stock.",
+            (TemplateKind::OutOfStock, Language::De) => "{seller} hat {quantity} x {product_id} nicht auf Lager.",
+            (TemplateKind::OutOfStock, Language::Fr) => "{seller} n'a pas {quantity} x {product_id} en stock.",
+            (TemplateKind::OutOfStock, Language::Es) => "{seller} no tiene {quantity} x {product_id} en existencias.",
+            (TemplateKind::OutOfStock, Language::Pt) => "{seller} não tem {quantity} x {product_id} em estoque.",
+            (TemplateKind::OutOfStock, Language::Ja) => "{seller} には {product_id} x {quantity} の在庫がありません。",
+            (TemplateKind::LowReputation, Language::En) => "{seller} can't trade with buyers below its reputation threshold.",
+            (TemplateKind::LowReputation, Language::De) => "{seller} handelt nicht mit Käufern unterhalb seiner Reputationsschwelle.",
+            (TemplateKind::LowReputation, Language::Fr) => "{seller} ne traite pas avec les acheteurs sous son seuil de réputation.",
+            (TemplateKind::LowReputation, Language::Es) => "{seller} no negocia con compradores por debajo de su umbral de reputación.",
+            (TemplateKind::LowReputation, Language::Pt) => "{seller} não negocia com compradores abaixo do seu limite de reputação.",
+            (TemplateKind::LowReputation, Language::Ja) => "{seller} は評価が基準に満たない購入者とは取引できません。",
+            (TemplateKind::Shipped, Language::En) => "Order {reference} has shipped with {carrier}, tracking number {tracking_number}.",
+            (TemplateKind::Shipped, Language::De) => "Bestellung {reference} wurde mit {carrier} versandt, Sendungsnummer {tracking_number}.",
+            (TemplateKind::Shipped, Language::Fr) => "La commande {reference} a été expédiée par {carrier}, numéro de suivi {tracking_number}.",
+            (TemplateKind::Shipped, Language::Es) => "El pedido {reference} se envió con {carrier}, número de seguimiento {tracking_number}.",
+            (TemplateKind::Shipped, Language::Pt) => "O pedido {reference} foi enviado pela {carrier}, código de rastreio {tracking_number}.",
+            (TemplateKind::Shipped, Language::Ja) => "ご注文 {reference} は {carrier} で発送されました。追跡番号 {tracking_number}。",
+        }
+    }
+}
+
+/// The built-in templates with `[templates]` overrides on top
+#[derive(Debug, Clone, Default)]
+pub struct MessageTemplates {
+    overrides: HashMap<(TemplateKind, Language), String>,
+}
+
+impl MessageTemplates {
+    /// Fails on an override using a variable its kind doesn't take.
+    pub fn new(config: &TemplatesConfig) -> Result<Self> {
+        let mut overrides = HashMap::new();
+        for (kind, templates) in &config.overrides {
+            for (language, template) in templates {
+                for name in placeholders(template)? {
+                    if !kind.variables().contains(&name) {
+                        return Err(NegotiationError::Config(format!(
+                            "Template {:?} ({}) uses unknown variable {{{}}}", kind, language, name
+                        )));
+                    }
+                }
+                overrides.insert((*kind, *language), template.clone());
+            }
+        }
+        Ok(Self { overrides })
+    }
+
+    pub fn template(&self, kind: TemplateKind, language: Language) -> &str {
+        self.overrides.get(&(kind, language))
+            .map(String::as_str)
+            .unwrap_or_else(|| kind.built_in(language))
+    }
+
+    /// The template with `variables` filled in. Every variable the template
+    /// uses must be given.
+    pub fn render(&self, kind: TemplateKind, language: Language, variables: &[(&str, String)]) -> Result<String> {
+        let template = self.template(kind, language);
+        let mut rendered = String::with_capacity(template.len());
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            let end = rest[start..].find('}').map(|end| start + end)
+                .ok_or_else(|| NegotiationError::Config(format!("Unclosed variable in template {:?}", kind)))?;
+            let name = &rest[start + 1..end];
+            let value = variables.iter()
+                .find(|(variable, _)| *variable == name)
+                .map(|(_, value)| value)
+                .ok_or_else(|| NegotiationError::Validation(format!("Template {:?} needs {{{}}}", kind, name)))?;
+            rendered.push_str(&rest[..start]);
+            rendered.push_str(value);
+            rest = &rest[end + 1..];
+        }
+        rendered.push_str(rest);
+        Ok(rendered)
+    }
+
+    /// A negotiation message rendered from the template, typed to suit it.
+    pub fn message(
+        &self,
+        kind: TemplateKind,
+        language: Language,
+        negotiation_id: TransactionId,
+        sender_id: AgentId,
+        variables: &[(&str, String)],
+        at: DateTime<Utc>,
+    ) -> Result<NegotiationMessage> {
+        Ok(NegotiationMessage {
+            id: uuid::Uuid::new_v4(),
+            negotiation_id,
+            sender_id,
+            content: self.render(kind, language, variables)?,
+            message_type: kind.message_type(),
+            created_at: at,
+        })
+    }
+}
+
+/// Names of the variables a template uses, in order.
+fn placeholders(template: &str) -> Result<Vec<&str>> {
+    let mut names = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let end = rest[start..].find('}').map(|end| start + end)
+            .ok_or_else(|| NegotiationError::Config(format!("Unclosed variable in template {:?}", template)))?;
+        names.push(&rest[start + 1..end]);
+        rest = &rest[end + 1..];
+    }
+    Ok(names)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_renders_built_in_and_configured_templates() {
+        let config: TemplatesConfig = toml::from_str(r#"
+            [out_of_stock]
+            en = "Sorry, {product_id} is sold out."
+        "#).unwrap();
+        let templates = MessageTemplates::new(&config).unwrap();
+        let variables = [
+            ("seller", "TechStore".to_string()),
+            ("product_id", "laptop-001".to_string()),
+            ("quantity", "3".to_string()),
+        ];
+
+        assert_eq!(templates.render(TemplateKind::OutOfStock, Language::En, &variables).unwrap(), "Sorry, laptop-001 is sold out.");
+        assert_eq!(
+            templates.render(TemplateKind::OutOfStock, Language::De, &variables).unwrap(),
+            "TechStore hat 3 x laptop-001 nicht auf Lager."
+        );
+        assert!(templates.render(TemplateKind::Shipped, Language::En, &variables).is_err());
+
+        let message = templates.message(
+            TemplateKind::Acceptance,
+            Language::Fr,
+            uuid::Uuid::new_v4(),
+            uuid::Uuid::new_v4(),
+            &[("seller", "TechStore".to_string()), ("reference", "A-17".to_string())],
+            Utc::now(),
+        ).unwrap();
+        assert_eq!(message.content, "TechStore confirme votre commande A-17. Merci de votre confiance.");
+        assert!(matches!(message.message_type, MessageType::Accept));
+
+        // Every built-in template only uses its kind's variables
+        for kind in TemplateKind::ALL {
+            for language in [Language::En, Language::De, Language::Fr, Language::Es, Language::Pt, Language::Ja] {
+                assert!(placeholders(kind.built_in(language)).unwrap().iter().all(|name| kind.variables().contains(name)));
+            }
+        }
+
+        let unknown: TemplatesConfig = toml::from_str("[shipped]\nen = \"Shipped {price}\"").unwrap();
+        assert!(MessageTemplates::new(&unknown).is_err());
+    }
+}
@@ -0,0 +1,307 @@
+//! Mutual TLS between agents, with certificate pinning.
+//!
+//! A service with `[server.tls]` serves HTTPS and presents the same
+//! certificate as a client on its outbound calls. Client certificates are
+//! accepted when they're pinned, when they chain to `client_ca_path`, or,
+//! with neither configured, whoever signed them; agents usually run on
+//! self-signed certificates (`generate`), so the handshake proves the caller
+//! holds the key and the fingerprint says which key it was. Handlers find it
+//! as a [`PeerCertificate`] request extension.
+//!
+//! Agents register their certificate's fingerprint with discovery, so a
+//! counterparty can pin it: once a host has a pinned fingerprint, calls to
+//! it only go through when the server presents that certificate. Hosts with
+//! nothing pinned are checked against the web PKI roots as usual. Pins are
+//! kept per host, so agents sharing a host accept each other's certificates.
+
+use crate::{
+    config::TlsConfig,
+    error::{NegotiationError, Result},
+};
+use parking_lot::RwLock;
+use rustls::{
+    client::{
+        danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+        WebPkiServerVerifier,
+    },
+    crypto::{ring, verify_tls12_signature, verify_tls13_signature, WebPkiSupportedAlgorithms},
+    pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer, ServerName, UnixTime},
+    server::{
+        danger::{ClientCertVerified, ClientCertVerifier},
+        WebPkiClientVerifier,
+    },
+    CertificateError, ClientConfig, DigitallySignedStruct, DistinguishedName, RootCertStore, ServerConfig, SignatureScheme,
+};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+    sync::Arc,
+};
+
+/// Fingerprint of the certificate a client connected with
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeerCertificate {
+    pub fingerprint: String,
+}
+
+/// Lowercase hex SHA-256 of a DER certificate
+pub fn fingerprint(certificate: &[u8]) -> String {
+    hex::encode(Sha256::digest(certificate))
+}
+
+/// Accepts fingerprints written with colons or in upper case.
+pub fn normalize_fingerprint(fingerprint: &str) -> String {
+    fingerprint.trim().replace(':', "").to_ascii_lowercase()
+}
+
+/// A certificate chain and its key
+pub struct TlsIdentity {
+    certificates: Vec<CertificateDer<'static>>,
+    key: PrivateKeyDer<'static>,
+}
+
+impl TlsIdentity {
+    /// Reads the configured certificate and key, first writing a self-signed
+    /// pair when `generate` is set and they don't exist.
+    pub fn load(config: &TlsConfig) -> Result<Self> {
+        if config.generate && !Path::new(&config.cert_path).exists() {
+            generate(config)?;
+        }
+        let certificates = read_certificates(&config.cert_path)?;
+        if certificates.is_empty() {
+            return Err(NegotiationError::Config(format!("No certificates in {}", config.cert_path)));
+        }
+        let key = PrivateKeyDer::from_pem_file(&config.key_path)
+            .map_err(|e| NegotiationError::Config(format!("Failed to read TLS key {}: {}", config.key_path, e)))?;
+        Ok(Self { certificates, key })
+    }
+
+    /// Fingerprint of the leaf certificate, as registered with discovery
+    pub fn fingerprint(&self) -> String {
+        fingerprint(&self.certificates[0])
+    }
+
+    pub fn server_config(&self, config: &TlsConfig) -> Result<ServerConfig> {
+        let provider = Arc::new(ring::default_provider());
+        let ca = match &config.client_ca_path {
+            Some(path) => {
+                let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(root_store(path)?), provider.clone())
+                    .allow_unauthenticated()
+                    .build()
+                    .map_err(|e| NegotiationError::Config(format!("Invalid client CA {}: {}", path, e)))?;
+                Some(verifier)
+            }
+            None => None,
+        };
+        let verifier = ClientCertificates {
+            ca,
+            pinned: pinned(config),
+            required: config.require_client_cert,
+            algorithms: provider.signature_verification_algorithms,
+        };
+        let mut server = ServerConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .map_err(tls_error)?
+            .with_client_cert_verifier(Arc::new(verifier))
+            .with_single_cert(self.certificates.clone(), self.key.clone_key())
+            .map_err(tls_error)?;
+        server.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        Ok(server)
+    }
+
+    /// Presents this identity to servers, trusting those pinned in `pins`
+    /// or in the config, and the web PKI for the rest.
+    pub fn client_config(&self, config: &TlsConfig, pins: CertificatePins) -> Result<ClientConfig> {
+        let provider = Arc::new(ring::default_provider());
+        let roots = RootCertStore { roots: webpki_roots::TLS_SERVER_ROOTS.to_vec() };
+        let webpki = WebPkiServerVerifier::builder_with_provider(Arc::new(roots), provider.clone())
+            .build()
+            .map_err(|e| NegotiationError::Config(format!("Failed to load web PKI roots: {}", e)))?;
+        let verifier = PinnedServers { pins, pinned: pinned(config), webpki, algorithms: provider.signature_verification_algorithms };
+        ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .map_err(tls_error)?
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(verifier))
+            .with_client_auth_cert(self.certificates.clone(), self.key.clone_key())
+            .map_err(tls_error)
+    }
+}
+
+/// Fingerprints pinned for the hosts agents are reached at, learned from
+/// discovery. Clones share the pins.
+#[derive(Debug, Clone, Default)]
+pub struct CertificatePins {
+    hosts: Arc<RwLock<HashMap<String, HashSet<String>>>>,
+}
+
+impl CertificatePins {
+    /// Pins `fingerprint` for the host of `endpoint`.
+    pub fn pin(&self, endpoint: &str, fingerprint: &str) -> Result<()> {
+        let url = reqwest::Url::parse(endpoint)
+            .map_err(|e| NegotiationError::Validation(format!("Invalid endpoint {}: {}", endpoint, e)))?;
+        let host = url.host_str()
+            .ok_or_else(|| NegotiationError::Validation(format!("Endpoint {} has no host", endpoint)))?;
+        self.hosts.write()
+            .entry(host.trim_matches(['[', ']']).to_ascii_lowercase())
+            .or_default()
+            .insert(normalize_fingerprint(fingerprint));
+        Ok(())
+    }
+
+    fn for_host(&self, host: &str) -> Option<HashSet<String>> {
+        self.hosts.read().get(&host.to_ascii_lowercase()).cloned()
+    }
+}
+
+#[derive(Debug)]
+struct ClientCertificates {
+    ca: Option<Arc<dyn ClientCertVerifier>>,
+    pinned: HashSet<String>,
+    required: bool,
+    algorithms: WebPkiSupportedAlgorithms,
+}
+
+impl ClientCertVerifier for ClientCertificates {
+    fn client_auth_mandatory(&self) -> bool {
+        self.required
+    }
+
+    fn root_hint_subjects(&self) -> &[DistinguishedName] {
+        self.ca.as_ref().map_or(&[], |ca| ca.root_hint_subjects())
+    }
+
+    fn verify_client_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        now: UnixTime,
+    ) -> std::result::Result<ClientCertVerified, rustls::Error> {
+        if self.pinned.contains(&fingerprint(end_entity)) {
+            return Ok(ClientCertVerified::assertion());
+        }
+        match &self.ca {
+            Some(ca) => ca.verify_client_cert(end_entity, intermediates, now),
+            None if self.pinned.is_empty() => Ok(ClientCertVerified::assertion()),
+            None => Err(rustls::Error::InvalidCertificate(CertificateError::ApplicationVerificationFailure)),
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.algorithms.supported_schemes()
+    }
+}
+
+#[derive(Debug)]
+struct PinnedServers {
+    pins: CertificatePins,
+    pinned: HashSet<String>,
+    webpki: Arc<WebPkiServerVerifier>,
+    algorithms: WebPkiSupportedAlgorithms,
+}
+
+impl ServerCertVerifier for PinnedServers {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> std::result::Result<ServerCertVerified, rustls::Error> {
+        let presented = fingerprint(end_entity);
+        if self.pinned.contains(&presented) {
+            return Ok(ServerCertVerified::assertion());
+        }
+        match self.pins.for_host(&server_name.to_str()) {
+            Some(pins) if pins.contains(&presented) => Ok(ServerCertVerified::assertion()),
+            Some(_) => {
+                tracing::warn!("{} presented certificate {}, which isn't pinned for it", server_name.to_str(), presented);
+                Err(rustls::Error::InvalidCertificate(CertificateError::ApplicationVerificationFailure))
+            }
+            None => self.webpki.verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now),
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.algorithms.supported_schemes()
+    }
+}
+
+fn generate(config: &TlsConfig) -> Result<()> {
+    let hostnames = if config.hostnames.is_empty() { vec!["localhost".to_string()] } else { config.hostnames.clone() };
+    let generated = rcgen::generate_simple_self_signed(hostnames)
+        .map_err(|e| NegotiationError::Config(format!("Failed to generate a TLS certificate: {}", e)))?;
+    let write = |path: &str, contents: String| {
+        std::fs::write(path, contents).map_err(|e| NegotiationError::Io(format!("Failed to write {}: {}", path, e)))
+    };
+    write(&config.key_path, generated.key_pair.serialize_pem())?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&config.key_path, std::fs::Permissions::from_mode(0o600))
+            .map_err(|e| NegotiationError::Io(format!("Failed to restrict {}: {}", config.key_path, e)))?;
+    }
+    write(&config.cert_path, generated.cert.pem())?;
+    tracing::info!("Generated self-signed TLS certificate {}", config.cert_path);
+    Ok(())
+}
+
+fn read_certificates(path: &str) -> Result<Vec<CertificateDer<'static>>> {
+    CertificateDer::pem_file_iter(path)
+        .and_then(|certificates| certificates.collect::<std::result::Result<Vec<_>, _>>())
+        .map_err(|e| NegotiationError::Config(format!("Failed to read certificates {}: {}", path, e)))
+}
+
+fn root_store(path: &str) -> Result<RootCertStore> {
+    let mut roots = RootCertStore::empty();
+    for certificate in read_certificates(path)? {
+        roots.add(certificate).map_err(tls_error)?;
+    }
+    Ok(roots)
+}
+
+fn pinned(config: &TlsConfig) -> HashSet<String> {
+    config.pinned_fingerprints.iter().map(|fingerprint| normalize_fingerprint(fingerprint)).collect()
+}
+
+fn tls_error(e: rustls::Error) -> NegotiationError {
+    NegotiationError::Config(format!("Invalid TLS setup: {}", e))
+}
@@ -1,25 +1,177 @@
 use crate::{
+    blocklist::{AgentBlock, BlockList},
+    config::TrustConfig,
+    delegation::{DelegationScope, DelegationTokens},
     error::{NegotiationError, Result},
-    AgentId,
+    gossip::ReputationAttestation,
+    http::HttpClient,
+    jwt_keys::{self, JwtKeyring},
+    reputation_snapshot::{ReputationSnapshot, SnapshotContents, SnapshotImport},
+    responsiveness::{ResponseKind, ResponseStats},
+    roles::{Permission, Role, RolePolicy},
+    session::{SessionTokens, TokenSubjects},
+    shared_state::SharedState,
+    AgentId, TransactionId,
 };
-use chrono::{Duration, Utc};
-use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use chrono::{DateTime, Duration, Utc};
+use ed25519_dalek::SigningKey;
+use hmac::{Hmac, Mac};
+use jsonwebtoken::{decode, decode_header, encode, jwk::JwkSet, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use parking_lot::{Mutex, RwLock};
+use rust_decimal::{prelude::ToPrimitive, Decimal};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use sha2::Sha256;
+use std::{collections::HashMap, sync::{Arc, OnceLock}};
 
-#[derive(Debug, Serialize, Deserialize)]
+pub const DEFAULT_ACCESS_TOKEN_TTL_SECONDS: u64 = 900;
+pub const DEFAULT_REFRESH_TOKEN_TTL_SECONDS: u64 = 604_800;
+pub const DEFAULT_REPUTATION_DECAY_RATE: f64 = 0.01;
+pub const DEFAULT_REFERENCE_DEAL_VALUE: f64 = 1000.0;
+pub const DEFAULT_MIN_VALUE_WEIGHT: f64 = 0.1;
+pub const DEFAULT_MAX_VALUE_WEIGHT: f64 = 3.0;
+pub const DEFAULT_VOLUME_EXPONENT: f64 = 0.5;
+
+/// Trust activities kept in process; the oldest are dropped past this
+const MAX_TRUST_ACTIVITIES: usize = 10_000;
+
+/// Agents with a reputation in the shared cache
+const REPUTATION_INDEX_KEY: &str = "reputation:agents";
+
+/// Least time between fetches of the trusted issuer's JWKS
+const JWKS_REFRESH_INTERVAL_SECONDS: i64 = 60;
+
+/// Refresh tokens are signed with a key derived from the JWT secret, so they
+/// can't be presented as access tokens and vice versa
+const REFRESH_KEY_CONTEXT: &[u8] = b"dcap-refresh-token:v1";
+
+/// The secret trust systems built without one share for the life of the
+/// process, so their tokens are good nowhere else
+fn process_secret() -> &'static str {
+    static SECRET: OnceLock<String> = OnceLock::new();
+    SECRET.get_or_init(|| hex::encode(rand::random::<[u8; 32]>()))
+}
+
+/// The configured JWT secret, or `JWT_SECRET`; empty secrets count as unset.
+fn configured_secret(config: &TrustConfig) -> Option<String> {
+    config.jwt_secret.clone().or_else(|| std::env::var("JWT_SECRET").ok()).filter(|secret| !secret.is_empty())
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ReputationScore {
     pub agent_id: AgentId,
     pub score: u32,
+    /// Score the changes are weighted on top of: one seeded, or kept from
+    /// before changes were weighted. It doesn't decay
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub baseline: Option<u32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub changes: Vec<ReputationEvent>,
     pub successful_transactions: u32,
     pub failed_transactions: u32,
     pub total_negotiations: u32,
     pub average_response_time_ms: u64,
     pub last_updated: chrono::DateTime<chrono::Utc>,
     pub trust_level: TrustLevel,
+    /// The peer's attestation the score was taken from, when it wasn't
+    /// earned here
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attestation: Option<ReputationAttestation>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl ReputationScore {
+    /// When the score last changed where it was earned
+    pub fn as_of(&self) -> DateTime<Utc> {
+        match &self.attestation {
+            Some(attestation) => attestation.as_of,
+            None => self.last_updated,
+        }
+    }
+}
+
+/// A change to an agent's reputation, as recorded before weighting
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReputationEvent {
+    pub change: i32,
+    /// Value of the deal that earned the change, in the agent's currency
+    pub deal_value: Option<Decimal>,
+    pub at: DateTime<Utc>,
+}
+
+/// How reputation changes add up to a score. Each change is scaled by its
+/// deal's value against `reference_deal_value`, within the value weight
+/// bounds (changes without a deal count fully), and loses `decay_rate` of
+/// its weight per day. The weighted sum is divided by the total weight
+/// raised to `volume_exponent`, so a long record of small deals doesn't
+/// outweigh a few large ones, and added to the baseline.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReputationWeights {
+    pub decay_rate: f64,
+    pub reference_deal_value: f64,
+    pub min_value_weight: f64,
+    pub max_value_weight: f64,
+    pub volume_exponent: f64,
+}
+
+impl Default for ReputationWeights {
+    fn default() -> Self {
+        Self {
+            decay_rate: DEFAULT_REPUTATION_DECAY_RATE,
+            reference_deal_value: DEFAULT_REFERENCE_DEAL_VALUE,
+            min_value_weight: DEFAULT_MIN_VALUE_WEIGHT,
+            max_value_weight: DEFAULT_MAX_VALUE_WEIGHT,
+            volume_exponent: DEFAULT_VOLUME_EXPONENT,
+        }
+    }
+}
+
+impl ReputationWeights {
+    pub fn from_config(config: &TrustConfig) -> Result<Self> {
+        let weights = Self {
+            decay_rate: config.reputation_decay_rate.unwrap_or(DEFAULT_REPUTATION_DECAY_RATE),
+            reference_deal_value: config.reference_deal_value.unwrap_or(DEFAULT_REFERENCE_DEAL_VALUE),
+            min_value_weight: config.min_value_weight.unwrap_or(DEFAULT_MIN_VALUE_WEIGHT),
+            max_value_weight: config.max_value_weight.unwrap_or(DEFAULT_MAX_VALUE_WEIGHT),
+            volume_exponent: config.volume_exponent.unwrap_or(DEFAULT_VOLUME_EXPONENT),
+        };
+        if !(0.0..1.0).contains(&weights.decay_rate) {
+            return Err(NegotiationError::Config("Reputation decay rate must be at least 0 and below 1".to_string()));
+        }
+        if weights.reference_deal_value <= 0.0 || weights.min_value_weight <= 0.0 || weights.max_value_weight < weights.min_value_weight {
+            return Err(NegotiationError::Config(
+                "Reference deal value and value weights must be positive, with the maximum weight at least the minimum".to_string()
+            ));
+        }
+        if !(0.0..=1.0).contains(&weights.volume_exponent) {
+            return Err(NegotiationError::Config("Volume exponent must be between 0 and 1".to_string()));
+        }
+        Ok(weights)
+    }
+
+    /// How much a deal's value scales its change
+    pub fn value_weight(&self, deal_value: Option<Decimal>) -> f64 {
+        match deal_value.and_then(|value| value.to_f64()) {
+            Some(value) => (value / self.reference_deal_value).clamp(self.min_value_weight, self.max_value_weight),
+            None => 1.0,
+        }
+    }
+
+    /// Share of its weight a change made at `at` still carries at `now`
+    pub fn decay(&self, at: DateTime<Utc>, now: DateTime<Utc>) -> f64 {
+        let age_days = (now - at).num_seconds().max(0) as f64 / 86_400.0;
+        (1.0 - self.decay_rate).powf(age_days)
+    }
+
+    pub fn score(&self, baseline: u32, changes: &[ReputationEvent], now: DateTime<Utc>) -> u32 {
+        let (weighted, volume) = changes.iter().fold((0.0, 0.0), |(weighted, volume), event| {
+            let weight = self.value_weight(event.deal_value) * self.decay(event.at, now);
+            (weighted + f64::from(event.change) * weight, volume + weight)
+        });
+        let normalized = weighted / volume.max(1.0).powf(self.volume_exponent);
+        (f64::from(baseline) + normalized).round().clamp(0.0, 100.0) as u32
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum TrustLevel {
     Untrusted,    // 0-49
@@ -39,9 +191,10 @@ impl From<u32> for TrustLevel {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct JWTClaims {
     pub sub: String, // agent_id
+    /// A [`Role`], or `agent` on tokens issued before roles
     pub role: String,
     pub exp: usize,
     pub iat: usize,
@@ -50,6 +203,26 @@ pub struct JWTClaims {
 }
 
 #[derive(Debug, Serialize, Deserialize)]
+pub struct RefreshClaims {
+    pub sub: String, // agent_id
+    pub jti: uuid::Uuid,
+    pub exp: usize,
+    pub iat: usize,
+}
+
+/// A short-lived access token and the refresh token that renews it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenPair {
+    pub access_token: String,
+    pub refresh_token: String,
+    pub token_type: String,
+    /// Seconds until the access token expires
+    pub expires_in: u64,
+    /// Seconds until the refresh token expires
+    pub refresh_expires_in: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TrustActivity {
     pub id: uuid::Uuid,
     pub agent_id: AgentId,
@@ -60,7 +233,7 @@ pub struct TrustActivity {
     pub timestamp: chrono::DateTime<chrono::Utc>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum TrustActivityType {
     SuccessfulTransaction,
@@ -69,54 +242,182 @@ pub enum TrustActivityType {
     NegotiationRejected,
     ReputationReport,
     SystemAdjustment,
+    KeyRecovery,
 }
 
 pub struct TrustSystem {
     jwt_secret: String,
     reputation_cache: HashMap<AgentId, ReputationScore>,
+    /// Replaces `reputation_cache` so replicas see each other's updates
+    shared_cache: Option<SharedState>,
     cache_ttl: Duration,
+    weights: ReputationWeights,
+    /// Response times measured for counterparties
+    response_stats: HashMap<AgentId, ResponseStats>,
+    access_token_ttl: Duration,
+    refresh_token_ttl: Duration,
+    /// Refresh tokens already traded in, with their expiry, so each is only
+    /// good once
+    spent_refresh_tokens: HashMap<uuid::Uuid, usize>,
+    /// Keys agent JWTs are signed and checked with, including those fetched
+    /// from `jwks_url`
+    jwt_keys: Arc<RwLock<JwtKeyring>>,
+    jwks_url: Option<String>,
+    jwks_client: HttpClient,
+    jwks_fetched_at: Arc<Mutex<Option<DateTime<Utc>>>>,
+    /// Blacklisted and greylisted agents; nobody is blocked without one
+    blocks: Option<BlockList>,
+    /// Role of the JWTs issued to agents
+    agent_role: Role,
+    roles: RolePolicy,
+    /// Recent trust activities, oldest first
+    activities: Vec<TrustActivity>,
 }
 
 impl TrustSystem {
+    /// A trust system keyed by `JWT_SECRET`, or without it by a secret made
+    /// up for this process. Services use [`TrustSystem::from_config`], which
+    /// requires one.
     pub fn new() -> Result<Self> {
-        let jwt_secret = std::env::var("JWT_SECRET")
-            .unwrap_or_else(|_| "your-secret-key-here".to_string());
+        let jwt_secret = std::env::var("JWT_SECRET").ok()
+            .filter(|secret| !secret.is_empty())
+            .unwrap_or_else(|| process_secret().to_string());
 
         Ok(Self {
             jwt_secret,
             reputation_cache: HashMap::new(),
+            shared_cache: None,
             cache_ttl: Duration::minutes(30),
+            weights: ReputationWeights::default(),
+            response_stats: HashMap::new(),
+            access_token_ttl: Duration::seconds(DEFAULT_ACCESS_TOKEN_TTL_SECONDS as i64),
+            refresh_token_ttl: Duration::seconds(DEFAULT_REFRESH_TOKEN_TTL_SECONDS as i64),
+            spent_refresh_tokens: HashMap::new(),
+            jwt_keys: Arc::new(RwLock::new(JwtKeyring::default())),
+            jwks_url: None,
+            jwks_client: HttpClient::default(),
+            jwks_fetched_at: Arc::new(Mutex::new(None)),
+            blocks: None,
+            agent_role: Role::Buyer,
+            roles: RolePolicy::default(),
+            activities: Vec::new(),
         })
     }
 
-    pub async fn get_reputation(&self, agent_id: AgentId) -> Result<u32> {
-        // Check cache first
-        if let Some(cached) = self.reputation_cache.get(&agent_id) {
-            if Utc::now() - cached.last_updated < self.cache_ttl {
-                return Ok(cached.score);
-            }
+    /// Falls back to `JWT_SECRET` and the default lifetimes for anything the
+    /// config leaves out. Refresh, session and delegation tokens are signed
+    /// with keys derived from the JWT secret, so it fails without one.
+    pub fn from_config(config: &TrustConfig) -> Result<Self> {
+        let mut trust = Self::new()?;
+        trust.jwt_secret = configured_secret(config).ok_or_else(|| {
+            NegotiationError::Config("JWT secret must be set (trust.jwt_secret or JWT_SECRET)".to_string())
+        })?;
+        if let Some(cache_ttl_seconds) = config.cache_ttl_seconds {
+            trust.cache_ttl = Duration::seconds(cache_ttl_seconds as i64);
+        }
+        trust.weights = ReputationWeights::from_config(config)?;
+        trust = trust.with_jwt_keys(JwtKeyring::from_config(&config.signing_keys, config.active_signing_key.as_deref())?);
+        trust.jwks_url = config.jwks_url.clone();
+        trust.roles = RolePolicy::from_config(&config.roles);
+        let access_token_ttl = config.access_token_ttl_seconds.unwrap_or(DEFAULT_ACCESS_TOKEN_TTL_SECONDS);
+        let refresh_token_ttl = config.refresh_token_ttl_seconds.unwrap_or(DEFAULT_REFRESH_TOKEN_TTL_SECONDS);
+        if access_token_ttl == 0 || refresh_token_ttl < access_token_ttl {
+            return Err(NegotiationError::Config(
+                "Token lifetimes must be positive, with refresh tokens outliving access tokens".to_string()
+            ));
         }
+        Ok(trust.with_token_lifetimes(
+            Duration::seconds(access_token_ttl as i64),
+            Duration::seconds(refresh_token_ttl as i64),
+        ))
+    }
+
+    pub fn with_token_lifetimes(mut self, access_token_ttl: Duration, refresh_token_ttl: Duration) -> Self {
+        self.access_token_ttl = access_token_ttl;
+        self.refresh_token_ttl = refresh_token_ttl;
+        self
+    }
+
+    /// Signs agent JWTs with the keyring's active key, and only accepts
+    /// agent JWTs signed by one of its keys.
+    pub fn with_jwt_keys(mut self, keys: JwtKeyring) -> Self {
+        self.jwt_keys = Arc::new(RwLock::new(keys));
+        self
+    }
+
+    /// Issues agent JWTs with `role`; buyers' by default.
+    pub fn with_agent_role(mut self, role: Role) -> Self {
+        self.agent_role = role;
+        self
+    }
 
+    pub fn with_block_list(mut self, blocks: BlockList) -> Self {
+        self.blocks = Some(blocks);
+        self
+    }
+
+    /// Keeps reputations in `shared` instead of in process.
+    pub fn with_shared_cache(mut self, shared: SharedState) -> Self {
+        self.shared_cache = Some(shared);
+        self
+    }
+
+    /// The agent's cached reputation record, while it's fresh. Records
+    /// cached before changes were weighted keep their score as the baseline.
+    async fn cached_reputation(&self, agent_id: AgentId) -> Result<Option<ReputationScore>> {
+        let cached = match &self.shared_cache {
+            Some(shared) => shared.get_json::<ReputationScore>(&Self::reputation_key(agent_id)).await?,
+            None => self.reputation_cache.get(&agent_id).cloned(),
+        };
+        Ok(cached
+            .filter(|cached| Utc::now() - cached.last_updated < self.cache_ttl)
+            .map(|mut cached| {
+                cached.baseline.get_or_insert(cached.score);
+                cached
+            }))
+    }
+
+    pub async fn get_reputation(&self, agent_id: AgentId) -> Result<u32> {
         // New agents start with 0 reputation
-        Ok(0)
+        Ok(match self.cached_reputation(agent_id).await? {
+            Some(cached) => self.weights.score(cached.baseline.unwrap_or(0), &cached.changes, Utc::now()),
+            None => 0,
+        })
+    }
+
+    pub fn reputation_weights(&self) -> &ReputationWeights {
+        &self.weights
     }
 
+    /// Adjusts the agent's reputation by a change with no deal behind it.
     pub async fn update_reputation(&mut self, agent_id: AgentId, score_change: i32) -> Result<()> {
-        let current_score = self.get_reputation(agent_id).await?;
-        let new_score = (current_score as i32 + score_change).max(0).min(100) as u32;
+        self.record_reputation_change(agent_id, score_change, None).await
+    }
 
-        // Update cache
-        let reputation_score = ReputationScore {
-            agent_id,
-            score: new_score,
-            successful_transactions: 0,
-            failed_transactions: 0,
-            total_negotiations: 0,
-            average_response_time_ms: 0,
-            last_updated: Utc::now(),
-            trust_level: TrustLevel::from(new_score),
+    /// Adjusts the agent's reputation by a change earned on a deal worth
+    /// `deal_value`, which scales it.
+    pub async fn record_reputation_change(&mut self, agent_id: AgentId, score_change: i32, deal_value: Option<Decimal>) -> Result<()> {
+        let reason = match deal_value {
+            Some(value) => format!("Reputation adjusted by {} on a deal worth {}", score_change, value),
+            None => format!("Reputation adjusted by {}", score_change),
+        };
+        self.apply_reputation_change(agent_id, score_change, deal_value, reason).await
+    }
+
+    /// Adjusts the agent's reputation by an operator's decision, logging
+    /// their reason.
+    pub async fn adjust_reputation(&mut self, agent_id: AgentId, score_change: i32, reason: &str) -> Result<()> {
+        self.apply_reputation_change(agent_id, score_change, None, reason.to_string()).await
+    }
+
+    async fn apply_reputation_change(&mut self, agent_id: AgentId, score_change: i32, deal_value: Option<Decimal>, reason: String) -> Result<()> {
+        let now = Utc::now();
+        let (baseline, mut changes) = match self.cached_reputation(agent_id).await? {
+            Some(cached) => (cached.baseline.unwrap_or(0), cached.changes),
+            None => (0, Vec::new()),
         };
-        self.reputation_cache.insert(agent_id, reputation_score);
+        changes.push(ReputationEvent { change: score_change, deal_value, at: now });
+        self.store_reputation(agent_id, baseline, changes, None).await?;
 
         // Log the activity
         self.log_trust_activity(TrustActivity {
@@ -124,102 +425,368 @@ impl TrustSystem {
             agent_id,
             activity_type: TrustActivityType::SystemAdjustment,
             score_change,
-            reason: format!("Reputation adjusted by {}", score_change),
+            reason,
             related_agent_id: None,
-            timestamp: Utc::now(),
+            timestamp: now,
         }).await?;
 
         Ok(())
     }
 
-    pub async fn record_successful_transaction(&mut self, buyer_id: AgentId, seller_id: AgentId) -> Result<()> {
-        // Both parties get reputation boost for successful transactions
-        self.update_reputation(buyer_id, 5).await?;
-        self.update_reputation(seller_id, 5).await?;
+    /// Sets the agent's reputation to `score`, dropping its recorded changes,
+    /// e.g. to carry a score over from another system.
+    pub async fn seed_reputation(&mut self, agent_id: AgentId, score: u32) -> Result<()> {
+        self.store_reputation(agent_id, score.min(100), Vec::new(), None).await
+    }
 
-        // Log activities
-        self.log_trust_activity(TrustActivity {
-            id: uuid::Uuid::new_v4(),
-            agent_id: buyer_id,
-            activity_type: TrustActivityType::SuccessfulTransaction,
-            score_change: 5,
-            reason: "Successful transaction completed".to_string(),
-            related_agent_id: Some(seller_id),
-            timestamp: Utc::now(),
-        }).await?;
+    /// Takes the agent's reputation from a peer's verified attestation if
+    /// it's newer than the one held. Returns whether it was taken.
+    pub async fn accept_attestation(&mut self, attestation: ReputationAttestation) -> Result<bool> {
+        if self.held_as_of(attestation.agent_id).await?.is_some_and(|as_of| as_of >= attestation.as_of) {
+            return Ok(false);
+        }
+        let (agent_id, score) = (attestation.agent_id, attestation.score.min(100));
+        self.store_reputation(agent_id, score, Vec::new(), Some(attestation)).await?;
+        tracing::info!("Took reputation {} for agent {} from a peer's attestation", score, agent_id);
+        Ok(true)
+    }
 
-        self.log_trust_activity(TrustActivity {
-            id: uuid::Uuid::new_v4(),
-            agent_id: seller_id,
-            activity_type: TrustActivityType::SuccessfulTransaction,
-            score_change: 5,
-            reason: "Successful transaction completed".to_string(),
-            related_agent_id: Some(buyer_id),
-            timestamp: Utc::now(),
-        }).await?;
+    /// When the agent's held reputation last changed where it was earned
+    async fn held_as_of(&self, agent_id: AgentId) -> Result<Option<DateTime<Utc>>> {
+        Ok(self.cached_reputation(agent_id).await?.map(|cached| cached.as_of()))
+    }
 
+    async fn store_reputation(
+        &mut self,
+        agent_id: AgentId,
+        baseline: u32,
+        changes: Vec<ReputationEvent>,
+        attestation: Option<ReputationAttestation>,
+    ) -> Result<()> {
+        let now = Utc::now();
+        let score = self.weights.score(baseline, &changes, now);
+        let reputation_score = ReputationScore {
+            agent_id,
+            score,
+            baseline: Some(baseline),
+            changes,
+            successful_transactions: 0,
+            failed_transactions: 0,
+            total_negotiations: 0,
+            average_response_time_ms: self.average_response_time_ms(agent_id),
+            last_updated: now,
+            trust_level: TrustLevel::from(score),
+            attestation,
+        };
+        match &self.shared_cache {
+            Some(shared) => {
+                let ttl = self.cache_ttl.to_std().unwrap_or_default();
+                shared.set_json(&Self::reputation_key(agent_id), &reputation_score, ttl).await?;
+                let mut agents = shared.get_json::<Vec<AgentId>>(REPUTATION_INDEX_KEY).await?.unwrap_or_default();
+                if !agents.contains(&agent_id) {
+                    agents.push(agent_id);
+                }
+                shared.set_json(REPUTATION_INDEX_KEY, &agents, ttl).await?;
+            }
+            None => {
+                self.reputation_cache.insert(agent_id, reputation_score);
+            }
+        }
         Ok(())
     }
 
-    pub async fn record_failed_transaction(&mut self, buyer_id: AgentId, seller_id: AgentId) -> Result<()> {
-        // Both parties lose reputation for failed transactions
-        self.update_reputation(buyer_id, -3).await?;
-        self.update_reputation(seller_id, -3).await?;
+    fn reputation_key(agent_id: AgentId) -> String {
+        format!("reputation:{}", agent_id)
+    }
 
-        // Log activities
-        self.log_trust_activity(TrustActivity {
-            id: uuid::Uuid::new_v4(),
-            agent_id: buyer_id,
-            activity_type: TrustActivityType::FailedTransaction,
-            score_change: -3,
-            reason: "Transaction failed".to_string(),
-            related_agent_id: Some(seller_id),
-            timestamp: Utc::now(),
-        }).await?;
+    /// Folds a measured response time into the agent's rolling averages.
+    pub fn record_response_time(&mut self, agent_id: AgentId, kind: ResponseKind, response_ms: u64) {
+        self.response_stats.entry(agent_id)
+            .or_insert_with(|| ResponseStats::new(agent_id))
+            .record(kind, response_ms);
+    }
 
-        self.log_trust_activity(TrustActivity {
-            id: uuid::Uuid::new_v4(),
-            agent_id: seller_id,
-            activity_type: TrustActivityType::FailedTransaction,
-            score_change: -3,
-            reason: "Transaction failed".to_string(),
-            related_agent_id: Some(buyer_id),
-            timestamp: Utc::now(),
-        }).await?;
+    pub fn response_stats(&self, agent_id: AgentId) -> Option<&ResponseStats> {
+        self.response_stats.get(&agent_id)
+    }
+
+    /// 0 until a response from the agent has been timed
+    fn average_response_time_ms(&self, agent_id: AgentId) -> u64 {
+        self.response_stats(agent_id)
+            .and_then(ResponseStats::average_response_time_ms)
+            .unwrap_or(0)
+    }
 
+    /// Both parties of a completed deal worth `deal_value` gain reputation.
+    pub async fn record_successful_transaction(&mut self, buyer_id: AgentId, seller_id: AgentId, deal_value: Option<Decimal>) -> Result<()> {
+        self.record_transaction(buyer_id, seller_id, 5, deal_value, TrustActivityType::SuccessfulTransaction, "Successful transaction completed").await
+    }
+
+    /// Both parties of a failed deal worth `deal_value` lose reputation, and
+    /// the failure counts towards greylisting them.
+    pub async fn record_failed_transaction(&mut self, buyer_id: AgentId, seller_id: AgentId, deal_value: Option<Decimal>) -> Result<()> {
+        self.record_transaction(buyer_id, seller_id, -3, deal_value, TrustActivityType::FailedTransaction, "Transaction failed").await?;
+        for agent_id in [buyer_id, seller_id] {
+            self.record_failure(agent_id, "Transaction failed").await?;
+        }
         Ok(())
     }
 
-    pub async fn generate_jwt(&mut self, agent_id: AgentId) -> Result<String> {
+    /// Counts a failed or disputed transaction the agent is at fault for
+    /// towards greylisting it.
+    pub async fn record_failure(&self, agent_id: AgentId, reason: &str) -> Result<Option<AgentBlock>> {
+        match &self.blocks {
+            Some(blocks) => blocks.record_failure(agent_id, reason).await,
+            None => Ok(None),
+        }
+    }
+
+    /// Bars the agent from trading until `until`, or for good.
+    pub async fn blacklist_agent(&self, agent_id: AgentId, reason: &str, until: Option<DateTime<Utc>>) -> Result<AgentBlock> {
+        self.block_list()?.blacklist(agent_id, reason, until).await
+    }
+
+    /// Lifts the agent's blacklisting or greylisting. Returns whether it was
+    /// blocked.
+    pub async fn unblock_agent(&self, agent_id: AgentId) -> Result<bool> {
+        self.block_list()?.unblock(agent_id).await
+    }
+
+    pub async fn block_status(&self, agent_id: AgentId) -> Result<Option<AgentBlock>> {
+        match &self.blocks {
+            Some(blocks) => blocks.active_block(agent_id).await,
+            None => Ok(None),
+        }
+    }
+
+    /// Fails if the agent is blacklisted or greylisted.
+    pub async fn ensure_not_blocked(&self, agent_id: AgentId) -> Result<()> {
+        match &self.blocks {
+            Some(blocks) => blocks.ensure_not_blocked(agent_id).await,
+            None => Ok(()),
+        }
+    }
+
+    fn block_list(&self) -> Result<&BlockList> {
+        self.blocks.as_ref()
+            .ok_or_else(|| NegotiationError::Config("No block list is configured".to_string()))
+    }
+
+    async fn record_transaction(
+        &mut self,
+        buyer_id: AgentId,
+        seller_id: AgentId,
+        score_change: i32,
+        deal_value: Option<Decimal>,
+        activity_type: TrustActivityType,
+        reason: &str,
+    ) -> Result<()> {
+        for (agent_id, counterparty) in [(buyer_id, seller_id), (seller_id, buyer_id)] {
+            self.record_reputation_change(agent_id, score_change, deal_value).await?;
+            self.log_trust_activity(TrustActivity {
+                id: uuid::Uuid::new_v4(),
+                agent_id,
+                activity_type: activity_type.clone(),
+                score_change,
+                reason: reason.to_string(),
+                related_agent_id: Some(counterparty),
+                timestamp: Utc::now(),
+            }).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn generate_jwt(&self, agent_id: AgentId) -> Result<String> {
+        self.generate_agent_jwt(agent_id, self.agent_role).await
+    }
+
+    async fn generate_agent_jwt(&self, agent_id: AgentId, role: Role) -> Result<String> {
         let reputation_score = self.get_reputation(agent_id).await?;
         let trust_level = TrustLevel::from(reputation_score);
 
         let claims = JWTClaims {
             sub: agent_id.to_string(),
-            role: "agent".to_string(),
-            exp: (Utc::now() + Duration::hours(24)).timestamp() as usize,
+            role: role.to_string(),
+            exp: (Utc::now() + self.access_token_ttl).timestamp() as usize,
             iat: Utc::now().timestamp() as usize,
             reputation_score,
             trust_level: format!("{:?}", trust_level).to_lowercase(),
         };
+        self.sign_claims(&claims)
+    }
+
+    /// A JWT carrying `role` for someone other than an agent, such as an
+    /// operator or a dashboard, good for `ttl`.
+    pub fn generate_role_jwt(&self, subject: &str, role: Role, ttl: Duration) -> Result<String> {
+        let now = Utc::now();
+        self.sign_claims(&JWTClaims {
+            sub: subject.to_string(),
+            role: role.to_string(),
+            exp: (now + ttl).timestamp() as usize,
+            iat: now.timestamp() as usize,
+            reputation_score: 0,
+            trust_level: role.to_string(),
+        })
+    }
 
+    /// Which permissions each role holds, under `[trust.roles]`
+    pub fn roles(&self) -> &RolePolicy {
+        &self.roles
+    }
+
+    /// Checks a JWT and that its role holds `permission` under `[trust.roles]`.
+    pub async fn authorize(&self, token: &str, permission: Permission) -> Result<JWTClaims> {
+        let claims = self.validate_jwt(token).await?;
+        self.roles.check(&claims.role, permission)?;
+        Ok(claims)
+    }
+
+    fn sign_claims(&self, claims: &JWTClaims) -> Result<String> {
+        if self.jwt_keys.read().active_kid().is_some() {
+            return self.jwt_keys.read().sign(claims);
+        }
         encode(
             &Header::default(),
-            &claims,
+            claims,
             &EncodingKey::from_secret(self.jwt_secret.as_ref()),
         ).map_err(|e| NegotiationError::Auth(format!("Failed to generate JWT: {}", e)))
     }
 
+    /// Checks an agent JWT against the key its `kid` names, fetching the
+    /// trusted issuer's keys again if it names one not seen yet. HS256
+    /// tokens are only accepted while no signing keys are set up.
     pub async fn validate_jwt(&self, token: &str) -> Result<JWTClaims> {
-        let mut validation = Validation::new(Algorithm::HS256);
+        let header = decode_header(token).map_err(|e| NegotiationError::Auth(format!("Invalid JWT: {}", e)))?;
+        let mut validation = Validation::new(header.alg);
         validation.validate_exp = true;
 
-        decode::<JWTClaims>(
-            token,
-            &DecodingKey::from_secret(self.jwt_secret.as_ref()),
-            &validation,
+        match header.alg {
+            Algorithm::EdDSA => {
+                let kid = header.kid
+                    .ok_or_else(|| NegotiationError::Auth("JWT doesn't name its signing key".to_string()))?;
+                if !self.jwt_keys.read().contains(&kid) {
+                    self.refresh_jwks().await?;
+                }
+                self.jwt_keys.read().verify(&kid, token, &validation)
+            }
+            Algorithm::HS256 if self.accepts_hs256() => decode::<JWTClaims>(
+                token,
+                &DecodingKey::from_secret(self.jwt_secret.as_ref()),
+                &validation,
+            ).map(|data| data.claims)
+                .map_err(|e| NegotiationError::Auth(format!("Invalid JWT: {}", e))),
+            alg => Err(NegotiationError::Auth(format!("JWTs signed with {:?} are not accepted", alg))),
+        }
+    }
+
+    /// Public keys agent JWTs issued here are signed with
+    pub fn jwks(&self) -> JwkSet {
+        self.jwt_keys.read().jwks()
+    }
+
+    fn accepts_hs256(&self) -> bool {
+        self.jwks_url.is_none() && self.jwt_keys.read().is_empty()
+    }
+
+    /// Fetches the trusted issuer's keys, at most once a minute.
+    async fn refresh_jwks(&self) -> Result<()> {
+        let Some(url) = &self.jwks_url else {
+            return Ok(());
+        };
+        {
+            let mut fetched_at = self.jwks_fetched_at.lock();
+            let now = Utc::now();
+            if fetched_at.is_some_and(|at| now - at < Duration::seconds(JWKS_REFRESH_INTERVAL_SECONDS)) {
+                return Ok(());
+            }
+            *fetched_at = Some(now);
+        }
+        let jwks = jwt_keys::fetch_jwks(&self.jwks_client, url).await?;
+        self.jwt_keys.write().set_remote(&jwks);
+        Ok(())
+    }
+
+    /// Issues a short-lived access token along with a refresh token that can
+    /// be traded for the next one.
+    pub async fn issue_tokens(&mut self, agent_id: AgentId) -> Result<TokenPair> {
+        let access_token = self.generate_agent_jwt(agent_id, self.agent_role).await?;
+        let now = Utc::now();
+        let claims = RefreshClaims {
+            sub: agent_id.to_string(),
+            jti: uuid::Uuid::new_v4(),
+            exp: (now + self.refresh_token_ttl).timestamp() as usize,
+            iat: now.timestamp() as usize,
+        };
+        let refresh_token = encode(&Header::default(), &claims, &EncodingKey::from_secret(&self.refresh_key()))
+            .map_err(|e| NegotiationError::Auth(format!("Failed to generate refresh token: {}", e)))?;
+
+        Ok(TokenPair {
+            access_token,
+            refresh_token,
+            token_type: "Bearer".to_string(),
+            expires_in: self.access_token_ttl.num_seconds().max(0) as u64,
+            refresh_expires_in: self.refresh_token_ttl.num_seconds().max(0) as u64,
+        })
+    }
+
+    /// Trades a refresh token for a new token pair. Refresh tokens rotate:
+    /// the one presented is spent and can't be used again. The new access
+    /// token carries this system's agent role, never one the caller names.
+    pub async fn refresh_tokens(&mut self, refresh_token: &str) -> Result<TokenPair> {
+        let claims = decode::<RefreshClaims>(
+            refresh_token,
+            &DecodingKey::from_secret(&self.refresh_key()),
+            &Validation::new(Algorithm::HS256),
         ).map(|data| data.claims)
-            .map_err(|e| NegotiationError::Auth(format!("Invalid JWT: {}", e)))
+            .map_err(|e| NegotiationError::Auth(format!("Invalid refresh token: {}", e)))?;
+        let agent_id = AgentId::parse_str(&claims.sub)
+            .map_err(|_| NegotiationError::Auth("Invalid refresh token subject".to_string()))?;
+
+        let now = Utc::now().timestamp() as usize;
+        self.spent_refresh_tokens.retain(|_, exp| *exp > now);
+        if self.spent_refresh_tokens.insert(claims.jti, claims.exp).is_some() {
+            return Err(NegotiationError::Auth("Refresh token has already been used".to_string()));
+        }
+        self.issue_tokens(agent_id).await
+    }
+
+    fn refresh_key(&self) -> Vec<u8> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.jwt_secret.as_bytes())
+            .expect("HMAC accepts keys of any length");
+        mac.update(REFRESH_KEY_CONTEXT);
+        mac.finalize().into_bytes().to_vec()
+    }
+
+    pub fn session_tokens(&self) -> SessionTokens {
+        SessionTokens::new(&self.jwt_secret)
+    }
+
+    /// Identifies the agents behind tokens this system issued
+    pub fn token_subjects(&self) -> TokenSubjects {
+        let subjects = TokenSubjects::new(&self.jwt_secret);
+        if self.accepts_hs256() {
+            subjects
+        } else {
+            subjects.with_jwt_keys(self.jwt_keys.clone())
+        }
+    }
+
+    /// Signs and checks the delegation tokens principals give sub-agents
+    pub fn delegation_tokens(&self) -> DelegationTokens {
+        DelegationTokens::new(&self.jwt_secret)
+    }
+
+    /// Trades a principal's valid agent JWT for a token letting `delegate`
+    /// act on its behalf within `scope`.
+    pub async fn issue_delegation(&self, agent_token: &str, delegate: AgentId, scope: DelegationScope, ttl: Duration) -> Result<String> {
+        let agent_claims = self.validate_jwt(agent_token).await?;
+        self.delegation_tokens().issue(&agent_claims, delegate, scope, ttl)
+    }
+
+    /// Trades a valid agent JWT for a session token scoped to one negotiation.
+    pub async fn issue_session_token(&self, agent_token: &str, negotiation_id: TransactionId) -> Result<String> {
+        let agent_claims = self.validate_jwt(agent_token).await?;
+        self.session_tokens().issue(&agent_claims, negotiation_id)
     }
 
     pub async fn check_min_reputation(&self, agent_id: AgentId, min_score: u32) -> Result<bool> {
@@ -234,22 +801,24 @@ impl TrustSystem {
 
     pub async fn get_agent_trust_info(&self, agent_id: AgentId) -> Result<ReputationScore> {
         let score = self.get_reputation(agent_id).await?;
+        let cached = self.cached_reputation(agent_id).await?;
 
         Ok(ReputationScore {
             agent_id,
             score,
+            baseline: cached.as_ref().and_then(|cached| cached.baseline),
+            changes: cached.map(|cached| cached.changes).unwrap_or_default(),
             successful_transactions: 0, // Would need additional queries
             failed_transactions: 0,
             total_negotiations: 0,
-            average_response_time_ms: 0,
+            average_response_time_ms: self.average_response_time_ms(agent_id),
             last_updated: Utc::now(),
             trust_level: TrustLevel::from(score),
+            attestation: None,
         })
     }
 
-    async fn log_trust_activity(&self, activity: TrustActivity) -> Result<()> {
-        // This would store trust activities in the database
-        // For now, we'll just log it
+    async fn log_trust_activity(&mut self, activity: TrustActivity) -> Result<()> {
         tracing::info!(
             "Trust activity: Agent {} {:?} ({} points) - {}",
             activity.agent_id,
@@ -257,9 +826,17 @@ impl TrustSystem {
             activity.score_change,
             activity.reason
         );
+        self.activities.push(activity);
+        self.drop_old_activities();
         Ok(())
     }
 
+    fn drop_old_activities(&mut self) {
+        if self.activities.len() > MAX_TRUST_ACTIVITIES {
+            self.activities.drain(..self.activities.len() - MAX_TRUST_ACTIVITIES);
+        }
+    }
+
     pub async fn calculate_dynamic_threshold(&self, agent_id: AgentId) -> Result<f64> {
         let trust_level = self.get_trust_level(agent_id).await?;
 
@@ -271,15 +848,68 @@ impl TrustSystem {
         }
     }
 
+    /// The agent's trust activities logged in process, oldest first
     pub async fn get_reputation_history(&self, agent_id: AgentId) -> Result<Vec<TrustActivity>> {
-        // This would query the database for trust activities
-        // For now, return empty vector
-        Ok(vec![])
+        Ok(self.activities.iter().filter(|activity| activity.agent_id == agent_id).cloned().collect())
+    }
+
+    /// Every fresh reputation and the activities logged in process, signed
+    /// with `key`.
+    pub async fn export_snapshot(&self, key: &SigningKey) -> Result<ReputationSnapshot> {
+        ReputationSnapshot::sign(key, SnapshotContents {
+            reputations: self.get_all_reputations().await?,
+            activities: self.activities.clone(),
+        })
+    }
+
+    /// Merges in a snapshot signed by `expected_signer`. Reputations replace
+    /// those held only when newer, and activities already held are skipped.
+    pub async fn import_snapshot(&mut self, snapshot: &ReputationSnapshot, expected_signer: &str) -> Result<SnapshotImport> {
+        snapshot.verify(expected_signer)?;
+        let mut import = SnapshotImport::default();
+        for reputation in &snapshot.contents.reputations {
+            if self.held_as_of(reputation.agent_id).await?.is_some_and(|held| held >= reputation.as_of()) {
+                import.reputations_kept += 1;
+                continue;
+            }
+            let baseline = reputation.baseline.unwrap_or(reputation.score).min(100);
+            self.store_reputation(reputation.agent_id, baseline, reputation.changes.clone(), reputation.attestation.clone()).await?;
+            import.reputations_imported += 1;
+        }
+
+        let held: std::collections::HashSet<uuid::Uuid> = self.activities.iter().map(|activity| activity.id).collect();
+        let new_activities: Vec<TrustActivity> = snapshot.contents.activities.iter()
+            .filter(|activity| !held.contains(&activity.id))
+            .cloned()
+            .collect();
+        import.activities_imported = new_activities.len();
+        self.activities.extend(new_activities);
+        self.activities.sort_by_key(|activity| activity.timestamp);
+        self.drop_old_activities();
+
+        tracing::info!(
+            "Imported reputation snapshot from {}: {} reputations taken, {} kept, {} activities",
+            snapshot.signer, import.reputations_imported, import.reputations_kept, import.activities_imported
+        );
+        Ok(import)
     }
 
+    /// Every agent's fresh reputation record, scored as of now
     pub async fn get_all_reputations(&self) -> Result<Vec<ReputationScore>> {
-        // Return empty vector for now - would need to be implemented with proper storage
-        Ok(Vec::new())
+        let agents: Vec<AgentId> = match &self.shared_cache {
+            Some(shared) => shared.get_json(REPUTATION_INDEX_KEY).await?.unwrap_or_default(),
+            None => self.reputation_cache.keys().copied().collect(),
+        };
+        let now = Utc::now();
+        let mut reputations = Vec::new();
+        for agent_id in agents {
+            if let Some(mut reputation) = self.cached_reputation(agent_id).await? {
+                reputation.score = self.weights.score(reputation.baseline.unwrap_or(0), &reputation.changes, now);
+                reputation.trust_level = TrustLevel::from(reputation.score);
+                reputations.push(reputation);
+            }
+        }
+        Ok(reputations)
     }
 
     pub async fn purge_old_cache_entries(&mut self) -> Result<()> {
@@ -289,4 +919,122 @@ impl TrustSystem {
         });
         Ok(())
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_refresh_tokens_rotate() {
+        let config = TrustConfig {
+            jwt_secret: Some("test-secret".to_string()),
+            access_token_ttl_seconds: Some(300),
+            ..TrustConfig::default()
+        };
+        let mut trust = TrustSystem::from_config(&config).unwrap();
+        let agent_id = uuid::Uuid::new_v4();
+
+        let tokens = trust.issue_tokens(agent_id).await.unwrap();
+        assert_eq!(tokens.expires_in, 300);
+        let claims = trust.validate_jwt(&tokens.access_token).await.unwrap();
+        assert!(claims.exp <= (Utc::now() + Duration::seconds(300)).timestamp() as usize);
+        // Neither token passes for the other
+        assert!(trust.validate_jwt(&tokens.refresh_token).await.is_err());
+        assert!(trust.refresh_tokens(&tokens.access_token).await.is_err());
+
+        let refreshed = trust.refresh_tokens(&tokens.refresh_token).await.unwrap();
+        assert_eq!(trust.validate_jwt(&refreshed.access_token).await.unwrap().sub, agent_id.to_string());
+        assert!(trust.refresh_tokens(&tokens.refresh_token).await.is_err());
+        assert!(trust.refresh_tokens(&refreshed.refresh_token).await.is_ok());
+
+        let inverted = TrustConfig { refresh_token_ttl_seconds: Some(60), ..config };
+        assert!(TrustSystem::from_config(&inverted).is_err());
+    }
+
+    #[test]
+    fn test_config_requires_a_jwt_secret() {
+        let config = TrustConfig { jwt_secret: Some(String::new()), ..TrustConfig::default() };
+        assert!(matches!(TrustSystem::from_config(&config), Err(NegotiationError::Config(_))));
+    }
+
+    #[tokio::test]
+    async fn test_tokens_keep_their_role_when_refreshed() {
+        let mut seller = TrustSystem::new().unwrap().with_agent_role(Role::Seller);
+        let agent_id = uuid::Uuid::new_v4();
+        let tokens = seller.issue_tokens(agent_id).await.unwrap();
+        assert!(seller.authorize(&tokens.access_token, Permission::AnswerRfqs).await.is_ok());
+        assert!(seller.authorize(&tokens.access_token, Permission::RequestQuotes).await.is_err());
+
+        let refreshed = seller.refresh_tokens(&tokens.refresh_token).await.unwrap();
+        assert_eq!(seller.validate_jwt(&refreshed.access_token).await.unwrap().role, "seller");
+    }
+
+    #[tokio::test]
+    async fn test_refresh_tokens_cannot_name_a_role() {
+        #[derive(Serialize)]
+        struct ForgedClaims {
+            sub: String,
+            jti: uuid::Uuid,
+            exp: usize,
+            iat: usize,
+            role: Role,
+        }
+        let mut trust = TrustSystem::new().unwrap();
+        let now = Utc::now();
+        let forged = ForgedClaims {
+            sub: uuid::Uuid::new_v4().to_string(),
+            jti: uuid::Uuid::new_v4(),
+            exp: (now + Duration::hours(1)).timestamp() as usize,
+            iat: now.timestamp() as usize,
+            role: Role::Admin,
+        };
+        let refresh_token = encode(&Header::default(), &forged, &EncodingKey::from_secret(&trust.refresh_key())).unwrap();
+
+        let refreshed = trust.refresh_tokens(&refresh_token).await.unwrap();
+        assert_eq!(trust.validate_jwt(&refreshed.access_token).await.unwrap().role, "buyer");
+    }
+    #[tokio::test]
+    async fn test_reputation_weighs_value_age_and_volume() {
+        let weights = ReputationWeights::default();
+        let now = Utc::now();
+        let event = |change, deal_value: Option<i64>, days_ago| ReputationEvent {
+            change,
+            deal_value: deal_value.map(Decimal::from),
+            at: now - Duration::days(days_ago),
+        };
+
+        // Large deals count up to the maximum weight, small ones down to the minimum
+        assert_eq!(weights.score(50, &[event(5, Some(2000), 0)], now), 57);
+        assert_eq!(weights.score(50, &[event(-5, Some(1_000_000), 0)], now), 41);
+        assert_eq!(weights.score(50, &[event(-3, Some(10), 0)], now), 50);
+        // Old changes fade
+        assert_eq!(weights.score(50, &[event(10, None, 0)], now), 60);
+        assert_eq!(weights.score(50, &[event(10, None, 69)], now), 55);
+        // Many small deals don't add up past a few large ones
+        let small: Vec<_> = (0..20).map(|_| event(5, Some(100), 0)).collect();
+        let large: Vec<_> = (0..2).map(|_| event(5, Some(3000), 0)).collect();
+        assert!(weights.score(50, &small, now) < weights.score(50, &large, now));
+
+        // Scores cached before weighting become the baseline
+        let mut trust = TrustSystem::new().unwrap();
+        let agent_id = uuid::Uuid::new_v4();
+        let legacy: ReputationScore = serde_json::from_value(serde_json::json!({
+            "agent_id": agent_id,
+            "score": 80,
+            "successful_transactions": 0,
+            "failed_transactions": 0,
+            "total_negotiations": 0,
+            "average_response_time_ms": 0,
+            "last_updated": now,
+            "trust_level": "trusted",
+        })).unwrap();
+        trust.reputation_cache.insert(agent_id, legacy);
+        assert_eq!(trust.get_reputation(agent_id).await.unwrap(), 80);
+        trust.record_reputation_change(agent_id, 4, Some(Decimal::from(1000))).await.unwrap();
+        assert_eq!(trust.get_reputation(agent_id).await.unwrap(), 84);
+        assert_eq!(trust.get_agent_trust_info(agent_id).await.unwrap().baseline, Some(80));
+
+        trust.seed_reputation(agent_id, 60).await.unwrap();
+        assert_eq!(trust.get_reputation(agent_id).await.unwrap(), 60);
+    }
+}
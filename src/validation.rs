@@ -0,0 +1,206 @@
+//! Field-level validation of request bodies.
+//!
+//! Handlers take their JSON body through [`Valid`] instead of `Json`, which
+//! deserializes it and runs the type's [`Validate`] checks. Anything wrong is
+//! answered with 422 Unprocessable Entity and every problem found, by field:
+//!
+//! ```json
+//! {"status": "error", "message": "Invalid request body", "errors": [
+//!   {"field": "quantity", "message": "Quantity must be greater than 0"},
+//!   {"field": "currency", "message": "Invalid currency code: usd"}
+//! ]}
+//! ```
+//!
+//! A body that isn't JSON at all gets 400 Bad Request in the same shape.
+
+use crate::{
+    currency::validate_currency_code,
+    error::{ApiError, NegotiationError, Result},
+};
+use axum::{
+    async_trait,
+    body::Bytes,
+    extract::{FromRequest, Request},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldError {
+    /// Path to the field, e.g. `products[0].base_price`
+    pub field: String,
+    pub message: String,
+}
+
+/// Problems found in a value, in the order they were checked
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FieldErrors(Vec<FieldError>);
+
+impl FieldErrors {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, field: impl Into<String>, message: impl Into<String>) {
+        self.0.push(FieldError { field: field.into(), message: message.into() });
+    }
+
+    /// Records `message` against `field` unless `valid`.
+    pub fn check(&mut self, valid: bool, field: &str, message: &str) {
+        if !valid {
+            self.add(field, message);
+        }
+    }
+
+    pub fn currency(&mut self, field: &str, code: &str) {
+        if validate_currency_code(code).is_err() {
+            self.add(field, format!("Invalid currency code: {}", code));
+        }
+    }
+
+    /// Records the problems of a nested value under `prefix`.
+    pub fn nested(&mut self, prefix: &str, value: &impl Validate) {
+        for error in value.field_errors().0 {
+            self.add(format!("{}.{}", prefix, error.field), error.message);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn errors(&self) -> &[FieldError] {
+        &self.0
+    }
+
+    /// Fails with the first problem, for callers that only report one.
+    pub fn into_result(self) -> Result<()> {
+        match self.0.into_iter().next() {
+            Some(error) => Err(NegotiationError::Validation(error.message)),
+            None => Ok(()),
+        }
+    }
+}
+
+pub trait Validate {
+    fn validate_fields(&self, errors: &mut FieldErrors);
+
+    fn field_errors(&self) -> FieldErrors {
+        let mut errors = FieldErrors::new();
+        self.validate_fields(&mut errors);
+        errors
+    }
+}
+
+/// Refusal of a request body, with the problems found
+#[derive(Debug)]
+pub struct ValidationRejection {
+    pub status: StatusCode,
+    pub message: String,
+    pub errors: Vec<FieldError>,
+}
+
+impl ValidationRejection {
+    fn new(status: StatusCode, message: &str, errors: Vec<FieldError>) -> Self {
+        Self { status, message: message.to_string(), errors }
+    }
+}
+
+impl IntoResponse for ValidationRejection {
+    fn into_response(self) -> Response {
+        ApiError::new(self.status, self.message).with_detail("errors", self.errors).into_response()
+    }
+}
+
+/// A JSON body that deserialized and passed its [`Validate`] checks.
+#[derive(Debug, Clone)]
+pub struct Valid<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for Valid<T>
+where
+    T: DeserializeOwned + Validate,
+    S: Send + Sync,
+{
+    type Rejection = ValidationRejection;
+
+    async fn from_request(request: Request, state: &S) -> std::result::Result<Self, Self::Rejection> {
+        let body = Bytes::from_request(request, state).await.map_err(|e| {
+            ValidationRejection::new(e.status(), "Failed to read request body", vec![])
+        })?;
+        let value: T = parse(&body)?;
+        let errors = value.field_errors();
+        if !errors.is_empty() {
+            return Err(ValidationRejection::new(StatusCode::UNPROCESSABLE_ENTITY, "Invalid request body", errors.0));
+        }
+        Ok(Valid(value))
+    }
+}
+
+fn parse<T: DeserializeOwned>(body: &[u8]) -> std::result::Result<T, ValidationRejection> {
+    let deserializer = &mut serde_json::Deserializer::from_slice(body);
+    serde_path_to_error::deserialize(deserializer).map_err(|e| {
+        let path = e.path().to_string();
+        let inner = e.into_inner();
+        if inner.is_syntax() || inner.is_eof() {
+            return ValidationRejection::new(StatusCode::BAD_REQUEST, "Request body is not valid JSON", vec![
+                FieldError { field: String::new(), message: inner.to_string() },
+            ]);
+        }
+        // Missing fields are reported against the object holding them
+        let message = inner.to_string();
+        let missing = message.strip_prefix("missing field `")
+            .and_then(|rest| rest.split_once('`'))
+            .map(|(field, _)| field.to_string());
+        let field = match (path.as_str(), missing) {
+            (".", Some(field)) => field,
+            (path, Some(field)) => format!("{}.{}", path, field),
+            (path, None) => path.to_string(),
+        };
+        let message = message.split(" at line ").next().unwrap_or_default().to_string();
+        ValidationRejection::new(StatusCode::UNPROCESSABLE_ENTITY, "Invalid request body", vec![FieldError { field, message }])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::RFQ;
+    use axum::{routing::post, Router};
+
+    #[tokio::test]
+    async fn test_rejects_invalid_bodies_with_field_errors() {
+        let app = Router::new().route("/quote", post(|Valid(rfq): Valid<RFQ>| async move { rfq.product_id }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/quote", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let client = reqwest::Client::new();
+        let post = |body: serde_json::Value| client.post(&url).json(&body).send();
+        let mut rfq = serde_json::to_value(RFQ::new(
+            uuid::Uuid::new_v4(), "laptop-001".to_string(), 2, rust_decimal::Decimal::from(100), "USD".to_string(),
+            chrono::Utc::now() + chrono::Duration::hours(1),
+        )).unwrap();
+        assert_eq!(post(rfq.clone()).await.unwrap().text().await.unwrap(), "laptop-001");
+
+        rfq["quantity"] = 0.into();
+        rfq["currency"] = "usd".into();
+        let response = post(rfq.clone()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let body: serde_json::Value = response.json().await.unwrap();
+        let fields: Vec<_> = body["errors"].as_array().unwrap().iter().map(|error| error["field"].as_str().unwrap()).collect();
+        assert_eq!(fields, ["quantity", "currency"]);
+
+        rfq["quantity"] = "two".into();
+        let body: serde_json::Value = post(rfq.clone()).await.unwrap().json().await.unwrap();
+        assert_eq!(body["errors"][0]["field"], "quantity");
+        rfq.as_object_mut().unwrap().remove("max_price");
+        rfq["quantity"] = 2.into();
+        let body: serde_json::Value = post(rfq).await.unwrap().json().await.unwrap();
+        assert_eq!(body["errors"][0]["field"], "max_price");
+
+        let response = client.post(&url).header("content-type", "application/json").body("{").send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,249 @@
+//! Webhooks signed with the sending agent's Ed25519 key.
+//!
+//! Every webhook an agent sends, the expiry warnings a seller posts to its
+//! buyers and the negotiation events an agent forwards to `[webhooks]
+//! endpoints`, carries two headers:
+//!
+//! - `X-DCAP-Signer`: the sending agent's id, to look its public key up in
+//!   discovery with
+//! - `X-DCAP-Signature`: `t=<unix seconds>,v1=<base64 signature>`, an Ed25519
+//!   signature over `<t>.<body>`
+//!
+//! Agents publish the matching public key when they register, so anyone can
+//! check a delivery with [`verify_webhook`] without a secret shared with the
+//! sender. Signatures more than [`SIGNATURE_TOLERANCE_SECONDS`] from the
+//! receiver's clock are refused, so a captured delivery can't be replayed
+//! later.
+
+use crate::{
+    audit_bundle,
+    config::WebhookConfig,
+    error::{NegotiationError, Result},
+    events::{EventBus, NegotiationEvent},
+    http::HttpClient,
+    idempotency::IDEMPOTENCY_KEY_HEADER,
+    nonce::Stamped,
+    protocol::{CURRENT_VERSION, PROTOCOL_VERSION_HEADER},
+    recovery, AgentId,
+};
+use base64::{engine::general_purpose, Engine as _};
+use chrono::{DateTime, TimeZone, Utc};
+use ed25519_dalek::{Signer, SigningKey};
+use reqwest::header::{HeaderMap, HeaderValue};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
+
+pub const SIGNATURE_HEADER: &str = "x-dcap-signature";
+pub const SIGNER_HEADER: &str = "x-dcap-signer";
+
+/// Environment variable holding an agent's base64 Ed25519 signing key
+pub const DEFAULT_SIGNING_KEY_ENV: &str = "DCAP_AGENT_SIGNING_KEY";
+/// Furthest a signature's timestamp may be from the receiver's clock
+pub const SIGNATURE_TOLERANCE_SECONDS: i64 = 300;
+
+/// A negotiation event as delivered to `[webhooks] endpoints`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WebhookEvent {
+    /// Unique per event; also sent as the delivery's `Idempotency-Key`
+    pub id: uuid::Uuid,
+    pub agent_id: AgentId,
+    #[serde(flatten)]
+    pub event: NegotiationEvent,
+}
+
+/// Signs an agent's outgoing webhooks. Clones share the key.
+#[derive(Clone)]
+pub struct WebhookSigner {
+    agent_id: AgentId,
+    key: Arc<SigningKey>,
+}
+
+impl WebhookSigner {
+    pub fn new(agent_id: AgentId, key: SigningKey) -> Self {
+        Self { agent_id, key: Arc::new(key) }
+    }
+
+    /// A signer with a fresh key, good until the agent restarts.
+    pub fn generate(agent_id: AgentId) -> Self {
+        Self::new(agent_id, SigningKey::from_bytes(&rand::random()))
+    }
+
+    /// Loads the key from `[webhooks] signing_key_env`, or generates one when
+    /// the variable isn't set.
+    pub fn from_config(agent_id: AgentId, config: &WebhookConfig) -> Result<Self> {
+        match std::env::var(&config.signing_key_env) {
+            Ok(encoded) => Ok(Self::new(agent_id, audit_bundle::signing_key(&encoded)?)),
+            Err(_) => {
+                tracing::warn!(
+                    "{} is not set; signing webhooks with a key that changes on restart",
+                    config.signing_key_env
+                );
+                Ok(Self::generate(agent_id))
+            }
+        }
+    }
+
+    pub fn agent_id(&self) -> AgentId {
+        self.agent_id
+    }
+
+    /// Base64 public key receivers verify signatures with.
+    pub fn public_key(&self) -> String {
+        audit_bundle::public_key(&self.key)
+    }
+
+    /// `X-DCAP-Signature` value for `payload` sent at `at`.
+    pub fn sign_at(&self, payload: &[u8], at: DateTime<Utc>) -> String {
+        let timestamp = at.timestamp();
+        let signature = self.key.sign(&signed_message(timestamp, payload));
+        format!("t={},v1={}", timestamp, general_purpose::STANDARD.encode(signature.to_bytes()))
+    }
+
+    /// Base64 signature over a message's [`Stamped::signed_payload`].
+    pub fn sign_stamped<M: Stamped>(&self, message: &M) -> Result<String> {
+        let signature = self.key.sign(&message.signed_payload()?);
+        Ok(general_purpose::STANDARD.encode(signature.to_bytes()))
+    }
+
+    /// The signer and signature headers for `payload`.
+    pub fn headers(&self, payload: &[u8]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        if let Ok(signature) = HeaderValue::from_str(&self.sign_at(payload, Utc::now())) {
+            headers.insert(SIGNATURE_HEADER, signature);
+        }
+        if let Ok(signer) = HeaderValue::from_str(&self.agent_id.to_string()) {
+            headers.insert(SIGNER_HEADER, signer);
+        }
+        headers
+    }
+
+    /// Posts every event published on `events` to each of `endpoints`,
+    /// signed, until the bus closes. Deliveries are retried by `http`;
+    /// ones that still fail are logged and dropped.
+    pub fn forward_events(&self, events: &EventBus, http: HttpClient, endpoints: Vec<String>) -> tokio::task::JoinHandle<()> {
+        let signer = self.clone();
+        let mut receiver = events.subscribe();
+        tokio::spawn(async move {
+            loop {
+                let event = match receiver.recv().await {
+                    Ok(event) => event,
+                    Err(RecvError::Lagged(skipped)) => {
+                        tracing::warn!("Webhook forwarding fell behind; skipped {} events", skipped);
+                        continue;
+                    }
+                    Err(RecvError::Closed) => break,
+                };
+                let event = WebhookEvent { id: uuid::Uuid::new_v4(), agent_id: signer.agent_id, event };
+                let payload = match serde_json::to_vec(&event) {
+                    Ok(payload) => payload,
+                    Err(e) => {
+                        tracing::error!("Failed to serialize webhook event {}: {}", event.id, e);
+                        continue;
+                    }
+                };
+                for endpoint in &endpoints {
+                    let delivery = http.post(endpoint)
+                        .headers(signer.headers(&payload))
+                        .header(reqwest::header::CONTENT_TYPE, "application/json")
+                        .header(PROTOCOL_VERSION_HEADER, CURRENT_VERSION.to_string())
+                        .header(IDEMPOTENCY_KEY_HEADER, event.id.to_string())
+                        .body(payload.clone())
+                        .send()
+                        .await
+                        .and_then(|response| Ok(response.error_for_status()?));
+                    if let Err(e) = delivery {
+                        tracing::warn!("Failed to deliver webhook event {} to {}: {}", event.id, endpoint, e);
+                    }
+                }
+            }
+        })
+    }
+}
+
+fn signed_message(timestamp: i64, payload: &[u8]) -> Vec<u8> {
+    let mut message = format!("{}.", timestamp).into_bytes();
+    message.extend_from_slice(payload);
+    message
+}
+
+/// Checks that `payload` was signed by the holder of `public_key` (base64
+/// Ed25519) within the last few minutes. `signature` is the
+/// `X-DCAP-Signature` header as received.
+pub fn verify_webhook(payload: &[u8], signature: &str, public_key: &str) -> Result<()> {
+    verify_webhook_at(payload, signature, public_key, Utc::now())
+}
+
+/// [`verify_webhook`] against the clock reading `now`.
+pub fn verify_webhook_at(payload: &[u8], signature: &str, public_key: &str, now: DateTime<Utc>) -> Result<()> {
+    let mut timestamp = None;
+    let mut signatures = Vec::new();
+    for part in signature.split(',') {
+        match part.trim().split_once('=') {
+            Some(("t", value)) => timestamp = value.parse::<i64>().ok(),
+            Some(("v1", value)) => signatures.push(value),
+            _ => {}
+        }
+    }
+    let timestamp = timestamp
+        .ok_or_else(|| NegotiationError::Auth("Webhook signature has no timestamp".to_string()))?;
+    let signed_at = Utc.timestamp_opt(timestamp, 0).single()
+        .ok_or_else(|| NegotiationError::Auth("Invalid webhook signature timestamp".to_string()))?;
+    if (now - signed_at).num_seconds().abs() > SIGNATURE_TOLERANCE_SECONDS {
+        return Err(NegotiationError::Auth(format!("Webhook was signed at {}, outside the tolerance", signed_at)));
+    }
+
+    let message = signed_message(timestamp, payload);
+    if signatures.iter().any(|signature| recovery::verify_signature(public_key, &message, signature).is_ok()) {
+        Ok(())
+    } else {
+        Err(NegotiationError::Auth("Webhook signature verification failed".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::EventKind;
+    use axum::{http::HeaderMap as AxumHeaderMap, routing::post, Router};
+    use chrono::Duration;
+    use tokio::sync::mpsc;
+
+    #[tokio::test]
+    async fn test_signed_webhooks_verify_against_public_key() {
+        let signer = WebhookSigner::new(uuid::Uuid::new_v4(), SigningKey::from_bytes(&[7; 32]));
+        let payload = br#"{"quote_id":"q1"}"#;
+        let now = Utc::now();
+        let signature = signer.sign_at(payload, now);
+
+        verify_webhook_at(payload, &signature, &signer.public_key(), now).unwrap();
+        assert!(verify_webhook_at(br#"{"quote_id":"q2"}"#, &signature, &signer.public_key(), now).is_err());
+        let stranger = WebhookSigner::generate(uuid::Uuid::new_v4());
+        assert!(verify_webhook_at(payload, &signature, &stranger.public_key(), now).is_err());
+        let stale = now + Duration::seconds(SIGNATURE_TOLERANCE_SECONDS + 1);
+        assert!(verify_webhook_at(payload, &signature, &signer.public_key(), stale).is_err());
+        assert!(verify_webhook_at(payload, "v1=abc", &signer.public_key(), now).is_err());
+
+        // Forwarded events arrive signed by the agent
+        let (sender, mut received) = mpsc::unbounded_channel();
+        let app = Router::new().route("/hooks", post(move |headers: AxumHeaderMap, body: axum::body::Bytes| {
+            let sender = sender.clone();
+            async move { sender.send((headers, body)).unwrap(); }
+        }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}/hooks", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let events = EventBus::new(4);
+        signer.forward_events(&events, HttpClient::default(), vec![endpoint]);
+        let negotiation_id = uuid::Uuid::new_v4();
+        events.publish(EventKind::Expired { negotiation_id, quote_id: uuid::Uuid::new_v4() });
+
+        let (headers, body) = received.recv().await.unwrap();
+        assert_eq!(headers[SIGNER_HEADER], signer.agent_id().to_string());
+        verify_webhook(&body, headers[SIGNATURE_HEADER].to_str().unwrap(), &signer.public_key()).unwrap();
+        let event: WebhookEvent = serde_json::from_slice(&body).unwrap();
+        assert_eq!(event.agent_id, signer.agent_id());
+        assert!(matches!(event.event.kind, EventKind::Expired { negotiation_id: id, .. } if id == negotiation_id));
+    }
+}